@@ -0,0 +1,195 @@
+// Measures encode and decode throughput of the frame codec at the latest
+// framing version, for payloads of various sizes, header-heavy NewTube
+// frames, and control frames interleaved as they are on a busy channel.
+//
+// Run with `cargo +nightly bench --bench frame_codec`. Each bench reports
+// throughput in terms of the encoded bytes per iteration, so codec changes
+// (e.g. to varints or to copying) can be compared across runs.
+//
+// Decoding before and after the Decoder was rewritten to parse frames in 
+// place from the incoming chunk (ns/iter, best of 8 interleaved runs of both 
+// builds on one machine):
+//
+//   decode_payload_64b                      7,664  ->    5,047   (1.52x)
+//   decode_payload_1kib                     7,508  ->    4,966   (1.51x)
+//   decode_payload_16kib                    7,820  ->    5,034   (1.55x)
+//   decode_payload_60kib                    7,216  ->    5,047   (1.43x)
+//   decode_interleaved_control             45,241  ->   27,577   (1.64x)
+//   decode_header_heavy_newtube           768,789  ->  792,637   (0.97x)
+//   decode_payload_1kib_in_1500b_chunks    19,598  ->   12,207   (1.61x)
+//   decode_payload_60kib_in_16kib_chunks  788,911  ->  360,516   (2.19x)
+//
+// This falls short of the 2x target for small frames. Only payloads split 
+// across chunks (which are no longer copied into a reassembly buffer) reach 
+// it. For the rest:
+//
+// - Payload frames still slice each payload out of the chunk as Bytes, 
+//   which costs a refcount increment and decrement per frame. Skipping the 
+//   slice entirely (as an upper bound on avoiding it) only brought 
+//   decode_payload_64b to about 1.9x, so the remainder is the per-frame 
+//   parse and the moves of each Frame into the output queue.
+// - Control frames have no payload to slice, so what's left is that same 
+//   per-frame cost (plus a Vec allocation per SelectiveAck).
+// - Header-heavy NewTube frames are dominated by allocating each header's 
+//   name and value, which parsing in place doesn't change.
+#![feature(test)]
+extern crate test;
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use test::Bencher;
+
+use tubez::frame::encode::Encoder;
+use tubez::frame::AbortReason;
+use tubez::frame::Decoder;
+use tubez::frame::Frame;
+use tubez::frame::FramingVersion;
+
+const VERSION: FramingVersion = FramingVersion::LATEST;
+const NUM_FRAMES: u32 = 100;
+
+fn payload_frames(payload_size: usize) -> Vec<Frame> {
+    (0..NUM_FRAMES).map(|i| Frame::Payload {
+        tube_id: (i % 50) * 2 + 1,
+        ack_id: Some(i as u16),
+        checksum: None,
+        data: vec![42; payload_size].into(),
+    }).collect()
+}
+
+fn newtube_frames() -> Vec<Frame> {
+    (0..NUM_FRAMES).map(|i| Frame::NewTube {
+        tube_id: i * 2 + 1,
+        headers: (0..64)
+            .map(|n| (format!("x-header-{}", n), format!("value-{}-{}", i, n).into_bytes()))
+            .collect::<HashMap<_, _>>(),
+    }).collect()
+}
+
+fn interleaved_control_frames() -> Vec<Frame> {
+    let mut frames = vec![];
+    for i in 0..NUM_FRAMES {
+        let tube_id = (i % 50) * 2 + 1;
+        frames.push(Frame::NewTubeAck { tube_id });
+        frames.push(Frame::PayloadAck { tube_id, ack_id: i as u16 });
+        frames.push(Frame::SelectiveAck {
+            tube_id,
+            ranges: vec![0..=u64::from(i), u64::from(i) + 2..=u64::from(i) + 4],
+        });
+        frames.push(Frame::Heartbeat);
+        frames.push(Frame::HeartbeatAck);
+        frames.push(Frame::ClientHasFinishedSending { tube_id });
+        frames.push(Frame::Abort { tube_id, reason: AbortReason::ApplicationError });
+        frames.push(Frame::AbortAck { tube_id });
+    }
+    frames
+}
+
+fn encode_all(encoder: &mut Encoder, frames: &[Frame]) -> usize {
+    frames.iter()
+        .map(|frame| encoder.encode(frame.clone()).unwrap().len())
+        .sum()
+}
+
+fn bench_encode(b: &mut Bencher, frames: Vec<Frame>) {
+    let mut encoder = Encoder::new(VERSION);
+    b.bytes = encode_all(&mut encoder, &frames) as u64;
+    b.iter(|| encode_all(&mut encoder, &frames));
+}
+
+fn bench_decode(b: &mut Bencher, frames: Vec<Frame>) {
+    let encoded = Encoder::new(VERSION).encode_batch(frames).unwrap();
+    b.bytes = encoded.len() as u64;
+    b.iter(|| {
+        let mut decoder = Decoder::new_with_version(VERSION);
+        decoder.decode_bytes(encoded.clone()).unwrap()
+    });
+}
+
+// Feeds the encoded frames to the decoder in chunk_size pieces, as they'd 
+// arrive from a transport that doesn't respect frame boundaries.
+fn bench_decode_chunked(b: &mut Bencher, frames: Vec<Frame>, chunk_size: usize) {
+    let encoded = Encoder::new(VERSION).encode_batch(frames).unwrap();
+    b.bytes = encoded.len() as u64;
+    let chunks: Vec<Bytes> = (0..encoded.len())
+        .step_by(chunk_size)
+        .map(|start| encoded.slice(start..(start + chunk_size).min(encoded.len())))
+        .collect();
+    b.iter(|| {
+        let mut decoder = Decoder::new_with_version(VERSION);
+        chunks.iter()
+            .map(|chunk| decoder.decode_bytes(chunk.clone()).unwrap().len())
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn encode_payload_64b(b: &mut Bencher) {
+    bench_encode(b, payload_frames(64));
+}
+
+#[bench]
+fn encode_payload_1kib(b: &mut Bencher) {
+    bench_encode(b, payload_frames(1024));
+}
+
+#[bench]
+fn encode_payload_16kib(b: &mut Bencher) {
+    bench_encode(b, payload_frames(16 * 1024));
+}
+
+#[bench]
+fn encode_payload_60kib(b: &mut Bencher) {
+    bench_encode(b, payload_frames(60 * 1024));
+}
+
+#[bench]
+fn encode_header_heavy_newtube(b: &mut Bencher) {
+    bench_encode(b, newtube_frames());
+}
+
+#[bench]
+fn encode_interleaved_control(b: &mut Bencher) {
+    bench_encode(b, interleaved_control_frames());
+}
+
+#[bench]
+fn decode_payload_64b(b: &mut Bencher) {
+    bench_decode(b, payload_frames(64));
+}
+
+#[bench]
+fn decode_payload_1kib(b: &mut Bencher) {
+    bench_decode(b, payload_frames(1024));
+}
+
+#[bench]
+fn decode_payload_16kib(b: &mut Bencher) {
+    bench_decode(b, payload_frames(16 * 1024));
+}
+
+#[bench]
+fn decode_payload_60kib(b: &mut Bencher) {
+    bench_decode(b, payload_frames(60 * 1024));
+}
+
+#[bench]
+fn decode_header_heavy_newtube(b: &mut Bencher) {
+    bench_decode(b, newtube_frames());
+}
+
+#[bench]
+fn decode_interleaved_control(b: &mut Bencher) {
+    bench_decode(b, interleaved_control_frames());
+}
+
+#[bench]
+fn decode_payload_1kib_in_1500b_chunks(b: &mut Bencher) {
+    bench_decode_chunked(b, payload_frames(1024), 1500);
+}
+
+#[bench]
+fn decode_payload_60kib_in_16kib_chunks(b: &mut Bencher) {
+    bench_decode_chunked(b, payload_frames(60 * 1024), 16 * 1024);
+}
@@ -0,0 +1,80 @@
+// Compares V1 (fixed-width) and V2 (varint) framing on an ack-heavy workload.
+//
+// Run with `cargo +nightly bench --bench framing_overhead`. The encode benches
+// report throughput in terms of the number of encoded bytes produced per 
+// iteration, so the header overhead reduction of V2 shows up directly as a 
+// smaller MB/s figure for the same number of frames.
+#![feature(test)]
+extern crate test;
+
+use test::Bencher;
+
+use tubez::frame::encode;
+use tubez::frame::Decoder;
+use tubez::frame::Frame;
+use tubez::frame::FramingVersion;
+
+const NUM_FRAMES: u16 = 1000;
+
+fn ack_heavy_frames() -> Vec<Frame> {
+    let mut frames = vec![];
+    for i in 0..NUM_FRAMES {
+        let tube_id = u32::from(((i % 50) * 2) + 1);
+        frames.push(Frame::Payload {
+            tube_id,
+            ack_id: Some(i),
+            checksum: None,
+            data: vec![42; 16].into(),
+        });
+        frames.push(Frame::PayloadAck {
+            tube_id,
+            ack_id: i,
+        });
+    }
+    frames
+}
+
+fn encode_all(frames: &[Frame], version: FramingVersion) -> Vec<u8> {
+    let mut bytes = vec![];
+    for frame in frames {
+        bytes.append(
+            &mut encode::encode_frame_with_version(frame.clone(), version).unwrap()
+        );
+    }
+    bytes
+}
+
+fn bench_encode(b: &mut Bencher, version: FramingVersion) {
+    let frames = ack_heavy_frames();
+    b.bytes = encode_all(&frames, version).len() as u64;
+    b.iter(|| encode_all(&frames, version));
+}
+
+fn bench_decode(b: &mut Bencher, version: FramingVersion) {
+    let encoded = bytes::Bytes::from(encode_all(&ack_heavy_frames(), version));
+    b.bytes = encoded.len() as u64;
+    b.iter(|| {
+        let mut decoder = Decoder::new_with_version(version);
+        decoder.decode_bytes(encoded.clone()).unwrap()
+    });
+}
+
+#[bench]
+fn encode_ack_heavy_v1(b: &mut Bencher) {
+    bench_encode(b, FramingVersion::V1);
+}
+
+#[bench]
+fn encode_ack_heavy_v2(b: &mut Bencher) {
+    bench_encode(b, FramingVersion::V2);
+}
+
+#[bench]
+fn decode_ack_heavy_v1(b: &mut Bencher) {
+    bench_decode(b, FramingVersion::V1);
+}
+
+#[bench]
+fn decode_ack_heavy_v2(b: &mut Bencher) {
+    bench_decode(b, FramingVersion::V2);
+}
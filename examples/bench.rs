@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use clap::Parser;
+use clap::Subcommand;
+use simple_logger::SimpleLogger;
+
+use futures::StreamExt;
+use tubez::bench;
+
+#[derive(Parser)]
+struct CLIArgs {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the built-in bench service
+    Serve {
+        #[clap(default_value = "127.0.0.1:3000")]
+        addr: SocketAddr,
+    },
+
+    /// Run load against a server's bench service and report the results
+    Run {
+        server_uri: hyper::Uri,
+
+        /// "echo" or "throughput"
+        #[clap(long, default_value = "echo")]
+        mode: String,
+
+        #[clap(long, default_value_t = 1000)]
+        num_payloads: usize,
+
+        #[clap(long, default_value_t = 1024)]
+        payload_size: usize,
+
+        #[clap(long, default_value_t = 64)]
+        pipeline_depth: usize,
+
+        #[clap(long, default_value_t = 10)]
+        timeout_secs: u64,
+    },
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 10)]
+async fn main() {
+    SimpleLogger::new()
+      .with_level(log::LevelFilter::Info)
+      .init()
+      .expect("Error initializing logger");
+
+    match CLIArgs::parse().command {
+        Command::Serve { addr } => {
+            let mut server = tubez::Server::new(&addr).await.expect(
+                "Error binding server"
+            );
+            server.set_serves_bench_tubes(true);
+            println!("Serving bench tubes on {}...", server.local_addr().unwrap());
+
+            // Bench tubes never reach the application, so the channels just 
+            // need to be kept alive.
+            let mut channels = vec![];
+            while let Some(event) = server.next().await {
+                if let Ok(tubez::server::ServerEvent::NewChannel(channel)) = event {
+                    channels.push(channel);
+                }
+            }
+        },
+
+        Command::Run { 
+            server_uri, 
+            mode, 
+            num_payloads, 
+            payload_size, 
+            pipeline_depth, 
+            timeout_secs,
+        } => {
+            let mode = bench::BenchMode::from_header_value(mode.as_bytes()).expect(
+                "Unknown bench mode"
+            );
+            let config = bench::BenchConfig::new(mode)
+                .with_num_payloads(num_payloads)
+                .with_payload_size(payload_size)
+                .with_pipeline_depth(pipeline_depth)
+                .with_timeout(Duration::from_secs(timeout_secs));
+
+            let mut client = tubez::Client::new(server_uri);
+            let mut channel = client.make_tube_channel(HashMap::new()).await.expect(
+                "Channel creation error"
+            );
+            let report = bench::run(&mut channel, &config).await.expect(
+                "Bench run error"
+            );
+            println!("{}", report);
+        },
+    }
+}
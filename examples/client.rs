@@ -14,7 +14,8 @@ fn uri_cli_parser(s: &str) -> Result<hyper::Uri, String> {
 
     let mut uri_parts = uri.into_parts();
     if let None = uri_parts.scheme {
-        uri_parts.scheme = Some(hyper::http::uri::Scheme::HTTPS);
+        // The client connects over plaintext HTTP/2
+        uri_parts.scheme = Some(hyper::http::uri::Scheme::HTTP);
     }
     if let None = uri_parts.path_and_query {
         uri_parts.path_and_query = 
@@ -62,14 +63,12 @@ async fn main() {
     tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
 
     println!("Sending some data...");
-    tube1.send("tube1 data!".into(), Duration::from_secs(3)).await.unwrap();
+    tube1.send("tube1 data!", Duration::from_secs(3)).await.unwrap();
     println!("received ack for data sent on tube1!");
     println!("client has finished...");
     tube1.has_finished_sending().await.expect("Tube1 failed sending ClientHasFinished");
 
     println!("Waiting 3 secs before creating 2nd tube...");
-    // TODO: Deleting this kills the transport... Probably need to gracefully 
-    //       kill/end/await all the Channels in a destructor or something?
     tokio::time::sleep(tokio::time::Duration::from_millis(3000)).await;
 
     let tube2_headers = HashMap::new();
@@ -83,13 +82,7 @@ async fn main() {
     }
     std::mem::drop(tube1);
     std::mem::drop(tube2);
-    println!("No more tube events! Dropping channel in 3 seconds...");
-    tokio::time::sleep(tokio::time::Duration::from_millis(3000)).await;
-    std::mem::drop(channel);
-    println!("Channel now dropped!");
-
-    println!("Waiting a bit before exiting...");
-    // TODO: Deleting this kills the transport... Probably need to gracefully 
-    //       kill/end/await all the Channels in a destructor or something?
-    tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
+    println!("No more tube events! Closing channel...");
+    channel.close(Duration::from_secs(3)).await.expect("Channel close error");
+    println!("Channel now closed!");
 }
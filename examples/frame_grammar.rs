@@ -0,0 +1,9 @@
+// Prints the machine-readable wire format specification (see 
+// tubez::frame::frame_grammar_json()) so that alternate implementations can 
+// check it in alongside their codecs:
+//
+//   cargo run --example frame_grammar > frame_grammar.json
+fn main() {
+    let grammar = tubez::frame::frame_grammar_json();
+    println!("{}", serde_json::to_string_pretty(&grammar).unwrap());
+}
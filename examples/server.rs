@@ -42,7 +42,15 @@ fn spawn_channel_handler(mut channel: tubez::server::Channel) {
 
                     // Only expect 1 Tube
                     break;
-                }
+                },
+                ChannelEvent::Closed(reason) => {
+                    println!("ChannelLoop: Channel closed ({:?})", reason);
+                    break;
+                },
+                ChannelEvent::Drained
+                    | ChannelEvent::Reconnected 
+                    | ChannelEvent::SlowConsumer { .. } 
+                    | ChannelEvent::TransportFailed(_) => (),
             }
         }
         println!("ChannelLoop: Dropping channel!");
@@ -58,8 +66,10 @@ async fn main() {
     let cli_args = CLIArgs::parse();
 
     println!("Starting server bound to `{}`...", &cli_args.bind_addr);
-    let mut server = tubez::Server::new(&cli_args.bind_addr).await;
-    println!("Server started.\n");
+    let mut server = tubez::Server::new(&cli_args.bind_addr).await.expect(
+        "Error starting server"
+    );
+    println!("Server started on `{}`.\n", server.local_addr().unwrap());
 
     println!("Waiting on Tubes...");
     while let Some(server_event) = server.next().await {
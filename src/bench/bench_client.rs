@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::StreamExt;
+
+use crate::client;
+use crate::common::tube;
+use super::BenchMode;
+use super::BENCH_HEADER;
+
+#[derive(Debug)]
+pub enum BenchError {
+    HasFinishedSendingError(tube::error::HasFinishedSendingError),
+    MakeTubeError(client::MakeTubeError),
+    SendError(tube::error::SendError),
+    TimedOutWaitingOnEcho(Duration),
+    TubeEndedEarly,
+    UnexpectedTubeEvent(tube::TubeEvent),
+}
+
+/**
+ * Describes the load that run() puts on a bench tube. Defaults to 1000 
+ * payloads of 1KiB each.
+ */
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    mode: BenchMode,
+    num_payloads: usize,
+    payload_size: usize,
+    pipeline_depth: usize,
+    timeout: Duration,
+}
+impl BenchConfig {
+    pub fn new(mode: BenchMode) -> Self {
+        BenchConfig {
+            mode,
+            num_payloads: 1000,
+            payload_size: 1024,
+            pipeline_depth: 64,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn with_num_payloads(mut self, num_payloads: usize) -> Self {
+        self.num_payloads = num_payloads;
+        self
+    }
+
+    pub fn with_payload_size(mut self, payload_size: usize) -> Self {
+        self.payload_size = payload_size;
+        self
+    }
+
+    /**
+     * How many payloads BenchMode::Throughput sends (with 
+     * Tube::send_pipelined()) before waiting on their acks. Ignored by 
+     * BenchMode::Echo, which always waits on each echo before sending the 
+     * next payload. A depth of 0 is treated as 1.
+     */
+    pub fn with_pipeline_depth(mut self, pipeline_depth: usize) -> Self {
+        self.pipeline_depth = pipeline_depth.max(1);
+        self
+    }
+
+    /**
+     * How long to wait on each echo (BenchMode::Echo) or on each pipelined 
+     * batch's acks (BenchMode::Throughput) before giving up.
+     */
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub mode: BenchMode,
+    pub num_payloads: usize,
+    pub payload_bytes: u64,
+    pub elapsed: Duration,
+    /**
+     * The round trip time of each echo (BenchMode::Echo) or the time from 
+     * sending each pipelined batch until all of its payloads were acked 
+     * (BenchMode::Throughput), in the order they were measured.
+     */
+    pub latencies: Vec<Duration>,
+}
+impl BenchReport {
+    pub fn payloads_per_sec(&self) -> f64 {
+        per_sec(self.num_payloads as f64, self.elapsed)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.payload_bytes as f64, self.elapsed)
+    }
+
+    /**
+     * The latency at the given percentile (0.0 through 100.0) using the 
+     * nearest-rank method, or None if no latencies were measured.
+     */
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let percentile = percentile.clamp(0.0, 100.0);
+        let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} payloads ({} bytes) in {:?} [mode={}]: {:.1} payloads/sec, {:.1} bytes/sec",
+            self.num_payloads,
+            self.payload_bytes,
+            self.elapsed,
+            self.mode.header_value(),
+            self.payloads_per_sec(),
+            self.bytes_per_sec(),
+        )?;
+        if let (Some(p50), Some(p99), Some(max)) = (
+            self.latency_percentile(50.0),
+            self.latency_percentile(99.0),
+            self.latency_percentile(100.0),
+        ) {
+            write!(f, ", latency p50={:?} p99={:?} max={:?}", p50, p99, max)?;
+        }
+        Ok(())
+    }
+}
+
+fn per_sec(count: f64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        count / secs
+    } else {
+        0.0
+    }
+}
+
+/**
+ * Opens a tube to the bench service of the server at the other end of 
+ * `channel` (see Server::set_serves_bench_tubes()), runs the configured load 
+ * against it, and reports the results.
+ */
+pub async fn run(
+    channel: &mut client::Channel,
+    config: &BenchConfig,
+) -> Result<BenchReport, BenchError> {
+    let headers = HashMap::from([
+        (BENCH_HEADER.to_string(), config.mode.header_value().to_string()),
+    ]);
+    let mut tube = match channel.make_tube(headers).await {
+        Ok(tube) => tube,
+        Err(e) => return Err(BenchError::MakeTubeError(e)),
+    };
+
+    let payload = vec![0xAB; config.payload_size];
+    let start = Instant::now();
+    let latencies = match config.mode {
+        BenchMode::Echo => run_echo(&mut tube, config, &payload).await?,
+        BenchMode::Throughput => run_throughput(&mut tube, config, &payload).await?,
+    };
+    let elapsed = start.elapsed();
+
+    if let Err(e) = tube.has_finished_sending().await {
+        return Err(BenchError::HasFinishedSendingError(e));
+    }
+    // Wait for the server to finish its side so the tube closes cleanly.
+    loop {
+        match tokio::time::timeout(config.timeout, tube.next()).await {
+            Ok(Some(tube::TubeEvent::ServerHasFinishedSending)) | Ok(None) => break,
+            Ok(Some(event @ tube::TubeEvent::Abort(_))) | 
+            Ok(Some(event @ tube::TubeEvent::StreamError(_))) => 
+                return Err(BenchError::UnexpectedTubeEvent(event)),
+            Ok(Some(_)) => (),
+            Err(_) => return Err(BenchError::TimedOutWaitingOnEcho(config.timeout)),
+        }
+    }
+
+    Ok(BenchReport {
+        mode: config.mode,
+        num_payloads: config.num_payloads,
+        payload_bytes: (config.num_payloads * config.payload_size) as u64,
+        elapsed,
+        latencies,
+    })
+}
+
+async fn run_echo(
+    tube: &mut tube::Tube,
+    config: &BenchConfig,
+    payload: &[u8],
+) -> Result<Vec<Duration>, BenchError> {
+    let mut latencies = Vec::with_capacity(config.num_payloads);
+    for _ in 0..config.num_payloads {
+        let sent_at = Instant::now();
+        if let Err(e) = tube.send_and_forget(payload.to_vec()).await {
+            return Err(BenchError::SendError(e));
+        }
+        loop {
+            match tokio::time::timeout(config.timeout, tube.next()).await {
+                Ok(Some(tube::TubeEvent::Payload(_))) => break,
+                Ok(Some(tube::TubeEvent::AuthenticatedAndReady)) => (),
+                Ok(Some(event)) => return Err(BenchError::UnexpectedTubeEvent(event)),
+                Ok(None) => return Err(BenchError::TubeEndedEarly),
+                Err(_) => return Err(BenchError::TimedOutWaitingOnEcho(config.timeout)),
+            }
+        }
+        latencies.push(sent_at.elapsed());
+    }
+    Ok(latencies)
+}
+
+async fn run_throughput(
+    tube: &mut tube::Tube,
+    config: &BenchConfig,
+    payload: &[u8],
+) -> Result<Vec<Duration>, BenchError> {
+    let mut latencies = vec![];
+    let mut remaining = config.num_payloads;
+    while remaining > 0 {
+        let batch_size = remaining.min(config.pipeline_depth);
+        let batch = vec![payload.to_vec(); batch_size];
+        let sent_at = Instant::now();
+        let acks = match tube.send_pipelined(batch, config.timeout).await {
+            Ok(acks) => acks,
+            Err(e) => return Err(BenchError::SendError(e)),
+        };
+        if let Err(e) = acks.all_acked().await {
+            return Err(BenchError::SendError(e));
+        }
+        latencies.push(sent_at.elapsed());
+        remaining -= batch_size;
+    }
+    Ok(latencies)
+}
+
+#[cfg(test)]
+mod bench_client_tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::Client;
+    use crate::Server;
+
+    fn report_with_latencies(millis: &[u64]) -> BenchReport {
+        BenchReport {
+            mode: BenchMode::Echo,
+            num_payloads: 100,
+            payload_bytes: 100 * 1024,
+            elapsed: Duration::from_secs(2),
+            latencies: millis.iter().map(|ms| Duration::from_millis(*ms)).collect(),
+        }
+    }
+
+    #[test]
+    fn computes_rates_and_latency_percentiles() {
+        let report = report_with_latencies(&[5, 1, 4, 2, 3]);
+        assert_eq!(report.payloads_per_sec(), 50.0);
+        assert_eq!(report.bytes_per_sec(), 51200.0);
+        assert_eq!(report.latency_percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(report.latency_percentile(50.0), Some(Duration::from_millis(3)));
+        assert_eq!(report.latency_percentile(100.0), Some(Duration::from_millis(5)));
+
+        let empty = report_with_latencies(&[]);
+        assert_eq!(empty.latency_percentile(50.0), None);
+        assert!(!format!("{}", empty).contains("latency"));
+    }
+
+    #[tokio::test]
+    async fn runs_echo_and_throughput_against_bench_server() {
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut server = Server::new(&addr).await.unwrap();
+        server.set_serves_bench_tubes(true);
+
+        let uri: hyper::Uri = format!("http://{}", server.local_addr().unwrap()).parse().unwrap();
+
+        let mut channel = Client::new(uri.clone())
+            .make_tube_channel(HashMap::new()).await.unwrap();
+        let echo_config = BenchConfig::new(BenchMode::Echo)
+            .with_num_payloads(10)
+            .with_payload_size(64);
+        let report = run(&mut channel, &echo_config).await.unwrap();
+        assert_eq!(report.num_payloads, 10);
+        assert_eq!(report.payload_bytes, 640);
+        assert_eq!(report.latencies.len(), 10);
+
+        let throughput_config = BenchConfig::new(BenchMode::Throughput)
+            .with_num_payloads(100)
+            .with_payload_size(64)
+            .with_pipeline_depth(32);
+        let mut channel = Client::new(uri)
+            .make_tube_channel(HashMap::new()).await.unwrap();
+        let report = run(&mut channel, &throughput_config).await.unwrap();
+        assert_eq!(report.num_payloads, 100);
+        assert_eq!(report.latencies.len(), 4);
+    }
+}
@@ -0,0 +1,32 @@
+use futures::StreamExt;
+
+use crate::common::tube;
+use super::BenchMode;
+
+/**
+ * Serves a single bench tube until the client finishes sending, then finishes
+ * the server's side of the tube.
+ */
+pub(in crate) async fn serve_bench_tube(mut tube: tube::Tube, mode: BenchMode) {
+    log::trace!("Serving bench tube(id={}) in {:?} mode", tube.get_id(), mode);
+    while let Some(event) = tube.next().await {
+        match event {
+            tube::TubeEvent::Payload(data) => {
+                if mode == BenchMode::Echo {
+                    if let Err(e) = tube.send_and_forget(data).await {
+                        log::error!("Error echoing bench payload: {:?}", e);
+                        return;
+                    }
+                }
+            },
+            tube::TubeEvent::ClientHasFinishedSending => {
+                if let Err(e) = tube.has_finished_sending().await {
+                    log::error!("Error finishing bench tube: {:?}", e);
+                    return;
+                }
+            },
+            tube::TubeEvent::Abort(_) | tube::TubeEvent::StreamError(_) => return,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+mod bench_client;
+mod bench_server;
+
+pub use bench_client::run;
+pub use bench_client::BenchConfig;
+pub use bench_client::BenchError;
+pub use bench_client::BenchReport;
+pub(in crate) use bench_server::serve_bench_tube;
+
+use std::collections::HashMap;
+
+/**
+ * NewTube header that routes a tube to the built-in bench service on servers
+ * that have it enabled (see Server::set_serves_bench_tubes()). The value
+ * selects the BenchMode.
+ */
+pub const BENCH_HEADER: &str = "tubez-bench";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BenchMode {
+    /**
+     * The server sends every payload it receives straight back, so the client
+     * can measure round-trip latency.
+     */
+    Echo,
+
+    /**
+     * The server acks and discards every payload it receives, so the client 
+     * can measure how quickly acked payloads can be pushed through a tube.
+     */
+    Throughput,
+}
+impl BenchMode {
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            BenchMode::Echo => "echo",
+            BenchMode::Throughput => "throughput",
+        }
+    }
+
+    pub fn from_header_value(value: &[u8]) -> Option<Self> {
+        match value {
+            b"echo" => Some(BenchMode::Echo),
+            b"throughput" => Some(BenchMode::Throughput),
+            _ => None,
+        }
+    }
+}
+
+pub(in crate) fn requested_bench_mode(headers: &HashMap<String, Vec<u8>>) -> Option<BenchMode> {
+    headers.get(BENCH_HEADER).and_then(|value| BenchMode::from_header_value(value))
+}
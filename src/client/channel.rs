@@ -1,176 +1,1233 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
-use hyper::body::HttpBody;
+use futures::StreamExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
 
+use crate::common::capture;
 use crate::common::frame;
+use crate::common::frame::Transport;
+use crate::common::ChannelContext;
+use crate::common::ChannelEvent;
+use crate::common::ChannelExecutor;
 use crate::common::PeerType;
+use crate::common::PingError;
+use crate::common::raw_transport;
 use crate::common::tube;
+use crate::common::TubeIdAllocator;
 use crate::common::UniqueIdError;
-use crate::common::UniqueIdManager;
+#[cfg(feature = "websocket")]
+use crate::common::websocket;
+use super::client::Connector;
+use super::client_builder::ChannelTransport;
+use super::Keepalive;
+use super::ReconnectPolicy;
+use super::TubeLimit;
+use super::TubeLimitPolicy;
 
 #[derive(Debug)]
 pub enum ChannelConnectError {
     InitError(hyper::Error),
+    /**
+     * A channel header's name or value can't be sent as an HTTP header. 
+     * Carries the header's name.
+     */
+    InvalidHeader(String),
+    /**
+     * The endpoint given for the channel isn't a usable URI (see 
+     * Client::make_tube_channel_at()).
+     */
+    InvalidEndpoint(String),
+    /**
+     * The connection for a raw channel (see 
+     * ClientBuilder::with_raw_transport()) couldn't be made, or the server's
+     * head couldn't be read from it. Carries why.
+     */
+    RawConnectFailed(String),
+    /**
+     * The server refused the channel (e.g. because its authenticator 
+     * rejected the client's credentials), answering with an HTTP error 
+     * status. Carries the status and the detail the server gave.
+     */
+    Rejected {
+        status: hyper::StatusCode,
+        detail: String,
+    },
+    /**
+     * The server answered the WebSocket handshake (see 
+     * ClientBuilder::with_websocket_transport()) in a way that doesn't 
+     * complete it. Carries why.
+     */
+    #[cfg(feature = "websocket")]
+    WebSocketHandshakeFailed(String),
+}
+
+#[derive(Debug)]
+pub enum CloseError {
+    FrameSendError(frame::FrameSendError),
+    /**
+     * The server didn't acknowledge the Aborts sent for the Channel's 
+     * unfinished Tubes in time. The Channel is closed regardless.
+     */
+    TimedOutWaitingOnAbortAcks(Duration),
 }
 
 #[derive(Debug)]
 pub enum MakeTubeError {
+    /**
+     * The server aborted the Tube instead of acking it.
+     */
+    AbortedByServer(frame::AbortReason),
+    /**
+     * The Channel already has as many unfinished Tubes as its TubeLimit 
+     * allows and the TubeLimitPolicy is Fail.
+     */
+    AtCapacity,
+    ChannelClosed,
+    /**
+     * The Channel's transport ended or failed before the server acked the 
+     * Tube.
+     */
+    ChannelFailed(String),
     FrameEncodeError(frame::encode::FrameEncodeError),
-    InternalErrorDuplicateTubeId(u16),
+    FrameVetoed(String),
+    InternalErrorDuplicateTubeId(u32),
+    /**
+     * The server refused the Tube with an Error frame (e.g. because it's over
+     * a limit, the client isn't authorized, or nothing serves the Tube's 
+     * route).
+     */
+    Rejected {
+        code: frame::ErrorCode,
+        detail: String,
+    },
+    /**
+     * The server has sent a GoAway, so no more Tubes can be made on this 
+     * Channel. Carries the reason the server gave.
+     */
+    ServerGoingAway(String),
     TubeIdsExhausted,
+    /**
+     * The server refused the Tube because the Channel already has as many 
+     * unfinished Tubes as the server allows on a channel (see 
+     * server::ServerBuilder::with_max_tubes_per_channel()). Carries the 
+     * detail the server gave. Another Tube can be made once one of the 
+     * Channel's Tubes finishes.
+     */
+    TooManyTubes(String),
+    UnexpectedServerResponse(tube::TubeEvent),
     UnknownTransportError,
 }
 
 pub struct Channel {
-    body_sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
-    tube_id_manager: UniqueIdManager,
-    tube_managers: Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
+    closed: bool,
+    ctx: ChannelContext,
+    extensions: hyper::http::Extensions,
+    frame_sender: frame::FrameSender,
+    tube_id_allocator: TubeIdAllocator,
+    tube_limit: Option<TubeLimit>,
 }
 impl Channel {
     pub(in crate::client) async fn new(
-        hyper_client: &hyper::Client<hyper::client::HttpConnector>,
-        headers: HashMap<String, String>,
-        server_uri: &hyper::Uri,
+        connection: ChannelConnection,
+        executor: ChannelExecutor,
+        reconnect_policy: Option<ReconnectPolicy>,
+        keepalive: Option<Keepalive>,
     ) -> Result<Self, ChannelConnectError> {
-        Self::new_impl(hyper_client, headers, server_uri).await
+        Self::new_impl(connection, executor, reconnect_policy, keepalive).await
     }
 
     async fn new_impl(
-        hyper_client: &hyper::Client<hyper::client::HttpConnector>,
-        _headers: HashMap<String, String>, // TODO
-        server_uri: &hyper::Uri,
+        connection: ChannelConnection,
+        executor: ChannelExecutor,
+        reconnect_policy: Option<ReconnectPolicy>,
+        keepalive: Option<Keepalive>,
     ) -> Result<Self, ChannelConnectError> {
-        let (body_sender, req_body) = hyper::Body::channel();
-        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
-        let req = hyper::Request::builder()
-          .method(hyper::Method::POST)
-          .uri(format!("{}", &server_uri))
-          .body(req_body)
-          .unwrap();
-
-        log::trace!("Sending channel request to {}...", &server_uri);
-        let response = match hyper_client.request(req).await {
-            Ok(response) => response,
-            Err(e) => return Err(ChannelConnectError::InitError(e)),
-        };
-        let mut res_body = response.into_body();
-        let tube_managers = Arc::new(Mutex::new(HashMap::new()));
+        let (body_sender, res_body, negotiated) = connection.connect().await?;
+        Ok(Self::from_transport(
+            (body_sender, res_body),
+            negotiated,
+            connection.frame_buffer_pool,
+            connection.write_coalescing,
+            executor,
+            reconnect_policy.map(|reconnect_policy| (connection, reconnect_policy)),
+            keepalive,
+        ))
+    }
 
-        let body_sender_weak = Arc::downgrade(&body_sender);
-        let tube_mgrs2 = tube_managers.clone();
-        tokio::spawn(async move {
-            let mut tube_mgrs = tube_mgrs2;
-            let mut frame_decoder = frame::Decoder::new();
-            let mut frame_handler = frame::FrameHandler::new(
-                PeerType::Client,
-                &mut tube_mgrs,
-            );
+    /**
+     * A Channel whose frames are carried over the given in-memory transport 
+     * rather than an HTTP connection (see testing::loopback()).
+     */
+    #[cfg(feature = "testing")]
+    pub(in crate) fn new_loopback(
+        body_sender: hyper::body::Sender,
+        res_body: hyper::Body,
+        executor: ChannelExecutor,
+    ) -> Self {
+        Self::from_transport(
+            (body_sender, res_body),
+            Negotiated {
+                framing_version: frame::FramingVersion::LATEST,
+                max_payload_frame_size: frame::MAX_PAYLOAD_FRAME_SIZE,
+            },
+            frame::BufferPoolConfig::default(),
+            frame::WriteCoalescing::default(),
+            executor,
+            None,
+            None,
+        )
+    }
+
+    /**
+     * Starts serving a Channel on a transport that's already established. 
+     * Without a connection (and ReconnectPolicy) to re-establish it with, 
+     * the Channel fails once the transport does.
+     */
+    fn from_transport(
+        transport: impl frame::Transport,
+        negotiated: Negotiated,
+        buffer_pool: frame::BufferPoolConfig,
+        write_coalescing: frame::WriteCoalescing,
+        executor: ChannelExecutor,
+        reconnection: Option<(ChannelConnection, ReconnectPolicy)>,
+        keepalive: Option<Keepalive>,
+    ) -> Self {
+        let framing_version = negotiated.framing_version;
+        let (frame_sink, frame_stream) = transport.into_parts();
+        let frame_sender = frame::FrameSender::new_with_buffer_pool(
+            frame_sink,
+            framing_version,
+            frame::FrameInterceptors::new(),
+            buffer_pool,
+        )
+            .with_executor(executor.clone())
+            .with_max_payload_frame_size(negotiated.max_payload_frame_size)
+            .with_write_coalescing(write_coalescing);
+        // Server-initiated tubes aren't supported yet, so the context doesn't
+        // accept peer tubes.
+        let ctx = ChannelContext::new(
+            PeerType::Client,
+            frame::ExtensionFrameHandlers::new(),
+        ).with_executor(executor);
+        ctx.set_frame_sender(frame_sender.downgrade());
 
-            while let Some(data_result) = res_body.data().await {
-                // This seems hacky...but it works.
-                //
-                // When the sender is dropped, res_body.data().await yields 
-                // Some(Buf{}) (an empty Buf)...presumably to indicate EOM? 
-                // Weird...but I guess it works?
-                //
-                // A better solution might be to wrap res_body.data() inside some
-                // stream that ends when EITHER .data() returns None OR 
-                // body_sender is dropped. That way the async loop 
-                // /intentionally/ polls and stops iterating when all tubes + 
-                // channels have been dropped.
-                let mut body_sender = match body_sender_weak.upgrade() {
-                    Some(body_sender) => body_sender,
-                    None => break,
+        let frame_sender_weak = frame_sender.downgrade();
+        let ctx2 = ctx.clone();
+        ctx.executor().spawn(async move {
+            let mut frame_handler = frame::FrameHandler::new(ctx2.clone());
+            let mut frame_stream = frame_stream;
+            loop {
+                let stream_failure = match read_frames(
+                    &ctx2,
+                    &mut frame_handler,
+                    &frame_sender_weak,
+                    frame_stream,
+                    framing_version,
+                ).await {
+                    Some(stream_failure) => stream_failure,
+                    None => return,
                 };
 
-                let raw_data = match data_result {
-                    Ok(data) => data,
-                    Err(e) => {
-                        log::trace!("Stream of data from server has errored: `{:?}`", e);
-                        break;
-                    }
+                let (frame_sender, (connection, reconnect_policy)) = match (
+                    frame_sender_weak.upgrade(),
+                    &reconnection,
+                ) {
+                    (Some(frame_sender), Some(reconnection)) if !ctx2.is_closed() =>
+                        (frame_sender, reconnection),
+                    _ => {
+                        ctx2.publish_transport_failed(stream_failure.clone());
+                        frame_handler.fail_all_tubes(stream_failure);
+                        return;
+                    },
                 };
 
-                let mut new_frames = match frame_decoder.decode(raw_data.to_vec()) {
-                    Ok(frames) => frames,
-                    Err(e) => {
-                        log::error!("Frame decode error: {:?}", e);
+                log::warn!("Channel transport failed ({}). Reconnecting...", stream_failure);
+                let establishment_frames = frame_handler.fail_established_tubes(
+                    stream_failure.clone()
+                );
+                frame_stream = match reconnect(
+                    connection,
+                    reconnect_policy,
+                    &frame_sender,
+                    establishment_frames,
+                ).await {
+                    Some(frame_stream) => frame_stream,
+                    None => {
+                        ctx2.publish_transport_failed(stream_failure.clone());
+                        frame_handler.fail_all_tubes(stream_failure);
                         return;
                     },
                 };
+                ctx2.clear_transport_failure();
+                ctx2.publish_reconnected();
+            }
+        });
 
-                while let Some(frame) = new_frames.pop_front() {
-                    log::trace!("Processing frame: {:?}", frame);
-                    match frame_handler.handle_frame(frame, &mut body_sender).await {
-                        Ok(frame::FrameHandlerResult::NewTube(_tube)) => {
-                            // TODO: Server-initiated tubes aren't supported yet.
-                            log::error!(
-                                "Received a NewTube frame from the server, but \
-                                 server-initiated tubes aren't supported yet!"
-                            );
-                        },
-                        Ok(frame::FrameHandlerResult::FullyHandled) => (),
-                        Err(e) => log::error!("Error handling frame: {:?}", e),
+        let heartbeat_settings = keepalive.and_then(|keepalive| {
+            keepalive.heartbeat_interval.map(|interval| (interval, keepalive.heartbeat_timeout))
+        });
+        match heartbeat_settings {
+            Some((interval, timeout)) if framing_version.acks_heartbeats() => {
+                ctx.executor().spawn(send_heartbeats(
+                    ctx.clone(),
+                    frame_sender.downgrade(),
+                    interval,
+                    timeout,
+                ));
+            },
+            Some(_) => log::warn!(
+                "Heartbeats are disabled on this channel: The server negotiated \
+                 FramingVersion::{:?}, which doesn't ack them.",
+                 framing_version,
+            ),
+            None => (),
+        }
+
+        Channel {
+            closed: false,
+            ctx,
+            extensions: hyper::http::Extensions::new(),
+            frame_sender,
+            tube_id_allocator: TubeIdAllocator::new(
+                PeerType::Client,
+                framing_version.max_tube_id(),
+            ),
+            tube_limit: None,
+        }
+    }
+
+    /**
+     * Gracefully shuts the Channel down. Tubes the server has finished 
+     * sending on are closed, and every other unfinished Tube is aborted with
+     * AbortReason::ApplicationAbort. Once the server has acknowledged those 
+     * Aborts (waiting up to ack_timeout), the request stream is flushed and
+     * ended.
+     *
+     * Tubes from this Channel that the application still holds can't send 
+     * anything afterwards (sends fail with SendError::ChannelClosed).
+     */
+    pub async fn close(mut self, ack_timeout: Duration) -> Result<(), CloseError> {
+        self.closed = true;
+        self.ctx.close();
+
+        let tube_mgrs = self.ctx.tube_managers.to_vec();
+        let mut frames = vec![];
+        let mut closed_tube_ids = vec![];
+        for (tube_id, tube_mgr) in tube_mgrs {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            use tube::TubeCompletionState::*;
+            match tube_mgr.completion_state {
+                ServerHasFinishedSending => {
+                    frames.push(frame::Frame::ClientHasFinishedSending { tube_id });
+                    tube_mgr.set_completion_state(Closed);
+                    closed_tube_ids.push(tube_id);
+                },
+                Open | ClientHasFinishedSending => {
+                    let reason = frame::AbortReason::ApplicationAbort;
+                    frames.push(frame::Frame::Abort { tube_id, reason: reason.clone() });
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::Abort(reason.clone()));
+                    tube_mgr.set_completion_state(AbortedFromLocal(reason));
+                    // Tubes the application still holds would otherwise 
+                    // abort again when they're dropped
+                    tube_mgr.abort_ack_pending = true;
+                    if let Some(waker) = tube_mgr.waker.take() {
+                        waker.wake();
                     }
-                }
+                },
+                Closed | AbortedFromLocal(_) | AbortedFromRemote(_) => (),
             }
-        });
+        }
+        for tube_id in closed_tube_ids {
+            self.ctx.tube_managers.remove(tube_id);
+        }
+
+        log::trace!("Closing channel ({} frames to send first)...", frames.len());
+        let result = match self.frame_sender.send_batch(frames).await {
+            Ok(()) => {
+                let tubes_settled = futures::future::poll_fn(|cx| {
+                    self.ctx.poll_tubes_settled(cx)
+                });
+                match tokio::time::timeout(ack_timeout, tubes_settled).await {
+                    Ok(()) => Ok(()),
+                    Err(_) => Err(CloseError::TimedOutWaitingOnAbortAcks(ack_timeout)),
+                }
+            },
+            Err(e) => Err(CloseError::FrameSendError(e)),
+        };
+        self.frame_sender.close().await;
+        result
+    }
+
+    /**
+     * A typed map where applications can stash per-channel context (auth 
+     * principal, tenant, trace span, etc).
+     */
+    pub fn extensions(&self) -> &hyper::http::Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut hyper::http::Extensions {
+        &mut self.extensions
+    }
+
+    /**
+     * Registers an interceptor that can inspect, rewrite, or veto every frame 
+     * sent on this channel (including frames sent by Tubes that were created 
+     * before the interceptor was added). Interceptors run in the order they 
+     * were added.
+     */
+    pub fn add_outgoing_frame_interceptor(
+        &mut self, 
+        interceptor: impl frame::FrameInterceptor + 'static,
+    ) {
+        self.frame_sender.interceptors().add(interceptor);
+    }
+
+    /**
+     * Registers an observer that is told about every frame received from the
+     * server that is rejected as a protocol violation, along with the raw 
+     * frame (see frame::RejectedFrame).
+     */
+    pub fn add_frame_error_observer(
+        &mut self,
+        observer: impl frame::FrameErrorObserver + 'static,
+    ) {
+        self.ctx.frame_error_observers.add(observer);
+    }
+
+    /**
+     * Registers a handler for ExtensionFrames of the given type_id received 
+     * from the server. type_id must be within the range reserved for 
+     * extensions (frame::MIN_EXTENSION_FRAMETYPE through 
+     * frame::MAX_EXTENSION_FRAMETYPE).
+     */
+    pub fn register_extension_frame_handler(
+        &mut self,
+        type_id: u8,
+        handler: impl frame::ExtensionFrameHandler + 'static,
+    ) -> Result<(), frame::ExtensionFrameRegistrationError> {
+        self.ctx.extension_frame_handlers.register(type_id, handler)
+    }
+
+    /**
+     * Sets what happens to payloads that arrive for Tubes on this Channel 
+     * that have already been closed or aborted. Defaults to 
+     * LatePayloadPolicy::CountAndLog.
+     */
+    pub fn set_late_payload_policy(&mut self, policy: frame::LatePayloadPolicy) {
+        self.ctx.set_late_payload_policy(policy);
+    }
+
+    /**
+     * Sets how long each Tube made on this Channel from here on waits for 
+     * the server to ack a payload, and what happens to the Tube when an ack
+     * doesn't come in time (see tube::AckTimeoutPolicy). None (the default)
+     * only fails the send that timed out.
+     */
+    pub fn set_ack_timeout_policy(&mut self, policy: Option<tube::AckTimeoutPolicy>) {
+        self.ctx.set_ack_timeout_policy(policy);
+    }
+
+    /**
+     * Bounds the TubeEvents queued on each Tube made on this Channel from 
+     * here on (see Tube::set_event_queue_limit()). None (the default) queues
+     * them without limit.
+     */
+    pub fn set_event_queue_limit(&mut self, limit: Option<tube::EventQueueLimit>) {
+        self.ctx.set_event_queue_limit(limit);
+    }
+
+    /**
+     * Bounds how many unfinished Tubes this Channel has at once. None (the 
+     * default) doesn't limit them. Tubes made before the limit was set count
+     * against it.
+     */
+    pub fn set_tube_limit(&mut self, tube_limit: Option<TubeLimit>) {
+        self.tube_limit = tube_limit;
+    }
 
-        Ok(Channel {
-            body_sender: body_sender,
-            tube_id_manager: UniqueIdManager::new_with_odd_ids(),
-            tube_managers,
-        })
+    /**
+     * Whether new Tubes can still be made on this Channel: it hasn't been 
+     * closed, its transport hasn't failed (for good), and the server hasn't
+     * sent a GoAway.
+     */
+    pub fn can_make_tubes(&self) -> bool {
+        !self.closed 
+            && self.ctx.transport_failure().is_none() 
+            && self.ctx.peer_going_away().is_none()
+    }
+
+    /**
+     * The number of Tubes on this Channel that haven't been closed or 
+     * aborted yet.
+     */
+    pub fn num_unfinished_tubes(&self) -> usize {
+        self.ctx.num_unfinished_tubes()
+    }
+
+    /**
+     * Why this Channel's transport failed, if it has (and wasn't 
+     * re-established per the Client's ReconnectPolicy).
+     */
+    pub fn transport_failure(&self) -> Option<String> {
+        self.ctx.transport_failure()
+    }
+
+    /**
+     * How often this Channel's outgoing frames were encoded into a reused
+     * buffer rather than a newly allocated one (see
+     * ClientBuilder::with_frame_buffer_pool()). These carry over when the
+     * Channel reconnects.
+     */
+    pub fn buffer_pool_stats(&self) -> frame::BufferPoolStats {
+        self.frame_sender.buffer_pool_stats()
+    }
+
+    /**
+     * The number of payloads counted (per the LatePayloadPolicy) that arrived
+     * for Tubes on this Channel that had already been closed or aborted.
+     */
+    pub fn num_late_payloads(&self) -> u64 {
+        self.ctx.num_late_payloads()
+    }
+
+    /**
+     * Records every chunk of frames sent and received on this channel from
+     * here on (see capture::CaptureReplayer for reading a capture back).
+     * Passing None stops recording.
+     */
+    pub fn set_frame_capture(&mut self, frame_capture: Option<capture::FrameCapture>) {
+        self.ctx.set_frame_capture(frame_capture);
+    }
+
+    /**
+     * Sends the server a Heartbeat and resolves with the round-trip time once
+     * it's acked (waiting up to timeout), e.g. to health-check this Channel 
+     * or collect latency metrics.
+     */
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration, PingError> {
+        self.ctx.ping(timeout).await
+    }
+
+    pub async fn send_extension_frame(
+        &mut self,
+        type_id: u8,
+        payload: Vec<u8>,
+    ) -> Result<(), frame::FrameSendError> {
+        self.frame_sender.send(frame::Frame::ExtensionFrame { type_id, payload }).await
+    }
+
+    /**
+     * Returns a future that resolves once every Tube created on this Channel 
+     * has been closed or aborted, yielding the id and TubeOutcome of each. 
+     * This is useful for shutdown code that needs to wait for in-flight work
+     * to finish without tracking Tubes itself.
+     */
+    pub fn join_all_tubes(&self) -> tube::JoinAllTubes {
+        self.ctx.tube_tracker.join_all()
     }
 
     pub async fn make_tube(
         &mut self, 
         headers: HashMap<String, String>,
     ) -> Result<tube::Tube, MakeTubeError> {
-        let tube_id = match self.tube_id_manager.take_id() {
+        self.make_tube_impl(string_headers_to_bytes(headers), false).await
+    }
+
+    /**
+     * Like make_tube(), but header values may be arbitrary bytes (e.g. 
+     * protobuf-encoded metadata). Non-UTF-8 values can only be sent on 
+     * channels that negotiated FramingVersion::V3 or later; on older channels
+     * they fail with a FrameEncodeError.
+     */
+    pub async fn make_tube_with_binary_headers(
+        &mut self, 
+        headers: HashMap<String, Vec<u8>>,
+    ) -> Result<tube::Tube, MakeTubeError> {
+        self.make_tube_impl(headers, false).await
+    }
+
+    /**
+     * Makes a Tube that the server streams to indefinitely (e.g. a server-push
+     * feed). The Tube is marked with the RECEIVE_ONLY_HEADER and the client 
+     * finishes sending on it immediately, so the returned Tube can only be 
+     * used to receive.
+     */
+    pub async fn make_receive_only_tube(
+        &mut self,
+        headers: HashMap<String, String>,
+    ) -> Result<tube::Tube, MakeTubeError> {
+        let mut headers = string_headers_to_bytes(headers);
+        headers.insert(tube::RECEIVE_ONLY_HEADER.to_string(), b"1".to_vec());
+        self.make_tube_impl(headers, true).await
+    }
+
+    async fn make_tube_impl(
+        &mut self, 
+        headers: HashMap<String, Vec<u8>>,
+        finished_sending: bool,
+    ) -> Result<tube::Tube, MakeTubeError> {
+        // With TubeLimitPolicy::Wait this waits for one of the Channel's Tubes
+        // to finish
+        if let Some(tube_limit) = self.tube_limit {
+            let has_capacity = futures::future::poll_fn(|cx| {
+                match (self.ctx.poll_tube_capacity(tube_limit.max_tubes, cx), tube_limit.policy) {
+                    (futures::task::Poll::Pending, TubeLimitPolicy::Wait) => 
+                        futures::task::Poll::Pending,
+                    (capacity, _) => futures::task::Poll::Ready(capacity.is_ready()),
+                }
+            }).await;
+            if !has_capacity {
+                return Err(MakeTubeError::AtCapacity);
+            }
+        }
+        if let Some(reason) = self.ctx.peer_going_away() {
+            return Err(MakeTubeError::ServerGoingAway(reason));
+        }
+        let tube_id = match self.tube_id_allocator.take_id() {
           Ok(id) => id,
           Err(UniqueIdError::NoIdsAvailable) => 
             return Err(MakeTubeError::TubeIdsExhausted),
         };
         let tube_id_val = tube_id.val();
-        let estab_tube_frame = match frame::encode::newtube_frame(tube_id.val(), headers) {
-            Ok(data) => data,
-            Err(e) => return Err(MakeTubeError::FrameEncodeError(e)),
-        };
+        let acks_new_tubes = self.frame_sender.framing_version().acks_new_tubes();
+        let mut frames = vec![frame::Frame::NewTube {
+            tube_id: tube_id_val,
+            headers: headers.clone(),
+        }];
+        // Batch the HasFinishedSending in with the NewTube so both go out in a
+        // single write.
+        if finished_sending {
+            frames.push(frame::Frame::ClientHasFinishedSending { tube_id: tube_id_val });
+        }
+
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.payload_checksums = tube::payload_checksums_requested(&headers);
+        tube_mgr.receive_only = tube::receive_only_requested(&headers);
+        tube_mgr.cumulative_acks = tube::cumulative_acks_requested(&headers);
+        tube_mgr.event_queue_limit = self.ctx.event_queue_limit();
+        tube_mgr.ack_timeout_policy = self.ctx.ack_timeout_policy();
+        tube_mgr.executor = self.ctx.executor().clone();
+        tube_mgr.channel_tube_managers = Some(Arc::downgrade(&self.ctx.tube_managers));
+        tube_mgr.establishment_pending = acks_new_tubes;
+        if acks_new_tubes {
+            tube_mgr.establishment_frames = frames.clone();
+        }
+        if finished_sending {
+            tube_mgr.completion_state = tube::TubeCompletionState::ClientHasFinishedSending;
+        }
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+
+        // Tracked before the NewTube is sent so that the server's response to
+        // it always finds the Tube.
+        if !self.ctx.tube_managers.try_insert(tube_id_val, tube_mgr.clone()) {
+            return Err(MakeTubeError::InternalErrorDuplicateTubeId(tube_id_val));
+        }
 
-        {
-            let mut body_sender = self.body_sender.lock().await;
-            log::trace!("Sending MakeTube(id={}) frame...", &tube_id);
-            if let Err(_bytes) = body_sender.send_data(estab_tube_frame.into()).await {
+        log::trace!("Sending MakeTube(id={}) frame...", &tube_id);
+        if let Err(e) = self.frame_sender.send_batch(frames).await {
+            self.ctx.tube_managers.remove(tube_id_val);
+            return Err(match e {
+                frame::FrameSendError::ChannelClosed => MakeTubeError::ChannelClosed,
+                frame::FrameSendError::FrameEncodeError(e) => 
+                    MakeTubeError::FrameEncodeError(e),
+                frame::FrameSendError::FrameVetoed(reason) => 
+                    MakeTubeError::FrameVetoed(reason),
                 // TODO: Should we panic here? Is it possible that the data was 
                 //       sent (even with some kind of error here) and now the 
                 //       client/server have disjoint states?
                 //      
                 //       Need to think this through more...
-                return Err(MakeTubeError::UnknownTransportError);
-            }
-        };
+                frame::FrameSendError::TransportError(_) => 
+                    MakeTubeError::UnknownTransportError,
+            });
+        }
 
-        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        // If this future is dropped while waiting on the server, dropping the
+        // Tube aborts it like any other unfinished Tube.
         let tube = tube::Tube::new(
             PeerType::Client, 
             tube_id, 
-            self.body_sender.clone(), 
+            headers,
+            self.frame_sender.clone(), 
             tube_mgr.clone(),
         );
 
-        let mut tube_managers = self.tube_managers.lock().unwrap();
-        if let Err(_) = tube_managers.try_insert(tube_id_val, tube_mgr) {
-            return Err(MakeTubeError::InternalErrorDuplicateTubeId(tube_id_val));
+        if acks_new_tubes {
+            log::trace!("Waiting for the server to ack MakeTube(id={})...", tube_id_val);
+            let establishment = futures::future::poll_fn(|cx| {
+                tube_mgr.lock().unwrap().poll_establishment(cx)
+            }).await;
+            if let Err(event) = establishment {
+                log::trace!("Server rejected MakeTube(id={}): {:?}", tube_id_val, event);
+                {
+                    let mut tube_mgr = tube_mgr.lock().unwrap();
+                    if !tube_mgr.completion_state.is_terminal() {
+                        // The server never tracked the Tube, so there's 
+                        // nothing to abort when it's dropped.
+                        tube_mgr.set_completion_state(
+                            tube::TubeCompletionState::AbortedFromRemote(
+                                frame::AbortReason::ApplicationError
+                            )
+                        );
+                    }
+                }
+                tube::TubeManager::untrack(&tube_mgr, tube_id_val);
+                return Err(rejection_to_make_tube_error(event));
+            }
         }
 
+        self.ctx.tube_tracker.track(&tube);
+
         Ok(tube)
     }
 }
 
+impl futures::stream::Stream for Channel {
+    type Item = ChannelEvent;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Option<Self::Item>> {
+        self.ctx.poll_next_event(cx)
+    }
+}
+impl Drop for Channel {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        self.ctx.close();
+
+        // Tubes the application still holds keep using the transport, so the
+        // request stream is only ended once they've all finished.
+        let ctx = self.ctx.clone();
+        let frame_sender = self.frame_sender.clone();
+        self.ctx.executor().spawn(async move {
+            futures::future::poll_fn(|cx| ctx.poll_tubes_settled(cx)).await;
+            log::trace!("Every Tube on the dropped channel has finished. Closing it...");
+            frame_sender.close().await;
+        });
+    }
+}
+
+/**
+ * Where channels go when they can't be made over HTTP/2 (see 
+ * ClientBuilder::with_http2_only()).
+ */
+#[derive(Clone)]
+pub(in crate::client) struct Http1Fallback {
+    pub(in crate::client) hyper_client: hyper::Client<Connector>,
+    /**
+     * Shared by the Client's channels, so that once one of them has fallen 
+     * back the rest go straight to HTTP/1.1.
+     */
+    pub(in crate::client) in_use: Arc<AtomicBool>,
+}
+
+/**
+ * What the server settled on for a channel as it was established.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(in crate::client) struct Negotiated {
+    framing_version: frame::FramingVersion,
+    max_payload_frame_size: usize,
+}
+
+/**
+ * What's needed to (re-)establish a Channel's transport.
+ */
+#[derive(Clone)]
+pub(in crate::client) struct ChannelConnection {
+    /**
+     * Makes the connections of channels that aren't carried over HTTP (see 
+     * ChannelTransport::Raw).
+     */
+    pub(in crate::client) connector: Connector,
+    pub(in crate::client) frame_buffer_pool: frame::BufferPoolConfig,
+    pub(in crate::client) headers: HashMap<String, String>,
+    pub(in crate::client) http1_fallback: Option<Http1Fallback>,
+    pub(in crate::client) hyper_client: hyper::Client<Connector>,
+    /**
+     * The largest payload frame size the server is asked for.
+     */
+    pub(in crate::client) max_payload_frame_size: usize,
+    pub(in crate::client) server_uri: hyper::Uri,
+    pub(in crate::client) transport: ChannelTransport,
+    pub(in crate::client) write_coalescing: frame::WriteCoalescing,
+}
+impl ChannelConnection {
+    /**
+     * The request that opens the channel, carrying its headers.
+     */
+    fn make_request(
+        &self,
+        method: hyper::Method,
+        body: hyper::Body,
+    ) -> Result<hyper::Request<hyper::Body>, ChannelConnectError> {
+        let mut req_builder = hyper::Request::builder()
+          .method(method)
+          .uri(format!("{}", self.server_uri));
+        for (name, value) in &self.headers {
+            let header_name = match hyper::header::HeaderName::from_bytes(name.as_bytes()) {
+                Ok(header_name) => header_name,
+                Err(_) => return Err(ChannelConnectError::InvalidHeader(name.clone())),
+            };
+            let mut header_value = match hyper::header::HeaderValue::from_str(value) {
+                Ok(header_value) => header_value,
+                Err(_) => return Err(ChannelConnectError::InvalidHeader(name.clone())),
+            };
+            // Keeps credentials out of HTTP/2 header compression tables
+            if header_name == hyper::header::AUTHORIZATION || 
+                    header_name == hyper::header::PROXY_AUTHORIZATION {
+                header_value.set_sensitive(true);
+            }
+            req_builder = req_builder.header(header_name, header_value);
+        }
+        let mut req = req_builder.body(body).unwrap();
+        req.headers_mut().insert(
+            frame::FRAMING_VERSION_HEADER, 
+            hyper::header::HeaderValue::from_static(frame::FramingVersion::LATEST.header_value()),
+        );
+        req.headers_mut().insert(
+            frame::MAX_PAYLOAD_FRAME_SIZE_HEADER,
+            hyper::header::HeaderValue::from(self.max_payload_frame_size),
+        );
+        Ok(req)
+    }
+
+    /**
+     * What the server settled on for the channel, per the headers of its
+     * response.
+     */
+    fn negotiate(&self, res_headers: &hyper::HeaderMap) -> Negotiated {
+        let header_str = |name| res_headers.get(name).and_then(|value| value.to_str().ok());
+        let negotiated = Negotiated {
+            framing_version: frame::FramingVersion::negotiate(
+                header_str(frame::FRAMING_VERSION_HEADER),
+            ),
+            max_payload_frame_size: frame::negotiate_max_payload_frame_size(
+                self.max_payload_frame_size,
+                header_str(frame::MAX_PAYLOAD_FRAME_SIZE_HEADER),
+            ),
+        };
+        log::trace!("Negotiated {:?}", negotiated);
+        negotiated
+    }
+
+    async fn connect(
+        &self,
+    ) -> Result<(hyper::body::Sender, hyper::Body, Negotiated), ChannelConnectError> {
+        match self.transport {
+            ChannelTransport::Http2 => (),
+            ChannelTransport::Raw => return self.connect_raw().await,
+            #[cfg(feature = "websocket")]
+            ChannelTransport::WebSocket => return self.connect_websocket().await,
+        }
+        let (body_sender, response) = match &self.http1_fallback {
+            Some(http1_fallback) if http1_fallback.in_use.load(Ordering::Relaxed) =>
+                self.request_channel(&http1_fallback.hyper_client).await?,
+            // Failing to connect at all says nothing about whether HTTP/2 
+            // is spoken, so only requests that fail once connected fall back.
+            Some(http1_fallback) => match self.request_channel(&self.hyper_client).await {
+                Err(ChannelConnectError::InitError(e)) if !e.is_connect() => {
+                    log::warn!(
+                        "Channel request to {} failed over HTTP/2 ({}). Retrying over HTTP/1.1...",
+                        self.server_uri,
+                        e,
+                    );
+                    match self.request_channel(&http1_fallback.hyper_client).await {
+                        Ok(requested) => {
+                            http1_fallback.in_use.store(true, Ordering::Relaxed);
+                            requested
+                        },
+                        Err(_) => return Err(ChannelConnectError::InitError(e)),
+                    }
+                },
+                requested => requested?,
+            },
+            None => self.request_channel(&self.hyper_client).await?,
+        };
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = match hyper::body::to_bytes(response.into_body()).await {
+                Ok(detail) => String::from_utf8_lossy(&detail).into_owned(),
+                Err(_) => String::new(),
+            };
+            return Err(ChannelConnectError::Rejected { status, detail });
+        }
+        let negotiated = self.negotiate(response.headers());
+        Ok((body_sender, response.into_body(), negotiated))
+    }
+
+    /**
+     * Sends the request that opens the channel with hyper_client, returning 
+     * the sender for its body along with the server's response.
+     */
+    async fn request_channel(
+        &self,
+        hyper_client: &hyper::Client<Connector>,
+    ) -> Result<(hyper::body::Sender, hyper::Response<hyper::Body>), ChannelConnectError> {
+        let (body_sender, req_body) = hyper::Body::channel();
+        let req = self.make_request(hyper::Method::POST, req_body)?;
+
+        log::trace!("Sending channel request to {}...", self.server_uri);
+        match hyper_client.request(req).await {
+            Ok(response) => Ok((body_sender, response)),
+            Err(e) => Err(ChannelConnectError::InitError(e)),
+        }
+    }
+
+    /**
+     * Like connect(), but makes a connection of the channel's own and carries
+     * the channel straight over it (see ClientBuilder::with_raw_transport()).
+     */
+    async fn connect_raw(
+        &self,
+    ) -> Result<(hyper::body::Sender, hyper::Body, Negotiated), ChannelConnectError> {
+        let req = self.make_request(hyper::Method::POST, hyper::Body::empty())?;
+        let (req_parts, _) = req.into_parts();
+        let req_head = raw_transport::RawHead {
+            subject: match req_parts.uri.path_and_query() {
+                Some(path_and_query) => path_and_query.to_string(),
+                None => "/".to_string(),
+            },
+            headers: req_parts.headers,
+        };
+
+        log::trace!("Making raw channel connection to {}...", self.server_uri);
+        let mut connector = self.connector.clone();
+        let connected = match futures::future::poll_fn(|cx| {
+            hyper::service::Service::poll_ready(&mut connector, cx)
+        }).await {
+            Ok(()) => hyper::service::Service::call(&mut connector, self.server_uri.clone()).await,
+            Err(e) => Err(e),
+        };
+        let mut io = match connected {
+            Ok(io) => tokio::io::BufReader::new(io),
+            Err(e) => return Err(ChannelConnectError::RawConnectFailed(
+                format!("Connecting failed: {}", e),
+            )),
+        };
+        let res_head = match io.write_all(&req_head.encode()).await {
+            Ok(()) => raw_transport::RawHead::read(&mut io).await,
+            Err(e) => Err(raw_transport::RawHeadError::IoError(e)),
+        };
+        let res_head = match res_head {
+            Ok(res_head) => res_head,
+            Err(e) => return Err(ChannelConnectError::RawConnectFailed(
+                format!("Exchanging heads failed: {}", e),
+            )),
+        };
+        let status = match hyper::StatusCode::from_bytes(res_head.subject.as_bytes()) {
+            Ok(status) => status,
+            Err(_) => return Err(ChannelConnectError::RawConnectFailed(
+                format!("Invalid status: `{}`", res_head.subject),
+            )),
+        };
+        if status != hyper::StatusCode::OK {
+            // The server ends the connection after the detail, but there's 
+            // no need to buffer an unbounded amount of it.
+            let mut detail = vec![];
+            let _ = (&mut io).take(64 * 1024).read_to_end(&mut detail).await;
+            let detail = String::from_utf8_lossy(&detail).into_owned();
+            return Err(ChannelConnectError::Rejected { status, detail });
+        }
+        let negotiated = self.negotiate(&res_head.headers);
+
+        let (body_sender, req_body) = hyper::Body::channel();
+        let (incoming_sender, res_body) = hyper::Body::channel();
+        tokio::spawn(raw_transport::bridge(io, incoming_sender, req_body));
+        Ok((body_sender, res_body, negotiated))
+    }
+
+    /**
+     * Like connect(), but opens a WebSocket and carries the channel over it.
+     */
+    #[cfg(feature = "websocket")]
+    async fn connect_websocket(
+        &self,
+    ) -> Result<(hyper::body::Sender, hyper::Body, Negotiated), ChannelConnectError> {
+        let key = websocket::new_key();
+        let mut req = self.make_request(hyper::Method::GET, hyper::Body::empty())?;
+        let headers = req.headers_mut();
+        headers.insert(
+            hyper::header::CONNECTION,
+            hyper::header::HeaderValue::from_static("upgrade"),
+        );
+        headers.insert(
+            hyper::header::UPGRADE,
+            hyper::header::HeaderValue::from_static("websocket"),
+        );
+        headers.insert(
+            hyper::header::SEC_WEBSOCKET_VERSION,
+            hyper::header::HeaderValue::from_static(websocket::VERSION),
+        );
+        match hyper::header::HeaderValue::from_str(&key) {
+            Ok(key) => headers.insert(hyper::header::SEC_WEBSOCKET_KEY, key),
+            Err(e) => unreachable!("Base64 is always a valid header value: {:?}", e),
+        };
+
+        log::trace!("Sending WebSocket channel request to {}...", self.server_uri);
+        let mut response = match self.hyper_client.request(req).await {
+            Ok(response) => response,
+            Err(e) => return Err(ChannelConnectError::InitError(e)),
+        };
+        if response.status() != hyper::StatusCode::SWITCHING_PROTOCOLS {
+            let status = response.status();
+            let detail = match hyper::body::to_bytes(response.into_body()).await {
+                Ok(detail) => String::from_utf8_lossy(&detail).into_owned(),
+                Err(_) => String::new(),
+            };
+            return Err(ChannelConnectError::Rejected { status, detail });
+        }
+        let expected_accept_key = websocket::accept_key(key.as_bytes());
+        match response.headers().get(hyper::header::SEC_WEBSOCKET_ACCEPT) {
+            Some(accept_key) if accept_key == expected_accept_key.as_str() => (),
+            accept_key => return Err(ChannelConnectError::WebSocketHandshakeFailed(format!(
+                "Expected Sec-WebSocket-Accept `{}`, but got {:?}",
+                expected_accept_key,
+                accept_key,
+            ))),
+        }
+        let negotiated = self.negotiate(response.headers());
+
+        let upgraded = match hyper::upgrade::on(&mut response).await {
+            Ok(upgraded) => upgraded,
+            Err(e) => return Err(ChannelConnectError::InitError(e)),
+        };
+        let (body_sender, req_body) = hyper::Body::channel();
+        let (incoming_sender, res_body) = hyper::Body::channel();
+        tokio::spawn(websocket::bridge(upgraded, incoming_sender, req_body, PeerType::Client));
+        Ok((body_sender, res_body, negotiated))
+    }
+}
+
+/**
+ * Handles the frames received on frame_stream until it ends or fails, returning
+ * why. Returns None if every FrameSender for the channel (i.e. the Channel 
+ * and all of its Tubes) has been dropped, since there's nobody left to tell.
+ */
+async fn read_frames(
+    ctx: &ChannelContext,
+    frame_handler: &mut frame::FrameHandler,
+    frame_sender_weak: &frame::WeakFrameSender,
+    mut frame_stream: frame::FrameStream,
+    framing_version: frame::FramingVersion,
+) -> Option<String> {
+    let mut frame_decoder = frame::Decoder::new_with_version(framing_version);
+    loop {
+        let data_result = tokio::select! {
+            data_result = frame_stream.next() => match data_result {
+                Some(data_result) => data_result,
+                None => return Some("Server closed the channel's response stream".to_string()),
+            },
+            failure = ctx.transport_failed() => return Some(failure),
+        };
+
+        // This seems hacky...but it works.
+        //
+        // When the sender is dropped, frame_stream.next().await yields 
+        // Some(Buf{}) (an empty Buf)...presumably to indicate EOM? 
+        // Weird...but I guess it works?
+        //
+        // A better solution might be to wrap frame_stream inside some
+        // stream that ends when EITHER .next() returns None OR 
+        // frame_sender is dropped. That way the async loop 
+        // /intentionally/ polls and stops iterating when all tubes + 
+        // channels have been dropped.
+        //
+        // When all tubes + channels have been dropped there's nobody
+        // left to notify, so just stop.
+        let frame_sender = frame_sender_weak.upgrade()?;
+
+        let raw_data = match data_result {
+            Ok(data) => data,
+            Err(e) => {
+                log::trace!("Stream of data from server has errored: `{:?}`", e);
+                return Some(format!("Stream of data from server has errored: {}", e));
+            }
+        };
+        ctx.record_incoming(&raw_data, framing_version);
+
+        let mut new_frames = match frame_decoder.decode_bytes(raw_data) {
+            Ok(frames) => frames,
+            Err(e) => {
+                log::error!("Frame decode error: {:?}", e);
+                return Some(format!("Frame decode error: {:?}", e));
+            },
+        };
+
+        while let Some(frame) = new_frames.pop_front() {
+            log::trace!("Processing frame: {:?}", frame);
+            if let Err(e) = frame_handler.handle_frame(frame, &frame_sender).await {
+                log::error!("Error handling frame: {:?}", e);
+            }
+        }
+        if let Err(e) = frame_handler.flush_deferred_acks(&frame_sender).await {
+            log::error!("Error sending deferred acks: {:?}", e);
+        }
+    }
+}
+
+/**
+ * Sends a Heartbeat every interval until the Channel is closed or dropped. If
+ * one isn't acked within timeout, the Channel's transport is failed (see 
+ * ChannelContext::fail_transport()).
+ */
+async fn send_heartbeats(
+    ctx: ChannelContext,
+    frame_sender_weak: frame::WeakFrameSender,
+    interval: Duration,
+    timeout: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let frame_sender = match frame_sender_weak.upgrade() {
+            Some(frame_sender) => frame_sender,
+            None => return,
+        };
+        if ctx.is_closed() {
+            return;
+        }
+
+        let heartbeat_ack = ctx.next_heartbeat_ack();
+        futures::pin_mut!(heartbeat_ack);
+        heartbeat_ack.as_mut().enable();
+        log::trace!("Sending Heartbeat...");
+        if let Err(e) = frame_sender.send(frame::Frame::Heartbeat).await {
+            // The reader notices (and handles) a transport that can't be 
+            // sent on.
+            log::trace!("Error sending Heartbeat: {:?}", e);
+            continue;
+        }
+        drop(frame_sender);
+
+        if tokio::time::timeout(timeout, heartbeat_ack).await.is_err() {
+            log::warn!("Heartbeat wasn't acked within {:?}.", timeout);
+            ctx.fail_transport(format!("Heartbeat wasn't acked within {:?}", timeout));
+        }
+    }
+}
+
+/**
+ * Re-establishes a Channel's transport per its ReconnectPolicy, moves the 
+ * Channel's FrameSender onto it, and re-sends establishment_frames (see 
+ * frame::FrameHandler::fail_established_tubes()). Returns the frames 
+ * received on the new transport, or None if every attempt failed (or the 
+ * Channel was closed meanwhile).
+ */
+async fn reconnect(
+    connection: &ChannelConnection,
+    reconnect_policy: &ReconnectPolicy,
+    frame_sender: &frame::FrameSender,
+    establishment_frames: Vec<frame::Frame>,
+) -> Option<frame::FrameStream> {
+    for attempt in 1..=reconnect_policy.max_attempts {
+        tokio::time::sleep(reconnect_policy.backoff(attempt)).await;
+
+        let (body_sender, res_body, negotiated) = match connection.connect().await {
+            Ok(connected) => connected,
+            Err(e) => {
+                log::warn!("Reconnect attempt {} failed: {:?}", attempt, e);
+                continue;
+            },
+        };
+        // Tubes (and their frames) were made for what the channel originally
+        // negotiated, so a server that negotiates anything else won't do.
+        let original = Negotiated {
+            framing_version: frame_sender.framing_version(),
+            max_payload_frame_size: frame_sender.max_payload_frame_size(),
+        };
+        if negotiated != original {
+            log::warn!(
+                "Reconnect attempt {} negotiated {:?} instead of {:?}",
+                attempt,
+                negotiated,
+                original,
+            );
+            continue;
+        }
+        let (frame_sink, frame_stream) = (body_sender, res_body).into_parts();
+        if !frame_sender.replace_transport(frame_sink).await {
+            return None;
+        }
+        if let Err(e) = frame_sender.send_batch(establishment_frames.clone()).await {
+            log::warn!("Reconnect attempt {} failed to re-make Tubes: {:?}", attempt, e);
+            continue;
+        }
+
+        log::info!("Channel reconnected after {} attempt(s)", attempt);
+        return Some(frame_stream);
+    }
+    log::error!("Giving up on reconnecting after {} attempt(s)", reconnect_policy.max_attempts);
+    None
+}
+
+fn rejection_to_make_tube_error(event: tube::TubeEvent) -> MakeTubeError {
+    match event {
+        tube::TubeEvent::Abort(reason) => MakeTubeError::AbortedByServer(reason),
+        tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::PeerError {
+            code: frame::ErrorCode::OverLimit,
+            detail,
+        }) => MakeTubeError::TooManyTubes(detail),
+        tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::PeerError { code, detail }) =>
+            MakeTubeError::Rejected { code, detail },
+        tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::PeerGoingAway(reason)) =>
+            MakeTubeError::ServerGoingAway(reason),
+        tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::TransportError(detail)) =>
+            MakeTubeError::ChannelFailed(detail),
+        event => MakeTubeError::UnexpectedServerResponse(event),
+    }
+}
+
+fn string_headers_to_bytes(headers: HashMap<String, String>) -> HashMap<String, Vec<u8>> {
+    headers.into_iter().map(|(name, value)| (name, value.into_bytes())).collect()
+}
+
 #[cfg(test)]
 mod channel_tests {
-    // TODO
+    use super::*;
+
+    #[test]
+    fn rejections_map_to_make_tube_errors() {
+        match rejection_to_make_tube_error(tube::TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::PeerError {
+                code: frame::ErrorCode::OverLimit,
+                detail: "too many tubes".to_string(),
+            }
+        )) {
+            MakeTubeError::TooManyTubes(detail) => assert_eq!(detail, "too many tubes"),
+            unexpected => panic!("Unexpected error: {:?}", unexpected),
+        }
+        match rejection_to_make_tube_error(tube::TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::PeerError {
+                code: frame::ErrorCode::Draining,
+                detail: "draining".to_string(),
+            }
+        )) {
+            MakeTubeError::Rejected { code: frame::ErrorCode::Draining, detail } =>
+                assert_eq!(detail, "draining"),
+            unexpected => panic!("Unexpected error: {:?}", unexpected),
+        }
+        match rejection_to_make_tube_error(tube::TubeEvent::Abort(
+            frame::AbortReason::ApplicationError,
+        )) {
+            MakeTubeError::AbortedByServer(frame::AbortReason::ApplicationError) => (),
+            unexpected => panic!("Unexpected error: {:?}", unexpected),
+        }
+        match rejection_to_make_tube_error(tube::TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::PeerGoingAway("restarting".to_string())
+        )) {
+            MakeTubeError::ServerGoingAway(reason) => assert_eq!(reason, "restarting"),
+            unexpected => panic!("Unexpected error: {:?}", unexpected),
+        }
+    }
 }
@@ -1,29 +1,80 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
+use crate::frame;
 use crate::tube;
+use crate::ChannelExecutor;
 use super::channel;
+use super::client_builder::ClientBuilder;
+use super::client_builder::TransportSettings;
+use super::Keepalive;
+use super::pool;
+use super::connector::TransportConnector;
+use super::ReconnectPolicy;
+#[cfg(feature = "tls")]
+use super::tls;
 
+/**
+ * Connects channels to the server (with the Client's connector or through
+ * its proxy, if it has either). With the `tls` feature, https:// endpoints 
+ * are connected to over TLS (see Client::set_tls_config()).
+ */
+#[cfg(feature = "tls")]
+pub(in crate::client) type Connector = hyper_rustls::HttpsConnector<TransportConnector>;
+#[cfg(not(feature = "tls"))]
+pub(in crate::client) type Connector = TransportConnector;
+
+#[derive(Debug)]
 pub enum ServerMakeTubeError {
     ChannelConnectError(channel::ChannelConnectError),
     MakeTubeError(channel::MakeTubeError),
 }
 
 pub struct Client {
-  hyper_client: hyper::Client<hyper::client::HttpConnector>,
+  connector: Connector,
+  default_channel_headers: HashMap<String, String>,
+  executor: ChannelExecutor,
+  /**
+   * Whether the Client's channels have fallen back to HTTP/1.1 (see 
+   * channel::Http1Fallback).
+   */
+  http1_fallback_in_use: Arc<AtomicBool>,
+  hyper_client: hyper::Client<Connector>,
   implicit_channel: Option<channel::Channel>,
+  reconnect_policy: Option<ReconnectPolicy>,
   server_uri: hyper::Uri,
+  transport: TransportSettings,
 }
 impl Client {
+  /**
+   * Makes a Client with hyper's default transport settings (see 
+   * Client::builder() for tuning them).
+   */
   pub fn new(server_uri: hyper::Uri) -> Self {
-    let hyper_client: hyper::Client<hyper::client::HttpConnector> = 
-      hyper::Client::builder()
-        .http2_only(true)
-        .build_http();
+    ClientBuilder::new(server_uri).build()
+  }
 
+  pub fn builder(server_uri: hyper::Uri) -> ClientBuilder {
+    ClientBuilder::new(server_uri)
+  }
+
+  pub(in crate::client) fn from_builder_parts(
+    server_uri: hyper::Uri,
+    executor: ChannelExecutor,
+    transport: TransportSettings,
+  ) -> Self {
+    let connector = transport.default_connector();
     Client {
-      hyper_client,
+      connector: connector.clone(),
+      default_channel_headers: HashMap::new(),
+      executor,
+      http1_fallback_in_use: Arc::new(AtomicBool::new(false)),
+      hyper_client: transport.build_hyper_client(connector),
       implicit_channel: None,
+      reconnect_policy: None,
       server_uri,
+      transport,
     }
   }
 
@@ -31,7 +82,79 @@ impl Client {
     &mut self,
     headers: HashMap<String, String>,
   ) -> Result<channel::Channel, channel::ChannelConnectError> {
-    channel::Channel::new(&self.hyper_client, headers, &self.server_uri).await
+    self.make_tube_channel_with_executor(headers, self.executor.clone()).await
+  }
+
+  /**
+   * Like make_tube_channel(), but the channel's internal tasks run on the 
+   * given executor (e.g. a runtime dedicated to latency-critical channels).
+   */
+  pub async fn make_tube_channel_with_executor(
+    &mut self,
+    headers: HashMap<String, String>,
+    executor: ChannelExecutor,
+  ) -> Result<channel::Channel, channel::ChannelConnectError> {
+    let connection = self.channel_connection(headers, self.server_uri.clone());
+    channel::Channel::new(
+      connection,
+      executor,
+      self.reconnect_policy,
+      self.transport.keepalive,
+    ).await
+  }
+
+  /**
+   * Like make_tube_channel(), but connects to the given endpoint instead of 
+   * the Client's server_uri. An endpoint that is only a path (e.g. "/chat") 
+   * is resolved against the server_uri's scheme and authority. Hostnames are
+   * resolved by the connector each time a channel connects.
+   */
+  pub async fn make_tube_channel_at(
+    &mut self,
+    endpoint: hyper::Uri,
+    headers: HashMap<String, String>,
+  ) -> Result<channel::Channel, channel::ChannelConnectError> {
+    let endpoint = resolve_endpoint(&self.server_uri, endpoint)?;
+    let connection = self.channel_connection(headers, endpoint);
+    channel::Channel::new(
+      connection,
+      self.executor.clone(),
+      self.reconnect_policy,
+      self.transport.keepalive,
+    ).await
+  }
+
+  /**
+   * Makes a ChannelPool that spreads the Tubes it makes across 
+   * config.channels_per_endpoint Channels to each of config.endpoints. The
+   * pool's Channels are made (and replaced) with the Client's settings at 
+   * the time pool() is called.
+   */
+  pub async fn pool(
+    &mut self,
+    config: pool::PoolConfig,
+  ) -> Result<pool::ChannelPool, channel::ChannelConnectError> {
+    let endpoints = if config.endpoints.is_empty() {
+      vec![self.server_uri.clone()]
+    } else {
+      let mut endpoints = vec![];
+      for endpoint in config.endpoints {
+        endpoints.push(resolve_endpoint(&self.server_uri, endpoint)?);
+      }
+      endpoints
+    };
+    let settings = pool::ChannelSettings {
+      connection: self.channel_connection(config.headers, self.server_uri.clone()),
+      executor: self.executor.clone(),
+      keepalive: self.transport.keepalive,
+      reconnect_policy: self.reconnect_policy,
+    };
+    pool::ChannelPool::connect(
+      settings,
+      endpoints,
+      config.channels_per_endpoint,
+      config.balancing,
+    ).await
   }
 
   pub async fn new_tube(
@@ -54,9 +177,410 @@ impl Client {
           Err(e) => Err(ServerMakeTubeError::MakeTubeError(e)),
       }
   }
+
+  pub fn server_uri(&self) -> &hyper::Uri {
+    &self.server_uri
+  }
+
+  /**
+   * HTTP headers sent when every channel is made (including the channel 
+   * new_tube() makes implicitly), e.g. an Authorization header with a bearer
+   * token. Headers given to make_tube_channel() take precedence over these.
+   */
+  pub fn set_default_channel_headers(&mut self, headers: HashMap<String, String>) {
+    self.default_channel_headers = headers;
+  }
+
+  /**
+   * Keeps channels made from here on alive while they're idle and fails 
+   * them once their connection is found to be dead (see Keepalive). A 
+   * Channel whose transport fails yields a ChannelEvent::TransportFailed (or
+   * reconnects per its ReconnectPolicy) and its unfinished Tubes are 
+   * aborted. None (the default) sends no pings or heartbeats.
+   */
+  pub fn set_keepalive(&mut self, keepalive: Option<Keepalive>) {
+    self.transport.keepalive = keepalive;
+    self.hyper_client = self.transport.build_hyper_client(self.connector.clone());
+  }
+
+  /**
+   * Secures channels made from here on to https:// endpoints per tls_config
+   * (e.g. to trust a private CA or present a client certificate). Without 
+   * this, https:// endpoints are authenticated against Mozilla's root 
+   * certificates (as packaged by webpki-roots).
+   */
+  #[cfg(feature = "tls")]
+  pub fn set_tls_config(
+    &mut self,
+    tls_config: tls::TlsConfig,
+  ) -> Result<(), tls::TlsConfigError> {
+    self.connector = tls::https_connector(
+      tls_config.to_rustls_config()?,
+      self.transport.transport_connector(false),
+    );
+    self.hyper_client = self.transport.build_hyper_client(self.connector.clone());
+    Ok(())
+  }
+
+  /**
+   * Makes channels made from here on re-establish their transport when the
+   * connection to the server fails (see ReconnectPolicy). Once reconnected,
+   * a Channel yields a ChannelEvent::Reconnected. None (the default) leaves
+   * a Channel whose transport failed unusable.
+   */
+  pub fn set_reconnect_policy(&mut self, reconnect_policy: Option<ReconnectPolicy>) {
+    self.reconnect_policy = reconnect_policy;
+  }
+
+  /**
+   * How a channel to server_uri is (re-)established with the Client's 
+   * current settings.
+   */
+  fn channel_connection(
+    &self,
+    headers: HashMap<String, String>,
+    server_uri: hyper::Uri,
+  ) -> channel::ChannelConnection {
+    let http1_fallback = self.transport.build_http1_client(self.connector.clone())
+      .map(|hyper_client| channel::Http1Fallback {
+        hyper_client,
+        in_use: self.http1_fallback_in_use.clone(),
+      });
+    channel::ChannelConnection {
+      connector: self.connector.clone(),
+      frame_buffer_pool: self.transport.frame_buffer_pool,
+      headers: self.channel_headers(headers),
+      http1_fallback,
+      hyper_client: self.hyper_client.clone(),
+      max_payload_frame_size: self.transport.max_payload_frame_size
+        .unwrap_or(frame::MAX_PAYLOAD_FRAME_SIZE),
+      server_uri,
+      transport: self.transport.channel_transport,
+      write_coalescing: self.transport.write_coalescing,
+    }
+  }
+
+  fn channel_headers(&self, headers: HashMap<String, String>) -> HashMap<String, String> {
+    let mut channel_headers = self.default_channel_headers.clone();
+    channel_headers.extend(headers);
+    channel_headers
+  }
+}
+
+fn resolve_endpoint(
+  server_uri: &hyper::Uri,
+  endpoint: hyper::Uri,
+) -> Result<hyper::Uri, channel::ChannelConnectError> {
+  if endpoint.authority().is_some() {
+    if endpoint.scheme().is_none() {
+      return Err(channel::ChannelConnectError::InvalidEndpoint(
+        format!("Endpoint `{}` has no scheme", endpoint)
+      ));
+    }
+    return Ok(endpoint);
+  }
+
+  let mut uri_parts = server_uri.clone().into_parts();
+  uri_parts.path_and_query = endpoint.into_parts().path_and_query;
+  match hyper::Uri::from_parts(uri_parts) {
+    Ok(uri) => Ok(uri),
+    Err(e) => Err(channel::ChannelConnectError::InvalidEndpoint(e.to_string())),
+  }
 }
 
 #[cfg(test)]
 mod client_tests {
-    // TODO
+    use super::*;
+
+    #[test]
+    fn path_only_endpoints_resolve_against_the_server_uri() {
+        let server_uri: hyper::Uri = "http://tubez.example.com:8080/".parse().unwrap();
+        let endpoint = resolve_endpoint(&server_uri, "/chat?room=1".parse().unwrap()).unwrap();
+        assert_eq!(endpoint, "http://tubez.example.com:8080/chat?room=1");
+
+        let endpoint = resolve_endpoint(
+            &server_uri, 
+            "http://127.0.0.1:3000/feed".parse().unwrap(),
+        ).unwrap();
+        assert_eq!(endpoint, "http://127.0.0.1:3000/feed");
+    }
+
+    #[test]
+    fn channel_headers_override_the_defaults() {
+        let mut client = Client::new("http://tubez.example.com/".parse().unwrap());
+        client.set_default_channel_headers(HashMap::from([
+            ("authorization".to_string(), "Bearer default".to_string()),
+            ("x-tenant-id".to_string(), "acme".to_string()),
+        ]));
+
+        let headers = client.channel_headers(HashMap::from([
+            ("authorization".to_string(), "Bearer override".to_string()),
+        ]));
+        assert_eq!(headers, HashMap::from([
+            ("authorization".to_string(), "Bearer override".to_string()),
+            ("x-tenant-id".to_string(), "acme".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn builder_settings_are_kept_for_the_clients_connections() {
+        let client = Client::builder("http://tubez.example.com/".parse().unwrap())
+            .with_connect_timeout(std::time::Duration::from_secs(3))
+            .with_http2_initial_stream_window_size(1 << 20)
+            .with_http2_max_frame_size(1 << 16)
+            .build();
+        assert_eq!(client.transport.connect_timeout, Some(std::time::Duration::from_secs(3)));
+        assert_eq!(client.transport.http2_initial_stream_window_size, Some(1 << 20));
+        assert_eq!(client.transport.http2_initial_connection_window_size, None);
+        assert_eq!(client.transport.http2_max_frame_size, Some(1 << 16));
+        assert!(!client.transport.http2_adaptive_window);
+    }
+
+    #[test]
+    fn endpoints_without_a_scheme_are_rejected() {
+        let server_uri: hyper::Uri = "http://tubez.example.com/".parse().unwrap();
+        match resolve_endpoint(&server_uri, "127.0.0.1:3000".parse().unwrap()) {
+            Err(channel::ChannelConnectError::InvalidEndpoint(_)) => (),
+            unexpected => panic!("Unexpected endpoint: {:?}", unexpected.map(|uri| uri.to_string())),
+        }
+    }
+
+    /**
+     * Serves server::service() over HTTP/1.1 only, echoing each payload sent
+     * on its Tubes back to the client.
+     */
+    #[cfg(feature = "server")]
+    fn serve_http1_only_echo() -> hyper::Uri {
+        use futures::StreamExt;
+        use crate::server::ChannelEvent;
+        use crate::server::ServerEvent;
+
+        let (service, mut server) = crate::server::service();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let service = service.clone();
+            async move { Ok::<_, std::convert::Infallible>(service) }
+        });
+        tokio::spawn(
+            hyper::Server::from_tcp(listener).unwrap().http1_only(true).serve(make_service)
+        );
+        tokio::spawn(async move {
+            while let Some(Ok(ServerEvent::NewChannel(mut channel))) = server.next().await {
+                tokio::spawn(async move {
+                    while let Some(ChannelEvent::NewTube(mut tube)) = channel.next().await {
+                        tokio::spawn(async move {
+                            while let Some(event) = tube.next().await {
+                                if let tube::TubeEvent::Payload(data) = event {
+                                    tube.send_and_forget(data).await.unwrap();
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+        });
+        uri
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn channels_fall_back_to_http1_when_http2_fails() {
+        use futures::StreamExt;
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        let mut client = Client::new(serve_http1_only_echo());
+        // The second channel goes straight to HTTP/1.1
+        for _ in 0..2 {
+            let mut channel = client.make_tube_channel(HashMap::new()).await.unwrap();
+            assert!(client.http1_fallback_in_use.load(Ordering::Relaxed));
+
+            let mut tube = channel.make_tube(HashMap::new()).await.unwrap();
+            tube.send(vec![7; 10_000], Duration::from_secs(5)).await.unwrap();
+            let echoed = tokio::time::timeout(Duration::from_secs(5), tube.next()).await.unwrap();
+            assert_eq!(echoed, Some(tube::TubeEvent::Payload(vec![7; 10_000].into())));
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn payloads_larger_than_the_max_payload_frame_size_arrive_whole() {
+        use futures::StreamExt;
+        use std::time::Duration;
+        use crate::server::ChannelEvent;
+        use crate::server::Server;
+        use crate::server::ServerEvent;
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let mut server = Server::builder()
+            .with_max_payload_frame_size(1024)
+            .build(&addr)
+            .await
+            .unwrap();
+        let uri = format!("http://{}/", server.local_addr().unwrap()).parse().unwrap();
+        let serving = tokio::spawn(async move {
+            let mut channel = match server.next().await {
+                Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+                unexpected => panic!("Unexpected server event: {:?}", unexpected),
+            };
+            let mut tube = match channel.next().await {
+                Some(ChannelEvent::NewTube(tube)) => tube,
+                unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+            };
+            let event = tube.next().await;
+            if let Some(tube::TubeEvent::Payload(data)) = &event {
+                tube.send(data.clone(), Duration::from_secs(5)).await.unwrap();
+            }
+            (event, tube, channel, server)
+        });
+
+        let mut client = Client::new(uri);
+        let mut channel = client.make_tube_channel(HashMap::new()).await.unwrap();
+        let mut tube = channel.make_tube(HashMap::new()).await.unwrap();
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<u8>>();
+        tube.send(data.clone(), Duration::from_secs(5)).await.unwrap();
+        let echoed = tokio::time::timeout(Duration::from_secs(5), tube.next()).await.unwrap();
+        assert_eq!(echoed, Some(tube::TubeEvent::Payload(data.clone().into())));
+
+        let (received, ..) = serving.await.unwrap();
+        assert_eq!(received, Some(tube::TubeEvent::Payload(data.into())));
+    }
+
+    // Serves on a runtime of its own, so that the server (along with its 
+    // connections) can be killed by shutting the runtime down. Counts the 
+    // channels the server accepts. Tubes are echoed, unless takes_tubes is
+    // false, in which case the server holds on to its channels without ever
+    // taking their tubes.
+    #[cfg(feature = "server")]
+    async fn serve_on_own_runtime(
+        addr: std::net::SocketAddr,
+        max_payload_frame_size: Option<usize>,
+        takes_tubes: bool,
+    ) -> (tokio::runtime::Runtime, Arc<std::sync::atomic::AtomicUsize>) {
+        use futures::StreamExt;
+        use std::sync::atomic::Ordering;
+        use crate::server::ChannelEvent;
+        use crate::server::Server;
+        use crate::server::ServerEvent;
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut builder = Server::builder();
+        if let Some(max_payload_frame_size) = max_payload_frame_size {
+            builder = builder.with_max_payload_frame_size(max_payload_frame_size);
+        }
+        let mut server = runtime.spawn(async move { builder.build(&addr).await })
+            .await
+            .unwrap()
+            .unwrap();
+        server.set_max_pending_tubes_per_channel(Some(1));
+
+        let num_channels = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let num_channels2 = num_channels.clone();
+        runtime.spawn(async move {
+            let mut held_channels = vec![];
+            while let Some(Ok(ServerEvent::NewChannel(mut channel))) = server.next().await {
+                num_channels2.fetch_add(1, Ordering::Relaxed);
+                if !takes_tubes {
+                    held_channels.push(channel);
+                    continue;
+                }
+                tokio::spawn(async move {
+                    while let Some(ChannelEvent::NewTube(mut tube)) = channel.next().await {
+                        tokio::spawn(async move {
+                            while let Some(event) = tube.next().await {
+                                if let tube::TubeEvent::Payload(data) = event {
+                                    tube.send_and_forget(data).await.unwrap();
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+        });
+        (runtime, num_channels)
+    }
+
+    #[cfg(feature = "server")]
+    async fn kill(runtime: tokio::runtime::Runtime) {
+        tokio::task::spawn_blocking(move || {
+            runtime.shutdown_timeout(std::time::Duration::from_secs(5));
+        }).await.unwrap();
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn reconnected_channels_remake_the_tubes_the_server_hadnt_acked() {
+        use futures::StreamExt;
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+        use crate::client::ChannelEvent;
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (server, _) = serve_on_own_runtime(addr, None, false).await;
+        let mut client = Client::new(format!("http://{}/", addr).parse().unwrap());
+        client.set_reconnect_policy(Some(ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_attempts: 2,
+            max_backoff: Duration::from_millis(100),
+        }));
+        let mut channel = client.make_tube_channel(HashMap::new()).await.unwrap();
+        let mut established_tube = channel.make_tube(HashMap::new()).await.unwrap();
+
+        // The server never takes the first Tube, so it holds off on acking 
+        // the second one until it's killed and restarted.
+        let (pending_tube, restarted_server) = futures::join!(
+            channel.make_tube(HashMap::new()),
+            async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                kill(server).await;
+                serve_on_own_runtime(addr, None, true).await.0
+            },
+        );
+        let mut remade_tube = pending_tube.unwrap();
+        match tokio::time::timeout(Duration::from_secs(5), channel.next()).await.unwrap() {
+            Some(ChannelEvent::Reconnected) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        match established_tube.next().await {
+            Some(tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::TransportError(_))) => (),
+            unexpected => panic!("Unexpected tube event: {:?}", unexpected),
+        }
+        remade_tube.send(b"hello".to_vec(), Duration::from_secs(5)).await.unwrap();
+        let echoed = tokio::time::timeout(Duration::from_secs(5), remade_tube.next()).await.unwrap();
+        assert_eq!(echoed, Some(tube::TubeEvent::Payload("hello".into())));
+
+        // A server that negotiates something else (here, a smaller max payload
+        // frame size) isn't reconnected to, even though it's reachable.
+        kill(restarted_server).await;
+        let (changed_server, num_channels) = 
+            serve_on_own_runtime(addr, Some(1024), true).await;
+        match tokio::time::timeout(Duration::from_secs(5), channel.next()).await.unwrap() {
+            Some(ChannelEvent::TransportFailed(_)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        assert!(num_channels.load(Ordering::Relaxed) > 0);
+        match remade_tube.next().await {
+            Some(tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::TransportError(_))) => (),
+            unexpected => panic!("Unexpected tube event: {:?}", unexpected),
+        }
+        kill(changed_server).await;
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn http2_only_clients_dont_fall_back_to_http1() {
+        use std::sync::atomic::Ordering;
+
+        let mut client = Client::builder(serve_http1_only_echo()).with_http2_only().build();
+        match client.make_tube_channel(HashMap::new()).await {
+            Err(channel::ChannelConnectError::InitError(e)) => assert!(!e.is_connect()),
+            unexpected => panic!("Unexpected channel: {:?}", unexpected.map(|_| ())),
+        }
+        assert!(!client.http1_fallback_in_use.load(Ordering::Relaxed));
+    }
 }
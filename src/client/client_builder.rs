@@ -0,0 +1,332 @@
+use std::time::Duration;
+
+use crate::common::frame;
+use crate::ChannelExecutor;
+use super::client::Client;
+use super::client::Connector;
+use super::Keepalive;
+use super::connector::ConnectionIo;
+use super::connector::TransportConnector;
+use super::proxy::ProxyConnector;
+use super::ProxyConfig;
+#[cfg(unix)]
+use super::UnixConnector;
+#[cfg(feature = "tls")]
+use super::tls;
+
+/**
+ * What a Client's channels are carried over.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(in crate::client) enum ChannelTransport {
+    /**
+     * A request (and its response) on an HTTP/2 connection per channel.
+     */
+    #[default]
+    Http2,
+    /**
+     * The connection itself, with no HTTP (see 
+     * ClientBuilder::with_raw_transport()).
+     */
+    Raw,
+    /**
+     * A WebSocket (see ClientBuilder::with_websocket_transport()).
+     */
+    #[cfg(feature = "websocket")]
+    WebSocket,
+}
+
+/**
+ * How a Client's connections to the server are tuned (see ClientBuilder).
+ * None leaves a setting at hyper's default.
+ */
+#[derive(Clone, Debug, Default)]
+pub(in crate::client) struct TransportSettings {
+    pub(in crate::client) channel_transport: ChannelTransport,
+    pub(in crate::client) connect_timeout: Option<Duration>,
+    /**
+     * Replaces the built-in connector (see ClientBuilder::with_connector()).
+     */
+    pub(in crate::client) connector: Option<TransportConnector>,
+    /**
+     * Sizes the pool of buffers each channel encodes its frames into.
+     */
+    pub(in crate::client) frame_buffer_pool: frame::BufferPoolConfig,
+    pub(in crate::client) http2_adaptive_window: bool,
+    pub(in crate::client) http2_initial_connection_window_size: Option<u32>,
+    pub(in crate::client) http2_initial_stream_window_size: Option<u32>,
+    pub(in crate::client) http2_max_frame_size: Option<u32>,
+    /**
+     * Whether channels that can't be made over HTTP/2 fail rather than being
+     * made over HTTP/1.1 (see ClientBuilder::with_http2_only()).
+     */
+    pub(in crate::client) http2_only: bool,
+    pub(in crate::client) keepalive: Option<Keepalive>,
+    /**
+     * The largest payload frame size channels ask the server for
+     * (frame::MAX_PAYLOAD_FRAME_SIZE if None).
+     */
+    pub(in crate::client) max_payload_frame_size: Option<usize>,
+    pub(in crate::client) proxy: Option<ProxyConfig>,
+    /**
+     * How each channel coalesces the frames it sends into writes.
+     */
+    pub(in crate::client) write_coalescing: frame::WriteCoalescing,
+}
+impl TransportSettings {
+    pub(in crate::client) fn build_hyper_client(
+        &self,
+        connector: Connector,
+    ) -> hyper::Client<Connector> {
+        // WebSocket handshakes are made over HTTP/1
+        #[cfg(feature = "websocket")]
+        let http2_only = self.channel_transport != ChannelTransport::WebSocket;
+        #[cfg(not(feature = "websocket"))]
+        let http2_only = true;
+        let mut builder = hyper::Client::builder();
+        builder
+            .http2_only(http2_only)
+            .http2_initial_connection_window_size(self.http2_initial_connection_window_size)
+            .http2_initial_stream_window_size(self.http2_initial_stream_window_size)
+            .http2_max_frame_size(self.http2_max_frame_size)
+            .http2_adaptive_window(self.http2_adaptive_window);
+        if let Some(keepalive) = &self.keepalive {
+            builder
+                .http2_keep_alive_interval(keepalive.http2_ping_interval)
+                .http2_keep_alive_timeout(keepalive.http2_ping_timeout);
+        }
+        builder.build(connector)
+    }
+
+    /**
+     * The client that channels fall back to when they can't be made over 
+     * HTTP/2 (see channel::Http1Fallback), or None if they don't fall back.
+     */
+    pub(in crate::client) fn build_http1_client(
+        &self,
+        connector: Connector,
+    ) -> Option<hyper::Client<Connector>> {
+        if self.http2_only || self.channel_transport != ChannelTransport::Http2 {
+            return None;
+        }
+        Some(hyper::Client::builder().build(connector))
+    }
+
+    /**
+     * The connector for the Client's transport connections. enforce_http
+     * makes the built-in connector refuse endpoints whose scheme isn't http
+     * (i.e. when nothing layered on top of it speaks TLS).
+     */
+    pub(in crate::client) fn transport_connector(&self, enforce_http: bool) -> TransportConnector {
+        if let Some(connector) = &self.connector {
+            return connector.clone();
+        }
+        let mut http_connector = hyper::client::HttpConnector::new();
+        http_connector.set_connect_timeout(self.connect_timeout);
+        let mut proxy_connector = ProxyConnector::new(http_connector, self.proxy.clone());
+        proxy_connector.enforce_http(enforce_http);
+        TransportConnector::new(proxy_connector)
+    }
+
+    #[cfg(feature = "tls")]
+    pub(in crate::client) fn default_connector(&self) -> Connector {
+        let tls_config = match tls::TlsConfig::default().to_rustls_config() {
+            Ok(tls_config) => tls_config,
+            Err(e) => unreachable!("The webpki roots are always trusted by default: {:?}", e),
+        };
+        tls::https_connector(tls_config, self.transport_connector(false))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub(in crate::client) fn default_connector(&self) -> Connector {
+        self.transport_connector(true)
+    }
+}
+
+/**
+ * Makes a Client whose connections to the server are tuned for the
+ * deployment (see Client::builder()). Settings that aren't given are left at
+ * hyper's defaults.
+ */
+pub struct ClientBuilder {
+    executor: ChannelExecutor,
+    server_uri: hyper::Uri,
+    transport: TransportSettings,
+}
+impl ClientBuilder {
+    pub fn new(server_uri: hyper::Uri) -> Self {
+        ClientBuilder {
+            executor: ChannelExecutor::default(),
+            server_uri,
+            transport: TransportSettings::default(),
+        }
+    }
+
+    pub fn build(self) -> Client {
+        Client::from_builder_parts(self.server_uri, self.executor, self.transport)
+    }
+
+    /**
+     * How long to wait for the TCP connection to the server to be
+     * established when a channel is made. Only applies to the built-in 
+     * connector.
+     */
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.transport.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /**
+     * Makes the Client's transport connections with connector (e.g. to 
+     * connect over Unix sockets, through a SOCKS proxy, with custom DNS 
+     * resolution, or over an in-memory transport in tests) instead of the
+     * built-in TCP connector. The connect timeout and proxy settings are 
+     * ignored, but with the `tls` feature https:// endpoints are still 
+     * connected to over TLS on top of connector's connections.
+     */
+    pub fn with_connector<C>(mut self, connector: C) -> Self
+    where
+        C: hyper::service::Service<hyper::Uri> + Clone + Send + Sync + 'static,
+        C::Response: ConnectionIo,
+        C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        C::Future: Send + 'static,
+    {
+        self.transport.connector = Some(TransportConnector::new(connector));
+        self
+    }
+
+    /**
+     * Runs the internal tasks of channels made by the Client on executor
+     * (see Client::make_tube_channel_with_executor() for doing this per
+     * channel).
+     */
+    pub fn with_executor(mut self, executor: ChannelExecutor) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /**
+     * Sizes HTTP/2 flow control windows based on a bandwidth-delay product
+     * estimate. This overrides the initial window sizes.
+     */
+    /**
+     * Sizes the pool of buffers each channel encodes the frames it sends
+     * into (see frame::BufferPoolConfig). Channels report how often they
+     * reused a pooled buffer via Channel::buffer_pool_stats().
+     */
+    pub fn with_frame_buffer_pool(mut self, config: frame::BufferPoolConfig) -> Self {
+        self.transport.frame_buffer_pool = config;
+        self
+    }
+
+    pub fn with_http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.transport.http2_adaptive_window = enabled;
+        self
+    }
+
+    pub fn with_http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.transport.http2_initial_connection_window_size = Some(size);
+        self
+    }
+
+    pub fn with_http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.transport.http2_initial_stream_window_size = Some(size);
+        self
+    }
+
+    pub fn with_http2_max_frame_size(mut self, size: u32) -> Self {
+        self.transport.http2_max_frame_size = Some(size);
+        self
+    }
+
+    /**
+     * Fails channels that can't be made over HTTP/2 rather than making them 
+     * over HTTP/1.1 instead. By default a channel whose HTTP/2 request fails
+     * (e.g. because a middlebox between the Client and server doesn't speak
+     * HTTP/2) is retried over HTTP/1.1, with its frames carried in the 
+     * chunked request and response bodies, and the Client's channels are 
+     * made over HTTP/1.1 from then on.
+     */
+    pub fn with_http2_only(mut self) -> Self {
+        self.transport.http2_only = true;
+        self
+    }
+
+    /**
+     * See Client::set_keepalive().
+     */
+    pub fn with_keepalive(mut self, keepalive: Keepalive) -> Self {
+        self.transport.keepalive = Some(keepalive);
+        self
+    }
+
+    /**
+     * The largest payload frame the Client's channels are willing to
+     * receive. The server may negotiate a smaller size (see
+     * ServerBuilder::with_max_payload_frame_size()), and payloads larger than
+     * the negotiated size are split into several frames by whichever peer
+     * sends them.
+     */
+    pub fn with_max_payload_frame_size(mut self, size: usize) -> Self {
+        self.transport.max_payload_frame_size = Some(size);
+        self
+    }
+
+    /**
+     * Establishes the Client's channels through an HTTP forward proxy. The
+     * connect timeout applies to connecting to the proxy.
+     */
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.transport.proxy = Some(proxy);
+        self
+    }
+
+    /**
+     * Carries the Client's channels straight over their connections, with 
+     * no HTTP at all: each channel gets a connection of its own (made with
+     * the Client's connector, and so over TLS for https:// endpoints with 
+     * the `tls` feature), whose frames follow a short head exchanged with 
+     * the server. This trades compatibility with HTTP proxies and load 
+     * balancers for less overhead, so it's meant for controlled 
+     * environments. The server must serve raw connections (see 
+     * server::ServerBuilder::with_raw_transport()), and the HTTP/2 settings
+     * are ignored.
+     */
+    pub fn with_raw_transport(mut self) -> Self {
+        self.transport.channel_transport = ChannelTransport::Raw;
+        self
+    }
+
+    /**
+     * Makes the Client's transport connections to the Unix domain socket at
+     * path (see UnixConnector and ClientBuilder::with_connector()), e.g. to
+     * reach a collocated server without it listening on a TCP port.
+     */
+    #[cfg(unix)]
+    pub fn with_unix_socket(self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.with_connector(UnixConnector::new(path))
+    }
+
+    /**
+     * Opens the Client's channels over WebSockets (carrying their frames in
+     * binary WebSocket messages) instead of over HTTP/2 streams, for 
+     * environments whose proxies or gateways can't carry a streaming HTTP/2
+     * request body. The server must serve WebSockets (see 
+     * server::ServerBuilder::with_websocket_transport()). Endpoints keep 
+     * their http:// or https:// scheme, and the HTTP/2 settings are ignored.
+     */
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket_transport(mut self) -> Self {
+        self.transport.channel_transport = ChannelTransport::WebSocket;
+        self
+    }
+
+    /**
+     * See ServerBuilder::with_write_coalescing(). Applies to every channel
+     * the Client makes.
+     */
+    pub fn with_write_coalescing(mut self, write_coalescing: frame::WriteCoalescing) -> Self {
+        self.transport.write_coalescing = write_coalescing;
+        self
+    }
+}
@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type ConnectFuture = Pin<Box<dyn Future<Output = Result<BoxedIo, BoxError>> + Send>>;
+
+/**
+ * What a connector given to ClientBuilder::with_connector() must yield for
+ * each connection: a bidirectional byte stream to the server.
+ */
+pub trait ConnectionIo: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static {}
+impl<T> ConnectionIo for T
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+{}
+
+/**
+ * A connection made by a TransportConnector, whatever its underlying type.
+ */
+pub(in crate::client) struct BoxedIo(Box<dyn ConnectionIo>);
+impl hyper::client::connect::Connection for BoxedIo {
+    fn connected(&self) -> hyper::client::connect::Connected {
+        hyper::client::connect::Connected::new()
+    }
+}
+impl tokio::io::AsyncRead for BoxedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_read(cx, buf)
+    }
+}
+impl tokio::io::AsyncWrite for BoxedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &[u8],
+    ) -> task::Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_shutdown(cx)
+    }
+}
+
+/**
+ * Makes the transport connections for a Client's channels with either the
+ * built-in connector (see proxy::ProxyConnector) or one given to
+ * ClientBuilder::with_connector(), hiding which behind a single type.
+ */
+#[derive(Clone)]
+pub(in crate::client) struct TransportConnector {
+    connect: Arc<dyn Fn(hyper::Uri) -> ConnectFuture + Send + Sync>,
+}
+impl TransportConnector {
+    pub(in crate::client) fn new<C>(connector: C) -> Self
+    where
+        C: hyper::service::Service<hyper::Uri> + Clone + Send + Sync + 'static,
+        C::Response: ConnectionIo,
+        C::Error: Into<BoxError>,
+        C::Future: Send + 'static,
+    {
+        TransportConnector {
+            connect: Arc::new(move |uri| {
+                let mut connector = connector.clone();
+                Box::pin(async move {
+                    futures::future::poll_fn(|cx| {
+                        connector.poll_ready(cx).map_err(Into::<BoxError>::into)
+                    }).await?;
+                    match connector.call(uri).await {
+                        Ok(io) => Ok(BoxedIo(Box::new(io))),
+                        Err(e) => Err(e.into()),
+                    }
+                })
+            }),
+        }
+    }
+}
+impl std::fmt::Debug for TransportConnector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportConnector").finish_non_exhaustive()
+    }
+}
+impl hyper::service::Service<hyper::Uri> for TransportConnector {
+    type Response = BoxedIo;
+    type Error = BoxError;
+    type Future = ConnectFuture;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context) -> task::Poll<Result<(), Self::Error>> {
+        // Readiness is checked on the wrapped connector for each connection
+        task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: hyper::Uri) -> Self::Future {
+        (self.connect)(uri)
+    }
+}
+
+#[cfg(test)]
+mod connector_tests {
+    use hyper::service::Service;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    /**
+     * Connects (once) to the client end of an in-memory pipe.
+     */
+    #[derive(Clone)]
+    struct DuplexConnector(Arc<std::sync::Mutex<Option<tokio::io::DuplexStream>>>);
+    impl Service<hyper::Uri> for DuplexConnector {
+        type Response = tokio::io::DuplexStream;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut task::Context) -> task::Poll<Result<(), Self::Error>> {
+            task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, uri: hyper::Uri) -> Self::Future {
+            assert_eq!(uri, "http://tubez.internal/");
+            let client_io = self.0.lock().unwrap().take();
+            futures::future::ready(
+                client_io.ok_or_else(|| std::io::Error::other("Already connected"))
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_connectors_make_the_transport_connections() {
+        let (client_io, mut server_io) = tokio::io::duplex(64);
+        let mut connector = TransportConnector::new(DuplexConnector(
+            Arc::new(std::sync::Mutex::new(Some(client_io)))
+        ));
+
+        let mut io = connector.call("http://tubez.internal/".parse().unwrap()).await.unwrap();
+        io.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        let result = connector.call("http://tubez.internal/".parse().unwrap()).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+/**
+ * Keeps a client Channel's connection alive while it's idle and detects when
+ * it has silently died (e.g. behind a NAT or load balancer that dropped it)
+ * (see Client::set_keepalive()).
+ *
+ * http2_ping_interval sends HTTP/2 PINGs on the connection, closing it if one
+ * isn't answered within http2_ping_timeout. heartbeat_interval sends tubez 
+ * Heartbeat frames on each Channel (on channels that negotiated 
+ * FramingVersion::V5 or later), failing the Channel's transport if one isn't
+ * answered within heartbeat_timeout. None disables either.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keepalive {
+    pub heartbeat_interval: Option<Duration>,
+    pub heartbeat_timeout: Duration,
+    pub http2_ping_interval: Option<Duration>,
+    pub http2_ping_timeout: Duration,
+}
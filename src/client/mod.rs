@@ -1,5 +1,38 @@
 mod channel;
 mod client;
+mod client_builder;
+mod connector;
+mod keepalive;
+mod pool;
+mod proxy;
+mod reconnect;
+#[cfg(feature = "tls")]
+mod tls;
+mod tube_limit;
+#[cfg(unix)]
+mod unix_connector;
 
 pub use channel::*;
 pub use client::Client;
+pub use client_builder::ClientBuilder;
+pub use connector::ConnectionIo;
+pub use client::ServerMakeTubeError;
+pub use crate::common::ChannelEvent;
+pub use crate::common::CloseReason;
+pub use crate::common::PingError;
+pub use keepalive::Keepalive;
+pub use pool::ChannelPool;
+pub use pool::PoolBalancing;
+pub use pool::PoolConfig;
+pub use proxy::ProxyConfig;
+pub use reconnect::ReconnectPolicy;
+#[cfg(feature = "tls")]
+pub use tls::ClientCertificate;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfigError;
+pub use tube_limit::TubeLimit;
+pub use tube_limit::TubeLimitPolicy;
+#[cfg(unix)]
+pub use unix_connector::UnixConnector;
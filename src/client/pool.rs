@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use crate::common::ChannelExecutor;
+use crate::common::tube;
+use super::channel;
+use super::client::ServerMakeTubeError;
+use super::Keepalive;
+use super::ReconnectPolicy;
+
+/**
+ * How a ChannelPool picks the Channel for each Tube it makes.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PoolBalancing {
+    /**
+     * Each Channel in turn.
+     */
+    #[default]
+    RoundRobin,
+    /**
+     * The Channel with the fewest unfinished Tubes.
+     */
+    LeastLoaded,
+}
+
+/**
+ * Configures a ChannelPool (see Client::pool()).
+ */
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub balancing: PoolBalancing,
+    pub channels_per_endpoint: usize,
+    /**
+     * Resolved like the endpoint given to Client::make_tube_channel_at().
+     * When empty, the pool's Channels connect to the Client's server_uri.
+     */
+    pub endpoints: Vec<hyper::Uri>,
+    /**
+     * Sent when each Channel is made (on top of the Client's default
+     * channel headers).
+     */
+    pub headers: HashMap<String, String>,
+}
+
+/**
+ * What the Client's settings were when a ChannelPool was made, so the pool
+ * can make replacement Channels like the Client would have.
+ */
+pub(in crate::client) struct ChannelSettings {
+    /**
+     * Connects to the Client's server_uri, so its server_uri is replaced 
+     * with each of the pool's endpoints.
+     */
+    pub(in crate::client) connection: channel::ChannelConnection,
+    pub(in crate::client) executor: ChannelExecutor,
+    pub(in crate::client) keepalive: Option<Keepalive>,
+    pub(in crate::client) reconnect_policy: Option<ReconnectPolicy>,
+}
+impl ChannelSettings {
+    async fn connect(
+        &self,
+        endpoint: &hyper::Uri,
+    ) -> Result<channel::Channel, channel::ChannelConnectError> {
+        let mut connection = self.connection.clone();
+        connection.server_uri = endpoint.clone();
+        channel::Channel::new(
+            connection,
+            self.executor.clone(),
+            self.reconnect_policy,
+            self.keepalive,
+        ).await
+    }
+}
+
+struct PoolSlot {
+    channel: Option<channel::Channel>,
+    endpoint: hyper::Uri,
+}
+
+/**
+ * Maintains a set of Channels to one or more endpoints and spreads the Tubes
+ * it makes across them (per its PoolBalancing), so that heavy workloads
+ * aren't bottlenecked on a single HTTP/2 stream.
+ *
+ * A Channel that fails, is exhausted, or whose server is going away is
+ * replaced with a new Channel to the same endpoint the next time it's
+ * picked, and the Tube is made on another Channel instead. Tubes made
+ * earlier keep working for as long as their Channel's transport does.
+ */
+pub struct ChannelPool {
+    balancing: PoolBalancing,
+    next_slot: usize,
+    settings: ChannelSettings,
+    slots: Vec<PoolSlot>,
+}
+impl ChannelPool {
+    /**
+     * Connects channels_per_endpoint Channels to each endpoint. Channels that
+     * fail to connect are retried when they're next picked, so this only
+     * fails if none of them connect.
+     */
+    pub(in crate::client) async fn connect(
+        settings: ChannelSettings,
+        endpoints: Vec<hyper::Uri>,
+        channels_per_endpoint: usize,
+        balancing: PoolBalancing,
+    ) -> Result<Self, channel::ChannelConnectError> {
+        let mut slots = vec![];
+        let mut last_error = None;
+        for endpoint in endpoints {
+            for _ in 0..channels_per_endpoint {
+                let channel = match settings.connect(&endpoint).await {
+                    Ok(channel) => Some(channel),
+                    Err(e) => {
+                        log::warn!("Error connecting a pooled channel to {}: {:?}", endpoint, e);
+                        last_error = Some(e);
+                        None
+                    },
+                };
+                slots.push(PoolSlot {
+                    channel,
+                    endpoint: endpoint.clone(),
+                });
+            }
+        }
+
+        if !slots.iter().any(|slot| slot.channel.is_some()) {
+            return Err(match last_error {
+                Some(e) => e,
+                None => channel::ChannelConnectError::InvalidEndpoint(
+                    "A pool needs at least one endpoint and one channel per endpoint".to_string()
+                ),
+            });
+        }
+
+        Ok(ChannelPool {
+            balancing,
+            next_slot: 0,
+            settings,
+            slots,
+        })
+    }
+
+    /**
+     * The number of Channels in the pool that are currently connected and
+     * can make Tubes.
+     */
+    pub fn num_usable_channels(&self) -> usize {
+        self.slots.iter()
+            .filter(|slot| slot.channel.as_ref().is_some_and(|c| c.can_make_tubes()))
+            .count()
+    }
+
+    /**
+     * Makes a Tube on one of the pool's Channels. If the picked Channel can't
+     * make Tubes anymore it is replaced and each other Channel is tried in
+     * turn before giving up.
+     */
+    pub async fn make_tube(
+        &mut self,
+        headers: HashMap<String, String>,
+    ) -> Result<tube::Tube, ServerMakeTubeError> {
+        let mut tried_slots = vec![];
+        let mut last_error = None;
+        loop {
+            let loads: Vec<Option<usize>> = self.slots.iter().map(|slot| {
+                slot.channel.as_ref()
+                    .filter(|channel| channel.can_make_tubes())
+                    .map(|channel| channel.num_unfinished_tubes())
+            }).collect();
+            let slot_idx = match pick_slot(
+                self.balancing,
+                &mut self.next_slot,
+                &loads,
+                &tried_slots,
+            ) {
+                Some(slot_idx) => slot_idx,
+                None => break,
+            };
+            tried_slots.push(slot_idx);
+
+            let slot = &mut self.slots[slot_idx];
+            if loads[slot_idx].is_none() {
+                log::trace!("Replacing pooled channel to {}...", slot.endpoint);
+                slot.channel = None;
+                match self.settings.connect(&slot.endpoint).await {
+                    Ok(channel) => slot.channel = Some(channel),
+                    Err(e) => {
+                        last_error = Some(ServerMakeTubeError::ChannelConnectError(e));
+                        continue;
+                    },
+                }
+            }
+
+            let channel = match slot.channel.as_mut() {
+                Some(channel) => channel,
+                None => continue,
+            };
+            match channel.make_tube(headers.clone()).await {
+                Ok(tube) => return Ok(tube),
+                Err(e) if is_channel_unusable(&e) => {
+                    log::warn!("Pooled channel to {} can't make tubes: {:?}", slot.endpoint, e);
+                    slot.channel = None;
+                    last_error = Some(ServerMakeTubeError::MakeTubeError(e));
+                },
+                Err(e) => return Err(ServerMakeTubeError::MakeTubeError(e)),
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => e,
+            None => ServerMakeTubeError::MakeTubeError(channel::MakeTubeError::ChannelClosed),
+        })
+    }
+}
+
+/**
+ * Whether a make_tube() error means the Channel itself can't make Tubes
+ * anymore (as opposed to the server refusing this particular Tube).
+ */
+fn is_channel_unusable(e: &channel::MakeTubeError) -> bool {
+    use channel::MakeTubeError::*;
+    matches!(
+        e,
+        ChannelClosed | ChannelFailed(_) | ServerGoingAway(_) | TubeIdsExhausted |
+            UnknownTransportError
+    )
+}
+
+/**
+ * Picks the slot to make the next Tube on, skipping tried_slots. loads has
+ * the number of unfinished Tubes on each slot's Channel (None for slots
+ * whose Channel needs replacing, which are only picked by LeastLoaded once
+ * no connected slot is left).
+ */
+fn pick_slot(
+    balancing: PoolBalancing,
+    next_slot: &mut usize,
+    loads: &[Option<usize>],
+    tried_slots: &[usize],
+) -> Option<usize> {
+    match balancing {
+        PoolBalancing::RoundRobin => {
+            let num_slots = loads.len();
+            let slot_idx = (0..num_slots)
+                .map(|offset| (*next_slot + offset) % num_slots)
+                .find(|idx| !tried_slots.contains(idx))?;
+            *next_slot = (slot_idx + 1) % num_slots;
+            Some(slot_idx)
+        },
+        PoolBalancing::LeastLoaded => (0..loads.len())
+            .filter(|idx| !tried_slots.contains(idx))
+            .min_by_key(|idx| match loads[*idx] {
+                Some(load) => (false, load),
+                None => (true, 0),
+            }),
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_cycles_through_untried_slots() {
+        let loads = [Some(5), None, Some(0)];
+        let mut next_slot = 0;
+        let picks: Vec<Option<usize>> = (0..4)
+            .map(|_| pick_slot(PoolBalancing::RoundRobin, &mut next_slot, &loads, &[]))
+            .collect();
+        assert_eq!(picks, vec![Some(0), Some(1), Some(2), Some(0)]);
+
+        assert_eq!(
+            pick_slot(PoolBalancing::RoundRobin, &mut next_slot, &loads, &[1, 2]),
+            Some(0),
+        );
+        assert_eq!(
+            pick_slot(PoolBalancing::RoundRobin, &mut next_slot, &loads, &[0, 1, 2]),
+            None,
+        );
+    }
+
+    #[test]
+    fn least_loaded_prefers_connected_slots_with_the_fewest_tubes() {
+        let loads = [Some(3), None, Some(1), Some(2)];
+        let mut next_slot = 0;
+        let mut pick = |tried_slots: &[usize]| {
+            pick_slot(PoolBalancing::LeastLoaded, &mut next_slot, &loads, tried_slots)
+        };
+        assert_eq!(pick(&[]), Some(2));
+        assert_eq!(pick(&[2]), Some(3));
+        assert_eq!(pick(&[0, 2, 3]), Some(1));
+        assert_eq!(pick(&[0, 1, 2, 3]), None);
+    }
+}
@@ -0,0 +1,225 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task;
+
+use base64::Engine;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+
+/**
+ * The most bytes of a proxy's response to a CONNECT request that are read
+ * looking for the end of its headers.
+ */
+const MAX_CONNECT_RESPONSE_BYTES: usize = 8 * 1024;
+
+/**
+ * An HTTP forward proxy that a Client establishes its channels through (see
+ * ClientBuilder::with_proxy()). Each connection to the server is tunneled
+ * through the proxy with a CONNECT request.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProxyConfig {
+    /**
+     * Sent as the Proxy-Authorization header of each CONNECT request.
+     */
+    pub authorization: Option<String>,
+    pub proxy_uri: hyper::Uri,
+}
+impl ProxyConfig {
+    pub fn new(proxy_uri: hyper::Uri) -> Self {
+        ProxyConfig {
+            authorization: None,
+            proxy_uri,
+        }
+    }
+
+    /**
+     * Authenticates with the proxy using HTTP Basic authentication.
+     */
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials = base64::engine::general_purpose::STANDARD
+            .encode(format!("{}:{}", username, password));
+        self.authorization = Some(format!("Basic {}", credentials));
+        self
+    }
+}
+
+/**
+ * Connects to the server directly, or through a CONNECT tunnel when a
+ * ProxyConfig is given.
+ */
+#[derive(Clone, Debug)]
+pub(in crate::client) struct ProxyConnector {
+    enforce_http: bool,
+    http_connector: hyper::client::HttpConnector,
+    proxy: Option<ProxyConfig>,
+}
+impl ProxyConnector {
+    pub(in crate::client) fn new(
+        mut http_connector: hyper::client::HttpConnector,
+        proxy: Option<ProxyConfig>,
+    ) -> Self {
+        // Schemes are checked here, since the proxy's scheme is what the
+        // HttpConnector sees when tunneling.
+        http_connector.enforce_http(false);
+        ProxyConnector {
+            enforce_http: true,
+            http_connector,
+            proxy,
+        }
+    }
+
+    /**
+     * Whether to refuse destinations whose scheme isn't http (e.g. because
+     * nothing layered on this connector speaks TLS).
+     */
+    pub(in crate::client) fn enforce_http(&mut self, enforce_http: bool) {
+        self.enforce_http = enforce_http;
+    }
+}
+impl hyper::service::Service<hyper::Uri> for ProxyConnector {
+    type Response = tokio::net::TcpStream;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context) -> task::Poll<Result<(), Self::Error>> {
+        self.http_connector.poll_ready(cx).map_err(std::io::Error::other)
+    }
+
+    fn call(&mut self, dst: hyper::Uri) -> Self::Future {
+        let mut http_connector = self.http_connector.clone();
+        let enforce_http = self.enforce_http;
+        let proxy = self.proxy.clone();
+        Box::pin(async move {
+            if enforce_http && dst.scheme() != Some(&hyper::http::uri::Scheme::HTTP) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Endpoint `{}` doesn't use the http scheme", dst),
+                ));
+            }
+            let proxy = match proxy {
+                Some(proxy) => proxy,
+                None => return http_connector.call(dst).await.map_err(std::io::Error::other),
+            };
+
+            let mut stream = match http_connector.call(proxy.proxy_uri.clone()).await {
+                Ok(stream) => stream,
+                Err(e) => return Err(std::io::Error::other(
+                    format!("Error connecting to proxy `{}`: {}", proxy.proxy_uri, e)
+                )),
+            };
+            let authority = tunnel_authority(&dst)?;
+            let connect_request = make_connect_request(&authority, proxy.authorization.as_deref());
+            stream.write_all(connect_request.as_bytes()).await?;
+            read_connect_response(&mut stream, &authority).await?;
+            Ok(stream)
+        })
+    }
+}
+
+/**
+ * The host:port to ask the proxy to tunnel to for dst (using the scheme's
+ * default port if dst doesn't have one).
+ */
+fn tunnel_authority(dst: &hyper::Uri) -> Result<String, std::io::Error> {
+    let host = match dst.host() {
+        Some(host) => host,
+        None => return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Endpoint `{}` has no host", dst),
+        )),
+    };
+    let port = match (dst.port_u16(), dst.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("https")) => 443,
+        (None, _) => 80,
+    };
+    Ok(format!("{}:{}", host, port))
+}
+
+fn make_connect_request(authority: &str, authorization: Option<&str>) -> String {
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(authorization) = authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    request
+}
+
+/**
+ * Reads the proxy's response to a CONNECT request up to the end of its
+ * headers (and no further, since whatever follows belongs to the tunnel) and
+ * fails unless the proxy established the tunnel.
+ */
+async fn read_connect_response(
+    stream: &mut tokio::net::TcpStream,
+    authority: &str,
+) -> Result<(), std::io::Error> {
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE_BYTES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Proxy's response to CONNECT is too large",
+            ));
+        }
+        let byte = match stream.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(
+                std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "Proxy closed the connection before responding to CONNECT",
+                )
+            ),
+            Err(e) => return Err(e),
+        };
+        response.push(byte);
+    }
+    check_connect_response(&response, authority)
+}
+
+fn check_connect_response(response: &[u8], authority: &str) -> Result<(), std::io::Error> {
+    let response = String::from_utf8_lossy(response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status_code = status_line.split_whitespace().nth(1);
+    match status_code {
+        Some(status_code) if status_code.starts_with('2') => Ok(()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            format!("Proxy refused to tunnel to {}: `{}`", authority, status_line),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod proxy_tests {
+    use super::*;
+
+    #[test]
+    fn connect_requests_carry_the_proxy_authorization() {
+        let proxy = ProxyConfig::new("http://proxy.example.com:3128".parse().unwrap())
+            .with_basic_auth("alice", "s3cret");
+        let authority = tunnel_authority(&"https://tubez.example.com/".parse().unwrap()).unwrap();
+        assert_eq!(authority, "tubez.example.com:443");
+        assert_eq!(
+            make_connect_request(&authority, proxy.authorization.as_deref()),
+            "CONNECT tubez.example.com:443 HTTP/1.1\r\n\
+             Host: tubez.example.com:443\r\n\
+             Proxy-Authorization: Basic YWxpY2U6czNjcmV0\r\n\r\n",
+        );
+    }
+
+    #[test]
+    fn only_successful_connect_responses_establish_the_tunnel() {
+        let authority = "tubez.example.com:80";
+        assert!(check_connect_response(
+            b"HTTP/1.1 200 Connection established\r\n\r\n",
+            authority,
+        ).is_ok());
+        let err = check_connect_response(
+            b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n",
+            authority,
+        ).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/**
+ * Opts a client Channel into re-establishing its transport when the 
+ * connection to the server fails (see Client::set_reconnect_policy()).
+ *
+ * Up to max_attempts reconnects are tried for each failure, waiting 
+ * initial_backoff before the first and doubling the wait (up to max_backoff)
+ * before each one after that.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_attempts: u32,
+    pub max_backoff: Duration,
+}
+impl ReconnectPolicy {
+    /**
+     * How long to wait before the given reconnect attempt (starting at 1).
+     */
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let multiplier = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(multiplier).min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod reconnect_tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_attempts: 10,
+            max_backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,162 @@
+use super::connector::TransportConnector;
+
+/**
+ * A certificate chain (leaf first) and the private key for its leaf, both
+ * DER-encoded, presented to servers that ask for a client certificate.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCertificate {
+    pub cert_chain: Vec<Vec<u8>>,
+    pub private_key: Vec<u8>,
+}
+
+/**
+ * How a Client secures channels to https:// endpoints (see
+ * Client::set_tls_config()). Certificates are DER-encoded (PEM files can be
+ * converted with e.g. the rustls-pemfile crate).
+ *
+ * root_certificates are trusted in addition to Mozilla's root certificates
+ * (as packaged by webpki-roots) unless trust_webpki_roots is false.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    pub client_certificate: Option<ClientCertificate>,
+    pub root_certificates: Vec<Vec<u8>>,
+    pub trust_webpki_roots: bool,
+}
+impl TlsConfig {
+    pub(in crate::client) fn to_rustls_config(
+        &self,
+    ) -> Result<rustls::ClientConfig, TlsConfigError> {
+        let mut roots = rustls::RootCertStore::empty();
+        if self.trust_webpki_roots {
+            add_webpki_roots(&mut roots);
+        }
+        for (idx, cert) in self.root_certificates.iter().enumerate() {
+            if let Err(e) = roots.add(&rustls::Certificate(cert.clone())) {
+                return Err(TlsConfigError::InvalidRootCertificate {
+                    idx,
+                    detail: e.to_string(),
+                });
+            }
+        }
+        if roots.is_empty() {
+            return Err(TlsConfigError::NoRootCertificates);
+        }
+
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        match &self.client_certificate {
+            Some(client_cert) => {
+                let cert_chain = client_cert.cert_chain.iter()
+                    .map(|cert| rustls::Certificate(cert.clone()))
+                    .collect();
+                let private_key = rustls::PrivateKey(client_cert.private_key.clone());
+                match builder.with_client_auth_cert(cert_chain, private_key) {
+                    Ok(config) => Ok(config),
+                    Err(e) => Err(TlsConfigError::InvalidClientCertificate(e.to_string())),
+                }
+            },
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            client_certificate: None,
+            root_certificates: vec![],
+            trust_webpki_roots: true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TlsConfigError {
+    InvalidClientCertificate(String),
+    /**
+     * The root certificate at idx within TlsConfig::root_certificates isn't
+     * a valid DER-encoded certificate.
+     */
+    InvalidRootCertificate {
+        idx: usize,
+        detail: String,
+    },
+    /**
+     * The TlsConfig doesn't trust any root certificates, so no server could
+     * be authenticated.
+     */
+    NoRootCertificates,
+}
+
+fn add_webpki_roots(roots: &mut rustls::RootCertStore) {
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+}
+
+/**
+ * Makes a connector that connects to http:// endpoints in plaintext and to
+ * https:// endpoints over TLS (negotiating HTTP/2 via ALPN).
+ */
+pub(in crate::client) fn https_connector(
+    tls_config: rustls::ClientConfig,
+    transport_connector: TransportConnector,
+) -> hyper_rustls::HttpsConnector<TransportConnector> {
+    hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config)
+        .https_or_http()
+        .enable_http2()
+        .wrap_connector(transport_connector)
+}
+
+#[cfg(test)]
+mod tls_tests {
+    use super::*;
+
+    #[test]
+    fn default_config_trusts_the_webpki_roots() {
+        assert!(TlsConfig::default().to_rustls_config().is_ok());
+    }
+
+    #[test]
+    fn invalid_certificates_are_rejected() {
+        let config = TlsConfig {
+            root_certificates: vec![b"not a certificate".to_vec()],
+            ..TlsConfig::default()
+        };
+        match config.to_rustls_config() {
+            Err(TlsConfigError::InvalidRootCertificate { idx: 0, .. }) => (),
+            unexpected => panic!("Unexpected result: {:?}", unexpected.map(|_| ())),
+        }
+
+        let config = TlsConfig {
+            client_certificate: Some(ClientCertificate {
+                cert_chain: vec![b"not a certificate".to_vec()],
+                private_key: b"not a key".to_vec(),
+            }),
+            ..TlsConfig::default()
+        };
+        match config.to_rustls_config() {
+            Err(TlsConfigError::InvalidClientCertificate(_)) => (),
+            unexpected => panic!("Unexpected result: {:?}", unexpected.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn configs_must_trust_some_root() {
+        let config = TlsConfig {
+            trust_webpki_roots: false,
+            ..TlsConfig::default()
+        };
+        assert_eq!(
+            config.to_rustls_config().map(|_| ()),
+            Err(TlsConfigError::NoRootCertificates),
+        );
+    }
+}
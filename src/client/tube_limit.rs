@@ -0,0 +1,27 @@
+/**
+ * What make_tube() does on a Channel that already has its TubeLimit's 
+ * max_tubes unfinished Tubes.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TubeLimitPolicy {
+    /**
+     * Wait until one of the Channel's Tubes is closed or aborted.
+     */
+    Wait,
+    /**
+     * Fail with MakeTubeError::AtCapacity.
+     */
+    Fail,
+}
+
+/**
+ * Bounds how many unfinished Tubes a Channel has at once (see 
+ * Channel::set_tube_limit()), so that an application can't overwhelm the 
+ * Channel's single HTTP/2 stream with thousands of Tubes. A Tube counts 
+ * against the limit until it has been closed or aborted.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TubeLimit {
+    pub max_tubes: usize,
+    pub policy: TubeLimitPolicy,
+}
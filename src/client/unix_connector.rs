@@ -0,0 +1,67 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task;
+
+/**
+ * Connects to a server listening on a Unix domain socket (see
+ * server::Server::from_unix_path()) regardless of the endpoint, e.g. for
+ * sidecars that are collocated with the server (see
+ * ClientBuilder::with_unix_socket()). The endpoint's authority is still sent
+ * to the server as the channel's Host.
+ */
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    path: Arc<PathBuf>,
+}
+impl UnixConnector {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        UnixConnector {
+            path: Arc::new(path.into()),
+        }
+    }
+}
+impl hyper::service::Service<hyper::Uri> for UnixConnector {
+    type Response = tokio::net::UnixStream;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context) -> task::Poll<Result<(), Self::Error>> {
+        task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: hyper::Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move { tokio::net::UnixStream::connect(&*path).await })
+    }
+}
+
+#[cfg(test)]
+mod unix_connector_tests {
+    use hyper::service::Service;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn connects_to_the_socket_for_any_endpoint() {
+        let path = std::env::temp_dir().join(
+            format!("tubez-unix-connector-{}.sock", std::process::id())
+        );
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let mut connector = UnixConnector::new(&path);
+
+        let mut client_io = connector.call("http://tubez.internal/".parse().unwrap()).await.unwrap();
+        let (mut server_io, _) = listener.accept().await.unwrap();
+        client_io.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        server_io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(connector.call("http://tubez.internal/".parse().unwrap()).await.is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::common::frame;
+use crate::common::hex;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    FrameDecodeError {
+        line: usize,
+        error: frame::FrameDecodeError,
+    },
+    InvalidRecord {
+        line: usize,
+        detail: String,
+    },
+    IoError(std::io::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureDirection {
+    Incoming,
+    Outgoing,
+}
+impl CaptureDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CaptureDirection::Incoming => "incoming",
+            CaptureDirection::Outgoing => "outgoing",
+        }
+    }
+}
+
+/**
+ * A chunk of encoded frames exactly as it was written to (or read from) a
+ * channel's transport.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedChunk {
+    pub timestamp_micros: u64,
+    pub direction: CaptureDirection,
+    pub framing_version: frame::FramingVersion,
+    pub bytes: Vec<u8>,
+}
+
+/**
+ * Records everything a channel sends and receives so that it can be inspected
+ * or replayed later (see CaptureReplayer). Each chunk of encoded frames is
+ * written as a line of JSON:
+ *
+ *   {"timestamp_micros": 1700000000000000, "direction": "outgoing",
+ *    "framing_version": 2, "bytes": "0404ac02ac02"}
+ *
+ * Chunks are recorded before they are decoded, so a capture also includes
+ * malformed data received from the peer.
+ *
+ * Clones share the same underlying writer.
+ */
+#[derive(Clone)]
+pub struct FrameCapture {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+impl FrameCapture {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        FrameCapture {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /**
+     * Creates (or truncates) the file at path and records to it.
+     */
+    pub fn to_file(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        match std::fs::File::create(path) {
+            Ok(file) => Ok(Self::new(std::io::BufWriter::new(file))),
+            Err(e) => Err(CaptureError::IoError(e)),
+        }
+    }
+
+    pub fn record(
+        &self,
+        direction: CaptureDirection,
+        framing_version: frame::FramingVersion,
+        bytes: &[u8],
+    ) -> Result<(), CaptureError> {
+        let timestamp_micros = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_micros() as u64,
+            Err(_) => 0,
+        };
+        let record = json!({
+            "timestamp_micros": timestamp_micros,
+            "direction": direction.as_str(),
+            "framing_version": framing_version_number(framing_version),
+            "bytes": hex::encode_hex(bytes),
+        });
+
+        let mut writer = self.writer.lock().unwrap();
+        match writeln!(writer, "{}", record).and_then(|()| writer.flush()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(CaptureError::IoError(e)),
+        }
+    }
+}
+impl std::fmt::Debug for FrameCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FrameCapture")
+    }
+}
+
+fn framing_version_number(framing_version: frame::FramingVersion) -> u64 {
+    match framing_version {
+        frame::FramingVersion::V1 => 1,
+        frame::FramingVersion::V2 => 2,
+        frame::FramingVersion::V3 => 3,
+        frame::FramingVersion::V4 => 4,
+        frame::FramingVersion::V5 => 5,
+    }
+}
+
+/**
+ * Reads a capture written by a FrameCapture and feeds it back through a
+ * Decoder (and optionally a FrameHandler), e.g. to reproduce a protocol issue
+ * seen in production or to turn real traffic into a regression test.
+ */
+#[derive(Clone, Debug)]
+pub struct CaptureReplayer {
+    chunks: Vec<CapturedChunk>,
+}
+impl CaptureReplayer {
+    pub fn new(chunks: Vec<CapturedChunk>) -> Self {
+        CaptureReplayer {
+            chunks,
+        }
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Self, CaptureError> {
+        let mut chunks = vec![];
+        for (idx, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Err(CaptureError::IoError(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_chunk(&line) {
+                Ok(chunk) => chunks.push(chunk),
+                Err(detail) => return Err(CaptureError::InvalidRecord {
+                    line: idx + 1,
+                    detail,
+                }),
+            }
+        }
+        Ok(Self::new(chunks))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        match std::fs::File::open(path) {
+            Ok(file) => Self::read(std::io::BufReader::new(file)),
+            Err(e) => Err(CaptureError::IoError(e)),
+        }
+    }
+
+    pub fn chunks(&self) -> &[CapturedChunk] {
+        &self.chunks
+    }
+
+    /**
+     * Decodes every chunk that flowed in the given direction, in the order
+     * they were captured.
+     */
+    pub fn decode(
+        &self,
+        direction: CaptureDirection,
+    ) -> Result<Vec<frame::Frame>, CaptureError> {
+        let mut frames = vec![];
+        let mut decoder = None;
+        for (idx, chunk) in self.chunks.iter().enumerate() {
+            if chunk.direction != direction {
+                continue;
+            }
+            let decoder = decoder.get_or_insert_with(|| {
+                frame::Decoder::new_with_version(chunk.framing_version)
+            });
+            match decoder.decode(chunk.bytes.clone()) {
+                Ok(decoded) => frames.extend(decoded),
+                Err(error) => return Err(CaptureError::FrameDecodeError {
+                    line: idx + 1,
+                    error,
+                }),
+            }
+        }
+        Ok(frames)
+    }
+
+    /**
+     * Feeds the captured incoming frames through frame_handler as though they
+     * had just been received from the peer. Errors from the FrameHandler don't
+     * stop the replay; they are returned in the order they occurred.
+     */
+    pub async fn replay(
+        &self,
+        frame_handler: &mut frame::FrameHandler,
+        frame_sender: &frame::FrameSender,
+    ) -> Result<Vec<frame::FrameHandlerError>, CaptureError> {
+        let mut handler_errors = vec![];
+        for frame in self.decode(CaptureDirection::Incoming)? {
+            if let Err(e) = frame_handler.handle_frame(frame, frame_sender).await {
+                handler_errors.push(e);
+            }
+        }
+        if let Err(e) = frame_handler.flush_deferred_acks(frame_sender).await {
+            handler_errors.push(e);
+        }
+        Ok(handler_errors)
+    }
+}
+
+fn parse_chunk(line: &str) -> Result<CapturedChunk, String> {
+    let record: Value = match serde_json::from_str(line) {
+        Ok(record) => record,
+        Err(e) => return Err(e.to_string()),
+    };
+    let timestamp_micros = match record.get("timestamp_micros").and_then(Value::as_u64) {
+        Some(timestamp_micros) => timestamp_micros,
+        None => return Err("invalid `timestamp_micros`".to_string()),
+    };
+    let direction = match record.get("direction").and_then(Value::as_str) {
+        Some("incoming") => CaptureDirection::Incoming,
+        Some("outgoing") => CaptureDirection::Outgoing,
+        _ => return Err("invalid `direction`".to_string()),
+    };
+    let framing_version = match record.get("framing_version").and_then(Value::as_u64) {
+        Some(1) => frame::FramingVersion::V1,
+        Some(2) => frame::FramingVersion::V2,
+        Some(3) => frame::FramingVersion::V3,
+        Some(4) => frame::FramingVersion::V4,
+        Some(5) => frame::FramingVersion::V5,
+        _ => return Err("invalid `framing_version`".to_string()),
+    };
+    let bytes = match record.get("bytes").and_then(Value::as_str).and_then(hex::decode_hex) {
+        Some(bytes) => bytes,
+        None => return Err("invalid `bytes`".to_string()),
+    };
+    Ok(CapturedChunk {
+        timestamp_micros,
+        direction,
+        framing_version,
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod capture_tests {
+    use super::*;
+
+    use crate::common::ChannelContext;
+    use crate::common::PeerType;
+    use crate::common::tube;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn encode(frames: Vec<frame::Frame>) -> Vec<u8> {
+        let mut encoder = frame::encode::Encoder::new(frame::FramingVersion::V2);
+        encoder.encode_batch(frames).unwrap().to_vec()
+    }
+
+    #[test]
+    fn recorded_chunks_can_be_read_back_and_decoded() {
+        let buf = SharedBuf::default();
+        let capture = FrameCapture::new(buf.clone());
+        let incoming = encode(vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 2 },
+            frame::Frame::AbortAck { tube_id: 1 },
+        ]);
+        // Split a frame across chunks, as a transport might
+        capture.record(CaptureDirection::Incoming, frame::FramingVersion::V2, &incoming[..3]).unwrap();
+        capture.record(
+            CaptureDirection::Outgoing,
+            frame::FramingVersion::V2,
+            &encode(vec![frame::Frame::Drain {
+                reason: frame::DrainReason::Migrate,
+                deadline_unix_millis: None,
+            }]),
+        ).unwrap();
+        capture.record(CaptureDirection::Incoming, frame::FramingVersion::V2, &incoming[3..]).unwrap();
+
+        let data = buf.0.lock().unwrap().clone();
+        let replayer = CaptureReplayer::read(&data[..]).unwrap();
+        assert_eq!(replayer.chunks().len(), 3);
+        assert_eq!(replayer.chunks()[1].direction, CaptureDirection::Outgoing);
+        assert_eq!(replayer.decode(CaptureDirection::Incoming).unwrap(), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 2 },
+            frame::Frame::AbortAck { tube_id: 1 },
+        ]);
+        assert_eq!(
+            replayer.decode(CaptureDirection::Outgoing).unwrap(),
+            vec![frame::Frame::Drain {
+                reason: frame::DrainReason::Migrate,
+                deadline_unix_millis: None,
+            }],
+        );
+    }
+
+    #[test]
+    fn errors_on_invalid_records() {
+        let capture = "{\"timestamp_micros\": 0, \"direction\": \"sideways\", \
+                       \"framing_version\": 1, \"bytes\": \"\"}\n";
+        match CaptureReplayer::read(capture.as_bytes()) {
+            Err(CaptureError::InvalidRecord { line: 1, .. }) => (),
+            unexpected => panic!("Unexpected read result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_incoming_frames_through_a_frame_handler() {
+        let ctx = ChannelContext::new(PeerType::Client, frame::ExtensionFrameHandlers::new());
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        ctx.tube_managers.insert(1, tube_mgr.clone());
+        let mut frame_handler = frame::FrameHandler::new(ctx);
+        let (body_sender, _body) = hyper::Body::channel();
+        let frame_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V2,
+            frame::FrameInterceptors::new(),
+        );
+
+        let replayer = CaptureReplayer::new(vec![CapturedChunk {
+            timestamp_micros: 0,
+            direction: CaptureDirection::Incoming,
+            framing_version: frame::FramingVersion::V2,
+            bytes: encode(vec![
+                frame::Frame::ServerHasFinishedSending { tube_id: 1 },
+                frame::Frame::ServerHasFinishedSending { tube_id: 3 },
+            ]),
+        }]);
+        let handler_errors = replayer.replay(&mut frame_handler, &frame_sender).await.unwrap();
+
+        assert_eq!(
+            tube_mgr.lock().unwrap().completion_state,
+            tube::TubeCompletionState::ServerHasFinishedSending,
+        );
+        match handler_errors.as_slice() {
+            [frame::FrameHandlerError::UntrackedTubeId(_)] => (),
+            unexpected => panic!("Unexpected handler errors: {:?}", unexpected),
+        }
+    }
+}
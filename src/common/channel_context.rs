@@ -0,0 +1,1044 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::common::capture;
+use crate::common::frame;
+use crate::common::tube;
+use crate::common::ChannelExecutor;
+use crate::common::PeerType;
+
+/**
+ * Why a server channel ended (see ChannelEvent::Closed).
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum CloseReason {
+    /**
+     * The client finished the channel cleanly. Carries the reason it gave in
+     * a GoAway, if it sent one.
+     */
+    PeerWentAway(Option<String>),
+    /**
+     * The channel's transport failed (e.g. the connection was reset or the 
+     * client sent data that couldn't be decoded).
+     */
+    TransportError(String),
+    /**
+     * The client finished the channel after being asked to drain it or go 
+     * away (see Channel::drain() and Channel::go_away()).
+     */
+    Drained,
+    /**
+     * Nothing was received from the client within the channel's idle 
+     * timeout (see server::ServerBuilder::with_idle_channel_timeout()), so 
+     * its unfinished Tubes were aborted and its transport was reset.
+     */
+    IdleTimeout,
+}
+
+#[derive(Debug)]
+pub enum ChannelEvent {
+    /**
+     * The last event a server channel yields: nothing more will be received
+     * on it, and every Tube that was still unfinished has been failed.
+     */
+    Closed(CloseReason),
+    /**
+     * Every Tube on a server channel that was drained (see Channel::drain())
+     * has finished. The client can't open any more Tubes on it, so the 
+     * channel can be let go of once the client finishes it.
+     */
+    Drained,
+    NewTube(tube::Tube),
+    /**
+     * The channel's transport ended or failed (and wasn't re-established), 
+     * so every unfinished Tube on it has been failed. Carries why.
+     */
+    TransportFailed(String),
+    /**
+     * A client channel's transport failed and was re-established per its
+     * ReconnectPolicy. Tubes the server had already acked were failed with a
+     * TubeEvent_StreamError::TransportError; Tubes that were still being 
+     * made were made again on the new transport.
+     */
+    Reconnected,
+    /**
+     * A Tube's consumer exceeded the channel's tube::SlowConsumerLimit, so it
+     * was evicted per the limit's action. Carries how many events the 
+     * consumer had left queued and how long it had gone without taking one.
+     */
+    SlowConsumer {
+        tube_id: u32,
+        queued_events: usize,
+        stalled_for: Duration,
+        action: tube::SlowConsumerAction,
+    },
+}
+
+#[derive(Debug)]
+pub enum PingError {
+    ChannelClosed,
+    FrameSendError(frame::FrameSendError),
+    TimedOut(Duration),
+    /**
+     * The channel negotiated a FramingVersion that predates Heartbeats (see
+     * FramingVersion::acks_heartbeats()), so the peer can't answer pings.
+     */
+    Unsupported(frame::FramingVersion),
+}
+
+#[derive(Debug, Default)]
+struct ChannelEvents {
+    accepts_peer_tubes: bool,
+    /**
+     * Set on server channels whose client was authenticated, so that each 
+     * Tube the peer opens starts with a TubeEvent::AuthenticatedAndReady.
+     */
+    authenticated: bool,
+    #[cfg(feature = "bench")]
+    serves_bench_tubes: bool,
+    closed: bool,
+    /**
+     * Set once a Drain has been sent to the peer, after which tubes it opens
+     * are refused with a Draining Error frame.
+     */
+    drain_requested: bool,
+    /**
+     * Set once a ChannelEvent::Closed has been queued, after which the 
+     * channel's stream of events ends.
+     */
+    ended: bool,
+    /**
+     * Set once a GoAway has been sent to the peer, after which tubes it opens
+     * are no longer accepted.
+     */
+    going_away: bool,
+    /**
+     * The highest id of the tubes opened by the peer that have been accepted.
+     */
+    last_peer_tube_id: u32,
+    /**
+     * The most unfinished tubes opened by the peer that the channel will
+     * have at once. NewTubes beyond it are answered with an OverLimit Error 
+     * frame.
+     */
+    max_peer_tubes: Option<usize>,
+    max_pending_peer_tubes: Option<usize>,
+    pending_events: VecDeque<ChannelEvent>,
+    /**
+     * Woken when an event is taken off the queue (or the context is closed)
+     * while the task processing received frames is waiting on
+     * max_pending_peer_tubes.
+     */
+    pending_capacity_waker: Option<task::Waker>,
+    /**
+     * The reason given in the GoAway received from the peer, if any.
+     */
+    peer_going_away: Option<String>,
+    /**
+     * Why the channel's transport failed (for good), if it has.
+     */
+    transport_failure: Option<String>,
+    waker: Option<task::Waker>,
+}
+
+/**
+ * Lets anything holding the context (e.g. a task sending Heartbeats) tell the
+ * task reading from the channel's transport to give up on it.
+ */
+#[derive(Debug, Default)]
+struct TransportFailure {
+    notify: tokio::sync::Notify,
+    reason: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Default)]
+struct LatePayloads {
+    num_received: u64,
+    policy: frame::LatePayloadPolicy,
+}
+
+/**
+ * The bookkeeping for a single channel, shared by the Channel handed to the
+ * application, the task that processes frames received on the channel, and
+ * every FrameTypeHandler. Both client and server channels are built on it, so
+ * clones share the same underlying state.
+ */
+#[derive(Clone, Debug)]
+pub struct ChannelContext {
+    /**
+     * The AckTimeoutPolicy given to Tubes on this channel when they're 
+     * created.
+     */
+    ack_timeout_policy: Arc<Mutex<Option<tube::AckTimeoutPolicy>>>,
+    /**
+     * The latest AckId received on each Tube with cumulative acks whose 
+     * PayloadAck hasn't been sent yet.
+     */
+    cumulative_acks: Arc<Mutex<HashMap<u32, u16>>>,
+    /**
+     * The EventQueueLimit given to Tubes on this channel when they're 
+     * created.
+     */
+    event_queue_limit: Arc<Mutex<Option<tube::EventQueueLimit>>>,
+    events: Arc<Mutex<ChannelEvents>>,
+    executor: ChannelExecutor,
+    pub(in crate) extension_frame_handlers: frame::ExtensionFrameHandlers,
+    frame_capture: Arc<Mutex<Option<capture::FrameCapture>>>,
+    pub(in crate) frame_error_observers: frame::FrameErrorObservers,
+    /**
+     * Weak so that holding on to the context (e.g. in a Channel) doesn't hold
+     * the outgoing stream open. Populated once the stream is established.
+     */
+    frame_sender: Arc<Mutex<Option<frame::WeakFrameSender>>>,
+    /**
+     * Notified whenever a HeartbeatAck is received.
+     */
+    heartbeat_acks: Arc<tokio::sync::Notify>,
+    late_payloads: Arc<Mutex<LatePayloads>>,
+    pub(in crate) peer_type: PeerType,
+    /**
+     * Enforces the RateLimits on what the peer sends, if any were given (see
+     * with_rate_limits()).
+     */
+    rate_limiter: Arc<Mutex<Option<frame::RateLimiter>>>,
+    /**
+     * Tubes that have received SequencedPayloads whose SelectiveAck hasn't
+     * been sent yet.
+     */
+    selective_acks: Arc<Mutex<HashSet<u32>>>,
+    slow_consumer_limit: Arc<Mutex<Option<tube::SlowConsumerLimit>>>,
+    /**
+     * Told about each Tube the peer opens and when it finishes, if given (see
+     * with_tube_lifecycle_observer()).
+     */
+    tube_lifecycle_observer: Option<Arc<dyn tube::TubeLifecycleObserver>>,
+    pub(in crate) tube_managers: Arc<tube::ChannelTubeManagers>,
+    transport_failure: Arc<TransportFailure>,
+    pub(in crate) tube_tracker: tube::TubeTracker,
+}
+impl ChannelContext {
+    pub(in crate) fn new(
+        peer_type: PeerType,
+        extension_frame_handlers: frame::ExtensionFrameHandlers,
+    ) -> Self {
+        ChannelContext {
+            ack_timeout_policy: Arc::new(Mutex::new(None)),
+            cumulative_acks: Arc::new(Mutex::new(HashMap::new())),
+            event_queue_limit: Arc::new(Mutex::new(None)),
+            events: Arc::new(Mutex::new(ChannelEvents::default())),
+            executor: ChannelExecutor::default(),
+            extension_frame_handlers,
+            frame_capture: Arc::new(Mutex::new(None)),
+            frame_error_observers: frame::FrameErrorObservers::new(),
+            frame_sender: Arc::new(Mutex::new(None)),
+            heartbeat_acks: Arc::new(tokio::sync::Notify::new()),
+            late_payloads: Arc::new(Mutex::new(LatePayloads::default())),
+            peer_type,
+            rate_limiter: Arc::new(Mutex::new(None)),
+            selective_acks: Arc::new(Mutex::new(HashSet::new())),
+            slow_consumer_limit: Arc::new(Mutex::new(None)),
+            transport_failure: Arc::new(TransportFailure::default()),
+            tube_lifecycle_observer: None,
+            tube_managers: Arc::new(tube::ChannelTubeManagers::new()),
+            tube_tracker: tube::TubeTracker::new(),
+        }
+    }
+
+    /**
+     * Tubes opened by the peer are only accepted on channels that opt in.
+     * Otherwise NewTube frames are answered with an UnsupportedFeature Error
+     * frame.
+     */
+    pub(in crate) fn accepting_peer_tubes(self) -> Self {
+        self.events.lock().unwrap().accepts_peer_tubes = true;
+        self
+    }
+
+    /**
+     * Marks the peer as authenticated (see ChannelEvents::authenticated).
+     */
+    pub(in crate) fn authenticated(self) -> Self {
+        self.events.lock().unwrap().authenticated = true;
+        self
+    }
+
+    /**
+     * Tells observer about each tube opened by the peer and, once each of 
+     * those tubes finishes, how it went.
+     */
+    pub(in crate) fn with_tube_lifecycle_observer(
+        mut self,
+        observer: Arc<dyn tube::TubeLifecycleObserver>,
+    ) -> Self {
+        self.tube_lifecycle_observer = Some(observer);
+        self
+    }
+
+    pub(in crate) fn tube_lifecycle_observer(&self) -> Option<Arc<dyn tube::TubeLifecycleObserver>> {
+        self.tube_lifecycle_observer.clone()
+    }
+
+    /**
+     * Runs the channel's internal tasks (and those of its tubes) on the given
+     * executor rather than the current runtime.
+     */
+    pub(in crate) fn with_executor(mut self, executor: ChannelExecutor) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /**
+     * Peer tubes that ask for the built-in bench service (see 
+     * bench::BENCH_HEADER) are served internally rather than being published
+     * to the application.
+     */
+    #[cfg(feature = "bench")]
+    pub(in crate) fn serving_bench_tubes(self) -> Self {
+        self.events.lock().unwrap().serves_bench_tubes = true;
+        self
+    }
+
+    /**
+     * Limits the number of peer tubes that may be queued on the context
+     * without having been received by the application. Once the limit is
+     * reached, frame processing for the whole channel waits until the
+     * application takes a tube off the queue. Since nothing more is read from
+     * the transport in the meantime, transport-level flow control pushes back
+     * on the peer (its sends, including new tubes, stall) and payloads for
+     * queued tubes aren't buffered before the application is ready for them.
+     *
+     * A limit of 0 is treated as 1.
+     */
+    pub(in crate) fn with_max_pending_peer_tubes(self, max_pending_peer_tubes: usize) -> Self {
+        self.events.lock().unwrap().max_pending_peer_tubes = Some(max_pending_peer_tubes.max(1));
+        self
+    }
+
+    /**
+     * Limits the number of unfinished tubes opened by the peer (see 
+     * ChannelEvents::max_peer_tubes).
+     */
+    pub(in crate) fn with_max_peer_tubes(self, max_peer_tubes: usize) -> Self {
+        self.events.lock().unwrap().max_peer_tubes = Some(max_peer_tubes);
+        self
+    }
+
+    pub(in crate) fn max_peer_tubes(&self) -> Option<usize> {
+        self.events.lock().unwrap().max_peer_tubes
+    }
+
+    /**
+     * Limits how quickly the peer may open tubes and send payload data (see
+     * frame::RateLimits).
+     */
+    pub(in crate) fn with_rate_limits(self, rate_limits: frame::RateLimits) -> Self {
+        *self.rate_limiter.lock().unwrap() = Some(frame::RateLimiter::new(rate_limits));
+        self
+    }
+
+    pub(in crate) fn check_new_tube_rate(&self) -> frame::RateLimitCheck {
+        match self.rate_limiter.lock().unwrap().as_mut() {
+            Some(rate_limiter) => rate_limiter.check_new_tube(Instant::now()),
+            None => frame::RateLimitCheck::Allowed,
+        }
+    }
+
+    pub(in crate) fn check_payload_rate(&self, num_bytes: usize) -> frame::RateLimitCheck {
+        match self.rate_limiter.lock().unwrap().as_mut() {
+            Some(rate_limiter) => rate_limiter.check_payload(num_bytes, Instant::now()),
+            None => frame::RateLimitCheck::Allowed,
+        }
+    }
+
+    pub(in crate) fn executor(&self) -> &ChannelExecutor {
+        &self.executor
+    }
+
+    pub(in crate) fn accepts_peer_tubes(&self) -> bool {
+        self.events.lock().unwrap().accepts_peer_tubes
+    }
+
+    pub(in crate) fn is_authenticated(&self) -> bool {
+        self.events.lock().unwrap().authenticated
+    }
+
+    /**
+     * Records that the peer has opened the tube with the given id, unless a
+     * GoAway has already been sent (see start_going_away()), in which case
+     * the tube is refused and false is returned.
+     */
+    pub(in crate) fn accept_peer_tube_id(&self, tube_id: u32) -> bool {
+        let mut events = self.events.lock().unwrap();
+        if events.going_away {
+            return false;
+        }
+        events.last_peer_tube_id = events.last_peer_tube_id.max(tube_id);
+        true
+    }
+
+    /**
+     * Stops accepting tubes opened by the peer and returns the highest id of
+     * those accepted so far, which is the LastTubeId to send in a GoAway.
+     */
+    pub(in crate) fn start_going_away(&self) -> u32 {
+        let mut events = self.events.lock().unwrap();
+        events.going_away = true;
+        events.last_peer_tube_id
+    }
+
+    /**
+     * Returns false if a Drain had already been sent to the peer.
+     */
+    pub(in crate) fn record_drain_requested(&self) -> bool {
+        let mut events = self.events.lock().unwrap();
+        !std::mem::replace(&mut events.drain_requested, true)
+    }
+
+    pub(in crate) fn drain_requested(&self) -> bool {
+        self.events.lock().unwrap().drain_requested
+    }
+
+    /**
+     * Whether the peer has been asked to drain the channel or go away (see 
+     * record_drain_requested() and start_going_away()).
+     */
+    pub(in crate) fn asked_peer_to_leave(&self) -> bool {
+        let events = self.events.lock().unwrap();
+        events.drain_requested || events.going_away
+    }
+
+    pub(in crate) fn set_peer_going_away(&self, reason: String) {
+        self.events.lock().unwrap().peer_going_away = Some(reason);
+    }
+
+    /**
+     * The reason the peer gave for going away, if it has sent a GoAway. No
+     * more tubes should be opened on the channel once it has.
+     */
+    pub(in crate) fn peer_going_away(&self) -> Option<String> {
+        self.events.lock().unwrap().peer_going_away.clone()
+    }
+
+    pub(in crate) fn transport_failure(&self) -> Option<String> {
+        self.events.lock().unwrap().transport_failure.clone()
+    }
+
+    pub(in crate) fn num_unfinished_tubes(&self) -> usize {
+        self.tube_managers.to_vec()
+            .iter()
+            .filter(|(_, tube_mgr)| !tube_mgr.lock().unwrap().completion_state.is_terminal())
+            .count()
+    }
+
+    pub(in crate) fn record_heartbeat_ack(&self) {
+        self.heartbeat_acks.notify_waiters();
+    }
+
+    /**
+     * Resolves when the next HeartbeatAck is received. Enable the returned 
+     * future before sending the Heartbeat so that a quick HeartbeatAck isn't
+     * missed.
+     */
+    pub(in crate) fn next_heartbeat_ack(&self) -> tokio::sync::futures::Notified<'_> {
+        self.heartbeat_acks.notified()
+    }
+
+    /**
+     * Sends a Heartbeat and resolves with how long it took for a 
+     * HeartbeatAck to arrive. Acks aren't matched to Heartbeats, so a ping 
+     * sent while another Heartbeat is in flight may be answered by that 
+     * Heartbeat's ack.
+     */
+    pub(in crate) async fn ping(&self, timeout: Duration) -> Result<Duration, PingError> {
+        let frame_sender = match self.frame_sender() {
+            Some(frame_sender) => frame_sender,
+            None => return Err(PingError::ChannelClosed),
+        };
+        let framing_version = frame_sender.framing_version();
+        if !framing_version.acks_heartbeats() {
+            return Err(PingError::Unsupported(framing_version));
+        }
+
+        let heartbeat_ack = self.next_heartbeat_ack();
+        futures::pin_mut!(heartbeat_ack);
+        heartbeat_ack.as_mut().enable();
+        let sent_at = Instant::now();
+        if let Err(e) = frame_sender.send(frame::Frame::Heartbeat).await {
+            return Err(PingError::FrameSendError(e));
+        }
+        drop(frame_sender);
+
+        match tokio::time::timeout(timeout, heartbeat_ack).await {
+            Ok(()) => Ok(sent_at.elapsed()),
+            Err(_) => Err(PingError::TimedOut(timeout)),
+        }
+    }
+
+    /**
+     * Tells the task reading from the channel's transport to treat it as 
+     * failed (see transport_failed()).
+     */
+    pub(in crate) fn fail_transport(&self, reason: String) {
+        *self.transport_failure.reason.lock().unwrap() = Some(reason);
+        self.transport_failure.notify.notify_one();
+    }
+
+    /**
+     * Resolves with the reason given to fail_transport() once it's called.
+     */
+    pub(in crate) async fn transport_failed(&self) -> String {
+        loop {
+            if let Some(reason) = self.transport_failure.reason.lock().unwrap().take() {
+                return reason;
+            }
+            self.transport_failure.notify.notified().await;
+        }
+    }
+
+    /**
+     * Forgets a fail_transport() that hasn't been acted on, e.g. one for a 
+     * transport that has since been replaced.
+     */
+    pub(in crate) fn clear_transport_failure(&self) {
+        self.transport_failure.reason.lock().unwrap().take();
+    }
+
+    /**
+     * Queues a ChannelEvent::TransportFailed.
+     */
+    pub(in crate) fn publish_transport_failed(&self, reason: String) {
+        let mut events = self.events.lock().unwrap();
+        events.transport_failure = Some(reason.clone());
+        if events.closed {
+            return;
+        }
+        events.pending_events.push_back(ChannelEvent::TransportFailed(reason));
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /**
+     * Called once a client channel has reconnected: a GoAway from the 
+     * previous transport doesn't apply to the new one. Queues a 
+     * ChannelEvent::Reconnected.
+     */
+    pub(in crate) fn publish_reconnected(&self) {
+        let mut events = self.events.lock().unwrap();
+        events.peer_going_away = None;
+        if events.closed {
+            return;
+        }
+        events.pending_events.push_back(ChannelEvent::Reconnected);
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /**
+     * Queues a ChannelEvent::Closed and ends the channel's stream of events 
+     * once it has been taken.
+     */
+    pub(in crate) fn publish_closed(&self, reason: CloseReason) {
+        let mut events = self.events.lock().unwrap();
+        if events.closed || events.ended {
+            return;
+        }
+        events.ended = true;
+        events.pending_events.push_back(ChannelEvent::Closed(reason));
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /**
+     * Queues a ChannelEvent::Drained, unless the channel has already been 
+     * closed.
+     */
+    pub(in crate) fn publish_drained(&self) {
+        let mut events = self.events.lock().unwrap();
+        if events.closed || events.ended {
+            return;
+        }
+        events.pending_events.push_back(ChannelEvent::Drained);
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /**
+     * Queues a ChannelEvent::SlowConsumer.
+     */
+    pub(in crate) fn publish_slow_consumer(
+        &self,
+        tube_id: u32,
+        queued_events: usize,
+        stalled_for: Duration,
+        action: tube::SlowConsumerAction,
+    ) {
+        let mut events = self.events.lock().unwrap();
+        if events.closed {
+            return;
+        }
+        events.pending_events.push_back(ChannelEvent::SlowConsumer {
+            tube_id,
+            queued_events,
+            stalled_for,
+            action,
+        });
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(in crate) fn set_frame_sender(&self, frame_sender: frame::WeakFrameSender) {
+        if let Some(sender) = frame_sender.upgrade() {
+            sender.set_capture(self.frame_capture.lock().unwrap().clone());
+        }
+        *self.frame_sender.lock().unwrap() = Some(frame_sender);
+    }
+
+    /**
+     * Records all frames sent and received on the channel from here on. The
+     * capture is applied to the channel's FrameSender whether or not the
+     * stream has been established yet.
+     */
+    pub(in crate) fn set_frame_capture(&self, frame_capture: Option<capture::FrameCapture>) {
+        *self.frame_capture.lock().unwrap() = frame_capture.clone();
+        if let Some(sender) = self.frame_sender() {
+            sender.set_capture(frame_capture);
+        }
+    }
+
+    /**
+     * Called with each chunk of data received from the peer, before it is
+     * decoded.
+     */
+    pub(in crate) fn record_incoming(
+        &self,
+        data: &[u8],
+        framing_version: frame::FramingVersion,
+    ) {
+        if let Some(capture) = self.frame_capture.lock().unwrap().as_ref() {
+            let direction = capture::CaptureDirection::Incoming;
+            if let Err(e) = capture.record(direction, framing_version, data) {
+                log::error!("Error recording incoming frames: {:?}", e);
+            }
+        }
+    }
+
+    /**
+     * Zeroed until the channel's FrameSender has been set.
+     */
+    pub(in crate) fn buffer_pool_stats(&self) -> frame::BufferPoolStats {
+        match self.frame_sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.buffer_pool_stats(),
+            None => frame::BufferPoolStats::default(),
+        }
+    }
+
+    pub(in crate) fn frame_sender(&self) -> Option<frame::FrameSender> {
+        self.frame_sender.lock().unwrap().as_ref().and_then(|sender| sender.upgrade())
+    }
+
+    pub(in crate) fn ack_timeout_policy(&self) -> Option<tube::AckTimeoutPolicy> {
+        *self.ack_timeout_policy.lock().unwrap()
+    }
+
+    pub(in crate) fn set_ack_timeout_policy(&self, policy: Option<tube::AckTimeoutPolicy>) {
+        *self.ack_timeout_policy.lock().unwrap() = policy;
+    }
+
+    pub(in crate) fn event_queue_limit(&self) -> Option<tube::EventQueueLimit> {
+        *self.event_queue_limit.lock().unwrap()
+    }
+
+    pub(in crate) fn set_event_queue_limit(&self, limit: Option<tube::EventQueueLimit>) {
+        *self.event_queue_limit.lock().unwrap() = limit;
+    }
+
+    pub(in crate) fn slow_consumer_limit(&self) -> Option<tube::SlowConsumerLimit> {
+        *self.slow_consumer_limit.lock().unwrap()
+    }
+
+    pub(in crate) fn set_slow_consumer_limit(&self, limit: Option<tube::SlowConsumerLimit>) {
+        *self.slow_consumer_limit.lock().unwrap() = limit;
+    }
+
+    pub(in crate) fn late_payload_policy(&self) -> frame::LatePayloadPolicy {
+        self.late_payloads.lock().unwrap().policy
+    }
+
+    pub(in crate) fn set_late_payload_policy(&self, policy: frame::LatePayloadPolicy) {
+        self.late_payloads.lock().unwrap().policy = policy;
+    }
+
+    /**
+     * Counts a payload that arrived for a Tube that had already finished, 
+     * returning the number counted on the channel so far.
+     */
+    pub(in crate) fn count_late_payload(&self) -> u64 {
+        let mut late_payloads = self.late_payloads.lock().unwrap();
+        late_payloads.num_received += 1;
+        late_payloads.num_received
+    }
+
+    pub(in crate) fn num_late_payloads(&self) -> u64 {
+        self.late_payloads.lock().unwrap().num_received
+    }
+
+    pub(in crate) fn defer_cumulative_ack(&self, tube_id: u32, ack_id: u16) {
+        self.cumulative_acks.lock().unwrap().insert(tube_id, ack_id);
+    }
+
+    pub(in crate) fn take_cumulative_acks(&self) -> HashMap<u32, u16> {
+        std::mem::take(&mut *self.cumulative_acks.lock().unwrap())
+    }
+
+    pub(in crate) fn defer_selective_ack(&self, tube_id: u32) {
+        self.selective_acks.lock().unwrap().insert(tube_id);
+    }
+
+    pub(in crate) fn take_selective_acks(&self) -> HashSet<u32> {
+        std::mem::take(&mut *self.selective_acks.lock().unwrap())
+    }
+
+    pub(in crate) fn get_tube_mgr(&self, tube_id: &u32) -> Option<Arc<Mutex<tube::TubeManager>>> {
+        self.tube_managers.get(*tube_id)
+    }
+
+    /**
+     * Ready once every Tube tracked by the channel has finished and the peer
+     * has acknowledged each Abort sent from this side (which untracks the 
+     * Tube, as does a failure of the transport).
+     */
+    pub(in crate) fn poll_tubes_settled(&self, cx: &mut task::Context) -> task::Poll<()> {
+        let tube_mgrs = self.tube_managers.to_vec();
+
+        let mut unsettled_tube_mgrs = vec![];
+        for (tube_id, tube_mgr) in tube_mgrs {
+            {
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                use tube::TubeCompletionState::*;
+                match &tube_mgr.completion_state {
+                    Closed | AbortedFromRemote(_) => continue,
+                    Open | 
+                        ClientHasFinishedSending | 
+                        ServerHasFinishedSending | 
+                        AbortedFromLocal(_) => (),
+                }
+                if !tube_mgr.completion_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    tube_mgr.completion_wakers.push(cx.waker().clone());
+                }
+            }
+            unsettled_tube_mgrs.push((tube_id, tube_mgr));
+        }
+        if unsettled_tube_mgrs.is_empty() {
+            return task::Poll::Ready(());
+        }
+
+        // A Tube that was untracked before the waker was registered above 
+        // won't wake it, so check again.
+        let all_still_tracked = unsettled_tube_mgrs.iter().all(|(tube_id, tube_mgr)| {
+            self.tube_managers.is_tracked(*tube_id, tube_mgr)
+        });
+        if !all_still_tracked {
+            cx.waker().wake_by_ref();
+        }
+        task::Poll::Pending
+    }
+
+    /**
+     * Ready once fewer than max_tubes of the Tubes tracked by the channel are
+     * unfinished (i.e. haven't been closed or aborted).
+     */
+    pub(in crate) fn poll_tube_capacity(
+        &self,
+        max_tubes: usize,
+        cx: &mut task::Context,
+    ) -> task::Poll<()> {
+        // Every Tube reaching a terminal completion_state wakes these, so 
+        // they're registered as the unfinished Tubes are counted.
+        let mut num_unfinished = 0;
+        for (_, tube_mgr) in self.tube_managers.to_vec() {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                continue;
+            }
+            num_unfinished += 1;
+            if !tube_mgr.completion_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                tube_mgr.completion_wakers.push(cx.waker().clone());
+            }
+        }
+        match num_unfinished < max_tubes {
+            true => task::Poll::Ready(()),
+            false => task::Poll::Pending,
+        }
+    }
+
+    /**
+     * Tracks a Tube opened by the peer and queues a ChannelEvent::NewTube for
+     * it. If the application has already dropped the Channel the Tube is
+     * handed back so that it can be aborted.
+     */
+    pub(in crate) fn publish_new_tube(&self, tube: tube::Tube) -> Result<(), Box<tube::Tube>> {
+        let mut events = self.events.lock().unwrap();
+        #[cfg(feature = "bench")]
+        if events.serves_bench_tubes {
+            if let Some(mode) = crate::bench::requested_bench_mode(tube.headers()) {
+                self.executor.spawn(crate::bench::serve_bench_tube(tube, mode));
+                return Ok(());
+            }
+        }
+        if events.closed {
+            return Err(Box::new(tube));
+        }
+        self.tube_tracker.track(&tube);
+        events.pending_events.push_back(ChannelEvent::NewTube(tube));
+        if let Some(waker) = events.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /**
+     * Resolves once there is room to queue another peer tube (see
+     * with_max_pending_peer_tubes()), or immediately if there is no limit or
+     * the context has been closed.
+     */
+    pub(in crate) fn poll_peer_tube_capacity(&self, cx: &mut task::Context) -> task::Poll<()> {
+        let mut events = self.events.lock().unwrap();
+        match events.max_pending_peer_tubes {
+            Some(max) if !events.closed && events.pending_events.len() >= max => {
+                events.pending_capacity_waker = Some(cx.waker().clone());
+                task::Poll::Pending
+            },
+            _ => task::Poll::Ready(()),
+        }
+    }
+
+    pub(in crate) fn poll_next_event(
+        &self,
+        cx: &mut task::Context,
+    ) -> task::Poll<Option<ChannelEvent>> {
+        let mut events = self.events.lock().unwrap();
+        events.waker = Some(cx.waker().clone());
+
+        match events.pending_events.pop_front() {
+            Some(channel_event) => {
+                if let Some(waker) = events.pending_capacity_waker.take() {
+                    waker.wake();
+                }
+                task::Poll::Ready(Some(channel_event))
+            },
+            None if events.ended => task::Poll::Ready(None),
+            None => task::Poll::Pending,
+        }
+    }
+
+    /**
+     * Called when the application drops its Channel. Events that were never
+     * received are dropped, and no further events are queued.
+     */
+    pub(in crate) fn is_closed(&self) -> bool {
+        self.events.lock().unwrap().closed
+    }
+
+    pub(in crate) fn close(&self) {
+        let mut events = self.events.lock().unwrap();
+        events.closed = true;
+        events.pending_events.clear();
+        events.waker = None;
+        if let Some(waker) = events.pending_capacity_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod channel_context_tests {
+    use super::*;
+
+    fn make_tube(ctx: &ChannelContext, tube_id: u32) -> tube::Tube {
+        let (body_sender, _body) = hyper::Body::channel();
+        let frame_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            frame::FrameInterceptors::new(),
+        );
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        ctx.tube_managers.insert(tube_id, tube_mgr.clone());
+        tube::Tube::new(
+            ctx.peer_type,
+            crate::common::UniqueId::new(tube_id, None),
+            HashMap::new(),
+            frame_sender,
+            tube_mgr,
+        )
+    }
+
+    #[tokio::test]
+    async fn published_tubes_are_shared_across_clones() {
+        let ctx = ChannelContext::new(PeerType::Server, frame::ExtensionFrameHandlers::new())
+            .accepting_peer_tubes();
+        let ctx2 = ctx.clone();
+        assert!(ctx2.accepts_peer_tubes());
+
+        let tube = make_tube(&ctx2, 1);
+        assert!(ctx2.publish_new_tube(tube).is_ok());
+
+        let event = futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await;
+        match event {
+            Some(ChannelEvent::NewTube(tube)) => assert_eq!(tube.get_id(), 1),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnecting_forgets_the_peers_go_away() {
+        let ctx = ChannelContext::new(PeerType::Client, frame::ExtensionFrameHandlers::new());
+        ctx.set_peer_going_away("restarting".to_string());
+
+        ctx.publish_reconnected();
+        assert_eq!(ctx.peer_going_away(), None);
+        let event = futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await;
+        match event {
+            Some(ChannelEvent::Reconnected) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_the_transport_wakes_the_reader_once() {
+        let ctx = ChannelContext::new(PeerType::Client, frame::ExtensionFrameHandlers::new());
+        let ctx2 = ctx.clone();
+        let reader = tokio::spawn(async move { ctx2.transport_failed().await });
+
+        ctx.fail_transport("Heartbeat timed out".to_string());
+        assert_eq!(reader.await.unwrap(), "Heartbeat timed out");
+
+        // A failure for a transport that has since been replaced is forgotten
+        ctx.fail_transport("stale".to_string());
+        ctx.clear_transport_failure();
+        let result = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            ctx.transport_failed(),
+        ).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn pings_resolve_once_the_heartbeat_is_acked() {
+        use hyper::body::HttpBody;
+
+        let ctx = ChannelContext::new(PeerType::Server, frame::ExtensionFrameHandlers::new());
+        match ctx.ping(Duration::from_secs(1)).await {
+            Err(PingError::ChannelClosed) => (),
+            unexpected => panic!("Unexpected ping result: {:?}", unexpected),
+        }
+
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V5,
+            frame::FrameInterceptors::new(),
+        );
+        ctx.set_frame_sender(frame_sender.downgrade());
+        let ctx2 = ctx.clone();
+        tokio::spawn(async move {
+            let data = body.data().await.unwrap().unwrap();
+            let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V5);
+            let frames: Vec<frame::Frame> = decoder.decode_bytes(data).unwrap().into();
+            assert_eq!(frames, vec![frame::Frame::Heartbeat]);
+            ctx2.record_heartbeat_ack();
+        });
+        assert!(ctx.ping(Duration::from_secs(1)).await.is_ok());
+        drop(frame_sender);
+    }
+
+    #[test]
+    fn tube_capacity_frees_up_as_tubes_finish() {
+        let ctx = ChannelContext::new(PeerType::Client, frame::ExtensionFrameHandlers::new());
+        let waker = futures::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        assert!(ctx.poll_tube_capacity(1, &mut cx).is_ready());
+
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        ctx.tube_managers.insert(1, tube_mgr.clone());
+        assert!(ctx.poll_tube_capacity(1, &mut cx).is_pending());
+        assert!(ctx.poll_tube_capacity(2, &mut cx).is_ready());
+        assert_eq!(tube_mgr.lock().unwrap().completion_wakers.len(), 1);
+
+        // Finished Tubes don't count, even while they're still tracked
+        tube_mgr.lock().unwrap().set_completion_state(tube::TubeCompletionState::Closed);
+        assert!(tube_mgr.lock().unwrap().completion_wakers.is_empty());
+        assert!(ctx.poll_tube_capacity(1, &mut cx).is_ready());
+    }
+
+    #[tokio::test]
+    async fn peer_tube_capacity_waits_for_pending_tubes_to_be_received() {
+        let ctx = ChannelContext::new(PeerType::Server, frame::ExtensionFrameHandlers::new())
+            .accepting_peer_tubes()
+            .with_max_pending_peer_tubes(1);
+        let has_capacity = |ctx: &ChannelContext| {
+            let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+            ctx.poll_peer_tube_capacity(&mut cx).is_ready()
+        };
+        assert!(has_capacity(&ctx));
+
+        let tube = make_tube(&ctx, 1);
+        ctx.publish_new_tube(tube).unwrap();
+        assert!(!has_capacity(&ctx));
+
+        futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await.unwrap();
+        assert!(has_capacity(&ctx));
+
+        let tube = make_tube(&ctx, 3);
+        ctx.publish_new_tube(tube).unwrap();
+        ctx.close();
+        assert!(has_capacity(&ctx));
+    }
+
+    #[test]
+    fn tubes_settle_once_finished_and_abort_acked() {
+        let ctx = ChannelContext::new(PeerType::Client, frame::ExtensionFrameHandlers::new());
+        let tube_mgr1 = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let tube_mgr3 = Arc::new(Mutex::new(tube::TubeManager::new()));
+        tube_mgr3.lock().unwrap().set_completion_state(tube::TubeCompletionState::AbortedFromLocal(
+            frame::AbortReason::ApplicationAbort
+        ));
+        ctx.tube_managers.insert(1, tube_mgr1.clone());
+        ctx.tube_managers.insert(3, tube_mgr3);
+        let is_settled = |ctx: &ChannelContext| {
+            let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+            ctx.poll_tubes_settled(&mut cx).is_ready()
+        };
+        assert!(!is_settled(&ctx));
+
+        tube_mgr1.lock().unwrap().set_completion_state(tube::TubeCompletionState::Closed);
+        assert!(!is_settled(&ctx));
+
+        // Tube 3 stays tracked until its AbortAck arrives
+        ctx.tube_managers.remove(3);
+        assert!(is_settled(&ctx));
+    }
+
+    #[tokio::test]
+    async fn closed_context_hands_back_new_tubes() {
+        let ctx = ChannelContext::new(PeerType::Server, frame::ExtensionFrameHandlers::new())
+            .accepting_peer_tubes();
+        ctx.close();
+
+        let tube = make_tube(&ctx, 1);
+        match ctx.publish_new_tube(tube) {
+            Err(tube) => assert_eq!(tube.get_id(), 1),
+            Ok(()) => panic!("Expected the tube to be handed back"),
+        }
+    }
+}
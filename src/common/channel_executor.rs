@@ -0,0 +1,120 @@
+use futures::future::BoxFuture;
+use futures::Future;
+
+/**
+ * Where a channel runs its internal tasks: the task that reads, decodes and
+ * handles the frames it receives (including any FrameTypeHandlers and
+ * ExtensionFrameHandlers), and the tasks its Tubes spawn to finish or abort
+ * themselves when they're dropped.
+ *
+ * Latency-critical channels can be pinned to a dedicated runtime (or
+ * LocalSet) so that they're isolated from bulk work in the same process.
+ * Note that the HTTP/2 connection a channel runs over is still driven by
+ * hyper, which spawns it onto the runtime the connection was established
+ * from.
+ */
+#[derive(Clone, Debug, Default)]
+pub enum ChannelExecutor {
+    /**
+     * Tasks are spawned onto whichever tokio runtime they are spawned from.
+     */
+    #[default]
+    Current,
+    /**
+     * Tasks are spawned onto the runtime with the given handle.
+     */
+    Runtime(tokio::runtime::Handle),
+    /**
+     * Tasks are spawned onto an application's LocalSet (see
+     * ChannelExecutor::local_set()).
+     */
+    LocalSet(LocalSetSpawner),
+}
+impl ChannelExecutor {
+    /**
+     * Runs a channel's tasks on the given LocalSet. Tasks can be spawned from
+     * any thread, but they only make progress while the application is
+     * driving the LocalSet. Tasks spawned after the LocalSet has been dropped
+     * are dropped without being run.
+     */
+    pub fn local_set(local_set: &tokio::task::LocalSet) -> Self {
+        let (sender, mut receiver) =
+            tokio::sync::mpsc::unbounded_channel::<BoxFuture<'static, ()>>();
+        local_set.spawn_local(async move {
+            while let Some(task) = receiver.recv().await {
+                tokio::task::spawn_local(task);
+            }
+        });
+        ChannelExecutor::LocalSet(LocalSetSpawner {
+            sender,
+        })
+    }
+
+    pub(in crate) fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        match self {
+            ChannelExecutor::Current => {
+                tokio::spawn(task);
+            },
+            ChannelExecutor::Runtime(handle) => {
+                handle.spawn(task);
+            },
+            ChannelExecutor::LocalSet(spawner) => {
+                if spawner.sender.send(Box::pin(task)).is_err() {
+                    log::error!("Dropping a channel task spawned after its LocalSet was dropped");
+                }
+            },
+        }
+    }
+}
+
+/**
+ * Hands tasks over to the LocalSet that a ChannelExecutor::LocalSet was made
+ * for.
+ */
+#[derive(Clone, Debug)]
+pub struct LocalSetSpawner {
+    sender: tokio::sync::mpsc::UnboundedSender<BoxFuture<'static, ()>>,
+}
+
+#[cfg(test)]
+mod channel_executor_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn runtime_executor_spawns_onto_the_given_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("dedicated")
+            .build()
+            .unwrap();
+        let executor = ChannelExecutor::Runtime(runtime.handle().clone());
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        executor.spawn(async move {
+            let thread_name = std::thread::current().name().map(|name| name.to_string());
+            sender.send(thread_name).unwrap();
+        });
+        let thread_name = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(thread_name, Some("dedicated".to_string()));
+    }
+
+    #[tokio::test]
+    async fn local_set_executor_runs_tasks_while_the_local_set_is_driven() {
+        let local_set = tokio::task::LocalSet::new();
+        let executor = ChannelExecutor::local_set(&local_set);
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        // Spawned from another thread, but run on this one by the LocalSet
+        let spawning_thread = std::thread::spawn(move || {
+            executor.spawn(async move {
+                sender.send(std::thread::current().id()).unwrap();
+            });
+        });
+        spawning_thread.join().unwrap();
+
+        let task_thread = local_set.run_until(receiver).await.unwrap();
+        assert_eq!(task_thread, std::thread::current().id());
+    }
+}
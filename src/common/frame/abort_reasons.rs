@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+
+/**
+ * AbortReason::ApplicationDefined codes are split into two ranges: codes up
+ * to MAX_APPLICATION_ABORT_CODE are free for applications to assign, and the
+ * remaining codes are reserved for reasons defined by tubez itself.
+ */
+pub const MAX_APPLICATION_ABORT_CODE: u32 = 0x7FFF_FFFF;
+
+#[derive(Debug)]
+pub enum AbortReasonRegistrationError {
+    AlreadyRegistered {
+        code: u32,
+        name: &'static str,
+    },
+    ReservedCode(u32),
+}
+
+fn registry() -> &'static RwLock<HashMap<u32, &'static str>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<u32, &'static str>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/**
+ * Registers a name for an application-defined abort code so that
+ * AbortReason::ApplicationDefined values carrying that code are displayed in
+ * domain terms (e.g. "quota_exceeded (429)") rather than as a bare number.
+ *
+ * Names are local to this process; only the code (and message) travel over
+ * the wire, so peers should register the same taxonomy.
+ */
+pub fn register_abort_reason(
+    code: u32,
+    name: &'static str,
+) -> Result<(), AbortReasonRegistrationError> {
+    if code > MAX_APPLICATION_ABORT_CODE {
+        return Err(AbortReasonRegistrationError::ReservedCode(code));
+    }
+
+    let mut registry = registry().write().unwrap();
+    match registry.try_insert(code, name) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(AbortReasonRegistrationError::AlreadyRegistered {
+            code,
+            name: e.entry.get(),
+        }),
+    }
+}
+
+pub fn abort_reason_name(code: u32) -> Option<&'static str> {
+    registry().read().unwrap().get(&code).copied()
+}
+
+#[cfg(test)]
+mod abort_reasons_tests {
+    use super::*;
+
+    // The registry is process-wide, so each test uses its own codes.
+
+    #[test]
+    fn registered_names_can_be_looked_up() {
+        assert_eq!(abort_reason_name(1001), None);
+        register_abort_reason(1001, "quota_exceeded").unwrap();
+        assert_eq!(abort_reason_name(1001), Some("quota_exceeded"));
+    }
+
+    #[test]
+    fn errors_on_duplicate_registration() {
+        register_abort_reason(1002, "auth_expired").unwrap();
+        match register_abort_reason(1002, "something_else") {
+            Err(AbortReasonRegistrationError::AlreadyRegistered { code, name }) => {
+                assert_eq!(code, 1002);
+                assert_eq!(name, "auth_expired");
+            },
+            unexpected => panic!("Unexpected registration result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn application_defined_reasons_display_registered_names() {
+        use super::super::frame::AbortReason;
+
+        register_abort_reason(1003, "rate_limited").unwrap();
+        let reason = AbortReason::ApplicationDefined {
+            code: 1003,
+            message: Some("try again in 5s".to_string()),
+        };
+        assert_eq!(reason.name(), Some("rate_limited"));
+        assert_eq!(reason.to_string(), "rate_limited (1003): try again in 5s");
+
+        let unregistered = AbortReason::ApplicationDefined {
+            code: 1004,
+            message: None,
+        };
+        assert_eq!(unregistered.name(), None);
+        assert_eq!(unregistered.to_string(), "application-defined abort (1004)");
+    }
+
+    #[test]
+    fn errors_on_reserved_code() {
+        match register_abort_reason(MAX_APPLICATION_ABORT_CODE + 1, "nope") {
+            Err(AbortReasonRegistrationError::ReservedCode(code)) =>
+                assert_eq!(code, MAX_APPLICATION_ABORT_CODE + 1),
+            unexpected => panic!("Unexpected registration result: {:?}", unexpected),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+
+/**
+ * Sizes the pool of buffers a channel encodes its outgoing frames into (see
+ * ServerBuilder::with_frame_buffer_pool() and
+ * ClientBuilder::with_frame_buffer_pool()).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferPoolConfig {
+    /**
+     * How many buffers are kept for reuse. 0 allocates a buffer for every
+     * frame (or batch of frames) that's sent.
+     */
+    pub max_buffers: usize,
+    /**
+     * How many bytes each buffer is allocated with, so that it can be reused
+     * for several frames before it's full. Frames bigger than this get a
+     * buffer of their own size.
+     */
+    pub buffer_size: usize,
+}
+impl Default for BufferPoolConfig {
+    /**
+     * 4 buffers of 16 KiB each.
+     */
+    fn default() -> Self {
+        BufferPoolConfig {
+            max_buffers: 4,
+            buffer_size: 16 * 1024,
+        }
+    }
+}
+
+/**
+ * How often a channel's outgoing frames were encoded into a pooled buffer
+ * (hits) rather than a newly allocated one (misses).
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BufferPoolStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+impl BufferPoolStats {
+    /**
+     * The fraction of buffers that were reused, or 0.0 if none have been
+     * needed yet.
+     */
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/**
+ * A BufferPool's counters, shared with the FrameSender (and its clones) so
+ * that they can be read without locking the pool.
+ */
+#[derive(Clone, Debug, Default)]
+pub(in crate) struct SharedBufferPoolStats {
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+impl SharedBufferPoolStats {
+    pub(in crate) fn get(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/**
+ * Buffers that encoded frames are split off of. A buffer is only reused once
+ * it has room for the next frame, either because it hasn't filled up yet or
+ * because every frame split off of it has been dropped by the transport (so
+ * its memory can be reclaimed in place).
+ */
+#[derive(Debug)]
+pub(in crate) struct BufferPool {
+    buffers: VecDeque<BytesMut>,
+    config: BufferPoolConfig,
+    stats: SharedBufferPoolStats,
+}
+impl BufferPool {
+    pub(in crate) fn new(config: BufferPoolConfig) -> Self {
+        BufferPool {
+            buffers: VecDeque::new(),
+            config,
+            stats: SharedBufferPoolStats::default(),
+        }
+    }
+
+    /**
+     * An empty buffer with room for at least len bytes. Hand it back with
+     * release() once the encoded frames have been split off of it.
+     */
+    pub(in crate) fn acquire(&mut self, len: usize) -> BytesMut {
+        let reusable = self.buffers.iter_mut().position(|buf| buf.try_reclaim(len));
+        if let Some(buf) = reusable.and_then(|index| self.buffers.remove(index)) {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return buf;
+        }
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+        BytesMut::with_capacity(len.max(self.config.buffer_size))
+    }
+
+    /**
+     * Keeps buf for reuse, in place of the least recently used buffer if the
+     * pool is full.
+     */
+    pub(in crate) fn release(&mut self, buf: BytesMut) {
+        if self.config.max_buffers == 0 {
+            return;
+        }
+        if self.buffers.len() >= self.config.max_buffers {
+            self.buffers.pop_front();
+        }
+        self.buffers.push_back(buf);
+    }
+
+    pub(in crate) fn stats(&self) -> &SharedBufferPoolStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod buffer_pool_tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_once_their_frames_are_dropped() {
+        let mut pool = BufferPool::new(BufferPoolConfig {
+            max_buffers: 1,
+            buffer_size: 8,
+        });
+
+        let mut buf = pool.acquire(8);
+        buf.extend_from_slice(b"12345678");
+        let frame = buf.split().freeze();
+        pool.release(buf);
+
+        // The only pooled buffer is full until frame is dropped
+        drop(pool.acquire(8));
+        assert_eq!(pool.stats().get(), BufferPoolStats { hits: 0, misses: 2 });
+        drop(frame);
+        let buf = pool.acquire(8);
+        assert_eq!(pool.stats().get(), BufferPoolStats { hits: 1, misses: 2 });
+        assert!(buf.is_empty());
+        assert_eq!(BufferPoolStats { hits: 1, misses: 1 }.hit_rate(), 0.5);
+        assert_eq!(BufferPoolStats::default().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn pools_without_buffers_allocate_every_time() {
+        let mut pool = BufferPool::new(BufferPoolConfig {
+            max_buffers: 0,
+            buffer_size: 1024,
+        });
+        for _ in 0..3 {
+            let buf = pool.acquire(16);
+            assert!(buf.capacity() >= 1024);
+            pool.release(buf);
+        }
+        assert_eq!(pool.stats().get(), BufferPoolStats { hits: 0, misses: 3 });
+    }
+}
@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 use std::collections::VecDeque;
 
+use bytes::Buf;
+use bytes::Bytes;
+use bytes::BytesMut;
 use serde_json;
 
 use super::frame;
+use super::header_block;
+use super::varint;
 
 // Returned by Decoder::decode() and provides context around 
 // a FrameParseError
@@ -18,12 +23,90 @@ pub struct FrameDecodeError {
 
 #[derive(Debug)]
 pub enum FrameParseError {
-    InternalByteOffsetLogicError(String),
+    AbortMessageUtf8Error(std::string::FromUtf8Error),
+    EmptySequenceRange {
+        start: u64,
+        end: u64,
+    },
+    ErrorDetailUtf8Error(std::string::FromUtf8Error),
+    GoAwayReasonUtf8Error(std::string::FromUtf8Error),
+    HeaderBlockDecodeError(header_block::HeaderBlockDecodeError),
     HeaderJsonDecodeError(serde_json::error::Error),
     HeaderUtf8Error(std::str::Utf8Error),
+    InvalidVarint,
+    LimitExceeded {
+        limit: DecoderLimit,
+        max: usize,
+        actual: usize,
+    },
+    TruncatedFrameBody(u8),
     UnknownFrameType(u8),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoderLimit {
+    FrameSize,
+    HeaderBlockSize,
+    HeaderCount,
+    PayloadSize,
+}
+
+/**
+ * Bounds on what a Decoder will accept from the peer. Frames that exceed a 
+ * limit produce a FrameParseError::LimitExceeded rather than being buffered 
+ * or parsed, so a misbehaving peer can't make the Decoder allocate 
+ * unboundedly.
+ */
+#[derive(Clone, Debug)]
+pub struct DecoderLimits {
+    /**
+     * The largest FrameBodyByteLength that will be buffered. This is checked 
+     * as soon as a frame's header arrives.
+     */
+    pub max_frame_size: usize,
+    pub max_header_block_size: usize,
+    pub max_header_count: usize,
+    pub max_payload_size: usize,
+}
+impl Default for DecoderLimits {
+    fn default() -> Self {
+        DecoderLimits {
+            // Encoders never produce frame bodies larger than this
+            max_frame_size: u16::MAX as usize,
+            max_header_block_size: u16::MAX as usize,
+            max_header_count: 256,
+            max_payload_size: u16::MAX as usize,
+        }
+    }
+}
+
+fn check_limit(limit: DecoderLimit, max: usize, actual: usize) -> Result<(), FrameParseError> {
+    if actual > max {
+        Err(FrameParseError::LimitExceeded { limit, max, actual })
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoderRecoveryMode {
+    /**
+     * The first malformed frame fails the decode and no further frames will 
+     * be decoded past it.
+     */
+    Fail,
+
+    /**
+     * Malformed frames (and frames that exceed a limit) are skipped so that 
+     * decoding can resume with the next frame. Skipped frames' errors are 
+     * available from Decoder::take_skipped_frame_errors().
+     *
+     * Errors in a frame's header (e.g. an invalid FrameBodyByteLength varint)
+     * leave the Decoder unable to find the next frame, so they always fail.
+     */
+    SkipMalformedFrames,
+}
+
 fn double_u8_to_u16(left_byte: u8, right_byte: u8) -> u16 {
     // 1) LLLLLLLL -> 00000000LLLLLLLL
     // 2) 00000000LLLLLLLL -> LLLLLLLL00000000
@@ -31,64 +114,236 @@ fn double_u8_to_u16(left_byte: u8, right_byte: u8) -> u16 {
     ((left_byte as u16) << 8) | (right_byte as u16)
 }
 
-fn parse_frame_body(frame_type: u8, mut frame_body_data: VecDeque<u8>) 
+fn parse_headers(
+    header_bytes: &[u8],
+    limits: &DecoderLimits,
+) -> Result<HashMap<String, Vec<u8>>, FrameParseError> {
+    check_limit(
+        DecoderLimit::HeaderBlockSize, 
+        limits.max_header_block_size, 
+        header_bytes.len(),
+    )?;
+    let headers_str = match std::str::from_utf8(header_bytes) {
+        Ok(str) => str,
+        Err(utf8_err) => return Err(FrameParseError::HeaderUtf8Error(utf8_err))
+    };
+    let headers = match serde_json::from_str::<HashMap<String, String>>(headers_str) {
+        Ok(headers) => headers,
+        Err(json_err) => return Err(FrameParseError::HeaderJsonDecodeError(json_err))
+    };
+    check_limit(DecoderLimit::HeaderCount, limits.max_header_count, headers.len())?;
+    Ok(headers.into_iter().map(|(name, value)| (name, value.into_bytes())).collect())
+}
+
+fn parse_header_block(
+    header_bytes: &[u8],
+    limits: &DecoderLimits,
+) -> Result<HashMap<String, Vec<u8>>, FrameParseError> {
+    check_limit(
+        DecoderLimit::HeaderBlockSize, 
+        limits.max_header_block_size, 
+        header_bytes.len(),
+    )?;
+    let num_fields = match header_block::header_block_num_fields(header_bytes) {
+        Ok(num_fields) => num_fields,
+        Err(e) => return Err(FrameParseError::HeaderBlockDecodeError(e)),
+    };
+    check_limit(
+        DecoderLimit::HeaderCount, 
+        limits.max_header_count, 
+        usize::try_from(num_fields).unwrap_or(usize::MAX),
+    )?;
+    match header_block::read_header_block(header_bytes) {
+        Ok(headers) => Ok(headers),
+        Err(e) => Err(FrameParseError::HeaderBlockDecodeError(e)),
+    }
+}
+
+fn parse_error_detail(
+    detail_bytes: &[u8],
+) -> Result<String, FrameParseError> {
+    match String::from_utf8(detail_bytes.to_vec()) {
+        Ok(detail) => Ok(detail),
+        Err(utf8_err) => Err(FrameParseError::ErrorDetailUtf8Error(utf8_err)),
+    }
+}
+
+fn parse_go_away_reason(
+    reason_bytes: &[u8],
+) -> Result<String, FrameParseError> {
+    match String::from_utf8(reason_bytes.to_vec()) {
+        Ok(reason) => Ok(reason),
+        Err(utf8_err) => Err(FrameParseError::GoAwayReasonUtf8Error(utf8_err)),
+    }
+}
+
+fn parse_abort_message(
+    message_bytes: &[u8],
+) -> Result<Option<String>, FrameParseError> {
+    if message_bytes.is_empty() {
+        return Ok(None);
+    }
+    match String::from_utf8(message_bytes.to_vec()) {
+        Ok(message) => Ok(Some(message)),
+        Err(utf8_err) => Err(FrameParseError::AbortMessageUtf8Error(utf8_err)),
+    }
+}
+
+fn parse_payload_ack_id(left_byte: u8, right_byte: u8) -> Option<u16> {
+    // First bit of ack_id indicates whether an ACK is expected for this 
+    // payload and should not be considered when interpreting the ack_id value.
+    if (0b1000_0000 & left_byte) > 0 {
+        Some(double_u8_to_u16(0b0111_1111 & left_byte, right_byte))
+    } else {
+        None
+    }
+}
+
+// Payload data (and other variable-length fields) are sliced out of 
+// frame_body_data without copying.
+fn parse_frame_body(frame_type: u8, mut frame_body_data: Bytes, limits: &DecoderLimits) 
         -> Result<frame::Frame, FrameParseError> {
     match frame_type {
         frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ).into();
             Ok(frame::Frame::ClientHasFinishedSending { tube_id })
         },
 
        frame::DRAIN_FRAMETYPE => {
-            Ok(frame::Frame::Drain)
+            let reason = match frame_body_data.first() {
+                Some(reason) => frame::DrainReason::from(*reason),
+                None => frame::DrainReason::Unspecified,
+            };
+            let deadline_unix_millis = match frame_body_data.len() {
+                0 | 1 => None,
+                9 => Some(u64::from_be_bytes([
+                    frame_body_data[1],
+                    frame_body_data[2],
+                    frame_body_data[3],
+                    frame_body_data[4],
+                    frame_body_data[5],
+                    frame_body_data[6],
+                    frame_body_data[7],
+                    frame_body_data[8],
+                ])),
+                _ => return Err(FrameParseError::TruncatedFrameBody(frame_type)),
+            };
+            Ok(frame::Frame::Drain { reason, deadline_unix_millis })
+        },
+
+        frame::GOAWAY_FRAMETYPE => {
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let reason_bytes = frame_body_data.split_off(2);
+            let last_tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            let reason = parse_go_away_reason(&reason_bytes)?;
+            Ok(frame::Frame::GoAway { last_tube_id, reason })
         },
 
         frame::NEWTUBE_FRAMETYPE => {
-            let mut header_bytes = frame_body_data.split_off(2);
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let header_bytes = frame_body_data.split_off(2);
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            let headers_str = match std::str::from_utf8(header_bytes.make_contiguous()) {
-                Ok(str) => str,
-                Err(utf8_err) => return Err(FrameParseError::HeaderUtf8Error(utf8_err))
-            };
-            let headers = match serde_json::from_str::<HashMap<String, String>>(&headers_str) {
-                Ok(headers) => headers,
-                Err(json_err) => return Err(FrameParseError::HeaderJsonDecodeError(json_err))
-            };
+            ).into();
+            let headers = parse_headers(&header_bytes, limits)?;
             Ok(frame::Frame::NewTube { tube_id, headers })
         },
 
         frame::PAYLOAD_FRAMETYPE => {
-            let data = frame_body_data.split_off(4).make_contiguous().to_vec();
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 4 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let data = frame_body_data.split_off(4);
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
+            ).into();
+            let ack_id = parse_payload_ack_id(
+                frame_body_data[2], 
+                frame_body_data[3],
             );
-            let ack_id = 
-                // First bit of ack_id indicates whether an ACK is expected for 
-                // this payload and should not be considered when interpreting 
-                // the ack_id value.
-                if (0b1000_0000 & frame_body_data[2]) > 0 {
-                    Some(double_u8_to_u16(
-                        0b0111_1111 & frame_body_data[2],
-                        frame_body_data[3]
-                    ))
-                } else {
-                    None
-                };
-            Ok(frame::Frame::Payload { tube_id, ack_id, data })
+            Ok(frame::Frame::Payload { tube_id, ack_id, checksum: None, data })
         },
 
-        frame::PAYLOAD_ACK_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+        frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE => {
+            if frame_body_data.len() < 8 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let data = frame_body_data.split_off(8);
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
+            ).into();
+            let ack_id = parse_payload_ack_id(
+                frame_body_data[2], 
+                frame_body_data[3],
             );
+            let checksum = u32::from_be_bytes([
+                frame_body_data[4],
+                frame_body_data[5],
+                frame_body_data[6],
+                frame_body_data[7],
+            ]);
+            Ok(frame::Frame::Payload { 
+                tube_id, 
+                ack_id, 
+                checksum: Some(checksum), 
+                data,
+            })
+        },
+
+        frame::PARTIAL_PAYLOAD_FRAMETYPE => {
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let data = frame_body_data.split_off(2);
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            Ok(frame::Frame::PartialPayload { tube_id, checksum: None, data })
+        },
+
+        frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE => {
+            if frame_body_data.len() < 6 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let data = frame_body_data.split_off(6);
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            let checksum = u32::from_be_bytes([
+                frame_body_data[2],
+                frame_body_data[3],
+                frame_body_data[4],
+                frame_body_data[5],
+            ]);
+            Ok(frame::Frame::PartialPayload { tube_id, checksum: Some(checksum), data })
+        },
+
+        frame::PAYLOAD_ACK_FRAMETYPE => {
+            if frame_body_data.len() < 4 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
             let ack_id = double_u8_to_u16(
                 // ack_ids are always 15 bits. The 16th/MSB here is only used in
                 // Payload frames to indicate if an ack is actually requested.
@@ -102,20 +357,75 @@ fn parse_frame_body(frame_type: u8, mut frame_body_data: VecDeque<u8>)
             Ok(frame::Frame::PayloadAck { tube_id, ack_id })
         },
 
+        frame::SEQUENCED_PAYLOAD_FRAMETYPE => {
+            if frame_body_data.len() < 10 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let data = frame_body_data.split_off(10);
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            let sequence_number = frame_body_data.slice(2..10).get_u64();
+            Ok(frame::Frame::SequencedPayload { tube_id, sequence_number, data })
+        },
+
+        frame::SELECTIVE_ACK_FRAMETYPE => {
+            if frame_body_data.len() < 2 || !(frame_body_data.len() - 2).is_multiple_of(16) {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let mut range_bytes = frame_body_data.split_off(2);
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            let mut ranges = Vec::with_capacity(range_bytes.len() / 16);
+            while range_bytes.has_remaining() {
+                let start = range_bytes.get_u64();
+                let end = range_bytes.get_u64();
+                if end < start {
+                    return Err(FrameParseError::EmptySequenceRange { start, end });
+                }
+                ranges.push(start..=end);
+            }
+            Ok(frame::Frame::SelectiveAck { tube_id, ranges })
+        },
+
         frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ).into();
             Ok(frame::Frame::ServerHasFinishedSending { tube_id })
         },
 
         frame::ABORT_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 3 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            let reason = frame::AbortReason::from(frame_body_data[2]);
+            ).into();
+            let reason = match frame::AbortReason::from(frame_body_data[2]) {
+                frame::AbortReason::ApplicationDefined { .. } => {
+                    if frame_body_data.len() < 7 {
+                        return Err(FrameParseError::TruncatedFrameBody(frame_type));
+                    }
+                    let message = parse_abort_message(&frame_body_data[7..])?;
+                    let code = u32::from_be_bytes([
+                        frame_body_data[3],
+                        frame_body_data[4],
+                        frame_body_data[5],
+                        frame_body_data[6],
+                    ]);
+                    frame::AbortReason::ApplicationDefined { code, message }
+                },
+                reason => reason,
+            };
             Ok(frame::Frame::Abort {
                 tube_id,
                 reason,
@@ -123,81 +433,577 @@ fn parse_frame_body(frame_type: u8, mut frame_body_data: VecDeque<u8>)
         },
 
         frame::ABORTACK_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ).into();
             Ok(frame::Frame::AbortAck {
                 tube_id,
             })
         },
 
+        frame::NEWTUBE_ACK_FRAMETYPE => {
+            if frame_body_data.len() < 2 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let tube_id: u32 = double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ).into();
+            Ok(frame::Frame::NewTubeAck {
+                tube_id,
+            })
+        },
+
+        frame::HEARTBEAT_FRAMETYPE => Ok(frame::Frame::Heartbeat),
+
+        frame::HEARTBEAT_ACK_FRAMETYPE => Ok(frame::Frame::HeartbeatAck),
+
+        frame::ERROR_FRAMETYPE => {
+            if frame_body_data.len() < 5 {
+                return Err(FrameParseError::TruncatedFrameBody(frame_type));
+            }
+            let detail_bytes = frame_body_data.split_off(5);
+            let tube_id = if frame_body_data[0] > 0 {
+                let tube_id: u32 = double_u8_to_u16(
+                    frame_body_data[1],
+                    frame_body_data[2],
+                ).into();
+                Some(tube_id)
+            } else {
+                None
+            };
+            let code = frame::ErrorCode::from(double_u8_to_u16(
+                frame_body_data[3],
+                frame_body_data[4],
+            ));
+            let detail = parse_error_detail(&detail_bytes)?;
+            Ok(frame::Frame::Error { tube_id, code, detail })
+        },
+
+        frame::MIN_EXTENSION_FRAMETYPE..=frame::MAX_EXTENSION_FRAMETYPE => {
+            Ok(frame::Frame::ExtensionFrame {
+                type_id: frame_type,
+                payload: frame_body_data.to_vec(),
+            })
+        },
+
+        _ => Err(FrameParseError::UnknownFrameType(frame_type)),
+    }
+}
+
+/**
+ * A cursor over a V2+ frame body, read in place from the chunk the frame 
+ * arrived in. Tube ids, varints and the like are read straight out of the 
+ * chunk; only payload data (which outlives the frame) is sliced out of it as 
+ * Bytes.
+ */
+struct FrameBody<'a> {
+    chunk: &'a Bytes,
+    end: usize,
+    frame_type: u8,
+    pos: usize,
+}
+impl<'a> FrameBody<'a> {
+    fn has_remaining(&self) -> bool {
+        self.pos < self.end
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        let byte = *self.remaining().first()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, FrameParseError> {
+        match self.remaining().first_chunk::<4>() {
+            Some(bytes) => {
+                self.pos += 4;
+                Ok(u32::from_be_bytes(*bytes))
+            },
+            None => Err(FrameParseError::TruncatedFrameBody(self.frame_type)),
+        }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, FrameParseError> {
+        let chunk: &'a [u8] = self.chunk;
+        match varint::read_varint(&chunk[..self.end], self.pos) {
+            Ok(Some((value, len))) => {
+                self.pos += len;
+                Ok(value)
+            },
+            Ok(None) => Err(FrameParseError::TruncatedFrameBody(self.frame_type)),
+            Err(varint::VarintDecodeError::Overflow) => Err(FrameParseError::InvalidVarint),
+        }
+    }
+
+    fn read_u16_varint(&mut self) -> Result<u16, FrameParseError> {
+        match u16::try_from(self.read_varint()?) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(FrameParseError::InvalidVarint),
+        }
+    }
+
+    fn read_u32_varint(&mut self) -> Result<u32, FrameParseError> {
+        match u32::try_from(self.read_varint()?) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(FrameParseError::InvalidVarint),
+        }
+    }
+
+    // The rest of the body, borrowed for fields that are parsed into owned 
+    // values (strings, headers) anyway.
+    fn remaining(&self) -> &'a [u8] {
+        let chunk: &'a [u8] = self.chunk;
+        &chunk[self.pos..self.end]
+    }
+
+    // The rest of the body, sliced out of the chunk without copying.
+    fn slice_remaining(&mut self) -> Bytes {
+        let data = self.chunk.slice(self.pos..self.end);
+        self.pos = self.end;
+        data
+    }
+}
+
+// Parses V2 through V5 frame bodies, which only differ in their NewTube 
+// header encoding.
+fn parse_frame_body_v2(
+    mut body: FrameBody,
+    version: frame::FramingVersion,
+    limits: &DecoderLimits,
+) -> Result<frame::Frame, FrameParseError> {
+    let frame_type = body.frame_type;
+    match frame_type {
+        frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            Ok(frame::Frame::ClientHasFinishedSending { tube_id })
+        },
+
+        frame::DRAIN_FRAMETYPE => {
+            let reason = match body.read_u8() {
+                Some(reason) => frame::DrainReason::from(reason),
+                None => frame::DrainReason::Unspecified,
+            };
+            let deadline_unix_millis = if body.has_remaining() {
+                Some(body.read_varint()?)
+            } else {
+                None
+            };
+            Ok(frame::Frame::Drain { reason, deadline_unix_millis })
+        },
+
+        frame::GOAWAY_FRAMETYPE => {
+            let last_tube_id = body.read_u32_varint()?;
+            let reason = parse_go_away_reason(body.remaining())?;
+            Ok(frame::Frame::GoAway { last_tube_id, reason })
+        },
+
+        frame::NEWTUBE_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let headers = match version {
+                frame::FramingVersion::V3 |
+                    frame::FramingVersion::V4 |
+                    frame::FramingVersion::V5 => 
+                    parse_header_block(body.remaining(), limits)?,
+                frame::FramingVersion::V1 | frame::FramingVersion::V2 => 
+                    parse_headers(body.remaining(), limits)?,
+            };
+            Ok(frame::Frame::NewTube { tube_id, headers })
+        },
+
+        frame::PAYLOAD_FRAMETYPE | frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let ack_id = match body.read_u16_varint()? {
+                0 => None,
+                ack_field => Some(ack_field - 1),
+            };
+            let checksum = if frame_type == frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE {
+                Some(body.read_u32()?)
+            } else {
+                None
+            };
+            let data = body.slice_remaining();
+            Ok(frame::Frame::Payload { tube_id, ack_id, checksum, data })
+        },
+
+        frame::PARTIAL_PAYLOAD_FRAMETYPE | frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let checksum = if frame_type == frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE {
+                Some(body.read_u32()?)
+            } else {
+                None
+            };
+            let data = body.slice_remaining();
+            Ok(frame::Frame::PartialPayload { tube_id, checksum, data })
+        },
+
+        frame::PAYLOAD_ACK_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let ack_id = body.read_u16_varint()?;
+            Ok(frame::Frame::PayloadAck { tube_id, ack_id })
+        },
+
+        frame::SEQUENCED_PAYLOAD_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let sequence_number = body.read_varint()?;
+            let data = body.slice_remaining();
+            Ok(frame::Frame::SequencedPayload { tube_id, sequence_number, data })
+        },
+
+        frame::SELECTIVE_ACK_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let mut ranges = vec![];
+            while body.has_remaining() {
+                let start = body.read_varint()?;
+                let length = body.read_varint()?;
+                let end = match start.checked_add(length) {
+                    Some(end) => end,
+                    None => return Err(FrameParseError::InvalidVarint),
+                };
+                ranges.push(start..=end);
+            }
+            Ok(frame::Frame::SelectiveAck { tube_id, ranges })
+        },
+
+        frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            Ok(frame::Frame::ServerHasFinishedSending { tube_id })
+        },
+
+        frame::ABORT_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            let reason = match body.read_u8() {
+                Some(reason) => frame::AbortReason::from(reason),
+                None => return Err(FrameParseError::TruncatedFrameBody(frame_type)),
+            };
+            let reason = match reason {
+                frame::AbortReason::ApplicationDefined { .. } => {
+                    let code = body.read_u32_varint()?;
+                    let message = parse_abort_message(body.remaining())?;
+                    frame::AbortReason::ApplicationDefined { code, message }
+                },
+                reason => reason,
+            };
+            Ok(frame::Frame::Abort { tube_id, reason })
+        },
+
+        frame::ABORTACK_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            Ok(frame::Frame::AbortAck { tube_id })
+        },
+
+        frame::NEWTUBE_ACK_FRAMETYPE => {
+            let tube_id = body.read_u32_varint()?;
+            Ok(frame::Frame::NewTubeAck { tube_id })
+        },
+
+        frame::HEARTBEAT_FRAMETYPE => Ok(frame::Frame::Heartbeat),
+
+        frame::HEARTBEAT_ACK_FRAMETYPE => Ok(frame::Frame::HeartbeatAck),
+
+        frame::ERROR_FRAMETYPE => {
+            let tube_id = match body.read_varint()? {
+                0 => None,
+                tube_id_field => match u32::try_from(tube_id_field - 1) {
+                    Ok(tube_id) => Some(tube_id),
+                    Err(_) => return Err(FrameParseError::InvalidVarint),
+                },
+            };
+            let code = frame::ErrorCode::from(body.read_u16_varint()?);
+            let detail = parse_error_detail(body.remaining())?;
+            Ok(frame::Frame::Error { tube_id, code, detail })
+        },
+
+        frame::MIN_EXTENSION_FRAMETYPE..=frame::MAX_EXTENSION_FRAMETYPE => {
+            let payload = body.remaining().to_vec();
+            Ok(frame::Frame::ExtensionFrame { type_id: frame_type, payload })
+        },
+
         _ => Err(FrameParseError::UnknownFrameType(frame_type)),
     }
 }
 
+/**
+ * Incrementally decodes frames from chunks of data as they arrive from the 
+ * transport. Frames are yielded as soon as they are complete, and frame 
+ * bodies are sliced out of the incoming chunks rather than copied. The only 
+ * copying happens when a frame straddles multiple chunks, in which case just 
+ * that frame's bytes are accumulated in an internal buffer until the rest 
+ * arrives; the frames after it in the chunk that completes it are still 
+ * decoded in place.
+ */
 pub struct Decoder {
-    partial_data: VecDeque<u8>,
+    limits: DecoderLimits,
+    partial_data: BytesMut,
+    recovery_mode: DecoderRecoveryMode,
+    // The number of bytes of an oversized frame that have yet to arrive and 
+    // should be discarded when they do.
+    skip_remaining: usize,
+    skipped_frame_errors: Vec<FrameParseError>,
+    version: frame::FramingVersion,
+}
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Decoder {
     pub fn new() -> Self {
+        Self::new_with_version(frame::FramingVersion::V1)
+    }
+
+    pub fn new_with_version(version: frame::FramingVersion) -> Self {
         Decoder {
-            partial_data: VecDeque::new(),
+            limits: DecoderLimits::default(),
+            partial_data: BytesMut::new(),
+            recovery_mode: DecoderRecoveryMode::Fail,
+            skip_remaining: 0,
+            skipped_frame_errors: vec![],
+            version,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: DecoderLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_recovery_mode(mut self, recovery_mode: DecoderRecoveryMode) -> Self {
+        self.recovery_mode = recovery_mode;
+        self
+    }
+
+    /**
+     * Returns (and forgets) the errors for any frames that were skipped in 
+     * DecoderRecoveryMode::SkipMalformedFrames.
+     */
+    pub fn take_skipped_frame_errors(&mut self) -> Vec<FrameParseError> {
+        std::mem::take(&mut self.skipped_frame_errors)
+    }
+
+    // Returns the (header_len, body_len) of the next frame in data, or None if
+    // not enough data has arrived to know yet.
+    fn peek_frame_header(&self, data: &[u8]) -> Result<Option<(usize, usize)>, FrameParseError> {
+        match self.version {
+            frame::FramingVersion::V1 => {
+                if data.len() < 3 {
+                    return Ok(None);
+                }
+                let body_len = double_u8_to_u16(data[1], data[2]);
+                Ok(Some((3, body_len.into())))
+            },
+
+            frame::FramingVersion::V2 |
+                frame::FramingVersion::V3 |
+                frame::FramingVersion::V4 |
+                frame::FramingVersion::V5 => {
+                match varint::read_varint(data, 1) {
+                    Ok(Some((body_len, varint_len))) => 
+                        match usize::try_from(body_len) {
+                            Ok(body_len) => Ok(Some((1 + varint_len, body_len))),
+                            Err(_) => Err(FrameParseError::InvalidVarint),
+                        },
+                    Ok(None) => Ok(None),
+                    Err(varint::VarintDecodeError::Overflow) => 
+                        Err(FrameParseError::InvalidVarint),
+                }
+            },
+        }
+    }
+
+    fn parse_frame(
+        &self,
+        chunk: &Bytes,
+        frame_start: usize,
+        header_len: usize,
+        frame_end: usize,
+    ) -> Result<frame::Frame, FrameParseError> {
+        let frame_type = chunk[frame_start];
+        // Skip past the FrameBodyByteLength bytes
+        let body_start = frame_start + header_len;
+        let frame = match self.version {
+            frame::FramingVersion::V1 => parse_frame_body(
+                frame_type,
+                chunk.slice(body_start..frame_end),
+                &self.limits,
+            )?,
+            frame::FramingVersion::V2 |
+                frame::FramingVersion::V3 |
+                frame::FramingVersion::V4 |
+                frame::FramingVersion::V5 => parse_frame_body_v2(
+                    FrameBody { chunk, end: frame_end, frame_type, pos: body_start },
+                    self.version,
+                    &self.limits,
+                )?,
+        };
+        if let frame::Frame::Payload { ref data, .. } | 
+                frame::Frame::PartialPayload { ref data, .. } |
+                frame::Frame::SequencedPayload { ref data, .. } = frame {
+            check_limit(DecoderLimit::PayloadSize, self.limits.max_payload_size, data.len())?;
         }
+        Ok(frame)
     }
 
     pub fn decode(
         &mut self, 
         data: Vec<u8>,
     ) -> Result<VecDeque<frame::Frame>, FrameDecodeError> {
-        self.partial_data.append(&mut VecDeque::from(data));
+        self.decode_bytes(Bytes::from(data))
+    }
 
+    pub fn decode_bytes(
+        &mut self, 
+        chunk: Bytes,
+    ) -> Result<VecDeque<frame::Frame>, FrameDecodeError> {
         let mut decoded_frames = VecDeque::new();
-        while self.partial_data.len() >= 3 {
-            let body_len = double_u8_to_u16(
-                self.partial_data[1], 
-                self.partial_data[2],
-            );
+        match self.decode_chunk(chunk, &mut decoded_frames) {
+            Ok(()) => Ok(decoded_frames),
+            Err(parse_error) => Err(FrameDecodeError {
+                parse_error,
+                num_frames_parsed_successfully: decoded_frames.len(),
+            }),
+        }
+    }
+
+    fn decode_chunk(
+        &mut self,
+        mut chunk: Bytes,
+        decoded_frames: &mut VecDeque<frame::Frame>,
+    ) -> Result<(), FrameParseError> {
+        self.discard_skipped(&mut chunk);
+
+        // A frame left partway through by the previous chunk is finished off 
+        // in partial_data, after which the rest of this chunk can be decoded 
+        // in place.
+        if !self.partial_data.is_empty() {
+            let frame_data = match self.complete_partial_frame(&mut chunk) {
+                Some(frame_data) => frame_data,
+                None => return Ok(()),
+            };
+            if let Err(parse_error) = self.decode_frames(frame_data, decoded_frames) {
+                self.partial_data.extend_from_slice(&chunk);
+                return Err(parse_error);
+            }
+            self.discard_skipped(&mut chunk);
+        }
 
-            // If we have at least a full frame, parse it
-            if (self.partial_data.len() as u16) >= 3 + body_len {
-                let index_after_last_frame_byte: usize = (3 + body_len).into();
-                let mut frame_data = 
-                    self.partial_data
-                        .drain(0..index_after_last_frame_byte)
-                        .collect::<VecDeque<_>>();
-
-                let frame_type = match frame_data.pop_front() {
-                    Some(ft) => ft,
-                    None => {
-                        let logic_error_str = concat!(
-                            "Although partial_data size was big enough, ",
-                            "pop_front returned None??"
-                        );
-                        return Err(FrameDecodeError {
-                            parse_error: 
-                                FrameParseError::InternalByteOffsetLogicError(
-                                    logic_error_str.to_string()
-                                ),
-                            num_frames_parsed_successfully: decoded_frames.len(),
-                        });
+        self.decode_frames(chunk, decoded_frames)
+    }
+
+    // Moves just enough of chunk into partial_data to complete the frame 
+    // that's partway through arriving, and returns that frame's data. Returns 
+    // None if chunk runs out first.
+    fn complete_partial_frame(&mut self, chunk: &mut Bytes) -> Option<Bytes> {
+        let (header_len, body_len) = loop {
+            match self.peek_frame_header(&self.partial_data) {
+                Ok(Some(frame_header_lens)) => break frame_header_lens,
+                Ok(None) => {
+                    // Headers are only a few bytes long, so finish them off a 
+                    // byte at a time.
+                    if chunk.is_empty() {
+                        return None;
                     }
-                };
-                frame_data.pop_front(); // FrameBodyByteLength[0]
-                frame_data.pop_front(); // FrameBodyByteLength[1]
-                match parse_frame_body(frame_type, frame_data) {
-                    Ok(frame) => decoded_frames.push_back(frame),
-                    Err(decode_error) => return Err(FrameDecodeError {
-                        parse_error: decode_error,
-                        num_frames_parsed_successfully: decoded_frames.len(),
-                    })
+                    self.partial_data.extend_from_slice(&chunk[..1]);
+                    chunk.advance(1);
+                },
+                // Leave it to decode_frames() to report the error
+                Err(_) => return Some(self.partial_data.split().freeze()),
+            }
+        };
+
+        // Oversized frames are never buffered: decode_frames() fails or skips 
+        // them as soon as it sees their header.
+        if body_len > self.limits.max_frame_size {
+            return Some(self.partial_data.split().freeze());
+        }
+
+        let frame_len = header_len + body_len;
+        if self.partial_data.len() >= frame_len {
+            // More than a partial frame was left over (because a previous 
+            // decode failed partway through), so just decode it all.
+            self.partial_data.extend_from_slice(chunk);
+            chunk.clear();
+            return Some(self.partial_data.split().freeze());
+        }
+
+        let num_needed = frame_len - self.partial_data.len();
+        let num_taken = num_needed.min(chunk.len());
+        self.partial_data.extend_from_slice(&chunk[..num_taken]);
+        chunk.advance(num_taken);
+        if num_taken < num_needed {
+            return None;
+        }
+        Some(self.partial_data.split().freeze())
+    }
+
+    // Decodes the frames in data in place, slicing payloads out of it as they 
+    // go. Whatever is left over (the start of a frame that hasn't fully 
+    // arrived yet) is kept in partial_data.
+    fn decode_frames(
+        &mut self,
+        data: Bytes,
+        decoded_frames: &mut VecDeque<frame::Frame>,
+    ) -> Result<(), FrameParseError> {
+        let mut pos = 0;
+        let result = loop {
+            let (header_len, body_len) = match self.peek_frame_header(&data[pos..]) {
+                Ok(Some(frame_header_lens)) => frame_header_lens,
+                Ok(None) => break Ok(()),
+                Err(parse_error) => break Err(parse_error),
+            };
+
+            if let Err(parse_error) = check_limit(
+                DecoderLimit::FrameSize, 
+                self.limits.max_frame_size, 
+                body_len,
+            ) {
+                if self.recovery_mode == DecoderRecoveryMode::Fail {
+                    break Err(parse_error);
                 }
-            } else {
-                break;
+                log::warn!("Skipping oversized frame: {:?}", parse_error);
+                self.skipped_frame_errors.push(parse_error);
+                let num_skipped = (header_len + body_len).min(data.len() - pos);
+                pos += num_skipped;
+                self.skip_remaining = (header_len + body_len) - num_skipped;
+                continue;
             }
+
+            // If we don't have a full frame yet, wait for more data
+            let frame_start = pos;
+            let frame_end = frame_start + header_len + body_len;
+            if data.len() < frame_end {
+                break Ok(());
+            }
+
+            pos = frame_end;
+            match self.parse_frame(&data, frame_start, header_len, frame_end) {
+                Ok(frame) => decoded_frames.push_back(frame),
+                Err(parse_error) => {
+                    if self.recovery_mode == DecoderRecoveryMode::Fail {
+                        break Err(parse_error);
+                    }
+                    log::warn!("Skipping malformed frame: {:?}", parse_error);
+                    self.skipped_frame_errors.push(parse_error);
+                },
+            }
+        };
+
+        if pos < data.len() {
+            self.partial_data.extend_from_slice(&data[pos..]);
         }
-        
-        Ok(decoded_frames)
+        result
+    }
+
+    // Discards the start of chunk if it's the rest of an oversized frame that 
+    // is being skipped.
+    fn discard_skipped(&mut self, chunk: &mut Bytes) {
+        let num_skipped = self.skip_remaining.min(chunk.len());
+        chunk.advance(num_skipped);
+        self.skip_remaining -= num_skipped;
     }
 }
 
@@ -260,6 +1066,66 @@ mod decoder_tests {
         assert_eq!(decoded_frames[0], frame::Frame::ServerHasFinishedSending { tube_id: 42 });
     }
 
+    #[test]
+    fn payload_data_is_sliced_from_incoming_chunk() {
+        let mut decoder = Decoder::new();
+        let chunk = Bytes::from(
+            encode::payload_frame(42, None, Bytes::from(vec![7; 1024])).unwrap()
+        );
+        let chunk_range = chunk.as_ptr_range();
+
+        let decoded_frames = decoder.decode_bytes(chunk.clone()).unwrap();
+        assert_eq!(decoded_frames.len(), 1);
+        match &decoded_frames[0] {
+            frame::Frame::Payload { data, .. } => {
+                assert_eq!(data.len(), 1024);
+                assert!(chunk_range.contains(&data.as_ptr()));
+            },
+            unexpected => panic!("Unexpected frame: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn frame_split_across_many_chunks_is_reassembled() {
+        let mut decoder = Decoder::new();
+        let data = encode::payload_frame(42, Some(1), Bytes::from(vec![7; 100])).unwrap();
+
+        let mut decoded_frames = VecDeque::new();
+        for chunk in data.chunks(7) {
+            decoded_frames.append(
+                &mut decoder.decode_bytes(Bytes::copy_from_slice(chunk)).unwrap()
+            );
+        }
+        assert_eq!(decoded_frames.len(), 1);
+        assert_eq!(decoded_frames[0], frame::Frame::Payload {
+            tube_id: 42,
+            ack_id: Some(1),
+            checksum: None,
+            data: Bytes::from(vec![7; 100]),
+        });
+    }
+
+    #[test]
+    fn frames_after_a_reassembled_frame_are_sliced_from_the_completing_chunk() {
+        let mut decoder = Decoder::new();
+        let mut data = encode::payload_frame(42, None, Bytes::from(vec![7; 100])).unwrap();
+        data.append(&mut encode::payload_frame(44, None, Bytes::from(vec![8; 1024])).unwrap());
+
+        let second_chunk = Bytes::copy_from_slice(&data[50..]);
+        let second_chunk_range = second_chunk.as_ptr_range();
+        assert_eq!(decoder.decode(data[..50].to_vec()).unwrap().len(), 0);
+
+        let decoded_frames = decoder.decode_bytes(second_chunk).unwrap();
+        assert_eq!(decoded_frames.len(), 2);
+        match &decoded_frames[1] {
+            frame::Frame::Payload { tube_id: 44, data, .. } => {
+                assert_eq!(data, &Bytes::from(vec![8; 1024]));
+                assert!(second_chunk_range.contains(&data.as_ptr()));
+            },
+            unexpected => panic!("Unexpected frame: {:?}", unexpected),
+        }
+    }
+
     #[test]
     fn errors_if_invalid_utf8_passed_for_newtube_headers() {
         let mut decoder = Decoder::new();
@@ -368,5 +1234,210 @@ mod decoder_tests {
             )
         };
     }
+
+    #[test]
+    fn errors_on_selective_ack_range_that_ends_before_it_starts() {
+        let mut decoder = Decoder::new();
+
+        // TubeId=3, Range(start=9, end=7)
+        let mut data = vec![frame::SELECTIVE_ACK_FRAMETYPE, 0, 18, 0, 3];
+        data.extend_from_slice(&9u64.to_be_bytes());
+        data.extend_from_slice(&7u64.to_be_bytes());
+
+        match decoder.decode(data) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::EmptySequenceRange { start: 9, end: 7 },
+              ..
+            }) => (),
+            unexpected => panic!("Unexpected decode result: {:?}", unexpected),
+        }
+    }
+
+    /**
+     * Decodes V1 frames of frame_type with every body length shorter than 
+     * min_body_len, with and without DecoderRecoveryMode::SkipMalformedFrames.
+     */
+    fn assert_truncated_v1_frames_error(frame_type: u8, min_body_len: u16) {
+        for body_len in 0..min_body_len {
+            let mut data = vec![frame_type];
+            data.extend_from_slice(&body_len.to_be_bytes());
+            data.extend(vec![0; body_len.into()]);
+
+            match Decoder::new().decode(data.clone()) {
+                Err(FrameDecodeError {
+                  parse_error: FrameParseError::TruncatedFrameBody(parsed_frame_type),
+                  ..
+                }) => assert_eq!(parsed_frame_type, frame_type),
+                unexpected => panic!(
+                    "Unexpected decode result for frame_type={} with body_len={}: {:?}",
+                    frame_type,
+                    body_len,
+                    unexpected,
+                ),
+            }
+
+            let mut decoder = Decoder::new()
+                .with_recovery_mode(DecoderRecoveryMode::SkipMalformedFrames);
+            data.append(&mut encode::client_has_finished_sending_frame(43).unwrap());
+            assert_eq!(
+                decoder.decode(data).unwrap(),
+                vec![frame::Frame::ClientHasFinishedSending { tube_id: 43 }],
+            );
+            assert_eq!(decoder.take_skipped_frame_errors().len(), 1);
+        }
+    }
+
+    #[test]
+    fn errors_on_truncated_v1_frame_bodies() {
+        assert_truncated_v1_frames_error(frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, 2);
+        assert_truncated_v1_frames_error(frame::NEWTUBE_FRAMETYPE, 2);
+        assert_truncated_v1_frames_error(frame::PAYLOAD_FRAMETYPE, 4);
+        assert_truncated_v1_frames_error(frame::PAYLOAD_ACK_FRAMETYPE, 4);
+        assert_truncated_v1_frames_error(frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE, 2);
+        assert_truncated_v1_frames_error(frame::ABORT_FRAMETYPE, 3);
+        assert_truncated_v1_frames_error(frame::ABORTACK_FRAMETYPE, 2);
+        assert_truncated_v1_frames_error(frame::PARTIAL_PAYLOAD_FRAMETYPE, 2);
+        assert_truncated_v1_frames_error(frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE, 6);
+    }
+
+    #[test]
+    fn errors_on_truncated_v1_payload_with_checksum_frame() {
+        assert_truncated_v1_frames_error(frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE, 8);
+    }
+
+    #[test]
+    fn errors_on_truncated_v1_error_frame() {
+        assert_truncated_v1_frames_error(frame::ERROR_FRAMETYPE, 5);
+    }
+
+    #[test]
+    fn errors_on_truncated_v1_newtube_ack_frame() {
+        assert_truncated_v1_frames_error(frame::NEWTUBE_ACK_FRAMETYPE, 2);
+    }
+
+    fn assert_limit_exceeded(
+        result: Result<VecDeque<frame::Frame>, FrameDecodeError>,
+        expected_limit: DecoderLimit,
+    ) {
+        match result {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::LimitExceeded { limit, max, actual },
+              ..
+            }) => {
+                assert_eq!(limit, expected_limit);
+                assert!(actual > max);
+            },
+            unexpected => panic!("Unexpected decode result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_oversized_frame_before_buffering_its_body() {
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V2);
+
+        // A frame header that claims a 1GiB body
+        let mut data = vec![frame::PAYLOAD_FRAMETYPE];
+        varint::write_varint(1024 * 1024 * 1024, &mut data);
+
+        assert_limit_exceeded(decoder.decode(data), DecoderLimit::FrameSize);
+    }
+
+    #[test]
+    fn errors_on_payload_larger_than_limit() {
+        let mut decoder = Decoder::new().with_limits(DecoderLimits {
+            max_payload_size: 10,
+            ..DecoderLimits::default()
+        });
+        let data = encode::payload_frame(42, None, Bytes::from(vec![7; 11])).unwrap();
+
+        assert_limit_exceeded(decoder.decode(data), DecoderLimit::PayloadSize);
+    }
+
+    #[test]
+    fn errors_on_too_many_headers() {
+        let mut decoder = Decoder::new().with_limits(DecoderLimits {
+            max_header_count: 1,
+            ..DecoderLimits::default()
+        });
+        let headers = HashMap::from([
+          ("header1".to_string(), "value1".to_string()),
+          ("header2".to_string(), "value2".to_string()),
+        ]);
+        let data = encode::newtube_frame(42, headers).unwrap();
+
+        assert_limit_exceeded(decoder.decode(data), DecoderLimit::HeaderCount);
+    }
+
+    #[test]
+    fn skip_mode_resumes_decoding_after_malformed_frame() {
+        let mut decoder = Decoder::new()
+            .with_recovery_mode(DecoderRecoveryMode::SkipMalformedFrames);
+
+        let mut data = encode::client_has_finished_sending_frame(42).unwrap();
+        data[0] = 255;
+        data.append(&mut encode::client_has_finished_sending_frame(43).unwrap());
+
+        let decoded_frames = decoder.decode(data).unwrap();
+        assert_eq!(decoded_frames.len(), 1);
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
+
+        let skipped_frame_errors = decoder.take_skipped_frame_errors();
+        assert_eq!(skipped_frame_errors.len(), 1);
+        match skipped_frame_errors[0] {
+            FrameParseError::UnknownFrameType(255) => (),
+            ref unexpected => panic!("Unexpected skipped error: {:?}", unexpected),
+        }
+        assert!(decoder.take_skipped_frame_errors().is_empty());
+    }
+
+    #[test]
+    fn skip_mode_discards_oversized_frame_across_chunks() {
+        let mut decoder = Decoder::new()
+            .with_limits(DecoderLimits {
+                max_frame_size: 20,
+                ..DecoderLimits::default()
+            })
+            .with_recovery_mode(DecoderRecoveryMode::SkipMalformedFrames);
+
+        let mut data = encode::payload_frame(42, None, Bytes::from(vec![7; 100])).unwrap();
+        data.append(&mut encode::client_has_finished_sending_frame(43).unwrap());
+
+        let mut decoded_frames = VecDeque::new();
+        for chunk in data.chunks(30) {
+            decoded_frames.append(
+                &mut decoder.decode_bytes(Bytes::copy_from_slice(chunk)).unwrap()
+            );
+        }
+        assert_eq!(decoded_frames.len(), 1);
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
+        assert_eq!(decoder.take_skipped_frame_errors().len(), 1);
+    }
+
+    #[test]
+    fn skip_mode_discards_oversized_frame_whose_header_spans_chunks() {
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V2)
+            .with_limits(DecoderLimits {
+                max_frame_size: 20,
+                ..DecoderLimits::default()
+            })
+            .with_recovery_mode(DecoderRecoveryMode::SkipMalformedFrames);
+
+        // A 200 byte extension frame (whose length takes a 2 byte varint),
+        // followed by a ClientHasFinishedSending frame
+        let mut data = vec![frame::MIN_EXTENSION_FRAMETYPE];
+        varint::write_varint(200, &mut data);
+        data.extend_from_slice(&[0; 200]);
+        data.extend_from_slice(&[frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, 1, 43]);
+
+        let mut decoded_frames = VecDeque::new();
+        for chunk in data.chunks(2) {
+            decoded_frames.append(
+                &mut decoder.decode_bytes(Bytes::copy_from_slice(chunk)).unwrap()
+            );
+        }
+        assert_eq!(decoded_frames.len(), 1);
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
+        assert_eq!(decoder.take_skipped_frame_errors().len(), 1);
+    }
 }
 
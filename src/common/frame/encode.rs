@@ -1,150 +1,776 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
+
+use super::buffer_pool::BufferPool;
+use super::buffer_pool::BufferPoolConfig;
+use super::buffer_pool::BufferPoolStats;
+use super::buffer_pool::SharedBufferPoolStats;
+use super::checksum;
+use super::extension;
 use super::frame;
+use super::header_block;
+use super::varint;
 
 #[derive(Debug)]
 pub enum FrameEncodeError {
     AckIdTooLarge(u16),
     DataTooLarge(usize),
+    EmptySequenceRange {
+        start: u64,
+        end: u64,
+    },
     HeaderJsonEncodeError(serde_json::error::Error),
+    InvalidExtensionFrameType(u8),
+    NonUtf8HeaderValue(String),
+    TubeIdTooLarge(u32),
+}
+
+// V1 frames only have room for 16-bit TubeIds
+fn v1_tube_id_bytes(tube_id: u32) -> Result<[u8; 2], FrameEncodeError> {
+    match u16::try_from(tube_id) {
+        Ok(tube_id) => Ok(tube_id.to_be_bytes()),
+        Err(_) => Err(FrameEncodeError::TubeIdTooLarge(tube_id)),
+    }
+}
+
+// Prior to V3, NewTube headers are encoded as JSON and so values must be UTF-8
+fn write_json_headers(
+    headers: &HashMap<String, Vec<u8>>,
+    body: &mut Vec<u8>,
+) -> Result<(), FrameEncodeError> {
+    let mut str_headers = HashMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        match std::str::from_utf8(value) {
+            Ok(value) => str_headers.insert(name, value),
+            Err(_) => return Err(FrameEncodeError::NonUtf8HeaderValue(name.clone())),
+        };
+    }
+    match serde_json::to_writer(body, &str_headers) {
+        Ok(()) => Ok(()),
+        Err(json_err) => Err(FrameEncodeError::HeaderJsonEncodeError(json_err)),
+    }
+}
+
+pub fn encode_frame(frame: frame::Frame) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame_with_version(frame, frame::FramingVersion::V1)
+}
+
+pub fn encode_frame_with_version(
+    frame: frame::Frame,
+    version: frame::FramingVersion,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    let mut body = vec![];
+    let frame_type = encode_frame_body(frame, version, &mut body)?;
+    let mut bytes = Vec::with_capacity(encoded_frame_len(body.len(), version));
+    write_frame(frame_type, &body, version, &mut bytes);
+    Ok(bytes)
+}
+
+// SelectiveAck ranges are inclusive, so they always contain at least one 
+// sequence number.
+fn check_sequence_range(range: &RangeInclusive<u64>) -> Result<(), FrameEncodeError> {
+    if range.is_empty() {
+        Err(FrameEncodeError::EmptySequenceRange { 
+            start: *range.start(), 
+            end: *range.end(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/**
+ * Replaces the contents of body with the encoded body of frame and returns 
+ * frame's FrameType. If encoding fails, body is left in an unspecified state.
+ */
+fn encode_frame_body(
+    frame: frame::Frame,
+    version: frame::FramingVersion,
+    body: &mut Vec<u8>,
+) -> Result<u8, FrameEncodeError> {
+    body.clear();
+    let frame_type = match version {
+        frame::FramingVersion::V1 => encode_frame_body_v1(frame, body)?,
+        frame::FramingVersion::V2 |
+            frame::FramingVersion::V3 |
+            frame::FramingVersion::V4 |
+            frame::FramingVersion::V5 => 
+            encode_frame_body_v2(frame, version, body)?,
+    };
+
+    if body.len() > u16::MAX as usize {
+        return Err(FrameEncodeError::DataTooLarge(body.len()));
+    }
+    Ok(frame_type)
+}
+
+fn encoded_frame_len(body_len: usize, version: frame::FramingVersion) -> usize {
+    // FrameType + FrameBodyByteLength + FrameBody
+    match version {
+        frame::FramingVersion::V1 => 1 + 2 + body_len,
+        frame::FramingVersion::V2 |
+            frame::FramingVersion::V3 |
+            frame::FramingVersion::V4 |
+            frame::FramingVersion::V5 => 
+            1 + varint::varint_len(body_len as u64) + body_len,
+    }
+}
+
+fn write_frame(
+    frame_type: u8,
+    body: &[u8],
+    version: frame::FramingVersion,
+    out: &mut impl BufMut,
+) {
+    out.put_u8(frame_type);
+    match version {
+        frame::FramingVersion::V1 => out.put_u16(body.len() as u16),
+        frame::FramingVersion::V2 |
+            frame::FramingVersion::V3 |
+            frame::FramingVersion::V4 |
+            frame::FramingVersion::V5 => 
+            varint::write_varint(body.len() as u64, out),
+    };
+    out.put_slice(body);
+}
+
+fn encode_frame_body_v1(
+    frame: frame::Frame,
+    body: &mut Vec<u8>,
+) -> Result<u8, FrameEncodeError> {
+    use frame::Frame::*;
+    let frame_type = match frame {
+        ClientHasFinishedSending { tube_id } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE
+        },
+        Drain { reason, deadline_unix_millis } => {
+            if reason != frame::DrainReason::Unspecified || deadline_unix_millis.is_some() {
+                body.push(reason.into());
+                if let Some(deadline_unix_millis) = deadline_unix_millis {
+                    body.extend_from_slice(&deadline_unix_millis.to_be_bytes());
+                }
+            }
+            frame::DRAIN_FRAMETYPE
+        },
+        GoAway { last_tube_id, reason } => {
+            // LastTubeId(2) + Reason must fit within BodyLenBytes
+            if reason.len() > (u16::MAX as usize) - 2 {
+                return Err(FrameEncodeError::DataTooLarge(reason.len()))
+            }
+            body.extend_from_slice(&v1_tube_id_bytes(last_tube_id)?);
+            body.extend_from_slice(reason.as_bytes());
+            frame::GOAWAY_FRAMETYPE
+        },
+        NewTube { tube_id, headers } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            write_json_headers(&headers, body)?;
+            frame::NEWTUBE_FRAMETYPE
+        },
+        Payload { tube_id, ack_id, checksum, data } => {
+            // TubeId(2) + AckId(2) + [Crc32(4)] + Data must fit within 
+            // BodyLenBytes
+            let (frame_type, header_len) = match checksum {
+                Some(_) => (frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE, 2 + 2 + 4),
+                None => (frame::PAYLOAD_FRAMETYPE, 2 + 2),
+            };
+            if data.len() > (u16::MAX as usize) - header_len {
+                return Err(FrameEncodeError::DataTooLarge(data.len()))
+            }
+
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            body.extend_from_slice(&payload_ack_bytes(ack_id)?);
+            // The checksum is always (re)computed from the data being encoded.
+            if checksum.is_some() {
+                body.extend_from_slice(&checksum::crc32(&data).to_be_bytes());
+            }
+            body.extend_from_slice(&data);
+            frame_type
+        },
+        PartialPayload { tube_id, checksum, data } => {
+            // TubeId(2) + [Crc32(4)] + Data must fit within BodyLenBytes
+            let (frame_type, header_len) = match checksum {
+                Some(_) => (frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE, 2 + 4),
+                None => (frame::PARTIAL_PAYLOAD_FRAMETYPE, 2),
+            };
+            if data.len() > (u16::MAX as usize) - header_len {
+                return Err(FrameEncodeError::DataTooLarge(data.len()))
+            }
+
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            // The checksum is always (re)computed from the data being encoded.
+            if checksum.is_some() {
+                body.extend_from_slice(&checksum::crc32(&data).to_be_bytes());
+            }
+            body.extend_from_slice(&data);
+            frame_type
+        },
+        PayloadAck { tube_id, ack_id } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            if ((0b1000_0000 << 8) & ack_id) > 0 {
+                return Err(FrameEncodeError::AckIdTooLarge(ack_id));
+            }
+            body.extend_from_slice(&ack_id.to_be_bytes());
+            frame::PAYLOAD_ACK_FRAMETYPE
+        },
+        SequencedPayload { tube_id, sequence_number, data } => {
+            // TubeId(2) + SequenceNumber(8) + Data must fit within 
+            // BodyLenBytes
+            if data.len() > (u16::MAX as usize) - (2 + 8) {
+                return Err(FrameEncodeError::DataTooLarge(data.len()))
+            }
+
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            body.extend_from_slice(&sequence_number.to_be_bytes());
+            body.extend_from_slice(&data);
+            frame::SEQUENCED_PAYLOAD_FRAMETYPE
+        },
+        SelectiveAck { tube_id, ranges } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            for range in ranges {
+                check_sequence_range(&range)?;
+                body.extend_from_slice(&range.start().to_be_bytes());
+                body.extend_from_slice(&range.end().to_be_bytes());
+            }
+            frame::SELECTIVE_ACK_FRAMETYPE
+        },
+        ServerHasFinishedSending { tube_id } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE
+        },
+        Abort { tube_id, reason } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            if let frame::AbortReason::ApplicationDefined { code, ref message } = reason {
+                let message_len = message.as_ref().map_or(0, |message| message.len());
+                // TubeId(2) + AbortReason(1) + Code(4) + Message must fit 
+                // within BodyLenBytes
+                if 4 + message_len > (u16::MAX as usize) - (2 + 1) {
+                    return Err(FrameEncodeError::DataTooLarge(4 + message_len));
+                }
+                body.push(reason.clone().into());
+                body.extend_from_slice(&code.to_be_bytes());
+                if let Some(message) = message {
+                    body.extend_from_slice(message.as_bytes());
+                }
+            } else {
+                body.push(reason.into());
+            }
+            frame::ABORT_FRAMETYPE
+        },
+        AbortAck { tube_id } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            frame::ABORTACK_FRAMETYPE
+        },
+        NewTubeAck { tube_id } => {
+            body.extend_from_slice(&v1_tube_id_bytes(tube_id)?);
+            frame::NEWTUBE_ACK_FRAMETYPE
+        },
+        Heartbeat => frame::HEARTBEAT_FRAMETYPE,
+        HeartbeatAck => frame::HEARTBEAT_ACK_FRAMETYPE,
+        Error { tube_id, code, detail } => {
+            // HasTubeId(1) + TubeId(2) + ErrorCode(2) + Detail must fit within 
+            // BodyLenBytes
+            if detail.len() > (u16::MAX as usize) - (1 + 2 + 2) {
+                return Err(FrameEncodeError::DataTooLarge(detail.len()))
+            }
+
+            let (has_tube_id, tubeid_bytes) = match tube_id {
+                Some(tube_id) => (1, v1_tube_id_bytes(tube_id)?),
+                None => (0, [0, 0]),
+            };
+            body.push(has_tube_id);
+            body.extend_from_slice(&tubeid_bytes);
+            body.extend_from_slice(&u16::from(code).to_be_bytes());
+            body.extend_from_slice(detail.as_bytes());
+            frame::ERROR_FRAMETYPE
+        },
+        ExtensionFrame { type_id, payload } => {
+            if !extension::is_extension_frame_type(type_id) {
+                return Err(FrameEncodeError::InvalidExtensionFrameType(type_id));
+            }
+            if payload.len() > u16::MAX as usize {
+                return Err(FrameEncodeError::DataTooLarge(payload.len()));
+            }
+            body.extend_from_slice(&payload);
+            type_id
+        },
+    };
+    Ok(frame_type)
+}
+
+// Encodes V2 through V5 frame bodies, which only differ in their NewTube 
+// header encoding.
+fn encode_frame_body_v2(
+    frame: frame::Frame,
+    version: frame::FramingVersion,
+    body: &mut Vec<u8>,
+) -> Result<u8, FrameEncodeError> {
+    use frame::Frame::*;
+    let frame_type = match frame {
+        ClientHasFinishedSending { tube_id } => {
+            varint::write_varint(tube_id as u64, body);
+            frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE
+        },
+        Drain { reason, deadline_unix_millis } => {
+            if reason != frame::DrainReason::Unspecified || deadline_unix_millis.is_some() {
+                body.push(reason.into());
+                if let Some(deadline_unix_millis) = deadline_unix_millis {
+                    varint::write_varint(deadline_unix_millis, body);
+                }
+            }
+            frame::DRAIN_FRAMETYPE
+        },
+        GoAway { last_tube_id, reason } => {
+            varint::write_varint(last_tube_id as u64, body);
+            body.extend_from_slice(reason.as_bytes());
+            frame::GOAWAY_FRAMETYPE
+        },
+        NewTube { tube_id, headers } => {
+            varint::write_varint(tube_id as u64, body);
+            match version {
+                frame::FramingVersion::V3 |
+                    frame::FramingVersion::V4 |
+                    frame::FramingVersion::V5 => 
+                    header_block::write_header_block(&headers, body),
+                frame::FramingVersion::V1 | frame::FramingVersion::V2 => 
+                    write_json_headers(&headers, body)?,
+            };
+            frame::NEWTUBE_FRAMETYPE
+        },
+        Payload { tube_id, ack_id, checksum, data } => {
+            varint::write_varint(tube_id as u64, body);
+            let ack_field = match ack_id {
+                Some(ack_id) if ack_id > frame::MAX_ACK_ID => 
+                    return Err(FrameEncodeError::AckIdTooLarge(ack_id)),
+                Some(ack_id) => (ack_id as u64) + 1,
+                None => 0,
+            };
+            varint::write_varint(ack_field, body);
+            let frame_type = match checksum {
+                Some(_) => {
+                    body.extend_from_slice(&checksum::crc32(&data).to_be_bytes());
+                    frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE
+                },
+                None => frame::PAYLOAD_FRAMETYPE,
+            };
+            body.extend_from_slice(&data);
+            frame_type
+        },
+        PartialPayload { tube_id, checksum, data } => {
+            varint::write_varint(tube_id as u64, body);
+            let frame_type = match checksum {
+                Some(_) => {
+                    body.extend_from_slice(&checksum::crc32(&data).to_be_bytes());
+                    frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE
+                },
+                None => frame::PARTIAL_PAYLOAD_FRAMETYPE,
+            };
+            body.extend_from_slice(&data);
+            frame_type
+        },
+        PayloadAck { tube_id, ack_id } => {
+            if ack_id > frame::MAX_ACK_ID {
+                return Err(FrameEncodeError::AckIdTooLarge(ack_id));
+            }
+            varint::write_varint(tube_id as u64, body);
+            varint::write_varint(ack_id as u64, body);
+            frame::PAYLOAD_ACK_FRAMETYPE
+        },
+        SequencedPayload { tube_id, sequence_number, data } => {
+            varint::write_varint(tube_id as u64, body);
+            varint::write_varint(sequence_number, body);
+            body.extend_from_slice(&data);
+            frame::SEQUENCED_PAYLOAD_FRAMETYPE
+        },
+        SelectiveAck { tube_id, ranges } => {
+            varint::write_varint(tube_id as u64, body);
+            for range in ranges {
+                check_sequence_range(&range)?;
+                varint::write_varint(*range.start(), body);
+                varint::write_varint(range.end() - range.start(), body);
+            }
+            frame::SELECTIVE_ACK_FRAMETYPE
+        },
+        ServerHasFinishedSending { tube_id } => {
+            varint::write_varint(tube_id as u64, body);
+            frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE
+        },
+        Abort { tube_id, reason } => {
+            varint::write_varint(tube_id as u64, body);
+            if let frame::AbortReason::ApplicationDefined { code, ref message } = reason {
+                body.push(reason.clone().into());
+                varint::write_varint(code as u64, body);
+                if let Some(message) = message {
+                    body.extend_from_slice(message.as_bytes());
+                }
+            } else {
+                body.push(reason.into());
+            }
+            frame::ABORT_FRAMETYPE
+        },
+        AbortAck { tube_id } => {
+            varint::write_varint(tube_id as u64, body);
+            frame::ABORTACK_FRAMETYPE
+        },
+        NewTubeAck { tube_id } => {
+            varint::write_varint(tube_id as u64, body);
+            frame::NEWTUBE_ACK_FRAMETYPE
+        },
+        Heartbeat => frame::HEARTBEAT_FRAMETYPE,
+        HeartbeatAck => frame::HEARTBEAT_ACK_FRAMETYPE,
+        Error { tube_id, code, detail } => {
+            let tube_id_field = match tube_id {
+                Some(tube_id) => (tube_id as u64) + 1,
+                None => 0,
+            };
+            varint::write_varint(tube_id_field, body);
+            varint::write_varint(u16::from(code) as u64, body);
+            body.extend_from_slice(detail.as_bytes());
+            frame::ERROR_FRAMETYPE
+        },
+        ExtensionFrame { type_id, payload } => {
+            if !extension::is_extension_frame_type(type_id) {
+                return Err(FrameEncodeError::InvalidExtensionFrameType(type_id));
+            }
+            body.extend_from_slice(&payload);
+            type_id
+        },
+    };
+    Ok(frame_type)
+}
+
+/**
+ * Encodes frames into reusable buffers rather than allocating a fresh Vec per
+ * frame. Frames handed out by encode() are split off of buffers from a
+ * BufferPool, so once the transport has dropped them their memory is
+ * reclaimed for subsequent frames.
+ */
+#[derive(Debug)]
+pub struct Encoder {
+    body: Vec<u8>,
+    buffer_pool: BufferPool,
+    version: frame::FramingVersion,
+}
+impl Encoder {
+    pub fn new(version: frame::FramingVersion) -> Self {
+        Encoder::with_buffer_pool(version, BufferPoolConfig::default())
+    }
+
+    pub fn with_buffer_pool(version: frame::FramingVersion, config: BufferPoolConfig) -> Self {
+        Encoder {
+            body: vec![],
+            buffer_pool: BufferPool::new(config),
+            version,
+        }
+    }
+
+    pub fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool.stats().get()
+    }
+
+    pub(in crate) fn shared_buffer_pool_stats(&self) -> &SharedBufferPoolStats {
+        self.buffer_pool.stats()
+    }
+
+    pub fn version(&self) -> frame::FramingVersion {
+        self.version
+    }
+
+    pub fn encode(&mut self, frame: frame::Frame) -> Result<Bytes, FrameEncodeError> {
+        let frame_type = encode_frame_body(frame, self.version, &mut self.body)?;
+        let mut buf = self.buffer_pool.acquire(encoded_frame_len(self.body.len(), self.version));
+        write_frame(frame_type, &self.body, self.version, &mut buf);
+        let frame_data = buf.split().freeze();
+        self.buffer_pool.release(buf);
+        Ok(frame_data)
+    }
+
+    /**
+     * Encodes several frames into one contiguous chunk. If any frame fails to
+     * encode then none of them are returned.
+     */
+    pub fn encode_batch(
+        &mut self, 
+        frames: Vec<frame::Frame>,
+    ) -> Result<Bytes, FrameEncodeError> {
+        let mut buf = None;
+        let result = self.append_batch(frames, &mut buf);
+        let batch_data = self.finish_chunk(buf);
+        result.map(|()| batch_data)
+    }
+
+    /**
+     * Appends frames to a chunk that's being built up (see encode_batch()).
+     * The chunk's buffer is acquired from the BufferPool when the first frame
+     * is appended, sized for that frame, and grows from there. If any frame
+     * fails to encode then the chunk is left as it was.
+     */
+    pub(in crate) fn append_batch(
+        &mut self,
+        frames: Vec<frame::Frame>,
+        chunk: &mut Option<BytesMut>,
+    ) -> Result<(), FrameEncodeError> {
+        let chunk_len = chunk.as_ref().map_or(0, |buf| buf.len());
+        for frame in frames {
+            let frame_type = match encode_frame_body(frame, self.version, &mut self.body) {
+                Ok(frame_type) => frame_type,
+                Err(e) => {
+                    if let Some(buf) = chunk.as_mut() {
+                        buf.truncate(chunk_len);
+                    }
+                    return Err(e);
+                },
+            };
+            let frame_len = encoded_frame_len(self.body.len(), self.version);
+            let buf = chunk.get_or_insert_with(|| self.buffer_pool.acquire(frame_len));
+            buf.reserve(frame_len);
+            write_frame(frame_type, &self.body, self.version, buf);
+        }
+        Ok(())
+    }
+
+    /**
+     * Splits the frames appended to chunk off of its buffer, and hands the
+     * buffer back to the BufferPool.
+     */
+    pub(in crate) fn finish_chunk(&mut self, chunk: Option<BytesMut>) -> Bytes {
+        match chunk {
+            Some(mut buf) => {
+                let chunk_data = buf.split().freeze();
+                self.buffer_pool.release(buf);
+                chunk_data
+            },
+            None => Bytes::new(),
+        }
+    }
+
+    /**
+     * Like encode_batch(), but returns each frame's encoding separately (each
+     * sliced out of the one chunk). If any frame fails to encode then none of
+     * them are returned.
+     */
+    pub(in crate) fn encode_each(
+        &mut self,
+        frames: Vec<frame::Frame>,
+    ) -> Result<Vec<Bytes>, FrameEncodeError> {
+        let mut buf = None;
+        let mut frame_ends = Vec::with_capacity(frames.len());
+        for frame in frames {
+            if let Err(e) = self.append_batch(vec![frame], &mut buf) {
+                self.finish_chunk(buf);
+                return Err(e);
+            }
+            frame_ends.push(buf.as_ref().map_or(0, |buf| buf.len()));
+        }
+        let batch_data = self.finish_chunk(buf);
+        let mut frame_start = 0;
+        Ok(frame_ends.into_iter().map(|frame_end| {
+            let frame_data = batch_data.slice(frame_start..frame_end);
+            frame_start = frame_end;
+            frame_data
+        }).collect())
+    }
+
+    /**
+     * Appends frames that have already been encoded (see encode_each()) to a
+     * chunk that's being built up (see append_batch()).
+     */
+    pub(in crate) fn append_encoded(&mut self, data: &[u8], chunk: &mut Option<BytesMut>) {
+        let buf = chunk.get_or_insert_with(|| self.buffer_pool.acquire(data.len()));
+        buf.extend_from_slice(data);
+    }
+
+    /**
+     * Appends the encoded frame to out. If encoding fails, out is left 
+     * unchanged.
+     */
+    pub fn encode_into(
+        &mut self, 
+        frame: frame::Frame, 
+        out: &mut BytesMut,
+    ) -> Result<(), FrameEncodeError> {
+        let frame_type = encode_frame_body(frame, self.version, &mut self.body)?;
+        out.reserve(encoded_frame_len(self.body.len(), self.version));
+        write_frame(frame_type, &self.body, self.version, out);
+        Ok(())
+    }
+}
+
+/**
+ * Serializes multiple frames into a single contiguous buffer so that they can
+ * be written to the transport with one send_data() call rather than several 
+ * small writes.
+ */
+#[derive(Debug)]
+pub struct FrameBatch {
+    bytes: Vec<u8>,
+    num_frames: usize,
+    version: frame::FramingVersion,
+}
+impl FrameBatch {
+    pub fn new(version: frame::FramingVersion) -> Self {
+        FrameBatch {
+            bytes: vec![],
+            num_frames: 0,
+            version,
+        }
+    }
+
+    /**
+     * Encodes frame onto the end of the batch. If encoding fails, the batch is
+     * left unchanged.
+     */
+    pub fn push(&mut self, frame: frame::Frame) -> Result<(), FrameEncodeError> {
+        let mut frame_bytes = encode_frame_with_version(frame, self.version)?;
+        self.bytes.append(&mut frame_bytes);
+        self.num_frames += 1;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_frames == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_frames
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
 }
 
 pub fn abort_frame(
-    tube_id: u16,
+    tube_id: u32,
     reason: frame::AbortReason,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let reason_u8: u8 = reason.into();
-    Ok(vec![
-       frame::ABORT_FRAMETYPE,
-       0, 3,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-       reason_u8,
-    ])
+    encode_frame(frame::Frame::Abort { tube_id, reason })
 }
 
 pub fn abort_ack_frame(
-    tube_id: u16,
+    tube_id: u32,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::AbortAck { tube_id })
+}
+
+pub fn new_tube_ack_frame(
+    tube_id: u32,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
-       frame::ABORTACK_FRAMETYPE,
-       0, 3,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-    ])
+    encode_frame(frame::Frame::NewTubeAck { tube_id })
 }
 
 pub fn client_has_finished_sending_frame(
-    tube_id: u16,
+    tube_id: u32,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
-        frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, 
-        0, 2,
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ])
+    encode_frame(frame::Frame::ClientHasFinishedSending { tube_id })
 }
 
-pub fn drain_frame() -> Result<Vec<u8>, FrameEncodeError> {
-    Ok(vec![
-        frame::DRAIN_FRAMETYPE,
-        // bodylen=0
-        0, 0
-    ])
+pub fn drain_frame(
+    reason: frame::DrainReason,
+    deadline_unix_millis: Option<u64>,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::Drain { reason, deadline_unix_millis })
 }
 
-pub fn newtube_frame(
-    tube_id: u16, 
-    headers: HashMap<String, String>
+pub fn error_frame(
+    tube_id: Option<u32>,
+    code: frame::ErrorCode,
+    detail: String,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let mut headers_json_str_bytes = match serde_json::to_string(&headers) {
-        Ok(json_str) => json_str.into_bytes(),
-        Err(json_err) => return Err(FrameEncodeError::HeaderJsonEncodeError(json_err))
-    };
-    let body_len: u16 = 2 + (headers_json_str_bytes.len() as u16);
-    let body_len_bytes = body_len.to_be_bytes();
-    let mut bytes = vec![
-        frame::NEWTUBE_FRAMETYPE, 
-        body_len_bytes[0],
-        body_len_bytes[1],
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ];
-    bytes.append(&mut headers_json_str_bytes);
-    Ok(bytes)
+    encode_frame(frame::Frame::Error { tube_id, code, detail })
 }
 
-pub fn payload_frame(
-    tube_id: u16,
-    ack_id: Option<u16>,
-    mut data: Vec<u8>,
+pub fn extension_frame(
+    type_id: u8,
+    payload: Vec<u8>,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    // BodyLenBytes maxes out at 2^16, so ensure that the size of data fits into
-    // that limit
-    if data.len() > 2 + 2 + (2^16) {
-        return Err(FrameEncodeError::DataTooLarge(data.len()))
-    }
+    encode_frame(frame::Frame::ExtensionFrame { type_id, payload })
+}
+
+pub fn go_away_frame(
+    last_tube_id: u32,
+    reason: String,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::GoAway { last_tube_id, reason })
+}
+
+pub fn newtube_frame(
+    tube_id: u32, 
+    headers: HashMap<String, String>
+) -> Result<Vec<u8>, FrameEncodeError> {
+    let headers = headers.into_iter()
+        .map(|(name, value)| (name, value.into_bytes()))
+        .collect();
+    encode_frame(frame::Frame::NewTube { tube_id, headers })
+}
 
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let ack_bytes = match ack_id {
+fn payload_ack_bytes(ack_id: Option<u16>) -> Result<[u8; 2], FrameEncodeError> {
+    match ack_id {
         Some(ack_id) => {
             if ((0b1000_0000 << 8) & ack_id) > 0 {
-                return Err(FrameEncodeError::AckIdTooLarge(ack_id));
+                Err(FrameEncodeError::AckIdTooLarge(ack_id))
             } else {
-                ((0b1000_0000 << 8) | ack_id).to_be_bytes()
+                Ok(((0b1000_0000 << 8) | ack_id).to_be_bytes())
             }
         },
-        None => [0, 0]
-    };
-    let body_len = 2 + 2 + (data.len() as u16);
-    let body_len_bytes = body_len.to_be_bytes();
-    let mut bytes = vec![
-        frame::PAYLOAD_FRAMETYPE,
-        body_len_bytes[0],
-        body_len_bytes[1],
-        tubeid_bytes[0],
-        tubeid_bytes[1],
-        ack_bytes[0],
-        ack_bytes[1],
-    ];
-    bytes.append(&mut data);
-    Ok(bytes)
+        None => Ok([0, 0])
+    }
+}
+
+pub fn payload_frame(
+    tube_id: u32,
+    ack_id: Option<u16>,
+    data: Bytes,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::Payload { tube_id, ack_id, checksum: None, data })
+}
+
+pub fn payload_frame_with_checksum(
+    tube_id: u32,
+    ack_id: Option<u16>,
+    data: Bytes,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    // The actual checksum is computed when the frame is encoded
+    encode_frame(frame::Frame::Payload { tube_id, ack_id, checksum: Some(0), data })
+}
+
+pub fn partial_payload_frame(
+    tube_id: u32,
+    data: Bytes,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::PartialPayload { tube_id, checksum: None, data })
 }
 
 pub fn payload_ack_frame(
-    tube_id: u16,
+    tube_id: u32,
     ack_id: u16,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let ack_id_bytes = if ((0b1000_0000 << 8) & ack_id) > 0 {
-        return Err(FrameEncodeError::AckIdTooLarge(ack_id));
-    } else {
-        ack_id.to_be_bytes()
-    };
-    Ok(vec![
-       frame::PAYLOAD_ACK_FRAMETYPE,
-       0, 4,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-       ack_id_bytes[0],
-       ack_id_bytes[1],
-    ])
+    encode_frame(frame::Frame::PayloadAck { tube_id, ack_id })
+}
+
+pub fn sequenced_payload_frame(
+    tube_id: u32,
+    sequence_number: u64,
+    data: Bytes,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::SequencedPayload { tube_id, sequence_number, data })
+}
+
+pub fn selective_ack_frame(
+    tube_id: u32,
+    ranges: Vec<RangeInclusive<u64>>,
+) -> Result<Vec<u8>, FrameEncodeError> {
+    encode_frame(frame::Frame::SelectiveAck { tube_id, ranges })
 }
 
 pub fn server_has_finished_sending_frame(
-    tube_id: u16,
+    tube_id: u32,
 ) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
-        frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE, 
-        0, 2,
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ])
+    encode_frame(frame::Frame::ServerHasFinishedSending { tube_id })
 }
 
 #[cfg(test)]
@@ -167,7 +793,7 @@ mod encode_payload_tests {
             }
         }
 
-        match encode::payload_frame(42, Some(42), data) {
+        match encode::payload_frame(42, Some(42), data.into()) {
             Err(FrameEncodeError::DataTooLarge(size)) => assert_eq!(size, 66000),
             Err(err) => panic!(concat!(
                 "Received the wrong error when passing too much data to ",
@@ -181,9 +807,24 @@ mod encode_payload_tests {
         }
     }
 
+    #[test]
+    fn errors_on_oversized_tubeid() {
+        match encode::payload_frame(70000, None, vec![].into()) {
+            Err(FrameEncodeError::TubeIdTooLarge(tube_id)) => assert_eq!(tube_id, 70000),
+            Err(err) => panic!(
+                "Received the wrong error when passing an oversized tube_id: {:?}",
+                err
+            ),
+            Ok(_) => panic!(concat!(
+                "Did not receive an error when passing an oversized tube_id to ",
+                "encode::payload_frame!"
+            )),
+        }
+    }
+
     #[test]
     fn errors_on_oversized_ackid() {
-        match encode::payload_frame(42, Some(65000), vec![]) {
+        match encode::payload_frame(42, Some(65000), vec![].into()) {
             Err(FrameEncodeError::AckIdTooLarge(size)) => assert_eq!(size, 65000),
             Err(err) => panic!(
                 "Received the wrong error when passing an oversized ack_id: {:?}",
@@ -197,6 +838,165 @@ mod encode_payload_tests {
     }
 }
 
+#[cfg(test)]
+mod frame_batch_tests {
+    use super::*;
+    use super::super::decode::Decoder;
+
+    #[test]
+    fn batched_frames_decode_in_order() {
+        for version in [
+            frame::FramingVersion::V1,
+            frame::FramingVersion::V2,
+            frame::FramingVersion::V3,
+            frame::FramingVersion::V4,
+            frame::FramingVersion::V5,
+        ] {
+            let frames = vec![
+                frame::Frame::AbortAck { tube_id: 3 },
+                frame::Frame::PayloadAck { tube_id: 5, ack_id: 42 },
+                frame::Frame::Payload { 
+                    tube_id: 5, 
+                    ack_id: None, 
+                    checksum: None, 
+                    data: vec![1, 2, 3].into(),
+                },
+            ];
+
+            let mut batch = FrameBatch::new(version);
+            assert!(batch.is_empty());
+            for frame in frames.clone() {
+                batch.push(frame).unwrap();
+            }
+            assert_eq!(batch.len(), 3);
+
+            let mut decoder = Decoder::new_with_version(version);
+            let decoded_frames = decoder.decode(batch.into_bytes()).unwrap();
+            assert_eq!(Vec::from(decoded_frames), frames);
+        }
+    }
+
+    #[test]
+    fn failed_push_leaves_batch_unchanged() {
+        let mut batch = FrameBatch::new(frame::FramingVersion::V1);
+        batch.push(frame::Frame::Drain {
+            reason: frame::DrainReason::Unspecified,
+            deadline_unix_millis: None,
+        }).unwrap();
+        match batch.push(frame::Frame::AbortAck { tube_id: 70000 }) {
+            Err(FrameEncodeError::TubeIdTooLarge(70000)) => (),
+            unexpected => panic!("Unexpected result from push(): {:?}", unexpected),
+        }
+        assert_eq!(batch.len(), 1);
+        assert_eq!(
+            batch.into_bytes(),
+            drain_frame(frame::DrainReason::Unspecified, None).unwrap(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod encoder_tests {
+    use super::*;
+
+    fn test_frames() -> Vec<frame::Frame> {
+        vec![
+            frame::Frame::PayloadAck { tube_id: 5, ack_id: 42 },
+            frame::Frame::Payload { 
+                tube_id: 5, 
+                ack_id: Some(1), 
+                checksum: Some(checksum::crc32(&[1, 2, 3])), 
+                data: vec![1, 2, 3].into(),
+            },
+            frame::Frame::Abort { 
+                tube_id: 3, 
+                reason: frame::AbortReason::ApplicationDefined {
+                    code: 429,
+                    message: Some("quota exceeded".to_string()),
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn encodes_same_bytes_as_encode_frame_with_version() {
+        for version in [
+            frame::FramingVersion::V1,
+            frame::FramingVersion::V2,
+            frame::FramingVersion::V3,
+            frame::FramingVersion::V4,
+            frame::FramingVersion::V5,
+        ] {
+            let mut encoder = Encoder::new(version);
+            for frame in test_frames() {
+                let expected = encode_frame_with_version(frame.clone(), version).unwrap();
+                assert_eq!(encoder.encode(frame.clone()).unwrap(), expected);
+
+                let mut out = BytesMut::new();
+                encoder.encode_into(frame, &mut out).unwrap();
+                assert_eq!(out, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_into_appends_to_existing_buffer() {
+        let mut encoder = Encoder::new(frame::FramingVersion::V2);
+        let mut out = BytesMut::new();
+        for frame in test_frames() {
+            encoder.encode_into(frame, &mut out).unwrap();
+        }
+
+        let mut decoder = super::super::decode::Decoder::new_with_version(
+            frame::FramingVersion::V2,
+        );
+        let decoded_frames = decoder.decode_bytes(out.freeze()).unwrap();
+        assert_eq!(Vec::from(decoded_frames), test_frames());
+    }
+
+    #[test]
+    fn failed_batch_encodes_nothing() {
+        let mut encoder = Encoder::new(frame::FramingVersion::V1);
+        let mut frames = test_frames();
+        frames.push(frame::Frame::AbortAck { tube_id: 70000 });
+        match encoder.encode_batch(frames) {
+            Err(FrameEncodeError::TubeIdTooLarge(70000)) => (),
+            unexpected => panic!("Unexpected result from encode_batch(): {:?}", unexpected),
+        }
+
+        let drain_bytes = encoder.encode_batch(vec![frame::Frame::Drain {
+            reason: frame::DrainReason::Unspecified,
+            deadline_unix_millis: None,
+        }]).unwrap();
+        assert_eq!(drain_bytes, drain_frame(frame::DrainReason::Unspecified, None).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod encode_extension_tests {
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::FrameEncodeError;
+    use super::frame;
+
+    #[test]
+    fn errors_on_builtin_frame_type() {
+        match encode::extension_frame(frame::PAYLOAD_FRAMETYPE, vec![]) {
+            Err(FrameEncodeError::InvalidExtensionFrameType(type_id)) => 
+                assert_eq!(type_id, frame::PAYLOAD_FRAMETYPE),
+            Err(err) => panic!(
+                "Received the wrong error when passing a built-in frame type: {:?}",
+                err
+            ),
+            Ok(_) => panic!(concat!(
+                "Did not receive an error when passing a built-in frame type ",
+                "to encode::extension_frame!"
+            )),
+        }
+    }
+}
+
 #[cfg(test)]
 mod encode_payload_ack_tests {
     // Hacky aesthetic workaround for `use super as encode`
@@ -219,3 +1019,24 @@ mod encode_payload_ack_tests {
         }
     }
 }
+
+#[cfg(test)]
+mod encode_selective_ack_tests {
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::FrameEncodeError;
+
+    #[test]
+    fn errors_on_empty_range() {
+        let (start, end) = (9, 7);
+        match encode::selective_ack_frame(42, vec![0..=3, start..=end]) {
+            Err(FrameEncodeError::EmptySequenceRange { start, end }) => 
+                assert_eq!((start, end), (9, 7)),
+            unexpected => panic!(
+                "Unexpected result when passing an empty range: {:?}",
+                unexpected
+            ),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use super::frame;
+
+#[derive(Debug)]
+pub enum ExtensionFrameRegistrationError {
+    AlreadyRegistered(u8),
+    InvalidFrameType(u8),
+}
+
+pub fn is_extension_frame_type(type_id: u8) -> bool {
+    (frame::MIN_EXTENSION_FRAMETYPE..=frame::MAX_EXTENSION_FRAMETYPE).contains(&type_id)
+}
+
+/**
+ * A handler for ExtensionFrames of a given FrameType received from the peer.
+ * This allows applications and middleware to piggyback their own control
+ * data on a channel without forking the codec.
+ *
+ * Handlers are invoked inline on the channel's frame-processing task, so they
+ * should not block.
+ */
+pub trait ExtensionFrameHandler: Send + Sync {
+    fn handle(&self, type_id: u8, payload: Vec<u8>);
+}
+impl<F> ExtensionFrameHandler for F
+    where F: Fn(u8, Vec<u8>) + Send + Sync {
+    fn handle(&self, type_id: u8, payload: Vec<u8>) {
+        self(type_id, payload)
+    }
+}
+
+/**
+ * The set of ExtensionFrameHandlers registered for a channel, keyed by
+ * FrameType.
+ *
+ * Clones share the same underlying registry, so handlers registered after a
+ * Channel was created still apply to it.
+ */
+#[derive(Clone, Default)]
+pub struct ExtensionFrameHandlers {
+    handlers: Arc<RwLock<HashMap<u8, Arc<dyn ExtensionFrameHandler>>>>,
+}
+impl ExtensionFrameHandlers {
+    pub fn new() -> Self {
+        ExtensionFrameHandlers {
+            handlers: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn register(
+        &self,
+        type_id: u8,
+        handler: impl ExtensionFrameHandler + 'static,
+    ) -> Result<(), ExtensionFrameRegistrationError> {
+        if !is_extension_frame_type(type_id) {
+            return Err(ExtensionFrameRegistrationError::InvalidFrameType(type_id));
+        }
+
+        let mut handlers = self.handlers.write().unwrap();
+        match handlers.try_insert(type_id, Arc::new(handler)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ExtensionFrameRegistrationError::AlreadyRegistered(type_id)),
+        }
+    }
+
+    /**
+     * Runs the handler registered for type_id (if any). Returns false if no
+     * handler was registered.
+     */
+    pub fn dispatch(&self, type_id: u8, payload: Vec<u8>) -> bool {
+        // Clone the handler out so that it's free to register other handlers
+        let handler = match self.handlers.read().unwrap().get(&type_id) {
+            Some(handler) => handler.clone(),
+            None => return false,
+        };
+        handler.handle(type_id, payload);
+        true
+    }
+}
+impl std::fmt::Debug for ExtensionFrameHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut type_ids = self.handlers.read().unwrap().keys().copied().collect::<Vec<_>>();
+        type_ids.sort_unstable();
+        write!(f, "ExtensionFrameHandlers(type_ids={:?})", type_ids)
+    }
+}
+
+#[cfg(test)]
+mod extension_tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn dispatches_to_registered_handler() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let received2 = received.clone();
+
+        let handlers = ExtensionFrameHandlers::new();
+        handlers.register(0x40, move |type_id, payload| {
+            received2.lock().unwrap().push((type_id, payload));
+        }).unwrap();
+
+        assert!(handlers.dispatch(0x40, vec![1, 2, 3]));
+        assert!(!handlers.dispatch(0x41, vec![4, 5, 6]));
+        assert_eq!(*received.lock().unwrap(), vec![(0x40, vec![1, 2, 3])]);
+    }
+
+    #[test]
+    fn errors_on_duplicate_registration() {
+        let handlers = ExtensionFrameHandlers::new();
+        handlers.register(0x40, |_, _| ()).unwrap();
+        match handlers.register(0x40, |_, _| ()) {
+            Err(ExtensionFrameRegistrationError::AlreadyRegistered(0x40)) => (),
+            unexpected => panic!("Unexpected registration result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_builtin_frame_type() {
+        let handlers = ExtensionFrameHandlers::new();
+        match handlers.register(frame::PAYLOAD_FRAMETYPE, |_, _| ()) {
+            Err(ExtensionFrameRegistrationError::InvalidFrameType(type_id)) =>
+                assert_eq!(type_id, frame::PAYLOAD_FRAMETYPE),
+            unexpected => panic!("Unexpected registration result: {:?}", unexpected),
+        }
+    }
+}
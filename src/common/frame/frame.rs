@@ -1,4 +1,8 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::abort_reasons;
 
 pub(in super) const CLIENT_HAS_FINISHED_SENDING_FRAMETYPE: u8 = 0x0;
 pub(in super) const DRAIN_FRAMETYPE: u8 = 0x1;
@@ -8,6 +12,24 @@ pub(in super) const PAYLOAD_ACK_FRAMETYPE: u8 = 0x4;
 pub(in super) const SERVER_HAS_FINISHED_SENDING_FRAMETYPE: u8 = 0x5;
 pub(in super) const ABORT_FRAMETYPE: u8 = 0x6;
 pub(in super) const ABORTACK_FRAMETYPE: u8 = 0x7;
+pub(in super) const PAYLOAD_WITH_CHECKSUM_FRAMETYPE: u8 = 0x8;
+pub(in super) const ERROR_FRAMETYPE: u8 = 0x9;
+pub(in super) const SEQUENCED_PAYLOAD_FRAMETYPE: u8 = 0xA;
+pub(in super) const SELECTIVE_ACK_FRAMETYPE: u8 = 0xB;
+pub(in super) const GOAWAY_FRAMETYPE: u8 = 0xC;
+pub(in super) const NEWTUBE_ACK_FRAMETYPE: u8 = 0xD;
+pub(in super) const HEARTBEAT_FRAMETYPE: u8 = 0xE;
+pub(in super) const HEARTBEAT_ACK_FRAMETYPE: u8 = 0xF;
+pub(in super) const PARTIAL_PAYLOAD_FRAMETYPE: u8 = 0x10;
+pub(in super) const PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE: u8 = 0x11;
+
+// FrameTypes in this range are reserved for vendor/experimental extensions 
+// and are never assigned to built-in frames.
+pub const MIN_EXTENSION_FRAMETYPE: u8 = 0x40;
+pub const MAX_EXTENSION_FRAMETYPE: u8 = 0x7F;
+
+// AckIds are always 15 bits on the wire
+pub const MAX_ACK_ID: u16 = 0b0111_1111_1111_1111;
 
 /**
  * Each encoded Tube frame specifies its own structure, but all frames begin 
@@ -23,12 +45,187 @@ pub(in super) const ABORTACK_FRAMETYPE: u8 = 0x7;
  *
  * FrameBodyByteLength only specifies the size of the frame's body, it does not 
  * account for the 3 bytes used in the frame's header structure.
+ *
+ * When both peers support it, a channel may negotiate the V2 framing mode 
+ * (see FramingVersion). V2 frames encode FrameBodyByteLength as an unsigned 
+ * LEB128 varint:
+ *
+ *   +-----------------+-------------------------------+
+ *   |  FrameType(u8)  |  FrameBodyByteLength(varint)  |
+ *   +-----------------+-------------------------------+
+ *
+ * ...and also encode all TubeId and AckId fields within frame bodies as 
+ * varints. V1 frames can only address TubeIds that fit in 16 bits, whereas V2 
+ * frames can address the full 32-bit TubeId space. In V2 Payload frames, the AckRequested/AckId fields are replaced by
+ * a single AckField(varint) that is 0 when no ack is requested and 
+ * (AckId + 1) otherwise. All other fields are encoded the same as in V1.
+ *
+ * V3 frames are identical to V2 frames except that NewTube headers are 
+ * encoded as a binary header block (rather than JSON) so that header values 
+ * can be arbitrary bytes and common header fields can be abbreviated.
+ *
+ * V4 frames are identical to V3 frames. On V4 channels the server answers 
+ * each NewTube with a NewTubeAck once it has registered the Tube, so the 
+ * client knows the Tube was established (or, if an Error or Abort arrives 
+ * for it instead, that it was rejected).
+ *
+ * V5 frames are identical to V4 frames. On V5 channels both peers answer 
+ * each Heartbeat with a HeartbeatAck, so either side can tell that the 
+ * channel is still alive.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FramingVersion {
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+}
+impl FramingVersion {
+    pub const LATEST: FramingVersion = FramingVersion::V5;
+
+    /**
+     * Picks the framing version to use for a channel given the (optional) 
+     * value of the FRAMING_VERSION_HEADER specified by the peer. Peers that 
+     * don't specify the header only support V1.
+     */
+    pub fn negotiate(peer_header_value: Option<&str>) -> Self {
+        match peer_header_value.map(|value| value.trim().parse::<u8>()) {
+            Some(Ok(version)) if version >= 5 => FramingVersion::V5,
+            Some(Ok(4)) => FramingVersion::V4,
+            Some(Ok(3)) => FramingVersion::V3,
+            Some(Ok(2)) => FramingVersion::V2,
+            _ => FramingVersion::V1,
+        }
+    }
+
+    pub fn max_tube_id(&self) -> u32 {
+        match self {
+            FramingVersion::V1 => u16::MAX as u32,
+            FramingVersion::V2 | 
+                FramingVersion::V3 | 
+                FramingVersion::V4 | 
+                FramingVersion::V5 => u32::MAX,
+        }
+    }
+
+    /**
+     * Whether the server answers each NewTube with a NewTubeAck.
+     */
+    pub fn acks_new_tubes(&self) -> bool {
+        match self {
+            FramingVersion::V1 | FramingVersion::V2 | FramingVersion::V3 => false,
+            FramingVersion::V4 | FramingVersion::V5 => true,
+        }
+    }
+
+    /**
+     * Whether peers answer each Heartbeat with a HeartbeatAck.
+     */
+    pub fn acks_heartbeats(&self) -> bool {
+        match self {
+            FramingVersion::V1 | 
+                FramingVersion::V2 | 
+                FramingVersion::V3 | 
+                FramingVersion::V4 => false,
+            FramingVersion::V5 => true,
+        }
+    }
+
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            FramingVersion::V1 => "1",
+            FramingVersion::V2 => "2",
+            FramingVersion::V3 => "3",
+            FramingVersion::V4 => "4",
+            FramingVersion::V5 => "5",
+        }
+    }
+}
+
+/**
+ * The HTTP header used to negotiate the FramingVersion when a channel is 
+ * established. The client specifies the latest version it supports on the 
+ * request and the server responds with the version the channel will use.
  */
+pub const FRAMING_VERSION_HEADER: &str = "tubez-framing-version";
+
+/**
+ * The HTTP header used to negotiate the largest payload either peer puts in a
+ * single Payload (or SequencedPayload) frame. The client specifies the
+ * largest it's willing to receive on the request and the server responds
+ * with the size the channel will use, which is no larger than what either
+ * peer is willing to receive. Tubes split larger payloads into several frames
+ * when they're sent (see Frame::PartialPayload).
+ */
+pub const MAX_PAYLOAD_FRAME_SIZE_HEADER: &str = "tubez-max-payload-frame-size";
+
+/**
+ * The largest payload frame size a channel can negotiate, which leaves room
+ * for a Payload frame's other fields within the largest frame body an Encoder
+ * produces. Channels negotiate it unless either peer asks for less.
+ */
+pub const MAX_PAYLOAD_FRAME_SIZE: usize = 60 * 1024;
+
+/**
+ * Picks the max payload frame size for a channel given the one configured
+ * locally and the (optional) value of the MAX_PAYLOAD_FRAME_SIZE_HEADER
+ * specified by the peer. Peers that don't specify the header (or specify an
+ * unusable one) are taken to accept MAX_PAYLOAD_FRAME_SIZE.
+ */
+pub fn negotiate_max_payload_frame_size(local: usize, peer_header_value: Option<&str>) -> usize {
+    let peer = match peer_header_value.map(|value| value.trim().parse::<usize>()) {
+        Some(Ok(peer)) if peer > 0 => peer,
+        _ => MAX_PAYLOAD_FRAME_SIZE,
+    };
+    local.min(peer).clamp(1, MAX_PAYLOAD_FRAME_SIZE)
+}
+
 #[derive(Clone,Debug,PartialEq)]
 pub enum AbortReason {
     ApplicationAbort,
     ApplicationError,
+    /**
+     * An abort initiated by the application that carries an app-defined code 
+     * and an optional message (e.g. "quota exceeded", "auth expired") so that
+     * the peer can tell why the Tube was killed.
+     */
+    ApplicationDefined {
+        code: u32,
+        message: Option<String>,
+    },
     TransportErrorWhileSynchronizingTubeState,
+    /**
+     * The Tube was aborted because it didn't receive a payload within its 
+     * idle timeout (see tube::IdleTimeout).
+     */
+    IdleTimeout,
+    /**
+     * The Tube was aborted because its consumer fell behind and its event 
+     * queue overflowed (see tube::EventQueueOverflowPolicy::AbortTube).
+     */
+    EventQueueOverflow,
+    /**
+     * The Tube was aborted because the peer exceeded the channel's rate 
+     * limits (see frame::RateLimitAction::Abort).
+     */
+    RateLimited,
+    /**
+     * The Tube was aborted because its consumer stopped taking events off of
+     * it (see tube::SlowConsumerLimit).
+     */
+    SlowConsumer,
+    /**
+     * The Tube was aborted because the peer didn't ack a payload sent on it 
+     * within its ack timeout (see tube::AckTimeoutAction::Abort).
+     */
+    AckTimeout,
+    /**
+     * The Tube was aborted because it received events in an order that 
+     * doesn't make sense, e.g. a Payload after the peer had finished sending
+     * (see tube::TubeEvent_StreamError::InvalidTubeEventTransition).
+     */
+    InvalidTubeEventTransition,
     Unknown,
 }
 impl From<u8> for AbortReason {
@@ -37,21 +234,188 @@ impl From<u8> for AbortReason {
             0x0 => AbortReason::ApplicationAbort,
             0x1 => AbortReason::ApplicationError,
             0x2 => AbortReason::TransportErrorWhileSynchronizingTubeState,
+            // The code and message are decoded from the rest of the frame
+            0x3 => AbortReason::ApplicationDefined { code: 0, message: None },
+            0x4 => AbortReason::IdleTimeout,
+            0x5 => AbortReason::EventQueueOverflow,
+            0x6 => AbortReason::RateLimited,
+            0x7 => AbortReason::SlowConsumer,
+            0x8 => AbortReason::AckTimeout,
+            0x9 => AbortReason::InvalidTubeEventTransition,
             _   => AbortReason::Unknown,
         }
     }
 }
+impl AbortReason {
+    /**
+     * The name registered (via frame::register_abort_reason()) for this 
+     * reason's application-defined code, if any.
+     */
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            AbortReason::ApplicationDefined { code, .. } => 
+                abort_reasons::abort_reason_name(*code),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbortReason::ApplicationAbort => 
+                write!(f, "application abort"),
+            AbortReason::ApplicationError => 
+                write!(f, "application error"),
+            AbortReason::ApplicationDefined { code, message } => {
+                match self.name() {
+                    Some(name) => write!(f, "{} ({})", name, code)?,
+                    None => write!(f, "application-defined abort ({})", code)?,
+                };
+                match message {
+                    Some(message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            },
+            AbortReason::TransportErrorWhileSynchronizingTubeState => 
+                write!(f, "transport error while synchronizing tube state"),
+            AbortReason::IdleTimeout => 
+                write!(f, "idle timeout"),
+            AbortReason::EventQueueOverflow => 
+                write!(f, "event queue overflow"),
+            AbortReason::RateLimited => 
+                write!(f, "rate limited"),
+            AbortReason::SlowConsumer => 
+                write!(f, "slow consumer"),
+            AbortReason::AckTimeout => 
+                write!(f, "ack timeout"),
+            AbortReason::InvalidTubeEventTransition => 
+                write!(f, "invalid tube event transition"),
+            AbortReason::Unknown => 
+                write!(f, "unknown abort reason"),
+        }
+    }
+}
 impl Into<u8> for AbortReason {
     fn into(self) -> u8 {
         match self {
             AbortReason::ApplicationAbort                          => 0x00,
             AbortReason::ApplicationError                          => 0x01,
             AbortReason::TransportErrorWhileSynchronizingTubeState => 0x02,
+            AbortReason::ApplicationDefined { .. }                 => 0x03,
+            AbortReason::IdleTimeout                               => 0x04,
+            AbortReason::EventQueueOverflow                        => 0x05,
+            AbortReason::RateLimited                               => 0x06,
+            AbortReason::SlowConsumer                              => 0x07,
+            AbortReason::AckTimeout                                => 0x08,
+            AbortReason::InvalidTubeEventTransition                => 0x09,
             AbortReason::Unknown                                   => 0xFF,
         }
     }
 }
 
+/**
+ * Why a peer is being asked to drain, which tells it how urgently it should
+ * move its work elsewhere.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum DrainReason {
+    /**
+     * No reason was given (this is what peers that predate DrainReason send).
+     */
+    Unspecified,
+    /**
+     * The peer is shutting down (e.g. for a deploy or maintenance). In-flight
+     * Tubes should be allowed to finish, ideally before the Drain's deadline.
+     */
+    Shutdown,
+    /**
+     * The peer wants Tubes moved to another peer right away (e.g. to 
+     * rebalance load). New Tubes should be made elsewhere and existing Tubes 
+     * should be migrated rather than finished.
+     */
+    Migrate,
+    /**
+     * The peer is overloaded and wants load shed from it.
+     */
+    Overloaded,
+    Unknown(u8),
+}
+impl From<u8> for DrainReason {
+    fn from(reason: u8) -> Self {
+        match reason {
+            0x0 => DrainReason::Unspecified,
+            0x1 => DrainReason::Shutdown,
+            0x2 => DrainReason::Migrate,
+            0x3 => DrainReason::Overloaded,
+            _   => DrainReason::Unknown(reason),
+        }
+    }
+}
+impl From<DrainReason> for u8 {
+    fn from(reason: DrainReason) -> Self {
+        match reason {
+            DrainReason::Unspecified     => 0x0,
+            DrainReason::Shutdown        => 0x1,
+            DrainReason::Migrate         => 0x2,
+            DrainReason::Overloaded      => 0x3,
+            DrainReason::Unknown(reason) => reason,
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum ErrorCode {
+    BadHeader,
+    /**
+     * The channel already has as many unfinished Tubes as the server allows 
+     * (see server::ServerBuilder::with_max_tubes_per_channel()), so the Tube
+     * was refused rather than opened.
+     */
+    OverLimit,
+    UnsupportedFeature,
+    ProtocolViolation,
+    /**
+     * The channel is being drained (see server::Channel::drain()), so it
+     * accepts no new Tubes.
+     */
+    Draining,
+    /**
+     * A payload arrived with a checksum that didn't match its data (see 
+     * tube::PAYLOAD_CHECKSUM_HEADER), so it was dropped rather than delivered
+     * or acked. The detail starts with the payload's AckId followed by a 
+     * colon (e.g. "3: ..."), so that the sender can fail the send that's 
+     * waiting on the ack rather than leaving it to time out.
+     */
+    PayloadChecksumMismatch,
+    Unknown(u16),
+}
+impl From<u16> for ErrorCode {
+    fn from(code: u16) -> Self {
+        match code {
+            0x0 => ErrorCode::BadHeader,
+            0x1 => ErrorCode::OverLimit,
+            0x2 => ErrorCode::UnsupportedFeature,
+            0x3 => ErrorCode::ProtocolViolation,
+            0x4 => ErrorCode::Draining,
+            0x5 => ErrorCode::PayloadChecksumMismatch,
+            _   => ErrorCode::Unknown(code),
+        }
+    }
+}
+impl From<ErrorCode> for u16 {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::BadHeader               => 0x0,
+            ErrorCode::OverLimit               => 0x1,
+            ErrorCode::UnsupportedFeature      => 0x2,
+            ErrorCode::ProtocolViolation       => 0x3,
+            ErrorCode::Draining                => 0x4,
+            ErrorCode::PayloadChecksumMismatch => 0x5,
+            ErrorCode::Unknown(code)           => code,
+        }
+    }
+}
+
 #[derive(Clone,Debug,PartialEq)]
 pub enum Frame {
     /**
@@ -63,7 +427,7 @@ pub enum Frame {
      *   +---------------+
      */
     ClientHasFinishedSending {
-        tube_id: u16,
+        tube_id: u32,
     },
 
     /**
@@ -72,8 +436,42 @@ pub enum Frame {
      * application running on each peer to use this signal to coordinate the 
      * graceful shutdown of all Tubes hosted by the TubeTransport this 
      * frame arrived on.
+     *
+     *   +-------------------+-----------------------------+
+     *   |  DrainReason(u8)  |  [DeadlineUnixMillis(u64)]  |
+     *   +-------------------+-----------------------------+
+     *
+     * DeadlineUnixMillis, when present, is the time (in milliseconds since the
+     * Unix epoch) by which the sender expects Tubes to have finished. In V2
+     * frames it is a varint. A Drain with an Unspecified reason and no 
+     * deadline is encoded with an empty body, which is how peers that predate
+     * DrainReason encode every Drain.
+     */
+    Drain {
+        reason: DrainReason,
+        deadline_unix_millis: Option<u64>,
+    },
+
+    /**
+     * This frame is sent by either peer to announce that the whole channel is
+     * going away (as opposed to an Abort, which only ends a single Tube). 
+     * Like HTTP/2's GOAWAY, it tells the receiving peer which of the Tubes it
+     * opened were seen by the sender: Tubes with an id up to and including
+     * LastTubeId will still be processed, while those above it were not (and
+     * never will be), so they can safely be retried on another channel. The 
+     * receiving peer should not open any more Tubes on the channel.
+     *
+     *   +-------------------+-----------------+
+     *   |  LastTubeId(u16)  |  Utf8Reason(*)  |
+     *   +-------------------+-----------------+
+     *
+     * A LastTubeId of 0 means that none of the receiving peer's Tubes were 
+     * processed. In V2 frames LastTubeId is a varint.
      */
-    Drain,
+    GoAway {
+        last_tube_id: u32,
+        reason: String,
+    },
 
     /**
      * This frame is sent by either peer to indicate the creation of a new 
@@ -83,23 +481,87 @@ pub enum Frame {
      *   +---------------+-----------------------------+
      *   |  TubeId(u16)  |  Utf8EncodedJSONHeaders(*)  |
      *   +---------------+-----------------------------+
+     *
+     * JSON headers can only carry UTF-8 header values. In V3 frames the 
+     * headers are instead encoded as a binary header block (see 
+     * header_block::write_header_block), which can carry any bytes.
      */
     NewTube {
-        tube_id: u16,
-        headers: HashMap<String, String>,
+        tube_id: u32,
+        headers: HashMap<String, Vec<u8>>,
     },
 
+    /**
+     * This frame is sent by the server on V4 channels once it has registered
+     * a Tube the client made, before any other frame for that Tube. A client
+     * that gets an Error or Abort for the Tube instead knows that the server
+     * rejected it.
+     *
+     *   +---------------+
+     *   |  TubeId(u16)  |
+     *   +---------------+
+     */
+    NewTubeAck {
+        tube_id: u32,
+    },
+
+    /**
+     * This frame may be sent by either peer on V5 channels to check that the
+     * channel is still alive. The receiving peer answers with a HeartbeatAck.
+     * Its body is empty.
+     */
+    Heartbeat,
+
+    /**
+     * This frame is sent in answer to a Heartbeat. Its body is empty.
+     */
+    HeartbeatAck,
+
     /**
      * This frame is sent by either peer to transmit data.
      *
      *   +---------------+-------------------+-------------+-----------+
      *   |  TubeId(u16)  |  AckRequested(1)  |  AckId(15)  |  Data(*)  |
      *   +---------------+-------------------+-------------+-----------+
+     *
+     * When payload checksums are enabled for a Tube, payloads are instead sent
+     * using a distinct FrameType that carries a CRC-32 of the Data:
+     *
+     *   +---------------+-------------------+-------------+---------------+-----------+
+     *   |  TubeId(u16)  |  AckRequested(1)  |  AckId(15)  |  Crc32(u32)  |  Data(*)  |
+     *   +---------------+-------------------+-------------+---------------+-----------+
      */
     Payload {
-        tube_id: u16,
+        tube_id: u32,
         ack_id: Option<u16>,
-        data: Vec<u8>,
+        checksum: Option<u32>,
+        data: bytes::Bytes,
+    },
+
+    /**
+     * This frame is sent by either peer for every chunk but the last of a 
+     * payload that's larger than the channel's max payload frame size. The 
+     * last chunk is sent in the Payload (or SequencedPayload) frame that 
+     * would have carried the whole payload, and the receiver buffers the 
+     * chunks until that frame arrives so that the payload is delivered (and
+     * acked) whole.
+     *
+     *   +---------------+-----------+
+     *   |  TubeId(u16)  |  Data(*)  |
+     *   +---------------+-----------+
+     *
+     * Like Payload frames, PartialPayloads on a Tube with payload checksums
+     * enabled are sent using a distinct FrameType that carries a CRC-32 of 
+     * the Data:
+     *
+     *   +---------------+--------------+-----------+
+     *   |  TubeId(u16)  |  Crc32(u32)  |  Data(*)  |
+     *   +---------------+--------------+-----------+
+     */
+    PartialPayload {
+        tube_id: u32,
+        checksum: Option<u32>,
+        data: bytes::Bytes,
     },
 
     /**
@@ -113,10 +575,48 @@ pub enum Frame {
      *   +---------------+---------------+-------------+
      */
     PayloadAck {
-        tube_id: u16,
+        tube_id: u32,
         ack_id: u16,
     },
 
+    /**
+     * This frame is sent by either peer to transmit data that should be 
+     * delivered at least once. Each SequencedPayload on a Tube carries the 
+     * next sequence number (starting at 0), and the receiver answers with 
+     * SelectiveAck frames listing every sequence number it has received so 
+     * that the sender can detect gaps and retransmit them. Receivers drop 
+     * payloads whose sequence number they've already received.
+     *
+     *   +---------------+-----------------------+-----------+
+     *   |  TubeId(u16)  |  SequenceNumber(u64)  |  Data(*)  |
+     *   +---------------+-----------------------+-----------+
+     *
+     * In V2 frames SequenceNumber is a varint.
+     */
+    SequencedPayload {
+        tube_id: u32,
+        sequence_number: u64,
+        data: bytes::Bytes,
+    },
+
+    /**
+     * This frame is sent in response to SequencedPayload frames and lists 
+     * every range of sequence numbers the sender of the SelectiveAck has 
+     * received on the Tube so far, in ascending order.
+     *
+     *   +---------------+---------------------------------------+
+     *   |  TubeId(u16)  |  [RangeStart(u64)  |  RangeEnd(u64)]*  |
+     *   +---------------+---------------------------------------+
+     *
+     * Both ends of each range are inclusive. In V2 frames each range is 
+     * instead encoded as RangeStart(varint) followed by 
+     * RangeLength(varint), where RangeLength is RangeEnd - RangeStart.
+     */
+    SelectiveAck {
+        tube_id: u32,
+        ranges: Vec<RangeInclusive<u64>>,
+    },
+
     /**
      * This frame is sent by the server when it will send no further Payload 
      * frames for a given Tube.
@@ -126,7 +626,7 @@ pub enum Frame {
      *   +---------------+
      */
     ServerHasFinishedSending {
-        tube_id: u16,
+        tube_id: u32,
     },
 
     /**
@@ -137,9 +637,18 @@ pub enum Frame {
      *   +-----------------------------------+
      *   |  TubeId(u16)  |  AbortReason(u8)  |
      *   +-----------------------------------+
+     *
+     * When AbortReason is ApplicationDefined, it is followed by the 
+     * application's code and an (optionally empty) message:
+     *
+     *   +-------------+------------------+
+     *   |  Code(u32)  |  Utf8Message(*)  |
+     *   +-------------+------------------+
+     *
+     * In V2 frames Code is a varint. An empty message is decoded as None.
      */
     Abort {
-        tube_id: u16,
+        tube_id: u32,
         reason: AbortReason,
     },
 
@@ -161,6 +670,99 @@ pub enum Frame {
      *   +---------------+
      */
     AbortAck {
-        tube_id: u16,
+        tube_id: u32,
     },
+
+    /**
+     * This frame is sent by either peer to report a recoverable protocol 
+     * error (a malformed header, a limit being exceeded, an unsupported 
+     * feature, etc) without tearing down the channel. Errors that pertain to 
+     * a specific Tube specify its TubeId; errors that pertain to the channel
+     * as a whole do not.
+     *
+     *   +-----------------+---------------+-----------------+-------------------+
+     *   |  HasTubeId(u8)  |  TubeId(u16)  |  ErrorCode(u16) |  Utf8Detail(*)   |
+     *   +-----------------+---------------+-----------------+-------------------+
+     *
+     * When HasTubeId is 0, TubeId is ignored. In V2 frames HasTubeId and 
+     * TubeId are replaced by a single TubeIdField(varint) that is 0 when no 
+     * TubeId is specified and (TubeId + 1) otherwise, and ErrorCode is a 
+     * varint.
+     */
+    Error {
+        tube_id: Option<u32>,
+        code: ErrorCode,
+        detail: String,
+    },
+
+    /**
+     * This frame carries application-defined data using one of the FrameTypes
+     * reserved for extensions (MIN_EXTENSION_FRAMETYPE through 
+     * MAX_EXTENSION_FRAMETYPE). The codec passes it through without 
+     * interpreting the payload; it is up to an ExtensionFrameHandler 
+     * registered for the type_id to make sense of it.
+     *
+     *   +--------------+
+     *   |  Payload(*)  |
+     *   +--------------+
+     */
+    ExtensionFrame {
+        type_id: u8,
+        payload: Vec<u8>,
+    },
+}
+impl Frame {
+    /**
+     * The FrameType this frame is sent with on the wire.
+     */
+    pub fn frame_type(&self) -> u8 {
+        match self {
+            Frame::ClientHasFinishedSending { .. } => CLIENT_HAS_FINISHED_SENDING_FRAMETYPE,
+            Frame::Drain { .. } => DRAIN_FRAMETYPE,
+            Frame::GoAway { .. } => GOAWAY_FRAMETYPE,
+            Frame::NewTube { .. } => NEWTUBE_FRAMETYPE,
+            Frame::NewTubeAck { .. } => NEWTUBE_ACK_FRAMETYPE,
+            Frame::Heartbeat => HEARTBEAT_FRAMETYPE,
+            Frame::HeartbeatAck => HEARTBEAT_ACK_FRAMETYPE,
+            Frame::Payload { checksum: Some(_), .. } => PAYLOAD_WITH_CHECKSUM_FRAMETYPE,
+            Frame::Payload { checksum: None, .. } => PAYLOAD_FRAMETYPE,
+            Frame::PartialPayload { checksum: Some(_), .. } => 
+                PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE,
+            Frame::PartialPayload { checksum: None, .. } => PARTIAL_PAYLOAD_FRAMETYPE,
+            Frame::PayloadAck { .. } => PAYLOAD_ACK_FRAMETYPE,
+            Frame::SequencedPayload { .. } => SEQUENCED_PAYLOAD_FRAMETYPE,
+            Frame::SelectiveAck { .. } => SELECTIVE_ACK_FRAMETYPE,
+            Frame::ServerHasFinishedSending { .. } => SERVER_HAS_FINISHED_SENDING_FRAMETYPE,
+            Frame::Abort { .. } => ABORT_FRAMETYPE,
+            Frame::AbortAck { .. } => ABORTACK_FRAMETYPE,
+            Frame::Error { .. } => ERROR_FRAMETYPE,
+            Frame::ExtensionFrame { type_id, .. } => *type_id,
+        }
+    }
+
+    /**
+     * The Tube this frame is sent for, or None for frames about the channel
+     * as a whole.
+     */
+    pub fn tube_id(&self) -> Option<u32> {
+        match self {
+            Frame::ClientHasFinishedSending { tube_id } |
+                Frame::NewTube { tube_id, .. } |
+                Frame::NewTubeAck { tube_id } |
+                Frame::Payload { tube_id, .. } |
+                Frame::PartialPayload { tube_id, .. } |
+                Frame::PayloadAck { tube_id, .. } |
+                Frame::SequencedPayload { tube_id, .. } |
+                Frame::SelectiveAck { tube_id, .. } |
+                Frame::ServerHasFinishedSending { tube_id } |
+                Frame::Abort { tube_id, .. } |
+                Frame::AbortAck { tube_id } => Some(*tube_id),
+            Frame::Error { tube_id, .. } => *tube_id,
+            Frame::Drain { .. } |
+                Frame::GoAway { .. } |
+                Frame::Heartbeat |
+                Frame::HeartbeatAck |
+                Frame::ExtensionFrame { .. } => None,
+        }
+    }
 }
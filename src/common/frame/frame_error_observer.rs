@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use super::encode;
+use super::frame::Frame;
+use super::frame::FramingVersion;
+use super::frame_handler::FrameHandlerError;
+
+/**
+ * The most bytes of a rejected frame that are handed to FrameErrorObservers.
+ * Larger frames (e.g. big payloads) are truncated to this length.
+ */
+pub const MAX_REJECTED_FRAME_BYTES: usize = 1024;
+
+/**
+ * The encoded bytes of a frame that the FrameHandler rejected, which are
+ * enough to reproduce the decode and dispatch of the frame locally (e.g. by
+ * feeding them to a Decoder with the same FramingVersion).
+ *
+ * The bytes are the frame as re-encoded with the channel's FramingVersion.
+ * They match what was received byte for byte, except that the headers of a
+ * NewTube may be in a different order.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedFrame {
+    pub framing_version: FramingVersion,
+    /**
+     * At most MAX_REJECTED_FRAME_BYTES of the encoded frame.
+     */
+    pub raw_frame: Vec<u8>,
+    /**
+     * The length of the whole encoded frame, before truncation.
+     */
+    pub raw_frame_len: usize,
+}
+impl RejectedFrame {
+    pub(in crate::common::frame) fn new(frame: Frame, framing_version: FramingVersion) -> Self {
+        let mut raw_frame = match encode::encode_frame_with_version(frame, framing_version) {
+            Ok(raw_frame) => raw_frame,
+            Err(e) => {
+                log::error!("Error re-encoding a rejected frame: {:?}", e);
+                vec![]
+            },
+        };
+        let raw_frame_len = raw_frame.len();
+        raw_frame.truncate(MAX_REJECTED_FRAME_BYTES);
+        RejectedFrame {
+            framing_version,
+            raw_frame,
+            raw_frame_len,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.raw_frame.len() < self.raw_frame_len
+    }
+}
+
+/**
+ * An observer is told about every received frame that the FrameHandler
+ * rejects (an untracked TubeId, a duplicate HasFinishedSending, etc), along
+ * with the raw frame, so that protocol violations seen in production can be
+ * reported in enough detail to be reproduced.
+ *
+ * Observers are run on the channel's frame-processing task, so they should
+ * not block.
+ */
+pub trait FrameErrorObserver: Send + Sync {
+    fn observe(&self, error: &FrameHandlerError, rejected_frame: &RejectedFrame);
+}
+impl<F> FrameErrorObserver for F
+    where F: Fn(&FrameHandlerError, &RejectedFrame) + Send + Sync {
+    fn observe(&self, error: &FrameHandlerError, rejected_frame: &RejectedFrame) {
+        self(error, rejected_frame)
+    }
+}
+
+/**
+ * The FrameErrorObservers registered on a channel. Clones share the same
+ * underlying observers.
+ */
+#[derive(Clone, Default)]
+pub struct FrameErrorObservers {
+    observers: Arc<RwLock<Vec<Arc<dyn FrameErrorObserver>>>>,
+}
+impl FrameErrorObservers {
+    pub fn new() -> Self {
+        FrameErrorObservers {
+            observers: Arc::new(RwLock::new(vec![])),
+        }
+    }
+
+    pub fn add(&self, observer: impl FrameErrorObserver + 'static) {
+        self.observers.write().unwrap().push(Arc::new(observer));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.read().unwrap().is_empty()
+    }
+
+    pub fn observe(&self, error: &FrameHandlerError, rejected_frame: &RejectedFrame) {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.observe(error, rejected_frame);
+        }
+    }
+}
+impl std::fmt::Debug for FrameErrorObservers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let num_observers = self.observers.read().unwrap().len();
+        write!(f, "FrameErrorObservers({} observers)", num_observers)
+    }
+}
+
+#[cfg(test)]
+mod frame_error_observer_tests {
+    use super::*;
+
+    #[test]
+    fn large_rejected_frames_are_truncated() {
+        let rejected_frame = RejectedFrame::new(Frame::Payload {
+            tube_id: 1,
+            ack_id: None,
+            checksum: None,
+            data: vec![42; MAX_REJECTED_FRAME_BYTES * 2].into(),
+        }, FramingVersion::V1);
+        assert!(rejected_frame.is_truncated());
+        assert_eq!(rejected_frame.raw_frame.len(), MAX_REJECTED_FRAME_BYTES);
+        // FrameType(1) + BodyLen(2) + TubeId(2) + AckId(2) + Data
+        assert_eq!(rejected_frame.raw_frame_len, 7 + MAX_REJECTED_FRAME_BYTES * 2);
+    }
+}
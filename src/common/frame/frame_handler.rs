@@ -1,310 +1,2546 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
+use futures::future::BoxFuture;
+
+use crate::common::ChannelContext;
 use crate::common::PeerType;
 use crate::common::tube;
 use crate::common::tube::TubeCompletionState;
+use crate::common::TubeIdAllocator;
 use crate::common::UniqueId;
-use super::encode;
+use super::checksum;
 use super::frame;
+use super::frame_error_observer::RejectedFrame;
+use super::frame_sender::FrameSender;
+use super::frame_sender::FrameSendError;
+use super::rate_limit::RateLimitCheck;
 
 #[derive(Debug)]
 pub enum FrameHandlerError {
-    AbortAckFrameEncodingError(encode::FrameEncodeError),
-    AbortAckTransmitError(hyper::Error),
-    DuplicateAbortFrame { tube_id: u16 },
-    DuplicateHasFinishedSendingFrame { tube_id: u16 },
+    AbortAckSendError(FrameSendError),
+    AbortSendError(FrameSendError),
+    /**
+     * The peer opened a Tube after being asked to drain the channel, so it
+     * was refused with a Draining Error frame.
+     */
+    ChannelDraining { tube_id: u32 },
+    ErrorSendError(FrameSendError),
+    DuplicateAbortFrame { tube_id: u32 },
+    DuplicateHasFinishedSendingFrame { tube_id: u32 },
+    HeartbeatAckSendError(FrameSendError),
     InappropriateHasFinishedSendingFrameFromPeer,
-    PayloadAckFrameEncodingError(encode::FrameEncodeError),
-    PayloadAckTransmitError(hyper::Error),
-    ReceivedHasFinishedSendingAfterRemoteAbort { tube_id: u16 },
+    /**
+     * The peer opened a Tube with an id from the half of the id space this
+     * side allocates from (see TubeIdAllocator), so it was refused with a
+     * ProtocolViolation Error frame.
+     */
+    InvalidPeerTubeId { tube_id: u32 },
+    NewTubeAckSendError(FrameSendError),
+    PayloadAckSendError(FrameSendError),
+    ReceivedHasFinishedSendingAfterRemoteAbort { tube_id: u32 },
     ServerInitiatedTubesNotImplemented,
-    TubeManagerInsertionError { tube_id: u16 },
+    TooManyLatePayloads {
+        tube_id: u32,
+        num_late_payloads: u64,
+    },
+    /**
+     * The peer opened a Tube while the channel already had as many unfinished
+     * peer Tubes as it allows, so it was refused with an OverLimit Error 
+     * frame.
+     */
+    TubeLimitExceeded {
+        tube_id: u32,
+        max_tubes: usize,
+    },
+    TubeManagerInsertionError { tube_id: u32 },
+    UnexpectedFrame(frame::Frame),
+    UnhandledExtensionFrame { type_id: u8 },
+    UnhandledFrameType(u8),
     UntrackedAckId {
-        tube_id: u16,
+        tube_id: u32,
         ack_id: u16,
     },
     UntrackedTubeId(frame::Frame),
 }
 
-// TODO: When server-initiated tubes are implemented, can we generalize 
-//       server_ctx into channel_ctx, pass in channel_ctx from both server and 
-//       client code, and then handle NewTube event-publishing entirely here? If
-//       so we could eliminate FrameHandlerResult whose sole purpose is to host 
-//       FrameHandlerResult::NewTube...
-pub enum FrameHandlerResult {
-    FullyHandled,
-    NewTube(tube::Tube),
+/**
+ * What to do with payloads that arrive for a Tube that has already been 
+ * closed or aborted (including Tubes that are no longer tracked because they
+ * finished). Late payloads are never delivered to the Tube or acked.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LatePayloadPolicy {
+    /**
+     * Drop late payloads without counting or logging them.
+     */
+    Drop,
+    /**
+     * Drop late payloads, counting each one on the channel and logging a 
+     * warning.
+     */
+    #[default]
+    CountAndLog,
+    /**
+     * Count and log late payloads, and once more than the given number have
+     * arrived on the channel, treat each further one as a protocol violation:
+     * the peer is sent a ProtocolViolation Error frame for the Tube and the 
+     * frame is rejected with FrameHandlerError::TooManyLatePayloads.
+     */
+    EscalateAfter(u64),
 }
 
-pub struct FrameHandler<'a> {
-    peer_type: PeerType,
-    tube_managers: &'a mut Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
+/**
+ * Handles every received frame of the FrameType(s) it is registered for (see
+ * FrameHandler::register_frame_type_handler()).
+ *
+ * Handlers are run on the channel's frame-processing task, one frame at a
+ * time, so they should not block.
+ */
+pub trait FrameTypeHandler: Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a ChannelContext,
+        frame: frame::Frame,
+        frame_sender: &'a FrameSender,
+    ) -> BoxFuture<'a, Result<(), FrameHandlerError>>;
 }
-impl<'a> FrameHandler<'a> {
-    pub fn new(
-        peer_type: PeerType,
-        tube_managers: &'a mut Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
-    ) -> Self {
-        FrameHandler {
-            peer_type,
-            tube_managers,
-        }
+impl<F> FrameTypeHandler for F
+    where F: for<'a> Fn(
+        &'a ChannelContext,
+        frame::Frame,
+        &'a FrameSender,
+    ) -> BoxFuture<'a, Result<(), FrameHandlerError>> + Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        ctx: &'a ChannelContext,
+        frame: frame::Frame,
+        frame_sender: &'a FrameSender,
+    ) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+        self(ctx, frame, frame_sender)
     }
+}
+
+/**
+ * Dispatches each frame received on a channel to the FrameTypeHandler
+ * registered for its FrameType.
+ */
+pub struct FrameHandler {
+    ctx: ChannelContext,
+    handlers: HashMap<u8, Arc<dyn FrameTypeHandler>>,
+}
+impl FrameHandler {
+    pub fn new(ctx: ChannelContext) -> Self {
+        let mut frame_handler = FrameHandler {
+            ctx,
+            handlers: HashMap::new(),
+        };
 
-    fn get_tube_mgr(&mut self, tube_id: &u16) -> Option<Arc<Mutex<tube::TubeManager>>> {
-        let tube_mgrs = self.tube_managers.lock().unwrap();
-        match tube_mgrs.get(tube_id) {
-            Some(tm) => Some(tm.clone()),
-            None => None,
+        frame_handler.register_frame_type_handler(
+            frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE,
+            handle_client_has_finished_sending,
+        );
+        frame_handler.register_frame_type_handler(frame::DRAIN_FRAMETYPE, handle_drain);
+        frame_handler.register_frame_type_handler(frame::GOAWAY_FRAMETYPE, handle_go_away);
+        frame_handler.register_frame_type_handler(frame::HEARTBEAT_FRAMETYPE, handle_heartbeat);
+        frame_handler.register_frame_type_handler(
+            frame::HEARTBEAT_ACK_FRAMETYPE,
+            handle_heartbeat_ack,
+        );
+        frame_handler.register_frame_type_handler(frame::NEWTUBE_FRAMETYPE, handle_newtube);
+        frame_handler.register_frame_type_handler(
+            frame::NEWTUBE_ACK_FRAMETYPE,
+            handle_newtube_ack,
+        );
+        frame_handler.register_frame_type_handler(frame::PAYLOAD_FRAMETYPE, handle_payload);
+        frame_handler.register_frame_type_handler(
+            frame::PAYLOAD_WITH_CHECKSUM_FRAMETYPE,
+            handle_payload,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::PARTIAL_PAYLOAD_FRAMETYPE,
+            handle_partial_payload,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE,
+            handle_partial_payload,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::PAYLOAD_ACK_FRAMETYPE,
+            handle_payload_ack,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::SEQUENCED_PAYLOAD_FRAMETYPE,
+            handle_sequenced_payload,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::SELECTIVE_ACK_FRAMETYPE,
+            handle_selective_ack,
+        );
+        frame_handler.register_frame_type_handler(
+            frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE,
+            handle_server_has_finished_sending,
+        );
+        frame_handler.register_frame_type_handler(frame::ABORT_FRAMETYPE, handle_abort);
+        frame_handler.register_frame_type_handler(frame::ABORTACK_FRAMETYPE, handle_abort_ack);
+        frame_handler.register_frame_type_handler(frame::ERROR_FRAMETYPE, handle_error);
+        for type_id in frame::MIN_EXTENSION_FRAMETYPE..=frame::MAX_EXTENSION_FRAMETYPE {
+            frame_handler.register_frame_type_handler(type_id, handle_extension_frame);
         }
+
+        frame_handler
+    }
+
+    /**
+     * Replaces the handler for a FrameType. Extension FrameTypes are handled
+     * by dispatching to the channel's ExtensionFrameHandlers by default.
+     */
+    pub fn register_frame_type_handler(
+        &mut self,
+        type_id: u8,
+        handler: impl FrameTypeHandler + 'static,
+    ) {
+        self.handlers.insert(type_id, Arc::new(handler));
     }
 
     pub async fn handle_frame(
-        &mut self, 
+        &mut self,
         frame: frame::Frame,
-        data_sender: &mut Arc<tokio::sync::Mutex<hyper::body::Sender>>,
-    ) -> Result<FrameHandlerResult, FrameHandlerError> {
-        match frame {
-            frame::Frame::ClientHasFinishedSending { tube_id } => {
-                if let PeerType::Client = self.peer_type {
-                    return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
-                }
+        frame_sender: &FrameSender,
+    ) -> Result<(), FrameHandlerError> {
+        // Frames are only copied when someone is observing rejected frames.
+        // Copying is cheap since payload data is reference counted.
+        let observed_frame = if self.ctx.frame_error_observers.is_empty() {
+            None
+        } else {
+            Some(frame.clone())
+        };
 
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
-                };
+        let frame_type = frame.frame_type();
+        let result = match self.handlers.get(&frame_type) {
+            Some(handler) => handler.clone().handle(&self.ctx, frame, frame_sender).await,
+            None => Err(FrameHandlerError::UnhandledFrameType(frame_type)),
+        };
+        if let (Err(e), Some(frame)) = (&result, observed_frame) {
+            let rejected_frame = RejectedFrame::new(frame, frame_sender.framing_version());
+            self.ctx.frame_error_observers.observe(e, &rejected_frame);
+        }
+        result
+    }
 
-                let should_remove_tube_mgr = {
-                    let mut tube_mgr = tube_mgr.lock().unwrap();
-                    let new_state = {
-                        use tube::TubeCompletionState::*;
-                        match tube_mgr.completion_state {
-                            Open => 
-                                ClientHasFinishedSending,
-                            ServerHasFinishedSending => 
-                                Closed,
-                            ClientHasFinishedSending | Closed => 
-                                return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
-                                    tube_id,
-                                }),
-                            AbortedFromRemote(_) => 
-                                return Err(FrameHandlerError::ReceivedHasFinishedSendingAfterRemoteAbort {
-                                    tube_id,
-                                }),
-                            AbortedFromLocal(_) =>
-                                return Ok(FrameHandlerResult::FullyHandled),
-                        }
-                    };
+    /**
+     * Sends the PayloadAcks deferred for Tubes with cumulative acks (see 
+     * tube::CUMULATIVE_ACKS_HEADER) and a SelectiveAck for each Tube that has
+     * received SequencedPayloads since the last flush. This should be called
+     * each time the frames from a chunk of data read from the transport have
+     * all been handled.
+     */
+    pub async fn flush_deferred_acks(
+        &mut self,
+        frame_sender: &FrameSender,
+    ) -> Result<(), FrameHandlerError> {
+        let mut ack_frames: Vec<frame::Frame> = self.ctx.take_cumulative_acks()
+            .into_iter()
+            .map(|(tube_id, ack_id)| frame::Frame::PayloadAck { tube_id, ack_id })
+            .collect();
+        for tube_id in self.ctx.take_selective_acks() {
+            if let Some(tube_mgr) = self.ctx.get_tube_mgr(&tube_id) {
+                let ranges = tube_mgr.lock().unwrap().received_sequences.ranges().to_vec();
+                ack_frames.push(frame::Frame::SelectiveAck { tube_id, ranges });
+            }
+        }
+        match frame_sender.send_batch(ack_frames).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(FrameHandlerError::PayloadAckSendError(e)),
+        }
+    }
 
-                    if tube_mgr.completion_state != new_state {
-                        tube_mgr.completion_state = new_state.clone();
-                        if tube_mgr.completion_state == tube::TubeCompletionState::ClientHasFinishedSending {
-                            tube_mgr.pending_events.push_back(tube::TubeEvent::ClientHasFinishedSending);
-                        }
-                        if let Some(waker) = tube_mgr.waker.take() {
-                          waker.wake();
-                        }
-                    }
+    /**
+     * Called when the Channel's incoming stream of frames has ended or failed.
+     * Every Tube that hasn't finished yet is told why via a
+     * TubeEvent::StreamError and is then considered aborted, since nothing
+     * more will ever arrive for it.
+     */
+    pub fn fail_all_tubes(&mut self, detail: String) {
+        self.finish_all_tubes(
+            tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::TransportError(detail)),
+            TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::TransportErrorWhileSynchronizingTubeState
+            ),
+        );
+    }
 
-                    tube::TubeCompletionState::Closed == new_state
-                };
+    /**
+     * Like fail_all_tubes(), but for a Channel that was given up on because 
+     * nothing arrived from the peer within its idle timeout. Every Tube that 
+     * hasn't finished yet gets a TubeEvent::IdleTimeout and is then 
+     * considered aborted (with AbortReason::IdleTimeout). No Abort frames are 
+     * sent since the peer isn't expected to read them.
+     */
+    pub fn time_out_all_tubes(&mut self) {
+        self.finish_all_tubes(
+            tube::TubeEvent::IdleTimeout,
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::IdleTimeout),
+        );
+    }
 
-                if should_remove_tube_mgr {
-                    self.tube_managers.lock().unwrap().remove(&tube_id);
+    /**
+     * Stops tracking every Tube, queueing last_event on (and moving into
+     * completion_state) each one that hasn't finished yet.
+     */
+    fn finish_all_tubes(
+        &mut self,
+        last_event: tube::TubeEvent,
+        completion_state: TubeCompletionState,
+    ) {
+        for (_, tube_mgr) in self.ctx.tube_managers.take_all() {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                // No AbortAck is coming for a Tube aborted from this side
+                for waker in tube_mgr.completion_wakers.drain(..) {
+                    waker.wake();
                 }
-            },
+                continue;
+            }
+            tube_mgr.pending_events.push_back(last_event.clone());
+            tube_mgr.set_completion_state(completion_state.clone());
+            if let Some(waker) = tube_mgr.waker.take() {
+                waker.wake();
+            }
+        }
+    }
 
-            frame::Frame::Drain => {
-                // TODO
-            },
+    /**
+     * Like fail_all_tubes(), but Tubes the peer hasn't acked yet (see 
+     * frame::FramingVersion::acks_new_tubes()) stay tracked so that they can 
+     * be made again on a new transport. Returns the frames that make them 
+     * (ordered by TubeId).
+     */
+    pub fn fail_established_tubes(&mut self, detail: String) -> Vec<frame::Frame> {
+        let mut pending_tube_mgrs = self.ctx.tube_managers.remove_where(|_, tube_mgr| {
+            let tube_mgr = tube_mgr.lock().unwrap();
+            tube_mgr.establishment_pending && !tube_mgr.completion_state.is_terminal()
+        });
+        pending_tube_mgrs.sort_by_key(|(tube_id, _)| *tube_id);
+
+        self.fail_all_tubes(detail);
+
+        let mut establishment_frames = vec![];
+        for (tube_id, tube_mgr) in pending_tube_mgrs {
+            establishment_frames.extend(
+                tube_mgr.lock().unwrap().establishment_frames.iter().cloned()
+            );
+            self.ctx.tube_managers.insert(tube_id, tube_mgr);
+        }
+        establishment_frames
+    }
+}
+
+fn handle_client_has_finished_sending<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let tube_id = match frame {
+            frame::Frame::ClientHasFinishedSending { tube_id } => tube_id,
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        if let PeerType::Client = ctx.peer_type {
+            return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
+        }
 
-            // TODO: Handle NewTube headers
-            frame::Frame::NewTube { tube_id, headers: _ } => {
-                if let PeerType::Client = self.peer_type {
-                    return Err(FrameHandlerError::ServerInitiatedTubesNotImplemented);
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+
+        let should_remove_tube_mgr = {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            let new_state = {
+                use tube::TubeCompletionState::*;
+                match tube_mgr.completion_state {
+                    Open =>
+                        ClientHasFinishedSending,
+                    ServerHasFinishedSending =>
+                        Closed,
+                    ClientHasFinishedSending | Closed =>
+                        return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
+                            tube_id,
+                        }),
+                    AbortedFromRemote(_) =>
+                        return Err(FrameHandlerError::ReceivedHasFinishedSendingAfterRemoteAbort {
+                            tube_id,
+                        }),
+                    AbortedFromLocal(_) =>
+                        return Ok(()),
                 }
+            };
 
-                let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
-                if let Err(_) = self.tube_managers.lock().unwrap().try_insert(tube_id, tube_mgr.clone()) {
-                    return Err(FrameHandlerError::TubeManagerInsertionError {
-                        tube_id,
-                    });
+            if tube_mgr.completion_state != new_state {
+                tube_mgr.set_completion_state(new_state.clone());
+                if tube_mgr.completion_state == tube::TubeCompletionState::ClientHasFinishedSending {
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::ClientHasFinishedSending);
+                }
+                if let Some(waker) = tube_mgr.waker.take() {
+                  waker.wake();
                 }
+            }
+
+            tube::TubeCompletionState::Closed == new_state
+        };
+
+        if should_remove_tube_mgr {
+            ctx.tube_managers.remove(tube_id);
+        }
+        Ok(())
+    })
+}
+
+fn handle_drain<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (reason, deadline_unix_millis) = match frame {
+            frame::Frame::Drain { reason, deadline_unix_millis } =>
+                (reason, deadline_unix_millis),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+        let deadline = deadline_unix_millis.map(|deadline_unix_millis| {
+            std::time::UNIX_EPOCH + std::time::Duration::from_millis(deadline_unix_millis)
+        });
+
+        for (_, tube_mgr) in ctx.tube_managers.to_vec() {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                continue;
+            }
+            tube_mgr.pending_events.push_back(tube::TubeEvent::ServerMustDrain {
+                reason,
+                deadline,
+            });
+            if let Some(waker) = tube_mgr.waker.take() {
+                waker.wake();
+            }
+        }
+        Ok(())
+    })
+}
 
-                log::trace!("Emitting tube...");
-                let tube_id = UniqueId::new(tube_id, None);
-                let tube = tube::Tube::new(
-                    self.peer_type,
+fn handle_go_away<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (last_tube_id, reason) = match frame {
+            frame::Frame::GoAway { last_tube_id, reason } => (last_tube_id, reason),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+        ctx.set_peer_going_away(reason.clone());
+
+        // Only the Tubes this side opened can have gone unprocessed by the 
+        // peer. Server-initiated tubes aren't supported yet, so a server has
+        // none.
+        if let PeerType::Server = ctx.peer_type {
+            return Ok(());
+        }
+
+        let unprocessed_tube_mgrs = ctx.tube_managers.remove_where(|tube_id, _| {
+            tube_id > last_tube_id
+        });
+        for (_, tube_mgr) in unprocessed_tube_mgrs {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                continue;
+            }
+            tube_mgr.pending_events.push_back(tube::TubeEvent::StreamError(
+                tube::TubeEvent_StreamError::PeerGoingAway(reason.clone())
+            ));
+            tube_mgr.set_completion_state(TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::ApplicationAbort
+            ));
+            if let Some(waker) = tube_mgr.waker.take() {
+                waker.wake();
+            }
+        }
+        Ok(())
+    })
+}
+
+// TODO: Handle remaining NewTube headers
+fn handle_newtube<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, headers) = match frame {
+            frame::Frame::NewTube { tube_id, headers } => (tube_id, headers),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        if !ctx.accepts_peer_tubes() {
+            let error_frame = frame::Frame::Error {
+                tube_id: Some(tube_id),
+                code: frame::ErrorCode::UnsupportedFeature,
+                detail: "Server-initiated tubes are not supported".to_string(),
+            };
+            if let Err(e) = frame_sender.send(error_frame).await {
+                return Err(FrameHandlerError::ErrorSendError(e));
+            }
+            return Err(FrameHandlerError::ServerInitiatedTubesNotImplemented);
+        }
+
+        let remote_peer_type = ctx.peer_type.remote();
+        if !TubeIdAllocator::is_allocated_by(remote_peer_type, tube_id) {
+            let error_frame = frame::Frame::Error {
+                tube_id: Some(tube_id),
+                code: frame::ErrorCode::ProtocolViolation,
+                detail: format!(
+                    "Tube id {} can't be used by a {:?} to open a Tube",
                     tube_id,
-                    data_sender.clone(),
-                    tube_mgr,
-                );
-
-                // TODO: When server-initiated tubes are implemented, can we 
-                //       generalize server_ctx into channel_ctx, pass in 
-                //       channel_ctx from both server and client code, and then
-                //       handle NewTube event-publishing entirely here? If so 
-                //       we could eliminate FrameHandlerResult whose sole 
-                //       purpose is to host FrameHandlerResult::NewTube...
-                return Ok(FrameHandlerResult::NewTube(tube))
-                /*
-                let mut server_ctx = server_ctx.lock().unwrap();
-                server_ctx.pending_events.push_back(ServerEvent::NewTube(tube));
-                if let Some(waker) = server_ctx.waker.take() {
-                    waker.wake();
+                    remote_peer_type,
+                ),
+            };
+            if let Err(e) = frame_sender.send(error_frame).await {
+                return Err(FrameHandlerError::ErrorSendError(e));
+            }
+            return Err(FrameHandlerError::InvalidPeerTubeId { tube_id });
+        }
+
+        // Hold off on processing anything else on the channel until the
+        // application has room for another tube.
+        futures::future::poll_fn(|cx| ctx.poll_peer_tube_capacity(cx)).await;
+
+        // Tubes that arrive after a GoAway was sent are dropped; the peer 
+        // fails them itself once it receives the GoAway.
+        if !ctx.accept_peer_tube_id(tube_id) {
+            log::trace!("Ignoring Tube(id={}) opened after sending a GoAway", tube_id);
+            return Ok(());
+        }
+
+        if ctx.drain_requested() {
+            let error_frame = frame::Frame::Error {
+                tube_id: Some(tube_id),
+                code: frame::ErrorCode::Draining,
+                detail: "The channel is draining".to_string(),
+            };
+            if let Err(e) = frame_sender.send(error_frame).await {
+                return Err(FrameHandlerError::ErrorSendError(e));
+            }
+            return Err(FrameHandlerError::ChannelDraining { tube_id });
+        }
+
+        if let Some(max_tubes) = ctx.max_peer_tubes() {
+            if ctx.num_unfinished_tubes() >= max_tubes {
+                let error_frame = frame::Frame::Error {
+                    tube_id: Some(tube_id),
+                    code: frame::ErrorCode::OverLimit,
+                    detail: format!("The channel is limited to {} concurrent tubes", max_tubes),
+                };
+                if let Err(e) = frame_sender.send(error_frame).await {
+                    return Err(FrameHandlerError::ErrorSendError(e));
                 }
-                */
-            },
+                return Err(FrameHandlerError::TubeLimitExceeded { tube_id, max_tubes });
+            }
+        }
 
-            frame::Frame::Payload { tube_id, ack_id, ref data } => {
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        let rate_limited = !within_rate_limit(|| ctx.check_new_tube_rate()).await;
+
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.payload_checksums = tube::payload_checksums_requested(&headers);
+        tube_mgr.receive_only = tube::receive_only_requested(&headers);
+        tube_mgr.cumulative_acks = tube::cumulative_acks_requested(&headers);
+        tube_mgr.event_queue_limit = ctx.event_queue_limit();
+        tube_mgr.ack_timeout_policy = ctx.ack_timeout_policy();
+        tube_mgr.executor = ctx.executor().clone();
+        tube_mgr.channel_tube_managers = Some(Arc::downgrade(&ctx.tube_managers));
+        if ctx.is_authenticated() {
+            tube_mgr.pending_events.push_back(tube::TubeEvent::AuthenticatedAndReady);
+        }
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+        if !ctx.tube_managers.try_insert(tube_id, tube_mgr.clone()) {
+            return Err(FrameHandlerError::TubeManagerInsertionError {
+                tube_id,
+            });
+        }
+        if let Some(observer) = ctx.tube_lifecycle_observer() {
+            let lifecycle = tube::TubeLifecycle::open(observer, tube_id, &headers);
+            tube_mgr.lock().unwrap().lifecycle = Some(lifecycle);
+        }
+
+        // The ack goes out before the Tube is emitted so that it precedes any
+        // frame the application sends on the Tube.
+        if frame_sender.framing_version().acks_new_tubes() {
+            log::trace!("Sending NewTubeAck(tube_id={})...", tube_id);
+            if let Err(e) = frame_sender.send(frame::Frame::NewTubeAck { tube_id }).await {
+                return Err(FrameHandlerError::NewTubeAckSendError(e));
+            }
+        }
+
+        log::trace!("Emitting tube...");
+        let tube = tube::Tube::new(
+            ctx.peer_type,
+            UniqueId::new(tube_id, None),
+            headers,
+            frame_sender.clone(),
+            tube_mgr,
+        );
+
+        if rate_limited {
+            log::warn!(
+                "Aborting Tube(id={}) because the peer exceeded the rate limit for new tubes",
+                tube_id,
+            );
+            let mut tube = tube;
+            match tube.abort_with_reason(frame::AbortReason::RateLimited).await {
+                Ok(()) => (),
+                Err(e) => log::error!("Error aborting tube: `{:?}`", e),
+            }
+            return Ok(());
+        }
+
+        if let Err(mut tube) = ctx.publish_new_tube(tube) {
+            log::error!(
+                "Received a new Tube(id={}) from the peer on a channel that \
+                 has been dropped!",
+                 tube.get_id(),
+            );
+            match tube.abort_with_reason(frame::AbortReason::ApplicationError).await {
+                Ok(()) => (),
+                Err(e) => log::error!("Error aborting tube: `{:?}`", e),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn handle_heartbeat<'a>(
+    _ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        match frame {
+            frame::Frame::Heartbeat => (),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        log::trace!("Sending HeartbeatAck...");
+        match frame_sender.send(frame::Frame::HeartbeatAck).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(FrameHandlerError::HeartbeatAckSendError(e)),
+        }
+    })
+}
+
+fn handle_heartbeat_ack<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        match frame {
+            frame::Frame::HeartbeatAck => (),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        ctx.record_heartbeat_ack();
+        Ok(())
+    })
+}
+
+fn handle_newtube_ack<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let tube_id = match frame {
+            frame::Frame::NewTubeAck { tube_id } => tube_id,
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        if !tube_mgr.establishment_pending {
+            return Err(FrameHandlerError::UnexpectedFrame(frame));
+        }
+        tube_mgr.establishment_pending = false;
+        tube_mgr.establishment_frames.clear();
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    })
+}
+
+fn handle_payload<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, ack_id, checksum) = match frame {
+            frame::Frame::Payload { tube_id, ack_id, checksum, .. } =>
+                (tube_id, ack_id, checksum),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match get_unfinished_tube_mgr(ctx, tube_id) {
+            Some(tm) => tm,
+            None => return handle_late_payload(ctx, tube_id, frame_sender).await,
+        };
+        let data = match frame {
+            frame::Frame::Payload { data, .. } => data,
+            _ => unreachable!(),
+        };
+
+        if !within_rate_limit(|| ctx.check_payload_rate(data.len())).await {
+            return abort_rate_limited_tube(tube_id, &tube_mgr, frame_sender).await;
+        }
+
+        // This frame carries the last chunk of the payload, so the payload 
+        // is delivered whole now. If any of its chunks were corrupted in 
+        // transit, surface that to the Tube in lieu of the payload itself. 
+        // Corrupted payloads are never acked; if the sender is waiting on an 
+        // ack, it's sent an Error instead so that its send fails right away 
+        // rather than timing out.
+        let data = match reassemble_payload(&tube_mgr, verify_payload_checksum(checksum, data)) {
+            Ok(data) => data,
+            Err(e) => {
+                let ack_id = match ack_id {
+                    Some(ack_id) => ack_id,
+                    None => return Ok(()),
                 };
+                let error_frame = frame::Frame::Error {
+                    tube_id: Some(tube_id),
+                    code: frame::ErrorCode::PayloadChecksumMismatch,
+                    detail: describe_checksum_mismatch(ack_id, &e),
+                };
+                if let Err(e) = frame_sender.send(error_frame).await {
+                    return Err(FrameHandlerError::ErrorSendError(e));
+                }
+                return Ok(());
+            },
+        };
+        if !queue_payload(ctx, tube_id, &tube_mgr, data, frame_sender).await? {
+            return Ok(());
+        }
+
+        // If an ack was requested, send one. Cumulative acks are deferred 
+        // until the frames that were read alongside this one have been 
+        // handled (see FrameHandler::flush_deferred_acks()), so that only 
+        // the latest of them is sent. Paused Tubes withhold their acks until
+        // they're resumed so that the backpressure reaches the sender.
+        if let Some(ack_id) = ack_id {
+            let (withheld, cumulative_acks) = {
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                (tube_mgr.withhold_ack(ack_id), tube_mgr.cumulative_acks)
+            };
+            if withheld {
+                log::trace!("Withholding PayloadAck for paused Tube(id={})", tube_id);
+            } else if cumulative_acks {
+                ctx.defer_cumulative_ack(tube_id, ack_id);
+            } else {
+                let ack_frame = frame::Frame::PayloadAck { tube_id, ack_id };
+                if let Err(e) = frame_sender.send(ack_frame).await {
+                    return Err(FrameHandlerError::PayloadAckSendError(e));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn handle_partial_payload<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, checksum, data) = match frame {
+            frame::Frame::PartialPayload { tube_id, checksum, data } => (tube_id, checksum, data),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        // The frame that carries the payload's last chunk is the one that's 
+        // counted as a late payload.
+        let tube_mgr = match get_unfinished_tube_mgr(ctx, tube_id) {
+            Some(tm) => tm,
+            None => return Ok(()),
+        };
+
+        if !within_rate_limit(|| ctx.check_payload_rate(data.len())).await {
+            return abort_rate_limited_tube(tube_id, &tube_mgr, frame_sender).await;
+        }
 
-                // If an ack was requested, send one...
-                if let Some(ack_id) = ack_id {
-                    let frame_data = match encode::payload_ack_frame(tube_id, ack_id) {
-                        Ok(data) => data,
-                        Err(e) => return Err(FrameHandlerError::PayloadAckFrameEncodingError(e)),
-                    };
-                    let mut sender = data_sender.lock().await;
-                    match sender.send_data(frame_data.into()).await {
-                        Ok(_) => (),
-                        Err(e) => return Err(FrameHandlerError::PayloadAckTransmitError(e)),
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        tube_mgr.record_payload_received();
+        tube_mgr.payload_reassembly.push_chunk(verify_payload_checksum(checksum, data));
+        Ok(())
+    })
+}
+
+/**
+ * The data of a received payload chunk, or the StreamError to surface in 
+ * lieu of it if it doesn't match its checksum.
+ */
+fn verify_payload_checksum(
+    checksum: Option<u32>,
+    data: bytes::Bytes,
+) -> Result<bytes::Bytes, tube::TubeEvent_StreamError> {
+    if let Some(expected) = checksum {
+        let computed = checksum::crc32(&data);
+        if computed != expected {
+            return Err(tube::TubeEvent_StreamError::PayloadChecksumMismatch {
+                expected,
+                computed,
+            });
+        }
+    }
+    Ok(data)
+}
+
+/**
+ * The detail of the PayloadChecksumMismatch Error sent for the payload with 
+ * the given AckId: the AckId, a colon, then a description of the mismatch 
+ * (see ErrorCode::PayloadChecksumMismatch).
+ */
+fn describe_checksum_mismatch(ack_id: u16, e: &tube::TubeEvent_StreamError) -> String {
+    match e {
+        tube::TubeEvent_StreamError::PayloadChecksumMismatch { expected, computed } =>
+            format!("{}: expected checksum {:#x}, computed {:#x}", ack_id, expected, computed),
+        e => format!("{}: {:?}", ack_id, e),
+    }
+}
+
+/**
+ * The AckId that the detail of a PayloadChecksumMismatch Error starts with, 
+ * if it's well formed.
+ */
+fn checksum_mismatch_ack_id(detail: &str) -> Option<u16> {
+    let (ack_id, _) = detail.split_once(':')?;
+    ack_id.trim().parse().ok()
+}
+
+/**
+ * Completes the payload whose last chunk was just received. If any of its 
+ * chunks were corrupted, that's surfaced to the Tube instead and the error 
+ * is returned.
+ */
+fn reassemble_payload(
+    tube_mgr: &Arc<Mutex<tube::TubeManager>>,
+    last_chunk: Result<bytes::Bytes, tube::TubeEvent_StreamError>,
+) -> Result<bytes::Bytes, tube::TubeEvent_StreamError> {
+    let mut tube_mgr = tube_mgr.lock().unwrap();
+    tube_mgr.record_payload_received();
+    match tube_mgr.payload_reassembly.finish(last_chunk) {
+        Ok(data) => {
+            tube_mgr.payload_bytes_received += data.len() as u64;
+            Ok(data)
+        },
+        Err(e) => {
+            tube_mgr.pending_events.push_back(tube::TubeEvent::StreamError(e.clone()));
+            if let Some(waker) = tube_mgr.waker.take() {
+                waker.wake();
+            }
+            Err(e)
+        },
+    }
+}
+
+fn handle_payload_ack<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, ack_id) = match frame {
+            frame::Frame::PayloadAck { tube_id, ack_id } => (tube_id, ack_id),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        if !tube_mgr.resolve_sendacks(ack_id) {
+            return Err(FrameHandlerError::UntrackedAckId {
+                tube_id,
+                ack_id
+            });
+        }
+        Ok(())
+    })
+}
+
+fn handle_sequenced_payload<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, sequence_number) = match frame {
+            frame::Frame::SequencedPayload { tube_id, sequence_number, .. } =>
+                (tube_id, sequence_number),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match get_unfinished_tube_mgr(ctx, tube_id) {
+            Some(tm) => tm,
+            None => return handle_late_payload(ctx, tube_id, frame_sender).await,
+        };
+        let data = match frame {
+            frame::Frame::SequencedPayload { data, .. } => data,
+            _ => unreachable!(),
+        };
+
+        if !within_rate_limit(|| ctx.check_payload_rate(data.len())).await {
+            return abort_rate_limited_tube(tube_id, &tube_mgr, frame_sender).await;
+        }
+
+        // Payloads with a corrupted chunk aren't acked, so the peer resends 
+        // them.
+        let data = match reassemble_payload(&tube_mgr, Ok(data)) {
+            Ok(data) => data,
+            Err(_) => return Ok(()),
+        };
+
+        // Retransmitted duplicates are acked again (the peer likely resent 
+        // them because an earlier SelectiveAck was lost), but they aren't 
+        // delivered to the Tube a second time.
+        ctx.defer_selective_ack(tube_id);
+        let is_new = tube_mgr.lock().unwrap().received_sequences.insert(sequence_number);
+        if is_new {
+            queue_payload(ctx, tube_id, &tube_mgr, data, frame_sender).await?;
+        }
+        Ok(())
+    })
+}
+
+/**
+ * Queues a received payload as a TubeEvent, applying the Tube's 
+ * EventQueueLimit if its queue is full. Returns false if the payload wasn't
+ * queued (because the Tube finished while the channel was blocked on it, or 
+ * because the Tube was aborted for overflowing).
+ */
+async fn queue_payload(
+    ctx: &ChannelContext,
+    tube_id: u32,
+    tube_mgr: &Arc<Mutex<tube::TubeManager>>,
+    data: bytes::Bytes,
+    frame_sender: &FrameSender,
+) -> Result<bool, FrameHandlerError> {
+    if let Some(limit) = ctx.slow_consumer_limit() {
+        let slow_consumer = {
+            let tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                return Ok(false);
+            }
+            limit.check(&tube_mgr, Instant::now())
+        };
+        if let Some((queued_events, stalled_for)) = slow_consumer {
+            evict_slow_consumer(
+                ctx,
+                tube_id,
+                tube_mgr,
+                limit.action,
+                queued_events,
+                stalled_for,
+                frame_sender,
+            ).await?;
+            return Ok(false);
+        }
+    }
+
+    loop {
+        let event_queue_space = {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            if tube_mgr.completion_state.is_terminal() {
+                return Ok(false);
+            }
+            match tube_mgr.make_room_for_payload() {
+                tube::PayloadRoom::Available => {
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::Payload(data));
+                    if tube_mgr.stalled_since.is_none() {
+                        tube_mgr.stalled_since = Some(Instant::now());
+                    }
+                    if let Some(waker) = tube_mgr.waker.take() {
+                        waker.wake();
                     }
+                    return Ok(true);
+                },
+                tube::PayloadRoom::Wait(event_queue_space) => event_queue_space,
+                tube::PayloadRoom::Overflowed => {
+                    abort_tube_locally(&mut tube_mgr, frame::AbortReason::EventQueueOverflow);
+                    break;
+                },
+            }
+        };
+        log::trace!("Waiting for room in the event queue of Tube(id={})...", tube_id);
+        event_queue_space.notified().await;
+    }
+
+    log::warn!("Aborting Tube(id={}) because its event queue overflowed", tube_id);
+    let abort_frame = frame::Frame::Abort {
+        tube_id,
+        reason: frame::AbortReason::EventQueueOverflow,
+    };
+    match frame_sender.send(abort_frame).await {
+        Ok(()) => Ok(false),
+        Err(e) => Err(FrameHandlerError::AbortSendError(e)),
+    }
+}
+
+/**
+ * Evicts the consumer of a Tube that exceeded its channel's 
+ * SlowConsumerLimit by aborting its Tube (or every Tube on its channel, and
+ * then closing the channel) with AbortReason::SlowConsumer.
+ */
+async fn evict_slow_consumer(
+    ctx: &ChannelContext,
+    tube_id: u32,
+    tube_mgr: &Arc<Mutex<tube::TubeManager>>,
+    action: tube::SlowConsumerAction,
+    queued_events: usize,
+    stalled_for: Duration,
+    frame_sender: &FrameSender,
+) -> Result<(), FrameHandlerError> {
+    log::warn!(
+        "Evicting the consumer of Tube(id={}), which left {} events queued for {:?} ({:?})",
+        tube_id,
+        queued_events,
+        stalled_for,
+        action,
+    );
+    ctx.publish_slow_consumer(tube_id, queued_events, stalled_for, action);
+
+    let mut tube_mgrs = match action {
+        tube::SlowConsumerAction::AbortTube => vec![(tube_id, tube_mgr.clone())],
+        tube::SlowConsumerAction::AbortChannel => ctx.tube_managers.to_vec(),
+    };
+    tube_mgrs.sort_by_key(|(tube_id, _)| *tube_id);
+
+    let mut abort_frames = vec![];
+    for (tube_id, tube_mgr) in tube_mgrs {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        if tube_mgr.completion_state.is_terminal() {
+            continue;
+        }
+        abort_tube_locally(&mut tube_mgr, frame::AbortReason::SlowConsumer);
+        abort_frames.push(frame::Frame::Abort {
+            tube_id,
+            reason: frame::AbortReason::SlowConsumer,
+        });
+    }
+    if let Err(e) = frame_sender.send_batch(abort_frames).await {
+        return Err(FrameHandlerError::AbortSendError(e));
+    }
+    if action == tube::SlowConsumerAction::AbortChannel {
+        frame_sender.close().await;
+    }
+    Ok(())
+}
+
+/**
+ * Marks a Tube as aborted from this end and surfaces the abort to its 
+ * application. The caller sends the Abort frame.
+ */
+fn abort_tube_locally(tube_mgr: &mut tube::TubeManager, reason: frame::AbortReason) {
+    tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(reason.clone()));
+    tube_mgr.pending_events.push_back(tube::TubeEvent::Abort(reason));
+    if let Some(waker) = tube_mgr.waker.take() {
+        waker.wake();
+    }
+    // The Tube keeps its id reserved until the AbortAck arrives
+    tube_mgr.abort_ack_pending = true;
+}
+
+/**
+ * Waits out any throttling under the channel's rate limits. Returns false if
+ * the peer exceeded them instead (see frame::RateLimitAction::Abort).
+ */
+async fn within_rate_limit(check: impl Fn() -> RateLimitCheck) -> bool {
+    loop {
+        match check() {
+            RateLimitCheck::Allowed => return true,
+            RateLimitCheck::ThrottleFor(wait) => {
+                log::trace!("Throttling the channel for {:?}...", wait);
+                tokio::time::sleep(wait).await;
+            },
+            RateLimitCheck::Exceeded => return false,
+        }
+    }
+}
+
+async fn abort_rate_limited_tube(
+    tube_id: u32,
+    tube_mgr: &Arc<Mutex<tube::TubeManager>>,
+    frame_sender: &FrameSender,
+) -> Result<(), FrameHandlerError> {
+    {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        if tube_mgr.completion_state.is_terminal() {
+            return Ok(());
+        }
+        abort_tube_locally(&mut tube_mgr, frame::AbortReason::RateLimited);
+    }
+
+    log::warn!(
+        "Aborting Tube(id={}) because the peer exceeded the payload rate limit", 
+        tube_id,
+    );
+    let abort_frame = frame::Frame::Abort {
+        tube_id,
+        reason: frame::AbortReason::RateLimited,
+    };
+    match frame_sender.send(abort_frame).await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(FrameHandlerError::AbortSendError(e)),
+    }
+}
+
+/**
+ * The TubeManager of a Tube that is still tracked and hasn't been closed or 
+ * aborted yet.
+ */
+fn get_unfinished_tube_mgr(
+    ctx: &ChannelContext,
+    tube_id: u32,
+) -> Option<Arc<Mutex<tube::TubeManager>>> {
+    match ctx.get_tube_mgr(&tube_id) {
+        Some(tube_mgr) if !tube_mgr.lock().unwrap().completion_state.is_terminal() =>
+            Some(tube_mgr),
+        _ => None,
+    }
+}
+
+/**
+ * Applies the channel's LatePayloadPolicy to a payload that arrived for a 
+ * Tube that has already finished.
+ */
+async fn handle_late_payload(
+    ctx: &ChannelContext,
+    tube_id: u32,
+    frame_sender: &FrameSender,
+) -> Result<(), FrameHandlerError> {
+    let policy = ctx.late_payload_policy();
+    if let LatePayloadPolicy::Drop = policy {
+        return Ok(());
+    }
+
+    let num_late_payloads = ctx.count_late_payload();
+    log::warn!(
+        "Dropping payload for finished Tube(id={}) ({} late payloads on this channel)",
+        tube_id,
+        num_late_payloads,
+    );
+    match policy {
+        LatePayloadPolicy::EscalateAfter(max) if num_late_payloads > max => {
+            let error_frame = frame::Frame::Error {
+                tube_id: Some(tube_id),
+                code: frame::ErrorCode::ProtocolViolation,
+                detail: format!("Received {} payloads for finished tubes", num_late_payloads),
+            };
+            if let Err(e) = frame_sender.send(error_frame).await {
+                return Err(FrameHandlerError::ErrorSendError(e));
+            }
+            Err(FrameHandlerError::TooManyLatePayloads { tube_id, num_late_payloads })
+        },
+        _ => Ok(()),
+    }
+}
+
+fn handle_selective_ack<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let tube_id = match frame {
+            frame::Frame::SelectiveAck { tube_id, .. } => tube_id,
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+        let ranges = match frame {
+            frame::Frame::SelectiveAck { ranges, .. } => ranges,
+            _ => unreachable!(),
+        };
+
+        tube_mgr.lock().unwrap().unacked_sequenced.apply_selective_ack(&ranges);
+        Ok(())
+    })
+}
+
+fn handle_server_has_finished_sending<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let tube_id = match frame {
+            frame::Frame::ServerHasFinishedSending { tube_id } => tube_id,
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        if let PeerType::Server = ctx.peer_type {
+            return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
+        }
+
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+
+        let should_remove_tube_mgr = {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            let new_state = {
+                use tube::TubeCompletionState::*;
+                match tube_mgr.completion_state {
+                    Open =>
+                        ServerHasFinishedSending,
+                    ClientHasFinishedSending =>
+                        Closed,
+                    ServerHasFinishedSending | Closed =>
+                        return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
+                            tube_id,
+                        }),
+                    AbortedFromRemote(_) =>
+                        return Err(FrameHandlerError::ReceivedHasFinishedSendingAfterRemoteAbort {
+                            tube_id,
+                        }),
+                    AbortedFromLocal(_) =>
+                        return Ok(()),
                 }
+            };
 
-                let mut tube_mgr = tube_mgr.lock().unwrap();
-                tube_mgr.pending_events.push_back(tube::TubeEvent::Payload(data.to_vec()));
+            if tube_mgr.completion_state != new_state {
+                tube_mgr.set_completion_state(new_state.clone());
+                if tube_mgr.completion_state == tube::TubeCompletionState::ServerHasFinishedSending {
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::ServerHasFinishedSending);
+                }
                 if let Some(waker) = tube_mgr.waker.take() {
                     waker.wake();
                 }
-            },
+            }
 
-            frame::Frame::PayloadAck { tube_id, ack_id } => {
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
-                };
+            tube::TubeCompletionState::Closed == new_state
+        };
 
-                let mut tube_mgr = tube_mgr.lock().unwrap();
-                match tube_mgr.sendacks.get_mut(&ack_id) {
-                    Some(res) => res.resolve(()),
-                    None => return Err(FrameHandlerError::UntrackedAckId {
+        if should_remove_tube_mgr {
+            ctx.tube_managers.remove(tube_id);
+        }
+        Ok(())
+    })
+}
+
+fn handle_abort<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (tube_id, reason) = match frame {
+            frame::Frame::Abort { tube_id, ref reason } => (tube_id, reason.clone()),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+
+        {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            match tube_mgr.completion_state {
+                TubeCompletionState::AbortedFromRemote(_) =>
+                    return Err(FrameHandlerError::DuplicateAbortFrame {
                         tube_id,
-                        ack_id
                     }),
-                };
-            },
 
-            frame::Frame::ServerHasFinishedSending { tube_id } => {
-                if let PeerType::Server = self.peer_type {
-                    return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
-                }
+                TubeCompletionState::AbortedFromLocal(_) => (),
 
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
-                };
+                _ => {
+                    tube_mgr.set_completion_state(
+                        TubeCompletionState::AbortedFromRemote(reason.clone())
+                    );
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::Abort(reason));
+                    if let Some(waker) = tube_mgr.waker.take() {
+                        waker.wake();
+                    }
+                },
+            }
+        };
 
-                let should_remove_tube_mgr = {
-                    let mut tube_mgr = tube_mgr.lock().unwrap();
-                    let new_state = {
-                        use tube::TubeCompletionState::*;
-                        match tube_mgr.completion_state {
-                            Open => 
-                                ServerHasFinishedSending,
-                            ClientHasFinishedSending => 
-                                Closed,
-                            ServerHasFinishedSending | Closed =>
-                                return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
-                                    tube_id,
-                                }),
-                            AbortedFromRemote(_) => 
-                                return Err(FrameHandlerError::ReceivedHasFinishedSendingAfterRemoteAbort {
-                                    tube_id,
-                                }),
-                            AbortedFromLocal(_) =>
-                                return Ok(FrameHandlerResult::FullyHandled),
-                        }
-                    };
+        ctx.tube_managers.remove(tube_id);
 
-                    if tube_mgr.completion_state != new_state {
-                        tube_mgr.completion_state = new_state.clone();
-                        if tube_mgr.completion_state == tube::TubeCompletionState::ServerHasFinishedSending {
-                            tube_mgr.pending_events.push_back(tube::TubeEvent::ServerHasFinishedSending);
-                        }
-                        if let Some(waker) = tube_mgr.waker.take() {
-                            waker.wake();
-                        }
-                    }
+        log::trace!("Sending AbortAck(tube_id={})...", tube_id);
+        let abortack_frame = frame::Frame::AbortAck { tube_id };
+        if let Err(e) = frame_sender.send(abortack_frame).await {
+            return Err(FrameHandlerError::AbortAckSendError(e));
+        }
+        Ok(())
+    })
+}
 
-                    tube::TubeCompletionState::Closed == new_state
-                };
+fn handle_abort_ack<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let tube_id = match frame {
+            frame::Frame::AbortAck { tube_id } => tube_id,
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
 
-                if should_remove_tube_mgr {
-                    self.tube_managers.lock().unwrap().remove(&tube_id);
-                }
-            },
+        // It is now safe to re-use tube_id for a future new tube!
+        let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+            Some(tm) => tm,
+            None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+        };
+        {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            log::trace!("Removing Tube(id={}) from list of pending Aborts.", &tube_id);
+            tube_mgr.abort_pending_id_reservation = None;
+            tube_mgr.abort_ack_pending = false;
+        };
 
-            frame::Frame::Abort { tube_id, ref reason } => {
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
-                };
+        ctx.tube_managers.remove(tube_id);
+        // Anything waiting on the channel's Tubes to settle (see 
+        // ChannelContext::poll_tubes_settled()) was waiting on this AbortAck
+        for waker in tube_mgr.lock().unwrap().completion_wakers.drain(..) {
+            waker.wake();
+        }
+        Ok(())
+    })
+}
 
-                {
-                    let mut tube_mgr = tube_mgr.lock().unwrap();
-                    match tube_mgr.completion_state {
-                        TubeCompletionState::AbortedFromRemote(_) =>
-                            return Err(FrameHandlerError::DuplicateAbortFrame {
-                                tube_id,
-                            }),
-
-                        TubeCompletionState::AbortedFromLocal(_) => (),
-
-                        _ => {
-                            tube_mgr.completion_state = 
-                                TubeCompletionState::AbortedFromLocal(reason.clone());
-                            tube_mgr.pending_events.push_back(tube::TubeEvent::Abort(reason.clone()));
-                            if let Some(waker) = tube_mgr.waker.take() {
-                                waker.wake();
-                            }
-                        },
-                    }
+fn handle_error<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    _frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        match frame {
+            frame::Frame::Error { tube_id: Some(tube_id), code, detail } => {
+                let tube_mgr = match ctx.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(
+                        frame::Frame::Error { tube_id: Some(tube_id), code, detail }
+                    )),
                 };
 
-                self.tube_managers.lock().unwrap().remove(&tube_id);
+                let mut tube_mgr = tube_mgr.lock().unwrap();
 
-                let abortack_frame_data = match encode::abort_ack_frame(tube_id) {
-                    Ok(data) => data,
-                    Err(e) => return Err(
-                        FrameHandlerError::AbortAckFrameEncodingError(e)
+                // A payload the peer received corrupted fails the send that's 
+                // waiting on its ack rather than surfacing on the Tube.
+                let failed_ack_id = match code {
+                    frame::ErrorCode::PayloadChecksumMismatch => checksum_mismatch_ack_id(&detail),
+                    _ => None,
+                };
+                let failed_sendack = match failed_ack_id {
+                    Some(ack_id) => tube_mgr.fail_sendack(
+                        ack_id,
+                        tube::error::SendError::PayloadChecksumMismatch(detail.clone()),
                     ),
+                    None => false,
                 };
-                let mut sender = data_sender.lock().await;
-                log::trace!("Sending AbortAck(tube_id={})...", tube_id);
-                if let Err(e) = sender.send_data(abortack_frame_data.into()).await {
-                    return Err(FrameHandlerError::AbortAckTransmitError(e));
+                if failed_sendack {
+                    return Ok(());
+                }
+
+                tube_mgr.pending_events.push_back(tube::TubeEvent::StreamError(
+                    tube::TubeEvent_StreamError::PeerError { code, detail }
+                ));
+                if let Some(waker) = tube_mgr.waker.take() {
+                    waker.wake();
                 }
             },
 
-            frame::Frame::AbortAck { tube_id } => {
-                // It is now safe to re-use tube_id for a future new tube!
-                let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
-                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
-                };
-                let mut tube_mgr = tube_mgr.lock().unwrap();
-                log::trace!("Removing Tube(id={}) from list of pending Aborts.", &tube_id);
-                tube_mgr.abort_pending_id_reservation = None
+            // Errors that aren't specific to a Tube are surfaced on every Tube
+            // in the channel.
+            frame::Frame::Error { tube_id: None, code, detail } => {
+                log::warn!("Peer reported a channel error ({:?}): {}", code, detail);
+                for (_, tube_mgr) in ctx.tube_managers.to_vec() {
+                    let mut tube_mgr = tube_mgr.lock().unwrap();
+                    tube_mgr.pending_events.push_back(tube::TubeEvent::StreamError(
+                        tube::TubeEvent_StreamError::PeerError {
+                            code: code.clone(),
+                            detail: detail.clone(),
+                        }
+                    ));
+                    if let Some(waker) = tube_mgr.waker.take() {
+                        waker.wake();
+                    }
+                }
+            },
+
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+        Ok(())
+    })
+}
+
+fn handle_extension_frame<'a>(
+    ctx: &'a ChannelContext,
+    frame: frame::Frame,
+    frame_sender: &'a FrameSender,
+) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+    Box::pin(async move {
+        let (type_id, payload) = match frame {
+            frame::Frame::ExtensionFrame { type_id, payload } => (type_id, payload),
+            frame => return Err(FrameHandlerError::UnexpectedFrame(frame)),
+        };
+
+        if !ctx.extension_frame_handlers.dispatch(type_id, payload) {
+            let error_frame = frame::Frame::Error {
+                tube_id: None,
+                code: frame::ErrorCode::UnsupportedFeature,
+                detail: format!(
+                    "No handler registered for extension frame type {:#x}",
+                    type_id,
+                ),
+            };
+            if let Err(e) = frame_sender.send(error_frame).await {
+                return Err(FrameHandlerError::ErrorSendError(e));
+            }
+            return Err(FrameHandlerError::UnhandledExtensionFrame { type_id });
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod frame_handler_tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    use crate::common::ChannelEvent;
+    use crate::common::frame::ExtensionFrameHandlers;
+    use crate::common::frame::FrameInterceptors;
+    use crate::common::frame::FramingVersion;
+    use crate::common::frame::RateLimitAction;
+    use crate::common::frame::RateLimits;
+
+    fn make_channel_ctx(peer_type: PeerType, tube_ids: &[u32]) -> ChannelContext {
+        let ctx = ChannelContext::new(peer_type, ExtensionFrameHandlers::new());
+        for tube_id in tube_ids {
+            ctx.tube_managers.insert(*tube_id, Arc::new(Mutex::new(tube::TubeManager::new())));
+        }
+        ctx
+    }
+
+    fn make_frame_sender() -> (FrameSender, hyper::Body) {
+        let (body_sender, body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            FramingVersion::V1,
+            FrameInterceptors::new(),
+        );
+        (frame_sender, body)
+    }
+
+    #[tokio::test]
+    async fn cumulative_acks_are_coalesced_until_flushed() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        ctx.get_tube_mgr(&1).unwrap().lock().unwrap().cumulative_acks = true;
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for ack_id in [4, 0, 7] {
+            frame_handler.handle_frame(frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: Some(ack_id),
+                checksum: None,
+                data: vec![42].into(),
+            }, &frame_sender).await.unwrap();
+        }
+        frame_handler.flush_deferred_acks(&frame_sender).await.unwrap();
+        drop(frame_sender);
+
+        let mut decoder = crate::common::frame::Decoder::new();
+        let mut ack_frames = vec![];
+        while let Some(data) = body.data().await {
+            ack_frames.extend(decoder.decode_bytes(data.unwrap()).unwrap());
+        }
+        assert_eq!(ack_frames, vec![frame::Frame::PayloadAck { tube_id: 1, ack_id: 7 }]);
+    }
+
+    #[tokio::test]
+    async fn paused_tubes_withhold_their_acks() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1, 3]);
+        ctx.get_tube_mgr(&1).unwrap().lock().unwrap().paused = true;
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for (tube_id, ack_id) in [(1, 4), (3, 0), (1, 5)] {
+            frame_handler.handle_frame(frame::Frame::Payload {
+                tube_id,
+                ack_id: Some(ack_id),
+                checksum: None,
+                data: vec![42].into(),
+            }, &frame_sender).await.unwrap();
+        }
+        drop(frame_sender);
+
+        let mut decoder = crate::common::frame::Decoder::new();
+        let mut ack_frames = vec![];
+        while let Some(data) = body.data().await {
+            ack_frames.extend(decoder.decode_bytes(data.unwrap()).unwrap());
+        }
+        assert_eq!(ack_frames, vec![frame::Frame::PayloadAck { tube_id: 3, ack_id: 0 }]);
+
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(tube_mgr.withheld_acks, vec![4, 5]);
+        assert_eq!(tube_mgr.pending_events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn partial_payloads_are_delivered_and_acked_whole() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, body) = make_frame_sender();
+        let reading = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for data in ["ab", "cd"] {
+            frame_handler.handle_frame(frame::Frame::PartialPayload {
+                tube_id: 1,
+                checksum: Some(checksum::crc32(data.as_bytes())),
+                data: data.into(),
+            }, &frame_sender).await.unwrap();
+        }
+        assert!(tube_mgr.lock().unwrap().pending_events.is_empty());
+        frame_handler.handle_frame(frame::Frame::Payload {
+            tube_id: 1,
+            ack_id: Some(3),
+            checksum: Some(checksum::crc32(b"e")),
+            data: "e".into(),
+        }, &frame_sender).await.unwrap();
+        drop(frame_sender);
+
+        assert_eq!(
+            reading.await.unwrap(),
+            vec![frame::Frame::PayloadAck { tube_id: 1, ack_id: 3 }],
+        );
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(
+            tube_mgr.pending_events,
+            vec![tube::TubeEvent::Payload("abcde".into())],
+        );
+        assert_eq!(tube_mgr.payload_bytes_received, 5);
+    }
+
+    #[tokio::test]
+    async fn payloads_with_a_corrupted_partial_payload_are_reported_instead_of_acked() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, body) = make_frame_sender();
+        let reading = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for (data, checksum) in [("ab", checksum::crc32(b"ab")), ("cd", 42)] {
+            frame_handler.handle_frame(frame::Frame::PartialPayload {
+                tube_id: 1,
+                checksum: Some(checksum),
+                data: data.into(),
+            }, &frame_sender).await.unwrap();
+        }
+        for (ack_id, data) in [(3, "e"), (4, "f")] {
+            frame_handler.handle_frame(frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: Some(ack_id),
+                checksum: Some(checksum::crc32(data.as_bytes())),
+                data: data.into(),
+            }, &frame_sender).await.unwrap();
+        }
+        drop(frame_sender);
+
+        // The payload after the corrupted one is unaffected
+        assert_eq!(reading.await.unwrap(), vec![
+            frame::Frame::Error {
+                tube_id: Some(1),
+                code: frame::ErrorCode::PayloadChecksumMismatch,
+                detail: format!(
+                    "3: expected checksum 0x2a, computed {:#x}",
+                    checksum::crc32(b"cd"),
+                ),
             },
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 4 },
+        ]);
+        assert_eq!(tube_mgr.lock().unwrap().pending_events, vec![
+            tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::PayloadChecksumMismatch {
+                expected: 42,
+                computed: checksum::crc32(b"cd"),
+            }),
+            tube::TubeEvent::Payload("f".into()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn partial_sequenced_payloads_share_a_sequence_number() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        // The second copy is a retransmission of the first
+        for _ in 0..2 {
+            frame_handler.handle_frame(frame::Frame::PartialPayload {
+                tube_id: 1,
+                checksum: None,
+                data: "ab".into(),
+            }, &frame_sender).await.unwrap();
+            frame_handler.handle_frame(frame::Frame::SequencedPayload {
+                tube_id: 1,
+                sequence_number: 0,
+                data: "c".into(),
+            }, &frame_sender).await.unwrap();
+        }
+
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(tube_mgr.pending_events, vec![tube::TubeEvent::Payload("abc".into())]);
+        assert_eq!(tube_mgr.received_sequences.ranges(), &[0..=0]);
+    }
+
+    fn make_payload(tube_id: u32, ack_id: u16) -> frame::Frame {
+        frame::Frame::Payload {
+            tube_id,
+            ack_id: Some(ack_id),
+            checksum: None,
+            data: vec![ack_id as u8].into(),
+        }
+    }
+
+    fn set_event_queue_limit(
+        ctx: &ChannelContext, 
+        tube_id: u32, 
+        policy: tube::EventQueueOverflowPolicy,
+    ) -> Arc<Mutex<tube::TubeManager>> {
+        let tube_mgr = ctx.get_tube_mgr(&tube_id).unwrap();
+        tube_mgr.lock().unwrap().event_queue_limit = Some(tube::EventQueueLimit {
+            capacity: 2,
+            policy,
+        });
+        tube_mgr
+    }
+
+    /**
+     * Reads the frames sent over body until every FrameSender is dropped.
+     */
+    fn spawn_frame_reader(mut body: hyper::Body) -> tokio::task::JoinHandle<Vec<frame::Frame>> {
+        use hyper::body::HttpBody;
+
+        tokio::spawn(async move {
+            let mut decoder = crate::common::frame::Decoder::new();
+            let mut sent_frames = vec![];
+            while let Some(data) = body.data().await {
+                sent_frames.extend(decoder.decode_bytes(data.unwrap()).unwrap());
+            }
+            sent_frames
+        })
+    }
+
+    #[tokio::test]
+    async fn full_event_queues_drop_their_oldest_payload() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = set_event_queue_limit(
+            &ctx,
+            1,
+            tube::EventQueueOverflowPolicy::DropOldest,
+        );
+        let (frame_sender, body) = make_frame_sender();
+        let _reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for ack_id in 0..3 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        assert_eq!(tube_mgr.lock().unwrap().pending_events, VecDeque::from([
+            tube::TubeEvent::Payload(vec![1].into()),
+            tube::TubeEvent::Payload(vec![2].into()),
+        ]));
+    }
+
+    #[tokio::test]
+    async fn full_event_queues_can_abort_the_tube() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = set_event_queue_limit(
+            &ctx,
+            1,
+            tube::EventQueueOverflowPolicy::AbortTube,
+        );
+        let (frame_sender, body) = make_frame_sender();
+        let reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for ack_id in 0..3 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        drop(frame_sender);
+
+        // The overflowing payload (and the late one after it) isn't acked
+        assert_eq!(reader.await.unwrap(), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 0 },
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 1 },
+            frame::Frame::Abort { tube_id: 1, reason: frame::AbortReason::EventQueueOverflow },
+        ]);
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(
+            tube_mgr.completion_state, 
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::EventQueueOverflow),
+        );
+        assert_eq!(
+            tube_mgr.pending_events.back(), 
+            Some(&tube::TubeEvent::Abort(frame::AbortReason::EventQueueOverflow)),
+        );
+        assert!(tube_mgr.abort_ack_pending);
+    }
+
+    #[tokio::test]
+    async fn slow_consumers_are_evicted_from_their_tube() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1, 3]);
+        ctx.set_slow_consumer_limit(Some(tube::SlowConsumerLimit {
+            action: tube::SlowConsumerAction::AbortTube,
+            max_stall: None,
+            max_queued_events: Some(2),
+        }));
+        let tube_mgr1 = ctx.get_tube_mgr(&1).unwrap();
+        let tube_mgr3 = ctx.get_tube_mgr(&3).unwrap();
+        let (frame_sender, body) = make_frame_sender();
+        let reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        for ack_id in 0..3 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        frame_handler.handle_frame(make_payload(3, 0), &frame_sender).await.unwrap();
+        drop(frame_sender);
+
+        assert_eq!(reader.await.unwrap(), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 0 },
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 1 },
+            frame::Frame::Abort { tube_id: 1, reason: frame::AbortReason::SlowConsumer },
+            frame::Frame::PayloadAck { tube_id: 3, ack_id: 0 },
+        ]);
+        assert_eq!(
+            tube_mgr1.lock().unwrap().completion_state, 
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::SlowConsumer),
+        );
+        assert_eq!(tube_mgr3.lock().unwrap().completion_state, TubeCompletionState::Open);
+        let event = futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await;
+        match event {
+            Some(ChannelEvent::SlowConsumer { 
+                tube_id: 1, 
+                queued_events: 2, 
+                action: tube::SlowConsumerAction::AbortTube,
+                ..
+            }) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_consumers_can_evict_the_whole_channel() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1, 3]);
+        ctx.set_slow_consumer_limit(Some(tube::SlowConsumerLimit {
+            action: tube::SlowConsumerAction::AbortChannel,
+            max_stall: Some(std::time::Duration::from_millis(500)),
+            max_queued_events: None,
+        }));
+        let tube_mgr1 = ctx.get_tube_mgr(&1).unwrap();
+        let tube_mgr3 = ctx.get_tube_mgr(&3).unwrap();
+        tube_mgr1.lock().unwrap().stalled_since = 
+            Some(Instant::now() - std::time::Duration::from_secs(1));
+        let (frame_sender, body) = make_frame_sender();
+        let reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        frame_handler.handle_frame(make_payload(1, 0), &frame_sender).await.unwrap();
+
+        // The channel is closed, so the reader finishes even though 
+        // frame_sender is still alive
+        assert_eq!(reader.await.unwrap(), vec![
+            frame::Frame::Abort { tube_id: 1, reason: frame::AbortReason::SlowConsumer },
+            frame::Frame::Abort { tube_id: 3, reason: frame::AbortReason::SlowConsumer },
+        ]);
+        for tube_mgr in [tube_mgr1, tube_mgr3] {
+            assert_eq!(
+                tube_mgr.lock().unwrap().completion_state, 
+                TubeCompletionState::AbortedFromLocal(frame::AbortReason::SlowConsumer),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn full_event_queues_can_block_the_channel_until_there_is_room() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = set_event_queue_limit(
+            &ctx,
+            1,
+            tube::EventQueueOverflowPolicy::Block,
+        );
+        let (frame_sender, body) = make_frame_sender();
+        let _reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for ack_id in 0..2 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        let blocked = tokio::spawn(async move {
+            frame_handler.handle_frame(make_payload(1, 2), &frame_sender).await.unwrap();
+        });
+        tokio::task::yield_now().await;
+        assert!(!blocked.is_finished());
+        assert_eq!(tube_mgr.lock().unwrap().pending_events.len(), 2);
+
+        {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            tube_mgr.pending_events.pop_front();
+            tube_mgr.notify_event_queue_space();
+        }
+        blocked.await.unwrap();
+        assert_eq!(tube_mgr.lock().unwrap().pending_events, VecDeque::from([
+            tube::TubeEvent::Payload(vec![1].into()),
+            tube::TubeEvent::Payload(vec![2].into()),
+        ]));
+    }
+
+    #[tokio::test]
+    async fn late_payloads_are_dropped_per_the_channel_policy() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        tube_mgr.lock().unwrap().set_completion_state(TubeCompletionState::AbortedFromLocal(
+            frame::AbortReason::ApplicationAbort
+        ));
+        ctx.set_late_payload_policy(LatePayloadPolicy::EscalateAfter(1));
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+        let payload = |tube_id| frame::Frame::Payload {
+            tube_id,
+            ack_id: Some(0),
+            checksum: None,
+            data: vec![42].into(),
         };
 
-        Ok(FrameHandlerResult::FullyHandled)
+        // Payloads for tubes that are no longer tracked are late too
+        frame_handler.handle_frame(payload(9), &frame_sender).await.unwrap();
+        match frame_handler.handle_frame(payload(1), &frame_sender).await {
+            Err(FrameHandlerError::TooManyLatePayloads { tube_id: 1, num_late_payloads: 2 }) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+        assert_eq!(ctx.num_late_payloads(), 2);
+        assert_eq!(tube_mgr.lock().unwrap().pending_events.len(), 0);
+
+        ctx.set_late_payload_policy(LatePayloadPolicy::Drop);
+        frame_handler.handle_frame(payload(1), &frame_sender).await.unwrap();
+        assert_eq!(ctx.num_late_payloads(), 2);
+        drop(frame_sender);
+
+        // Late payloads aren't acked, so the only frame sent is the Error
+        let mut decoder = crate::common::frame::Decoder::new();
+        let mut sent_frames = vec![];
+        while let Some(data) = body.data().await {
+            sent_frames.extend(decoder.decode_bytes(data.unwrap()).unwrap());
+        }
+        assert_eq!(sent_frames, vec![frame::Frame::Error {
+            tube_id: Some(1),
+            code: frame::ErrorCode::ProtocolViolation,
+            detail: "Received 2 payloads for finished tubes".to_string(),
+        }]);
+    }
+
+    #[tokio::test]
+    async fn sequenced_payloads_are_deduplicated_and_selectively_acked() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        // Payload 1 was lost and payload 2 was retransmitted
+        for sequence_number in [0, 2, 3, 2] {
+            frame_handler.handle_frame(frame::Frame::SequencedPayload {
+                tube_id: 1,
+                sequence_number,
+                data: vec![sequence_number as u8].into(),
+            }, &frame_sender).await.unwrap();
+        }
+        frame_handler.flush_deferred_acks(&frame_sender).await.unwrap();
+        drop(frame_sender);
+
+        assert_eq!(Vec::from(tube_mgr.lock().unwrap().pending_events.clone()), vec![
+            tube::TubeEvent::Payload(vec![0].into()),
+            tube::TubeEvent::Payload(vec![2].into()),
+            tube::TubeEvent::Payload(vec![3].into()),
+        ]);
+
+        let mut decoder = crate::common::frame::Decoder::new();
+        let mut ack_frames = vec![];
+        while let Some(data) = body.data().await {
+            ack_frames.extend(decoder.decode_bytes(data.unwrap()).unwrap());
+        }
+        assert_eq!(ack_frames, vec![frame::Frame::SelectiveAck {
+            tube_id: 1,
+            ranges: vec![0..=0, 2..=3],
+        }]);
+    }
+
+    #[tokio::test]
+    async fn selective_ack_releases_acked_sequenced_payloads() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        for byte in 0..4u8 {
+            tube_mgr.lock().unwrap().unacked_sequenced.push(vec![byte].into());
+        }
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        frame_handler.handle_frame(frame::Frame::SelectiveAck {
+            tube_id: 1,
+            ranges: vec![0..=0, 2..=2],
+        }, &frame_sender).await.unwrap();
+
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(tube_mgr.unacked_sequenced.gaps(), vec![(1, vec![1].into())]);
+        assert_eq!(tube_mgr.unacked_sequenced.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn tube_error_frame_becomes_stream_error_event() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3]);
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let result = frame_handler.handle_frame(frame::Frame::Error {
+            tube_id: Some(1),
+            code: frame::ErrorCode::BadHeader,
+            detail: "bad header".to_string(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+
+        let tube_mgr1 = tube_managers.get(1).unwrap();
+        let tube_mgr1 = tube_mgr1.lock().unwrap();
+        assert_eq!(
+            tube_mgr1.pending_events.front(),
+            Some(&tube::TubeEvent::StreamError(
+                tube::TubeEvent_StreamError::PeerError {
+                    code: frame::ErrorCode::BadHeader,
+                    detail: "bad header".to_string(),
+                }
+            )),
+        );
+        let tube_mgr3 = tube_managers.get(3).unwrap();
+        let tube_mgr3 = tube_mgr3.lock().unwrap();
+        assert_eq!(tube_mgr3.pending_events.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_error_fails_the_send_awaiting_its_ack() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1]);
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let mut sendack = tube_mgr.lock().unwrap().insert_sendack(7).unwrap();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let detail = "7: expected checksum 0x1, computed 0x2".to_string();
+        frame_handler.handle_frame(frame::Frame::Error {
+            tube_id: Some(1),
+            code: frame::ErrorCode::PayloadChecksumMismatch,
+            detail: detail.clone(),
+        }, &frame_sender).await.unwrap();
+        assert!(matches!(
+            sendack.try_recv(),
+            Ok(Err(tube::error::SendError::PayloadChecksumMismatch(d))) if d == detail
+        ));
+        assert!(tube_mgr.lock().unwrap().pending_events.is_empty());
+
+        // With no send awaiting the ack, it's surfaced on the Tube like any 
+        // other error
+        frame_handler.handle_frame(frame::Frame::Error {
+            tube_id: Some(1),
+            code: frame::ErrorCode::PayloadChecksumMismatch,
+            detail: detail.clone(),
+        }, &frame_sender).await.unwrap();
+        assert_eq!(tube_mgr.lock().unwrap().pending_events, vec![
+            tube::TubeEvent::StreamError(tube::TubeEvent_StreamError::PeerError {
+                code: frame::ErrorCode::PayloadChecksumMismatch,
+                detail,
+            }),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn abort_ack_releases_the_id_and_untracks_the_tube() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1]);
+        let tube_managers = ctx.tube_managers.clone();
+        let tube_mgr = tube_managers.get(1).unwrap().clone();
+        {
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(
+                frame::AbortReason::ApplicationAbort,
+            ));
+            tube_mgr.abort_pending_id_reservation = Some(UniqueId::new(1, None));
+        }
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let result = frame_handler.handle_frame(
+            frame::Frame::AbortAck { tube_id: 1 }, 
+            &frame_sender,
+        ).await;
+        assert!(result.is_ok());
+        assert!(tube_mgr.lock().unwrap().abort_pending_id_reservation.is_none());
+        assert_eq!(tube_managers.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn channel_error_frame_is_surfaced_on_every_tube() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3]);
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let result = frame_handler.handle_frame(frame::Frame::Error {
+            tube_id: None,
+            code: frame::ErrorCode::OverLimit,
+            detail: "".to_string(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+
+        for (_, tube_mgr) in tube_managers.to_vec() {
+            assert_eq!(tube_mgr.lock().unwrap().pending_events.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn drain_frame_becomes_server_must_drain_event() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3]);
+        let tube_managers = ctx.tube_managers.clone();
+        tube_managers.get(3).unwrap().lock().unwrap()
+            .set_completion_state(TubeCompletionState::Closed);
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let result = frame_handler.handle_frame(frame::Frame::Drain {
+            reason: frame::DrainReason::Shutdown,
+            deadline_unix_millis: Some(1_700_000_000_000),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+
+        let tube_mgr1 = tube_managers.get(1).unwrap();
+        let tube_mgr1 = tube_mgr1.lock().unwrap();
+        assert_eq!(
+            tube_mgr1.pending_events.front(),
+            Some(&tube::TubeEvent::ServerMustDrain {
+                reason: frame::DrainReason::Shutdown,
+                deadline: Some(
+                    std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)
+                ),
+            }),
+        );
+        let tube_mgr3 = tube_managers.get(3).unwrap();
+        let tube_mgr3 = tube_mgr3.lock().unwrap();
+        assert_eq!(tube_mgr3.pending_events.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejected_frames_are_reported_to_observers() {
+        let ctx = make_channel_ctx(PeerType::Client, &[]);
+        let rejections = Arc::new(Mutex::new(vec![]));
+        let rejections2 = rejections.clone();
+        ctx.frame_error_observers.add(
+            move |error: &FrameHandlerError, rejected_frame: &RejectedFrame| {
+                rejections2.lock().unwrap().push((format!("{:?}", error), rejected_frame.clone()));
+            }
+        );
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let untracked_frame = frame::Frame::ServerHasFinishedSending { tube_id: 7 };
+        assert!(frame_handler.handle_frame(untracked_frame.clone(), &frame_sender).await.is_err());
+        frame_handler.handle_frame(frame::Frame::Drain {
+            reason: frame::DrainReason::Unspecified,
+            deadline_unix_millis: None,
+        }, &frame_sender).await.unwrap();
+
+        let rejections = rejections.lock().unwrap();
+        assert_eq!(rejections.len(), 1);
+        let (error, rejected_frame) = &rejections[0];
+        assert!(error.starts_with("UntrackedTubeId"));
+        assert!(!rejected_frame.is_truncated());
+        let mut decoder = crate::common::frame::Decoder::new_with_version(
+            rejected_frame.framing_version,
+        );
+        let frames = decoder.decode(rejected_frame.raw_frame.clone()).unwrap();
+        assert_eq!(frames, vec![untracked_frame]);
+    }
+
+    #[tokio::test]
+    async fn go_away_fails_tubes_the_peer_never_processed() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3, 5]);
+        let tube_managers = ctx.tube_managers.clone();
+        let tube_mgr5 = tube_managers.get(5).unwrap().clone();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        let result = frame_handler.handle_frame(frame::Frame::GoAway {
+            last_tube_id: 3,
+            reason: "restarting".to_string(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+        assert_eq!(ctx.peer_going_away(), Some("restarting".to_string()));
+
+        {
+            assert!(!tube_managers.contains(5));
+            for tube_id in [1, 3] {
+                let tube_mgr = tube_managers.get(tube_id).unwrap();
+                let tube_mgr = tube_mgr.lock().unwrap();
+                assert_eq!(tube_mgr.pending_events.len(), 0);
+                assert_eq!(tube_mgr.completion_state, TubeCompletionState::Open);
+            }
+        }
+
+        let tube_mgr5 = tube_mgr5.lock().unwrap();
+        assert_eq!(
+            tube_mgr5.pending_events.front(),
+            Some(&tube::TubeEvent::StreamError(
+                tube::TubeEvent_StreamError::PeerGoingAway("restarting".to_string())
+            )),
+        );
+        assert!(tube_mgr5.completion_state.is_terminal());
+    }
+
+    #[tokio::test]
+    async fn newtube_is_ignored_after_going_away() {
+        let ctx = make_channel_ctx(PeerType::Server, &[]).accepting_peer_tubes();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 1,
+            headers: HashMap::new(),
+        }, &frame_sender).await.unwrap();
+        assert_eq!(ctx.start_going_away(), 1);
+
+        frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 3,
+            headers: HashMap::new(),
+        }, &frame_sender).await.unwrap();
+        assert!(ctx.tube_managers.contains(1));
+        assert!(!ctx.tube_managers.contains(3));
+        assert_eq!(ctx.start_going_away(), 1);
+    }
+
+    #[tokio::test]
+    async fn fail_all_tubes_aborts_unfinished_tubes() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3]);
+        let tube_managers = ctx.tube_managers.clone();
+        let tube_mgr1 = tube_managers.get(1).unwrap().clone();
+        let tube_mgr3 = tube_managers.get(3).unwrap().clone();
+        tube_mgr3.lock().unwrap().set_completion_state(TubeCompletionState::Closed);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        frame_handler.fail_all_tubes("connection reset".to_string());
+        assert_eq!(tube_managers.len(), 0);
+
+        let tube_mgr1 = tube_mgr1.lock().unwrap();
+        assert_eq!(
+            tube_mgr1.pending_events.front(),
+            Some(&tube::TubeEvent::StreamError(
+                tube::TubeEvent_StreamError::TransportError(
+                    "connection reset".to_string()
+                )
+            )),
+        );
+        assert_eq!(
+            tube_mgr1.completion_state,
+            TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::TransportErrorWhileSynchronizingTubeState
+            ),
+        );
+        let tube_mgr3 = tube_mgr3.lock().unwrap();
+        assert_eq!(tube_mgr3.pending_events.len(), 0);
+        assert_eq!(tube_mgr3.completion_state, TubeCompletionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn fail_established_tubes_keeps_tubes_awaiting_their_ack() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1, 3]);
+        let tube_managers = ctx.tube_managers.clone();
+        let tube_mgr1 = tube_managers.get(1).unwrap().clone();
+        let tube_mgr3 = tube_managers.get(3).unwrap().clone();
+        let establishment_frames = vec![
+            frame::Frame::NewTube { tube_id: 3, headers: HashMap::new() },
+            frame::Frame::ClientHasFinishedSending { tube_id: 3 },
+        ];
+        {
+            let mut tube_mgr3 = tube_mgr3.lock().unwrap();
+            tube_mgr3.establishment_pending = true;
+            tube_mgr3.establishment_frames = establishment_frames.clone();
+        }
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let replayed_frames = frame_handler.fail_established_tubes("connection reset".to_string());
+        assert_eq!(replayed_frames, establishment_frames);
+        let tube_ids: Vec<u32> = tube_managers.to_vec().into_iter()
+            .map(|(tube_id, _)| tube_id)
+            .collect();
+        assert_eq!(tube_ids, vec![3]);
+        assert_eq!(
+            tube_mgr1.lock().unwrap().completion_state,
+            TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::TransportErrorWhileSynchronizingTubeState
+            ),
+        );
+        let tube_mgr3 = tube_mgr3.lock().unwrap();
+        assert_eq!(tube_mgr3.pending_events.len(), 0);
+        assert_eq!(tube_mgr3.completion_state, TubeCompletionState::Open);
+    }
+
+    #[tokio::test]
+    async fn newtube_is_published_via_the_channel_context() {
+        let ctx = make_channel_ctx(PeerType::Server, &[]).accepting_peer_tubes();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        let result = frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 1,
+            headers: HashMap::new(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+        assert!(ctx.tube_managers.contains(1));
+
+        let event = futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await;
+        match event {
+            Some(ChannelEvent::NewTube(tube)) => assert_eq!(tube.get_id(), 1),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn newtube_is_acked_on_channels_that_ack_new_tubes() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[]).accepting_peer_tubes();
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            FramingVersion::V4,
+            FrameInterceptors::new(),
+        );
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        let result = frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 1,
+            headers: HashMap::new(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+
+        let mut decoder = crate::common::frame::Decoder::new_with_version(FramingVersion::V4);
+        let sent_frames = decoder.decode_bytes(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![frame::Frame::NewTubeAck { tube_id: 1 }]);
+    }
+
+    #[tokio::test]
+    async fn newtube_ack_establishes_the_tube() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1]);
+        let tube_mgr = ctx.tube_managers.get(1).unwrap().clone();
+        tube_mgr.lock().unwrap().establishment_pending = true;
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        let result = frame_handler.handle_frame(
+            frame::Frame::NewTubeAck { tube_id: 1 }, 
+            &frame_sender,
+        ).await;
+        assert!(result.is_ok());
+        let establishment = futures::future::poll_fn(|cx| {
+            tube_mgr.lock().unwrap().poll_establishment(cx)
+        }).await;
+        assert_eq!(establishment, Ok(()));
+
+        match frame_handler.handle_frame(
+            frame::Frame::NewTubeAck { tube_id: 1 }, 
+            &frame_sender,
+        ).await {
+            Err(FrameHandlerError::UnexpectedFrame(frame::Frame::NewTubeAck { tube_id: 1 })) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn heartbeats_are_answered_and_acks_are_recorded() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[]);
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            FramingVersion::V5,
+            FrameInterceptors::new(),
+        );
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        frame_handler.handle_frame(frame::Frame::Heartbeat, &frame_sender).await.unwrap();
+        let mut decoder = crate::common::frame::Decoder::new_with_version(FramingVersion::V5);
+        let sent_frames = decoder.decode_bytes(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![frame::Frame::HeartbeatAck]);
+
+        let heartbeat_ack = ctx.next_heartbeat_ack();
+        futures::pin_mut!(heartbeat_ack);
+        heartbeat_ack.as_mut().enable();
+        frame_handler.handle_frame(frame::Frame::HeartbeatAck, &frame_sender).await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), heartbeat_ack).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn newtube_errors_when_peer_tubes_arent_accepted() {
+        let ctx = make_channel_ctx(PeerType::Client, &[]);
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        match frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 2,
+            headers: HashMap::new(),
+        }, &frame_sender).await {
+            Err(FrameHandlerError::ServerInitiatedTubesNotImplemented) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+        assert_eq!(tube_managers.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn newtube_errors_when_peer_tube_limit_is_reached() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1])
+            .accepting_peer_tubes()
+            .with_max_peer_tubes(1);
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        match frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 3,
+            headers: HashMap::new(),
+        }, &frame_sender).await {
+            Err(FrameHandlerError::TubeLimitExceeded { tube_id: 3, max_tubes: 1 }) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+        assert_eq!(tube_managers.len(), 1);
+        let sent_frames = crate::common::frame::Decoder::new()
+            .decode_bytes(body.data().await.unwrap().unwrap())
+            .unwrap();
+        match sent_frames.front() {
+            Some(frame::Frame::Error { tube_id: Some(3), code: frame::ErrorCode::OverLimit, .. }) => (),
+            unexpected => panic!("Unexpected frame sent: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn newtube_is_refused_when_its_id_has_the_wrong_parity() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[]).accepting_peer_tubes();
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        match frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 2,
+            headers: HashMap::new(),
+        }, &frame_sender).await {
+            Err(FrameHandlerError::InvalidPeerTubeId { tube_id: 2 }) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+        assert_eq!(tube_managers.len(), 0);
+        let sent_frames = crate::common::frame::Decoder::new()
+            .decode_bytes(body.data().await.unwrap().unwrap())
+            .unwrap();
+        match sent_frames.front() {
+            Some(frame::Frame::Error {
+                tube_id: Some(2),
+                code: frame::ErrorCode::ProtocolViolation,
+                ..
+            }) => (),
+            unexpected => panic!("Unexpected frame sent: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn newtube_is_refused_once_a_drain_was_requested() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[1]).accepting_peer_tubes();
+        let tube_managers = ctx.tube_managers.clone();
+        assert!(ctx.record_drain_requested());
+        assert!(!ctx.record_drain_requested());
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        match frame_handler.handle_frame(frame::Frame::NewTube {
+            tube_id: 3,
+            headers: HashMap::new(),
+        }, &frame_sender).await {
+            Err(FrameHandlerError::ChannelDraining { tube_id: 3 }) => (),
+            unexpected => panic!("Unexpected handler result: {:?}", unexpected),
+        }
+        assert_eq!(tube_managers.len(), 1);
+        let sent_frames = crate::common::frame::Decoder::new()
+            .decode_bytes(body.data().await.unwrap().unwrap())
+            .unwrap();
+        match sent_frames.front() {
+            Some(frame::Frame::Error { tube_id: Some(3), code: frame::ErrorCode::Draining, .. }) => (),
+            unexpected => panic!("Unexpected frame sent: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn newtubes_beyond_the_rate_limit_are_aborted() {
+        use hyper::body::HttpBody;
+
+        let ctx = make_channel_ctx(PeerType::Server, &[])
+            .accepting_peer_tubes()
+            .with_rate_limits(RateLimits {
+                action: RateLimitAction::Abort,
+                max_new_tubes_per_sec: Some(1),
+                max_payload_bytes_per_sec: None,
+            });
+        let (frame_sender, mut body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx.clone());
+
+        for tube_id in [3, 5] {
+            frame_handler.handle_frame(frame::Frame::NewTube {
+                tube_id,
+                headers: HashMap::new(),
+            }, &frame_sender).await.unwrap();
+        }
+
+        // Only the first Tube is surfaced; the second is aborted
+        let event = futures::future::poll_fn(|cx| ctx.poll_next_event(cx)).await;
+        match event {
+            Some(ChannelEvent::NewTube(tube)) => assert_eq!(tube.get_id(), 3),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        let rate_limited = frame::Frame::Abort { tube_id: 5, reason: frame::AbortReason::RateLimited };
+        let mut decoder = crate::common::frame::Decoder::new();
+        let mut sent_frames = vec![];
+        while !sent_frames.contains(&rate_limited) {
+            let data = body.data().await.unwrap().unwrap();
+            sent_frames.extend(decoder.decode_bytes(data).unwrap());
+        }
+        assert_eq!(
+            ctx.get_tube_mgr(&5).unwrap().lock().unwrap().completion_state, 
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::RateLimited),
+        );
+    }
+
+    #[tokio::test]
+    async fn payloads_beyond_the_rate_limit_abort_their_tube() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1])
+            .with_rate_limits(RateLimits {
+                action: RateLimitAction::Abort,
+                max_new_tubes_per_sec: None,
+                max_payload_bytes_per_sec: Some(2),
+            });
+        let tube_mgr = ctx.get_tube_mgr(&1).unwrap();
+        let (frame_sender, body) = make_frame_sender();
+        let reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        for ack_id in 0..3 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        drop(frame_sender);
+
+        assert_eq!(reader.await.unwrap(), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 0 },
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 1 },
+            frame::Frame::Abort { tube_id: 1, reason: frame::AbortReason::RateLimited },
+        ]);
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(
+            tube_mgr.completion_state, 
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::RateLimited),
+        );
+        assert_eq!(
+            tube_mgr.pending_events.back(), 
+            Some(&tube::TubeEvent::Abort(frame::AbortReason::RateLimited)),
+        );
+    }
+
+    #[tokio::test]
+    async fn payloads_beyond_the_rate_limit_are_throttled() {
+        let ctx = make_channel_ctx(PeerType::Server, &[1])
+            .with_rate_limits(RateLimits {
+                action: RateLimitAction::Throttle,
+                max_new_tubes_per_sec: None,
+                max_payload_bytes_per_sec: Some(20),
+            });
+        let (frame_sender, body) = make_frame_sender();
+        let _reader = spawn_frame_reader(body);
+        let mut frame_handler = FrameHandler::new(ctx);
+
+        // The first 20 payloads are a burst; the next 2 wait ~50ms each
+        let started = std::time::Instant::now();
+        for ack_id in 0..22 {
+            frame_handler.handle_frame(make_payload(1, ack_id), &frame_sender).await.unwrap();
+        }
+        assert!(started.elapsed() >= std::time::Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn registered_handler_replaces_builtin_handler() {
+        let ctx = make_channel_ctx(PeerType::Client, &[1]);
+        let tube_managers = ctx.tube_managers.clone();
+        let (frame_sender, _body) = make_frame_sender();
+        let mut frame_handler = FrameHandler::new(ctx);
+        fn forget_all_tubes<'a>(
+            ctx: &'a ChannelContext,
+            _frame: frame::Frame,
+            _frame_sender: &'a FrameSender,
+        ) -> BoxFuture<'a, Result<(), FrameHandlerError>> {
+            ctx.tube_managers.take_all();
+            Box::pin(futures::future::ready(Ok(())))
+        }
+        frame_handler.register_frame_type_handler(frame::ERROR_FRAMETYPE, forget_all_tubes);
+
+        let result = frame_handler.handle_frame(frame::Frame::Error {
+            tube_id: Some(1),
+            code: frame::ErrorCode::BadHeader,
+            detail: "".to_string(),
+        }, &frame_sender).await;
+        assert!(result.is_ok());
+        assert_eq!(tube_managers.len(), 0);
     }
 }
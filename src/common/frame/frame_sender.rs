@@ -0,0 +1,898 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::Weak;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+use crate::common::capture;
+use crate::common::ChannelExecutor;
+use super::buffer_pool::BufferPoolConfig;
+use super::buffer_pool::BufferPoolStats;
+use super::buffer_pool::SharedBufferPoolStats;
+use super::encode;
+use super::frame;
+use super::interceptor::FrameInterceptors;
+use super::interceptor::InterceptedFrame;
+use super::outbound_scheduler::OutboundScheduler;
+use super::transport::FrameSink;
+use super::transport::TransportError;
+
+/**
+ * The priority of frames sent without one (see
+ * FrameSender::send_batch_with_priority()), and that Tubes start with.
+ */
+pub(in crate) const DEFAULT_PRIORITY: u8 = 1;
+
+#[derive(Debug)]
+pub enum FrameSendError {
+    /**
+     * The FrameSender has been closed (see FrameSender::close()).
+     */
+    ChannelClosed,
+    FrameEncodeError(encode::FrameEncodeError),
+    FrameVetoed(String),
+    TransportError(TransportError),
+}
+
+/**
+ * How a FrameSender's writer task coalesces the frames queued for it into
+ * fewer, larger writes to the transport (see FrameSender::with_write_coalescing()).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteCoalescing {
+    /**
+     * Once the frames coalesced into a chunk add up to this many bytes, it's
+     * written without waiting for more. 0 writes every send on its own.
+     */
+    pub max_chunk_size: usize,
+    /**
+     * How long to wait for more frames to be queued before writing a chunk
+     * that's smaller than max_chunk_size. Zero (the default) only coalesces
+     * frames that were queued while the previous chunk was being written, so
+     * no frame is held back.
+     */
+    pub max_delay: Duration,
+}
+impl Default for WriteCoalescing {
+    fn default() -> Self {
+        WriteCoalescing {
+            max_chunk_size: 64 * 1024,
+            max_delay: Duration::ZERO,
+        }
+    }
+}
+
+/**
+ * The write half of a channel's transport along with the Encoder used to 
+ * serialize frames onto it. These are owned by the writer task (see
+ * FrameWriter::run()), so frames are encoded into reused buffers and written
+ * in the order they're queued without the FrameSenders contending on a lock.
+ */
+#[derive(Debug)]
+struct FrameWriter {
+    bytes_sent: Arc<AtomicU64>,
+    capture: Arc<Mutex<Option<capture::FrameCapture>>>,
+    encoder: encode::Encoder,
+    /**
+     * None once the FrameSender has been closed.
+     */
+    sink: Option<Box<dyn FrameSink>>,
+}
+impl FrameWriter {
+    /**
+     * Carries out the commands queued by a FrameSender and its clones. Writes
+     * that are waiting at the same time are interleaved across Tubes by an
+     * OutboundScheduler; everything else is carried out in the order it was
+     * queued. Ends once the FrameSenders have all been dropped.
+     */
+    async fn run(
+        mut self,
+        mut commands: mpsc::UnboundedReceiver<WriterCommand>,
+        coalescing: WriteCoalescing,
+    ) {
+        let mut scheduler = OutboundScheduler::new();
+        loop {
+            if scheduler.is_empty() {
+                match commands.recv().await {
+                    Some(command) => self.schedule(command, &mut scheduler),
+                    None => return,
+                }
+            }
+            self.schedule_queued(&mut commands, &mut scheduler);
+            let command = match scheduler.pop() {
+                Some(command) => command,
+                None => continue,
+            };
+            match command {
+                WriterCommand::Abort(aborted) => {
+                    if let Some(sink) = self.sink.take() {
+                        sink.abort();
+                    }
+                    let _ = aborted.send(());
+                },
+                WriterCommand::Close(closed) => {
+                    if let Some(mut sink) = self.sink.take() {
+                        // An error just means the transport has already gone away
+                        let _ = futures::future::poll_fn(|cx| sink.poll_ready(cx)).await;
+                    }
+                    let _ = closed.send(());
+                },
+                WriterCommand::ReplaceTransport(sink, replaced) => {
+                    let is_open = self.sink.is_some();
+                    if is_open {
+                        self.sink = Some(sink);
+                    }
+                    let _ = replaced.send(is_open);
+                },
+                WriterCommand::Write(queued_write) => {
+                    self.write_coalesced(queued_write, &mut commands, &mut scheduler, coalescing)
+                        .await;
+                },
+            }
+        }
+    }
+
+    /**
+     * Encodes first, along with the writes scheduled after it (per
+     * WriteCoalescing), into one chunk and writes that to the transport. The
+     * chunk ends early if the next command scheduled isn't a write, which is
+     * left in scheduler to be carried out next.
+     */
+    async fn write_coalesced(
+        &mut self,
+        first: QueuedWrite,
+        commands: &mut mpsc::UnboundedReceiver<WriterCommand>,
+        scheduler: &mut OutboundScheduler<WriterCommand>,
+        coalescing: WriteCoalescing,
+    ) {
+        if self.sink.is_none() {
+            first.result.report(Err(FrameSendError::ChannelClosed));
+            return;
+        }
+
+        let deadline = tokio::time::Instant::now() + coalescing.max_delay;
+        let mut chunk = None;
+        let mut results = vec![];
+        let mut next_write = Some(first);
+        while let Some(queued_write) = next_write.take() {
+            match queued_write.frames {
+                QueuedFrames::Unencoded(frames) => 
+                    match self.encoder.append_batch(frames, &mut chunk) {
+                        Ok(()) => results.push(queued_write.result),
+                        Err(e) => queued_write.result.report(
+                            Err(FrameSendError::FrameEncodeError(e)),
+                        ),
+                    },
+                QueuedFrames::Encoded(frame_data) => {
+                    // The rest of a write's frames are dropped once one of 
+                    // them has failed
+                    if !queued_write.result.has_failed() {
+                        self.encoder.append_encoded(&frame_data, &mut chunk);
+                        results.push(queued_write.result);
+                    }
+                },
+            }
+            if chunk.as_ref().map_or(0, |buf| buf.len()) >= coalescing.max_chunk_size {
+                break;
+            }
+            self.schedule_queued(commands, scheduler);
+            if scheduler.is_empty() && !coalescing.max_delay.is_zero() {
+                let command = tokio::time::timeout_at(deadline, commands.recv()).await;
+                if let Ok(Some(command)) = command {
+                    self.schedule(command, scheduler);
+                }
+            }
+            let command = scheduler.pop_if(|command| matches!(command, WriterCommand::Write(_)));
+            if let Some(WriterCommand::Write(queued_write)) = command {
+                next_write = Some(queued_write);
+            }
+        }
+        let chunk_data = self.encoder.finish_chunk(chunk);
+        if results.is_empty() {
+            return;
+        }
+
+        self.record_outgoing(&chunk_data);
+        let num_bytes = chunk_data.len() as u64;
+        let send_result = match self.sink.as_mut() {
+            Some(sink) => sink.send(chunk_data).await,
+            None => unreachable!("Only commands other than writes take the sink"),
+        };
+        match send_result {
+            Ok(()) => {
+                self.bytes_sent.fetch_add(num_bytes, Ordering::Relaxed);
+                for result in results {
+                    result.report(Ok(()));
+                }
+            },
+            Err(e) => {
+                // Every write in the chunk failed with the same error
+                let message = e.to_string();
+                let mut e = Some(e);
+                for result in results {
+                    let e = match e.take() {
+                        Some(e) => e,
+                        None => TransportError::new(message.clone()),
+                    };
+                    result.report(Err(FrameSendError::TransportError(e)));
+                }
+            },
+        }
+    }
+
+    /**
+     * Writes for a single Tube are scheduled in that Tube's lane, weighted by
+     * their priority, a frame at a time: a write of several frames is encoded
+     * as it's scheduled (so that if any of them fail to encode, none of them
+     * are written) and split into a write per frame. These keep their order 
+     * in the lane, so a Tube's frames are only ever interleaved with other 
+     * Tubes' frames (and a large payload split across several frames can't
+     * hold up the other Tubes until it has all been written). Anything else 
+     * (including writes for the channel as a whole) keeps its place relative 
+     * to everything queued around it.
+     */
+    fn schedule(&mut self, command: WriterCommand, scheduler: &mut OutboundScheduler<WriterCommand>) {
+        let queued_write = match command {
+            WriterCommand::Write(queued_write) => queued_write,
+            command => return scheduler.push_barrier(command),
+        };
+        let tube_id = match queued_write.tube_id() {
+            Some(tube_id) => tube_id,
+            None => return scheduler.push_barrier(WriterCommand::Write(queued_write)),
+        };
+        let weight = u32::from(queued_write.priority);
+        let frames = match queued_write.frames {
+            QueuedFrames::Unencoded(frames) if frames.len() > 1 => frames,
+            frames => {
+                let queued_write = QueuedWrite { frames, ..queued_write };
+                return scheduler.push(tube_id, weight, WriterCommand::Write(queued_write));
+            },
+        };
+
+        let encoded_frames = match self.encoder.encode_each(frames) {
+            Ok(encoded_frames) => encoded_frames,
+            Err(e) => {
+                queued_write.result.report(Err(FrameSendError::FrameEncodeError(e)));
+                return;
+            },
+        };
+        let results = queued_write.result.split(encoded_frames.len());
+        for (frame_data, result) in encoded_frames.into_iter().zip(results) {
+            let queued_write = QueuedWrite {
+                frames: QueuedFrames::Encoded(frame_data),
+                priority: queued_write.priority,
+                result,
+            };
+            scheduler.push(tube_id, weight, WriterCommand::Write(queued_write));
+        }
+    }
+
+    /**
+     * Moves the commands that are waiting in the writer task's queue into
+     * scheduler, so that they're all weighed against each other.
+     */
+    fn schedule_queued(
+        &mut self,
+        commands: &mut mpsc::UnboundedReceiver<WriterCommand>,
+        scheduler: &mut OutboundScheduler<WriterCommand>,
+    ) {
+        while let Ok(command) = commands.try_recv() {
+            self.schedule(command, scheduler);
+        }
+    }
+
+    /**
+     * Called as each chunk is written so that chunks are recorded in the
+     * order they're written. A failure to record is logged rather than failing
+     * the send.
+     */
+    fn record_outgoing(&self, data: &[u8]) {
+        if let Some(capture) = self.capture.lock().unwrap().as_ref() {
+            let direction = capture::CaptureDirection::Outgoing;
+            if let Err(e) = capture.record(direction, self.encoder.version(), data) {
+                log::error!("Error recording outgoing frames: {:?}", e);
+            }
+        }
+    }
+}
+
+/**
+ * What the writer task is asked to do. Each command that doesn't write frames
+ * carries where to report that it has been carried out.
+ */
+#[derive(Debug)]
+enum WriterCommand {
+    Abort(oneshot::Sender<()>),
+    Close(oneshot::Sender<()>),
+    ReplaceTransport(Box<dyn FrameSink>, oneshot::Sender<bool>),
+    Write(QueuedWrite),
+}
+
+type ResultSender = oneshot::Sender<Result<(), FrameSendError>>;
+
+/**
+ * Frames to be written to the transport (all or none of them), along with
+ * where to report the result.
+ */
+#[derive(Debug)]
+struct QueuedWrite {
+    frames: QueuedFrames,
+    priority: u8,
+    result: WriteResult,
+}
+impl QueuedWrite {
+    /**
+     * The Tube that all of the frames are for, if there is one. Only known
+     * for frames that haven't been encoded yet.
+     */
+    fn tube_id(&self) -> Option<u32> {
+        let frames = match &self.frames {
+            QueuedFrames::Unencoded(frames) => frames,
+            QueuedFrames::Encoded(_) => return None,
+        };
+        let tube_id = frames.first()?.tube_id()?;
+        if frames.iter().all(|frame| frame.tube_id() == Some(tube_id)) {
+            Some(tube_id)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+enum QueuedFrames {
+    /**
+     * Encoded as they're written.
+     */
+    Unencoded(Vec<frame::Frame>),
+    /**
+     * A single frame of a write that was split into a write per frame as it
+     * was scheduled (see FrameWriter::schedule()).
+     */
+    Encoded(Bytes),
+}
+
+/**
+ * Where to report the result of a QueuedWrite.
+ */
+#[derive(Debug)]
+enum WriteResult {
+    Whole(ResultSender),
+    /**
+     * The result of a write that was split into a write per frame, shared by
+     * all of them: the first of them to fail reports that failure, otherwise
+     * the last of them reports success.
+     */
+    Frame {
+        is_last: bool,
+        sender: Arc<Mutex<Option<ResultSender>>>,
+    },
+}
+impl WriteResult {
+    /**
+     * Whether an earlier frame of a write that was split into a write per
+     * frame has already failed.
+     */
+    fn has_failed(&self) -> bool {
+        match self {
+            WriteResult::Whole(_) => false,
+            WriteResult::Frame { sender, .. } => sender.lock().unwrap().is_none(),
+        }
+    }
+
+    fn report(self, result: Result<(), FrameSendError>) {
+        match self {
+            WriteResult::Whole(sender) => {
+                let _ = sender.send(result);
+            },
+            WriteResult::Frame { is_last, sender } => {
+                if !is_last && result.is_ok() {
+                    return;
+                }
+                if let Some(sender) = sender.lock().unwrap().take() {
+                    let _ = sender.send(result);
+                }
+            },
+        }
+    }
+
+    /**
+     * Shares this result across the num_frames writes that a write is split
+     * into.
+     */
+    fn split(self, num_frames: usize) -> impl Iterator<Item = WriteResult> {
+        let sender = match self {
+            WriteResult::Whole(sender) => Arc::new(Mutex::new(Some(sender))),
+            WriteResult::Frame { sender, .. } => sender,
+        };
+        (1..=num_frames).map(move |frame_num| WriteResult::Frame {
+            is_last: frame_num == num_frames,
+            sender: sender.clone(),
+        })
+    }
+}
+
+/**
+ * Feeds the writer task of a FrameSender (and its clones), along with how the
+ * writer task is spawned once it's first needed.
+ */
+#[derive(Clone, Debug, Default)]
+struct WriteQueue {
+    executor: ChannelExecutor,
+    sender: OnceLock<mpsc::UnboundedSender<WriterCommand>>,
+    write_coalescing: WriteCoalescing,
+}
+
+/**
+ * A cloneable handle for sending frames to the peer on a given channel. All 
+ * outgoing frames are run through the channel's FrameInterceptors, encoded, 
+ * and then written to the underlying transport.
+ *
+ * Frames are written by a writer task (spawned onto the FrameSender's
+ * ChannelExecutor when it's first needed), which owns the transport and
+ * coalesces the frames sent while it's writing into a single write. Sending
+ * just queues frames for it, though sends still resolve once the transport
+ * has taken their frames.
+ */
+#[derive(Clone, Debug)]
+pub struct FrameSender {
+    buffer_pool_stats: SharedBufferPoolStats,
+    /**
+     * The encoded bytes the transport has taken (across all clones).
+     */
+    bytes_sent: Arc<AtomicU64>,
+    capture: Arc<Mutex<Option<capture::FrameCapture>>>,
+    framing_version: frame::FramingVersion,
+    interceptors: FrameInterceptors,
+    max_payload_frame_size: usize,
+    write_queue: Arc<WriteQueue>,
+    /**
+     * Handed over to the writer task once it's spawned.
+     */
+    writer: Arc<Mutex<Option<FrameWriter>>>,
+}
+impl FrameSender {
+    pub fn new(
+        sink: Box<dyn FrameSink>,
+        framing_version: frame::FramingVersion,
+        interceptors: FrameInterceptors,
+    ) -> Self {
+        FrameSender::new_with_buffer_pool(
+            sink,
+            framing_version,
+            interceptors,
+            BufferPoolConfig::default(),
+        )
+    }
+
+    /**
+     * Like new(), but encodes frames into a pool of buffers sized by
+     * buffer_pool rather than the default one.
+     */
+    pub fn new_with_buffer_pool(
+        sink: Box<dyn FrameSink>,
+        framing_version: frame::FramingVersion,
+        interceptors: FrameInterceptors,
+        buffer_pool: BufferPoolConfig,
+    ) -> Self {
+        let encoder = encode::Encoder::with_buffer_pool(framing_version, buffer_pool);
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let capture = Arc::new(Mutex::new(None));
+        FrameSender {
+            buffer_pool_stats: encoder.shared_buffer_pool_stats().clone(),
+            bytes_sent: bytes_sent.clone(),
+            capture: capture.clone(),
+            framing_version,
+            interceptors,
+            max_payload_frame_size: frame::MAX_PAYLOAD_FRAME_SIZE,
+            write_queue: Arc::new(WriteQueue::default()),
+            writer: Arc::new(Mutex::new(Some(FrameWriter {
+                bytes_sent,
+                capture,
+                encoder,
+                sink: Some(sink),
+            }))),
+        }
+    }
+
+    /**
+     * Where the writer task is spawned. Meant to be given as the FrameSender
+     * is made, before it's cloned or sends anything.
+     */
+    pub fn with_executor(mut self, executor: ChannelExecutor) -> Self {
+        Arc::make_mut(&mut self.write_queue).executor = executor;
+        self
+    }
+
+    /**
+     * The max payload frame size negotiated for the channel (see
+     * frame::negotiate_max_payload_frame_size()), which Tubes split their
+     * payloads by. Defaults to frame::MAX_PAYLOAD_FRAME_SIZE.
+     */
+    pub fn with_max_payload_frame_size(mut self, max_payload_frame_size: usize) -> Self {
+        self.max_payload_frame_size = max_payload_frame_size.max(1);
+        self
+    }
+
+    /**
+     * Meant to be given as the FrameSender is made, before it's cloned or
+     * sends anything.
+     */
+    pub fn with_write_coalescing(mut self, write_coalescing: WriteCoalescing) -> Self {
+        Arc::make_mut(&mut self.write_queue).write_coalescing = write_coalescing;
+        self
+    }
+
+    /**
+     * Ends the stream of frames sent to the peer (for this FrameSender and all
+     * of its clones) once the frames that were already queued have been 
+     * taken by the transport. Frames sent afterwards fail with 
+     * FrameSendError::ChannelClosed.
+     */
+    pub async fn close(&self) {
+        let (closed_sender, closed) = oneshot::channel();
+        if self.commands().send(WriterCommand::Close(closed_sender)).is_ok() {
+            let _ = closed.await;
+        }
+    }
+
+    /**
+     * Like close(), but resets the transport rather than waiting for the peer
+     * to take the frames that were already written (e.g. once the peer has
+     * stopped reading them).
+     */
+    pub async fn abort(&self) {
+        let (aborted_sender, aborted) = oneshot::channel();
+        if self.commands().send(WriterCommand::Abort(aborted_sender)).is_ok() {
+            let _ = aborted.await;
+        }
+    }
+
+    /**
+     * Moves this FrameSender (and all of its clones) onto a new transport, 
+     * e.g. once a client channel has reconnected. Returns false (and leaves 
+     * the FrameSender closed) if it has already been closed. The Encoder (and
+     * its pool of buffers) carries over to the new transport.
+     */
+    pub(in crate) async fn replace_transport(&self, sink: Box<dyn FrameSink>) -> bool {
+        let (replaced_sender, replaced) = oneshot::channel();
+        let command = WriterCommand::ReplaceTransport(sink, replaced_sender);
+        if self.commands().send(command).is_err() {
+            return false;
+        }
+        replaced.await.unwrap_or(false)
+    }
+
+    pub fn downgrade(&self) -> WeakFrameSender {
+        WeakFrameSender {
+            buffer_pool_stats: self.buffer_pool_stats.clone(),
+            bytes_sent: self.bytes_sent.clone(),
+            capture: self.capture.clone(),
+            framing_version: self.framing_version,
+            interceptors: self.interceptors.clone(),
+            max_payload_frame_size: self.max_payload_frame_size,
+            write_queue: Arc::downgrade(&self.write_queue),
+            writer: Arc::downgrade(&self.writer),
+        }
+    }
+
+    /**
+     * How often frames (across all clones) were encoded into a reused buffer
+     * rather than a newly allocated one.
+     */
+    pub fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool_stats.get()
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn framing_version(&self) -> frame::FramingVersion {
+        self.framing_version
+    }
+
+    pub fn interceptors(&self) -> &FrameInterceptors {
+        &self.interceptors
+    }
+
+    pub fn max_payload_frame_size(&self) -> usize {
+        self.max_payload_frame_size
+    }
+
+    /**
+     * Records every chunk of encoded frames written to the transport (by this
+     * FrameSender or any of its clones) to the given FrameCapture.
+     */
+    pub fn set_capture(&self, capture: Option<capture::FrameCapture>) {
+        *self.capture.lock().unwrap() = capture;
+    }
+
+    pub async fn send(&self, frame: frame::Frame) -> Result<(), FrameSendError> {
+        let frame = match self.interceptors.intercept(frame) {
+            InterceptedFrame::Forward(frame) => frame,
+            InterceptedFrame::Veto(reason) => 
+                return Err(FrameSendError::FrameVetoed(reason)),
+        };
+        self.write(vec![frame], DEFAULT_PRIORITY).await
+    }
+
+    /**
+     * Sends several frames together. Each frame is run through the 
+     * interceptors individually, but if any frame is vetoed or fails to encode
+     * then none of the frames are sent. The frames are written with a single
+     * write to the transport unless they're all for one Tube, in which case
+     * they're written a frame at a time (see send_batch_with_priority()).
+     */
+    pub async fn send_batch(&self, frames: Vec<frame::Frame>) -> Result<(), FrameSendError> {
+        self.send_batch_with_priority(frames, DEFAULT_PRIORITY).await
+    }
+
+    /**
+     * Like send_batch(), but when the writer task has writes from several
+     * Tubes waiting, a batch of frames for one Tube is weighted by priority:
+     * the Tube gets up to that many frames in a row before the next Tube
+     * gets its turn. The batch's frames are never interleaved with the Tube's
+     * other frames, only with other Tubes'.
+     */
+    pub(in crate) async fn send_batch_with_priority(
+        &self,
+        frames: Vec<frame::Frame>,
+        priority: u8,
+    ) -> Result<(), FrameSendError> {
+        let mut intercepted_frames = Vec::with_capacity(frames.len());
+        for frame in frames {
+            match self.interceptors.intercept(frame) {
+                InterceptedFrame::Forward(frame) => intercepted_frames.push(frame),
+                InterceptedFrame::Veto(reason) => 
+                    return Err(FrameSendError::FrameVetoed(reason)),
+            };
+        }
+
+        if intercepted_frames.is_empty() {
+            return Ok(());
+        }
+        self.write(intercepted_frames, priority).await
+    }
+
+    /**
+     * Queues frames for the writer task, and resolves once the transport has
+     * taken them.
+     */
+    async fn write(&self, frames: Vec<frame::Frame>, priority: u8) -> Result<(), FrameSendError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let command = WriterCommand::Write(QueuedWrite {
+            frames: QueuedFrames::Unencoded(frames),
+            priority,
+            result: WriteResult::Whole(result_sender),
+        });
+        if self.commands().send(command).is_err() {
+            return Err(FrameSendError::ChannelClosed);
+        }
+        match result_receiver.await {
+            Ok(result) => result,
+            // The writer task was dropped without being run (e.g. because the
+            // channel's LocalSet was dropped)
+            Err(_) => Err(FrameSendError::ChannelClosed),
+        }
+    }
+
+    /**
+     * The writer task's queue of commands, spawning the writer task if this
+     * is the first time it's needed.
+     */
+    fn commands(&self) -> &mpsc::UnboundedSender<WriterCommand> {
+        self.write_queue.sender.get_or_init(|| {
+            let (commands, queued_commands) = mpsc::unbounded_channel();
+            // The FrameWriter is only missing if with_executor() or
+            // with_write_coalescing() split this FrameSender's WriteQueue off
+            // from a clone's, in which case the commands are dropped (and this
+            // FrameSender acts as though it's closed).
+            if let Some(writer) = self.writer.lock().unwrap().take() {
+                let write_coalescing = self.write_queue.write_coalescing;
+                self.write_queue.executor.spawn(writer.run(queued_commands, write_coalescing));
+            }
+            commands
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WeakFrameSender {
+    buffer_pool_stats: SharedBufferPoolStats,
+    bytes_sent: Arc<AtomicU64>,
+    capture: Arc<Mutex<Option<capture::FrameCapture>>>,
+    framing_version: frame::FramingVersion,
+    interceptors: FrameInterceptors,
+    max_payload_frame_size: usize,
+    write_queue: Weak<WriteQueue>,
+    writer: Weak<Mutex<Option<FrameWriter>>>,
+}
+impl WeakFrameSender {
+    /**
+     * See FrameSender::buffer_pool_stats(). These stay readable once every
+     * FrameSender has been dropped.
+     */
+    pub fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool_stats.get()
+    }
+
+    pub fn upgrade(&self) -> Option<FrameSender> {
+        let (write_queue, writer) = match (self.write_queue.upgrade(), self.writer.upgrade()) {
+            (Some(write_queue), Some(writer)) => (write_queue, writer),
+            _ => return None,
+        };
+        Some(FrameSender {
+            buffer_pool_stats: self.buffer_pool_stats.clone(),
+            bytes_sent: self.bytes_sent.clone(),
+            capture: self.capture.clone(),
+            framing_version: self.framing_version,
+            interceptors: self.interceptors.clone(),
+            max_payload_frame_size: self.max_payload_frame_size,
+            write_queue,
+            writer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod frame_sender_tests {
+    use hyper::body::HttpBody;
+
+    use super::*;
+    use crate::common::frame::Decoder;
+
+    #[tokio::test]
+    async fn frames_sent_together_are_coalesced_into_one_write() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            FrameInterceptors::new(),
+        );
+
+        // A TubeId that's too large for V1 only fails its own send
+        let sends = [1, 70000, 3].map(|tube_id| {
+            frame_sender.send(frame::Frame::PayloadAck { tube_id, ack_id: 1 })
+        });
+        let (results, chunk) = futures::join!(futures::future::join_all(sends), body.data());
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(FrameSendError::FrameEncodeError(_))));
+        assert!(results[2].is_ok());
+
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V1);
+        let frames = decoder.decode_bytes(chunk.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 1 },
+            frame::Frame::PayloadAck { tube_id: 3, ack_id: 1 },
+        ]);
+        assert_eq!(frame_sender.bytes_sent(), 14);
+    }
+
+    #[tokio::test]
+    async fn close_is_ordered_after_the_frames_queued_before_it() {
+        let (body_sender, body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V2,
+            FrameInterceptors::new(),
+        );
+
+        let (send_result, (), received) = futures::join!(
+            frame_sender.send(frame::Frame::Heartbeat),
+            frame_sender.close(),
+            hyper::body::to_bytes(body),
+        );
+        assert!(send_result.is_ok());
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V2);
+        let frames = decoder.decode_bytes(received.unwrap()).unwrap();
+        assert_eq!(Vec::from(frames), vec![frame::Frame::Heartbeat]);
+        assert!(matches!(
+            frame_sender.send(frame::Frame::Heartbeat).await,
+            Err(FrameSendError::ChannelClosed),
+        ));
+    }
+
+    #[tokio::test]
+    async fn frames_are_written_on_their_own_without_coalescing() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V2,
+            FrameInterceptors::new(),
+        ).with_write_coalescing(WriteCoalescing {
+            max_chunk_size: 0,
+            max_delay: Duration::ZERO,
+        });
+
+        let frame_sender2 = frame_sender.clone();
+        let sending = tokio::spawn(async move {
+            let sends = (0..3).map(|_| frame_sender2.send(frame::Frame::Heartbeat));
+            futures::future::join_all(sends).await
+        });
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V2);
+        for _ in 0..3 {
+            let frames = decoder.decode_bytes(body.data().await.unwrap().unwrap()).unwrap();
+            assert_eq!(Vec::from(frames), vec![frame::Frame::Heartbeat]);
+        }
+        assert!(sending.await.unwrap().into_iter().all(|result| result.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn tubes_take_turns_writing_their_queued_frames() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V2,
+            FrameInterceptors::new(),
+        ).with_write_coalescing(WriteCoalescing {
+            max_chunk_size: 0,
+            max_delay: Duration::ZERO,
+        });
+
+        // Tube 1 queues all of its acks before Tube 3 queues its one
+        let frame_sender2 = frame_sender.clone();
+        let sending = tokio::spawn(async move {
+            let sends = [(1, 1), (1, 2), (1, 3), (3, 1)].map(|(tube_id, ack_id)| {
+                frame_sender2.send(frame::Frame::PayloadAck { tube_id, ack_id })
+            });
+            futures::future::join_all(sends).await
+        });
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V2);
+        let mut written = vec![];
+        for _ in 0..4 {
+            let frames = decoder.decode_bytes(body.data().await.unwrap().unwrap()).unwrap();
+            written.extend(Vec::from(frames));
+        }
+        assert_eq!(written, [(1, 1), (3, 1), (1, 2), (1, 3)].map(|(tube_id, ack_id)| {
+            frame::Frame::PayloadAck { tube_id, ack_id }
+        }));
+        assert!(sending.await.unwrap().into_iter().all(|result| result.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn batches_for_a_tube_take_turns_a_frame_at_a_time() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let frame_sender = FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            FrameInterceptors::new(),
+        ).with_write_coalescing(WriteCoalescing {
+            max_chunk_size: 0,
+            max_delay: Duration::ZERO,
+        });
+
+        // Tube 1 queues a batch of acks before Tube 3 queues its one, and 
+        // Tube 5 queues a batch with an ack that's too large for V1
+        let frame_sender2 = frame_sender.clone();
+        let sending = tokio::spawn(async move {
+            let payload_acks = |acks: &[(u32, u16)]| acks.iter()
+                .map(|&(tube_id, ack_id)| frame::Frame::PayloadAck { tube_id, ack_id })
+                .collect::<Vec<_>>();
+            futures::join!(
+                frame_sender2.send_batch(payload_acks(&[(1, 1), (1, 2), (1, 3)])),
+                frame_sender2.send_batch(payload_acks(&[(3, 1)])),
+                frame_sender2.send_batch(payload_acks(&[(5, 1), (5, 0x8000)])),
+            )
+        });
+        let mut decoder = Decoder::new_with_version(frame::FramingVersion::V1);
+        let mut written = vec![];
+        for _ in 0..4 {
+            let frames = decoder.decode_bytes(body.data().await.unwrap().unwrap()).unwrap();
+            written.extend(Vec::from(frames));
+        }
+        assert_eq!(written, [(1, 1), (3, 1), (1, 2), (1, 3)].map(|(tube_id, ack_id)| {
+            frame::Frame::PayloadAck { tube_id, ack_id }
+        }));
+        let (tube1_result, tube3_result, tube5_result) = sending.await.unwrap();
+        assert!(tube1_result.is_ok());
+        assert!(tube3_result.is_ok());
+        assert!(matches!(tube5_result, Err(FrameSendError::FrameEncodeError(_))));
+    }
+}
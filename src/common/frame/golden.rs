@@ -0,0 +1,202 @@
+use serde_json::Value;
+
+use crate::common::hex::decode_hex;
+use super::decode;
+use super::encode;
+use super::frame;
+use super::json;
+
+const GOLDEN_VECTORS_JSON: &str = include_str!("../../../golden/wire_vectors.json");
+
+#[derive(Debug)]
+pub enum GoldenVectorError {
+    DecodeError(decode::FrameDecodeError),
+    DecodedFrameMismatch {
+        name: String,
+        expected: frame::Frame,
+        actual: Vec<frame::Frame>,
+    },
+    EncodeError(encode::FrameEncodeError),
+    EncodedBytesMismatch {
+        name: String,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    InvalidJson(serde_json::Error),
+    InvalidVector {
+        index: usize,
+        detail: String,
+    },
+}
+
+/**
+ * A frame paired with the exact bytes it is encoded as using a given
+ * FramingVersion.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenVector {
+    pub name: String,
+    pub framing_version: frame::FramingVersion,
+    pub frame: frame::Frame,
+    pub bytes: Vec<u8>,
+}
+impl GoldenVector {
+    /**
+     * Checks that bytes produced by an implementation when encoding this
+     * vector's frame match the golden bytes exactly.
+     */
+    pub fn verify_encoded(&self, bytes: &[u8]) -> Result<(), GoldenVectorError> {
+        if bytes != self.bytes.as_slice() {
+            return Err(GoldenVectorError::EncodedBytesMismatch {
+                name: self.name.clone(),
+                expected: self.bytes.clone(),
+                actual: bytes.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Checks that the frames an implementation decoded from this vector's
+     * bytes are exactly this vector's frame.
+     */
+    pub fn verify_decoded(&self, frames: &[frame::Frame]) -> Result<(), GoldenVectorError> {
+        if frames != [self.frame.clone()] {
+            return Err(GoldenVectorError::DecodedFrameMismatch {
+                name: self.name.clone(),
+                expected: self.frame.clone(),
+                actual: frames.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Checks this crate's own Encoder and Decoder against the vector.
+     */
+    pub fn verify(&self) -> Result<(), GoldenVectorError> {
+        let encoded = match encode::encode_frame_with_version(
+            self.frame.clone(),
+            self.framing_version,
+        ) {
+            Ok(encoded) => encoded,
+            Err(e) => return Err(GoldenVectorError::EncodeError(e)),
+        };
+        self.verify_encoded(&encoded)?;
+
+        let mut decoder = decode::Decoder::new_with_version(self.framing_version);
+        let frames = match decoder.decode(self.bytes.clone()) {
+            Ok(frames) => Vec::from(frames),
+            Err(e) => return Err(GoldenVectorError::DecodeError(e)),
+        };
+        self.verify_decoded(&frames)
+    }
+}
+
+/**
+ * The machine-readable golden vectors (golden/wire_vectors.json) that
+ * alternate implementations can use to check byte-level interoperability with
+ * this crate.
+ */
+pub fn golden_vectors_json() -> &'static str {
+    GOLDEN_VECTORS_JSON
+}
+
+pub fn golden_vectors() -> Result<Vec<GoldenVector>, GoldenVectorError> {
+    parse_golden_vectors(GOLDEN_VECTORS_JSON)
+}
+
+/**
+ * Parses a set of vectors in the golden/wire_vectors.json format.
+ */
+pub fn parse_golden_vectors(json: &str) -> Result<Vec<GoldenVector>, GoldenVectorError> {
+    let root: Value = match serde_json::from_str(json) {
+        Ok(root) => root,
+        Err(e) => return Err(GoldenVectorError::InvalidJson(e)),
+    };
+    let vectors = match root.get("vectors").and_then(Value::as_array) {
+        Some(vectors) => vectors,
+        None => return Err(GoldenVectorError::InvalidVector {
+            index: 0,
+            detail: "missing `vectors` array".to_string(),
+        }),
+    };
+
+    let mut golden_vectors = Vec::with_capacity(vectors.len());
+    for (index, vector) in vectors.iter().enumerate() {
+        match parse_golden_vector(vector) {
+            Ok(golden_vector) => golden_vectors.push(golden_vector),
+            Err(detail) => return Err(GoldenVectorError::InvalidVector { index, detail }),
+        }
+    }
+    Ok(golden_vectors)
+}
+
+fn parse_golden_vector(vector: &Value) -> Result<GoldenVector, String> {
+    let name = match vector.get("name").and_then(Value::as_str) {
+        Some(name) => name.to_string(),
+        None => return Err("missing `name`".to_string()),
+    };
+    let framing_version = match vector.get("framing_version").and_then(Value::as_u64) {
+        Some(1) => frame::FramingVersion::V1,
+        Some(2) => frame::FramingVersion::V2,
+        Some(3) => frame::FramingVersion::V3,
+        Some(4) => frame::FramingVersion::V4,
+        Some(5) => frame::FramingVersion::V5,
+        _ => return Err(format!("{}: invalid `framing_version`", name)),
+    };
+    let frame = match vector.get("frame").map(json::frame_from_json) {
+        Some(Ok(frame)) => frame,
+        Some(Err(e)) => return Err(format!("{}: invalid `frame`: {}", name, e)),
+        None => return Err(format!("{}: missing `frame`", name)),
+    };
+    let bytes = match vector.get("bytes").and_then(Value::as_str).and_then(decode_hex) {
+        Some(bytes) => bytes,
+        None => return Err(format!("{}: invalid `bytes`", name)),
+    };
+    Ok(GoldenVector {
+        name,
+        framing_version,
+        frame,
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod golden_tests {
+    use super::*;
+
+    #[test]
+    fn crate_matches_all_golden_vectors() {
+        let vectors = golden_vectors().unwrap();
+        assert!(!vectors.is_empty());
+        for vector in vectors {
+            if let Err(e) = vector.verify() {
+                panic!("Golden vector mismatch: {:?}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn verify_encoded_reports_mismatched_bytes() {
+        let vector = golden_vectors().unwrap().remove(0);
+        let mut bytes = vector.bytes.clone();
+        bytes.push(0);
+        match vector.verify_encoded(&bytes) {
+            Err(GoldenVectorError::EncodedBytesMismatch { name, actual, .. }) => {
+                assert_eq!(name, vector.name);
+                assert_eq!(actual, bytes);
+            },
+            unexpected => panic!("Unexpected verify result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_invalid_vectors() {
+        let json = r#"{"vectors": [{"name": "bad", "framing_version": 9, "frame": "Drain", "bytes": ""}]}"#;
+        match parse_golden_vectors(json) {
+            Err(GoldenVectorError::InvalidVector { index: 0, .. }) => (),
+            unexpected => panic!("Unexpected parse result: {:?}", unexpected),
+        }
+    }
+}
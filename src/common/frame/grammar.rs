@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::common::tube;
+use super::frame;
+use super::header_block;
+
+/**
+ * The version of the frame_grammar_json() document format. It only changes
+ * when the shape of the document changes, not when frame types or fields are
+ * added to the wire format.
+ */
+pub const FRAME_GRAMMAR_FORMAT_VERSION: u32 = 1;
+
+/**
+ * How a field is encoded on the wire. Fixed-width integers are big-endian and
+ * varints are unsigned LEB128. The Utf8, Bytes, JsonHeaders and HeaderBlock
+ * encodings occupy the rest of the frame body.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldEncoding {
+    U8,
+    U16,
+    U32,
+    U64,
+    Varint,
+    Utf8,
+    Bytes,
+    JsonHeaders,
+    HeaderBlock,
+}
+impl FieldEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldEncoding::U8 => "u8",
+            FieldEncoding::U16 => "u16",
+            FieldEncoding::U32 => "u32",
+            FieldEncoding::U64 => "u64",
+            FieldEncoding::Varint => "varint",
+            FieldEncoding::Utf8 => "utf8",
+            FieldEncoding::Bytes => "bytes",
+            FieldEncoding::JsonHeaders => "json_headers",
+            FieldEncoding::HeaderBlock => "header_block",
+        }
+    }
+
+    /**
+     * The number of bytes the field occupies, if that doesn't depend on its
+     * value.
+     */
+    pub fn fixed_len(&self) -> Option<usize> {
+        match self {
+            FieldEncoding::U8 => Some(1),
+            FieldEncoding::U16 => Some(2),
+            FieldEncoding::U32 => Some(4),
+            FieldEncoding::U64 => Some(8),
+            FieldEncoding::Varint |
+                FieldEncoding::Utf8 |
+                FieldEncoding::Bytes |
+                FieldEncoding::JsonHeaders |
+                FieldEncoding::HeaderBlock => None,
+        }
+    }
+
+    pub fn is_rest_of_body(&self) -> bool {
+        match self {
+            FieldEncoding::Utf8 |
+                FieldEncoding::Bytes |
+                FieldEncoding::JsonHeaders |
+                FieldEncoding::HeaderBlock => true,
+            FieldEncoding::U8 |
+                FieldEncoding::U16 |
+                FieldEncoding::U32 |
+                FieldEncoding::U64 |
+                FieldEncoding::Varint => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldPresence {
+    Always,
+    /**
+     * The field is only present if the frame body has bytes left.
+     */
+    IfRemaining,
+    /**
+     * The field is only present if the named (earlier) field has the given
+     * value.
+     */
+    IfFieldEquals(&'static str, u64),
+    /**
+     * Consecutive Repeated fields form a group that repeats until the end of
+     * the frame body.
+     */
+    Repeated,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub encoding: FieldEncoding,
+    pub presence: FieldPresence,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameTypeSpec {
+    /**
+     * The name of the Frame variant the frame type decodes into. Payloads
+     * (and PartialPayloads) with a checksum are named PayloadWithChecksum 
+     * (and PartialPayloadWithChecksum).
+     */
+    pub name: &'static str,
+    pub frame_type: u8,
+    pub fields: Vec<FieldSpec>,
+}
+
+/**
+ * A machine-readable description of the wire format of every frame for a
+ * given FramingVersion, for alternate implementations and conformance
+ * harnesses to generate or check their codecs against. The FrameTypes come
+ * from the same constants the codec uses, and the field layouts are checked
+ * against the golden vectors.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameGrammar {
+    pub framing_version: frame::FramingVersion,
+    /**
+     * The fields every frame begins with. FrameBodyByteLength is the length
+     * of the body that follows (the fields of the frame's FrameTypeSpec).
+     */
+    pub frame_header: Vec<FieldSpec>,
+    pub frame_types: Vec<FrameTypeSpec>,
+    /**
+     * The body of an ExtensionFrame (whose FrameType is anywhere in
+     * MIN_EXTENSION_FRAMETYPE..=MAX_EXTENSION_FRAMETYPE).
+     */
+    pub extension_frame_fields: Vec<FieldSpec>,
+}
+impl FrameGrammar {
+    pub fn new(framing_version: frame::FramingVersion) -> Self {
+        use FieldEncoding::*;
+        use FieldPresence::*;
+
+        let is_v1 = framing_version == frame::FramingVersion::V1;
+        let id = if is_v1 { U16 } else { Varint };
+        let field = |name, encoding, presence| FieldSpec { name, encoding, presence };
+        let application_defined_abort: u8 =
+            frame::AbortReason::ApplicationDefined { code: 0, message: None }.into();
+        let if_application_defined =
+            IfFieldEquals("AbortReason", application_defined_abort as u64);
+
+        let payload_fields = |with_checksum: bool| {
+            let mut fields = vec![
+                field("TubeId", id, Always),
+                // In V1 frames, the high bit of AckField is AckRequested and
+                // the low 15 bits are the AckId. In V2 frames AckField is 0
+                // when no ack is requested and (AckId + 1) otherwise.
+                field("AckField", id, Always),
+            ];
+            if with_checksum {
+                fields.push(field("Crc32", U32, Always));
+            }
+            fields.push(field("Data", Bytes, Always));
+            fields
+        };
+
+        let partial_payload_fields = |with_checksum: bool| {
+            let mut fields = vec![field("TubeId", id, Always)];
+            if with_checksum {
+                fields.push(field("Crc32", U32, Always));
+            }
+            fields.push(field("Data", Bytes, Always));
+            fields
+        };
+
+        let spec = |sample: frame::Frame, name, fields| FrameTypeSpec {
+            name,
+            frame_type: sample.frame_type(),
+            fields,
+        };
+        let frame_types = vec![
+            spec(frame::Frame::ClientHasFinishedSending { tube_id: 0 },
+                "ClientHasFinishedSending", vec![
+                field("TubeId", id, Always),
+            ]),
+            spec(frame::Frame::Drain {
+                reason: frame::DrainReason::Unspecified,
+                deadline_unix_millis: None,
+            }, "Drain", vec![
+                field("DrainReason", U8, IfRemaining),
+                field("DeadlineUnixMillis", if is_v1 { U64 } else { Varint }, IfRemaining),
+            ]),
+            spec(frame::Frame::NewTube { tube_id: 0, headers: HashMap::new() },
+                "NewTube", vec![
+                field("TubeId", id, Always),
+                match framing_version {
+                    frame::FramingVersion::V1 | frame::FramingVersion::V2 =>
+                        field("Headers", JsonHeaders, Always),
+                    frame::FramingVersion::V3 | 
+                        frame::FramingVersion::V4 | 
+                        frame::FramingVersion::V5 =>
+                        field("Headers", HeaderBlock, Always),
+                },
+            ]),
+            spec(frame::Frame::Payload {
+                tube_id: 0,
+                ack_id: None,
+                checksum: None,
+                data: bytes::Bytes::new(),
+            }, "Payload", payload_fields(false)),
+            spec(frame::Frame::PayloadAck { tube_id: 0, ack_id: 0 }, "PayloadAck", vec![
+                field("TubeId", id, Always),
+                // In V1 frames the high bit is reserved
+                field("AckId", id, Always),
+            ]),
+            spec(frame::Frame::ServerHasFinishedSending { tube_id: 0 },
+                "ServerHasFinishedSending", vec![
+                field("TubeId", id, Always),
+            ]),
+            spec(frame::Frame::Abort {
+                tube_id: 0,
+                reason: frame::AbortReason::ApplicationAbort,
+            }, "Abort", vec![
+                field("TubeId", id, Always),
+                field("AbortReason", U8, Always),
+                field("Code", if is_v1 { U32 } else { Varint }, if_application_defined),
+                field("Message", Utf8, if_application_defined),
+            ]),
+            spec(frame::Frame::AbortAck { tube_id: 0 }, "AbortAck", vec![
+                field("TubeId", id, Always),
+            ]),
+            spec(frame::Frame::NewTubeAck { tube_id: 0 }, "NewTubeAck", vec![
+                field("TubeId", id, Always),
+            ]),
+            spec(frame::Frame::Heartbeat, "Heartbeat", vec![]),
+            spec(frame::Frame::HeartbeatAck, "HeartbeatAck", vec![]),
+            spec(frame::Frame::Payload {
+                tube_id: 0,
+                ack_id: None,
+                checksum: Some(0),
+                data: bytes::Bytes::new(),
+            }, "PayloadWithChecksum", payload_fields(true)),
+            spec(frame::Frame::Error {
+                tube_id: None,
+                code: frame::ErrorCode::BadHeader,
+                detail: String::new(),
+            }, "Error", if is_v1 {
+                vec![
+                    field("HasTubeId", U8, Always),
+                    field("TubeId", U16, Always),
+                    field("ErrorCode", U16, Always),
+                    field("Detail", Utf8, Always),
+                ]
+            } else {
+                vec![
+                    // 0 when no TubeId is specified, (TubeId + 1) otherwise
+                    field("TubeIdField", Varint, Always),
+                    field("ErrorCode", Varint, Always),
+                    field("Detail", Utf8, Always),
+                ]
+            }),
+            spec(frame::Frame::SequencedPayload {
+                tube_id: 0,
+                sequence_number: 0,
+                data: bytes::Bytes::new(),
+            }, "SequencedPayload", vec![
+                field("TubeId", id, Always),
+                field("SequenceNumber", if is_v1 { U64 } else { Varint }, Always),
+                field("Data", Bytes, Always),
+            ]),
+            spec(frame::Frame::SelectiveAck { tube_id: 0, ranges: vec![] },
+                "SelectiveAck", if is_v1 {
+                vec![
+                    field("TubeId", U16, Always),
+                    field("RangeStart", U64, Repeated),
+                    field("RangeEnd", U64, Repeated),
+                ]
+            } else {
+                vec![
+                    field("TubeId", Varint, Always),
+                    field("RangeStart", Varint, Repeated),
+                    field("RangeLength", Varint, Repeated),
+                ]
+            }),
+            spec(frame::Frame::GoAway { last_tube_id: 0, reason: String::new() },
+                "GoAway", vec![
+                field("LastTubeId", id, Always),
+                field("Reason", Utf8, Always),
+            ]),
+            spec(frame::Frame::PartialPayload {
+                tube_id: 0,
+                checksum: None,
+                data: bytes::Bytes::new(),
+            }, "PartialPayload", partial_payload_fields(false)),
+            spec(frame::Frame::PartialPayload {
+                tube_id: 0,
+                checksum: Some(0),
+                data: bytes::Bytes::new(),
+            }, "PartialPayloadWithChecksum", partial_payload_fields(true)),
+        ];
+
+        FrameGrammar {
+            framing_version,
+            frame_header: vec![
+                field("FrameType", U8, Always),
+                field("FrameBodyByteLength", if is_v1 { U16 } else { Varint }, Always),
+            ],
+            frame_types,
+            extension_frame_fields: vec![
+                field("Payload", Bytes, Always),
+            ],
+        }
+    }
+
+    /**
+     * The layout of the frame body for a given FrameType, including
+     * extension FrameTypes.
+     */
+    pub fn fields_for_frame_type(&self, frame_type: u8) -> Option<&[FieldSpec]> {
+        if (frame::MIN_EXTENSION_FRAMETYPE..=frame::MAX_EXTENSION_FRAMETYPE)
+            .contains(&frame_type) {
+            return Some(&self.extension_frame_fields);
+        }
+        self.frame_types.iter()
+            .find(|spec| spec.frame_type == frame_type)
+            .map(|spec| spec.fields.as_slice())
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "framing_version": self.framing_version.header_value(),
+            "frame_header": fields_to_json(&self.frame_header),
+            "frame_types": self.frame_types.iter().map(|spec| json!({
+                "name": spec.name,
+                "frame_type": spec.frame_type,
+                "fields": fields_to_json(&spec.fields),
+            })).collect::<Vec<Value>>(),
+            "extension_frame_types": {
+                "min": frame::MIN_EXTENSION_FRAMETYPE,
+                "max": frame::MAX_EXTENSION_FRAMETYPE,
+                "fields": fields_to_json(&self.extension_frame_fields),
+            },
+        })
+    }
+}
+
+fn fields_to_json(fields: &[FieldSpec]) -> Vec<Value> {
+    fields.iter().map(|field| {
+        let presence = match field.presence {
+            FieldPresence::Always => json!("always"),
+            FieldPresence::IfRemaining => json!("if_remaining"),
+            FieldPresence::IfFieldEquals(name, value) =>
+                json!({"if_field_equals": {"field": name, "value": value}}),
+            FieldPresence::Repeated => json!("repeated"),
+        };
+        json!({
+            "name": field.name,
+            "encoding": field.encoding.name(),
+            "presence": presence,
+        })
+    }).collect()
+}
+
+/**
+ * A machine-readable description of the whole wire format: the FrameGrammar
+ * of every FramingVersion, the values of the enums carried in frames, the V3
+ * header block static table, and the headers peers use to negotiate
+ * FramingVersions and per-tube features. See FRAME_GRAMMAR_FORMAT_VERSION.
+ */
+pub fn frame_grammar_json() -> Value {
+    let framing_versions = [
+        frame::FramingVersion::V1,
+        frame::FramingVersion::V2,
+        frame::FramingVersion::V3,
+        frame::FramingVersion::V4,
+        frame::FramingVersion::V5,
+    ];
+
+    let abort_reasons = [
+        ("ApplicationAbort", frame::AbortReason::ApplicationAbort),
+        ("ApplicationError", frame::AbortReason::ApplicationError),
+        ("TransportErrorWhileSynchronizingTubeState",
+            frame::AbortReason::TransportErrorWhileSynchronizingTubeState),
+        ("ApplicationDefined",
+            frame::AbortReason::ApplicationDefined { code: 0, message: None }),
+        ("IdleTimeout", frame::AbortReason::IdleTimeout),
+        ("EventQueueOverflow", frame::AbortReason::EventQueueOverflow),
+        ("RateLimited", frame::AbortReason::RateLimited),
+        ("SlowConsumer", frame::AbortReason::SlowConsumer),
+        ("AckTimeout", frame::AbortReason::AckTimeout),
+        ("InvalidTubeEventTransition", frame::AbortReason::InvalidTubeEventTransition),
+    ];
+    let drain_reasons = [
+        ("Unspecified", frame::DrainReason::Unspecified),
+        ("Shutdown", frame::DrainReason::Shutdown),
+        ("Migrate", frame::DrainReason::Migrate),
+        ("Overloaded", frame::DrainReason::Overloaded),
+    ];
+    let error_codes = [
+        ("BadHeader", frame::ErrorCode::BadHeader),
+        ("OverLimit", frame::ErrorCode::OverLimit),
+        ("UnsupportedFeature", frame::ErrorCode::UnsupportedFeature),
+        ("ProtocolViolation", frame::ErrorCode::ProtocolViolation),
+        ("Draining", frame::ErrorCode::Draining),
+        ("PayloadChecksumMismatch", frame::ErrorCode::PayloadChecksumMismatch),
+    ];
+
+    json!({
+        "format_version": FRAME_GRAMMAR_FORMAT_VERSION,
+        "framing_versions": framing_versions.iter()
+            .map(|version| FrameGrammar::new(*version).to_json())
+            .collect::<Vec<Value>>(),
+        "enums": {
+            "AbortReason": abort_reasons.into_iter()
+                .map(|(name, reason)| json!({"name": name, "value": Into::<u8>::into(reason)}))
+                .collect::<Vec<Value>>(),
+            "DrainReason": drain_reasons.into_iter()
+                .map(|(name, reason)| json!({"name": name, "value": u8::from(reason)}))
+                .collect::<Vec<Value>>(),
+            "ErrorCode": error_codes.into_iter()
+                .map(|(name, code)| json!({"name": name, "value": u16::from(code)}))
+                .collect::<Vec<Value>>(),
+        },
+        "header_block_static_table": header_block::STATIC_TABLE.iter()
+            .enumerate()
+            .map(|(idx, (name, value))| json!({"index": idx + 1, "name": name, "value": value}))
+            .collect::<Vec<Value>>(),
+        "negotiation": {
+            "channel_headers": [
+                {
+                    "name": frame::FRAMING_VERSION_HEADER,
+                    "values": framing_versions.iter()
+                        .map(|version| version.header_value())
+                        .collect::<Vec<&str>>(),
+                },
+                {
+                    "name": frame::MAX_PAYLOAD_FRAME_SIZE_HEADER,
+                    "max_value": frame::MAX_PAYLOAD_FRAME_SIZE,
+                },
+            ],
+            "tube_headers": [
+                {"name": tube::PAYLOAD_CHECKSUM_HEADER, "value": tube::PAYLOAD_CHECKSUM_HEADER_CRC32},
+                {"name": tube::CUMULATIVE_ACKS_HEADER, "value": "1"},
+                {"name": tube::RECEIVE_ONLY_HEADER, "value": "1"},
+            ],
+        },
+    })
+}
+
+#[cfg(test)]
+mod grammar_tests {
+    use super::*;
+    use crate::common::frame::golden_vectors;
+
+    fn read_field(encoding: FieldEncoding, body: &mut &[u8]) -> Result<u64, String> {
+        if encoding.is_rest_of_body() {
+            *body = &[];
+            return Ok(0);
+        }
+        let len = match encoding.fixed_len() {
+            Some(len) => len,
+            None => match body.iter().position(|byte| byte & 0x80 == 0) {
+                Some(pos) => pos + 1,
+                None => return Err("unterminated varint".to_string()),
+            },
+        };
+        if body.len() < len {
+            return Err(format!("{} needs {} bytes, {} left", encoding.name(), len, body.len()));
+        }
+        let (field, rest) = body.split_at(len);
+        *body = rest;
+        let value = match encoding {
+            FieldEncoding::Varint => field.iter().rev()
+                .fold(0, |value, byte| (value << 7) | (byte & 0x7F) as u64),
+            _ => field.iter().fold(0, |value, byte| (value << 8) | *byte as u64),
+        };
+        Ok(value)
+    }
+
+    // Parses an encoded frame using nothing but the grammar.
+    fn walk_frame(grammar: &FrameGrammar, mut bytes: &[u8]) -> Result<(), String> {
+        let frame_type = read_field(grammar.frame_header[0].encoding, &mut bytes)?;
+        let body_len = read_field(grammar.frame_header[1].encoding, &mut bytes)?;
+        if bytes.len() as u64 != body_len {
+            return Err(format!("body is {} bytes, header says {}", bytes.len(), body_len));
+        }
+        let fields = match grammar.fields_for_frame_type(frame_type as u8) {
+            Some(fields) => fields,
+            None => return Err(format!("unknown frame type {}", frame_type)),
+        };
+
+        let mut values = HashMap::new();
+        let repeated = fields.iter()
+            .filter(|field| field.presence == FieldPresence::Repeated)
+            .collect::<Vec<_>>();
+        for field in fields {
+            let present = match field.presence {
+                FieldPresence::Always => true,
+                FieldPresence::IfRemaining => !bytes.is_empty(),
+                FieldPresence::IfFieldEquals(name, value) => values.get(name) == Some(&value),
+                FieldPresence::Repeated => continue,
+            };
+            if present {
+                values.insert(field.name, read_field(field.encoding, &mut bytes)?);
+            }
+        }
+        while !bytes.is_empty() && !repeated.is_empty() {
+            for field in &repeated {
+                read_field(field.encoding, &mut bytes)?;
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(format!("{} bytes left over", bytes.len()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn grammar_parses_every_golden_vector() {
+        for vector in golden_vectors().unwrap() {
+            let grammar = FrameGrammar::new(vector.framing_version);
+            if let Err(e) = walk_frame(&grammar, &vector.bytes) {
+                panic!("Grammar mismatch for {}: {}", vector.name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn grammar_covers_every_frame_type() {
+        let grammar = FrameGrammar::new(frame::FramingVersion::LATEST);
+        for frame_type in 0..frame::MIN_EXTENSION_FRAMETYPE {
+            let frame_type_known = grammar.fields_for_frame_type(frame_type).is_some();
+            let frame_type_used = frame_type <= frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE;
+            assert_eq!(frame_type_known, frame_type_used, "FrameType {}", frame_type);
+        }
+        assert_eq!(
+            grammar.frame_types.len(),
+            frame::PARTIAL_PAYLOAD_WITH_CHECKSUM_FRAMETYPE as usize + 1,
+        );
+    }
+
+    #[test]
+    fn grammar_json_describes_all_framing_versions() {
+        let grammar = frame_grammar_json();
+        assert_eq!(grammar["format_version"], FRAME_GRAMMAR_FORMAT_VERSION);
+        let versions = grammar["framing_versions"].as_array().unwrap();
+        assert_eq!(versions.len(), 5);
+        assert_eq!(versions[4]["framing_version"], "5");
+        assert_eq!(
+            versions[0]["frame_header"][1],
+            json!({"name": "FrameBodyByteLength", "encoding": "u16", "presence": "always"}),
+        );
+        assert_eq!(
+            grammar["negotiation"]["channel_headers"][0]["name"],
+            frame::FRAMING_VERSION_HEADER,
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use super::varint;
+
+/**
+ * Header fields that are common enough to be worth referencing by index in V3
+ * header blocks (see write_header_block). Entries with an empty value are
+ * typically only used to abbreviate the name.
+ *
+ * This table is part of the wire format, so entries may only ever be appended.
+ */
+pub(in super) const STATIC_TABLE: &[(&str, &str)] = &[
+    ("tubez-payload-checksum", "crc32"),
+    ("tubez-receive-only", "1"),
+    ("authorization", ""),
+    ("content-type", ""),
+    ("content-type", "application/json"),
+    ("content-type", "application/octet-stream"),
+    ("content-type", "application/protobuf"),
+    ("traceparent", ""),
+    ("tracestate", ""),
+    ("user-agent", ""),
+    ("x-request-id", ""),
+];
+
+#[derive(Debug)]
+pub enum HeaderBlockDecodeError {
+    InvalidStaticTableIndex(u64),
+    InvalidVarint,
+    NameUtf8Error(std::str::Utf8Error),
+    TrailingData,
+    Truncated,
+}
+
+fn static_table_index(name: &str, value: &[u8]) -> Option<(usize, bool)> {
+    let mut name_match = None;
+    for (idx, (entry_name, entry_value)) in STATIC_TABLE.iter().enumerate() {
+        if *entry_name != name {
+            continue;
+        }
+        if entry_value.as_bytes() == value {
+            return Some((idx + 1, true));
+        }
+        name_match.get_or_insert((idx + 1, false));
+    }
+    name_match
+}
+
+/**
+ * Encodes headers as a V3 header block:
+ *
+ *   +---------------------+-------------+-----+-------------+
+ *   |  NumFields(varint)  |  Field(*)   | ... |  Field(*)   |
+ *   +---------------------+-------------+-----+-------------+
+ *
+ * Each Field starts with a FieldPrefix(varint). A FieldPrefix of 0 is
+ * followed by a literal name and a literal value. Otherwise the upper bits of
+ * the FieldPrefix are a 1-based index into the STATIC_TABLE: if the low bit
+ * is 0 the field is the entry's name and value, and if it is 1 the field is
+ * the entry's name followed by a literal value. Literal names and values are
+ * each a Length(varint) followed by that many bytes.
+ */
+pub fn write_header_block(headers: &HashMap<String, Vec<u8>>, out: &mut Vec<u8>) {
+    varint::write_varint(headers.len() as u64, out);
+    for (name, value) in headers {
+        match static_table_index(name, value) {
+            Some((idx, true)) =>
+                varint::write_varint((idx as u64) << 1, out),
+            Some((idx, false)) => {
+                varint::write_varint(((idx as u64) << 1) | 1, out);
+                write_literal(value, out);
+            },
+            None => {
+                varint::write_varint(0, out);
+                write_literal(name.as_bytes(), out);
+                write_literal(value, out);
+            },
+        }
+    }
+}
+
+fn write_literal(literal: &[u8], out: &mut Vec<u8>) {
+    varint::write_varint(literal.len() as u64, out);
+    out.extend_from_slice(literal);
+}
+
+/**
+ * The number of fields a header block claims to contain, so that limits can
+ * be checked before the block is decoded.
+ */
+pub fn header_block_num_fields(data: &[u8]) -> Result<u64, HeaderBlockDecodeError> {
+    let mut offset = 0;
+    read_varint(data, &mut offset)
+}
+
+pub fn read_header_block(
+    data: &[u8],
+) -> Result<HashMap<String, Vec<u8>>, HeaderBlockDecodeError> {
+    let mut offset = 0;
+    let num_fields = read_varint(data, &mut offset)?;
+    // Each field occupies at least 1 byte, so a block can't actually hold more
+    // fields than it has bytes.
+    let mut headers = HashMap::with_capacity(
+        (num_fields as usize).min(data.len() - offset)
+    );
+    for _ in 0..num_fields {
+        let field_prefix = read_varint(data, &mut offset)?;
+        let (name, value) = if field_prefix == 0 {
+            let name = read_literal(data, &mut offset)?;
+            let name = match std::str::from_utf8(name) {
+                Ok(name) => name.to_string(),
+                Err(utf8_err) => return Err(HeaderBlockDecodeError::NameUtf8Error(utf8_err)),
+            };
+            (name, read_literal(data, &mut offset)?.to_vec())
+        } else {
+            let idx = field_prefix >> 1;
+            let entry = idx.checked_sub(1).and_then(|idx| STATIC_TABLE.get(idx as usize));
+            let (entry_name, entry_value) = match entry {
+                Some(entry) => entry,
+                None => return Err(HeaderBlockDecodeError::InvalidStaticTableIndex(idx)),
+            };
+            let value = if field_prefix & 1 == 1 {
+                read_literal(data, &mut offset)?.to_vec()
+            } else {
+                entry_value.as_bytes().to_vec()
+            };
+            (entry_name.to_string(), value)
+        };
+        headers.insert(name, value);
+    }
+
+    if offset != data.len() {
+        return Err(HeaderBlockDecodeError::TrailingData);
+    }
+    Ok(headers)
+}
+
+fn read_varint(data: &[u8], offset: &mut usize) -> Result<u64, HeaderBlockDecodeError> {
+    match varint::read_varint(data, *offset) {
+        Ok(Some((value, len))) => {
+            *offset += len;
+            Ok(value)
+        },
+        Ok(None) => Err(HeaderBlockDecodeError::Truncated),
+        Err(varint::VarintDecodeError::Overflow) => Err(HeaderBlockDecodeError::InvalidVarint),
+    }
+}
+
+fn read_literal<'a>(
+    data: &'a [u8],
+    offset: &mut usize,
+) -> Result<&'a [u8], HeaderBlockDecodeError> {
+    let len = read_varint(data, offset)?;
+    let end = match usize::try_from(len).ok().and_then(|len| offset.checked_add(len)) {
+        Some(end) if end <= data.len() => end,
+        _ => return Err(HeaderBlockDecodeError::Truncated),
+    };
+    let literal = &data[*offset..end];
+    *offset = end;
+    Ok(literal)
+}
+
+#[cfg(test)]
+mod header_block_tests {
+    use super::*;
+
+    fn roundtrip(headers: HashMap<String, Vec<u8>>) -> usize {
+        let mut block = vec![];
+        write_header_block(&headers, &mut block);
+        assert_eq!(header_block_num_fields(&block).unwrap(), headers.len() as u64);
+        assert_eq!(read_header_block(&block).unwrap(), headers);
+        block.len()
+    }
+
+    #[test]
+    fn binary_values_roundtrip() {
+        roundtrip(HashMap::from([
+            ("metadata".to_string(), vec![0, 159, 255, 10]),
+            ("empty".to_string(), vec![]),
+        ]));
+    }
+
+    #[test]
+    fn static_table_entries_are_abbreviated() {
+        let full_match_len = roundtrip(HashMap::from([
+            ("tubez-receive-only".to_string(), b"1".to_vec()),
+        ]));
+        // NumFields + FieldPrefix
+        assert_eq!(full_match_len, 2);
+
+        let name_match_len = roundtrip(HashMap::from([
+            ("content-type".to_string(), b"text/plain".to_vec()),
+        ]));
+        // NumFields + FieldPrefix + Length + "text/plain"
+        assert_eq!(name_match_len, 1 + 1 + 1 + 10);
+    }
+
+    #[test]
+    fn errors_on_unknown_static_table_index() {
+        let mut block = vec![];
+        varint::write_varint(1, &mut block);
+        varint::write_varint(((STATIC_TABLE.len() as u64) + 1) << 1, &mut block);
+        match read_header_block(&block) {
+            Err(HeaderBlockDecodeError::InvalidStaticTableIndex(idx)) =>
+                assert_eq!(idx, (STATIC_TABLE.len() as u64) + 1),
+            unexpected => panic!("Unexpected decode result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_truncated_literal() {
+        let mut block = vec![];
+        write_header_block(&HashMap::from([
+            ("metadata".to_string(), vec![1, 2, 3]),
+        ]), &mut block);
+        block.pop();
+        match read_header_block(&block) {
+            Err(HeaderBlockDecodeError::Truncated) => (),
+            unexpected => panic!("Unexpected decode result: {:?}", unexpected),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use super::frame::Frame;
+
+pub enum InterceptedFrame {
+    /**
+     * Continue sending the (possibly rewritten) frame.
+     */
+    Forward(Frame),
+
+    /**
+     * Do not send the frame. The String describes why the frame was vetoed 
+     * and is surfaced to whichever code attempted to send it.
+     */
+    Veto(String),
+}
+
+/**
+ * An interceptor is given the opportunity to inspect, rewrite, or veto every 
+ * frame before it is sent to the peer (e.g. to strip sensitive headers, 
+ * enforce size caps, or stamp tenant ids).
+ *
+ * Interceptors operate on the structured Frame *before* it is encoded. This 
+ * means that any byte-level transforms on the encoded frame (such as payload 
+ * checksums) are always computed over the frame as rewritten by interceptors.
+ *
+ * Note that interceptors see every outgoing frame, including control frames 
+ * like PayloadAck and AbortAck. Vetoing control frames will likely leave the 
+ * peers disagreeing on the state of a Tube.
+ */
+pub trait FrameInterceptor: Send + Sync {
+    fn intercept(&self, frame: Frame) -> InterceptedFrame;
+}
+impl<F> FrameInterceptor for F 
+    where F: Fn(Frame) -> InterceptedFrame + Send + Sync {
+    fn intercept(&self, frame: Frame) -> InterceptedFrame {
+        self(frame)
+    }
+}
+
+/**
+ * An ordered chain of FrameInterceptors. Interceptors run in the order they 
+ * were added, each receiving the frame as emitted by the previous one. The 
+ * first interceptor to veto a frame ends the chain.
+ *
+ * Clones share the same underlying chain, so interceptors added after a 
+ * Channel (or Tube) was created still apply to it.
+ */
+#[derive(Clone, Default)]
+pub struct FrameInterceptors {
+    interceptors: Arc<RwLock<Vec<Arc<dyn FrameInterceptor>>>>,
+}
+impl FrameInterceptors {
+    pub fn new() -> Self {
+        FrameInterceptors {
+            interceptors: Arc::new(RwLock::new(vec![])),
+        }
+    }
+
+    pub fn add(&self, interceptor: impl FrameInterceptor + 'static) {
+        self.interceptors.write().unwrap().push(Arc::new(interceptor));
+    }
+
+    pub fn intercept(&self, frame: Frame) -> InterceptedFrame {
+        let interceptors = self.interceptors.read().unwrap();
+        let mut frame = frame;
+        for interceptor in interceptors.iter() {
+            frame = match interceptor.intercept(frame) {
+                InterceptedFrame::Forward(frame) => frame,
+                veto @ InterceptedFrame::Veto(_) => return veto,
+            };
+        }
+        InterceptedFrame::Forward(frame)
+    }
+}
+impl std::fmt::Debug for FrameInterceptors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let num_interceptors = self.interceptors.read().unwrap().len();
+        write!(f, "FrameInterceptors({} interceptors)", num_interceptors)
+    }
+}
+
+#[cfg(test)]
+mod interceptor_tests {
+    use super::*;
+    use super::super::frame::DrainReason;
+
+    #[test]
+    fn forwards_unmodified_frame_with_no_interceptors() {
+        let interceptors = FrameInterceptors::new();
+        match interceptors.intercept(Frame::AbortAck { tube_id: 42 }) {
+            InterceptedFrame::Forward(frame) => 
+                assert_eq!(frame, Frame::AbortAck { tube_id: 42 }),
+            InterceptedFrame::Veto(reason) => 
+                panic!("Frame unexpectedly vetoed: {}", reason),
+        }
+    }
+
+    #[test]
+    fn runs_interceptors_in_registration_order() {
+        let interceptors = FrameInterceptors::new();
+        interceptors.add(|frame| match frame {
+            Frame::AbortAck { tube_id } => 
+                InterceptedFrame::Forward(Frame::AbortAck { tube_id: tube_id + 1 }),
+            frame => InterceptedFrame::Forward(frame),
+        });
+        interceptors.add(|frame| match frame {
+            Frame::AbortAck { tube_id } => 
+                InterceptedFrame::Forward(Frame::AbortAck { tube_id: tube_id * 2 }),
+            frame => InterceptedFrame::Forward(frame),
+        });
+
+        match interceptors.intercept(Frame::AbortAck { tube_id: 1 }) {
+            InterceptedFrame::Forward(frame) => 
+                assert_eq!(frame, Frame::AbortAck { tube_id: 4 }),
+            InterceptedFrame::Veto(reason) => 
+                panic!("Frame unexpectedly vetoed: {}", reason),
+        }
+    }
+
+    #[test]
+    fn veto_ends_the_chain() {
+        let interceptors = FrameInterceptors::new();
+        interceptors.add(|_frame| InterceptedFrame::Veto("nope".to_string()));
+        interceptors.add(|_frame| -> InterceptedFrame {
+            panic!("Interceptor ran after a veto!")
+        });
+
+        match interceptors.intercept(Frame::Drain {
+            reason: DrainReason::Unspecified,
+            deadline_unix_millis: None,
+        }) {
+            InterceptedFrame::Veto(reason) => assert_eq!(reason, "nope"),
+            InterceptedFrame::Forward(frame) => 
+                panic!("Frame unexpectedly forwarded: {:?}", frame),
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+use super::frame;
+
+#[derive(Debug)]
+pub enum FrameJsonError {
+    InvalidField(&'static str),
+    MissingField(&'static str),
+    UnknownVariant(String),
+}
+impl std::fmt::Display for FrameJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FrameJsonError::InvalidField(field) => write!(f, "invalid field `{}`", field),
+            FrameJsonError::MissingField(field) => write!(f, "missing field `{}`", field),
+            FrameJsonError::UnknownVariant(variant) => write!(f, "unknown variant `{}`", variant),
+        }
+    }
+}
+
+/**
+ * Converts a Frame into its JSON representation. This is the same
+ * externally-tagged shape a serde derive would produce, e.g.:
+ *
+ *   {"PayloadAck": {"tube_id": 3, "ack_id": 10}}
+ *
+ * Binary data (payloads and header values) is represented as an array of
+ * byte values.
+ */
+pub fn frame_to_json(frame: &frame::Frame) -> Value {
+    use frame::Frame::*;
+    match frame {
+        ClientHasFinishedSending { tube_id } =>
+            json!({"ClientHasFinishedSending": {"tube_id": tube_id}}),
+        Drain { reason, deadline_unix_millis } =>
+            json!({"Drain": {
+                "reason": drain_reason_to_json(reason),
+                "deadline_unix_millis": deadline_unix_millis,
+            }}),
+        GoAway { last_tube_id, reason } =>
+            json!({"GoAway": {"last_tube_id": last_tube_id, "reason": reason}}),
+        NewTube { tube_id, headers } =>
+            json!({"NewTube": {"tube_id": tube_id, "headers": headers}}),
+        Payload { tube_id, ack_id, checksum, data } =>
+            json!({"Payload": {
+                "tube_id": tube_id,
+                "ack_id": ack_id,
+                "checksum": checksum,
+                "data": data.as_ref(),
+            }}),
+        PartialPayload { tube_id, checksum, data } =>
+            json!({"PartialPayload": {
+                "tube_id": tube_id,
+                "checksum": checksum,
+                "data": data.as_ref(),
+            }}),
+        PayloadAck { tube_id, ack_id } =>
+            json!({"PayloadAck": {"tube_id": tube_id, "ack_id": ack_id}}),
+        SequencedPayload { tube_id, sequence_number, data } =>
+            json!({"SequencedPayload": {
+                "tube_id": tube_id,
+                "sequence_number": sequence_number,
+                "data": data.as_ref(),
+            }}),
+        SelectiveAck { tube_id, ranges } =>
+            json!({"SelectiveAck": {
+                "tube_id": tube_id,
+                "ranges": ranges.iter()
+                    .map(|range| json!([range.start(), range.end()]))
+                    .collect::<Vec<Value>>(),
+            }}),
+        ServerHasFinishedSending { tube_id } =>
+            json!({"ServerHasFinishedSending": {"tube_id": tube_id}}),
+        Abort { tube_id, reason } =>
+            json!({"Abort": {"tube_id": tube_id, "reason": abort_reason_to_json(reason)}}),
+        AbortAck { tube_id } =>
+            json!({"AbortAck": {"tube_id": tube_id}}),
+        NewTubeAck { tube_id } =>
+            json!({"NewTubeAck": {"tube_id": tube_id}}),
+        Heartbeat => json!({"Heartbeat": {}}),
+        HeartbeatAck => json!({"HeartbeatAck": {}}),
+        Error { tube_id, code, detail } =>
+            json!({"Error": {
+                "tube_id": tube_id,
+                "code": error_code_to_json(code),
+                "detail": detail,
+            }}),
+        ExtensionFrame { type_id, payload } =>
+            json!({"ExtensionFrame": {"type_id": type_id, "payload": payload}}),
+    }
+}
+
+fn abort_reason_to_json(reason: &frame::AbortReason) -> Value {
+    use frame::AbortReason::*;
+    match reason {
+        ApplicationAbort => json!("ApplicationAbort"),
+        ApplicationError => json!("ApplicationError"),
+        ApplicationDefined { code, message } =>
+            json!({"ApplicationDefined": {"code": code, "message": message}}),
+        TransportErrorWhileSynchronizingTubeState =>
+            json!("TransportErrorWhileSynchronizingTubeState"),
+        IdleTimeout => json!("IdleTimeout"),
+        EventQueueOverflow => json!("EventQueueOverflow"),
+        RateLimited => json!("RateLimited"),
+        SlowConsumer => json!("SlowConsumer"),
+        AckTimeout => json!("AckTimeout"),
+        InvalidTubeEventTransition => json!("InvalidTubeEventTransition"),
+        Unknown => json!("Unknown"),
+    }
+}
+
+fn drain_reason_to_json(reason: &frame::DrainReason) -> Value {
+    use frame::DrainReason::*;
+    match reason {
+        Unspecified => json!("Unspecified"),
+        Shutdown => json!("Shutdown"),
+        Migrate => json!("Migrate"),
+        Overloaded => json!("Overloaded"),
+        Unknown(reason) => json!({"Unknown": reason}),
+    }
+}
+
+fn error_code_to_json(code: &frame::ErrorCode) -> Value {
+    use frame::ErrorCode::*;
+    match code {
+        BadHeader => json!("BadHeader"),
+        OverLimit => json!("OverLimit"),
+        UnsupportedFeature => json!("UnsupportedFeature"),
+        ProtocolViolation => json!("ProtocolViolation"),
+        Draining => json!("Draining"),
+        PayloadChecksumMismatch => json!("PayloadChecksumMismatch"),
+        Unknown(code) => json!({"Unknown": code}),
+    }
+}
+
+/**
+ * The inverse of frame_to_json().
+ */
+pub fn frame_from_json(value: &Value) -> Result<frame::Frame, FrameJsonError> {
+    let (variant, fields) = variant_and_fields(value)?;
+    Ok(match variant {
+        "ClientHasFinishedSending" => frame::Frame::ClientHasFinishedSending {
+            tube_id: int_field(fields, "tube_id")?,
+        },
+        "Drain" => frame::Frame::Drain {
+            reason: drain_reason_from_json(field(fields, "reason")?)?,
+            deadline_unix_millis: optional_int_field(fields, "deadline_unix_millis")?,
+        },
+        "GoAway" => frame::Frame::GoAway {
+            last_tube_id: int_field(fields, "last_tube_id")?,
+            reason: string_field(fields, "reason")?,
+        },
+        "NewTube" => frame::Frame::NewTube {
+            tube_id: int_field(fields, "tube_id")?,
+            headers: headers_field(fields, "headers")?,
+        },
+        "Payload" => frame::Frame::Payload {
+            tube_id: int_field(fields, "tube_id")?,
+            ack_id: optional_int_field(fields, "ack_id")?,
+            checksum: optional_int_field(fields, "checksum")?,
+            data: bytes_field(fields, "data")?.into(),
+        },
+        "PartialPayload" => frame::Frame::PartialPayload {
+            tube_id: int_field(fields, "tube_id")?,
+            checksum: optional_int_field(fields, "checksum")?,
+            data: bytes_field(fields, "data")?.into(),
+        },
+        "PayloadAck" => frame::Frame::PayloadAck {
+            tube_id: int_field(fields, "tube_id")?,
+            ack_id: int_field(fields, "ack_id")?,
+        },
+        "SequencedPayload" => frame::Frame::SequencedPayload {
+            tube_id: int_field(fields, "tube_id")?,
+            sequence_number: int_field(fields, "sequence_number")?,
+            data: bytes_field(fields, "data")?.into(),
+        },
+        "SelectiveAck" => frame::Frame::SelectiveAck {
+            tube_id: int_field(fields, "tube_id")?,
+            ranges: sequence_ranges_field(fields, "ranges")?,
+        },
+        "ServerHasFinishedSending" => frame::Frame::ServerHasFinishedSending {
+            tube_id: int_field(fields, "tube_id")?,
+        },
+        "Abort" => frame::Frame::Abort {
+            tube_id: int_field(fields, "tube_id")?,
+            reason: abort_reason_from_json(field(fields, "reason")?)?,
+        },
+        "AbortAck" => frame::Frame::AbortAck {
+            tube_id: int_field(fields, "tube_id")?,
+        },
+        "NewTubeAck" => frame::Frame::NewTubeAck {
+            tube_id: int_field(fields, "tube_id")?,
+        },
+        "Heartbeat" => frame::Frame::Heartbeat,
+        "HeartbeatAck" => frame::Frame::HeartbeatAck,
+        "Error" => frame::Frame::Error {
+            tube_id: optional_int_field(fields, "tube_id")?,
+            code: error_code_from_json(field(fields, "code")?)?,
+            detail: string_field(fields, "detail")?,
+        },
+        "ExtensionFrame" => frame::Frame::ExtensionFrame {
+            type_id: int_field(fields, "type_id")?,
+            payload: bytes_field(fields, "payload")?,
+        },
+        variant => return Err(FrameJsonError::UnknownVariant(variant.to_string())),
+    })
+}
+
+fn abort_reason_from_json(value: &Value) -> Result<frame::AbortReason, FrameJsonError> {
+    let (variant, fields) = variant_and_fields(value)?;
+    Ok(match variant {
+        "ApplicationAbort" => frame::AbortReason::ApplicationAbort,
+        "ApplicationError" => frame::AbortReason::ApplicationError,
+        "ApplicationDefined" => frame::AbortReason::ApplicationDefined {
+            code: int_field(fields, "code")?,
+            message: match fields.get("message") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(message)) => Some(message.clone()),
+                Some(_) => return Err(FrameJsonError::InvalidField("message")),
+            },
+        },
+        "TransportErrorWhileSynchronizingTubeState" =>
+            frame::AbortReason::TransportErrorWhileSynchronizingTubeState,
+        "IdleTimeout" => frame::AbortReason::IdleTimeout,
+        "EventQueueOverflow" => frame::AbortReason::EventQueueOverflow,
+        "RateLimited" => frame::AbortReason::RateLimited,
+        "SlowConsumer" => frame::AbortReason::SlowConsumer,
+        "AckTimeout" => frame::AbortReason::AckTimeout,
+        "InvalidTubeEventTransition" => frame::AbortReason::InvalidTubeEventTransition,
+        "Unknown" => frame::AbortReason::Unknown,
+        variant => return Err(FrameJsonError::UnknownVariant(variant.to_string())),
+    })
+}
+
+fn drain_reason_from_json(value: &Value) -> Result<frame::DrainReason, FrameJsonError> {
+    match value {
+        Value::String(variant) => match variant.as_str() {
+            "Unspecified" => Ok(frame::DrainReason::Unspecified),
+            "Shutdown" => Ok(frame::DrainReason::Shutdown),
+            "Migrate" => Ok(frame::DrainReason::Migrate),
+            "Overloaded" => Ok(frame::DrainReason::Overloaded),
+            variant => Err(FrameJsonError::UnknownVariant(variant.to_string())),
+        },
+        Value::Object(object) => match object.get("Unknown") {
+            Some(reason) => match reason.as_u64().and_then(|reason| u8::try_from(reason).ok()) {
+                Some(reason) => Ok(frame::DrainReason::Unknown(reason)),
+                None => Err(FrameJsonError::InvalidField("Unknown")),
+            },
+            None => Err(FrameJsonError::InvalidField("reason")),
+        },
+        _ => Err(FrameJsonError::InvalidField("reason")),
+    }
+}
+
+fn error_code_from_json(value: &Value) -> Result<frame::ErrorCode, FrameJsonError> {
+    match value {
+        Value::String(variant) => match variant.as_str() {
+            "BadHeader" => Ok(frame::ErrorCode::BadHeader),
+            "OverLimit" => Ok(frame::ErrorCode::OverLimit),
+            "UnsupportedFeature" => Ok(frame::ErrorCode::UnsupportedFeature),
+            "ProtocolViolation" => Ok(frame::ErrorCode::ProtocolViolation),
+            "Draining" => Ok(frame::ErrorCode::Draining),
+            "PayloadChecksumMismatch" => Ok(frame::ErrorCode::PayloadChecksumMismatch),
+            variant => Err(FrameJsonError::UnknownVariant(variant.to_string())),
+        },
+        Value::Object(object) => match object.get("Unknown") {
+            Some(code) => match code.as_u64().and_then(|code| u16::try_from(code).ok()) {
+                Some(code) => Ok(frame::ErrorCode::Unknown(code)),
+                None => Err(FrameJsonError::InvalidField("Unknown")),
+            },
+            None => Err(FrameJsonError::InvalidField("code")),
+        },
+        _ => Err(FrameJsonError::InvalidField("code")),
+    }
+}
+
+// Unit variants are plain strings; all others are a single-entry object
+// mapping the variant name to its fields.
+fn variant_and_fields(value: &Value) -> Result<(&str, &Map<String, Value>), FrameJsonError> {
+    static NO_FIELDS: std::sync::OnceLock<Map<String, Value>> = std::sync::OnceLock::new();
+    match value {
+        Value::String(variant) => Ok((variant, NO_FIELDS.get_or_init(Map::new))),
+        Value::Object(object) if object.len() == 1 => {
+            let (variant, fields) = object.iter().next().unwrap();
+            match fields {
+                Value::Object(fields) => Ok((variant, fields)),
+                _ => Err(FrameJsonError::InvalidField("fields")),
+            }
+        },
+        _ => Err(FrameJsonError::InvalidField("variant")),
+    }
+}
+
+fn field<'a>(
+    fields: &'a Map<String, Value>,
+    name: &'static str,
+) -> Result<&'a Value, FrameJsonError> {
+    match fields.get(name) {
+        Some(value) => Ok(value),
+        None => Err(FrameJsonError::MissingField(name)),
+    }
+}
+
+fn int_field<T: TryFrom<u64>>(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<T, FrameJsonError> {
+    match field(fields, name)?.as_u64().and_then(|value| T::try_from(value).ok()) {
+        Some(value) => Ok(value),
+        None => Err(FrameJsonError::InvalidField(name)),
+    }
+}
+
+fn optional_int_field<T: TryFrom<u64>>(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<Option<T>, FrameJsonError> {
+    match fields.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(_) => Ok(Some(int_field(fields, name)?)),
+    }
+}
+
+fn string_field(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<String, FrameJsonError> {
+    match field(fields, name)? {
+        Value::String(value) => Ok(value.clone()),
+        _ => Err(FrameJsonError::InvalidField(name)),
+    }
+}
+
+fn bytes_from_json(value: &Value, name: &'static str) -> Result<Vec<u8>, FrameJsonError> {
+    let values = match value {
+        Value::Array(values) => values,
+        _ => return Err(FrameJsonError::InvalidField(name)),
+    };
+    let mut bytes = Vec::with_capacity(values.len());
+    for value in values {
+        match value.as_u64().and_then(|byte| u8::try_from(byte).ok()) {
+            Some(byte) => bytes.push(byte),
+            None => return Err(FrameJsonError::InvalidField(name)),
+        }
+    }
+    Ok(bytes)
+}
+
+fn bytes_field(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<Vec<u8>, FrameJsonError> {
+    bytes_from_json(field(fields, name)?, name)
+}
+
+// Each range is a [start, end] pair (both inclusive)
+fn sequence_ranges_field(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<Vec<std::ops::RangeInclusive<u64>>, FrameJsonError> {
+    let values = match field(fields, name)? {
+        Value::Array(values) => values,
+        _ => return Err(FrameJsonError::InvalidField(name)),
+    };
+    let mut ranges = Vec::with_capacity(values.len());
+    for value in values {
+        let bounds = match value.as_array() {
+            Some(bounds) if bounds.len() == 2 => bounds,
+            _ => return Err(FrameJsonError::InvalidField(name)),
+        };
+        match (bounds[0].as_u64(), bounds[1].as_u64()) {
+            (Some(start), Some(end)) => ranges.push(start..=end),
+            _ => return Err(FrameJsonError::InvalidField(name)),
+        }
+    }
+    Ok(ranges)
+}
+
+fn headers_field(
+    fields: &Map<String, Value>,
+    name: &'static str,
+) -> Result<HashMap<String, Vec<u8>>, FrameJsonError> {
+    let headers = match field(fields, name)? {
+        Value::Object(headers) => headers,
+        _ => return Err(FrameJsonError::InvalidField(name)),
+    };
+    let mut decoded = HashMap::with_capacity(headers.len());
+    for (header_name, value) in headers {
+        decoded.insert(header_name.clone(), bytes_from_json(value, name)?);
+    }
+    Ok(decoded)
+}
+
+#[cfg(feature = "serde")]
+impl serde_core::Serialize for frame::Frame {
+    fn serialize<S: serde_core::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde_core::Serialize::serialize(&frame_to_json(self), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde_core::Deserialize<'de> for frame::Frame {
+    fn deserialize<D: serde_core::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <Value as serde_core::Deserialize>::deserialize(deserializer)?;
+        match frame_from_json(&value) {
+            Ok(frame) => Ok(frame),
+            Err(e) => Err(<D::Error as serde_core::de::Error>::custom(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    #[test]
+    fn frames_roundtrip_through_json() {
+        let frames = vec![
+            frame::Frame::Drain {
+                reason: frame::DrainReason::Unspecified,
+                deadline_unix_millis: None,
+            },
+            frame::Frame::Drain {
+                reason: frame::DrainReason::Unknown(200),
+                deadline_unix_millis: Some(1_700_000_000_000),
+            },
+            frame::Frame::GoAway {
+                last_tube_id: 7,
+                reason: "restarting".to_string(),
+            },
+            frame::Frame::NewTube {
+                tube_id: 1,
+                headers: HashMap::from([("metadata".to_string(), vec![0, 159, 255])]),
+            },
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: None,
+                checksum: Some(42),
+                data: vec![0, 1, 42, 255].into(),
+            },
+            frame::Frame::PartialPayload {
+                tube_id: 1,
+                checksum: None,
+                data: vec![3, 4].into(),
+            },
+            frame::Frame::SequencedPayload {
+                tube_id: 1,
+                sequence_number: u64::MAX,
+                data: vec![7, 8].into(),
+            },
+            frame::Frame::SelectiveAck {
+                tube_id: 1,
+                ranges: vec![0..=4, 6..=6, 9..=300],
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::ApplicationDefined { code: 429, message: None },
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::IdleTimeout,
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::EventQueueOverflow,
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::RateLimited,
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::SlowConsumer,
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::AckTimeout,
+            },
+            frame::Frame::Abort {
+                tube_id: 1,
+                reason: frame::AbortReason::InvalidTubeEventTransition,
+            },
+            frame::Frame::Error {
+                tube_id: Some(3),
+                code: frame::ErrorCode::Draining,
+                detail: "The channel is draining".to_string(),
+            },
+            frame::Frame::Error {
+                tube_id: Some(3),
+                code: frame::ErrorCode::PayloadChecksumMismatch,
+                detail: "7: expected checksum 0x1, computed 0x2".to_string(),
+            },
+            frame::Frame::Error {
+                tube_id: None,
+                code: frame::ErrorCode::Unknown(1234),
+                detail: "".to_string(),
+            },
+        ];
+        for frame in frames {
+            assert_eq!(frame_from_json(&frame_to_json(&frame)).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn uses_externally_tagged_representation() {
+        assert_eq!(
+            frame_to_json(&frame::Frame::PayloadAck { tube_id: 3, ack_id: 10 }),
+            json!({"PayloadAck": {"tube_id": 3, "ack_id": 10}}),
+        );
+        assert_eq!(
+            frame_to_json(&frame::Frame::Drain {
+                reason: frame::DrainReason::Unknown(200),
+                deadline_unix_millis: None,
+            }),
+            json!({"Drain": {"reason": {"Unknown": 200}, "deadline_unix_millis": null}}),
+        );
+    }
+
+    #[test]
+    fn errors_on_out_of_range_fields() {
+        let value = json!({"PayloadAck": {"tube_id": 3, "ack_id": 70000}});
+        match frame_from_json(&value) {
+            Err(FrameJsonError::InvalidField("ack_id")) => (),
+            unexpected => panic!("Unexpected result: {:?}", unexpected),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_impls_use_the_json_representation() {
+        let frame = frame::Frame::AbortAck { tube_id: 3 };
+        let value = serde_json::to_value(&frame).unwrap();
+        assert_eq!(value, frame_to_json(&frame));
+        assert_eq!(serde_json::from_value::<frame::Frame>(value).unwrap(), frame);
+    }
+}
@@ -1,13 +1,93 @@
+mod abort_reasons;
+mod buffer_pool;
+mod checksum;
 mod decode;
+mod extension;
 mod frame;
+mod frame_error_observer;
 mod frame_handler;
+mod frame_sender;
+mod golden;
+mod grammar;
+mod header_block;
+mod interceptor;
+mod json;
+mod outbound_scheduler;
+mod rate_limit;
+mod transport;
+mod varint;
 
+// FrameTypeHandlers are handed the ChannelContext of the channel a frame arrived
+// on.
+pub use crate::common::ChannelContext;
+pub use abort_reasons::abort_reason_name;
+pub use abort_reasons::register_abort_reason;
+pub use abort_reasons::AbortReasonRegistrationError;
+pub use buffer_pool::BufferPoolConfig;
+pub use buffer_pool::BufferPoolStats;
+pub use abort_reasons::MAX_APPLICATION_ABORT_CODE;
 pub use decode::Decoder;
+pub use decode::DecoderLimit;
+pub use decode::DecoderLimits;
+pub use decode::DecoderRecoveryMode;
+pub use decode::FrameDecodeError;
+pub use decode::FrameParseError;
 pub mod encode;
+pub use extension::ExtensionFrameHandler;
+pub use extension::ExtensionFrameHandlers;
+pub use extension::ExtensionFrameRegistrationError;
 pub use frame::AbortReason;
+pub use frame::DrainReason;
+pub use frame::ErrorCode;
 pub use frame::Frame;
+pub use frame::FramingVersion;
+pub use frame::FRAMING_VERSION_HEADER;
+pub use frame::MAX_PAYLOAD_FRAME_SIZE;
+pub use frame::MAX_PAYLOAD_FRAME_SIZE_HEADER;
+pub use frame::MAX_EXTENSION_FRAMETYPE;
+pub use frame::MIN_EXTENSION_FRAMETYPE;
+pub use frame::MAX_ACK_ID;
+pub use frame::negotiate_max_payload_frame_size;
+pub use frame_error_observer::FrameErrorObserver;
+pub use frame_error_observer::FrameErrorObservers;
+pub use frame_error_observer::RejectedFrame;
+pub use frame_error_observer::MAX_REJECTED_FRAME_BYTES;
 pub use frame_handler::FrameHandler;
-pub use frame_handler::FrameHandlerResult;
+pub use frame_handler::FrameHandlerError;
+pub use frame_handler::FrameTypeHandler;
+pub use frame_handler::LatePayloadPolicy;
+pub use frame_sender::FrameSendError;
+pub use frame_sender::FrameSender;
+pub use frame_sender::WeakFrameSender;
+pub use frame_sender::WriteCoalescing;
+pub use golden::golden_vectors;
+pub use golden::golden_vectors_json;
+pub use golden::parse_golden_vectors;
+pub use golden::GoldenVector;
+pub use golden::GoldenVectorError;
+pub use grammar::frame_grammar_json;
+pub use grammar::FieldEncoding;
+pub use grammar::FieldPresence;
+pub use grammar::FieldSpec;
+pub use grammar::FrameGrammar;
+pub use grammar::FrameTypeSpec;
+pub use grammar::FRAME_GRAMMAR_FORMAT_VERSION;
+pub use header_block::HeaderBlockDecodeError;
+pub use interceptor::FrameInterceptor;
+pub use interceptor::FrameInterceptors;
+pub use interceptor::InterceptedFrame;
+pub use json::frame_from_json;
+pub use json::frame_to_json;
+pub use json::FrameJsonError;
+pub use rate_limit::RateLimitAction;
+pub(in crate) use frame_sender::DEFAULT_PRIORITY;
+pub(in crate) use rate_limit::RateLimitCheck;
+pub(in crate) use rate_limit::RateLimiter;
+pub use rate_limit::RateLimits;
+pub use transport::FrameSink;
+pub use transport::FrameStream;
+pub use transport::Transport;
+pub use transport::TransportError;
 
 #[cfg(test)]
 mod codec_tests {
@@ -28,12 +108,31 @@ mod codec_tests {
 
     #[test]
     fn drain_frame_encodes_and_decodes() {
-        let encoded_bytes = encode::drain_frame().unwrap();
+        let encoded_bytes = encode::drain_frame(
+            DrainReason::Shutdown,
+            Some(1_700_000_000_000),
+        ).unwrap();
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
         assert_eq!(frames.len(), 1);
-        assert_eq!(frames[0], Frame::Drain);
+        assert_eq!(frames[0], Frame::Drain {
+            reason: DrainReason::Shutdown,
+            deadline_unix_millis: Some(1_700_000_000_000),
+        });
+    }
+
+    #[test]
+    fn go_away_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::go_away_frame(65000, "restarting".to_string()).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::GoAway {
+            last_tube_id: 65000,
+            reason: "restarting".to_string(),
+        });
     }
 
     #[test]
@@ -43,7 +142,9 @@ mod codec_tests {
           ("header1".to_string(), "value1".to_string()),
           ("header2".to_string(), "value2".to_string()),
         ]);
-        let expected_headers = encoded_headers.clone();
+        let expected_headers = encoded_headers.iter()
+          .map(|(name, value)| (name.clone(), value.clone().into_bytes()))
+          .collect();
 
         let encoded_bytes = 
           encode::newtube_frame(tube_id, encoded_headers).unwrap();
@@ -61,7 +162,7 @@ mod codec_tests {
     fn payload_frame_with_ack_encodes_and_decodes() {
         let tube_id = 65000;
         let ack_id = 32000;
-        let data = vec![0, 1, 42, 255];
+        let data = bytes::Bytes::from(vec![0, 1, 42, 255]);
         let expected_data = data.clone();
 
         let encoded_bytes = encode::payload_frame(tube_id, Some(ack_id), data).unwrap();
@@ -72,6 +173,29 @@ mod codec_tests {
         assert_eq!(frames[0], Frame::Payload {
           tube_id,
           ack_id: Some(ack_id),
+          checksum: None,
+          data: expected_data,
+        });
+    }
+
+    #[test]
+    fn payload_frame_with_checksum_encodes_and_decodes() {
+        let tube_id = 65000;
+        let ack_id = 32000;
+        let data = bytes::Bytes::from(vec![0, 1, 42, 255]);
+        let expected_checksum = checksum::crc32(&data);
+        let expected_data = data.clone();
+
+        let encoded_bytes = 
+          encode::payload_frame_with_checksum(tube_id, Some(ack_id), data).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Payload {
+          tube_id,
+          ack_id: Some(ack_id),
+          checksum: Some(expected_checksum),
           data: expected_data,
         });
     }
@@ -79,7 +203,7 @@ mod codec_tests {
     #[test]
     fn payload_frame_without_ack_encodes_and_decodes() {
         let tube_id = 65000;
-        let data = vec![0, 1, 42, 255];
+        let data = bytes::Bytes::from(vec![0, 1, 42, 255]);
         let expected_data = data.clone();
 
         let encoded_bytes = encode::payload_frame(tube_id, None, data).unwrap();
@@ -90,10 +214,28 @@ mod codec_tests {
         assert_eq!(frames[0], Frame::Payload {
           tube_id,
           ack_id: None,
+          checksum: None,
           data: expected_data,
         });
     }
 
+    #[test]
+    fn partial_payload_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::partial_payload_frame(
+          65000,
+          vec![0, 1, 42, 255].into(),
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::PartialPayload {
+          tube_id: 65000,
+          checksum: None,
+          data: vec![0, 1, 42, 255].into(),
+        });
+    }
+
     #[test]
     fn payload_ack_frame_encodes_and_decodes() {
         let tube_id = 65000;
@@ -110,6 +252,114 @@ mod codec_tests {
         });
     }
 
+    #[test]
+    fn sequenced_payload_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::sequenced_payload_frame(
+          65000,
+          u64::MAX,
+          vec![0, 1, 42, 255].into(),
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::SequencedPayload {
+          tube_id: 65000,
+          sequence_number: u64::MAX,
+          data: vec![0, 1, 42, 255].into(),
+        });
+    }
+
+    #[test]
+    fn selective_ack_frame_encodes_and_decodes() {
+        let ranges = vec![0..=4, 6..=6, 9..=u64::MAX];
+        let encoded_bytes = encode::selective_ack_frame(65000, ranges.clone()).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::SelectiveAck { tube_id: 65000, ranges });
+    }
+
+    #[test]
+    fn abort_frame_encodes_and_decodes() {
+        let tube_id = 65000;
+        let encoded_bytes = 
+          encode::abort_frame(tube_id, AbortReason::ApplicationError).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Abort {
+          tube_id,
+          reason: AbortReason::ApplicationError,
+        });
+    }
+
+    #[test]
+    fn abort_frame_with_application_code_encodes_and_decodes() {
+        let tube_id = 65000;
+        let reason = AbortReason::ApplicationDefined {
+          code: 429,
+          message: Some("quota exceeded".to_string()),
+        };
+        let encoded_bytes = encode::abort_frame(tube_id, reason.clone()).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Abort { tube_id, reason });
+    }
+
+    #[test]
+    fn error_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::error_frame(
+          Some(65000),
+          ErrorCode::OverLimit,
+          "too many tubes".to_string(),
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Error {
+          tube_id: Some(65000),
+          code: ErrorCode::OverLimit,
+          detail: "too many tubes".to_string(),
+        });
+    }
+
+    #[test]
+    fn channel_level_error_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::error_frame(
+          None,
+          ErrorCode::Unknown(1234),
+          "".to_string(),
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Error {
+          tube_id: None,
+          code: ErrorCode::Unknown(1234),
+          detail: "".to_string(),
+        });
+    }
+
+    #[test]
+    fn extension_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::extension_frame(0x42, vec![0, 1, 42, 255]).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::ExtensionFrame {
+          type_id: 0x42,
+          payload: vec![0, 1, 42, 255],
+        });
+    }
+
     #[test]
     fn serverhasfinishedsending_frame_encodes_and_decodes() {
         let tube_id = 65000;
@@ -120,4 +370,240 @@ mod codec_tests {
         assert_eq!(frames.len(), 1);
         assert_eq!(frames[0], Frame::ServerHasFinishedSending { tube_id });
     }
+
+    #[test]
+    fn newtubeack_frame_encodes_and_decodes() {
+        let tube_id = 65000;
+        let encoded_bytes = encode::new_tube_ack_frame(tube_id).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::NewTubeAck { tube_id });
+    }
+}
+
+#[cfg(test)]
+mod codec_v2_tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn roundtrip_v2(frame: Frame) {
+        let expected_frame = frame.clone();
+        let encoded_bytes = 
+          encode::encode_frame_with_version(frame, FramingVersion::V2).unwrap();
+
+        let mut decoder = Decoder::new_with_version(FramingVersion::V2);
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], expected_frame);
+    }
+
+    #[test]
+    fn all_frames_encode_and_decode() {
+        roundtrip_v2(Frame::ClientHasFinishedSending { tube_id: 65000 });
+        roundtrip_v2(Frame::Drain { reason: DrainReason::Unspecified, deadline_unix_millis: None });
+        roundtrip_v2(Frame::Drain {
+            reason: DrainReason::Migrate,
+            deadline_unix_millis: None,
+        });
+        roundtrip_v2(Frame::Drain {
+            reason: DrainReason::Unknown(200),
+            deadline_unix_millis: Some(u64::MAX),
+        });
+        roundtrip_v2(Frame::GoAway { last_tube_id: 0, reason: "".to_string() });
+        roundtrip_v2(Frame::GoAway { last_tube_id: u32::MAX, reason: "bye".to_string() });
+        roundtrip_v2(Frame::NewTube {
+          tube_id: 65000,
+          headers: HashMap::from([
+            ("header1".to_string(), b"value1".to_vec()),
+          ]),
+        });
+        roundtrip_v2(Frame::Payload {
+          tube_id: 65000,
+          ack_id: Some(0),
+          checksum: None,
+          data: vec![0, 1, 42, 255].into(),
+        });
+        roundtrip_v2(Frame::Payload {
+          tube_id: 1,
+          ack_id: None,
+          checksum: Some(checksum::crc32(&[0, 1, 42, 255])),
+          data: vec![0, 1, 42, 255].into(),
+        });
+        roundtrip_v2(Frame::PartialPayload {
+          tube_id: 65000,
+          checksum: None,
+          data: vec![0, 1, 42, 255].into(),
+        });
+        roundtrip_v2(Frame::PartialPayload {
+          tube_id: 1,
+          checksum: Some(checksum::crc32(&[0, 1, 42, 255])),
+          data: vec![0, 1, 42, 255].into(),
+        });
+        roundtrip_v2(Frame::PayloadAck { tube_id: 65000, ack_id: 32767 });
+        roundtrip_v2(Frame::SequencedPayload {
+          tube_id: 65000,
+          sequence_number: 1 << 40,
+          data: vec![0, 1, 42, 255].into(),
+        });
+        roundtrip_v2(Frame::SelectiveAck { tube_id: 65000, ranges: vec![] });
+        roundtrip_v2(Frame::SelectiveAck {
+          tube_id: 65000,
+          ranges: vec![0..=4, 6..=6, 9..=u64::MAX],
+        });
+        roundtrip_v2(Frame::ServerHasFinishedSending { tube_id: 65000 });
+        roundtrip_v2(Frame::Abort { 
+          tube_id: 65000, 
+          reason: AbortReason::ApplicationError,
+        });
+        roundtrip_v2(Frame::AbortAck { tube_id: 65000 });
+        roundtrip_v2(Frame::NewTubeAck { tube_id: 65000 });
+        roundtrip_v2(Frame::Heartbeat);
+        roundtrip_v2(Frame::HeartbeatAck);
+        roundtrip_v2(Frame::Abort { 
+          tube_id: 65000, 
+          reason: AbortReason::ApplicationDefined { 
+            code: 401, 
+            message: Some("auth expired".to_string()),
+          },
+        });
+        roundtrip_v2(Frame::Abort { 
+          tube_id: 65000, 
+          reason: AbortReason::ApplicationDefined { code: 0, message: None },
+        });
+        roundtrip_v2(Frame::Error {
+          tube_id: Some(0),
+          code: ErrorCode::BadHeader,
+          detail: "bad header".to_string(),
+        });
+        roundtrip_v2(Frame::Error {
+          tube_id: None,
+          code: ErrorCode::UnsupportedFeature,
+          detail: "".to_string(),
+        });
+        roundtrip_v2(Frame::ExtensionFrame { 
+          type_id: MAX_EXTENSION_FRAMETYPE, 
+          payload: vec![0, 1, 42, 255],
+        });
+    }
+
+    #[test]
+    fn tube_ids_beyond_16_bits_encode_and_decode() {
+        roundtrip_v2(Frame::NewTube {
+          tube_id: 4_000_000_000,
+          headers: HashMap::new(),
+        });
+        roundtrip_v2(Frame::PayloadAck { tube_id: 4_000_000_000, ack_id: 1 });
+        roundtrip_v2(Frame::AbortAck { tube_id: u32::MAX });
+    }
+
+    #[test]
+    fn v1_rejects_tube_ids_beyond_16_bits() {
+        let result = encode::encode_frame_with_version(
+          Frame::AbortAck { tube_id: 70000 },
+          FramingVersion::V1,
+        );
+        match result {
+          Err(encode::FrameEncodeError::TubeIdTooLarge(70000)) => (),
+          other => panic!("Expected TubeIdTooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partial_frame_yields_nothing_until_rest_of_data_provided() {
+        let mut encoded_bytes = encode::encode_frame_with_version(
+          Frame::PayloadAck { tube_id: 3, ack_id: 200 },
+          FramingVersion::V2,
+        ).unwrap();
+        let final_byte = encoded_bytes.pop().unwrap();
+
+        let mut decoder = Decoder::new_with_version(FramingVersion::V2);
+        assert_eq!(decoder.decode(encoded_bytes).unwrap().len(), 0);
+
+        let frames = decoder.decode(vec![final_byte]).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::PayloadAck { tube_id: 3, ack_id: 200 });
+    }
+
+    #[test]
+    fn small_control_frames_are_smaller_than_v1() {
+        let payload_ack = Frame::PayloadAck { tube_id: 3, ack_id: 10 };
+        let v1_len = encode::encode_frame_with_version(
+          payload_ack.clone(), 
+          FramingVersion::V1,
+        ).unwrap().len();
+        let v2_len = encode::encode_frame_with_version(
+          payload_ack, 
+          FramingVersion::V2,
+        ).unwrap().len();
+        assert_eq!(v1_len, 7);
+        assert_eq!(v2_len, 4);
+    }
+
+    #[test]
+    fn negotiates_v1_for_peers_that_dont_specify_a_version() {
+        assert_eq!(FramingVersion::negotiate(None), FramingVersion::V1);
+        assert_eq!(FramingVersion::negotiate(Some("1")), FramingVersion::V1);
+        assert_eq!(FramingVersion::negotiate(Some("garbage")), FramingVersion::V1);
+    }
+
+    #[test]
+    fn negotiates_latest_supported_version() {
+        assert_eq!(FramingVersion::negotiate(Some("2")), FramingVersion::V2);
+        assert_eq!(FramingVersion::negotiate(Some("3")), FramingVersion::V3);
+        assert_eq!(FramingVersion::negotiate(Some("4")), FramingVersion::V4);
+        assert_eq!(FramingVersion::negotiate(Some("5")), FramingVersion::V5);
+        assert_eq!(FramingVersion::negotiate(Some("6")), FramingVersion::V5);
+    }
+
+    #[test]
+    fn negotiates_the_smaller_max_payload_frame_size() {
+        assert_eq!(negotiate_max_payload_frame_size(4096, Some("1024")), 1024);
+        assert_eq!(negotiate_max_payload_frame_size(1024, Some(" 4096 ")), 1024);
+        assert_eq!(negotiate_max_payload_frame_size(4096, None), 4096);
+        assert_eq!(negotiate_max_payload_frame_size(4096, Some("0")), 4096);
+        assert_eq!(negotiate_max_payload_frame_size(4096, Some("garbage")), 4096);
+        assert_eq!(
+            negotiate_max_payload_frame_size(usize::MAX, Some("1000000")),
+            MAX_PAYLOAD_FRAME_SIZE,
+        );
+        assert_eq!(negotiate_max_payload_frame_size(0, None), 1);
+    }
+
+    #[test]
+    fn v3_newtube_headers_carry_binary_values() {
+        let frame = Frame::NewTube {
+          tube_id: 65000,
+          headers: HashMap::from([
+            ("metadata".to_string(), vec![0, 159, 255]),
+            ("content-type".to_string(), b"application/protobuf".to_vec()),
+          ]),
+        };
+        let expected_frame = frame.clone();
+        let encoded_bytes = 
+          encode::encode_frame_with_version(frame, FramingVersion::V3).unwrap();
+
+        let mut decoder = Decoder::new_with_version(FramingVersion::V3);
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], expected_frame);
+    }
+
+    #[test]
+    fn v2_rejects_non_utf8_header_values() {
+        let result = encode::encode_frame_with_version(
+          Frame::NewTube {
+            tube_id: 1,
+            headers: HashMap::from([("metadata".to_string(), vec![159])]),
+          },
+          FramingVersion::V2,
+        );
+        match result {
+          Err(encode::FrameEncodeError::NonUtf8HeaderValue(name)) => 
+            assert_eq!(name, "metadata"),
+          other => panic!("Expected NonUtf8HeaderValue, got {:?}", other),
+        }
+    }
 }
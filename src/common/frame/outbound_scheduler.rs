@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/**
+ * Orders the writes queued for a channel's writer task so that Tubes sending
+ * at the same time take turns, rather than a Tube that queues many writes at
+ * once holding up the others until they've all been written.
+ *
+ * Each Tube gets a lane of its own (in which writes keep the order they were
+ * queued in), and lanes are served round-robin: a lane gets as many writes in
+ * a row as its weight before the next lane gets its turn. Items that aren't
+ * for a single lane (writes for the channel as a whole, closing the
+ * transport, etc) are barriers: everything queued before a barrier is served
+ * before it, and everything queued after it is served after it.
+ */
+#[derive(Debug)]
+pub(in crate) struct OutboundScheduler<T> {
+    segments: VecDeque<Segment<T>>,
+}
+impl<T> OutboundScheduler<T> {
+    pub(in crate) fn new() -> Self {
+        OutboundScheduler {
+            segments: VecDeque::new(),
+        }
+    }
+
+    pub(in crate) fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /**
+     * The item pop() would return next.
+     */
+    pub(in crate) fn peek(&self) -> Option<&T> {
+        match self.segments.front()? {
+            Segment::Barrier(item) => Some(item),
+            Segment::Lanes(lanes) => lanes.peek(),
+        }
+    }
+
+    pub(in crate) fn pop(&mut self) -> Option<T> {
+        if let Segment::Lanes(lanes) = self.segments.front_mut()? {
+            let item = lanes.pop();
+            if lanes.is_empty() {
+                self.segments.pop_front();
+            }
+            return item;
+        }
+        match self.segments.pop_front() {
+            Some(Segment::Barrier(item)) => Some(item),
+            _ => None,
+        }
+    }
+
+    /**
+     * Pops the next item only if it satisfies predicate.
+     */
+    pub(in crate) fn pop_if(&mut self, predicate: impl FnOnce(&T) -> bool) -> Option<T> {
+        match self.peek() {
+            Some(item) if predicate(item) => self.pop(),
+            _ => None,
+        }
+    }
+
+    /**
+     * Queues item at the back of the given lane. The lane's weight (which is
+     * treated as at least 1) is updated to the one given.
+     */
+    pub(in crate) fn push(&mut self, lane: u32, weight: u32, item: T) {
+        match self.segments.back_mut() {
+            Some(Segment::Lanes(lanes)) => lanes.push(lane, weight.max(1), item),
+            _ => {
+                let mut lanes = Lanes::new();
+                lanes.push(lane, weight.max(1), item);
+                self.segments.push_back(Segment::Lanes(lanes));
+            },
+        }
+    }
+
+    pub(in crate) fn push_barrier(&mut self, item: T) {
+        self.segments.push_back(Segment::Barrier(item));
+    }
+}
+
+#[derive(Debug)]
+enum Segment<T> {
+    Barrier(T),
+    Lanes(Lanes<T>),
+}
+
+/**
+ * The lanes with items queued between two barriers.
+ */
+#[derive(Debug)]
+struct Lanes<T> {
+    lanes: HashMap<u32, Lane<T>>,
+    /**
+     * The lanes that have items queued, in the order they take turns. The
+     * lane at the front is the one being served.
+     */
+    ring: VecDeque<u32>,
+    /**
+     * How many items the lane at the front of the ring has been served in
+     * its current turn.
+     */
+    served: u32,
+}
+impl<T> Lanes<T> {
+    fn new() -> Self {
+        Lanes {
+            lanes: HashMap::new(),
+            ring: VecDeque::new(),
+            served: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    fn peek(&self) -> Option<&T> {
+        let lane_id = self.ring.front()?;
+        self.lanes.get(lane_id)?.items.front()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let lane_id = *self.ring.front()?;
+        let lane = self.lanes.get_mut(&lane_id)?;
+        let item = lane.items.pop_front();
+        self.served += 1;
+        if lane.items.is_empty() {
+            self.lanes.remove(&lane_id);
+            self.ring.pop_front();
+            self.served = 0;
+        } else if self.served >= lane.weight {
+            self.ring.rotate_left(1);
+            self.served = 0;
+        }
+        item
+    }
+
+    fn push(&mut self, lane_id: u32, weight: u32, item: T) {
+        let lane = self.lanes.entry(lane_id).or_insert_with(|| {
+            self.ring.push_back(lane_id);
+            Lane {
+                items: VecDeque::new(),
+                weight,
+            }
+        });
+        lane.items.push_back(item);
+        lane.weight = weight;
+    }
+}
+
+#[derive(Debug)]
+struct Lane<T> {
+    items: VecDeque<T>,
+    weight: u32,
+}
+
+#[cfg(test)]
+mod outbound_scheduler_tests {
+    use super::*;
+
+    fn drain(scheduler: &mut OutboundScheduler<&'static str>) -> Vec<&'static str> {
+        std::iter::from_fn(|| scheduler.pop()).collect()
+    }
+
+    #[test]
+    fn lanes_take_turns() {
+        let mut scheduler = OutboundScheduler::new();
+        for item in ["a1", "a2", "a3", "a4"] {
+            scheduler.push(1, 1, item);
+        }
+        scheduler.push(3, 1, "b1");
+        scheduler.push(5, 1, "c1");
+        scheduler.push(3, 1, "b2");
+
+        assert_eq!(drain(&mut scheduler), vec!["a1", "b1", "c1", "a2", "b2", "a3", "a4"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn heavier_lanes_get_more_items_per_turn() {
+        let mut scheduler = OutboundScheduler::new();
+        for item in ["a1", "a2", "a3", "a4"] {
+            scheduler.push(1, 1, item);
+        }
+        for item in ["b1", "b2", "b3", "b4"] {
+            scheduler.push(3, 3, item);
+        }
+
+        assert_eq!(
+            drain(&mut scheduler),
+            vec!["a1", "b1", "b2", "b3", "a2", "b4", "a3", "a4"],
+        );
+    }
+
+    #[test]
+    fn barriers_keep_their_place_in_line() {
+        let mut scheduler = OutboundScheduler::new();
+        scheduler.push(1, 1, "a1");
+        scheduler.push(1, 1, "a2");
+        scheduler.push_barrier("close");
+        scheduler.push(3, 1, "b1");
+        scheduler.push(1, 1, "a3");
+
+        assert_eq!(scheduler.peek(), Some(&"a1"));
+        assert_eq!(scheduler.pop_if(|item| *item == "close"), None);
+        assert_eq!(drain(&mut scheduler), vec!["a1", "a2", "close", "b1", "a3"]);
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/**
+ * What a channel does once its peer exceeds one of its RateLimits.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RateLimitAction {
+    /**
+     * Hold off on processing anything else received on the channel until the
+     * rate is back under the limit. Since the channel isn't read from in the
+     * meantime, HTTP/2 flow control pushes back on the peer.
+     */
+    #[default]
+    Throttle,
+    /**
+     * Abort the Tube that went over the limit with AbortReason::RateLimited.
+     * Tubes opened over the limit are aborted as soon as they're acked.
+     */
+    Abort,
+}
+
+/**
+ * Bounds how quickly the peer on a channel may open Tubes and send payload
+ * data, so that one peer can't monopolize a shared server. Each limit
+ * allows bursts of up to one second's worth. A limit of 0 refuses
+ * everything it applies to (even with RateLimitAction::Throttle). None
+ * leaves a limit off.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimits {
+    pub action: RateLimitAction,
+    pub max_new_tubes_per_sec: Option<u32>,
+    pub max_payload_bytes_per_sec: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(in crate) enum RateLimitCheck {
+    Allowed,
+    /**
+     * Check again after this long (see RateLimitAction::Throttle).
+     */
+    ThrottleFor(Duration),
+    Exceeded,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    /**
+     * Both the refill rate (per second) and the most tokens the bucket holds.
+     */
+    capacity: f64,
+    last_refill: Instant,
+    tokens: f64,
+}
+impl TokenBucket {
+    fn new(per_sec: f64, now: Instant) -> Self {
+        TokenBucket {
+            capacity: per_sec,
+            last_refill: now,
+            tokens: per_sec,
+        }
+    }
+
+    /**
+     * Takes cost tokens or returns how long it'll be until there are enough.
+     * Costs beyond the capacity are let through once the bucket is full,
+     * which leaves it in debt for the excess.
+     */
+    fn try_take(&mut self, cost: f64, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity).min(self.capacity);
+        self.last_refill = now;
+
+        let needed = cost.min(self.capacity);
+        if self.tokens >= needed {
+            self.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((needed - self.tokens) / self.capacity))
+        }
+    }
+}
+
+/**
+ * Tracks a channel's peer against its RateLimits (see
+ * ChannelContext::with_rate_limits()).
+ */
+#[derive(Debug)]
+pub(in crate) struct RateLimiter {
+    action: RateLimitAction,
+    new_tubes: Option<TokenBucket>,
+    payload_bytes: Option<TokenBucket>,
+}
+impl RateLimiter {
+    pub(in crate) fn new(limits: RateLimits) -> Self {
+        let now = Instant::now();
+        RateLimiter {
+            action: limits.action,
+            new_tubes: limits.max_new_tubes_per_sec.map(|max| TokenBucket::new(max as f64, now)),
+            payload_bytes:
+                limits.max_payload_bytes_per_sec.map(|max| TokenBucket::new(max as f64, now)),
+        }
+    }
+
+    pub(in crate) fn check_new_tube(&mut self, now: Instant) -> RateLimitCheck {
+        Self::check(self.action, &mut self.new_tubes, 1.0, now)
+    }
+
+    pub(in crate) fn check_payload(&mut self, num_bytes: usize, now: Instant) -> RateLimitCheck {
+        Self::check(self.action, &mut self.payload_bytes, num_bytes as f64, now)
+    }
+
+    fn check(
+        action: RateLimitAction,
+        bucket: &mut Option<TokenBucket>,
+        cost: f64,
+        now: Instant,
+    ) -> RateLimitCheck {
+        let bucket = match bucket {
+            Some(bucket) => bucket,
+            None => return RateLimitCheck::Allowed,
+        };
+        if bucket.capacity == 0.0 {
+            return RateLimitCheck::Exceeded;
+        }
+        match (bucket.try_take(cost, now), action) {
+            (Ok(()), _) => RateLimitCheck::Allowed,
+            (Err(wait), RateLimitAction::Throttle) => RateLimitCheck::ThrottleFor(wait),
+            (Err(_), RateLimitAction::Abort) => RateLimitCheck::Exceeded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    #[test]
+    fn bursts_up_to_the_limit_then_throttles_until_refilled() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimits {
+            action: RateLimitAction::Throttle,
+            max_new_tubes_per_sec: Some(2),
+            max_payload_bytes_per_sec: None,
+        });
+        limiter.new_tubes.as_mut().unwrap().last_refill = start;
+
+        assert_eq!(limiter.check_new_tube(start), RateLimitCheck::Allowed);
+        assert_eq!(limiter.check_new_tube(start), RateLimitCheck::Allowed);
+        assert_eq!(
+            limiter.check_new_tube(start),
+            RateLimitCheck::ThrottleFor(Duration::from_millis(500)),
+        );
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check_new_tube(later), RateLimitCheck::Allowed);
+
+        // Payloads aren't limited
+        assert_eq!(limiter.check_payload(1 << 20, later), RateLimitCheck::Allowed);
+    }
+
+    #[test]
+    fn oversized_payloads_pass_when_full_and_leave_the_bucket_in_debt() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimits {
+            action: RateLimitAction::Abort,
+            max_new_tubes_per_sec: None,
+            max_payload_bytes_per_sec: Some(100),
+        });
+        limiter.payload_bytes.as_mut().unwrap().last_refill = start;
+
+        assert_eq!(limiter.check_payload(150, start), RateLimitCheck::Allowed);
+        let later = start + Duration::from_millis(1000);
+        assert_eq!(limiter.check_payload(60, later), RateLimitCheck::Exceeded);
+        let even_later = start + Duration::from_millis(1100);
+        assert_eq!(limiter.check_payload(60, even_later), RateLimitCheck::Allowed);
+    }
+
+    #[test]
+    fn zero_limits_refuse_everything() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            action: RateLimitAction::Throttle,
+            max_new_tubes_per_sec: Some(0),
+            max_payload_bytes_per_sec: None,
+        });
+        assert_eq!(limiter.check_new_tube(Instant::now()), RateLimitCheck::Exceeded);
+    }
+}
@@ -0,0 +1,148 @@
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use hyper::body::HttpBody;
+
+/**
+ * A failure of the transport that a channel's frames are carried over (e.g.
+ * an HTTP/2 stream, a WebSocket, or a raw connection). Carries the error the
+ * transport failed with.
+ */
+#[derive(Debug)]
+pub struct TransportError(Box<dyn std::error::Error + Send + Sync>);
+impl TransportError {
+    pub fn new<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        TransportError(error.into())
+    }
+
+    pub fn into_inner(self) -> Box<dyn std::error::Error + Send + Sync> {
+        self.0
+    }
+}
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+impl From<hyper::Error> for TransportError {
+    fn from(e: hyper::Error) -> Self {
+        TransportError::new(e)
+    }
+}
+
+/**
+ * The write side of a Transport, which takes chunks of encoded frames for
+ * the peer (see FrameSender).
+ */
+pub trait FrameSink: std::fmt::Debug + Send + 'static {
+    /**
+     * Resolves once the sink can take another chunk, or fails if the
+     * transport has gone away.
+     */
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>>;
+
+    /**
+     * Writes a chunk. Only called once poll_ready() has resolved with Ok.
+     */
+    fn start_send(&mut self, data: Bytes) -> Result<(), TransportError>;
+
+    /**
+     * Resets the transport rather than letting the peer take what's already
+     * been written. Dropping a FrameSink ends the stream of frames cleanly.
+     */
+    fn abort(self: Box<Self>);
+}
+impl dyn FrameSink {
+    pub(in crate) async fn send(&mut self, data: Bytes) -> Result<(), TransportError> {
+        futures::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.start_send(data)
+    }
+}
+impl FrameSink for hyper::body::Sender {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        hyper::body::Sender::poll_ready(self, cx).map_err(TransportError::from)
+    }
+
+    fn start_send(&mut self, data: Bytes) -> Result<(), TransportError> {
+        match self.try_send_data(data) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(TransportError::new("Body sender isn't ready for more data")),
+        }
+    }
+
+    fn abort(self: Box<Self>) {
+        hyper::body::Sender::abort(*self)
+    }
+}
+
+/**
+ * The read side of a Transport: the chunks of encoded frames received from
+ * the peer, which end when the peer ends its stream of frames.
+ */
+pub type FrameStream = BoxStream<'static, Result<Bytes, TransportError>>;
+
+/**
+ * Carries a channel's encoded frames to and from its peer, so that a channel
+ * is served the same way whatever it's carried over.
+ */
+pub trait Transport {
+    fn into_parts(self) -> (Box<dyn FrameSink>, FrameStream);
+}
+impl Transport for (hyper::body::Sender, hyper::Body) {
+    fn into_parts(self) -> (Box<dyn FrameSink>, FrameStream) {
+        let (body_sender, body) = self;
+        (Box::new(body_sender), body_stream(body))
+    }
+}
+
+/**
+ * The chunks of data received on body as a FrameStream.
+ */
+fn body_stream(body: hyper::Body) -> FrameStream {
+    Box::pin(futures::stream::unfold(body, |mut body| async move {
+        match body.data().await {
+            Some(Ok(data)) => Some((Ok(data), body)),
+            Some(Err(e)) => Some((Err(TransportError::from(e)), body)),
+            None => None,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod transport_tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn hyper_bodies_carry_chunks_both_ways() {
+        let (body_sender, body) = hyper::Body::channel();
+        let (mut sink, _) = (body_sender, hyper::Body::empty()).into_parts();
+        let mut stream = body_stream(body);
+
+        sink.send(Bytes::from_static(b"frames")).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), Bytes::from_static(b"frames"));
+        drop(sink);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn aborted_sinks_fail_their_streams() {
+        let (body_sender, body) = hyper::Body::channel();
+        let sink: Box<dyn FrameSink> = Box::new(body_sender);
+        let mut stream = body_stream(body);
+
+        sink.abort();
+        assert!(stream.next().await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+// u64 values never need more than 10 LEB128 bytes
+const MAX_VARINT_LEN: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum VarintDecodeError {
+    Overflow,
+}
+
+/**
+ * Appends `value` to `out` as an unsigned LEB128 varint: 7 bits of the value
+ * per byte (least-significant group first) with the MSB of each byte set if 
+ * more bytes follow.
+ */
+pub fn write_varint(value: u64, out: &mut impl bytes::BufMut) {
+    let mut value = value;
+    loop {
+        let byte = (value & 0b0111_1111) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.put_u8(byte);
+            return;
+        }
+        out.put_u8(byte | 0b1000_0000);
+    }
+}
+
+pub fn varint_len(value: u64) -> usize {
+    let mut len = 1;
+    let mut value = value >> 7;
+    while value > 0 {
+        len += 1;
+        value >>= 7;
+    }
+    len
+}
+
+/**
+ * Reads a varint from `data` starting at `offset`. Returns Ok(None) if `data` 
+ * ends before the varint does, otherwise the decoded value and the number of 
+ * bytes the varint occupied.
+ */
+pub fn read_varint(
+    data: &[u8],
+    offset: usize,
+) -> Result<Option<(u64, usize)>, VarintDecodeError> {
+    // Most varints on the wire (tube ids, small lengths) fit in one byte
+    match data.get(offset) {
+        Some(byte) if (byte & 0b1000_0000) == 0 => return Ok(Some((*byte as u64, 1))),
+        Some(_) => (),
+        None => return Ok(None),
+    }
+    let mut value: u64 = 0;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = match data.get(offset + i) {
+            Some(byte) => *byte,
+            None => return Ok(None),
+        };
+        let bits = (byte & 0b0111_1111) as u64;
+        if i == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Err(VarintDecodeError::Overflow);
+        }
+        value |= bits << (7 * i);
+        if (byte & 0b1000_0000) == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+    Err(VarintDecodeError::Overflow)
+}
+
+#[cfg(test)]
+mod varint_tests {
+    use super::*;
+
+    fn roundtrip(value: u64) -> (u64, usize) {
+        let mut bytes = vec![];
+        write_varint(value, &mut bytes);
+        assert_eq!(bytes.len(), varint_len(value));
+        read_varint(&bytes, 0).unwrap().unwrap()
+    }
+
+    #[test]
+    fn small_values_occupy_one_byte() {
+        assert_eq!(roundtrip(0), (0, 1));
+        assert_eq!(roundtrip(127), (127, 1));
+    }
+
+    #[test]
+    fn larger_values_roundtrip() {
+        assert_eq!(roundtrip(128), (128, 2));
+        assert_eq!(roundtrip(u16::MAX as u64), (u16::MAX as u64, 3));
+        assert_eq!(roundtrip(u64::MAX), (u64::MAX, 10));
+    }
+
+    #[test]
+    fn incomplete_varint_yields_none() {
+        let data = [0b1000_0000];
+        assert_eq!(read_varint(&data, 0), Ok(None));
+    }
+
+    #[test]
+    fn errors_on_overlong_varint() {
+        let data = [0xFF; 11];
+        assert_eq!(read_varint(&data, 0), Err(VarintDecodeError::Overflow));
+    }
+}
@@ -0,0 +1,12 @@
+pub(in crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(in crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|idx| {
+        hex.get(idx..idx + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok())
+    }).collect()
+}
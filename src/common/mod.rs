@@ -1,10 +1,23 @@
-mod inverted_future;
+mod channel_context;
+mod channel_executor;
+mod hex;
+#[cfg(any(feature = "client", feature = "server"))]
+pub(in crate) mod raw_transport;
+mod tube_id_allocator;
 mod unique_id_manager;
+#[cfg(all(feature = "websocket", any(feature = "client", feature = "server")))]
+pub(in crate) mod websocket;
 
+pub mod capture;
+pub use channel_context::ChannelContext;
+pub use channel_context::ChannelEvent;
+pub use channel_context::CloseReason;
+pub use channel_context::PingError;
+pub use channel_executor::ChannelExecutor;
+pub use channel_executor::LocalSetSpawner;
 pub mod frame;
-pub use inverted_future::InvertedFuture;
-pub use inverted_future::InvertedFutureResolver;
 pub mod tube;
+pub(in crate) use tube_id_allocator::TubeIdAllocator;
 pub use unique_id_manager::UniqueId;
 pub use unique_id_manager::UniqueIdError;
 pub use unique_id_manager::UniqueIdManager;
@@ -15,3 +28,14 @@ pub enum PeerType {
     Client,
     Server,
 }
+impl PeerType {
+    /**
+     * The type of the peer on the other end of a channel from this one.
+     */
+    pub(in crate) fn remote(self) -> PeerType {
+        match self {
+            PeerType::Client => PeerType::Server,
+            PeerType::Server => PeerType::Client,
+        }
+    }
+}
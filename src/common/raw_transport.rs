@@ -0,0 +1,257 @@
+use bytes::Bytes;
+use hyper::body::HttpBody;
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+/**
+ * Starts the head each peer sends before a raw channel's frames. The client's
+ * head names the path the channel is made on (e.g. `TUBEZ/1 /chat`), and the
+ * server's answers it with a status code (e.g. `TUBEZ/1 200`). Both are
+ * followed by HTTP-style header lines and a blank line.
+ */
+pub(in crate) const PROTOCOL: &str = "TUBEZ/1";
+
+/**
+ * The largest head that's read, so a peer can't make the other buffer
+ * without bound before the channel is even established.
+ */
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub(in crate) enum RawHeadError {
+    InvalidHeader(String),
+    IoError(std::io::Error),
+    /**
+     * The head doesn't start with PROTOCOL. Carries the line it starts with.
+     */
+    UnsupportedProtocol(String),
+    TooLarge,
+    /**
+     * The connection ended before the head did.
+     */
+    UnexpectedEof,
+}
+impl std::fmt::Display for RawHeadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RawHeadError::InvalidHeader(header) => write!(f, "invalid header: `{}`", header),
+            RawHeadError::IoError(e) => write!(f, "io error: {}", e),
+            RawHeadError::UnsupportedProtocol(start_line) =>
+                write!(f, "unsupported protocol: `{}`", start_line),
+            RawHeadError::TooLarge => write!(f, "head is over {} bytes", MAX_HEAD_SIZE),
+            RawHeadError::UnexpectedEof => write!(f, "connection ended before the head did"),
+        }
+    }
+}
+
+/**
+ * What follows PROTOCOL on the first line of a head (the path for a client's
+ * head, the status code for a server's) and the headers after it.
+ */
+#[derive(Debug, PartialEq)]
+pub(in crate) struct RawHead {
+    pub(in crate) subject: String,
+    pub(in crate) headers: hyper::HeaderMap,
+}
+impl RawHead {
+    pub(in crate) fn encode(&self) -> Vec<u8> {
+        let mut encoded = format!("{} {}\r\n", PROTOCOL, self.subject).into_bytes();
+        for (name, value) in &self.headers {
+            encoded.extend_from_slice(name.as_str().as_bytes());
+            encoded.extend_from_slice(b": ");
+            encoded.extend_from_slice(value.as_bytes());
+            encoded.extend_from_slice(b"\r\n");
+        }
+        encoded.extend_from_slice(b"\r\n");
+        encoded
+    }
+
+    /**
+     * Reads a head from io, leaving whatever follows it (i.e. the channel's
+     * frames) unread.
+     */
+    pub(in crate) async fn read<IO>(io: &mut IO) -> Result<Self, RawHeadError>
+    where
+        IO: AsyncBufRead + Unpin,
+    {
+        let mut num_bytes_read = 0;
+        let mut head = None;
+        loop {
+            let mut line = vec![];
+            let limit = (MAX_HEAD_SIZE - num_bytes_read + 1) as u64;
+            match (&mut *io).take(limit).read_until(b'\n', &mut line).await {
+                Ok(0) => return Err(RawHeadError::UnexpectedEof),
+                Ok(num_bytes) => num_bytes_read += num_bytes,
+                Err(e) => return Err(RawHeadError::IoError(e)),
+            }
+            if num_bytes_read > MAX_HEAD_SIZE {
+                return Err(RawHeadError::TooLarge);
+            }
+            let line = match line.strip_suffix(b"\r\n") {
+                Some(line) => line,
+                None => return Err(RawHeadError::UnexpectedEof),
+            };
+
+            let head = match head.as_mut() {
+                Some(head) => head,
+                None => {
+                    let start_line = String::from_utf8_lossy(line);
+                    let subject = match start_line.strip_prefix(PROTOCOL) {
+                        Some(subject) if subject.starts_with(' ') => subject.trim(),
+                        _ => return Err(RawHeadError::UnsupportedProtocol(start_line.into())),
+                    };
+                    head = Some(RawHead {
+                        subject: subject.to_string(),
+                        headers: hyper::HeaderMap::new(),
+                    });
+                    continue;
+                },
+            };
+            if line.is_empty() {
+                break;
+            }
+            let separator = line.iter().position(|byte| *byte == b':');
+            let (name, value) = match separator {
+                Some(separator) => (&line[..separator], &line[separator + 1..]),
+                None => return Err(RawHeadError::InvalidHeader(
+                    String::from_utf8_lossy(line).into(),
+                )),
+            };
+            let name = match hyper::header::HeaderName::from_bytes(name) {
+                Ok(name) => name,
+                Err(_) => return Err(RawHeadError::InvalidHeader(
+                    String::from_utf8_lossy(name).into(),
+                )),
+            };
+            let value = match hyper::header::HeaderValue::from_bytes(value.trim_ascii()) {
+                Ok(value) => value,
+                Err(_) => return Err(RawHeadError::InvalidHeader(name.to_string())),
+            };
+            head.headers.append(name, value);
+        }
+        match head {
+            Some(head) => Ok(head),
+            None => unreachable!("A head's start line is read before its end"),
+        }
+    }
+}
+
+/**
+ * Carries a channel's frames straight over io once the heads have been
+ * exchanged, so that the channel itself is served just as it is over an
+ * HTTP/2 stream. The data read from io is sent to incoming, and the data from
+ * outgoing is written to io. Resolves once both directions have finished:
+ * incoming ends when io does (or is aborted if reading from it fails), and
+ * io is shut down for writing once outgoing ends.
+ */
+pub(in crate) async fn bridge<IO>(
+    io: IO,
+    mut incoming: hyper::body::Sender,
+    mut outgoing: hyper::Body,
+) where
+    IO: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(io);
+
+    let read_incoming = async move {
+        let mut read_buf = vec![0; 16 * 1024];
+        loop {
+            let num_bytes = match reader.read(&mut read_buf).await {
+                Ok(0) => return,
+                Ok(num_bytes) => num_bytes,
+                Err(e) => {
+                    log::trace!("Reading from raw connection failed: {:?}", e);
+                    incoming.abort();
+                    return;
+                },
+            };
+            let data = Bytes::copy_from_slice(&read_buf[..num_bytes]);
+            if incoming.send_data(data).await.is_err() {
+                return;
+            }
+        }
+    };
+
+    let write_outgoing = async move {
+        while let Some(data_result) = outgoing.data().await {
+            let data = match data_result {
+                Ok(data) => data,
+                Err(e) => {
+                    log::trace!("Stream of data for raw connection has errored: {:?}", e);
+                    break;
+                },
+            };
+            if let Err(e) = writer.write_all(&data).await {
+                log::trace!("Writing to raw connection failed: {:?}", e);
+                return;
+            }
+        }
+        let _ = writer.shutdown().await;
+    };
+
+    futures::future::join(read_incoming, write_outgoing).await;
+}
+
+#[cfg(test)]
+mod raw_transport_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn heads_are_read_back_as_they_were_encoded() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        headers.append("x-tag", "a".parse().unwrap());
+        headers.append("x-tag", "b".parse().unwrap());
+        let head = RawHead {
+            subject: "/chat".to_string(),
+            headers,
+        };
+        let mut encoded = head.encode();
+        encoded.extend_from_slice(b"frames");
+
+        let mut io = &encoded[..];
+        assert_eq!(RawHead::read(&mut io).await.unwrap(), head);
+        assert_eq!(io, b"frames");
+    }
+
+    #[tokio::test]
+    async fn malformed_and_oversized_heads_are_refused() {
+        let mut io = &b"HTTP/1.1 200 OK\r\n\r\n"[..];
+        assert!(matches!(
+            RawHead::read(&mut io).await,
+            Err(RawHeadError::UnsupportedProtocol(_)),
+        ));
+        let mut io = &b"TUBEZ/1 /\r\nno-separator\r\n\r\n"[..];
+        assert!(matches!(RawHead::read(&mut io).await, Err(RawHeadError::InvalidHeader(_))));
+        let mut io = &b"TUBEZ/1 /\r\nx-tenant-id: acme\r\n"[..];
+        assert!(matches!(RawHead::read(&mut io).await, Err(RawHeadError::UnexpectedEof)));
+
+        let oversized = format!("TUBEZ/1 /\r\nx-padding: {}\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        let mut io = oversized.as_bytes();
+        assert!(matches!(RawHead::read(&mut io).await, Err(RawHeadError::TooLarge)));
+    }
+
+    #[tokio::test]
+    async fn bridge_carries_data_until_both_sides_end() {
+        let (local_io, mut remote_io) = tokio::io::duplex(1024);
+        let (incoming_sender, mut incoming) = hyper::Body::channel();
+        let (mut outgoing_sender, outgoing) = hyper::Body::channel();
+        let bridge_task = tokio::spawn(bridge(local_io, incoming_sender, outgoing));
+
+        remote_io.write_all(b"in").await.unwrap();
+        assert_eq!(incoming.data().await.unwrap().unwrap(), Bytes::from_static(b"in"));
+        outgoing_sender.send_data(Bytes::from_static(b"out")).await.unwrap();
+        drop(outgoing_sender);
+        let mut received = vec![];
+        remote_io.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"out");
+
+        remote_io.shutdown().await.unwrap();
+        bridge_task.await.unwrap();
+        assert!(incoming.data().await.is_none());
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::common::frame;
+use super::tube_manager::TubeCompletionState;
+use super::tube_manager::TubeManager;
+use super::TubeEvent;
+use super::TubeEvent_StreamError;
+
+/**
+ * What a Tube does when the peer doesn't ack a payload within the Tube's ack
+ * timeout, on top of failing the send with SendError::TimedOutWaitingOnAck.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AckTimeoutAction {
+    /**
+     * Emit a TubeEvent::StreamError(TimedOutWaitingOnAck), so that the Tube's
+     * consumer hears about the unresponsive peer too.
+     */
+    Notify,
+    /**
+     * Emit the StreamError and abort the Tube with AbortReason::AckTimeout.
+     * Any other sends still waiting on acks fail right away rather than each
+     * waiting out its own timeout.
+     */
+    Abort,
+}
+
+/**
+ * Keeps a peer that stops acking (because of a bug, or because it died)
+ * from leaving the Tubes on a channel waiting on acks that will never come
+ * (see Channel::set_ack_timeout_policy()).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AckTimeoutPolicy {
+    pub action: AckTimeoutAction,
+    /**
+     * The ack timeout each Tube starts out with (see Tube::set_ack_timeout()).
+     */
+    pub timeout: Duration,
+}
+
+/**
+ * Applies the Tube's AckTimeoutPolicy, if it has one, once a payload sent on
+ * it has gone unacked for ack_timeout.
+ */
+pub(in crate::common::tube) fn handle_ack_timeout(
+    tube_id: u32,
+    ack_timeout: Duration,
+    tube_manager: &Mutex<TubeManager>,
+    sender: &frame::FrameSender,
+) {
+    let executor = {
+        let mut tube_mgr = tube_manager.lock().unwrap();
+        let policy = match tube_mgr.ack_timeout_policy {
+            Some(policy) => policy,
+            None => return,
+        };
+        if tube_mgr.completion_state.is_terminal() {
+            return;
+        }
+
+        log::debug!("Tube(id={}) waited {:?} on an ack from the peer", tube_id, ack_timeout);
+        tube_mgr.pending_events.push_back(TubeEvent::StreamError(
+            TubeEvent_StreamError::TimedOutWaitingOnAck(ack_timeout)
+        ));
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+        if policy.action == AckTimeoutAction::Notify {
+            return;
+        }
+
+        tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(
+            frame::AbortReason::AckTimeout
+        ));
+        tube_mgr.abort_ack_pending = true;
+        // Dropping the remaining sendacks fails the sends waiting on them
+        tube_mgr.sendacks.clear();
+        tube_mgr.sendack_order.clear();
+        tube_mgr.executor.clone()
+    };
+
+    let sender = sender.clone();
+    executor.spawn(async move {
+        let abort_frame = frame::Frame::Abort {
+            tube_id,
+            reason: frame::AbortReason::AckTimeout,
+        };
+        if let Err(e) = sender.send(abort_frame).await {
+            log::error!(
+                "Attempted to send an Abort for unacked Tube(id={}), but failed: {:?}",
+                tube_id,
+                e,
+            );
+        }
+    });
+}
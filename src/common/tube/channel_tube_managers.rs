@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use super::tube_manager::TubeManager;
+
+/**
+ * How many shards a channel's TubeManagers are spread across.
+ */
+const NUM_SHARDS: usize = 16;
+
+type Shard = HashMap<u32, Arc<Mutex<TubeManager>>>;
+
+/**
+ * A channel's TubeManagers, keyed by TubeId. They're spread across shards
+ * that are each locked on their own, so that handling frames for one Tube
+ * doesn't contend with handling frames for the channel's other Tubes.
+ *
+ * A shard is locked before the TubeManagers in it, so a TubeManager must not
+ * be locked while calling anything here. Methods that visit every Tube lock
+ * one shard at a time, so Tubes made or untracked meanwhile may or may not
+ * be visited.
+ */
+#[derive(Debug)]
+pub(in crate) struct ChannelTubeManagers {
+    shards: [Mutex<Shard>; NUM_SHARDS],
+}
+impl ChannelTubeManagers {
+    pub(in crate) fn new() -> Self {
+        ChannelTubeManagers {
+            shards: std::array::from_fn(|_| Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn shard(&self, tube_id: u32) -> MutexGuard<'_, Shard> {
+        // Each peer's TubeIds step by 2, so the low bit only says who made
+        // the Tube.
+        self.shards[(tube_id >> 1) as usize % NUM_SHARDS].lock().unwrap()
+    }
+
+    #[cfg(test)]
+    pub(in crate) fn contains(&self, tube_id: u32) -> bool {
+        self.shard(tube_id).contains_key(&tube_id)
+    }
+
+    pub(in crate) fn get(&self, tube_id: u32) -> Option<Arc<Mutex<TubeManager>>> {
+        self.shard(tube_id).get(&tube_id).cloned()
+    }
+
+    pub(in crate) fn insert(&self, tube_id: u32, tube_mgr: Arc<Mutex<TubeManager>>) {
+        self.shard(tube_id).insert(tube_id, tube_mgr);
+    }
+
+    /**
+     * Like insert(), but returns false (and leaves the map as it was) if a
+     * TubeManager is already tracked under tube_id.
+     */
+    pub(in crate) fn try_insert(&self, tube_id: u32, tube_mgr: Arc<Mutex<TubeManager>>) -> bool {
+        self.shard(tube_id).try_insert(tube_id, tube_mgr).is_ok()
+    }
+
+    #[cfg(test)]
+    pub(in crate) fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.lock().unwrap().is_empty())
+    }
+
+    /**
+     * Whether tube_mgr is the TubeManager tracked under tube_id.
+     */
+    pub(in crate) fn is_tracked(&self, tube_id: u32, tube_mgr: &Arc<Mutex<TubeManager>>) -> bool {
+        match self.shard(tube_id).get(&tube_id) {
+            Some(tracked) => Arc::ptr_eq(tracked, tube_mgr),
+            None => false,
+        }
+    }
+
+    #[cfg(test)]
+    pub(in crate) fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub(in crate) fn remove(&self, tube_id: u32) -> Option<Arc<Mutex<TubeManager>>> {
+        self.shard(tube_id).remove(&tube_id)
+    }
+
+    /**
+     * Stops tracking (and returns) every TubeManager that should_remove
+     * returns true for.
+     */
+    pub(in crate) fn remove_where<F>(
+        &self,
+        mut should_remove: F,
+    ) -> Vec<(u32, Arc<Mutex<TubeManager>>)>
+    where
+        F: FnMut(u32, &Mutex<TubeManager>) -> bool,
+    {
+        let mut removed = vec![];
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let tube_ids: Vec<u32> = shard.iter()
+                .filter(|(tube_id, tube_mgr)| should_remove(**tube_id, tube_mgr))
+                .map(|(tube_id, _)| *tube_id)
+                .collect();
+            for tube_id in tube_ids {
+                if let Some(tube_mgr) = shard.remove(&tube_id) {
+                    removed.push((tube_id, tube_mgr));
+                }
+            }
+        }
+        removed
+    }
+
+    /**
+     * Stops tracking (and returns) every TubeManager.
+     */
+    pub(in crate) fn take_all(&self) -> Vec<(u32, Arc<Mutex<TubeManager>>)> {
+        self.shards.iter()
+            .flat_map(|shard| std::mem::take(&mut *shard.lock().unwrap()))
+            .collect()
+    }
+
+    /**
+     * Every tracked TubeManager along with its TubeId.
+     */
+    pub(in crate) fn to_vec(&self) -> Vec<(u32, Arc<Mutex<TubeManager>>)> {
+        let mut tube_mgrs = vec![];
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            tube_mgrs.extend(
+                shard.iter().map(|(tube_id, tube_mgr)| (*tube_id, tube_mgr.clone()))
+            );
+        }
+        tube_mgrs
+    }
+
+    /**
+     * Stops tracking tube_mgr if it's the TubeManager tracked under tube_id.
+     */
+    pub(in crate) fn untrack(&self, tube_id: u32, tube_mgr: &Arc<Mutex<TubeManager>>) {
+        let mut shard = self.shard(tube_id);
+        let is_tracked = match shard.get(&tube_id) {
+            Some(tracked) => Arc::ptr_eq(tracked, tube_mgr),
+            None => false,
+        };
+        if is_tracked {
+            shard.remove(&tube_id);
+        }
+    }
+}
+impl Default for ChannelTubeManagers {
+    fn default() -> Self {
+        ChannelTubeManagers::new()
+    }
+}
+
+#[cfg(test)]
+mod channel_tube_managers_tests {
+    use super::*;
+
+    fn make_tube_mgr() -> Arc<Mutex<TubeManager>> {
+        Arc::new(Mutex::new(TubeManager::new()))
+    }
+
+    #[test]
+    fn tubes_are_tracked_across_shards() {
+        let tube_mgrs = ChannelTubeManagers::new();
+        for tube_id in 0..100 {
+            assert!(tube_mgrs.try_insert(tube_id, make_tube_mgr()));
+        }
+        assert!(!tube_mgrs.try_insert(42, make_tube_mgr()));
+        assert_eq!(tube_mgrs.len(), 100);
+
+        let mut tube_ids: Vec<u32> = tube_mgrs.to_vec().into_iter()
+            .map(|(tube_id, _)| tube_id)
+            .collect();
+        tube_ids.sort();
+        assert_eq!(tube_ids, (0..100).collect::<Vec<_>>());
+
+        let removed = tube_mgrs.remove_where(|tube_id, _| tube_id >= 50);
+        assert_eq!(removed.len(), 50);
+        assert!(tube_mgrs.contains(49));
+        assert!(!tube_mgrs.contains(50));
+        assert_eq!(tube_mgrs.take_all().len(), 50);
+        assert!(tube_mgrs.is_empty());
+    }
+
+    #[test]
+    fn only_the_tracked_tube_manager_is_untracked() {
+        let tube_mgrs = ChannelTubeManagers::new();
+        let tracked = make_tube_mgr();
+        let untracked = make_tube_mgr();
+        tube_mgrs.insert(3, tracked.clone());
+
+        tube_mgrs.untrack(3, &untracked);
+        assert!(tube_mgrs.is_tracked(3, &tracked));
+        assert!(!tube_mgrs.is_tracked(3, &untracked));
+        tube_mgrs.untrack(3, &tracked);
+        assert!(tube_mgrs.get(3).is_none());
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+/**
+ * What happens to a payload that arrives for a Tube whose event queue is
+ * already at its EventQueueLimit's capacity.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventQueueOverflowPolicy {
+    /**
+     * Stop reading from the channel until the Tube's consumer makes room.
+     * Nothing is lost, but every other Tube on the channel stalls too.
+     */
+    Block,
+    /**
+     * Drop the oldest payload queued on the Tube to make room.
+     */
+    DropOldest,
+    /**
+     * Abort the Tube with AbortReason::EventQueueOverflow.
+     */
+    AbortTube,
+}
+
+/**
+ * Bounds how many TubeEvents a Tube queues for a consumer that isn't keeping
+ * up (see Tube::set_event_queue_limit()). Events that change the Tube's 
+ * state (e.g. an Abort) are always queued, even beyond the capacity.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventQueueLimit {
+    pub capacity: usize,
+    pub policy: EventQueueOverflowPolicy,
+}
+
+/**
+ * Whether a received payload can be queued on its Tube (see
+ * TubeManager::make_room_for_payload()).
+ */
+pub(in crate) enum PayloadRoom {
+    Available,
+    /**
+     * The queue is full and the Tube's policy is to block. The Notify is
+     * notified when the queue may have room again.
+     */
+    Wait(Arc<tokio::sync::Notify>),
+    Overflowed,
+}
@@ -0,0 +1,109 @@
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::common::frame;
+use super::tube_manager::TubeCompletionState;
+use super::tube_manager::TubeManager;
+use super::TubeEvent;
+
+/**
+ * What a Tube does once it has gone an IdleTimeout's duration without 
+ * receiving a payload.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdleTimeoutAction {
+    /**
+     * Emit a TubeEvent::IdleTimeout, and again after every further idle 
+     * period.
+     */
+    Notify,
+    /**
+     * Emit a TubeEvent::IdleTimeout and abort the Tube with 
+     * AbortReason::IdleTimeout.
+     */
+    Abort,
+}
+
+/**
+ * Detects a silent peer on a long-lived Tube (see Tube::set_idle_timeout()).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdleTimeout {
+    pub duration: Duration,
+    pub action: IdleTimeoutAction,
+}
+
+/**
+ * Watches a Tube for idle_timeout until the Tube reaches a terminal state, 
+ * its idle timeout is changed (which bumps the TubeManager's 
+ * idle_timer_generation), or the Tube and its channel are gone.
+ */
+pub(in crate::common::tube) async fn run_idle_timer(
+    idle_timeout: IdleTimeout,
+    generation: u64,
+    tube_id: u32,
+    tube_manager: Weak<Mutex<TubeManager>>,
+    sender: frame::WeakFrameSender,
+) {
+    loop {
+        let deadline = {
+            let tube_manager = match tube_manager.upgrade() {
+                Some(tube_manager) => tube_manager,
+                None => return,
+            };
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            if tube_mgr.idle_timer_generation != generation 
+                || tube_mgr.completion_state.is_terminal() {
+                return;
+            }
+
+            let deadline = tube_mgr.last_payload_received + idle_timeout.duration;
+            if deadline > Instant::now() {
+                deadline
+            } else {
+                log::debug!(
+                    "Tube(id={}) received no payloads for {:?}", 
+                    tube_id, 
+                    idle_timeout.duration,
+                );
+                tube_mgr.pending_events.push_back(TubeEvent::IdleTimeout);
+                if let Some(waker) = tube_mgr.waker.take() {
+                    waker.wake();
+                }
+
+                match idle_timeout.action {
+                    IdleTimeoutAction::Notify => {
+                        tube_mgr.record_payload_received();
+                        continue;
+                    },
+                    IdleTimeoutAction::Abort => {
+                        tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(
+                            frame::AbortReason::IdleTimeout
+                        ));
+                        tube_mgr.abort_ack_pending = true;
+                        break;
+                    },
+                }
+            }
+        };
+        tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await;
+    }
+
+    let sender = match sender.upgrade() {
+        Some(sender) => sender,
+        None => return,
+    };
+    let abort_frame = frame::Frame::Abort {
+        tube_id,
+        reason: frame::AbortReason::IdleTimeout,
+    };
+    if let Err(e) = sender.send(abort_frame).await {
+        log::error!(
+            "Attempted to send an Abort for idle Tube(id={}), but failed: {:?}", 
+            tube_id, 
+            e,
+        );
+    }
+}
@@ -1,12 +1,59 @@
+mod ack_timeout;
+mod channel_tube_managers;
+mod event_queue;
+mod idle_timeout;
+mod payload_reassembly;
+mod send_acks;
+mod send_window;
+mod sequence_tracking;
+mod slow_consumer;
+mod split;
 mod tube;
 mod tube_event;
+mod tube_io;
+mod tube_lifecycle;
 mod tube_manager;
+mod tube_tracker;
+mod write_handle;
 
+pub use ack_timeout::AckTimeoutAction;
+pub use ack_timeout::AckTimeoutPolicy;
+pub(in crate) use channel_tube_managers::ChannelTubeManagers;
+pub use event_queue::EventQueueLimit;
+pub use event_queue::EventQueueOverflowPolicy;
+pub(in crate) use event_queue::PayloadRoom;
+pub use idle_timeout::IdleTimeout;
+pub use idle_timeout::IdleTimeoutAction;
+pub use send_acks::SendAcks;
+pub use send_window::DEFAULT_MAX_IN_FLIGHT_BYTES;
+pub use sequence_tracking::ReceivedSequences;
+pub use sequence_tracking::UnackedSequencedPayloads;
+pub use slow_consumer::SlowConsumerAction;
+pub use slow_consumer::SlowConsumerLimit;
+pub use split::TubeReader;
+pub use split::TubeWriter;
 pub use tube::error;
+pub use tube::CUMULATIVE_ACKS_HEADER;
+pub use tube::DEFAULT_ACK_TIMEOUT;
+pub use tube::PAYLOAD_CHECKSUM_HEADER;
+pub use tube::PAYLOAD_CHECKSUM_HEADER_CRC32;
+pub use tube::RECEIVE_ONLY_HEADER;
+pub use tube::SEND_STREAM_CHUNK_BYTES;
+pub(in crate) use tube::cumulative_acks_requested;
+pub(in crate) use tube::payload_checksums_requested;
+pub(in crate) use tube::receive_only_requested;
 pub use tube::Tube;
 pub use tube_event::TubeEvent;
 pub use tube_event::TubeEvent_StreamError;
 pub use tube_event::TubeEventTag;
+pub use tube_io::TubeIo;
+pub(in crate) use tube_lifecycle::TubeLifecycle;
+pub(in crate) use tube_lifecycle::TubeLifecycleObserver;
+pub(in crate) use tube_lifecycle::TubeStats;
 
-pub(in crate::common) use tube_manager::TubeCompletionState;
+pub(in crate) use tube_manager::TubeCompletionState;
 pub use tube_manager::TubeManager;
+pub use tube_tracker::JoinAllTubes;
+pub use tube_tracker::TubeOutcome;
+pub(in crate) use tube_tracker::TubeTracker;
+pub use write_handle::TubeWriteHandle;
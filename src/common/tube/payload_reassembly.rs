@@ -0,0 +1,94 @@
+use super::tube_event::TubeEvent_StreamError;
+
+/**
+ * Buffers the chunks of a payload that the peer split across PartialPayload
+ * frames until the Payload (or SequencedPayload) frame that carries its last
+ * chunk arrives, so that the payload is delivered to the Tube whole.
+ */
+#[derive(Debug, Default)]
+pub struct PayloadReassembly {
+    data: bytes::BytesMut,
+    /**
+     * Set when a chunk of the payload arrived corrupted. The payload is then
+     * surfaced as this error rather than delivered, and the rest of its
+     * chunks are dropped.
+     */
+    error: Option<TubeEvent_StreamError>,
+}
+impl PayloadReassembly {
+    pub fn new() -> Self {
+        PayloadReassembly {
+            data: bytes::BytesMut::new(),
+            error: None,
+        }
+    }
+
+    /**
+     * Buffers a chunk of the payload (or the error it arrived with).
+     */
+    pub fn push_chunk(&mut self, chunk: Result<bytes::Bytes, TubeEvent_StreamError>) {
+        if self.error.is_some() {
+            return;
+        }
+        match chunk {
+            Ok(data) => self.data.extend_from_slice(&data),
+            Err(e) => {
+                self.data.clear();
+                self.error = Some(e);
+            },
+        }
+    }
+
+    /**
+     * Completes the payload with its last chunk and starts over for the next
+     * one. Fails with the error of the first chunk that arrived corrupted, if
+     * any. Payloads that weren't split are returned without copying them.
+     */
+    pub fn finish(
+        &mut self,
+        last_chunk: Result<bytes::Bytes, TubeEvent_StreamError>,
+    ) -> Result<bytes::Bytes, TubeEvent_StreamError> {
+        if self.data.is_empty() && self.error.is_none() {
+            return last_chunk;
+        }
+        self.push_chunk(last_chunk);
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(self.data.split().freeze()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod payload_reassembly_tests {
+    use super::*;
+
+    fn checksum_mismatch(expected: u32) -> TubeEvent_StreamError {
+        TubeEvent_StreamError::PayloadChecksumMismatch { expected, computed: 0 }
+    }
+
+    #[test]
+    fn reassembles_chunks_in_order() {
+        let mut reassembly = PayloadReassembly::new();
+        reassembly.push_chunk(Ok("ab".into()));
+        reassembly.push_chunk(Ok("cd".into()));
+        assert_eq!(reassembly.finish(Ok("e".into())).unwrap(), "abcde");
+
+        // The next payload starts over
+        assert_eq!(reassembly.finish(Ok("fg".into())).unwrap(), "fg");
+    }
+
+    #[test]
+    fn fails_with_the_first_corrupted_chunk() {
+        let mut reassembly = PayloadReassembly::new();
+        reassembly.push_chunk(Ok("ab".into()));
+        reassembly.push_chunk(Err(checksum_mismatch(1)));
+        reassembly.push_chunk(Err(checksum_mismatch(2)));
+        assert_eq!(reassembly.finish(Ok("e".into())), Err(checksum_mismatch(1)));
+
+        // The next payload starts over
+        reassembly.push_chunk(Ok("hi".into()));
+        assert_eq!(reassembly.finish(Err(checksum_mismatch(3))), Err(checksum_mismatch(3)));
+        assert_eq!(reassembly.finish(Ok("fg".into())).unwrap(), "fg");
+    }
+}
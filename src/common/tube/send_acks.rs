@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task;
+use std::time::Duration;
+
+use futures::Future;
+use futures::StreamExt;
+use tokio::sync::OwnedSemaphorePermit;
+
+use crate::common::frame;
+use crate::common::UniqueId;
+use super::ack_timeout;
+use super::tube::error;
+use super::tube_manager::SendAck;
+use super::tube_manager::TubeManager;
+
+/**
+ * Completion notifications for a batch of payloads sent with
+ * Tube::send_pipelined(). Yields one item per payload, in the order the
+ * payloads were sent, as each is acked by the peer.
+ *
+ * A payload the peer received corrupted yields an
+ * Err(SendError::PayloadChecksumMismatch) in its place instead, and the
+ * stream carries on with the rest of the batch.
+ *
+ * If the acks don't all arrive within the batch's ack_timeout, the next item
+ * is an Err(SendError::TimedOutWaitingOnAck) and the stream ends; payloads
+ * that were still unacked at that point should be considered lost.
+ *
+ * The batch's bytes count against the Tube's in-flight window until the
+ * stream ends or is dropped.
+ */
+#[derive(Debug)]
+pub struct SendAcks {
+    ack_timeout: Duration,
+    deadline: Pin<Box<tokio::time::Sleep>>,
+    pending: VecDeque<(UniqueId, SendAck)>,
+    sender: frame::FrameSender,
+    tube_id: u32,
+    tube_manager: Arc<Mutex<TubeManager>>,
+    _send_window_permit: OwnedSemaphorePermit,
+}
+impl SendAcks {
+    pub(in crate::common::tube) fn new(
+        tube_id: u32,
+        pending: VecDeque<(UniqueId, SendAck)>,
+        ack_timeout: Duration,
+        sender: frame::FrameSender,
+        tube_manager: Arc<Mutex<TubeManager>>,
+        send_window_permit: OwnedSemaphorePermit,
+    ) -> Self {
+        SendAcks {
+            ack_timeout,
+            deadline: Box::pin(tokio::time::sleep(ack_timeout)),
+            pending,
+            sender,
+            tube_id,
+            tube_manager,
+            _send_window_permit: send_window_permit,
+        }
+    }
+
+    /**
+     * The number of payloads in the batch that have not been acked yet.
+     */
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /**
+     * Resolves once every payload in the batch has been acked, or with the
+     * first error encountered.
+     */
+    pub async fn all_acked(mut self) -> Result<(), error::SendError> {
+        while let Some(result) = self.next().await {
+            result?;
+        }
+        Ok(())
+    }
+
+    fn stop_tracking_pending(&mut self) {
+        let mut tube_mgr = self.tube_manager.lock().unwrap();
+        for (ack_id, _) in self.pending.drain(..) {
+            tube_mgr.remove_sendack(ack_id.val() as u16);
+        }
+    }
+}
+impl futures::stream::Stream for SendAcks {
+    type Item = Result<(), error::SendError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+    ) -> task::Poll<Option<Self::Item>> {
+        let sendack = match self.pending.front_mut() {
+            Some((_, sendack)) => sendack,
+            None => return task::Poll::Ready(None),
+        };
+
+        // Resolved sendacks are no longer tracked by the TubeManager, so acks
+        // are yielded without locking it
+        let timed_out = match Pin::new(sendack).poll(cx) {
+            task::Poll::Ready(Ok(result)) => {
+                self.pending.pop_front();
+                return task::Poll::Ready(Some(result));
+            },
+            // Dropped unresolved, so the ack isn't coming
+            task::Poll::Ready(Err(_)) => false,
+            task::Poll::Pending => {
+                if self.deadline.as_mut().poll(cx).is_pending() {
+                    return task::Poll::Pending;
+                }
+                true
+            },
+        };
+        self.stop_tracking_pending();
+        let ack_timeout = self.ack_timeout;
+        if timed_out {
+            ack_timeout::handle_ack_timeout(
+                self.tube_id,
+                ack_timeout,
+                &self.tube_manager,
+                &self.sender,
+            );
+        }
+        task::Poll::Ready(Some(Err(error::SendError::TimedOutWaitingOnAck(ack_timeout))))
+    }
+}
+impl Drop for SendAcks {
+    fn drop(&mut self) {
+        self.stop_tracking_pending();
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+pub const DEFAULT_MAX_IN_FLIGHT_BYTES: usize = 1024 * 1024;
+
+/**
+ * Caps the number of payload bytes a Tube may have outstanding at once.
+ *
+ * Bytes are considered "in flight" from the moment a Payload is handed to the
+ * transport until either the transport has accepted it (for send_and_forget)
+ * or the peer has acked it (for send). Producers that outrun the transport
+ * will block in acquire() until earlier payloads have made progress.
+ */
+#[derive(Clone, Debug)]
+pub(in crate) struct SendWindow {
+    max_bytes: usize,
+    semaphore: Arc<Semaphore>,
+}
+impl SendWindow {
+    pub fn new(max_bytes: usize) -> Self {
+        SendWindow {
+            max_bytes,
+            semaphore: Arc::new(Semaphore::new(max_bytes)),
+        }
+    }
+
+    /**
+     * Waits until num_bytes fit in the window and reserves them until the
+     * returned permit is dropped.
+     *
+     * A single payload larger than the entire window is allowed through once
+     * the window is otherwise empty so that it can't block forever.
+     */
+    pub async fn acquire(&self, num_bytes: usize) -> OwnedSemaphorePermit {
+        let num_permits = num_bytes.min(self.max_bytes).min(u32::MAX as usize);
+        self.semaphore.clone()
+            .acquire_many_owned(num_permits as u32).await
+            .expect("SendWindow semaphores are never closed")
+    }
+
+    pub fn in_flight_bytes(&self) -> usize {
+        self.max_bytes - self.semaphore.available_permits()
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
+
+#[cfg(test)]
+mod send_window_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tracks_in_flight_bytes_until_permits_are_dropped() {
+        let window = SendWindow::new(100);
+        assert_eq!(window.in_flight_bytes(), 0);
+
+        let permit1 = window.acquire(30).await;
+        let permit2 = window.acquire(50).await;
+        assert_eq!(window.in_flight_bytes(), 80);
+
+        drop(permit1);
+        assert_eq!(window.in_flight_bytes(), 50);
+
+        drop(permit2);
+        assert_eq!(window.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn blocks_when_window_is_full() {
+        let window = SendWindow::new(100);
+        let permit = window.acquire(80).await;
+
+        let blocked = tokio::time::timeout(
+            Duration::from_millis(10),
+            window.acquire(30),
+        ).await;
+        assert!(blocked.is_err());
+
+        drop(permit);
+        let unblocked = tokio::time::timeout(
+            Duration::from_millis(10),
+            window.acquire(30),
+        ).await;
+        assert!(unblocked.is_ok());
+    }
+
+    #[tokio::test]
+    async fn oversized_payloads_consume_the_whole_window() {
+        let window = SendWindow::new(100);
+        let _permit = window.acquire(500).await;
+        assert_eq!(window.in_flight_bytes(), 100);
+    }
+}
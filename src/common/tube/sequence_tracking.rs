@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/**
+ * The sequence numbers of the SequencedPayloads received on a Tube, kept as
+ * the same ascending, non-overlapping ranges that are reported back to the
+ * sender in SelectiveAck frames.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReceivedSequences {
+    ranges: Vec<RangeInclusive<u64>>,
+}
+impl ReceivedSequences {
+    pub fn new() -> Self {
+        ReceivedSequences {
+            ranges: vec![],
+        }
+    }
+
+    /**
+     * Records the receipt of sequence_number. Returns false if it had already
+     * been received (i.e. the payload is a retransmitted duplicate).
+     */
+    pub fn insert(&mut self, sequence_number: u64) -> bool {
+        // The first range that ends at or after sequence_number
+        let idx = self.ranges.partition_point(|range| *range.end() < sequence_number);
+        if let Some(range) = self.ranges.get(idx) {
+            if range.contains(&sequence_number) {
+                return false;
+            }
+        }
+
+        // Every range before idx ends before sequence_number and the range at
+        // idx (if any) starts after it, so neither of these overflow.
+        let extends_prev = idx > 0 && *self.ranges[idx - 1].end() + 1 == sequence_number;
+        let extends_next =
+            idx < self.ranges.len() && sequence_number + 1 == *self.ranges[idx].start();
+        match (extends_prev, extends_next) {
+            (true, true) => {
+                let next = self.ranges.remove(idx);
+                let prev_start = *self.ranges[idx - 1].start();
+                self.ranges[idx - 1] = prev_start..=*next.end();
+            },
+            (true, false) => {
+                let prev_start = *self.ranges[idx - 1].start();
+                self.ranges[idx - 1] = prev_start..=sequence_number;
+            },
+            (false, true) => {
+                let next_end = *self.ranges[idx].end();
+                self.ranges[idx] = sequence_number..=next_end;
+            },
+            (false, false) => self.ranges.insert(idx, sequence_number..=sequence_number),
+        }
+        true
+    }
+
+    pub fn contains(&self, sequence_number: u64) -> bool {
+        let idx = self.ranges.partition_point(|range| *range.end() < sequence_number);
+        match self.ranges.get(idx) {
+            Some(range) => range.contains(&sequence_number),
+            None => false,
+        }
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<u64>] {
+        &self.ranges
+    }
+}
+
+/**
+ * The SequencedPayloads sent on a Tube that the peer hasn't selectively acked
+ * yet, retained so that they can be retransmitted.
+ */
+#[derive(Debug, Default)]
+pub struct UnackedSequencedPayloads {
+    /**
+     * The highest sequence number the peer has acked so far. Unacked payloads
+     * below it were skipped over by the peer, so they're considered gaps.
+     */
+    highest_acked: Option<u64>,
+    next_sequence_number: u64,
+    payloads: BTreeMap<u64, bytes::Bytes>,
+}
+impl UnackedSequencedPayloads {
+    pub fn new() -> Self {
+        UnackedSequencedPayloads {
+            highest_acked: None,
+            next_sequence_number: 0,
+            payloads: BTreeMap::new(),
+        }
+    }
+
+    /**
+     * Assigns the next sequence number to data and retains it until it is
+     * acked.
+     */
+    pub fn push(&mut self, data: bytes::Bytes) -> u64 {
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        self.payloads.insert(sequence_number, data);
+        sequence_number
+    }
+
+    /**
+     * Stops retaining every payload covered by the ranges of a SelectiveAck.
+     */
+    pub fn apply_selective_ack(&mut self, ranges: &[RangeInclusive<u64>]) {
+        for range in ranges {
+            let acked: Vec<u64> = self.payloads.range(range.clone())
+                .map(|(sequence_number, _)| *sequence_number)
+                .collect();
+            for sequence_number in acked {
+                self.payloads.remove(&sequence_number);
+            }
+            let is_highest = match self.highest_acked {
+                Some(highest_acked) => *range.end() > highest_acked,
+                None => true,
+            };
+            if is_highest {
+                self.highest_acked = Some(*range.end());
+            }
+        }
+    }
+
+    /**
+     * Unacked payloads that were sent before a payload the peer has acked,
+     * and so were most likely lost in transit.
+     */
+    pub fn gaps(&self) -> Vec<(u64, bytes::Bytes)> {
+        match self.highest_acked {
+            Some(highest_acked) => self.payloads.range(..highest_acked)
+                .map(|(sequence_number, data)| (*sequence_number, data.clone()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /**
+     * Every payload that hasn't been acked yet, in sequence order.
+     */
+    pub fn unacked(&self) -> Vec<(u64, bytes::Bytes)> {
+        self.payloads.iter()
+            .map(|(sequence_number, data)| (*sequence_number, data.clone()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod sequence_tracking_tests {
+    use super::*;
+
+    #[test]
+    fn received_sequences_merge_into_ranges() {
+        let mut received = ReceivedSequences::new();
+        for sequence_number in [0, 1, 5, 3, 7, 6] {
+            assert!(received.insert(sequence_number));
+        }
+        assert_eq!(received.ranges(), &[0..=1, 3..=3, 5..=7]);
+
+        assert!(!received.insert(6));
+        assert!(received.insert(2));
+        assert_eq!(received.ranges(), &[0..=3, 5..=7]);
+        assert!(received.insert(4));
+        assert_eq!(received.ranges(), &[0..=7]);
+
+        assert!(received.contains(7));
+        assert!(!received.contains(8));
+    }
+
+    #[test]
+    fn selective_acks_reveal_gaps() {
+        let mut unacked = UnackedSequencedPayloads::new();
+        for byte in 0..5u8 {
+            assert_eq!(unacked.push(vec![byte].into()), byte as u64);
+        }
+        assert!(unacked.gaps().is_empty());
+
+        // Payloads 1 and 2 were lost, 4 just hasn't been acked yet
+        unacked.apply_selective_ack(&[0..=0, 3..=3]);
+        assert_eq!(unacked.gaps(), vec![(1, vec![1].into()), (2, vec![2].into())]);
+        assert_eq!(unacked.len(), 3);
+
+        unacked.apply_selective_ack(&[0..=4]);
+        assert!(unacked.is_empty());
+        assert!(unacked.gaps().is_empty());
+    }
+}
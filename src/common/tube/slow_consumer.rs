@@ -0,0 +1,109 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use super::tube_manager::TubeManager;
+
+/**
+ * What's evicted once a Tube's consumer is found to be slow (see
+ * SlowConsumerLimit).
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlowConsumerAction {
+    /**
+     * Abort the slow consumer's Tube with AbortReason::SlowConsumer.
+     */
+    AbortTube,
+    /**
+     * Abort every unfinished Tube on the channel with
+     * AbortReason::SlowConsumer and close the channel, for applications that
+     * consume a channel's Tubes together (so that one stalled Tube means the
+     * whole channel is stuck).
+     */
+    AbortChannel,
+}
+
+/**
+ * Detects a consumer that has stopped taking events off of a Tube, so that
+ * payloads don't pile up in its queue indefinitely. A consumer is slow once
+ * either threshold given is exceeded. This is checked as each payload
+ * arrives for the Tube, and a ChannelEvent::SlowConsumer is queued on the
+ * channel when it acts.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlowConsumerLimit {
+    pub action: SlowConsumerAction,
+    /**
+     * The longest the consumer may leave events queued without taking one.
+     */
+    pub max_stall: Option<Duration>,
+    pub max_queued_events: Option<usize>,
+}
+impl SlowConsumerLimit {
+    /**
+     * Checks whether queueing another payload on tube_mgr would exceed this
+     * limit. Returns how many events the consumer has left queued and for
+     * how long if so.
+     */
+    pub(in crate) fn check(
+        &self,
+        tube_mgr: &TubeManager,
+        now: Instant,
+    ) -> Option<(usize, Duration)> {
+        let queued_events = tube_mgr.pending_events.len();
+        let stalled_for = match tube_mgr.stalled_since {
+            Some(stalled_since) => now.saturating_duration_since(stalled_since),
+            None => Duration::ZERO,
+        };
+        let too_many_queued = match self.max_queued_events {
+            Some(max_queued_events) => queued_events >= max_queued_events,
+            None => false,
+        };
+        let stalled_too_long = match self.max_stall {
+            Some(max_stall) => stalled_for > max_stall,
+            None => false,
+        };
+        if too_many_queued || stalled_too_long {
+            Some((queued_events, stalled_for))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod slow_consumer_tests {
+    use super::*;
+    use crate::common::tube::TubeEvent;
+
+    fn make_tube_mgr(num_queued: usize, stalled_since: Option<Instant>) -> TubeManager {
+        let mut tube_mgr = TubeManager::new();
+        for _ in 0..num_queued {
+            tube_mgr.pending_events.push_back(TubeEvent::Payload(vec![0].into()));
+        }
+        tube_mgr.stalled_since = stalled_since;
+        tube_mgr
+    }
+
+    #[test]
+    fn consumers_are_slow_once_either_threshold_is_exceeded() {
+        let now = Instant::now();
+        let limit = SlowConsumerLimit {
+            action: SlowConsumerAction::AbortTube,
+            max_stall: Some(Duration::from_secs(5)),
+            max_queued_events: Some(3),
+        };
+
+        assert_eq!(limit.check(&make_tube_mgr(0, None), now), None);
+        let stalled_since = now - Duration::from_secs(1);
+        assert_eq!(limit.check(&make_tube_mgr(2, Some(stalled_since)), now), None);
+        assert_eq!(
+            limit.check(&make_tube_mgr(3, Some(stalled_since)), now),
+            Some((3, Duration::from_secs(1))),
+        );
+        let stalled_since = now - Duration::from_secs(6);
+        assert_eq!(
+            limit.check(&make_tube_mgr(1, Some(stalled_since)), now),
+            Some((1, Duration::from_secs(6))),
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::common::frame;
+use crate::common::PeerType;
+use super::error;
+use super::send_acks::SendAcks;
+use super::tube::finish_dropped_tube;
+use super::tube::poll_next_tube_event;
+use super::tube::Tube;
+use super::tube_manager::TubeManager;
+use super::TubeEvent;
+
+/**
+ * The receiving half of a split Tube (see Tube::split()). Yields the Tube's
+ * TubeEvents.
+ */
+#[derive(Debug)]
+pub struct TubeReader {
+    peer_type: PeerType,
+    sender: frame::FrameSender,
+    tube_id: u32,
+    tube_manager: Arc<Mutex<TubeManager>>,
+}
+impl TubeReader {
+    pub(in crate::common::tube) fn new(
+        peer_type: PeerType,
+        tube_id: u32,
+        tube_manager: Arc<Mutex<TubeManager>>,
+        sender: frame::FrameSender,
+    ) -> Self {
+        TubeReader {
+            peer_type,
+            sender,
+            tube_id,
+            tube_manager,
+        }
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.tube_id
+    }
+}
+impl futures::stream::Stream for TubeReader {
+    type Item = TubeEvent;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Option<Self::Item>> {
+        poll_next_tube_event(
+            self.peer_type, 
+            self.tube_id, 
+            &self.tube_manager, 
+            &self.sender,
+            cx,
+        )
+    }
+}
+impl Drop for TubeReader {
+    fn drop(&mut self) {
+        let deferred_drop = {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            tube_mgr.split_reader_alive = false;
+            tube_mgr.deferred_drop.take()
+        };
+
+        // If the TubeWriter was dropped first, it left finishing the Tube to 
+        // us.
+        if let Some(deferred_drop) = deferred_drop {
+            finish_dropped_tube(
+                deferred_drop.peer_type,
+                deferred_drop.tube_id,
+                self.tube_manager.clone(),
+                deferred_drop.sender,
+            );
+        }
+    }
+}
+
+/**
+ * The sending half of a split Tube (see Tube::split()). Like Tube, it's a 
+ * Sink of payloads.
+ */
+#[derive(Debug)]
+pub struct TubeWriter {
+    tube: Tube,
+}
+impl TubeWriter {
+    pub(in crate::common::tube) fn new(tube: Tube) -> Self {
+        TubeWriter {
+            tube,
+        }
+    }
+
+    pub fn ack_timeout(&self) -> Duration {
+        self.tube.ack_timeout()
+    }
+
+    pub async fn abort(&mut self) -> Result<(), error::AbortError> {
+        self.tube.abort().await
+    }
+
+    pub async fn abort_with_code(
+        &mut self,
+        code: u32,
+        message: Option<String>,
+    ) -> Result<(), error::AbortError> {
+        self.tube.abort_with_code(code, message).await
+    }
+
+    pub async fn abort_with_reason(
+        &mut self,
+        reason: crate::common::frame::AbortReason,
+    ) -> Result<(), error::AbortError> {
+        self.tube.abort_with_reason(reason).await
+    }
+
+    pub fn event_queue_limit(&self) -> Option<super::EventQueueLimit> {
+        self.tube.event_queue_limit()
+    }
+
+    pub fn extensions(&self) -> &hyper::http::Extensions {
+        self.tube.extensions()
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut hyper::http::Extensions {
+        self.tube.extensions_mut()
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.tube.get_id()
+    }
+
+    pub async fn has_finished_sending(&mut self) -> Result<(), error::HasFinishedSendingError> {
+        self.tube.has_finished_sending().await
+    }
+
+    pub fn headers(&self) -> &std::collections::HashMap<String, Vec<u8>> {
+        self.tube.headers()
+    }
+
+    pub fn idle_timeout(&self) -> Option<super::IdleTimeout> {
+        self.tube.idle_timeout()
+    }
+
+    pub fn in_flight_bytes(&self) -> usize {
+        self.tube.in_flight_bytes()
+    }
+
+    pub fn is_receive_only(&self) -> bool {
+        self.tube.is_receive_only()
+    }
+
+    pub fn max_in_flight_bytes(&self) -> usize {
+        self.tube.max_in_flight_bytes()
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.tube.priority()
+    }
+
+    pub async fn retransmit_sequence_gaps(&mut self) -> Result<usize, error::SendError> {
+        self.tube.retransmit_sequence_gaps().await
+    }
+
+    pub async fn retransmit_unacked_sequenced(&mut self) -> Result<usize, error::SendError> {
+        self.tube.retransmit_unacked_sequenced().await
+    }
+
+    pub async fn send(
+        &mut self,
+        data: impl Into<bytes::Bytes>,
+        ack_timeout: Duration,
+    ) -> Result<(), error::SendError> {
+        self.tube.send(data, ack_timeout).await
+    }
+
+    pub async fn send_acked(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        self.tube.send_acked(data).await
+    }
+
+    pub async fn send_and_forget(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        self.tube.send_and_forget(data).await
+    }
+
+    pub async fn send_pipelined(
+        &mut self,
+        payloads: Vec<impl Into<bytes::Bytes>>,
+        ack_timeout: Duration,
+    ) -> Result<SendAcks, error::SendError> {
+        self.tube.send_pipelined(payloads, ack_timeout).await
+    }
+
+    pub async fn send_stream(
+        &mut self,
+        reader: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<u64, error::SendStreamError> {
+        self.tube.send_stream(reader).await
+    }
+
+    pub async fn send_sequenced(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<u64, error::SendError> {
+        self.tube.send_sequenced(data).await
+    }
+
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.tube.set_ack_timeout(ack_timeout)
+    }
+
+    pub fn set_event_queue_limit(&mut self, limit: Option<super::EventQueueLimit>) {
+        self.tube.set_event_queue_limit(limit)
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<super::IdleTimeout>) {
+        self.tube.set_idle_timeout(idle_timeout)
+    }
+
+    pub fn set_max_in_flight_bytes(&mut self, max_bytes: usize) {
+        self.tube.set_max_in_flight_bytes(max_bytes)
+    }
+
+    pub fn set_priority(&mut self, priority: u8) {
+        self.tube.set_priority(priority)
+    }
+
+    pub fn writer(&self) -> super::TubeWriteHandle {
+        self.tube.writer()
+    }
+}
+impl futures::sink::Sink<bytes::Bytes> for TubeWriter {
+    type Error = error::SinkError;
+
+    fn poll_ready(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        core::pin::Pin::new(&mut self.tube).poll_ready(cx)
+    }
+
+    fn start_send(
+        mut self: core::pin::Pin<&mut Self>, 
+        data: bytes::Bytes,
+    ) -> Result<(), Self::Error> {
+        core::pin::Pin::new(&mut self.tube).start_send(data)
+    }
+
+    fn poll_flush(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        core::pin::Pin::new(&mut self.tube).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        core::pin::Pin::new(&mut self.tube).poll_close(cx)
+    }
+}
@@ -1,18 +1,35 @@
 use futures;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
 
 use crate::common::frame;
-use crate::common::InvertedFuture;
 use crate::common::PeerType;
 use crate::common::UniqueId;
 use crate::common::UniqueIdError;
 use crate::common::UniqueIdManager;
 use super::TubeEvent;
+use super::TubeEvent_StreamError;
 use super::TubeEventTag;
+use super::event_queue::EventQueueLimit;
+use super::idle_timeout;
+use super::idle_timeout::IdleTimeout;
+use super::send_acks::SendAcks;
+use super::send_window::SendWindow;
+use super::send_window::DEFAULT_MAX_IN_FLIGHT_BYTES;
+use super::split::TubeReader;
+use super::split::TubeWriter;
+use super::tube_io::TubeIo;
+use super::tube_manager::DeferredTubeDrop;
+use super::tube_manager::SendAck;
 use super::tube_manager::TubeCompletionState;
 use super::tube_manager::TubeManager;
+use super::write_handle::split_payload;
+use super::write_handle::TubeWriteHandle;
 
 pub mod error {
     use super::Duration;
@@ -22,39 +39,159 @@ pub mod error {
     pub enum AbortError {
         AlreadyAborted(frame::AbortReason),
         AlreadyClosed,
+        ChannelClosed,
         FrameEncodeError(frame::encode::FrameEncodeError),
-        FatalTransportError(hyper::Error),
+        FrameVetoed(String),
+        FatalTransportError(frame::TransportError),
+    }
+    impl From<frame::FrameSendError> for AbortError {
+        fn from(e: frame::FrameSendError) -> Self {
+            match e {
+                frame::FrameSendError::ChannelClosed => AbortError::ChannelClosed,
+                frame::FrameSendError::FrameEncodeError(e) => AbortError::FrameEncodeError(e),
+                frame::FrameSendError::FrameVetoed(reason) => AbortError::FrameVetoed(reason),
+                frame::FrameSendError::TransportError(e) => AbortError::FatalTransportError(e),
+            }
+        }
     }
 
     #[derive(Debug)]
     pub enum HasFinishedSendingError {
         AlreadyMarkedAsFinishedSending,
+        ChannelClosed,
         FrameEncodeError(frame::encode::FrameEncodeError),
+        FrameVetoed(String),
         InternalError(String),
-        FatalTransportError(hyper::Error),
+        FatalTransportError(frame::TransportError),
         TubeAlreadyAborted(frame::AbortReason),
     }
 
+    #[derive(Debug)]
+    pub enum SendStreamError {
+        HasFinishedSendingError(HasFinishedSendingError),
+        ReadError(std::io::Error),
+        SendError(SendError),
+    }
+
+    #[derive(Debug)]
+    pub enum SinkError {
+        HasFinishedSendingError(HasFinishedSendingError),
+        SendError(SendError),
+    }
+
     #[derive(Debug)]
     pub enum SendError {
         AckIdAlreadyInUseInternalError,
         AckIdsExhausted,
+        /**
+         * The Tube's Channel has been closed (see Channel::close()).
+         */
+        ChannelClosed,
         FrameEncodeError(frame::encode::FrameEncodeError),
+        FrameVetoed(String),
+        /**
+         * The peer received the payload corrupted, so it was neither 
+         * delivered nor acked. Carries the peer's description of the 
+         * mismatch.
+         */
+        PayloadChecksumMismatch(String),
         TimedOutWaitingOnAck(Duration),
-        TransportError(hyper::Error),
+        TransportError(frame::TransportError),
+        /**
+         * Sent through a TubeWriteHandle after the Tube finished sending (or 
+         * was closed, aborted, or dropped).
+         */
+        TubeNotWritable,
         UnknownTransportError,
     }
+    impl From<frame::FrameSendError> for SendError {
+        fn from(e: frame::FrameSendError) -> Self {
+            match e {
+                frame::FrameSendError::ChannelClosed => SendError::ChannelClosed,
+                frame::FrameSendError::FrameEncodeError(e) => SendError::FrameEncodeError(e),
+                frame::FrameSendError::FrameVetoed(reason) => SendError::FrameVetoed(reason),
+                frame::FrameSendError::TransportError(e) => SendError::TransportError(e),
+            }
+        }
+    }
+}
+
+/**
+ * The most data Tube::send_stream() sends in a single Payload frame.
+ */
+pub const SEND_STREAM_CHUNK_BYTES: usize = 16 * 1024;
+
+/**
+ * How long Tube::send_acked() waits for the peer to ack a payload unless the
+ * Tube is configured otherwise (see Tube::set_ack_timeout()).
+ */
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/**
+ * Specifying this NewTube header (with a value of 
+ * PAYLOAD_CHECKSUM_HEADER_CRC32) enables payload integrity checksums for the 
+ * Tube: both peers attach a CRC-32 to every Payload frame they send, and 
+ * payloads that arrive with a mismatched checksum are surfaced as a 
+ * TubeEvent::StreamError(PayloadChecksumMismatch) instead of a 
+ * TubeEvent::Payload. Rather than acking such a payload, the receiver sends 
+ * an Error(PayloadChecksumMismatch) for it, which fails the send awaiting its
+ * ack with SendError::PayloadChecksumMismatch.
+ */
+pub const PAYLOAD_CHECKSUM_HEADER: &str = "tubez-payload-checksum";
+pub const PAYLOAD_CHECKSUM_HEADER_CRC32: &str = "crc32";
+
+pub(in crate) fn payload_checksums_requested(headers: &HashMap<String, Vec<u8>>) -> bool {
+    match headers.get(PAYLOAD_CHECKSUM_HEADER) {
+        Some(value) => value == PAYLOAD_CHECKSUM_HEADER_CRC32.as_bytes(),
+        None => false,
+    }
+}
+
+/**
+ * Specifying this NewTube header (with a value of "1") makes PayloadAcks on 
+ * the Tube cumulative: rather than acking every payload individually, the 
+ * receiver acks only the latest payload in each chunk of frames it reads, 
+ * which resolves the sends of that payload and every payload sent before it.
+ * This roughly halves control traffic for streaming workloads.
+ *
+ * A payload that fails its checksum (see PAYLOAD_CHECKSUM_HEADER) is 
+ * reported as soon as it's received, ahead of the cumulative ack that's sent 
+ * for the payloads around it, so its send still fails.
+ */
+pub const CUMULATIVE_ACKS_HEADER: &str = "tubez-cumulative-acks";
+
+pub(in crate) fn cumulative_acks_requested(headers: &HashMap<String, Vec<u8>>) -> bool {
+    match headers.get(CUMULATIVE_ACKS_HEADER) {
+        Some(value) => value == b"1",
+        None => false,
+    }
+}
+
+/**
+ * Specifying this NewTube header (with a value of "1") marks the Tube as 
+ * receive-only for the client: the client finishes sending immediately and 
+ * the server streams payloads for as long as it likes (e.g. server-push 
+ * feeds). Servers can check Tube::is_receive_only() to route these Tubes 
+ * differently.
+ */
+pub const RECEIVE_ONLY_HEADER: &str = "tubez-receive-only";
+
+pub(in crate) fn receive_only_requested(headers: &HashMap<String, Vec<u8>>) -> bool {
+    match headers.get(RECEIVE_ONLY_HEADER) {
+        Some(value) => value == b"1",
+        None => false,
+    }
 }
 
 async fn send_abort(
     tube_id: &mut UniqueId,
     reason: frame::AbortReason,
     tube_manager: &Arc<Mutex<TubeManager>>,
-    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    sender: &frame::FrameSender,
 ) -> Result<(), error::AbortError> {
-    let frame_data = match frame::encode::abort_frame(tube_id.val(), reason.clone()) {
-        Ok(frame_data) => frame_data,
-        Err(e) => return Err(error::AbortError::FrameEncodeError(e)),
+    let abort_frame = frame::Frame::Abort {
+        tube_id: tube_id.val(),
+        reason: reason.clone(),
     };
 
     {
@@ -70,47 +207,40 @@ async fn send_abort(
             _ => (),
         };
 
-        tube_mgr.completion_state = TubeCompletionState::AbortedFromLocal(reason);
+        tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(reason));
         log::trace!("Tracking Tube(id={}) as a pending abort...", tube_id);
         tube_mgr.abort_pending_id_reservation = Some(tube_id.take());
     };
 
     // TODO: Stick a timeout on these awaits so that some kind of pathological 
     //       hyper issue doesn't block the tube_mgr Mutex forever or something
-    let mut sender = sender.lock().await;
     log::trace!("Sending Abort(tube_id={})...", tube_id);
-    match sender.send_data(frame_data.into()).await {
+    match sender.send(abort_frame).await {
         Ok(_) => Ok(()),
         // TODO: Should this just be a panic? If we get into this state we don't
         //       really know if the client and server are synchronized on the 
         //       state of this Tube...havoc?
-        Err(e) => Err(error::AbortError::FatalTransportError(e)),
+        Err(e) => Err(e.into()),
     }
 
-    // TODO: !!! Need to somehow remove this tube from the channel's map of 
-    //           TubeManagers! As-is, we only remove from that map if we recieve and Abort or a
-    //           closing HasFinishedSending from the peer...but not if we initiate full closure
-    //           from the local Tube object!!
+    // The TubeManager stays in the channel's map of TubeManagers until the 
+    // peer's AbortAck arrives (see handle_abort_ack()).
 }
 
 async fn send_has_finished_sending(
     peer_type: PeerType,
     tube_id: &mut UniqueId,
     tube_manager: &Arc<Mutex<TubeManager>>,
-    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    sender: &frame::FrameSender,
 ) -> Result<(), error::HasFinishedSendingError> {
-    let maybe_frame_data = match peer_type {
+    let finished_frame = match peer_type {
         PeerType::Client => 
-            frame::encode::client_has_finished_sending_frame(tube_id.val()),
+            frame::Frame::ClientHasFinishedSending { tube_id: tube_id.val() },
         PeerType::Server => 
-            frame::encode::server_has_finished_sending_frame(tube_id.val()),
-    };
-    let frame_data = match maybe_frame_data {
-        Ok(data) => data,
-        Err(e) => return Err(error::HasFinishedSendingError::FrameEncodeError(e)),
+            frame::Frame::ServerHasFinishedSending { tube_id: tube_id.val() },
     };
 
-    {
+    let closed = {
         let mut tube_mgr = tube_manager.lock().unwrap();
         use TubeCompletionState::*;
         use PeerType::*;
@@ -130,20 +260,19 @@ async fn send_has_finished_sending(
                 return Err(error::HasFinishedSendingError::TubeAlreadyAborted(reason.clone())),
         };
 
-        tube_mgr.completion_state = new_state;
+        let closed = new_state == Closed;
+        tube_mgr.set_completion_state(new_state);
+        closed
     };
 
     // TODO: Stick a timeout on these awaits so that some kind of pathological 
     //       hyper issue doesn't block the tube_mgr Mutex forever or something
-    let transport_error = {
-        let mut sender = sender.lock().await;
-        sender.send_data(frame_data.into()).await
-    };
+    let send_result = sender.send(finished_frame).await;
 
     // If the transmit failed, we can't be certain if the HasFinishedSending was
     // actually received by the peer...so [try to] abort the Tube before 
-    // returning a transport error.
-    if let Err(e) = transport_error {
+    // returning an error.
+    if let Err(e) = send_result {
         let _ = send_abort(
             tube_id, 
             frame::AbortReason::TransportErrorWhileSynchronizingTubeState,
@@ -154,33 +283,88 @@ async fn send_has_finished_sending(
         // At this point the Tube is considered terminal in an Aborted state and
         // cannot send or receive data. The application must be replace it with
         // a new Tube.
-        return Err(error::HasFinishedSendingError::FatalTransportError(e));
+        return Err(match e {
+            frame::FrameSendError::ChannelClosed => 
+                error::HasFinishedSendingError::ChannelClosed,
+            frame::FrameSendError::FrameEncodeError(e) => 
+                error::HasFinishedSendingError::FrameEncodeError(e),
+            frame::FrameSendError::FrameVetoed(reason) => 
+                error::HasFinishedSendingError::FrameVetoed(reason),
+            frame::FrameSendError::TransportError(e) => 
+                error::HasFinishedSendingError::FatalTransportError(e),
+        });
     }
 
-    // TODO: !!! Need to somehow remove this tube from the channel's map of 
-    //           TubeManagers if it is now fully Closed! As-is, we only remove 
-    //           from that map if we recieve and Abort or a closing 
-    //           HasFinishedSending from the peer...but not if we initiate full
-    //           closure from the local Tube object!!
+    // Both sides have now finished sending, so the channel no longer needs to
+    // route frames to this Tube.
+    if closed {
+        TubeManager::untrack(tube_manager, tube_id.val());
+    }
 
     Ok(())
 }
 
+/**
+ * The send (and the HasFinishedSending) that a Tube's Sink impl has started 
+ * but not yet finished.
+ */
+#[derive(Default)]
+struct PendingSinkOps {
+    close: Option<BoxFuture<'static, (UniqueId, Result<(), error::HasFinishedSendingError>)>>,
+    send: Option<BoxFuture<'static, Result<(), error::SendError>>>,
+}
+impl std::fmt::Debug for PendingSinkOps {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PendingSinkOps")
+            .field("close", &self.close.is_some())
+            .field("send", &self.send.is_some())
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct Tube {
-    ackid_manager: UniqueIdManager,
-    last_tube_event: Option<TubeEventTag>,
-    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    ack_timeout: Duration,
+    ackid_manager: Arc<Mutex<UniqueIdManager>>,
+    extensions: hyper::http::Extensions,
+    headers: HashMap<String, Vec<u8>>,
+    payload_checksums: bool,
+    pending_sink_ops: PendingSinkOps,
+    priority: u8,
+    receive_only: bool,
+    send_window: SendWindow,
+    sender: frame::FrameSender,
     tube_id: UniqueId,
     tube_manager: Arc<Mutex<TubeManager>>,
     peer_type: PeerType,
 }
 impl Tube {
     pub async fn abort(&mut self) -> Result<(), error::AbortError> {
-        self.abort_internal(frame::AbortReason::ApplicationAbort).await
+        self.abort_with_reason(frame::AbortReason::ApplicationAbort).await
+    }
+
+    /**
+     * Aborts the Tube with an application-defined code and optional message,
+     * which the peer receives as 
+     * TubeEvent::Abort(AbortReason::ApplicationDefined { code, message }).
+     */
+    pub async fn abort_with_code(
+        &mut self,
+        code: u32,
+        message: Option<String>,
+    ) -> Result<(), error::AbortError> {
+        self.abort_with_reason(frame::AbortReason::ApplicationDefined { 
+            code, 
+            message,
+        }).await
     }
     
-    pub(in crate) async fn abort_internal(
+    /**
+     * Aborts the Tube with the given reason: sends an Abort frame to the peer
+     * and marks the Tube as AbortedFromLocal. The TubeId stays reserved until
+     * the peer's AbortAck arrives.
+     */
+    pub async fn abort_with_reason(
         &mut self, 
         reason: frame::AbortReason,
     ) -> Result<(), error::AbortError> {
@@ -192,10 +376,237 @@ impl Tube {
         ).await
     }
 
-    pub fn get_id(&self) -> u16 {
+    /**
+     * How long send_acked() waits for the peer to ack each payload.
+     */
+    pub fn ack_timeout(&self) -> Duration {
+        self.ack_timeout
+    }
+
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.ack_timeout = ack_timeout;
+    }
+
+    /**
+     * A typed map where middleware and routers can stash per-tube context 
+     * (auth principal, tenant, trace span, etc) for downstream handlers.
+     */
+    pub fn extensions(&self) -> &hyper::http::Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut hyper::http::Extensions {
+        &mut self.extensions
+    }
+
+    /**
+     * The headers the client specified when it created this Tube. Header 
+     * values are arbitrary bytes (though they must be UTF-8 on channels that 
+     * negotiated a FramingVersion older than V3).
+     */
+    pub fn headers(&self) -> &HashMap<String, Vec<u8>> {
+        &self.headers
+    }
+
+    pub fn get_id(&self) -> u32 {
         return self.tube_id.val();
     }
 
+    /**
+     * The number of payload bytes that have been handed to this Tube for 
+     * sending but have not yet been flushed to the transport (for 
+     * send_and_forget()) or acked by the peer (for send()).
+     */
+    pub fn in_flight_bytes(&self) -> usize {
+        self.send_window.in_flight_bytes()
+    }
+
+    pub fn max_in_flight_bytes(&self) -> usize {
+        self.send_window.max_bytes()
+    }
+
+    /**
+     * Sets the cap on in_flight_bytes(). Once the cap is reached, send() and 
+     * send_and_forget() wait for earlier payloads to make progress before 
+     * sending more. Payloads already in flight continue to count against the
+     * previous cap.
+     */
+    pub fn set_max_in_flight_bytes(&mut self, max_bytes: usize) {
+        self.send_window = SendWindow::new(max_bytes);
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /**
+     * Weights this Tube's payloads against those of the channel's other
+     * Tubes. When several Tubes have payloads waiting to be written, they
+     * take turns, and each turn a Tube gets up to `priority` writes in a row.
+     * Tubes start at priority 1 (and 0 is treated as 1).
+     */
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+    }
+
+    /**
+     * Whether the client created this Tube as receive-only (see 
+     * RECEIVE_ONLY_HEADER), meaning that only the server will send payloads.
+     */
+    pub fn is_receive_only(&self) -> bool {
+        self.receive_only
+    }
+
+    /**
+     * Converts the Tube into a TubeIo, which implements tokio's AsyncRead and
+     * AsyncWrite over the Tube's payloads.
+     */
+    pub fn into_io(self) -> TubeIo {
+        TubeIo::new(self)
+    }
+
+    /**
+     * Frames the Tube's byte stream (see into_io()) with a tokio_util codec, 
+     * so existing Encoder/Decoder implementations (e.g. LinesCodec or 
+     * LengthDelimitedCodec) can be used on it unchanged.
+     */
+    #[cfg(feature = "codec")]
+    pub fn framed<C>(self, codec: C) -> tokio_util::codec::Framed<TubeIo, C> {
+        tokio_util::codec::Framed::new(self.into_io(), codec)
+    }
+
+    pub fn event_queue_limit(&self) -> Option<EventQueueLimit> {
+        self.tube_manager.lock().unwrap().event_queue_limit
+    }
+
+    /**
+     * Bounds how many TubeEvents this Tube queues while its consumer isn't 
+     * keeping up, and what happens to payloads that arrive once the queue is
+     * full (see EventQueueOverflowPolicy). None queues them without limit.
+     * Tubes start with their channel's limit (see 
+     * Channel::set_event_queue_limit()).
+     */
+    pub fn set_event_queue_limit(&mut self, limit: Option<EventQueueLimit>) {
+        let mut tube_mgr = self.tube_manager.lock().unwrap();
+        tube_mgr.event_queue_limit = limit;
+        // The new limit may leave room for a payload the channel is blocked on
+        tube_mgr.event_queue_space.notify_one();
+    }
+
+    /**
+     * Pauses the receive side of the Tube: payloads that arrive while it's 
+     * paused are still queued as TubeEvents, but their acks are withheld 
+     * until resume() is called. Senders that wait on acks (and so are bound 
+     * by their send window) slow down accordingly instead of the payloads 
+     * being buffered here without bound.
+     *
+     * Payloads sent with send_and_forget() aren't acked, so pausing doesn't
+     * hold them back.
+     */
+    pub fn pause(&mut self) {
+        self.tube_manager.lock().unwrap().paused = true;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.tube_manager.lock().unwrap().paused
+    }
+
+    /**
+     * Resumes a paused Tube, sending the acks it withheld while it was 
+     * paused.
+     */
+    pub async fn resume(&mut self) -> Result<(), error::SendError> {
+        let withheld_acks = self.tube_manager.lock().unwrap().resume();
+        if withheld_acks.is_empty() {
+            return Ok(());
+        }
+
+        let tube_id = self.tube_id.val();
+        let ack_frames = withheld_acks.into_iter()
+            .map(|ack_id| frame::Frame::PayloadAck { tube_id, ack_id })
+            .collect();
+        match self.sender.send_batch(ack_frames).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /**
+     * Returns a cloneable TubeWriteHandle that other tasks can use to send 
+     * payloads on this Tube while this Tube keeps its read side (e.g. to 
+     * fan several producers into one Tube).
+     */
+    pub fn writer(&self) -> TubeWriteHandle {
+        TubeWriteHandle {
+            ack_timeout: self.ack_timeout,
+            ackid_manager: self.ackid_manager.clone(),
+            payload_checksums: self.payload_checksums,
+            peer_type: self.peer_type,
+            priority: self.priority,
+            send_window: self.send_window.clone(),
+            sender: self.sender.clone(),
+            tube_id: self.tube_id.val(),
+            tube_manager: self.tube_manager.clone(),
+        }
+    }
+
+    /**
+     * Splits the Tube into a TubeReader (which yields its TubeEvents) and a
+     * TubeWriter (which sends on it) so that one task can consume events 
+     * while another sends, without sharing the Tube behind a Mutex.
+     *
+     * The halves share the Tube's state, so e.g. the TubeReader ends once 
+     * the writer has finished sending and the peer has too. The Tube is only
+     * finished (or aborted) on drop once both halves have been dropped; 
+     * dropping just the TubeWriter does not mark it as having finished 
+     * sending.
+     */
+    pub fn split(self) -> (TubeReader, TubeWriter) {
+        self.tube_manager.lock().unwrap().split_reader_alive = true;
+        let reader = TubeReader::new(
+            self.peer_type, 
+            self.tube_id.val(), 
+            self.tube_manager.clone(),
+            self.sender.clone(),
+        );
+        (reader, TubeWriter::new(self))
+    }
+
+    /**
+     * Watches for the peer going silent: once the Tube goes 
+     * idle_timeout.duration without receiving a payload, it emits a 
+     * TubeEvent::IdleTimeout (and aborts itself if the action is 
+     * IdleTimeoutAction::Abort). This is typically set right after the Tube 
+     * is made; the idle period starts over whenever it's set. None disables
+     * the idle timeout.
+     */
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<IdleTimeout>) {
+        let (generation, executor) = {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            tube_mgr.idle_timeout = idle_timeout;
+            tube_mgr.idle_timer_generation += 1;
+            tube_mgr.record_payload_received();
+            (tube_mgr.idle_timer_generation, tube_mgr.executor.clone())
+        };
+        if let Some(idle_timeout) = idle_timeout {
+            executor.spawn(idle_timeout::run_idle_timer(
+                idle_timeout,
+                generation,
+                self.tube_id.val(),
+                Arc::downgrade(&self.tube_manager),
+                self.sender.downgrade(),
+            ));
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Option<IdleTimeout> {
+        self.tube_manager.lock().unwrap().idle_timeout
+    }
+
+    pub(in crate) fn tube_manager(&self) -> &Arc<Mutex<TubeManager>> {
+        &self.tube_manager
+    }
+
     pub async fn has_finished_sending(&mut self) -> Result<(), error::HasFinishedSendingError> {
         send_has_finished_sending(
             self.peer_type,
@@ -208,12 +619,30 @@ impl Tube {
     pub(in crate) fn new(
         peer_type: PeerType,
         tube_id: UniqueId,
-        sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>, 
+        headers: HashMap<String, Vec<u8>>,
+        sender: frame::FrameSender, 
         tube_manager: Arc<Mutex<TubeManager>>,
     ) -> Self {
+        let (ack_timeout, payload_checksums, receive_only) = {
+            let tube_mgr = tube_manager.lock().unwrap();
+            let ack_timeout = match tube_mgr.ack_timeout_policy {
+                Some(policy) => policy.timeout,
+                None => DEFAULT_ACK_TIMEOUT,
+            };
+            (ack_timeout, tube_mgr.payload_checksums, tube_mgr.receive_only)
+        };
         Tube {
-            ackid_manager: UniqueIdManager::new(),
-            last_tube_event: None,
+            ack_timeout,
+            ackid_manager: Arc::new(Mutex::new(
+                UniqueIdManager::new().with_max_id(frame::MAX_ACK_ID.into()),
+            )),
+            extensions: hyper::http::Extensions::new(),
+            headers,
+            payload_checksums,
+            pending_sink_ops: PendingSinkOps::default(),
+            priority: frame::DEFAULT_PRIORITY,
+            receive_only,
+            send_window: SendWindow::new(DEFAULT_MAX_IN_FLIGHT_BYTES),
             sender,
             tube_id,
             tube_manager,
@@ -221,68 +650,238 @@ impl Tube {
         }
     }
 
+    /**
+     * Sends data and waits up to ack_timeout for the peer to ack it. Data
+     * larger than the channel's max payload frame size (see
+     * frame::MAX_PAYLOAD_FRAME_SIZE_HEADER) is sent in several frames, 
+     * which the peer reassembles and receives as a single 
+     * TubeEvent::Payload.
+     */
     pub async fn send(
         &mut self, 
-        data: Vec<u8>,
+        data: impl Into<bytes::Bytes>,
         ack_timeout: Duration,
     ) -> Result<(), error::SendError> {
-        let ack_id = match self.ackid_manager.take_id() {
-            Ok(ack_id) => ack_id,
-            Err(UniqueIdError::NoIdsAvailable) => return Err(error::SendError::AckIdsExhausted),
-        };
+        self.writer().send_unchecked(data.into(), ack_timeout).await
+    }
 
-        let frame_data = match frame::encode::payload_frame(
-            self.tube_id.val(), 
-            Some(ack_id.val()), 
-            data,
-        ) {
-            Ok(frame_data) => frame_data,
-            Err(e) => return Err(error::SendError::FrameEncodeError(e)),
-        };
+    /**
+     * Like send(), but waits for the ack for as long as this Tube's 
+     * ack_timeout() (so that a lost ack can't hang the send forever).
+     */
+    pub async fn send_acked(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        let ack_timeout = self.ack_timeout;
+        self.send(data, ack_timeout).await
+    }
 
-        let (sendack_future, sendack_resolver) = InvertedFuture::<()>::new();
+    /**
+     * Sends a batch of payloads with a single write to the transport and 
+     * returns a SendAcks stream that yields a completion notification for 
+     * each payload, in order, as the peer acks them. This avoids a round of
+     * task wakeups and lock acquisitions per payload for high-throughput 
+     * producers, and the returned SendAcks doesn't borrow the Tube, so the 
+     * next batch can be sent while acks for this one are outstanding.
+     *
+     * ack_timeout applies to the batch as a whole, starting once the batch 
+     * has been handed to the transport.
+     */
+    pub async fn send_pipelined(
+        &mut self,
+        payloads: Vec<impl Into<bytes::Bytes>>,
+        ack_timeout: Duration,
+    ) -> Result<SendAcks, error::SendError> {
+        let payloads: Vec<bytes::Bytes> = payloads.into_iter().map(Into::into).collect();
+        let mut ack_ids = Vec::with_capacity(payloads.len());
         {
-            let mut tube_mgr = self.tube_manager.lock().unwrap();
-            if let Err(_) = tube_mgr.sendacks.try_insert(ack_id.val(), sendack_resolver) {
-                return Err(error::SendError::AckIdAlreadyInUseInternalError)
+            let mut ackid_manager = self.ackid_manager.lock().unwrap();
+            for _ in 0..payloads.len() {
+                match ackid_manager.take_id() {
+                    Ok(ack_id) => ack_ids.push(ack_id),
+                    Err(UniqueIdError::NoIdsAvailable) => 
+                        return Err(error::SendError::AckIdsExhausted),
+                }
             }
         }
 
+        // The whole batch is sent at once, so it is admitted to the send 
+        // window all at once too (a batch larger than the window waits for 
+        // the window to empty).
+        let num_bytes = payloads.iter().map(|data| data.len()).sum();
+        let send_window_permit = self.send_window.acquire(num_bytes).await;
+
+        let writer = self.writer();
+        let mut payload_frames = Vec::with_capacity(payloads.len());
+        let mut pending: VecDeque<(UniqueId, SendAck)> = VecDeque::with_capacity(payloads.len());
         {
-            let mut sender = self.sender.lock().await;
-            if let Err(e) = sender.send_data(frame_data.into()).await {
-                let mut tube_mgr = self.tube_manager.lock().unwrap();
-                tube_mgr.sendacks.remove(&ack_id.val());
-                return Err(error::SendError::TransportError(e))
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            for (ack_id, data) in ack_ids.into_iter().zip(payloads) {
+                // ackid_manager is capped at frame::MAX_ACK_ID, so this always
+                // fits.
+                let ack_id_val = ack_id.val() as u16;
+                let sendack = match tube_mgr.insert_sendack(ack_id_val) {
+                    Some(sendack) => sendack,
+                    None => {
+                        for (ack_id, _) in pending {
+                            tube_mgr.remove_sendack(ack_id.val() as u16);
+                        }
+                        return Err(error::SendError::AckIdAlreadyInUseInternalError);
+                    },
+                };
+                payload_frames.extend(writer.make_payload_frames(Some(ack_id_val), data));
+                pending.push_back((ack_id, sendack));
             }
-        };
-
-        let sendack_future_with_timeout = 
-            tokio::time::timeout(ack_timeout, sendack_future);
-        let sendack_future_result = sendack_future_with_timeout.await;
+        }
 
-        {
+        let send_result = self.sender.send_batch_with_priority(payload_frames, self.priority).await;
+        if let Err(e) = send_result {
             let mut tube_mgr = self.tube_manager.lock().unwrap();
-            tube_mgr.sendacks.remove(&ack_id.val());
+            for (ack_id, _) in pending {
+                tube_mgr.remove_sendack(ack_id.val() as u16);
+            }
+            return Err(e.into());
         }
+        self.tube_manager.lock().unwrap().payload_bytes_sent += num_bytes as u64;
+
+        Ok(SendAcks::new(
+            self.tube_id.val(),
+            pending,
+            ack_timeout,
+            self.sender.clone(),
+            self.tube_manager.clone(),
+            send_window_permit,
+        ))
+    }
+
+    pub async fn send_and_forget(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        self.writer().send_and_forget_unchecked(data.into()).await
+    }
+
+    /**
+     * Reads from reader until it's exhausted, sending what's read as Payload 
+     * frames of up to SEND_STREAM_CHUNK_BYTES each (waiting for room in the 
+     * send window between them), then marks the Tube as having finished 
+     * sending. Returns the number of bytes sent.
+     */
+    pub async fn send_stream(
+        &mut self,
+        mut reader: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<u64, error::SendStreamError> {
+        let mut chunk = vec![0; SEND_STREAM_CHUNK_BYTES];
+        let mut num_bytes_sent = 0;
+        loop {
+            let mut read_buf = tokio::io::ReadBuf::new(&mut chunk);
+            let read_result = futures::future::poll_fn(|cx| {
+                core::pin::Pin::new(&mut reader).poll_read(cx, &mut read_buf)
+            }).await;
+            if let Err(e) = read_result {
+                return Err(error::SendStreamError::ReadError(e));
+            }
 
-        if let Err(_) = sendack_future_result {
-            return Err(error::SendError::TimedOutWaitingOnAck(ack_timeout));
+            let data = bytes::Bytes::copy_from_slice(read_buf.filled());
+            if data.is_empty() {
+                break;
+            }
+            let data_len = data.len() as u64;
+            if let Err(e) = self.send_and_forget(data).await {
+                return Err(error::SendStreamError::SendError(e));
+            }
+            num_bytes_sent += data_len;
         }
 
-        Ok(())
+        match self.has_finished_sending().await {
+            Ok(()) => Ok(num_bytes_sent),
+            Err(e) => Err(error::SendStreamError::HasFinishedSendingError(e)),
+        }
     }
 
-    pub async fn send_and_forget(&mut self, data: Vec<u8>) -> Result<(), error::SendError> {
-        match frame::encode::payload_frame(self.tube_id.val(), None, data) {
-            Ok(frame_data) => {
-                let mut sender = self.sender.lock().await;
-                if let Err(e) = sender.send_data(frame_data.into()).await {
-                    return Err(error::SendError::TransportError(e));
-                }
-                Ok(())
+    /**
+     * Sends data as a SequencedPayload for at-least-once delivery and returns
+     * the sequence number it was assigned. The payload is retained until the
+     * peer selectively acks it so that it can be resent with 
+     * retransmit_sequence_gaps() or retransmit_unacked_sequenced() if it's 
+     * lost (including when this send fails with a transient error).
+     *
+     * Data larger than the channel's max payload frame size is sent in 
+     * several frames (see Frame::PartialPayload) that share its sequence 
+     * number, and is resent whole.
+     */
+    pub async fn send_sequenced(
+        &mut self, 
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<u64, error::SendError> {
+        let data = data.into();
+        // Held until the transport has accepted the frames
+        let _send_window_permit = self.send_window.acquire(data.len()).await;
+        let num_bytes = data.len() as u64;
+        let sequence_number =
+            self.tube_manager.lock().unwrap().unacked_sequenced.push(data.clone());
+        let sequenced_frames = self.make_sequenced_payload_frames(sequence_number, data);
+        match self.sender.send_batch_with_priority(sequenced_frames, self.priority).await {
+            Ok(()) => {
+                self.tube_manager.lock().unwrap().payload_bytes_sent += num_bytes;
+                Ok(sequence_number)
             },
-            Err(e) => Err(error::SendError::FrameEncodeError(e)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /**
+     * Resends the sequenced payloads that the peer's SelectiveAcks show were 
+     * skipped over (and so were most likely lost). Returns the number of 
+     * payloads resent.
+     */
+    pub async fn retransmit_sequence_gaps(&mut self) -> Result<usize, error::SendError> {
+        let gaps = self.tube_manager.lock().unwrap().unacked_sequenced.gaps();
+        self.resend_sequenced(gaps).await
+    }
+
+    /**
+     * Resends every sequenced payload the peer hasn't acked yet (e.g. after a
+     * transient failure, when the peer may not have received any of them). 
+     * Returns the number of payloads resent.
+     */
+    pub async fn retransmit_unacked_sequenced(&mut self) -> Result<usize, error::SendError> {
+        let unacked = self.tube_manager.lock().unwrap().unacked_sequenced.unacked();
+        self.resend_sequenced(unacked).await
+    }
+
+    /**
+     * The frames that carry data as a SequencedPayload (see split_payload()).
+     */
+    fn make_sequenced_payload_frames(
+        &self,
+        sequence_number: u64,
+        data: bytes::Bytes,
+    ) -> Vec<frame::Frame> {
+        let tube_id = self.tube_id.val();
+        split_payload(
+            tube_id,
+            data,
+            self.sender.max_payload_frame_size(),
+            None,
+            |data| frame::Frame::SequencedPayload { tube_id, sequence_number, data },
+        )
+    }
+
+    async fn resend_sequenced(
+        &mut self,
+        payloads: Vec<(u64, bytes::Bytes)>,
+    ) -> Result<usize, error::SendError> {
+        let num_payloads = payloads.len();
+        let sequenced_frames = payloads.into_iter()
+            .flat_map(|(sequence_number, data)| {
+                self.make_sequenced_payload_frames(sequence_number, data)
+            })
+            .collect();
+        match self.sender.send_batch_with_priority(sequenced_frames, self.priority).await {
+            Ok(()) => Ok(num_payloads),
+            Err(e) => Err(e.into()),
         }
     }
 }
@@ -293,121 +892,303 @@ impl futures::stream::Stream for Tube {
         self: core::pin::Pin<&mut Self>,
         cx: &mut futures::task::Context,
     ) -> futures::task::Poll<Option<Self::Item>> {
-        let mut tube_mgr = self.tube_manager.lock().unwrap();
-        tube_mgr.waker = Some(cx.waker().clone());
-
-        match (self.last_tube_event.as_ref(), tube_mgr.pending_events.pop_front()) {
-            // No more pending_events
-            (_, None) => {
-                use TubeCompletionState::*;
-                match (&self.peer_type, &tube_mgr.completion_state) {
-                    (_, AbortedFromLocal(_)) |
-                        (_, AbortedFromRemote(_)) => {
-                        // TODO: Error all pending SendAcks
-                        futures::task::Poll::Ready(None)
-                    },
+        poll_next_tube_event(
+            self.peer_type, 
+            self.tube_id.val(), 
+            &self.tube_manager, 
+            &self.sender,
+            cx,
+        )
+    }
+}
+/**
+ * Sends each item as a Payload (like send_and_forget()). Only one payload is 
+ * sent at a time, so the Sink isn't ready for the next item until the 
+ * transport has accepted the previous one. Closing the Sink marks the Tube as
+ * having finished sending.
+ */
+impl futures::sink::Sink<bytes::Bytes> for Tube {
+    type Error = error::SinkError;
+
+    fn poll_ready(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        let send = match self.pending_sink_ops.send.as_mut() {
+            Some(send) => send,
+            None => return futures::task::Poll::Ready(Ok(())),
+        };
+        let send_result = futures::ready!(send.as_mut().poll(cx));
+        self.pending_sink_ops.send = None;
+        futures::task::Poll::Ready(send_result.map_err(error::SinkError::SendError))
+    }
 
-                    (&PeerType::Client, &Open | &ClientHasFinishedSending) |
-                    (&PeerType::Server, &Open | &ServerHasFinishedSending) => 
-                        futures::task::Poll::Pending,
+    fn start_send(
+        mut self: core::pin::Pin<&mut Self>, 
+        data: bytes::Bytes,
+    ) -> Result<(), Self::Error> {
+        let writer = self.writer();
+        self.pending_sink_ops.send = Some(Box::pin(async move {
+            writer.send_and_forget_unchecked(data).await
+        }));
+        Ok(())
+    }
 
-                    (&PeerType::Client, &Closed | &ServerHasFinishedSending) |
-                    (&PeerType::Server, &Closed | &ClientHasFinishedSending) =>
-                        futures::task::Poll::Ready(None),
-                }
-            },
+    fn poll_flush(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        self.poll_ready(cx)
+    }
 
-            // TODO: Enumerate various TubeEvents and validate state transitions 
-            //       here. Issue a 
-            //       TubeEvent::StreamError(InvalidTubeEventTransition) when the
-            //       transition doesn't make sense.
-            (_, Some(tube_event)) => 
-                futures::task::Poll::Ready(Some(tube_event)),
+    fn poll_close(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        futures::ready!(self.as_mut().poll_flush(cx))?;
+
+        if self.pending_sink_ops.close.is_none() {
+            let peer_type = self.peer_type;
+            let mut tube_id = self.tube_id.take();
+            let tube_manager = self.tube_manager.clone();
+            let sender = self.sender.clone();
+            self.pending_sink_ops.close = Some(Box::pin(async move {
+                let result = send_has_finished_sending(
+                    peer_type,
+                    &mut tube_id,
+                    &tube_manager,
+                    &sender,
+                ).await;
+                (tube_id, result)
+            }));
         }
+        let close = self.pending_sink_ops.close.as_mut().unwrap();
+        let (tube_id, close_result) = futures::ready!(close.as_mut().poll(cx));
+        self.pending_sink_ops.close = None;
+        // The id reservation was lent to the HasFinishedSending
+        self.tube_id = tube_id;
+        futures::task::Poll::Ready(
+            close_result.map_err(error::SinkError::HasFinishedSendingError)
+        )
     }
 }
 impl Drop for Tube {
     fn drop(&mut self) {
-        let completion_state = {
-            let tube_mgr = self.tube_manager.lock().unwrap();
-            tube_mgr.completion_state.clone()
-        };
-        let remote_peer_str = match self.peer_type {
-            PeerType::Client => "server",
-            PeerType::Server => "client"
-        };
+        {
+            // If this Tube was split, the TubeReader may still be receiving. 
+            // Leave finishing the Tube to it.
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            if tube_mgr.split_reader_alive {
+                log::trace!(
+                    "Deferring the drop of Tube(id={}) to its TubeReader...", 
+                    self.tube_id,
+                );
+                tube_mgr.deferred_drop = Some(DeferredTubeDrop {
+                    peer_type: self.peer_type,
+                    sender: self.sender.clone(),
+                    tube_id: self.tube_id.take(),
+                });
+                return;
+            }
+        }
 
-        use PeerType::*;
-        use TubeCompletionState::*;
-        log::trace!(
-            "Checking completion_state={:?} before dropping Tube(id={})...", 
-            &completion_state, 
-            self.tube_id,
+        finish_dropped_tube(
+            self.peer_type,
+            self.tube_id.take(),
+            self.tube_manager.clone(),
+            self.sender.clone(),
         );
-        match (self.peer_type, &completion_state) {
-            (_, &AbortedFromLocal(_) | &AbortedFromRemote(_) | &Closed) => (),
-
-            (Client, &ServerHasFinishedSending) |
-            (Server, &ClientHasFinishedSending) => {
-                let peer_type = self.peer_type;
-                let mut tube_id = self.tube_id.take();
-                let tube_manager = self.tube_manager.clone();
-                let sender = self.sender.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = send_has_finished_sending(
-                        peer_type,
-                        &mut tube_id,
-                        &tube_manager,
-                        &sender,
-                    ).await {
-                        log::error!(
-                            "Attempted to communicate to the {:?} that \
-                             Tube(id={}) has finished sending when dropping \
-                             the Tube object, but failed: {:?}", 
-                            remote_peer_str, 
-                            tube_id, 
-                            e
-                        )
-                    }
-                });
-            },
+    }
+}
 
-            (Client, &ClientHasFinishedSending) |
-            (Server, &ServerHasFinishedSending) |
-            (_, &Open) => {
-                log::error!(
-                    "Dropping Tube(id={}) before {} has finished sending! \
-                     Sending abort to {}",
-                    self.tube_id,
-                    remote_peer_str,
-                    remote_peer_str,
+pub(in crate::common::tube) fn poll_next_tube_event(
+    peer_type: PeerType,
+    tube_id: u32,
+    tube_manager: &Mutex<TubeManager>,
+    sender: &frame::FrameSender,
+    cx: &mut futures::task::Context,
+) -> futures::task::Poll<Option<TubeEvent>> {
+    let mut tube_mgr = tube_manager.lock().unwrap();
+    tube_mgr.waker = Some(cx.waker().clone());
+
+    match tube_mgr.pending_events.pop_front() {
+        // No more pending_events
+        None => {
+            use TubeCompletionState::*;
+            match (&peer_type, &tube_mgr.completion_state) {
+                (_, AbortedFromLocal(_)) |
+                    (_, AbortedFromRemote(_)) => {
+                    // TODO: Error all pending SendAcks
+                    futures::task::Poll::Ready(None)
+                },
+
+                (&PeerType::Client, &Open | &ClientHasFinishedSending) |
+                (&PeerType::Server, &Open | &ServerHasFinishedSending) => 
+                    futures::task::Poll::Pending,
+
+                (&PeerType::Client, &Closed | &ServerHasFinishedSending) |
+                (&PeerType::Server, &Closed | &ClientHasFinishedSending) =>
+                    futures::task::Poll::Ready(None),
+            }
+        },
+
+        Some(tube_event) => {
+            let tube_event_tag = TubeEventTag::from(&tube_event);
+            if !tube_mgr.last_tube_event.can_transition_to(peer_type, &tube_event_tag) {
+                let invalid_transition = TubeEvent_StreamError::InvalidTubeEventTransition(
+                    tube_mgr.last_tube_event.clone(),
+                    tube_event_tag,
                 );
+                abort_after_invalid_transition(tube_id, &mut tube_mgr, sender);
+                return futures::task::Poll::Ready(Some(TubeEvent::StreamError(
+                    invalid_transition
+                )));
+            }
+            if tube_event_tag.is_lifecycle_event() {
+                tube_mgr.last_tube_event = tube_event_tag;
+            }
 
-                let mut tube_id = self.tube_id.take();
-                let tube_manager = self.tube_manager.clone();
-                let sender = self.sender.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = send_abort(
-                        &mut tube_id, 
-                        frame::AbortReason::ApplicationError,
-                        &tube_manager,
-                        &sender,
-                    ).await {
-                        // TODO: Should this just be a panic? If we get into 
-                        //       this state we don't really know if the client 
-                        //       and server are synchronized on the state of 
-                        //       this Tube...havoc?
-                        log::error!(
-                            "Attempted to send an Abort for Tube(id={}) \
-                             to the {}, but failed: {:?}", 
-                            remote_peer_str,
-                            tube_id, 
-                            e
-                        )
-                    }
-                });
-            },
+            tube_mgr.stalled_since = if tube_mgr.pending_events.is_empty() {
+                None
+            } else {
+                Some(Instant::now())
+            };
+            tube_mgr.notify_event_queue_space();
+            futures::task::Poll::Ready(Some(tube_event))
+        },
+    }
+}
+
+/**
+ * Ends a Tube whose events stopped making sense (see 
+ * TubeEventTag::can_transition_to()): the events still queued on it are 
+ * dropped and, unless it has already completed, it is aborted with 
+ * AbortReason::InvalidTubeEventTransition.
+ */
+fn abort_after_invalid_transition(
+    tube_id: u32,
+    tube_mgr: &mut TubeManager,
+    sender: &frame::FrameSender,
+) {
+    log::error!(
+        "Tube(id={}) received events out of order. Aborting the Tube...",
+        tube_id,
+    );
+    tube_mgr.pending_events.clear();
+    tube_mgr.notify_event_queue_space();
+    if tube_mgr.completion_state.is_terminal() {
+        return;
+    }
+
+    let reason = frame::AbortReason::InvalidTubeEventTransition;
+    tube_mgr.set_completion_state(TubeCompletionState::AbortedFromLocal(reason.clone()));
+    // The Tube keeps its id reserved until the AbortAck arrives
+    tube_mgr.abort_ack_pending = true;
+    // Dropping the sendacks fails the sends waiting on them
+    tube_mgr.sendacks.clear();
+    tube_mgr.sendack_order.clear();
+
+    let sender = sender.clone();
+    tube_mgr.executor.spawn(async move {
+        let abort_frame = frame::Frame::Abort { tube_id, reason };
+        if let Err(e) = sender.send(abort_frame).await {
+            log::error!(
+                "Attempted to send an Abort for out-of-order Tube(id={}), but failed: {:?}",
+                tube_id,
+                e,
+            );
         }
+    });
+}
+
+/**
+ * Finishes (or, if the peer is still sending, aborts) a Tube whose local 
+ * object(s) have been dropped.
+ */
+pub(in crate::common::tube) fn finish_dropped_tube(
+    peer_type: PeerType,
+    mut tube_id: UniqueId,
+    tube_manager: Arc<Mutex<TubeManager>>,
+    sender: frame::FrameSender,
+) {
+    let (completion_state, executor) = {
+        let mut tube_mgr = tube_manager.lock().unwrap();
+        if tube_mgr.abort_ack_pending {
+            // The Tube was aborted by its idle timer, which couldn't hold 
+            // onto the id. Keep it reserved until the AbortAck arrives.
+            tube_mgr.abort_pending_id_reservation = Some(tube_id);
+            return;
+        }
+        (tube_mgr.completion_state.clone(), tube_mgr.executor.clone())
+    };
+    let remote_peer_str = match peer_type {
+        PeerType::Client => "server",
+        PeerType::Server => "client"
+    };
+
+    use PeerType::*;
+    use TubeCompletionState::*;
+    log::trace!(
+        "Checking completion_state={:?} before dropping Tube(id={})...", 
+        &completion_state, 
+        tube_id,
+    );
+    match (peer_type, &completion_state) {
+        (_, &AbortedFromLocal(_) | &AbortedFromRemote(_) | &Closed) => (),
+
+        (Client, &ServerHasFinishedSending) |
+        (Server, &ClientHasFinishedSending) => {
+            executor.spawn(async move {
+                if let Err(e) = send_has_finished_sending(
+                    peer_type,
+                    &mut tube_id,
+                    &tube_manager,
+                    &sender,
+                ).await {
+                    log::error!(
+                        "Attempted to communicate to the {:?} that \
+                         Tube(id={}) has finished sending when dropping \
+                         the Tube object, but failed: {:?}", 
+                        remote_peer_str, 
+                        tube_id, 
+                        e
+                    )
+                }
+            });
+        },
+
+        (Client, &ClientHasFinishedSending) |
+        (Server, &ServerHasFinishedSending) |
+        (_, &Open) => {
+            log::error!(
+                "Dropping Tube(id={}) before {} has finished sending! \
+                 Sending abort to {}",
+                tube_id,
+                remote_peer_str,
+                remote_peer_str,
+            );
+
+            executor.spawn(async move {
+                if let Err(e) = send_abort(
+                    &mut tube_id, 
+                    frame::AbortReason::ApplicationError,
+                    &tube_manager,
+                    &sender,
+                ).await {
+                    // TODO: Should this just be a panic? If we get into 
+                    //       this state we don't really know if the client 
+                    //       and server are synchronized on the state of 
+                    //       this Tube...havoc?
+                    log::error!(
+                        "Attempted to send an Abort for Tube(id={}) \
+                         to the {}, but failed: {:?}", 
+                        remote_peer_str,
+                        tube_id, 
+                        e
+                    )
+                }
+            });
+        },
     }
 }
 
@@ -415,7 +1196,6 @@ impl Drop for Tube {
 mod tube_tests {
     use super::*;
 
-    use crate::common::InvertedFuture;
     use crate::tube;
 
     struct TestTubeStuff {
@@ -424,14 +1204,23 @@ mod tube_tests {
     }
 
     fn make_test_tube() -> (Tube, TestTubeStuff) {
+        make_test_tube_with(TubeManager::new())
+    }
+
+    fn make_test_tube_with(tube_manager: TubeManager) -> (Tube, TestTubeStuff) {
         let (body_sender, req_body) = hyper::Body::channel();
-        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let body_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            frame::FrameInterceptors::new(),
+        );
         let mut id_manager = UniqueIdManager::new();
         let tube_id = id_manager.take_id().unwrap();
-        let tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+        let tube_manager = Arc::new(Mutex::new(tube_manager));
         let tube = Tube::new(
             PeerType::Client,
             tube_id,
+            HashMap::new(),
             body_sender,
             tube_manager.clone(),
         );
@@ -447,13 +1236,9 @@ mod tube_tests {
         let (mut tube, tube_stuff) = make_test_tube();
 
         // Add a sendack(id=0) to the TubeManager
-        let (_fut, res) = InvertedFuture::<()>::new();
-        {
-            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
-            tube_mgr.sendacks.insert(0, res);
-        }
+        let _sendack = tube_stuff.tube_manager.lock().unwrap().insert_sendack(0);
 
-        match tube.send("test data".into(), Duration::from_millis(100)).await {
+        match tube.send("test data", Duration::from_millis(100)).await {
             Err(tube::error::SendError::AckIdAlreadyInUseInternalError) => {
                 let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
                 assert_eq!(tube_mgr.sendacks.len(), 1);
@@ -468,11 +1253,157 @@ mod tube_tests {
         }
     }
 
+    #[tokio::test]
+    async fn tubes_can_be_moved_into_spawned_tasks() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("hello".into()));
+        }
+
+        let event = tokio::spawn(async move { tube.next().await }).await.unwrap();
+        assert_eq!(event, Some(TubeEvent::Payload("hello".into())));
+    }
+
+    #[tokio::test]
+    async fn extensions_store_typed_values() {
+        #[derive(Debug, PartialEq)]
+        struct TenantId(u32);
+
+        let (mut tube, _tube_stuff) = make_test_tube();
+        assert_eq!(tube.extensions().get::<TenantId>(), None);
+
+        tube.extensions_mut().insert(TenantId(42));
+        assert_eq!(tube.extensions().get::<TenantId>(), Some(&TenantId(42)));
+    }
+
+    #[test]
+    fn receive_only_is_requested_via_header() {
+        assert!(!receive_only_requested(&HashMap::new()));
+        assert!(receive_only_requested(&HashMap::from([
+            (RECEIVE_ONLY_HEADER.to_string(), b"1".to_vec()),
+        ])));
+        assert!(!receive_only_requested(&HashMap::from([
+            (RECEIVE_ONLY_HEADER.to_string(), b"0".to_vec()),
+        ])));
+    }
+
+    #[tokio::test]
+    async fn is_receive_only_reflects_tube_manager() {
+        let (tube, _tube_stuff) = make_test_tube();
+        assert!(!tube.is_receive_only());
+
+        let (body_sender, _req_body) = hyper::Body::channel();
+        let mut tube_manager = TubeManager::new();
+        tube_manager.receive_only = true;
+        let tube = Tube::new(
+            PeerType::Server,
+            UniqueIdManager::new().take_id().unwrap(),
+            HashMap::new(),
+            frame::FrameSender::new(
+                Box::new(body_sender),
+                frame::FramingVersion::V1,
+                frame::FrameInterceptors::new(),
+            ),
+            Arc::new(Mutex::new(tube_manager)),
+        );
+        assert!(tube.is_receive_only());
+    }
+
+    #[tokio::test]
+    async fn send_and_forget_waits_for_room_in_send_window() {
+        let (mut tube, _tube_stuff) = make_test_tube();
+        tube.set_max_in_flight_bytes(16);
+        assert_eq!(tube.max_in_flight_bytes(), 16);
+
+        let window = tube.send_window.clone();
+        let permit = window.acquire(10).await;
+        assert_eq!(tube.in_flight_bytes(), 10);
+
+        let blocked = tokio::time::timeout(
+            Duration::from_millis(10),
+            tube.send_and_forget(vec![42; 10]),
+        ).await;
+        assert!(blocked.is_err());
+
+        drop(permit);
+        tube.send_and_forget(vec![42; 10]).await.unwrap();
+        assert_eq!(tube.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn send_holds_send_window_until_acked() {
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        tokio::spawn(async move {
+            use hyper::body::HttpBody;
+            while req_body.data().await.is_some() {}
+        });
+        tube.set_max_in_flight_bytes(16);
+        let window = tube.send_window.clone();
+
+        let sending = tube.send(vec![42; 10], Duration::from_secs(10));
+        let acking = async {
+            while tube_manager.lock().unwrap().sendacks.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            // Sent but unacked, so another 10 bytes don't fit
+            assert_eq!(window.in_flight_bytes(), 10);
+            let blocked = tokio::time::timeout(
+                Duration::from_millis(10),
+                window.acquire(10),
+            ).await;
+            assert!(blocked.is_err());
+
+            assert!(tube_manager.lock().unwrap().resolve_sendacks(0));
+        };
+        let (send_result, ()) = futures::join!(sending, acking);
+        send_result.unwrap();
+        assert_eq!(window.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn send_fails_without_waiting_out_the_ack_timeout_on_a_checksum_mismatch() {
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        tokio::spawn(async move {
+            use hyper::body::HttpBody;
+            while req_body.data().await.is_some() {}
+        });
+
+        let sending = tube.send("test data", DEFAULT_ACK_TIMEOUT);
+        let failing = async {
+            while tube_manager.lock().unwrap().sendacks.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            let e = tube::error::SendError::PayloadChecksumMismatch("0: oops".to_string());
+            assert!(tube_manager.lock().unwrap().fail_sendack(0, e));
+        };
+        let (send_result, ()) = tokio::time::timeout(
+            Duration::from_secs(5),
+            async { futures::join!(sending, failing) },
+        ).await.unwrap();
+        match send_result {
+            Err(tube::error::SendError::PayloadChecksumMismatch(detail)) =>
+                assert_eq!(detail, "0: oops"),
+            unexpected => panic!("Unexpected result from Tube::send(): {:?}", unexpected),
+        }
+        assert_eq!(tube.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn send_releases_send_window_after_ack_timeout() {
+        let (mut tube, _tube_stuff) = make_test_tube();
+        let result = tube.send(vec![42; 10], Duration::from_nanos(1)).await;
+        assert!(result.is_err());
+        assert_eq!(tube.in_flight_bytes(), 0);
+    }
+
     #[tokio::test]
     async fn send_errors_if_ack_not_received_in_time() {
         let (mut tube, tube_stuff) = make_test_tube();
         let timeout = Duration::from_nanos(1);
-        match tube.send("test data".into(), timeout.clone()).await {
+        match tube.send("test data", timeout).await {
             Err(tube::error::SendError::TimedOutWaitingOnAck(err_timeout)) => {
                 assert_eq!(err_timeout, timeout);
 
@@ -488,112 +1419,653 @@ mod tube_tests {
             ),
         }
     }
-/*
-    use futures::StreamExt;
-    use hyper;
-    use tokio;
 
-    use crate::tube::TubeEvent;
-    use crate::tube::TubeEvent_StreamError;
-    use crate::tube::TubeEventTag;
-    use crate::tube::Tube;
+    #[tokio::test]
+    async fn abort_with_reason_reserves_the_id_until_abortack() {
+        let (mut tube, tube_stuff) = make_test_tube();
+        tokio::spawn(async move {
+            use hyper::body::HttpBody;
+            let mut req_body = tube_stuff.req_body;
+            while req_body.data().await.is_some() {}
+        });
+
+        tube.abort_with_reason(frame::AbortReason::ApplicationError).await.unwrap();
+        {
+            let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            assert_eq!(
+                tube_mgr.completion_state,
+                TubeCompletionState::AbortedFromLocal(frame::AbortReason::ApplicationError),
+            );
+            assert!(tube_mgr.abort_pending_id_reservation.is_some());
+        }
+
+        match tube.abort_with_reason(frame::AbortReason::ApplicationAbort).await {
+            Err(tube::error::AbortError::AlreadyAborted(frame::AbortReason::ApplicationError)) => (),
+            unexpected => panic!("Unexpected result from Tube::abort_with_reason(): {:?}", unexpected),
+        }
+    }
 
     #[tokio::test]
-    async fn emits_valid_initial_event() {
-        let (sender, _body) = hyper::Body::channel();
-        let mut stream = Tube::new(42, sender);
-        let test_events = vec![
-            TubeEvent::AuthenticatedAndReady,
-        ];
-        let expected_events = test_events.clone();
+    async fn dropping_a_tube_the_peer_finished_closes_and_untracks_it() {
+        use hyper::body::HttpBody;
 
-        stream.set_test_events(test_events);
+        let (tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        let tube_id = tube.get_id();
+        let channel_tube_managers = Arc::new(tube::ChannelTubeManagers::new());
+        channel_tube_managers.insert(tube_id, tube_manager.clone());
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.channel_tube_managers = Some(Arc::downgrade(&channel_tube_managers));
+            tube_mgr.set_completion_state(TubeCompletionState::ServerHasFinishedSending);
+        }
 
-        let actual_events = stream
-            .take(expected_events.len())
-            .collect::<Vec<TubeEvent>>().await;
+        drop(tube);
+        let data = req_body.data().await.unwrap().unwrap();
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        assert_eq!(
+            decoder.decode(data.to_vec()).unwrap(), 
+            vec![frame::Frame::ClientHasFinishedSending { tube_id }],
+        );
+        assert_eq!(tube_manager.lock().unwrap().completion_state, TubeCompletionState::Closed);
+        assert_eq!(channel_tube_managers.len(), 0);
+    }
 
-        assert_eq!(actual_events, expected_events);
+    #[tokio::test]
+    async fn send_stream_chunks_the_reader_and_finishes_sending() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, .. }) = make_test_tube();
+        let reading = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+            let mut frames = vec![];
+            while let Some(Ok(data)) = req_body.data().await {
+                frames.extend(decoder.decode(data.to_vec()).unwrap());
+            }
+            frames
+        });
+
+        let data = vec![42; SEND_STREAM_CHUNK_BYTES * 2 + 10];
+        assert_eq!(tube.send_stream(data.as_slice()).await.unwrap(), data.len() as u64);
+        let tube_id = tube.get_id();
+        // Dropping the Tube (which aborts it, since the server hasn't 
+        // finished sending) ends the request body
+        drop(tube);
+
+        let frames = reading.await.unwrap();
+        let payload_lens = frames.iter().filter_map(|frame| match frame {
+            frame::Frame::Payload { data, .. } => Some(data.len()),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(payload_lens, vec![SEND_STREAM_CHUNK_BYTES, SEND_STREAM_CHUNK_BYTES, 10]);
+        assert_eq!(frames[3], frame::Frame::ClientHasFinishedSending { tube_id });
     }
 
     #[tokio::test]
-    async fn emits_error_on_payload_before_authenticated() {
-        let (sender, _body) = hyper::Body::channel();
-        let mut stream = Tube::new(42, sender);
-        let test_events = vec![
-            TubeEvent::Payload(vec![]),
-        ];
-        let expected_events = vec![
-            TubeEvent::StreamError(
-                TubeEvent_StreamError::InvalidTubeEventTransition(
-                    TubeEventTag::Uninitialized,
-                    TubeEventTag::Payload,
-                )
-            )
-        ];
+    async fn payloads_are_split_by_the_max_payload_frame_size() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, .. }) = make_test_tube();
+        tube.sender = tube.sender.clone().with_max_payload_frame_size(4);
+        let reading = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+            let mut frames = vec![];
+            while let Some(Ok(data)) = req_body.data().await {
+                frames.extend(decoder.decode(data.to_vec()).unwrap());
+            }
+            frames
+        });
+
+        tube.send_and_forget(&b"0123456789"[..]).await.unwrap();
+        assert_eq!(tube.send_sequenced(&b"abcdef"[..]).await.unwrap(), 0);
+        assert_eq!(tube.retransmit_unacked_sequenced().await.unwrap(), 1);
+        // Only the last chunk waits on an ack
+        let _ = tube.send(&b"ABCDE"[..], Duration::from_millis(1)).await;
+        let tube_id = tube.get_id();
+        drop(tube);
+
+        let partial = |data: &'static [u8]| frame::Frame::PartialPayload {
+            tube_id,
+            checksum: None,
+            data: data.into(),
+        };
+        let payload = |ack_id, data: &'static [u8]| frame::Frame::Payload {
+            tube_id,
+            ack_id,
+            checksum: None,
+            data: data.into(),
+        };
+        let sequenced = |sequence_number, data: &'static [u8]| frame::Frame::SequencedPayload {
+            tube_id,
+            sequence_number,
+            data: data.into(),
+        };
+        let frames = reading.await.unwrap();
+        assert_eq!(frames[..9], [
+            partial(b"0123"),
+            partial(b"4567"),
+            payload(None, b"89"),
+            partial(b"abcd"),
+            sequenced(0, b"ef"),
+            partial(b"abcd"),
+            sequenced(0, b"ef"),
+            partial(b"ABCD"),
+            payload(Some(0), b"E"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_notifies_until_a_payload_arrives() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        tube.set_idle_timeout(Some(IdleTimeout {
+            duration: Duration::from_millis(20),
+            action: tube::IdleTimeoutAction::Notify,
+        }));
+        for _ in 0..2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), tube.next()).await;
+            assert_eq!(event.unwrap(), Some(TubeEvent::IdleTimeout));
+        }
+        assert_eq!(
+            tube_stuff.tube_manager.lock().unwrap().completion_state, 
+            TubeCompletionState::Open,
+        );
+
+        tube.set_idle_timeout(None);
+        let event = tokio::time::timeout(Duration::from_millis(50), tube.next()).await;
+        assert!(event.is_err());
+    }
 
-        stream.set_test_events(test_events);
-        let actual_events = stream.collect::<Vec<TubeEvent>>().await;
-        assert_eq!(actual_events, expected_events);
+    #[tokio::test]
+    async fn idle_timeout_can_abort_the_tube() {
+        use futures::StreamExt;
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        tube.set_idle_timeout(Some(IdleTimeout {
+            duration: Duration::from_millis(10),
+            action: tube::IdleTimeoutAction::Abort,
+        }));
+        let event = tokio::time::timeout(Duration::from_secs(5), tube.next()).await;
+        assert_eq!(event.unwrap(), Some(TubeEvent::IdleTimeout));
+        assert_eq!(tube.next().await, None);
+
+        let abort_bytes = req_body.data().await.unwrap().unwrap();
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        assert_eq!(decoder.decode(abort_bytes.to_vec()).unwrap()[0], frame::Frame::Abort {
+            tube_id: tube.get_id(),
+            reason: frame::AbortReason::IdleTimeout,
+        });
+
+        // The id stays reserved until the peer's AbortAck arrives
+        drop(tube);
+        assert!(tube_manager.lock().unwrap().abort_pending_id_reservation.is_some());
+    }
+
+    #[tokio::test]
+    async fn send_acked_times_out_after_the_tube_ack_timeout() {
+        let (mut tube, tube_stuff) = make_test_tube();
+        assert_eq!(tube.ack_timeout(), DEFAULT_ACK_TIMEOUT);
+        tube.set_ack_timeout(Duration::from_millis(1));
+
+        match tube.send_acked(vec![42]).await {
+            Err(tube::error::SendError::TimedOutWaitingOnAck(timeout)) =>
+                assert_eq!(timeout, Duration::from_millis(1)),
+            unexpected => panic!("Unexpected result from Tube::send_acked(): {:?}", unexpected),
+        }
+        assert_eq!(tube_stuff.tube_manager.lock().unwrap().sendacks.len(), 0);
+    }
+
+    fn make_test_tube_with_ack_timeout_policy(
+        action: tube::AckTimeoutAction,
+    ) -> (Tube, TestTubeStuff) {
+        let mut tube_manager = TubeManager::new();
+        tube_manager.ack_timeout_policy = Some(tube::AckTimeoutPolicy {
+            action,
+            timeout: Duration::from_millis(10),
+        });
+        make_test_tube_with(tube_manager)
+    }
+
+    #[tokio::test]
+    async fn ack_timeout_policy_can_notify_the_tube() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) =
+            make_test_tube_with_ack_timeout_policy(tube::AckTimeoutAction::Notify);
+        assert_eq!(tube.ack_timeout(), Duration::from_millis(10));
+
+        assert!(tube.send_acked(vec![42]).await.is_err());
+        assert_eq!(tube.next().await, Some(TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::TimedOutWaitingOnAck(Duration::from_millis(10))
+        )));
+        assert_eq!(
+            tube_stuff.tube_manager.lock().unwrap().completion_state,
+            TubeCompletionState::Open,
+        );
     }
 
     #[tokio::test]
-    async fn emits_error_on_payload_after_clienthasfinished() {
-        let (sender, _body) = hyper::Body::channel();
-        let mut stream = Tube::new(42, sender);
-        let test_events = vec![
-            TubeEvent::AuthenticatedAndReady,
-            TubeEvent::ClientHasFinishedSending,
-            TubeEvent::Payload(vec![]),
-        ];
-
-        let mut expected_events = test_events.clone();
-        // Delete the Payload event from the end...
-        expected_events.pop();
-        // Push on a StreamError event...
-        expected_events.push(
-            TubeEvent::StreamError(
-                TubeEvent_StreamError::InvalidTubeEventTransition(
-                    TubeEventTag::ClientHasFinishedSending,
-                    TubeEventTag::Payload,
-                )
+    async fn ack_timeout_policy_can_abort_the_tube() {
+        use futures::StreamExt;
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) =
+            make_test_tube_with_ack_timeout_policy(tube::AckTimeoutAction::Abort);
+        let writer = tube.writer();
+
+        // The send with the long ack timeout fails as soon as the Tube aborts
+        let (timed_out, failed) = tokio::time::timeout(Duration::from_secs(5), async {
+            futures::join!(
+                tube.send_acked(vec![42]),
+                writer.send(vec![43], Duration::from_secs(60)),
             )
+        }).await.unwrap();
+        assert!(timed_out.is_err());
+        assert!(failed.is_err());
+        assert!(tube_manager.lock().unwrap().sendacks.is_empty());
+
+        assert_eq!(tube.next().await, Some(TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::TimedOutWaitingOnAck(Duration::from_millis(10))
+        )));
+        assert_eq!(tube.next().await, None);
+
+        let abort_frame = frame::Frame::Abort {
+            tube_id: tube.get_id(),
+            reason: frame::AbortReason::AckTimeout,
+        };
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        loop {
+            let data = req_body.data().await.unwrap().unwrap();
+            if decoder.decode(data.to_vec()).unwrap().contains(&abort_frame) {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_pipelined_yields_acks_in_send_order() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        let mut send_acks = tube.send_pipelined(
+            vec![vec![1; 10], vec![2; 10], vec![3; 10]],
+            Duration::from_secs(10),
+        ).await.unwrap();
+        assert_eq!(send_acks.num_pending(), 3);
+        assert_eq!(tube.in_flight_bytes(), 30);
+
+        // Acks arriving out of order are still reported in send order
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            assert!(tube_mgr.resolve_sendacks(1));
+            assert!(tube_mgr.resolve_sendacks(0));
+        }
+        assert!(send_acks.next().await.unwrap().is_ok());
+        assert!(send_acks.next().await.unwrap().is_ok());
+        assert_eq!(send_acks.num_pending(), 1);
+
+        assert!(tube_stuff.tube_manager.lock().unwrap().resolve_sendacks(2));
+        send_acks.all_acked().await.unwrap();
+        assert_eq!(tube_stuff.tube_manager.lock().unwrap().sendacks.len(), 0);
+        assert_eq!(tube.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn retransmits_sequenced_payloads_until_selectively_acked() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        tokio::spawn(async move {
+            while req_body.data().await.is_some() {}
+        });
+
+        for expected_sequence_number in 0..3 {
+            let sequence_number = tube.send_sequenced(vec![42]).await.unwrap();
+            assert_eq!(sequence_number, expected_sequence_number);
+        }
+        assert_eq!(tube.retransmit_sequence_gaps().await.unwrap(), 0);
+
+        // The peer received payloads 0 and 2, but not 1
+        tube_manager.lock().unwrap().unacked_sequenced.apply_selective_ack(&[0..=0, 2..=2]);
+        assert_eq!(tube.retransmit_sequence_gaps().await.unwrap(), 1);
+        assert_eq!(tube.retransmit_unacked_sequenced().await.unwrap(), 1);
+
+        tube_manager.lock().unwrap().unacked_sequenced.apply_selective_ack(&[0..=2]);
+        assert_eq!(tube.retransmit_unacked_sequenced().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn resume_sends_the_acks_withheld_while_paused() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        let tube_id = tube.get_id();
+        tube.pause();
+        assert!(tube.is_paused());
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            assert!(tube_mgr.withhold_ack(3));
+            assert!(tube_mgr.withhold_ack(1));
+        }
+
+        tube.resume().await.unwrap();
+        assert!(!tube.is_paused());
+        assert!(!tube_manager.lock().unwrap().withhold_ack(2));
+        let data = req_body.data().await.unwrap().unwrap();
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        assert_eq!(decoder.decode(data.to_vec()).unwrap(), vec![
+            frame::Frame::PayloadAck { tube_id, ack_id: 3 },
+            frame::Frame::PayloadAck { tube_id, ack_id: 1 },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn write_handles_fan_in_from_several_tasks() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, .. }) = make_test_tube();
+        let tube_id = tube.get_id();
+        let reading = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+            let mut payloads = vec![];
+            while payloads.len() < 6 {
+                let data = req_body.data().await.unwrap().unwrap();
+                for frame in decoder.decode(data.to_vec()).unwrap() {
+                    match frame {
+                        frame::Frame::Payload { tube_id: id, data, .. } => {
+                            assert_eq!(id, tube_id);
+                            payloads.push(data);
+                        },
+                        unexpected => panic!("Unexpected frame: {:?}", unexpected),
+                    }
+                }
+            }
+            (payloads, req_body)
+        });
+
+        let writer = tube.writer();
+        let producers: Vec<_> = (0..3u8).map(|n| {
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                writer.send_and_forget(vec![n; 2]).await.unwrap();
+                writer.send_and_forget(vec![n; 2]).await.unwrap();
+            })
+        }).collect();
+        for producer in producers {
+            producer.await.unwrap();
+        }
+
+        let (mut payloads, mut req_body) = reading.await.unwrap();
+        payloads.sort();
+        let expected: Vec<bytes::Bytes> = (0..3u8)
+            .flat_map(|n| vec![vec![n; 2].into(), vec![n; 2].into()])
+            .collect();
+        assert_eq!(payloads, expected);
+
+        // Handles stop writing once the Tube has finished sending
+        let finishing = tokio::spawn(async move { req_body.data().await.is_some() });
+        tube.has_finished_sending().await.unwrap();
+        assert!(finishing.await.unwrap());
+        assert!(!writer.is_writable());
+        match writer.send_and_forget("late").await {
+            Err(tube::error::SendError::TubeNotWritable) => (),
+            unexpected => panic!("Unexpected result from send_and_forget(): {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn split_halves_read_and_write_concurrently() {
+        use futures::StreamExt;
+        use hyper::body::HttpBody;
+
+        let (tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        let (mut reader, mut writer) = tube.split();
+        assert_eq!(reader.get_id(), writer.get_id());
+
+        let reading = tokio::spawn(async move {
+            let mut events = vec![];
+            while let Some(event) = reader.next().await {
+                events.push(event);
+            }
+            events
+        });
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Payload(vec![1].into()));
+            tube_mgr.set_completion_state(TubeCompletionState::ServerHasFinishedSending);
+            if let Some(waker) = tube_mgr.waker.take() {
+                waker.wake();
+            }
+        }
+
+        writer.send_and_forget(vec![42]).await.unwrap();
+        assert!(req_body.data().await.is_some());
+        writer.has_finished_sending().await.unwrap();
+        assert_eq!(
+            tube_manager.lock().unwrap().completion_state, 
+            TubeCompletionState::Closed,
+        );
+        assert_eq!(reading.await.unwrap(), vec![TubeEvent::Payload(vec![1].into())]);
+    }
+
+    #[tokio::test]
+    async fn split_tube_is_only_aborted_once_both_halves_are_dropped() {
+        let (tube, tube_stuff) = make_test_tube();
+        let (reader, writer) = tube.split();
+
+        drop(writer);
+        tokio::task::yield_now().await;
+        {
+            let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            assert_eq!(tube_mgr.completion_state, TubeCompletionState::Open);
+            assert!(tube_mgr.deferred_drop.is_some());
+        }
+
+        drop(reader);
+        tokio::task::yield_now().await;
+        let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+        assert_eq!(
+            tube_mgr.completion_state, 
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::ApplicationError),
         );
+        assert!(tube_mgr.abort_pending_id_reservation.is_some());
+    }
 
-        stream.set_test_events(test_events);
-        let actual_events = stream.collect::<Vec<TubeEvent>>().await;
-        assert_eq!(actual_events, expected_events);
+    #[tokio::test]
+    async fn stream_can_be_forwarded_into_the_tube_sink() {
+        use futures::StreamExt;
+        use hyper::body::HttpBody;
+
+        let (mut tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        let reading = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+            let mut frames = vec![];
+            while let Some(Ok(data)) = req_body.data().await {
+                frames.extend(decoder.decode(data.to_vec()).unwrap());
+            }
+            frames
+        });
+
+        let payloads = vec![bytes::Bytes::from(vec![1]), bytes::Bytes::from(vec![2, 2])];
+        futures::stream::iter(payloads.clone())
+            .map(Ok)
+            .forward(&mut tube)
+            .await
+            .unwrap();
+        assert_eq!(
+            tube_manager.lock().unwrap().completion_state, 
+            TubeCompletionState::ClientHasFinishedSending,
+        );
+        assert_eq!(tube.in_flight_bytes(), 0);
+
+        let tube_id = tube.get_id();
+        drop(tube);
+        let frames = reading.await.unwrap();
+        assert_eq!(frames[..3], [
+            frame::Frame::Payload { 
+                tube_id, 
+                ack_id: None, 
+                checksum: None, 
+                data: payloads[0].clone(),
+            },
+            frame::Frame::Payload { 
+                tube_id, 
+                ack_id: None, 
+                checksum: None, 
+                data: payloads[1].clone(),
+            },
+            frame::Frame::ClientHasFinishedSending { tube_id },
+        ]);
     }
 
     #[tokio::test]
-    async fn terminates_stream_on_first_erroneous_event() {
-        let (sender, _body) = hyper::Body::channel();
-        let mut stream = Tube::new(42, sender);
-        let test_events = vec![
-            TubeEvent::AuthenticatedAndReady,
-            TubeEvent::ClientHasFinishedSending,
-            TubeEvent::Payload(vec![]),
-            TubeEvent::Payload(vec![]),
-        ];
-
-        let mut expected_events = test_events.clone();
-        // Delete both Payload events from the end...
-        expected_events.pop();
-        expected_events.pop();
-
-        // Push on a StreamError event...
-        expected_events.push(
-            TubeEvent::StreamError(
-                TubeEvent_StreamError::InvalidTubeEventTransition(
-                    TubeEventTag::ClientHasFinishedSending,
-                    TubeEventTag::Payload,
-                )
-            )
+    async fn send_pipelined_errors_if_acks_not_received_in_time() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        let timeout = Duration::from_millis(1);
+        let mut send_acks = tube.send_pipelined(
+            vec![vec![1; 10], vec![2; 10]],
+            timeout,
+        ).await.unwrap();
+        assert!(tube_stuff.tube_manager.lock().unwrap().resolve_sendacks(0));
+
+        assert!(send_acks.next().await.unwrap().is_ok());
+        match send_acks.next().await {
+            Some(Err(tube::error::SendError::TimedOutWaitingOnAck(err_timeout))) =>
+                assert_eq!(err_timeout, timeout),
+            unexpected => panic!("Unexpected ack notification: {:?}", unexpected),
+        }
+        assert!(send_acks.next().await.is_none());
+        assert_eq!(tube_stuff.tube_manager.lock().unwrap().sendacks.len(), 0);
+
+        drop(send_acks);
+        assert_eq!(tube.in_flight_bytes(), 0);
+    }
+
+    fn push_events(tube_manager: &Mutex<TubeManager>, events: Vec<TubeEvent>) {
+        let mut tube_mgr = tube_manager.lock().unwrap();
+        tube_mgr.pending_events.extend(events);
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+    }
+
+    #[tokio::test]
+    async fn payload_after_peer_finished_sending_aborts_the_tube() {
+        use futures::StreamExt;
+        use hyper::body::HttpBody;
+
+        let (tube, TestTubeStuff { mut req_body, tube_manager }) = make_test_tube();
+        let tube_id = tube.get_id();
+        push_events(&tube_manager, vec![
+            TubeEvent::Payload("hello".into()),
+            TubeEvent::ServerHasFinishedSending,
+            TubeEvent::Payload("late".into()),
+            TubeEvent::Payload("later".into()),
+        ]);
+
+        // The stream ends at the first event that's out of order
+        let events = tube.collect::<Vec<TubeEvent>>().await;
+        assert_eq!(events, vec![
+            TubeEvent::Payload("hello".into()),
+            TubeEvent::ServerHasFinishedSending,
+            TubeEvent::StreamError(tube::TubeEvent_StreamError::InvalidTubeEventTransition(
+                TubeEventTag::ServerHasFinishedSending,
+                TubeEventTag::Payload,
+            )),
+        ]);
+        assert_eq!(
+            tube_manager.lock().unwrap().completion_state,
+            TubeCompletionState::AbortedFromLocal(
+                frame::AbortReason::InvalidTubeEventTransition
+            ),
         );
 
-        stream.set_test_events(test_events);
-        let actual_events = stream.collect::<Vec<TubeEvent>>().await;
-        assert_eq!(actual_events, expected_events);
+        let abort_frame = frame::Frame::Abort {
+            tube_id,
+            reason: frame::AbortReason::InvalidTubeEventTransition,
+        };
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        loop {
+            let data = req_body.data().await.unwrap().unwrap();
+            if decoder.decode(data.to_vec()).unwrap().contains(&abort_frame) {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn informational_events_dont_reset_the_tube_event_transitions() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        push_events(&tube_stuff.tube_manager, vec![
+            TubeEvent::ServerHasFinishedSending,
+            TubeEvent::IdleTimeout,
+            TubeEvent::Payload("late".into()),
+        ]);
+
+        assert_eq!(tube.next().await, Some(TubeEvent::ServerHasFinishedSending));
+        assert_eq!(tube.next().await, Some(TubeEvent::IdleTimeout));
+        assert_eq!(tube.next().await, Some(TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::InvalidTubeEventTransition(
+                TubeEventTag::ServerHasFinishedSending,
+                TubeEventTag::Payload,
+            )
+        )));
+        assert_eq!(tube.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn tube_event_transitions_carry_over_to_a_split_reader() {
+        use futures::StreamExt;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        push_events(&tube_stuff.tube_manager, vec![TubeEvent::ServerHasFinishedSending]);
+        assert_eq!(tube.next().await, Some(TubeEvent::ServerHasFinishedSending));
+
+        let (mut reader, _writer) = tube.split();
+        push_events(&tube_stuff.tube_manager, vec![TubeEvent::ServerHasFinishedSending]);
+        assert_eq!(reader.next().await, Some(TubeEvent::StreamError(
+            tube::TubeEvent_StreamError::InvalidTubeEventTransition(
+                TubeEventTag::ServerHasFinishedSending,
+                TubeEventTag::ServerHasFinishedSending,
+            )
+        )));
+        assert_eq!(reader.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn events_after_an_abort_end_the_tube_without_aborting_it_again() {
+        use futures::StreamExt;
+
+        let (tube, tube_stuff) = make_test_tube();
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            tube_mgr.set_completion_state(TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::ApplicationAbort
+            ));
+        }
+        push_events(&tube_stuff.tube_manager, vec![
+            TubeEvent::Abort(frame::AbortReason::ApplicationAbort),
+            TubeEvent::Payload("late".into()),
+        ]);
+
+        let events = tube.collect::<Vec<TubeEvent>>().await;
+        assert_eq!(events, vec![
+            TubeEvent::Abort(frame::AbortReason::ApplicationAbort),
+            TubeEvent::StreamError(tube::TubeEvent_StreamError::InvalidTubeEventTransition(
+                TubeEventTag::Abort,
+                TubeEventTag::Payload,
+            )),
+        ]);
+        let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+        assert_eq!(
+            tube_mgr.completion_state,
+            TubeCompletionState::AbortedFromRemote(frame::AbortReason::ApplicationAbort),
+        );
+        assert!(!tube_mgr.abort_ack_pending);
     }
-*/
 }
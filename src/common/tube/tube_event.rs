@@ -1,17 +1,37 @@
-use crate::common::frame;
+use std::time::Duration;
+use std::time::SystemTime;
 
-// TODO
-/*
-#[derive(Clone, Debug, PartialEq)]
-#[allow(non_camel_case_types)]
-pub enum TubeEvent_DrainReason {}
-*/
+use crate::common::frame;
+use crate::common::PeerType;
 
 #[derive(Clone, Debug, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum TubeEvent_StreamError {
   InvalidTubeEventTransition(TubeEventTag, TubeEventTag),
+  PeerError {
+    code: frame::ErrorCode,
+    detail: String,
+  },
+  PayloadChecksumMismatch {
+    expected: u32,
+    computed: u32,
+  },
   ServerError(String),
+  /**
+   * The peer didn't ack a payload sent on this Tube within the given ack 
+   * timeout (see tube::AckTimeoutPolicy).
+   */
+  TimedOutWaitingOnAck(Duration),
+  /**
+   * The peer sent a GoAway before it processed this Tube, so it never will.
+   * Since the peer never saw the Tube, it is safe to retry elsewhere.
+   */
+  PeerGoingAway(String),
+  /**
+   * The Channel's underlying transport ended or failed, so no more frames
+   * will arrive for the Tube.
+   */
+  TransportError(String),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -19,14 +39,24 @@ pub enum TubeEvent {
     Abort(frame::AbortReason),
     AuthenticatedAndReady,
     ClientHasFinishedSending,
-    Payload(Vec<u8>),
+    Payload(bytes::Bytes),
     StreamError(TubeEvent_StreamError),
     ServerHasFinishedSending,
-
-    // TODO
-    /*
-    ServerMustDrain(TubeEvent_DrainReason),
-    */
+    /**
+     * The Tube hasn't received a payload within its idle timeout (see 
+     * Tube::set_idle_timeout()), or nothing at all was received on its 
+     * channel within the channel's idle timeout.
+     */
+    IdleTimeout,
+    /**
+     * The peer has asked for the Channel this Tube is on to be drained. The
+     * reason indicates whether the Tube should be finished gracefully (ideally
+     * before the deadline, if one was given) or migrated elsewhere right away.
+     */
+    ServerMustDrain {
+        reason: frame::DrainReason,
+        deadline: Option<SystemTime>,
+    },
 }
 
 // TODO: Is there a way to macro-ize this so TubeEvent and 
@@ -40,11 +70,8 @@ pub enum TubeEventTag {
     ClientHasFinishedSending,
     StreamError,
     ServerHasFinishedSending,
-
-    // TODO
-    /*
+    IdleTimeout,
     ServerMustDrain,
-    */
 }
 impl From<&TubeEvent> for TubeEventTag {
     fn from(event: &TubeEvent) -> Self {
@@ -55,11 +82,121 @@ impl From<&TubeEvent> for TubeEventTag {
             TubeEvent::ClientHasFinishedSending => TubeEventTag::ClientHasFinishedSending,
             TubeEvent::StreamError(_) => TubeEventTag::StreamError,
             TubeEvent::ServerHasFinishedSending => TubeEventTag::ServerHasFinishedSending,
+            TubeEvent::IdleTimeout => TubeEventTag::IdleTimeout,
+            TubeEvent::ServerMustDrain { .. } => TubeEventTag::ServerMustDrain,
+        }
+    }
+}
+impl TubeEventTag {
+    /**
+     * Whether a Tube on the peer_type end of a channel may emit an event 
+     * tagged next after self, the last event that moved it along its 
+     * lifecycle (see is_lifecycle_event()):
+     *
+     *   * AuthenticatedAndReady may only be the first event.
+     *   * Payloads stop once the peer has finished sending or the Tube was
+     *     aborted.
+     *   * The peer finishes sending at most once, and never after an abort. A
+     *     Tube never hears that its own end has finished sending.
+     *   * An Abort may follow anything but another Abort.
+     *   * StreamErrors, IdleTimeouts and ServerMustDrains may come at any 
+     *     point.
+     */
+    pub(in crate::common::tube) fn can_transition_to(
+        &self,
+        peer_type: PeerType,
+        next: &TubeEventTag,
+    ) -> bool {
+        use TubeEventTag::*;
+        let peer_finished_sending = match peer_type {
+            PeerType::Client => ServerHasFinishedSending,
+            PeerType::Server => ClientHasFinishedSending,
+        };
+        match next {
+            Uninitialized => false,
+            AuthenticatedAndReady => *self == Uninitialized,
+            Payload => matches!(self, Uninitialized | AuthenticatedAndReady | Payload),
+            ClientHasFinishedSending | ServerHasFinishedSending => 
+                *next == peer_finished_sending
+                    && matches!(self, Uninitialized | AuthenticatedAndReady | Payload),
+            Abort => *self != Abort,
+            StreamError | IdleTimeout | ServerMustDrain => true,
+        }
+    }
+
+    /**
+     * Whether events tagged self move a Tube along its lifecycle, as opposed 
+     * to reporting on it without changing which events may follow.
+     */
+    pub(in crate::common::tube) fn is_lifecycle_event(&self) -> bool {
+        match self {
+            TubeEventTag::AuthenticatedAndReady |
+            TubeEventTag::Payload |
+            TubeEventTag::ClientHasFinishedSending |
+            TubeEventTag::ServerHasFinishedSending |
+            TubeEventTag::Abort => true,
+
+            TubeEventTag::Uninitialized |
+            TubeEventTag::StreamError |
+            TubeEventTag::IdleTimeout |
+            TubeEventTag::ServerMustDrain => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tube_event_tests {
+    use super::*;
+
+    const ALL_TAGS: [TubeEventTag; 9] = [
+        TubeEventTag::Abort,
+        TubeEventTag::Uninitialized,
+        TubeEventTag::AuthenticatedAndReady,
+        TubeEventTag::Payload,
+        TubeEventTag::ClientHasFinishedSending,
+        TubeEventTag::StreamError,
+        TubeEventTag::ServerHasFinishedSending,
+        TubeEventTag::IdleTimeout,
+        TubeEventTag::ServerMustDrain,
+    ];
+
+    #[test]
+    fn tube_event_transitions_follow_the_tube_lifecycle() {
+        use TubeEventTag::*;
 
-            // TODO
-            /*
-            TubeEvent::ServerMustDrain(_) => TubeEventTag::ServerMustDrain,
-            */
+        for (peer_type, peer_finished_sending) in [
+            (PeerType::Client, ServerHasFinishedSending),
+            (PeerType::Server, ClientHasFinishedSending),
+        ] {
+            // Beyond StreamError, IdleTimeout and ServerMustDrain, which are 
+            // allowed after anything
+            let valid_transitions = [
+                (Uninitialized, vec![
+                    AuthenticatedAndReady,
+                    Payload,
+                    peer_finished_sending.clone(),
+                    Abort,
+                ]),
+                (AuthenticatedAndReady, vec![Payload, peer_finished_sending.clone(), Abort]),
+                (Payload, vec![Payload, peer_finished_sending.clone(), Abort]),
+                (peer_finished_sending.clone(), vec![Abort]),
+                (Abort, vec![]),
+            ];
+            for (from, valid_next_tags) in valid_transitions {
+                assert!(from.is_lifecycle_event() || from == Uninitialized);
+                for next in ALL_TAGS {
+                    let expected = valid_next_tags.contains(&next)
+                        || !next.is_lifecycle_event() && next != Uninitialized;
+                    assert_eq!(
+                        from.can_transition_to(peer_type, &next),
+                        expected,
+                        "{:?} Tube: {:?} -> {:?}",
+                        peer_type,
+                        from,
+                        next,
+                    );
+                }
+            }
         }
     }
 }
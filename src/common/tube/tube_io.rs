@@ -0,0 +1,250 @@
+use futures::Sink;
+use futures::Stream;
+
+use super::tube::Tube;
+use super::tube::SEND_STREAM_CHUNK_BYTES;
+use super::TubeEvent;
+
+/**
+ * A byte-stream view of a Tube (see Tube::into_io()) for code written
+ * against tokio's AsyncRead and AsyncWrite.
+ *
+ * Reads yield the Tube's payloads back to back (payload boundaries aren't
+ * preserved) and reach EOF once the peer has finished sending. An Abort
+ * surfaces as an io::ErrorKind::ConnectionAborted error and a StreamError as
+ * an io::ErrorKind::InvalidData error; other TubeEvents are skipped.
+ *
+ * Each write is sent as a Payload of up to SEND_STREAM_CHUNK_BYTES (without
+ * waiting for an ack) and shutting down marks the Tube as having finished
+ * sending.
+ */
+#[derive(Debug)]
+pub struct TubeIo {
+    read_buf: bytes::Bytes,
+    tube: Tube,
+}
+impl TubeIo {
+    pub(in crate::common::tube) fn new(tube: Tube) -> Self {
+        TubeIo {
+            read_buf: bytes::Bytes::new(),
+            tube,
+        }
+    }
+
+    pub fn get_ref(&self) -> &Tube {
+        &self.tube
+    }
+
+    pub fn get_mut(&mut self) -> &mut Tube {
+        &mut self.tube
+    }
+
+    /**
+     * Returns the Tube. Any data that was received but not yet read is
+     * discarded.
+     */
+    pub fn into_inner(self) -> Tube {
+        self.tube
+    }
+}
+impl tokio::io::AsyncRead for TubeIo {
+    fn poll_read(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> futures::task::Poll<std::io::Result<()>> {
+        while self.read_buf.is_empty() {
+            let event = futures::ready!(core::pin::Pin::new(&mut self.tube).poll_next(cx));
+            match event {
+                Some(TubeEvent::Payload(data)) => self.read_buf = data,
+                Some(TubeEvent::Abort(reason)) => return futures::task::Poll::Ready(Err(
+                    std::io::Error::new(std::io::ErrorKind::ConnectionAborted, reason.to_string())
+                )),
+                Some(TubeEvent::StreamError(e)) => return futures::task::Poll::Ready(Err(
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e))
+                )),
+                Some(_) => (),
+                None => return futures::task::Poll::Ready(Ok(())),
+            }
+        }
+
+        let num_bytes = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf.split_to(num_bytes));
+        futures::task::Poll::Ready(Ok(()))
+    }
+}
+impl tokio::io::AsyncWrite for TubeIo {
+    fn poll_write(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+        buf: &[u8],
+    ) -> futures::task::Poll<std::io::Result<usize>> {
+        let tube = core::pin::Pin::new(&mut self.tube);
+        futures::ready!(Sink::poll_ready(tube, cx)).map_err(sink_error_to_io)?;
+
+        let num_bytes = buf.len().min(SEND_STREAM_CHUNK_BYTES);
+        let data = bytes::Bytes::copy_from_slice(&buf[..num_bytes]);
+        let tube = core::pin::Pin::new(&mut self.tube);
+        Sink::start_send(tube, data).map_err(sink_error_to_io)?;
+        futures::task::Poll::Ready(Ok(num_bytes))
+    }
+
+    fn poll_flush(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<std::io::Result<()>> {
+        let tube = core::pin::Pin::new(&mut self.tube);
+        Sink::poll_flush(tube, cx).map_err(sink_error_to_io)
+    }
+
+    fn poll_shutdown(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<std::io::Result<()>> {
+        let tube = core::pin::Pin::new(&mut self.tube);
+        Sink::poll_close(tube, cx).map_err(sink_error_to_io)
+    }
+}
+
+fn sink_error_to_io(e: super::error::SinkError) -> std::io::Error {
+    std::io::Error::other(format!("{:?}", e))
+}
+
+#[cfg(test)]
+mod tube_io_tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use hyper::body::HttpBody;
+
+    use super::*;
+    use crate::common::frame;
+    use crate::common::PeerType;
+    use crate::common::UniqueId;
+    use crate::common::tube::TubeCompletionState;
+    use crate::common::tube::TubeManager;
+
+    fn make_test_tube() -> (Tube, hyper::Body, Arc<Mutex<TubeManager>>) {
+        let (body_sender, req_body) = hyper::Body::channel();
+        let frame_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            frame::FrameInterceptors::new(),
+        );
+        let tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+        let tube = Tube::new(
+            PeerType::Client,
+            UniqueId::new(1, None),
+            HashMap::new(),
+            frame_sender,
+            tube_manager.clone(),
+        );
+        (tube, req_body, tube_manager)
+    }
+
+    async fn read_frames(req_body: &mut hyper::Body, num_frames: usize) -> Vec<frame::Frame> {
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        let mut frames = vec![];
+        while frames.len() < num_frames {
+            let data = req_body.data().await.unwrap().unwrap();
+            frames.extend(decoder.decode(data.to_vec()).unwrap());
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn reads_payloads_as_a_byte_stream_until_the_peer_finishes() {
+        let (tube, _req_body, tube_manager) = make_test_tube();
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("hello ".into()));
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("world".into()));
+            tube_mgr.pending_events.push_back(TubeEvent::ServerHasFinishedSending);
+            tube_mgr.set_completion_state(TubeCompletionState::ServerHasFinishedSending);
+        }
+
+        let mut io = tube.into_io();
+        let mut data = vec![];
+        let mut chunk = [0; 4];
+        loop {
+            let mut read_buf = tokio::io::ReadBuf::new(&mut chunk);
+            futures::future::poll_fn(|cx| {
+                tokio::io::AsyncRead::poll_read(core::pin::Pin::new(&mut io), cx, &mut read_buf)
+            }).await.unwrap();
+            if read_buf.filled().is_empty() {
+                break;
+            }
+            data.extend_from_slice(read_buf.filled());
+        }
+        assert_eq!(data, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn aborts_surface_as_read_errors() {
+        let (tube, _req_body, tube_manager) = make_test_tube();
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Abort(
+                frame::AbortReason::ApplicationAbort,
+            ));
+        }
+
+        let mut io = tube.into_io();
+        let mut chunk = [0; 4];
+        let mut read_buf = tokio::io::ReadBuf::new(&mut chunk);
+        let result = futures::future::poll_fn(|cx| {
+            tokio::io::AsyncRead::poll_read(core::pin::Pin::new(&mut io), cx, &mut read_buf)
+        }).await;
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::ConnectionAborted);
+    }
+
+    #[tokio::test]
+    async fn writes_are_sent_as_payloads_and_shutdown_finishes_sending() {
+        let (tube, mut req_body, _tube_manager) = make_test_tube();
+        let reading = tokio::spawn(async move { read_frames(&mut req_body, 2).await });
+
+        let mut io = tube.into_io();
+        let num_bytes = futures::future::poll_fn(|cx| {
+            tokio::io::AsyncWrite::poll_write(core::pin::Pin::new(&mut io), cx, b"hi")
+        }).await.unwrap();
+        assert_eq!(num_bytes, 2);
+        futures::future::poll_fn(|cx| {
+            tokio::io::AsyncWrite::poll_shutdown(core::pin::Pin::new(&mut io), cx)
+        }).await.unwrap();
+
+        assert_eq!(reading.await.unwrap(), vec![
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: None,
+                checksum: None,
+                data: "hi".into(),
+            },
+            frame::Frame::ClientHasFinishedSending { tube_id: 1 },
+        ]);
+    }
+
+    #[cfg(feature = "codec")]
+    #[tokio::test]
+    async fn framed_tubes_work_with_tokio_util_codecs() {
+        use futures::SinkExt;
+        use futures::StreamExt;
+
+        let (tube, mut req_body, tube_manager) = make_test_tube();
+        let reading = tokio::spawn(async move { read_frames(&mut req_body, 1).await });
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("one\ntw".into()));
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("o\n".into()));
+        }
+
+        let mut framed = tube.framed(tokio_util::codec::LinesCodec::new());
+        framed.send("hello").await.unwrap();
+        match reading.await.unwrap().as_slice() {
+            [frame::Frame::Payload { data, .. }] => assert_eq!(data.as_ref(), b"hello\n"),
+            unexpected => panic!("Unexpected frames: {:?}", unexpected),
+        }
+        assert_eq!(framed.next().await.unwrap().unwrap(), "one");
+        assert_eq!(framed.next().await.unwrap().unwrap(), "two");
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::tube_tracker::TubeOutcome;
+
+/**
+ * How much a Tube carried and for how long, as of when it finished.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(in crate) struct TubeStats {
+    pub(in crate) duration: Duration,
+    pub(in crate) payload_bytes_received: u64,
+    pub(in crate) payload_bytes_sent: u64,
+}
+
+/**
+ * Told when the peer opens each Tube on a channel and when each of those
+ * Tubes finishes (see ChannelContext::with_tube_lifecycle_observer()).
+ *
+ * tube_closed() is called with the Tube's TubeManager locked, so observers
+ * must not block or touch the Tube.
+ */
+pub(in crate) trait TubeLifecycleObserver: std::fmt::Debug + Send + Sync {
+    fn tube_opened(&self, tube_id: u32, headers: &HashMap<String, Vec<u8>>);
+    fn tube_closed(&self, tube_id: u32, outcome: TubeOutcome, stats: TubeStats);
+}
+
+/**
+ * Held by the TubeManager of an observed Tube until the Tube finishes (see
+ * TubeManager::set_completion_state()).
+ */
+#[derive(Debug)]
+pub(in crate) struct TubeLifecycle {
+    observer: Arc<dyn TubeLifecycleObserver>,
+    opened_at: Instant,
+    tube_id: u32,
+}
+impl TubeLifecycle {
+    /**
+     * Tells observer that the Tube has been opened.
+     */
+    pub(in crate) fn open(
+        observer: Arc<dyn TubeLifecycleObserver>,
+        tube_id: u32,
+        headers: &HashMap<String, Vec<u8>>,
+    ) -> Self {
+        observer.tube_opened(tube_id, headers);
+        TubeLifecycle {
+            observer,
+            opened_at: Instant::now(),
+            tube_id,
+        }
+    }
+
+    pub(in crate) fn close(
+        self,
+        outcome: TubeOutcome,
+        payload_bytes_received: u64,
+        payload_bytes_sent: u64,
+    ) {
+        self.observer.tube_closed(self.tube_id, outcome, TubeStats {
+            duration: self.opened_at.elapsed(),
+            payload_bytes_received,
+            payload_bytes_sent,
+        });
+    }
+}
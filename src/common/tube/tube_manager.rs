@@ -1,11 +1,36 @@
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 use std::task;
+use std::time::Instant;
+
+use tokio::sync::oneshot;
 
 use crate::common::frame;
-use crate::common::InvertedFutureResolver;
+use crate::common::ChannelExecutor;
+use crate::common::PeerType;
 use crate::common::UniqueId;
+use super::ack_timeout::AckTimeoutPolicy;
+use super::channel_tube_managers::ChannelTubeManagers;
+use super::event_queue::EventQueueLimit;
+use super::event_queue::EventQueueOverflowPolicy;
+use super::event_queue::PayloadRoom;
+use super::idle_timeout::IdleTimeout;
+use super::payload_reassembly::PayloadReassembly;
+use super::sequence_tracking::ReceivedSequences;
+use super::sequence_tracking::UnackedSequencedPayloads;
+use super::tube::error;
 use super::tube_event;
+use super::tube_lifecycle::TubeLifecycle;
+use super::tube_tracker::TubeOutcome;
+
+/**
+ * Resolves once the peer acks a payload, or with the error the peer reported
+ * for it instead (see TubeManager::fail_sendack()).
+ */
+pub(in crate) type SendAck = oneshot::Receiver<Result<(), error::SendError>>;
 
 #[derive(Clone,Debug,PartialEq)]
 pub enum TubeCompletionState {
@@ -16,6 +41,26 @@ pub enum TubeCompletionState {
     AbortedFromLocal(frame::AbortReason),
     AbortedFromRemote(frame::AbortReason),
 }
+impl TubeCompletionState {
+    pub fn is_terminal(&self) -> bool {
+        use TubeCompletionState::*;
+        match self {
+            Closed | AbortedFromLocal(_) | AbortedFromRemote(_) => true,
+            Open | ClientHasFinishedSending | ServerHasFinishedSending => false,
+        }
+    }
+}
+
+/**
+ * What's needed to finish a split Tube whose TubeWriter was dropped before 
+ * its TubeReader (see Tube::split()).
+ */
+#[derive(Debug)]
+pub(in crate) struct DeferredTubeDrop {
+    pub peer_type: PeerType,
+    pub sender: frame::FrameSender,
+    pub tube_id: UniqueId,
+}
 
 #[derive(Debug)]
 pub struct TubeManager {
@@ -25,19 +70,476 @@ pub struct TubeManager {
      * here, ultimately dropped, and the TubeId can then be re-used).
      */
     pub abort_pending_id_reservation: Option<UniqueId>,
+    /**
+     * Set when the Tube's idle timer (or its AckTimeoutPolicy) aborts it. 
+     * Neither owns the Tube's UniqueId, so the id is moved into 
+     * abort_pending_id_reservation when the Tube is dropped if the peer 
+     * hasn't acknowledged the Abort by then.
+     */
+    pub(in crate) abort_ack_pending: bool,
+    /**
+     * The channel's AckTimeoutPolicy when the Tube was created, if it has 
+     * one.
+     */
+    pub ack_timeout_policy: Option<AckTimeoutPolicy>,
+    /**
+     * The channel's map of TubeManagers that this TubeManager is tracked in,
+     * so that a Tube the local side closes can remove itself once it's done.
+     */
+    pub(in crate) channel_tube_managers: Option<Weak<ChannelTubeManagers>>,
+    /**
+     * Wakers for futures (e.g. JoinAllTubes) waiting on this Tube to reach a
+     * terminal completion_state (or, once it has been aborted from this side,
+     * to stop being tracked by its channel).
+     */
+    pub completion_wakers: Vec<task::Waker>,
+    /**
+     * Whether PayloadAcks on this Tube are cumulative (see 
+     * CUMULATIVE_ACKS_HEADER), in which case a single PayloadAck resolves 
+     * every sendack up to and including the acked payload.
+     */
+    pub cumulative_acks: bool,
+    /**
+     * Set when a split Tube's TubeWriter is dropped while its TubeReader is
+     * still alive. The TubeReader finishes the Tube when it's dropped.
+     */
+    pub(in crate) deferred_drop: Option<DeferredTubeDrop>,
+    /**
+     * Set while the client waits for the server to ack the NewTube for this
+     * Tube (see FramingVersion::acks_new_tubes()).
+     */
+    pub(in crate) establishment_pending: bool,
+    /**
+     * The frames that made this Tube, kept while establishment_pending so 
+     * that a client channel that reconnects can make the Tube again.
+     */
+    pub(in crate) establishment_frames: Vec<frame::Frame>,
+    /**
+     * Bounds pending_events for a consumer that isn't keeping up. None queues
+     * without limit.
+     */
+    pub event_queue_limit: Option<EventQueueLimit>,
+    /**
+     * Notified when pending_events may have room again for a channel that's
+     * blocked on EventQueueOverflowPolicy::Block.
+     */
+    pub(in crate) event_queue_space: Arc<tokio::sync::Notify>,
+    /**
+     * Where the Tube spawns the tasks that finish or abort it when it's 
+     * dropped (the executor of the channel it's on).
+     */
+    pub executor: ChannelExecutor,
+    pub idle_timeout: Option<IdleTimeout>,
+    /**
+     * Incremented whenever the idle_timeout is changed so that timers for 
+     * the previous idle_timeout stop.
+     */
+    pub(in crate) idle_timer_generation: u64,
+    /**
+     * When the Tube last received a payload (or had its idle_timeout set, or 
+     * last emitted a TubeEvent::IdleTimeout).
+     */
+    pub last_payload_received: Instant,
+    /**
+     * The last TubeEvent emitted by the Tube that moved it along its
+     * lifecycle (Uninitialized until then). Each event the Tube emits is 
+     * checked against it (see TubeEventTag::can_transition_to()).
+     */
+    pub(in crate) last_tube_event: tube_event::TubeEventTag,
+    /**
+     * Set for Tubes whose channel observes their lifecycle, until the Tube
+     * reaches a terminal completion_state.
+     */
+    pub(in crate) lifecycle: Option<TubeLifecycle>,
+    /**
+     * Whether Payload frames sent on this Tube carry a CRC-32 of their data.
+     * This is enabled per-tube via the PAYLOAD_CHECKSUM_HEADER NewTube header.
+     */
+    pub payload_checksums: bool,
+    /**
+     * Whether the Tube is paused (see Tube::pause()), in which case the acks
+     * for payloads it receives are withheld until it's resumed.
+     */
+    pub paused: bool,
+    /**
+     * The data received in payloads from the peer (including retransmitted
+     * duplicates, which aren't delivered again).
+     */
+    pub(in crate) payload_bytes_received: u64,
+    /**
+     * The data sent in payloads to the peer (not counting retransmissions).
+     */
+    pub(in crate) payload_bytes_sent: u64,
+    /**
+     * The chunks received so far of a payload that the peer split across 
+     * PartialPayload frames.
+     */
+    pub(in crate) payload_reassembly: PayloadReassembly,
     pub pending_events: VecDeque<tube_event::TubeEvent>,
-    pub sendacks: HashMap<u16, InvertedFutureResolver<()>>,
+    /**
+     * Whether the client created this Tube via the RECEIVE_ONLY_HEADER 
+     * NewTube header.
+     */
+    pub receive_only: bool,
+    /**
+     * The sequence numbers of SequencedPayloads received from the peer, used
+     * to drop retransmitted duplicates and to build SelectiveAcks.
+     */
+    pub received_sequences: ReceivedSequences,
+    /**
+     * Where to report the ack for each payload that's waiting on one, keyed
+     * by the payload's AckId.
+     */
+    pub sendacks: HashMap<u16, oneshot::Sender<Result<(), error::SendError>>>,
+    /**
+     * Whether this Tube was split and its TubeReader hasn't been dropped.
+     */
+    pub split_reader_alive: bool,
+    /**
+     * For Tubes with cumulative_acks, the AckIds of outstanding sendacks in 
+     * the order their payloads were sent. AckIds are recycled, so "up to" an
+     * AckId is determined by send order rather than by AckId value.
+     */
+    pub sendack_order: VecDeque<u16>,
+    /**
+     * When the Tube's consumer last left events queued without taking one 
+     * (see SlowConsumerLimit). None while the queue is empty.
+     */
+    pub(in crate) stalled_since: Option<Instant>,
     pub completion_state: TubeCompletionState,
+    /**
+     * SequencedPayloads sent to the peer that it hasn't selectively acked 
+     * yet.
+     */
+    pub unacked_sequenced: UnackedSequencedPayloads,
     pub waker: Option<task::Waker>,
+    /**
+     * The AckIds of payloads received while the Tube was paused, in the 
+     * order they were received.
+     */
+    pub(in crate) withheld_acks: Vec<u16>,
 }
 impl TubeManager {
     pub fn new() -> Self {
         TubeManager {
             abort_pending_id_reservation: None,
+            abort_ack_pending: false,
+            ack_timeout_policy: None,
+            channel_tube_managers: None,
+            completion_wakers: vec![],
             completion_state: TubeCompletionState::Open,
+            cumulative_acks: false,
+            deferred_drop: None,
+            establishment_frames: vec![],
+            establishment_pending: false,
+            event_queue_limit: None,
+            event_queue_space: Arc::new(tokio::sync::Notify::new()),
+            executor: ChannelExecutor::default(),
+            idle_timeout: None,
+            idle_timer_generation: 0,
+            last_payload_received: Instant::now(),
+            last_tube_event: tube_event::TubeEventTag::Uninitialized,
+            lifecycle: None,
+            payload_bytes_received: 0,
+            payload_bytes_sent: 0,
+            payload_checksums: false,
+            payload_reassembly: PayloadReassembly::new(),
+            paused: false,
             pending_events: VecDeque::new(),
+            receive_only: false,
+            received_sequences: ReceivedSequences::new(),
+            sendack_order: VecDeque::new(),
             sendacks: HashMap::new(),
+            split_reader_alive: false,
+            stalled_since: None,
+            unacked_sequenced: UnackedSequencedPayloads::new(),
             waker: None,
+            withheld_acks: vec![],
+        }
+    }
+
+    /**
+     * Tracks a sendack for the payload sent with ack_id, returning a receiver
+     * that resolves once the peer acks it (so waiting on the ack doesn't
+     * involve this TubeManager). Sendacks must be inserted in the order their
+     * payloads are sent. Returns None if ack_id is already in use.
+     */
+    pub(in crate) fn insert_sendack(&mut self, ack_id: u16) -> Option<SendAck> {
+        let (sendack_sender, sendack) = oneshot::channel();
+        if self.sendacks.try_insert(ack_id, sendack_sender).is_err() {
+            return None;
+        }
+        if self.cumulative_acks {
+            self.sendack_order.push_back(ack_id);
+        }
+        Some(sendack)
+    }
+
+    /**
+     * Removes this TubeManager from its channel's map of TubeManagers (if 
+     * it's still the one tracked under tube_id). Must not be called while 
+     * this TubeManager is locked (see ChannelTubeManagers).
+     */
+    pub(in crate) fn untrack(tube_manager: &Arc<Mutex<TubeManager>>, tube_id: u32) {
+        let channel_tube_managers = match &tube_manager.lock().unwrap().channel_tube_managers {
+            Some(channel_tube_managers) => channel_tube_managers.upgrade(),
+            None => None,
+        };
+        let channel_tube_managers = match channel_tube_managers {
+            Some(channel_tube_managers) => channel_tube_managers,
+            None => return,
+        };
+        channel_tube_managers.untrack(tube_id, tube_manager);
+    }
+
+    pub(in crate) fn remove_sendack(&mut self, ack_id: u16) {
+        if self.sendacks.remove(&ack_id).is_some() && self.cumulative_acks {
+            self.sendack_order.retain(|pending_ack_id| *pending_ack_id != ack_id);
+        }
+    }
+
+    /**
+     * Resolves (and stops tracking) the sendack for ack_id and, for Tubes
+     * with cumulative_acks, every sendack whose payload was sent before it.
+     * Returns false if no sendack is tracked for ack_id.
+     */
+    pub(in crate) fn resolve_sendacks(&mut self, ack_id: u16) -> bool {
+        if !self.cumulative_acks {
+            return match self.sendacks.remove(&ack_id) {
+                Some(sendack_sender) => {
+                    // The sender may have stopped waiting on the ack already
+                    let _ = sendack_sender.send(Ok(()));
+                    true
+                },
+                None => false,
+            };
+        }
+
+        if !self.sendack_order.contains(&ack_id) {
+            return false;
+        }
+        while let Some(acked_id) = self.sendack_order.pop_front() {
+            if let Some(sendack_sender) = self.sendacks.remove(&acked_id) {
+                let _ = sendack_sender.send(Ok(()));
+            }
+            if acked_id == ack_id {
+                break;
+            }
+        }
+        true
+    }
+
+    /**
+     * Fails (and stops tracking) the sendack for ack_id with the error the
+     * peer reported for its payload. Returns false if no sendack is tracked
+     * for ack_id.
+     */
+    pub(in crate) fn fail_sendack(&mut self, ack_id: u16, e: error::SendError) -> bool {
+        let sendack_sender = match self.sendacks.remove(&ack_id) {
+            Some(sendack_sender) => sendack_sender,
+            None => return false,
+        };
+        if self.cumulative_acks {
+            self.sendack_order.retain(|pending_ack_id| *pending_ack_id != ack_id);
+        }
+        let _ = sendack_sender.send(Err(e));
+        true
+    }
+
+    /**
+     * Determines whether a received payload can be queued, applying the 
+     * event_queue_limit's policy if pending_events is full. For 
+     * EventQueueOverflowPolicy::DropOldest this drops the oldest queued 
+     * payload.
+     */
+    pub(in crate) fn make_room_for_payload(&mut self) -> PayloadRoom {
+        let limit = match self.event_queue_limit {
+            Some(limit) if self.pending_events.len() >= limit.capacity => limit,
+            _ => return PayloadRoom::Available,
+        };
+        match limit.policy {
+            EventQueueOverflowPolicy::Block => 
+                PayloadRoom::Wait(self.event_queue_space.clone()),
+            EventQueueOverflowPolicy::DropOldest => {
+                let oldest_payload = self.pending_events.iter().position(|event| {
+                    matches!(event, tube_event::TubeEvent::Payload(_))
+                });
+                if let Some(idx) = oldest_payload {
+                    self.pending_events.remove(idx);
+                }
+                PayloadRoom::Available
+            },
+            EventQueueOverflowPolicy::AbortTube => PayloadRoom::Overflowed,
+        }
+    }
+
+    /**
+     * Lets a channel that's blocked on this Tube's event queue check for room
+     * again.
+     */
+    pub(in crate) fn notify_event_queue_space(&self) {
+        if self.event_queue_limit.is_some() {
+            self.event_queue_space.notify_one();
         }
     }
+
+    /**
+     * Withholds the ack for a received payload if the Tube is paused. Returns
+     * false (and leaves acking to the caller) if it isn't.
+     */
+    pub(in crate) fn withhold_ack(&mut self, ack_id: u16) -> bool {
+        if self.paused {
+            self.withheld_acks.push(ack_id);
+        }
+        self.paused
+    }
+
+    /**
+     * Unpauses the Tube and returns the AckIds of the withheld acks that 
+     * should now be sent. For Tubes with cumulative_acks that's only the 
+     * latest, since it covers the others. Acks are dropped if the Tube has 
+     * reached a terminal completion_state in the meantime.
+     */
+    pub(in crate) fn resume(&mut self) -> Vec<u16> {
+        self.paused = false;
+        let mut withheld_acks = std::mem::take(&mut self.withheld_acks);
+        if self.completion_state.is_terminal() {
+            return vec![];
+        }
+        if self.cumulative_acks && withheld_acks.len() > 1 {
+            withheld_acks.drain(..withheld_acks.len() - 1);
+        }
+        withheld_acks
+    }
+
+    /**
+     * Resolves once the server has acked the NewTube for this Tube (Ok) or 
+     * has rejected it (Err, carrying the TubeEvent that reported why).
+     */
+    pub(in crate) fn poll_establishment(
+        &mut self,
+        cx: &mut task::Context,
+    ) -> task::Poll<Result<(), tube_event::TubeEvent>> {
+        if !self.establishment_pending {
+            return task::Poll::Ready(Ok(()));
+        }
+        let rejection = self.pending_events.iter().position(|event| matches!(
+            event,
+            tube_event::TubeEvent::Abort(_) | tube_event::TubeEvent::StreamError(_)
+        ));
+        if let Some(idx) = rejection {
+            self.establishment_pending = false;
+            self.establishment_frames.clear();
+            return task::Poll::Ready(Err(self.pending_events.remove(idx).unwrap()));
+        }
+        self.waker = Some(cx.waker().clone());
+        task::Poll::Pending
+    }
+
+    pub(in crate) fn record_payload_received(&mut self) {
+        self.last_payload_received = Instant::now();
+    }
+
+    pub fn set_completion_state(&mut self, completion_state: TubeCompletionState) {
+        self.completion_state = completion_state;
+        if let Some(outcome) = TubeOutcome::from_completion_state(&self.completion_state) {
+            if let Some(lifecycle) = self.lifecycle.take() {
+                lifecycle.close(outcome, self.payload_bytes_received, self.payload_bytes_sent);
+            }
+            // A channel blocked on this Tube's event queue should stop waiting
+            self.event_queue_space.notify_one();
+            for waker in self.completion_wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tube_manager_tests {
+    use super::*;
+
+    #[test]
+    fn cumulative_acks_resolve_all_earlier_sendacks() {
+        let mut tube_mgr = TubeManager::new();
+        tube_mgr.cumulative_acks = true;
+        // AckIds are recycled, so send order needn't match AckId order
+        let mut sendacks = vec![];
+        for ack_id in [5, 2, 9] {
+            sendacks.push(tube_mgr.insert_sendack(ack_id).unwrap());
+        }
+
+        assert!(tube_mgr.resolve_sendacks(2));
+        assert!(matches!(sendacks[0].try_recv(), Ok(Ok(()))));
+        assert!(matches!(sendacks[1].try_recv(), Ok(Ok(()))));
+        assert!(matches!(sendacks[2].try_recv(), Err(oneshot::error::TryRecvError::Empty)));
+        assert_eq!(tube_mgr.sendack_order, VecDeque::from([9]));
+        assert_eq!(tube_mgr.sendacks.keys().collect::<Vec<_>>(), vec![&9]);
+
+        // Already covered by the previous cumulative ack
+        assert!(!tube_mgr.resolve_sendacks(5));
+    }
+
+    #[test]
+    fn resuming_a_cumulative_tube_only_sends_the_latest_withheld_ack() {
+        let mut tube_mgr = TubeManager::new();
+        tube_mgr.cumulative_acks = true;
+        tube_mgr.paused = true;
+        for ack_id in [5, 2, 9] {
+            assert!(tube_mgr.withhold_ack(ack_id));
+        }
+        assert_eq!(tube_mgr.resume(), vec![9]);
+        assert!(!tube_mgr.paused);
+        assert!(tube_mgr.resume().is_empty());
+    }
+
+    #[test]
+    fn removed_sendacks_leave_the_cumulative_order() {
+        let mut tube_mgr = TubeManager::new();
+        tube_mgr.cumulative_acks = true;
+        let _sendack = tube_mgr.insert_sendack(1);
+        tube_mgr.remove_sendack(1);
+
+        assert!(tube_mgr.sendack_order.is_empty());
+        assert!(!tube_mgr.resolve_sendacks(1));
+    }
+
+    #[test]
+    fn failed_sendacks_resolve_with_the_error_and_stop_being_tracked() {
+        let mut tube_mgr = TubeManager::new();
+        tube_mgr.cumulative_acks = true;
+        let mut failed = tube_mgr.insert_sendack(1).unwrap();
+        let mut pending = tube_mgr.insert_sendack(2).unwrap();
+
+        let e = error::SendError::PayloadChecksumMismatch("1: oops".to_string());
+        assert!(tube_mgr.fail_sendack(1, e));
+        assert!(matches!(
+            failed.try_recv(),
+            Ok(Err(error::SendError::PayloadChecksumMismatch(detail))) if detail == "1: oops"
+        ));
+        assert!(matches!(pending.try_recv(), Err(oneshot::error::TryRecvError::Empty)));
+        assert_eq!(tube_mgr.sendack_order, VecDeque::from([2]));
+
+        let e = error::SendError::PayloadChecksumMismatch("1: oops".to_string());
+        assert!(!tube_mgr.fail_sendack(1, e));
+    }
+
+    #[test]
+    fn establishment_fails_with_the_event_that_rejected_the_tube() {
+        let mut tube_mgr = TubeManager::new();
+        tube_mgr.establishment_pending = true;
+        let waker = futures::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        assert_eq!(tube_mgr.poll_establishment(&mut cx), task::Poll::Pending);
+
+        let rejection = tube_event::TubeEvent::StreamError(
+            tube_event::TubeEvent_StreamError::PeerError {
+                code: frame::ErrorCode::OverLimit,
+                detail: "too many tubes".to_string(),
+            }
+        );
+        tube_mgr.pending_events.push_back(rejection.clone());
+        assert_eq!(tube_mgr.poll_establishment(&mut cx), task::Poll::Ready(Err(rejection)));
+        assert!(tube_mgr.pending_events.is_empty());
+    }
 }
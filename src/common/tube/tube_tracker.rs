@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task;
+
+use crate::common::frame;
+use super::tube::Tube;
+use super::tube_manager::TubeCompletionState;
+use super::tube_manager::TubeManager;
+
+/**
+ * How a Tube finished.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum TubeOutcome {
+    Closed,
+    AbortedFromLocal(frame::AbortReason),
+    AbortedFromRemote(frame::AbortReason),
+}
+impl TubeOutcome {
+    /**
+     * None for a Tube that hasn't finished.
+     */
+    pub(in crate) fn from_completion_state(completion_state: &TubeCompletionState) -> Option<Self> {
+        use TubeCompletionState::*;
+        match completion_state {
+            Closed => Some(TubeOutcome::Closed),
+            AbortedFromLocal(reason) => Some(TubeOutcome::AbortedFromLocal(reason.clone())),
+            AbortedFromRemote(reason) => Some(TubeOutcome::AbortedFromRemote(reason.clone())),
+            Open | ClientHasFinishedSending | ServerHasFinishedSending => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TrackedTube {
+    tube_id: u32,
+    tube_mgr: Arc<Mutex<TubeManager>>,
+}
+
+/**
+ * Keeps track of every Tube created on a Channel so that
+ * Channel::join_all_tubes() can wait for all of them to finish.
+ */
+#[derive(Clone, Debug, Default)]
+pub(in crate) struct TubeTracker {
+    tubes: Arc<Mutex<Vec<TrackedTube>>>,
+}
+impl TubeTracker {
+    pub fn new() -> Self {
+        TubeTracker {
+            tubes: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn track(&self, tube: &Tube) {
+        self.tubes.lock().unwrap().push(TrackedTube {
+            tube_id: tube.get_id(),
+            tube_mgr: tube.tube_manager().clone(),
+        });
+    }
+
+    pub fn join_all(&self) -> JoinAllTubes {
+        JoinAllTubes {
+            tracker: self.clone(),
+        }
+    }
+}
+
+/**
+ * Resolves once every Tube created on a Channel (including Tubes created
+ * after this future was first polled) has been closed or aborted, yielding
+ * each Tube's id and TubeOutcome in the order the Tubes were created.
+ *
+ * Tubes that have been reported by a resolved JoinAllTubes are forgotten by
+ * the Channel, so a subsequent join_all_tubes() only reports Tubes created
+ * after that.
+ */
+#[derive(Debug)]
+pub struct JoinAllTubes {
+    tracker: TubeTracker,
+}
+impl futures::future::Future for JoinAllTubes {
+    type Output = Vec<(u32, TubeOutcome)>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Self::Output> {
+        let mut tubes = self.tracker.tubes.lock().unwrap();
+        let mut outcomes = Vec::with_capacity(tubes.len());
+        for tracked_tube in tubes.iter() {
+            let mut tube_mgr = tracked_tube.tube_mgr.lock().unwrap();
+            let outcome = match TubeOutcome::from_completion_state(&tube_mgr.completion_state) {
+                Some(outcome) => outcome,
+                None => {
+                    if !tube_mgr.completion_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                        tube_mgr.completion_wakers.push(cx.waker().clone());
+                    }
+                    return task::Poll::Pending;
+                },
+            };
+            outcomes.push((tracked_tube.tube_id, outcome));
+        }
+
+        tubes.clear();
+        task::Poll::Ready(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tube_tracker_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn track_tube_mgr(tracker: &TubeTracker, tube_id: u32) -> Arc<Mutex<TubeManager>> {
+        let tube_mgr = Arc::new(Mutex::new(TubeManager::new()));
+        tracker.tubes.lock().unwrap().push(TrackedTube {
+            tube_id,
+            tube_mgr: tube_mgr.clone(),
+        });
+        tube_mgr
+    }
+
+    #[tokio::test]
+    async fn resolves_once_all_tubes_are_terminal() {
+        let tracker = TubeTracker::new();
+        let tube_mgr1 = track_tube_mgr(&tracker, 1);
+        let tube_mgr3 = track_tube_mgr(&tracker, 3);
+
+        let join = tokio::spawn(tracker.join_all());
+
+        tube_mgr1.lock().unwrap().set_completion_state(TubeCompletionState::Closed);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!join.is_finished());
+
+        tube_mgr3.lock().unwrap().set_completion_state(
+            TubeCompletionState::AbortedFromRemote(frame::AbortReason::ApplicationAbort)
+        );
+        let outcomes = tokio::time::timeout(Duration::from_millis(100), join)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcomes, vec![
+            (1, TubeOutcome::Closed),
+            (3, TubeOutcome::AbortedFromRemote(frame::AbortReason::ApplicationAbort)),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn forgets_tubes_after_reporting_them() {
+        let tracker = TubeTracker::new();
+        let tube_mgr = track_tube_mgr(&tracker, 1);
+        tube_mgr.lock().unwrap().set_completion_state(TubeCompletionState::Closed);
+
+        assert_eq!(tracker.join_all().await, vec![(1, TubeOutcome::Closed)]);
+        assert_eq!(tracker.join_all().await, vec![]);
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::common::frame;
+use crate::common::PeerType;
+use crate::common::UniqueIdError;
+use crate::common::UniqueIdManager;
+use super::ack_timeout;
+use super::error;
+use super::send_window::SendWindow;
+use super::tube_manager::TubeCompletionState;
+use super::tube_manager::TubeManager;
+
+/**
+ * A cheap, cloneable handle for sending payloads on a Tube from several tasks
+ * at once (see Tube::writer()). The read side stays exclusively on the Tube.
+ *
+ * Frames written through any handle (or the Tube itself) are serialized by
+ * the channel's FrameSender, so each payload reaches the peer whole and
+ * payloads sent from one task arrive in the order that task sent them.
+ *
+ * Handles don't keep the Tube open: once the Tube has finished sending (or
+ * has been closed, aborted, or dropped) sends fail with
+ * SendError::TubeNotWritable. A handle uses the ack_timeout,
+ * max_in_flight_bytes, and priority the Tube had when the handle was made.
+ */
+#[derive(Clone, Debug)]
+pub struct TubeWriteHandle {
+    pub(in crate::common::tube) ack_timeout: Duration,
+    pub(in crate::common::tube) ackid_manager: Arc<Mutex<UniqueIdManager>>,
+    pub(in crate::common::tube) payload_checksums: bool,
+    pub(in crate::common::tube) peer_type: PeerType,
+    pub(in crate::common::tube) priority: u8,
+    pub(in crate::common::tube) send_window: SendWindow,
+    pub(in crate::common::tube) sender: frame::FrameSender,
+    pub(in crate::common::tube) tube_id: u32,
+    pub(in crate::common::tube) tube_manager: Arc<Mutex<TubeManager>>,
+}
+impl TubeWriteHandle {
+    pub fn get_id(&self) -> u32 {
+        self.tube_id
+    }
+
+    /**
+     * Whether payloads can still be sent on the Tube (i.e. this side hasn't
+     * finished sending and the Tube hasn't been aborted).
+     */
+    pub fn is_writable(&self) -> bool {
+        use TubeCompletionState::*;
+        let tube_mgr = self.tube_manager.lock().unwrap();
+        matches!(
+            (&tube_mgr.completion_state, &self.peer_type),
+            (&Open, _) |
+                (&ServerHasFinishedSending, &PeerType::Client) |
+                (&ClientHasFinishedSending, &PeerType::Server)
+        )
+    }
+
+    fn ensure_writable(&self) -> Result<(), error::SendError> {
+        if self.is_writable() {
+            Ok(())
+        } else {
+            Err(error::SendError::TubeNotWritable)
+        }
+    }
+
+    /**
+     * The frames that carry data as a Payload (see split_payload()).
+     */
+    pub(in crate::common::tube) fn make_payload_frames(
+        &self,
+        ack_id: Option<u16>,
+        data: bytes::Bytes,
+    ) -> Vec<frame::Frame> {
+        // The actual checksums are computed when the frames are encoded
+        let checksum = if self.payload_checksums { Some(0) } else { None };
+        split_payload(
+            self.tube_id,
+            data,
+            self.sender.max_payload_frame_size(),
+            checksum,
+            |data| frame::Frame::Payload { tube_id: self.tube_id, ack_id, checksum, data },
+        )
+    }
+
+    /**
+     * Like Tube::send(): sends data and waits up to ack_timeout for the peer
+     * to ack it.
+     */
+    pub async fn send(
+        &self,
+        data: impl Into<bytes::Bytes>,
+        ack_timeout: Duration,
+    ) -> Result<(), error::SendError> {
+        self.ensure_writable()?;
+        self.send_unchecked(data.into(), ack_timeout).await
+    }
+
+    /**
+     * Like send(), but waits for the ack for as long as the handle's
+     * ack_timeout.
+     */
+    pub async fn send_acked(
+        &self,
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        self.send(data, self.ack_timeout).await
+    }
+
+    pub async fn send_and_forget(
+        &self,
+        data: impl Into<bytes::Bytes>,
+    ) -> Result<(), error::SendError> {
+        self.ensure_writable()?;
+        self.send_and_forget_unchecked(data.into()).await
+    }
+
+    pub(in crate::common::tube) async fn send_unchecked(
+        &self,
+        data: bytes::Bytes,
+        ack_timeout: Duration,
+    ) -> Result<(), error::SendError> {
+        let ack_id = match self.ackid_manager.lock().unwrap().take_id() {
+            Ok(ack_id) => ack_id,
+            Err(UniqueIdError::NoIdsAvailable) => return Err(error::SendError::AckIdsExhausted),
+        };
+
+        // ackid_manager is capped at frame::MAX_ACK_ID, so this always fits.
+        let ack_id_val = ack_id.val() as u16;
+
+        // Held until the ack arrives (or we give up waiting on it)
+        let _send_window_permit = self.send_window.acquire(data.len()).await;
+        let num_bytes = data.len() as u64;
+        let payload_frames = self.make_payload_frames(Some(ack_id_val), data);
+
+        let sendack = match self.tube_manager.lock().unwrap().insert_sendack(ack_id_val) {
+            Some(sendack) => sendack,
+            None => return Err(error::SendError::AckIdAlreadyInUseInternalError),
+        };
+
+        let send_result =
+            self.sender.send_batch_with_priority(payload_frames, self.priority).await;
+        if let Err(e) = send_result {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            tube_mgr.remove_sendack(ack_id_val);
+            return Err(e.into())
+        }
+        self.tube_manager.lock().unwrap().payload_bytes_sent += num_bytes;
+
+        match tokio::time::timeout(ack_timeout, sendack).await {
+            // Resolving (or failing) the sendack also stopped tracking it
+            Ok(Ok(result)) => result,
+            // A sendack is only dropped unresolved once it's no longer
+            // tracked, in which case the ack isn't coming either
+            Ok(Err(_)) => {
+                self.tube_manager.lock().unwrap().remove_sendack(ack_id_val);
+                Err(error::SendError::TimedOutWaitingOnAck(ack_timeout))
+            },
+            Err(_) => {
+                self.tube_manager.lock().unwrap().remove_sendack(ack_id_val);
+                ack_timeout::handle_ack_timeout(
+                    self.tube_id,
+                    ack_timeout,
+                    &self.tube_manager,
+                    &self.sender,
+                );
+                Err(error::SendError::TimedOutWaitingOnAck(ack_timeout))
+            },
+        }
+    }
+
+    pub(in crate::common::tube) async fn send_and_forget_unchecked(
+        &self,
+        data: bytes::Bytes,
+    ) -> Result<(), error::SendError> {
+        // Held until the transport has accepted the frame
+        let _send_window_permit = self.send_window.acquire(data.len()).await;
+        let num_bytes = data.len() as u64;
+        let payload_frames = self.make_payload_frames(None, data);
+        match self.sender.send_batch_with_priority(payload_frames, self.priority).await {
+            Ok(()) => {
+                self.tube_manager.lock().unwrap().payload_bytes_sent += num_bytes;
+                Ok(())
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/**
+ * Splits data into chunks of at most max_size bytes without copying it. Each
+ * chunk but the last is sent in a PartialPayload frame, and the last one (or
+ * all of data, if it fits in one frame) in the frame made by 
+ * make_last_frame, which the peer waits on to deliver (and ack) the payload
+ * whole.
+ */
+pub(in crate::common::tube) fn split_payload(
+    tube_id: u32,
+    mut data: bytes::Bytes,
+    max_size: usize,
+    checksum: Option<u32>,
+    make_last_frame: impl FnOnce(bytes::Bytes) -> frame::Frame,
+) -> Vec<frame::Frame> {
+    let mut frames = Vec::with_capacity(data.len().div_ceil(max_size).max(1));
+    while data.len() > max_size {
+        frames.push(frame::Frame::PartialPayload {
+            tube_id,
+            checksum,
+            data: data.split_to(max_size),
+        });
+    }
+    frames.push(make_last_frame(data));
+    frames
+}
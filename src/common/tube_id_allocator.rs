@@ -0,0 +1,84 @@
+use super::PeerType;
+#[cfg(feature = "client")]
+use super::UniqueId;
+#[cfg(feature = "client")]
+use super::UniqueIdError;
+#[cfg(feature = "client")]
+use super::UniqueIdManager;
+
+/**
+ * Allocates the ids for the Tubes that one side of a channel opens. Each side
+ * allocates from its own half of the id space so that the Tubes opened by the
+ * two sides can never collide: clients open Tubes with odd ids, and even ids
+ * are left to servers.
+ *
+ * An id can be taken again once the Tube holding it is done with it. The
+ * counter never wraps around, though: once every id up to max_id is in use,
+ * take_id() fails with UniqueIdError::NoIdsAvailable until one is returned.
+ */
+#[derive(Debug)]
+pub(in crate) struct TubeIdAllocator {
+    #[cfg(feature = "client")]
+    ids: UniqueIdManager,
+}
+impl TubeIdAllocator {
+    #[cfg(feature = "client")]
+    pub(in crate) fn new(opener: PeerType, max_id: u32) -> Self {
+        let ids = match opener {
+            PeerType::Client => UniqueIdManager::new_with_odd_ids(),
+            PeerType::Server => UniqueIdManager::new_with_even_ids(),
+        };
+        TubeIdAllocator {
+            ids: ids.with_max_id(max_id),
+        }
+    }
+
+    /**
+     * Whether tube_id is from the half of the id space that opener allocates
+     * from, i.e. whether a Tube opened by opener may have it.
+     */
+    pub(in crate) fn is_allocated_by(opener: PeerType, tube_id: u32) -> bool {
+        match opener {
+            PeerType::Client => !tube_id.is_multiple_of(2),
+            PeerType::Server => tube_id.is_multiple_of(2),
+        }
+    }
+
+    #[cfg(feature = "client")]
+    pub(in crate) fn take_id(&mut self) -> Result<UniqueId, UniqueIdError> {
+        self.ids.take_id()
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tube_id_allocator_tests {
+    use super::*;
+
+    #[test]
+    fn each_side_allocates_ids_the_other_side_never_does() {
+        for opener in [PeerType::Client, PeerType::Server] {
+            let mut allocator = TubeIdAllocator::new(opener, u16::MAX.into());
+            for _ in 0..3 {
+                let tube_id = allocator.take_id().unwrap();
+                assert!(TubeIdAllocator::is_allocated_by(opener, tube_id.val()));
+                assert!(!TubeIdAllocator::is_allocated_by(opener.remote(), tube_id.val()));
+            }
+        }
+    }
+
+    #[test]
+    fn ids_run_out_rather_than_wrapping_around() {
+        let mut ids = vec![];
+        let mut allocator = TubeIdAllocator::new(PeerType::Client, 7);
+        while let Ok(tube_id) = allocator.take_id() {
+            ids.push(tube_id);
+        }
+        assert_eq!(ids.iter().map(UniqueId::val).collect::<Vec<_>>(), vec![1, 3, 5, 7]);
+
+        // Only ids that are returned can be taken again
+        let returned_id = ids.remove(1).val();
+        let retaken_id = allocator.take_id().unwrap();
+        assert_eq!(retaken_id.val(), returned_id);
+        assert!(allocator.take_id().is_err());
+    }
+}
@@ -17,12 +17,12 @@ pub enum UniqueIdIncrementPolicy {
 
 #[derive(Debug)]
 pub struct UniqueId {
-    avail_ids: Option<Arc<Mutex<VecDeque<u16>>>>,
-    id: u16,
+    avail_ids: Option<Arc<Mutex<VecDeque<u32>>>>,
+    id: u32,
     taken: bool,
 }
 impl UniqueId {
-    pub fn new(id: u16, avail_ids: Option<Arc<Mutex<VecDeque<u16>>>>) -> Self {
+    pub fn new(id: u32, avail_ids: Option<Arc<Mutex<VecDeque<u32>>>>) -> Self {
         UniqueId {
             avail_ids,
             id,
@@ -52,7 +52,7 @@ impl UniqueId {
         new
     }
 
-    pub fn val(&self) -> u16 {
+    pub fn val(&self) -> u32 {
         self.id
     }
 }
@@ -80,10 +80,11 @@ impl PartialEq for UniqueId {
 
 #[derive(Debug)]
 pub struct UniqueIdManager { 
-    avail_ids: Arc<Mutex<VecDeque<u16>>>,
-    counter: u16,
+    avail_ids: Arc<Mutex<VecDeque<u32>>>,
+    counter: u32,
     ids_exhausted: bool,
     increment_policy: UniqueIdIncrementPolicy,
+    max_id: u32,
 }
 impl UniqueIdManager {
     fn new_impl(increment_policy: UniqueIdIncrementPolicy) -> Self {
@@ -96,6 +97,7 @@ impl UniqueIdManager {
             },
             ids_exhausted: false,
             increment_policy,
+            max_id: u16::MAX as u32,
         }
     }
 
@@ -111,10 +113,19 @@ impl UniqueIdManager {
         UniqueIdManager::new_impl(UniqueIdIncrementPolicy::Odd)
     }
 
+    /**
+     * By default ids are limited to 16 bits. This sets the largest id that 
+     * will be emitted before the manager reports NoIdsAvailable.
+     */
+    pub fn with_max_id(mut self, max_id: u32) -> Self {
+        self.max_id = max_id;
+        self
+    }
+
     pub fn take_id(&mut self) -> Result<UniqueId, UniqueIdError> {
         // TODO: This implementation will grow the avail_ids vec up to 
-        //       ~65k (2^16) if a large number of ids are taken without being
-        //       returned fast enough.
+        //       max_id entries if a large number of ids are taken without 
+        //       being returned fast enough.
         //
         //       This is probably fine for now, but we could probably do a 
         //       little better with a heap of range structs (or something 
@@ -128,17 +139,21 @@ impl UniqueIdManager {
                 }
 
                 let id = self.counter;
-                if id == u16::MAX {
+                if id > self.max_id {
                     self.ids_exhausted = true;
-                } else {
-                    match self.increment_policy {
-                        UniqueIdIncrementPolicy::Even
-                        | UniqueIdIncrementPolicy::Odd =>
-                            self.counter += 2,
-
-                        UniqueIdIncrementPolicy::Sequential =>
-                            self.counter += 1,
-                    }
+                    return Err(UniqueIdError::NoIdsAvailable);
+                }
+
+                let increment = match self.increment_policy {
+                    UniqueIdIncrementPolicy::Even
+                    | UniqueIdIncrementPolicy::Odd => 2,
+
+                    UniqueIdIncrementPolicy::Sequential => 1,
+                };
+                match self.counter.checked_add(increment) {
+                    Some(next_id) if next_id <= self.max_id => 
+                        self.counter = next_id,
+                    _ => self.ids_exhausted = true,
                 }
                 id
             }
@@ -177,6 +192,31 @@ mod uniqueidmanager_tests {
         }
     }
 
+    #[test]
+    fn errors_when_ids_exceed_max_id() {
+        let mut idman = UniqueIdManager::new_with_even_ids().with_max_id(5);
+        let ids = [
+            idman.take_id().unwrap(), 
+            idman.take_id().unwrap(), 
+            idman.take_id().unwrap(),
+        ];
+        assert_eq!(ids.iter().map(|id| id.val()).collect::<Vec<_>>(), vec![0, 2, 4]);
+        match idman.take_id() {
+            Err(UniqueIdError::NoIdsAvailable) => (),
+            Ok(id) => panic!("Emitted id={} beyond the max_id!", id),
+        }
+    }
+
+    #[test]
+    fn emits_ids_beyond_16_bits_when_max_id_allows() {
+        let mut idman = UniqueIdManager::new().with_max_id(u32::MAX);
+        let mut ids = vec![];
+        for _i in 0..=u16::MAX {
+            ids.push(idman.take_id().unwrap());
+        }
+        assert_eq!(idman.take_id().unwrap().val(), (u16::MAX as u32) + 1);
+    }
+
     #[test]
     fn reuses_ids_after_they_are_dropped() {
         let mut idman = UniqueIdManager::new();
@@ -0,0 +1,449 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
+use hyper::body::HttpBody;
+use ring::rand::SecureRandom;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use super::PeerType;
+
+/**
+ * Appended to the client's Sec-WebSocket-Key before it's hashed into the
+ * server's Sec-WebSocket-Accept (see RFC 6455, section 1.3).
+ */
+const ACCEPT_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/**
+ * The largest payload accepted in a single WebSocket frame. Tubez frames are
+ * carried as a stream of bytes (so they needn't line up with WebSocket
+ * frames), and this only bounds how much is buffered for one WebSocket frame.
+ */
+const MAX_FRAME_PAYLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+/**
+ * The only Sec-WebSocket-Version that's spoken.
+ */
+pub(in crate) const VERSION: &str = "13";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/**
+ * The Sec-WebSocket-Accept a server answers the given Sec-WebSocket-Key with.
+ */
+pub(in crate) fn accept_key(key: &[u8]) -> String {
+    let mut digest = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    digest.update(key);
+    digest.update(ACCEPT_GUID);
+    base64::engine::general_purpose::STANDARD.encode(digest.finish())
+}
+
+/**
+ * A random Sec-WebSocket-Key for a client to open a WebSocket with.
+ */
+#[cfg(feature = "client")]
+pub(in crate) fn new_key() -> String {
+    let mut key = [0; 16];
+    fill_random(&mut key);
+    base64::engine::general_purpose::STANDARD.encode(key)
+}
+
+fn fill_random(dest: &mut [u8]) {
+    if ring::rand::SystemRandom::new().fill(dest).is_err() {
+        panic!("The system's random number generator failed");
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(in crate) enum WsFrame {
+    Close,
+    /**
+     * The payload of a binary (or continuation) frame. Tubez frames are read
+     * out of these as a stream of bytes, so message boundaries don't matter.
+     */
+    Data(Bytes),
+    Ping(Bytes),
+    Pong(Bytes),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(in crate) enum WsDecodeError {
+    /**
+     * A control frame (Close, Ping, or Pong) was fragmented or had more than
+     * 125 bytes of payload.
+     */
+    InvalidControlFrame(u8),
+    /**
+     * The frame was (or wasn't) masked when the peer must (or mustn't) mask
+     * its frames.
+     */
+    InvalidMasking,
+    /**
+     * A reserved bit was set, but no extensions are negotiated.
+     */
+    ReservedBitsSet,
+    FrameTooLarge(u64),
+    /**
+     * Text frames (and opcodes that aren't defined) aren't spoken.
+     */
+    UnsupportedOpcode(u8),
+}
+
+/**
+ * Reads WebSocket frames out of the bytes received from the peer.
+ */
+pub(in crate) struct WsDecoder {
+    buf: BytesMut,
+    expect_masked: bool,
+}
+impl WsDecoder {
+    /**
+     * Frames from a client are masked and frames from a server aren't.
+     */
+    pub(in crate) fn new(peer_type: PeerType) -> Self {
+        WsDecoder {
+            buf: BytesMut::new(),
+            expect_masked: matches!(peer_type, PeerType::Client),
+        }
+    }
+
+    /**
+     * Returns the frames completed by data (which may be none).
+     */
+    pub(in crate) fn decode(&mut self, data: &[u8]) -> Result<Vec<WsFrame>, WsDecodeError> {
+        self.buf.extend_from_slice(data);
+        let mut frames = vec![];
+        while let Some(frame) = self.decode_frame()? {
+            match frame {
+                WsFrame::Data(data) if data.is_empty() => (),
+                frame => frames.push(frame),
+            }
+        }
+        Ok(frames)
+    }
+
+    fn decode_frame(&mut self) -> Result<Option<WsFrame>, WsDecodeError> {
+        if self.buf.len() < 2 {
+            return Ok(None);
+        }
+        let (fin, opcode) = (self.buf[0] & 0x80 != 0, self.buf[0] & 0x0F);
+        if self.buf[0] & 0x70 != 0 {
+            return Err(WsDecodeError::ReservedBitsSet);
+        }
+        let masked = self.buf[1] & 0x80 != 0;
+        if masked != self.expect_masked {
+            return Err(WsDecodeError::InvalidMasking);
+        }
+
+        let (payload_len, len_size) = match self.buf[1] & 0x7F {
+            126 if self.buf.len() < 4 => return Ok(None),
+            126 => (u16::from_be_bytes([self.buf[2], self.buf[3]]) as u64, 2),
+            127 if self.buf.len() < 10 => return Ok(None),
+            127 => {
+                let mut len_bytes = [0; 8];
+                len_bytes.copy_from_slice(&self.buf[2..10]);
+                (u64::from_be_bytes(len_bytes), 8)
+            },
+            len => (len as u64, 0),
+        };
+        let is_control = opcode & 0x8 != 0;
+        if is_control && (!fin || payload_len > 125) {
+            return Err(WsDecodeError::InvalidControlFrame(opcode));
+        }
+        if payload_len > MAX_FRAME_PAYLOAD_SIZE {
+            return Err(WsDecodeError::FrameTooLarge(payload_len));
+        }
+
+        let mask_size = if masked { 4 } else { 0 };
+        let header_size = 2 + len_size + mask_size;
+        if self.buf.len() < header_size + payload_len as usize {
+            return Ok(None);
+        }
+        let mut mask = [0; 4];
+        mask[..mask_size].copy_from_slice(&self.buf[2 + len_size..header_size]);
+        self.buf.advance(header_size);
+        let mut payload = self.buf.split_to(payload_len as usize);
+        if masked {
+            apply_mask(&mut payload, mask);
+        }
+        let payload = payload.freeze();
+
+        match opcode {
+            OPCODE_BINARY | OPCODE_CONTINUATION => Ok(Some(WsFrame::Data(payload))),
+            OPCODE_CLOSE => Ok(Some(WsFrame::Close)),
+            OPCODE_PING => Ok(Some(WsFrame::Ping(payload))),
+            OPCODE_PONG => Ok(Some(WsFrame::Pong(payload))),
+            opcode => Err(WsDecodeError::UnsupportedOpcode(opcode)),
+        }
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (idx, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[idx % 4];
+    }
+}
+
+/**
+ * Encodes frame as sent by local_peer_type (clients mask their frames). Data
+ * is sent as a single binary frame.
+ */
+pub(in crate) fn encode(frame: &WsFrame, local_peer_type: PeerType) -> Vec<u8> {
+    let (opcode, payload) = match frame {
+        WsFrame::Close => (OPCODE_CLOSE, &[][..]),
+        WsFrame::Data(data) => (OPCODE_BINARY, &data[..]),
+        WsFrame::Ping(data) => (OPCODE_PING, &data[..]),
+        WsFrame::Pong(data) => (OPCODE_PONG, &data[..]),
+    };
+    let masked = matches!(local_peer_type, PeerType::Client);
+    let mask_bit = if masked { 0x80 } else { 0 };
+
+    let mut encoded = Vec::with_capacity(14 + payload.len());
+    encoded.put_u8(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => encoded.put_u8(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            encoded.put_u8(mask_bit | 126);
+            encoded.put_u16(len as u16);
+        },
+        len => {
+            encoded.put_u8(mask_bit | 127);
+            encoded.put_u64(len as u64);
+        },
+    }
+    if masked {
+        let mut mask = [0; 4];
+        fill_random(&mut mask);
+        encoded.extend_from_slice(&mask);
+        let payload_start = encoded.len();
+        encoded.extend_from_slice(payload);
+        apply_mask(&mut encoded[payload_start..], mask);
+    } else {
+        encoded.extend_from_slice(payload);
+    }
+    encoded
+}
+
+/**
+ * The write half of a WebSocket, shared by the tasks that send data and
+ * answer Pings.
+ */
+struct WsWriter<W> {
+    close_sent: bool,
+    local_peer_type: PeerType,
+    writer: W,
+}
+impl<W: AsyncWrite + Unpin> WsWriter<W> {
+    /**
+     * Nothing more is sent once a Close has been, and the write half is shut
+     * down along with it.
+     */
+    async fn send(&mut self, frame: WsFrame) -> std::io::Result<()> {
+        if self.close_sent {
+            return Ok(());
+        }
+        self.close_sent = frame == WsFrame::Close;
+        self.writer.write_all(&encode(&frame, self.local_peer_type)).await?;
+        if self.close_sent {
+            self.writer.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Carries a channel's frames over an established WebSocket (io), so that the
+ * channel itself is served just as it is over an HTTP/2 stream. The data
+ * received on the WebSocket is sent to incoming, and the data from outgoing
+ * is sent on the WebSocket in binary frames. Resolves once both directions
+ * have finished: incoming is aborted if the WebSocket fails (or ends without
+ * a Close), and a Close is sent once outgoing ends.
+ */
+pub(in crate) async fn bridge<IO>(
+    io: IO,
+    mut incoming: hyper::body::Sender,
+    mut outgoing: hyper::Body,
+    local_peer_type: PeerType,
+) where
+    IO: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, writer) = tokio::io::split(io);
+    let writer = Arc::new(tokio::sync::Mutex::new(WsWriter {
+        close_sent: false,
+        local_peer_type,
+        writer,
+    }));
+    let remote_peer_type = local_peer_type.remote();
+
+    let pong_writer = writer.clone();
+    let read_incoming = async move {
+        let mut decoder = WsDecoder::new(remote_peer_type);
+        let mut read_buf = vec![0; 16 * 1024];
+        loop {
+            let num_bytes = match reader.read(&mut read_buf).await {
+                Ok(0) => {
+                    log::trace!("WebSocket ended without a Close");
+                    incoming.abort();
+                    return;
+                },
+                Ok(num_bytes) => num_bytes,
+                Err(e) => {
+                    log::trace!("Reading from WebSocket failed: {:?}", e);
+                    incoming.abort();
+                    return;
+                },
+            };
+            let frames = match decoder.decode(&read_buf[..num_bytes]) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    log::error!("WebSocket frame decode error: {:?}", e);
+                    incoming.abort();
+                    let _ = pong_writer.lock().await.send(WsFrame::Close).await;
+                    return;
+                },
+            };
+            for frame in frames {
+                match frame {
+                    WsFrame::Data(data) => {
+                        if incoming.send_data(data).await.is_err() {
+                            return;
+                        }
+                    },
+                    WsFrame::Ping(data) => {
+                        let _ = pong_writer.lock().await.send(WsFrame::Pong(data)).await;
+                    },
+                    WsFrame::Pong(_) => (),
+                    WsFrame::Close => {
+                        let _ = pong_writer.lock().await.send(WsFrame::Close).await;
+                        return;
+                    },
+                }
+            }
+        }
+    };
+
+    let write_outgoing = async move {
+        while let Some(data_result) = outgoing.data().await {
+            let data = match data_result {
+                Ok(data) => data,
+                Err(e) => {
+                    log::trace!("Stream of data for WebSocket has errored: {:?}", e);
+                    break;
+                },
+            };
+            if data.is_empty() {
+                continue;
+            }
+            if let Err(e) = writer.lock().await.send(WsFrame::Data(data)).await {
+                log::trace!("Writing to WebSocket failed: {:?}", e);
+                return;
+            }
+        }
+        let _ = writer.lock().await.send(WsFrame::Close).await;
+    };
+
+    futures::future::join(read_incoming, write_outgoing).await;
+}
+
+#[cfg(test)]
+mod websocket_tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frames_decode_across_chunks_and_lengths() {
+        let data = Bytes::from(vec![7; 300]);
+        let mut encoded = encode(&WsFrame::Data(data.clone()), PeerType::Client);
+        encoded.extend(encode(&WsFrame::Ping(Bytes::from_static(b"hi")), PeerType::Client));
+        encoded.extend(encode(&WsFrame::Close, PeerType::Client));
+
+        let mut decoder = WsDecoder::new(PeerType::Client);
+        assert_eq!(decoder.decode(&encoded[..3]), Ok(vec![]));
+        assert_eq!(decoder.decode(&encoded[3..]), Ok(vec![
+            WsFrame::Data(data),
+            WsFrame::Ping(Bytes::from_static(b"hi")),
+            WsFrame::Close,
+        ]));
+    }
+
+    #[test]
+    fn frames_must_be_masked_only_by_clients() {
+        let encoded = encode(&WsFrame::Data(Bytes::from_static(b"x")), PeerType::Server);
+        assert_eq!(encoded, vec![0x82, 0x01, b'x']);
+        assert_eq!(
+            WsDecoder::new(PeerType::Client).decode(&encoded),
+            Err(WsDecodeError::InvalidMasking),
+        );
+        let encoded = encode(&WsFrame::Data(Bytes::from_static(b"x")), PeerType::Client);
+        assert_eq!(
+            WsDecoder::new(PeerType::Server).decode(&encoded),
+            Err(WsDecodeError::InvalidMasking),
+        );
+    }
+
+    #[test]
+    fn fragments_are_data_and_text_is_refused() {
+        // An unfinished binary frame followed by its (final) continuation
+        let fragments = [0x02, 0x01, b'a', 0x80, 0x01, b'b'];
+        assert_eq!(WsDecoder::new(PeerType::Server).decode(&fragments), Ok(vec![
+            WsFrame::Data(Bytes::from_static(b"a")),
+            WsFrame::Data(Bytes::from_static(b"b")),
+        ]));
+        assert_eq!(
+            WsDecoder::new(PeerType::Server).decode(&[0x81, 0x01, b'a']),
+            Err(WsDecodeError::UnsupportedOpcode(0x1)),
+        );
+        assert_eq!(
+            WsDecoder::new(PeerType::Server).decode(&[0x09, 0x00]),
+            Err(WsDecodeError::InvalidControlFrame(OPCODE_PING)),
+        );
+    }
+
+    #[tokio::test]
+    async fn bridge_carries_data_and_answers_pings_and_closes() {
+        let (local_io, mut remote_io) = tokio::io::duplex(1024);
+        let (incoming_sender, mut incoming) = hyper::Body::channel();
+        let (mut outgoing_sender, outgoing) = hyper::Body::channel();
+        let bridge_task = tokio::spawn(
+            bridge(local_io, incoming_sender, outgoing, PeerType::Server)
+        );
+
+        let mut remote_frames = vec![];
+        remote_frames.extend(encode(&WsFrame::Data(Bytes::from_static(b"in")), PeerType::Client));
+        remote_frames.extend(encode(&WsFrame::Ping(Bytes::from_static(b"p")), PeerType::Client));
+        remote_io.write_all(&remote_frames).await.unwrap();
+        assert_eq!(incoming.data().await.unwrap().unwrap(), Bytes::from_static(b"in"));
+        outgoing_sender.send_data(Bytes::from_static(b"out")).await.unwrap();
+        drop(outgoing_sender);
+
+        let mut decoder = WsDecoder::new(PeerType::Server);
+        let mut received = vec![];
+        let mut read_buf = [0; 64];
+        while received.last() != Some(&WsFrame::Close) {
+            let num_bytes = remote_io.read(&mut read_buf).await.unwrap();
+            received.extend(decoder.decode(&read_buf[..num_bytes]).unwrap());
+        }
+        // The Pong and the outgoing data are sent by separate tasks
+        assert_eq!(received.len(), 3);
+        assert!(received.contains(&WsFrame::Pong(Bytes::from_static(b"p"))));
+        assert!(received.contains(&WsFrame::Data(Bytes::from_static(b"out"))));
+
+        remote_io.write_all(&encode(&WsFrame::Close, PeerType::Client)).await.unwrap();
+        bridge_task.await.unwrap();
+        assert!(incoming.data().await.is_none());
+    }
+}
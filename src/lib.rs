@@ -3,6 +3,10 @@
 
 mod common;
 
+pub use common::capture;
+pub use common::ChannelExecutor;
+pub use common::LocalSetSpawner;
+pub use common::frame;
 pub use common::tube;
 
 // "client"-feature exports
@@ -12,3 +16,12 @@ pub use common::tube;
 // "server"-feature exports
 #[cfg(feature = "server")] pub mod server;
 #[cfg(feature = "server")] pub use server::Server;
+
+// "typed"-feature exports
+#[cfg(feature = "typed")] pub mod typed;
+
+// "bench"-feature exports
+#[cfg(feature = "bench")] pub mod bench;
+
+// "testing"-feature exports
+#[cfg(feature = "testing")] pub mod testing;
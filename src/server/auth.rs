@@ -0,0 +1,114 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+type AuthenticateFn =
+    dyn Fn(&ChannelInfo) -> BoxFuture<'static, Result<Identity, AuthError>> + Send + Sync;
+
+/**
+ * What's known about a channel before it is accepted: the HTTP request the
+ * client opened it with and the address it came from.
+ */
+#[derive(Clone, Debug)]
+pub struct ChannelInfo {
+    /**
+     * The address of the client's end of the connection (i.e. the peer's
+     * address, which may be a proxy's). None for connections from an
+     * accept stream given to Server::from_incoming().
+     */
+    pub remote_addr: Option<SocketAddr>,
+    pub request_headers: hyper::HeaderMap,
+    pub request_method: hyper::Method,
+    /**
+     * Its path is whatever endpoint path the client connected to (see
+     * Client::make_tube_channel_at()), which servers can route by.
+     */
+    pub request_uri: hyper::Uri,
+}
+impl ChannelInfo {
+    pub(in crate::server) fn new(
+        request_parts: hyper::http::request::Parts,
+        remote_addr: Option<SocketAddr>,
+    ) -> Self {
+        ChannelInfo {
+            remote_addr,
+            request_headers: request_parts.headers,
+            request_method: request_parts.method,
+            request_uri: request_parts.uri,
+        }
+    }
+}
+
+/**
+ * Who a Server's authenticator determined the client of a channel to be.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub principal: String,
+}
+impl Identity {
+    pub fn new(principal: impl Into<String>) -> Self {
+        Identity {
+            principal: principal.into(),
+        }
+    }
+}
+
+/**
+ * Why a Server's authenticator rejected a channel. The client's request is
+ * answered with the corresponding HTTP status (see status_code()) and the
+ * detail as the response body.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthError {
+    /**
+     * The client was identified but isn't allowed to open channels.
+     */
+    Forbidden(String),
+    /**
+     * The client's credentials are missing or invalid.
+     */
+    Unauthenticated(String),
+}
+impl AuthError {
+    pub fn status_code(&self) -> hyper::StatusCode {
+        match self {
+            AuthError::Forbidden(_) => hyper::StatusCode::FORBIDDEN,
+            AuthError::Unauthenticated(_) => hyper::StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            AuthError::Forbidden(detail) | AuthError::Unauthenticated(detail) => detail,
+        }
+    }
+}
+
+/**
+ * Decides whether each channel is accepted (see Server::with_authenticator()).
+ */
+#[derive(Clone)]
+pub(in crate::server) struct Authenticator {
+    authenticate: Arc<AuthenticateFn>,
+}
+impl Authenticator {
+    pub(in crate::server) fn new<F, Fut>(authenticate: F) -> Self
+    where
+        F: Fn(&ChannelInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Identity, AuthError>> + Send + 'static,
+    {
+        Authenticator {
+            authenticate: Arc::new(move |info| Box::pin(authenticate(info))),
+        }
+    }
+
+    pub(in crate::server) fn authenticate(
+        &self,
+        info: &ChannelInfo,
+    ) -> BoxFuture<'static, Result<Identity, AuthError>> {
+        (self.authenticate)(info)
+    }
+}
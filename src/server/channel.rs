@@ -1,38 +1,293 @@
-use std::collections::VecDeque;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
 
-use crate::common::tube::Tube;
+use crate::common::capture;
+use crate::common::frame;
+use crate::common::tube;
+use crate::common::ChannelContext;
+use crate::common::ChannelEvent;
+use crate::common::PingError;
+use super::auth::ChannelInfo;
+use super::auth::Identity;
 
 #[derive(Debug)]
-pub enum ChannelEvent {
-    NewTube(Tube),
+pub enum DrainError {
+    ChannelClosed,
+    FrameSendError(frame::FrameSendError),
 }
 
 #[derive(Debug)]
-pub(in crate::server) struct ChannelContext {
-    pub(in crate::server) pending_events: VecDeque<ChannelEvent>,
-    pub(in crate::server) waker: Option<std::task::Waker>,
-}
-impl ChannelContext {
-    pub fn new() -> Self {
-        ChannelContext {
-            pending_events: VecDeque::new(),
-            waker: None,
+pub enum GoAwayError {
+    ChannelClosed,
+    FrameSendError(frame::FrameSendError),
+}
+
+#[derive(Debug)]
+pub enum SendExtensionFrameError {
+    ChannelClosed,
+    FrameSendError(frame::FrameSendError),
+}
+
+/**
+ * Sends a Drain to the client of the channel ctx belongs to, after which it
+ * refuses new Tubes and queues a ChannelEvent::Drained once its Tubes have
+ * all finished (see Channel::drain()).
+ */
+pub(in crate::server) async fn drain_channel(
+    ctx: &ChannelContext,
+    reason: frame::DrainReason,
+    deadline: Option<SystemTime>,
+) -> Result<(), DrainError> {
+    let frame_sender = match ctx.frame_sender() {
+        Some(frame_sender) => frame_sender,
+        None => return Err(DrainError::ChannelClosed),
+    };
+
+    // Deadlines before the Unix epoch are clamped to it.
+    let deadline_unix_millis = deadline.map(|deadline| {
+        match deadline.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_millis() as u64,
+            Err(_) => 0,
         }
+    });
+    if ctx.record_drain_requested() {
+        let drained_ctx = ctx.clone();
+        ctx.executor().spawn(async move {
+            futures::future::poll_fn(|cx| drained_ctx.poll_tubes_settled(cx)).await;
+            drained_ctx.publish_drained();
+        });
+    }
+    match frame_sender.send(frame::Frame::Drain { reason, deadline_unix_millis }).await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(DrainError::FrameSendError(e)),
     }
 }
 
 #[derive(Debug)]
 pub struct Channel {
-    ctx: Arc<Mutex<ChannelContext>>,
+    ctx: ChannelContext,
+    extensions: hyper::http::Extensions,
+    identity: Option<Identity>,
+    info: ChannelInfo,
 }
 impl Channel {
     pub(in crate::server) fn new(
-        ctx: Arc<Mutex<ChannelContext>>,
+        ctx: ChannelContext, 
+        info: ChannelInfo,
+        identity: Option<Identity>,
     ) -> Self {
         Channel {
             ctx,
+            extensions: hyper::http::Extensions::new(),
+            identity,
+            info,
+        }
+    }
+
+    /**
+     * A typed map where middleware and routers can stash per-channel context
+     * (auth principal, tenant, trace span, etc) for downstream handlers.
+     */
+    pub fn extensions(&self) -> &hyper::http::Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut hyper::http::Extensions {
+        &mut self.extensions
+    }
+
+    /**
+     * The HTTP headers of the request the client opened this Channel with 
+     * (e.g. an Authorization header to authenticate the client by).
+     */
+    pub fn request_headers(&self) -> &hyper::HeaderMap {
+        &self.info.request_headers
+    }
+
+    pub fn request_method(&self) -> &hyper::Method {
+        &self.info.request_method
+    }
+
+    /**
+     * The URI of the request the client opened this Channel with. Its path
+     * is whatever endpoint path the client connected to (see 
+     * Client::make_tube_channel_at()), which servers can route by.
+     */
+    pub fn request_uri(&self) -> &hyper::Uri {
+        &self.info.request_uri
+    }
+
+    /**
+     * The address of the client's end of the connection this Channel was
+     * opened on (see ChannelInfo::remote_addr).
+     */
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.info.remote_addr
+    }
+
+    /**
+     * Everything above as the ChannelInfo the Server's authenticator was 
+     * given.
+     */
+    pub fn info(&self) -> &ChannelInfo {
+        &self.info
+    }
+
+    /**
+     * Who the Server's authenticator identified the client as, or None if 
+     * the Server has no authenticator (see Server::with_authenticator()).
+     */
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /**
+     * Returns a future that resolves once every Tube the client has created 
+     * on this Channel has been closed or aborted, yielding the id and 
+     * TubeOutcome of each.
+     */
+    pub fn join_all_tubes(&self) -> tube::JoinAllTubes {
+        self.ctx.tube_tracker.join_all()
+    }
+
+    /**
+     * Registers an observer that is told about every frame received from the
+     * client that is rejected as a protocol violation, along with the raw 
+     * frame (see frame::RejectedFrame).
+     */
+    pub fn add_frame_error_observer(
+        &mut self,
+        observer: impl frame::FrameErrorObserver + 'static,
+    ) {
+        self.ctx.frame_error_observers.add(observer);
+    }
+
+    /**
+     * Sets what happens to payloads that arrive for Tubes on this Channel 
+     * that have already been closed or aborted. Defaults to 
+     * LatePayloadPolicy::CountAndLog.
+     */
+    pub fn set_late_payload_policy(&mut self, policy: frame::LatePayloadPolicy) {
+        self.ctx.set_late_payload_policy(policy);
+    }
+
+    /**
+     * Sets how long each Tube the client opens on this Channel from here on 
+     * waits for the client to ack a payload, and what happens to the Tube 
+     * when an ack doesn't come in time (see tube::AckTimeoutPolicy). None 
+     * (the default) only fails the send that timed out.
+     */
+    pub fn set_ack_timeout_policy(&mut self, policy: Option<tube::AckTimeoutPolicy>) {
+        self.ctx.set_ack_timeout_policy(policy);
+    }
+
+    /**
+     * Bounds the TubeEvents queued on each Tube the client opens on this 
+     * Channel from here on (see Tube::set_event_queue_limit()). None (the 
+     * default) queues them without limit.
+     */
+    pub fn set_event_queue_limit(&mut self, limit: Option<tube::EventQueueLimit>) {
+        self.ctx.set_event_queue_limit(limit);
+    }
+
+    /**
+     * Evicts the consumer of any Tube on this Channel that stops taking 
+     * events off of it (see tube::SlowConsumerLimit), yielding a 
+     * ChannelEvent::SlowConsumer when it does. None (the default) lets 
+     * consumers fall behind indefinitely.
+     */
+    pub fn set_slow_consumer_limit(&mut self, limit: Option<tube::SlowConsumerLimit>) {
+        self.ctx.set_slow_consumer_limit(limit);
+    }
+
+    /**
+     * How often this Channel's outgoing frames were encoded into a reused
+     * buffer rather than a newly allocated one (see
+     * ServerBuilder::with_frame_buffer_pool()).
+     */
+    pub fn buffer_pool_stats(&self) -> frame::BufferPoolStats {
+        self.ctx.buffer_pool_stats()
+    }
+
+    /**
+     * The number of payloads counted (per the LatePayloadPolicy) that arrived
+     * for Tubes on this Channel that had already been closed or aborted.
+     */
+    pub fn num_late_payloads(&self) -> u64 {
+        self.ctx.num_late_payloads()
+    }
+
+    /**
+     * Records every chunk of frames sent and received on this channel from
+     * here on. Passing None stops recording.
+     */
+    pub fn set_frame_capture(&mut self, frame_capture: Option<capture::FrameCapture>) {
+        self.ctx.set_frame_capture(frame_capture);
+    }
+
+    /**
+     * Asks the client to drain this Channel. The client's Tubes each receive a
+     * TubeEvent::ServerMustDrain carrying the reason and deadline, which lets
+     * it decide whether to finish its Tubes (ideally before the deadline) or 
+     * migrate them elsewhere immediately. 
+     *
+     * From here on the Channel accepts no new Tubes: any the client opens are
+     * refused with a Draining Error frame (which fails them on the client 
+     * with a MakeTubeError::Rejected). Once every Tube on the Channel has 
+     * finished it yields a ChannelEvent::Drained, and once the client 
+     * finishes the Channel it's closed with CloseReason::Drained.
+     */
+    pub async fn drain(
+        &mut self,
+        reason: frame::DrainReason,
+        deadline: Option<SystemTime>,
+    ) -> Result<(), DrainError> {
+        drain_channel(&self.ctx, reason, deadline).await
+    }
+
+    /**
+     * Tells the client that this whole Channel is going away. Tubes the client
+     * has already made continue as usual, but any it makes from here on are
+     * never received by the application: they end with a 
+     * TubeEvent_StreamError::PeerGoingAway on the client (which may retry 
+     * them on another Channel), and the client can't make any more.
+     */
+    pub async fn go_away(&mut self, reason: String) -> Result<(), GoAwayError> {
+        let frame_sender = match self.ctx.frame_sender() {
+            Some(frame_sender) => frame_sender,
+            None => return Err(GoAwayError::ChannelClosed),
+        };
+
+        let last_tube_id = self.ctx.start_going_away();
+        match frame_sender.send(frame::Frame::GoAway { last_tube_id, reason }).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(GoAwayError::FrameSendError(e)),
+        }
+    }
+
+    /**
+     * Sends the client a Heartbeat and resolves with the round-trip time once
+     * it's acked (waiting up to timeout), e.g. to health-check this Channel 
+     * or collect latency metrics.
+     */
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration, PingError> {
+        self.ctx.ping(timeout).await
+    }
+
+    pub async fn send_extension_frame(
+        &mut self,
+        type_id: u8,
+        payload: Vec<u8>,
+    ) -> Result<(), SendExtensionFrameError> {
+        let frame_sender = match self.ctx.frame_sender() {
+            Some(frame_sender) => frame_sender,
+            None => return Err(SendExtensionFrameError::ChannelClosed),
+        };
+
+        match frame_sender.send(frame::Frame::ExtensionFrame { type_id, payload }).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(SendExtensionFrameError::FrameSendError(e)),
         }
     }
 }
@@ -43,12 +298,11 @@ impl futures::stream::Stream for Channel {
         self: core::pin::Pin<&mut Self>,
         cx: &mut futures::task::Context,
     ) -> futures::task::Poll<Option<Self::Item>> {
-        let mut ctx = self.ctx.lock().unwrap();
-        ctx.waker = Some(cx.waker().clone());
-
-        match ctx.pending_events.pop_front() {
-            Some(channel_event) => futures::task::Poll::Ready(Some(channel_event)),
-            None => futures::task::Poll::Pending,
-        }
+        self.ctx.poll_next_event(cx)
+    }
+}
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.ctx.close();
     }
 }
@@ -0,0 +1,100 @@
+/**
+ * The paths a Server answers plain HTTP GETs (and HEADs) on so that load
+ * balancers and orchestrators (e.g. Kubernetes probes) can check on it
+ * without opening a channel (see ServerBuilder::with_health_checks()).
+ *
+ * The liveness path is answered with a 200 OK for as long as the Server is
+ * serving. The readiness path is answered with a 200 OK until the Server
+ * starts draining (see Server::drain_all()), after which it's answered with
+ * a 503 Service Unavailable.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthChecks {
+    pub liveness_path: String,
+    pub readiness_path: String,
+}
+impl HealthChecks {
+    /**
+     * Returns the response to req if it's a health check.
+     */
+    pub(in crate::server) fn respond<T>(
+        &self,
+        req: &hyper::Request<T>,
+        draining: bool,
+    ) -> Option<hyper::Response<hyper::Body>> {
+        if req.method() != hyper::Method::GET && req.method() != hyper::Method::HEAD {
+            return None;
+        }
+        let (status, body) = if req.uri().path() == self.liveness_path {
+            (hyper::StatusCode::OK, "ok")
+        } else if req.uri().path() == self.readiness_path {
+            match draining {
+                false => (hyper::StatusCode::OK, "ready"),
+                true => (hyper::StatusCode::SERVICE_UNAVAILABLE, "draining"),
+            }
+        } else {
+            return None;
+        };
+        let mut res = match req.method() {
+            &hyper::Method::HEAD => hyper::Response::new(hyper::Body::empty()),
+            _ => hyper::Response::new(hyper::Body::from(body)),
+        };
+        *res.status_mut() = status;
+        res.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_static("text/plain"),
+        );
+        Some(res)
+    }
+}
+impl Default for HealthChecks {
+    /**
+     * Liveness on /healthz and readiness on /readyz.
+     */
+    fn default() -> Self {
+        HealthChecks {
+            liveness_path: "/healthz".to_string(),
+            readiness_path: "/readyz".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod health_checks_tests {
+    use super::*;
+
+    fn make_request(method: hyper::Method, path: &str) -> hyper::Request<()> {
+        hyper::Request::builder()
+            .method(method)
+            .uri(format!("http://api.example.com{}", path))
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn readiness_reports_draining_and_liveness_does_not() {
+        let health_checks = HealthChecks::default();
+
+        let res = health_checks.respond(&make_request(hyper::Method::GET, "/healthz"), true);
+        assert_eq!(res.unwrap().status(), hyper::StatusCode::OK);
+        let res = health_checks.respond(&make_request(hyper::Method::GET, "/readyz"), false);
+        assert_eq!(res.unwrap().status(), hyper::StatusCode::OK);
+        let res = health_checks.respond(&make_request(hyper::Method::HEAD, "/readyz"), true);
+        assert_eq!(res.unwrap().status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn only_gets_and_heads_on_the_health_paths_are_answered() {
+        let health_checks = HealthChecks {
+            liveness_path: "/live".to_string(),
+            readiness_path: "/ready".to_string(),
+        };
+
+        let req = make_request(hyper::Method::GET, "/healthz");
+        assert!(health_checks.respond(&req, false).is_none());
+        let req = make_request(hyper::Method::POST, "/live");
+        assert!(health_checks.respond(&req, false).is_none());
+        let req = make_request(hyper::Method::GET, "/live");
+        assert!(health_checks.respond(&req, false).is_some());
+    }
+}
@@ -1,65 +1,344 @@
 use futures::future;
-use std::collections::HashMap;
+use futures::StreamExt;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::sync::Weak;
 
-use hyper::body::HttpBody;
+use tokio::io::AsyncWriteExt;
 
 use crate::common::frame;
+use crate::common::frame::Transport;
+use crate::common::ChannelContext;
+use crate::common::CloseReason;
 use crate::common::PeerType;
+use crate::common::raw_transport;
+#[cfg(feature = "websocket")]
+use crate::common::websocket;
+use super::auth::AuthError;
+use super::auth::ChannelInfo;
+use super::auth::Identity;
 use super::channel::Channel;
-use super::channel::ChannelContext;
-use super::channel::ChannelEvent;
+use super::incoming::PeerAddr;
+use super::lifecycle_hooks::ChannelLifecycle;
 use super::server_context::ServerContext;
 use super::server_event::ServerEvent;
 
+/**
+ * Request headers whose values are replaced when headers are logged.
+ */
+const REDACTED_HEADERS: [hyper::header::HeaderName; 3] = [
+    hyper::header::AUTHORIZATION,
+    hyper::header::COOKIE,
+    hyper::header::PROXY_AUTHORIZATION,
+];
+
+fn redact_headers(headers: &hyper::HeaderMap) -> hyper::HeaderMap {
+    let mut headers = headers.clone();
+    for name in REDACTED_HEADERS.iter() {
+        if let hyper::header::Entry::Occupied(mut entry) = headers.entry(name) {
+            entry.insert(hyper::header::HeaderValue::from_static("<redacted>"));
+        }
+    }
+    headers
+}
+
+fn make_rejection_response(e: &AuthError) -> hyper::Response<hyper::Body> {
+    let mut res = hyper::Response::new(hyper::Body::from(e.detail().to_string()));
+    *res.status_mut() = e.status_code();
+    res
+}
+
+/**
+ * Counts an accepted channel against ServerLimits::max_concurrent_channels 
+ * until it's dropped.
+ */
+struct OpenChannelGuard {
+    id: u64,
+    server_ctx: Arc<Mutex<ServerContext>>,
+}
+impl OpenChannelGuard {
+    /**
+     * Returns None if the Server already has as many channels open as it
+     * allows.
+     */
+    fn try_new(server_ctx: &Arc<Mutex<ServerContext>>) -> Option<Self> {
+        let mut ctx = server_ctx.lock().unwrap();
+        if let Some(max_channels) = ctx.limits.max_concurrent_channels {
+            if ctx.num_open_channels >= max_channels {
+                return None;
+            }
+        }
+        ctx.num_open_channels += 1;
+        let id = ctx.next_open_channel_id;
+        ctx.next_open_channel_id += 1;
+        Some(OpenChannelGuard {
+            id,
+            server_ctx: server_ctx.clone(),
+        })
+    }
+
+    /**
+     * Makes the channel reachable through the Server (see 
+     * ServerContext::open_channels) until it's dropped.
+     */
+    fn track(&self, channel_ctx: &ChannelContext) {
+        self.server_ctx.lock().unwrap().open_channels.insert(self.id, channel_ctx.clone());
+    }
+}
+impl Drop for OpenChannelGuard {
+    fn drop(&mut self) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.num_open_channels -= 1;
+        server_ctx.open_channels.remove(&self.id);
+    }
+}
+
+/**
+ * A validated request to open a channel over a WebSocket (see 
+ * ServerBuilder::with_websocket_transport()).
+ */
+#[cfg(feature = "websocket")]
+struct WebSocketUpgrade {
+    accept_key: String,
+    on_upgrade: hyper::upgrade::OnUpgrade,
+}
+#[cfg(feature = "websocket")]
+impl WebSocketUpgrade {
+    /**
+     * Returns None if req isn't a WebSocket handshake, or the response to 
+     * refuse it with if it's one that isn't spoken.
+     */
+    fn take(
+        req: &mut hyper::Request<hyper::Body>,
+    ) -> Result<Option<Self>, Box<hyper::Response<hyper::Body>>> {
+        let is_websocket = req.headers()
+            .get(hyper::header::UPGRADE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+        if !is_websocket {
+            return Ok(None);
+        }
+        let version_is_spoken = req.headers()
+            .get(hyper::header::SEC_WEBSOCKET_VERSION)
+            .is_some_and(|value| value == websocket::VERSION);
+        let key = req.headers().get(hyper::header::SEC_WEBSOCKET_KEY).cloned();
+
+        let (status, detail) = match (req.method(), version_is_spoken, key) {
+            (&hyper::Method::GET, true, Some(key)) => return Ok(Some(WebSocketUpgrade {
+                accept_key: websocket::accept_key(key.as_bytes()),
+                on_upgrade: hyper::upgrade::on(req),
+            })),
+            (_, false, _) => (hyper::StatusCode::UPGRADE_REQUIRED, "Unsupported WebSocket version"),
+            _ => (hyper::StatusCode::BAD_REQUEST, "Invalid WebSocket handshake"),
+        };
+        log::warn!("Refusing WebSocket handshake: {}", detail);
+        let mut res = hyper::Response::new(hyper::Body::from(detail));
+        *res.status_mut() = status;
+        res.headers_mut().insert(
+            hyper::header::SEC_WEBSOCKET_VERSION,
+            hyper::header::HeaderValue::from_static(websocket::VERSION),
+        );
+        Err(Box::new(res))
+    }
+}
+
+/**
+ * Each request on a connection is a separate channel.
+ */
+#[derive(Clone)]
 pub(in crate::server) struct TubezHttpReq {
-    channel_ctx: Weak<Mutex<ChannelContext>>,
+    remote_addr: Option<SocketAddr>,
     server_ctx: Arc<Mutex<ServerContext>>,
 }
 impl TubezHttpReq {
-    fn new(
-        server_ctx: Arc<Mutex<ServerContext>>,
-        channel_ctx: Weak<Mutex<ChannelContext>>,
-    ) -> Self {
+    fn new(server_ctx: Arc<Mutex<ServerContext>>, remote_addr: Option<SocketAddr>) -> Self {
         TubezHttpReq {
-            channel_ctx,
+            remote_addr,
             server_ctx,
         }
     }
-}
-impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
-    type Response = hyper::Response<hyper::Body>;
-    type Error = hyper::Error;
-    type Future = future::Ready<Result<Self::Response, Self::Error>>;
 
-    fn poll_ready(
-        &mut self, 
-        _cx: &mut futures::task::Context<'_>,
-    ) -> futures::task::Poll<Result<(), Self::Error>> {
-        Ok(()).into()
+    fn make_channel_ctx(&self) -> ChannelContext {
+        let (
+            ack_timeout_policy,
+            channel_executor, 
+            event_queue_limit, 
+            extension_frame_handlers, 
+            late_payload_policy, 
+            max_pending_tubes,
+            max_tubes,
+            rate_limits,
+            slow_consumer_limit,
+        ) = {
+            let server_ctx = self.server_ctx.lock().unwrap();
+            (
+                server_ctx.ack_timeout_policy,
+                server_ctx.channel_executor.clone(),
+                server_ctx.event_queue_limit,
+                server_ctx.extension_frame_handlers.clone(),
+                server_ctx.late_payload_policy,
+                server_ctx.max_pending_tubes_per_channel,
+                server_ctx.limits.max_tubes_per_channel,
+                server_ctx.limits.rate_limits,
+                server_ctx.slow_consumer_limit,
+            )
+        };
+        #[cfg(feature = "bench")]
+        let serves_bench_tubes = self.server_ctx.lock().unwrap().serves_bench_tubes;
+        let mut channel_ctx = ChannelContext::new(
+            PeerType::Server,
+            extension_frame_handlers,
+        ).accepting_peer_tubes().with_executor(channel_executor);
+        channel_ctx.set_ack_timeout_policy(ack_timeout_policy);
+        channel_ctx.set_event_queue_limit(event_queue_limit);
+        channel_ctx.set_late_payload_policy(late_payload_policy);
+        channel_ctx.set_slow_consumer_limit(slow_consumer_limit);
+        if let Some(max_pending_tubes) = max_pending_tubes {
+            channel_ctx = channel_ctx.with_max_pending_peer_tubes(max_pending_tubes);
+        }
+        if let Some(max_tubes) = max_tubes {
+            channel_ctx = channel_ctx.with_max_peer_tubes(max_tubes);
+        }
+        if let Some(rate_limits) = rate_limits {
+            channel_ctx = channel_ctx.with_rate_limits(rate_limits);
+        }
+        #[cfg(feature = "bench")]
+        if serves_bench_tubes {
+            channel_ctx = channel_ctx.serving_bench_tubes();
+        }
+        channel_ctx
     }
 
-    fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+    fn publish_channel(&self, channel: Channel) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.pending_events.push_back(
+            Ok(ServerEvent::NewChannel(channel))
+        );
+        if let Some(waker) = server_ctx.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /**
+     * Starts serving the channel and publishes it to the application, 
+     * returning the response whose body carries the channel's frames to the 
+     * client.
+     */
+    fn accept_channel(
+        &self,
+        info: ChannelInfo,
+        identity: Option<Identity>,
+        req_body: hyper::Body,
+        open_channel_guard: OpenChannelGuard,
+    ) -> hyper::Response<hyper::Body> {
+        let framing_version = frame::FramingVersion::negotiate(
+            info.request_headers
+                .get(frame::FRAMING_VERSION_HEADER)
+                .and_then(|value| value.to_str().ok())
+        );
         let (body_sender, body) = hyper::Body::channel();
-        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
-        let res = hyper::Response::new(body);
-
-        // TODO: Sanitize these headers (e.g. blank out auth, app-headers, etc)
-        log::trace!("Http request received. Headers: {:?}", req.headers());
-
-        let channel_ctx = self.channel_ctx.clone();
-        let mut body = req.into_body();
-        tokio::spawn(async move {
-            let mut frame_decoder = frame::Decoder::new();
-            let mut tube_store = Arc::new(Mutex::new(HashMap::new()));
-            let mut frame_handler = frame::FrameHandler::new(
-                PeerType::Server,
-                &mut tube_store,
+        let (frame_sink, mut frame_stream) = (body_sender, req_body).into_parts();
+        let (
+            channel_executor,
+            decoder_limits,
+            frame_buffer_pool,
+            idle_channel_timeout,
+            lifecycle_hooks,
+            max_payload_frame_size,
+            outgoing_frame_interceptors,
+            write_coalescing,
+        ) = {
+            let server_ctx = self.server_ctx.lock().unwrap();
+            (
+                server_ctx.channel_executor.clone(),
+                server_ctx.limits.decoder_limits.clone(),
+                server_ctx.limits.frame_buffer_pool,
+                server_ctx.limits.idle_channel_timeout,
+                server_ctx.lifecycle_hooks.clone(),
+                server_ctx.limits.max_payload_frame_size,
+                server_ctx.outgoing_frame_interceptors.clone(),
+                server_ctx.limits.write_coalescing,
+            )
+        };
+        let max_payload_frame_size = frame::negotiate_max_payload_frame_size(
+            max_payload_frame_size
+                .unwrap_or(frame::MAX_PAYLOAD_FRAME_SIZE)
+                .min(decoder_limits.max_payload_size),
+            info.request_headers
+                .get(frame::MAX_PAYLOAD_FRAME_SIZE_HEADER)
+                .and_then(|value| value.to_str().ok()),
+        );
+        let frame_sender = frame::FrameSender::new_with_buffer_pool(
+            frame_sink,
+            framing_version,
+            outgoing_frame_interceptors,
+            frame_buffer_pool,
+        )
+            .with_executor(channel_executor)
+            .with_max_payload_frame_size(max_payload_frame_size)
+            .with_write_coalescing(write_coalescing);
+        let mut res = hyper::Response::new(body);
+        res.headers_mut().insert(
+            frame::FRAMING_VERSION_HEADER,
+            hyper::header::HeaderValue::from_static(framing_version.header_value()),
+        );
+        res.headers_mut().insert(
+            frame::MAX_PAYLOAD_FRAME_SIZE_HEADER,
+            hyper::header::HeaderValue::from(max_payload_frame_size),
+        );
+
+        let mut channel_ctx = self.make_channel_ctx();
+        if identity.is_some() {
+            channel_ctx = channel_ctx.authenticated();
+        }
+        let lifecycle = if lifecycle_hooks.is_empty() {
+            None
+        } else {
+            let lifecycle = ChannelLifecycle::open(
+                lifecycle_hooks,
+                open_channel_guard.id,
+                &info,
+                &identity,
             );
+            channel_ctx = channel_ctx.with_tube_lifecycle_observer(lifecycle.clone());
+            Some(lifecycle)
+        };
+        channel_ctx.set_frame_sender(frame_sender.downgrade());
+        open_channel_guard.track(&channel_ctx);
+        self.publish_channel(Channel::new(channel_ctx.clone(), info, identity));
+
+        let executor = channel_ctx.executor().clone();
+        executor.spawn(async move {
+            let _open_channel_guard = open_channel_guard;
+            let mut frame_decoder = frame::Decoder::new_with_version(framing_version)
+                .with_limits(decoder_limits);
+            let mut frame_handler = frame::FrameHandler::new(channel_ctx.clone());
 
-            while let Some(data_result) = body.data().await {
+            let mut bytes_received = 0;
+            let mut idle_timed_out = false;
+            let mut transport_error = None;
+            loop {
+                let next_data = match idle_channel_timeout {
+                    Some(timeout) => match tokio::time::timeout(
+                        timeout,
+                        frame_stream.next(),
+                    ).await {
+                        Ok(next_data) => next_data,
+                        Err(_) => {
+                            log::warn!(
+                                "Nothing was received from client for {:?}. Closing channel.",
+                                timeout,
+                            );
+                            idle_timed_out = true;
+                            break;
+                        },
+                    },
+                    None => frame_stream.next().await,
+                };
+                let data_result = match next_data {
+                    Some(data_result) => data_result,
+                    None => break,
+                };
                 let raw_data = match data_result {
                     Ok(data) => data,
                     Err(e) => {
@@ -67,11 +346,14 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
                             "Stream of data from client has errored: `{:?}`", 
                             e,
                         );
+                        transport_error = Some(format!("{:?}", e.into_inner()));
                         break;
                     },
                 };
+                bytes_received += raw_data.len() as u64;
+                channel_ctx.record_incoming(&raw_data, framing_version);
 
-                let mut new_frames = match frame_decoder.decode(raw_data.to_vec()) {
+                let mut new_frames = match frame_decoder.decode_bytes(raw_data) {
                     Ok(frames) => frames,
                     Err(e) => {
                         // TODO: What happens if we get weird data from the client? Should we 
@@ -80,51 +362,293 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
                         //       For now just log and ignore to avoid some kind of hand-wavy 
                         //       DDOS situation
                         log::error!("Frame decode error: {:?}", e);
-                        return;
+                        transport_error = Some(format!("Frame decode error: {:?}", e));
+                        break;
                     },
                 };
 
                 while let Some(frame) = new_frames.pop_front() {
                     log::trace!("New frame received: {:?}", frame);
-                    match frame_handler.handle_frame(frame, &mut body_sender).await {
-                        Ok(frame::FrameHandlerResult::NewTube(mut tube)) => {
-                            if let Some(channel_ctx) = Weak::upgrade(&channel_ctx) {
-                                let mut channel_ctx = channel_ctx.lock().unwrap();
-                                channel_ctx.pending_events.push_back(
-                                    ChannelEvent::NewTube(tube)
-                                );
-                                if let Some(waker) = channel_ctx.waker.take() {
-                                    waker.wake();
-                                }
-                            } else {
-                                log::error!(
-                                    "Received a new Tube(id={}) from the \
-                                     client on a channel that has been \
-                                     dropped!",
-                                     tube.get_id(),
-                                );
-                                match tube.abort_internal(
-                                    frame::AbortReason::ApplicationError
-                                ).await {
-                                    Ok(()) => (),
-                                    Err(e) => log::error!(
-                                        "Error aborting tube: `{:?}`", 
-                                        e,
-                                    ),
-                                }
-                            }
-                        },
-                        Ok(frame::FrameHandlerResult::FullyHandled) => (),
-                        Err(e) => log::error!("Error handling frame: {:?}", e),
+                    if let Err(e) = frame_handler.handle_frame(frame, &frame_sender).await {
+                        log::error!("Error handling frame: {:?}", e);
                     }
                 }
+                if let Err(e) = frame_handler.flush_deferred_acks(&frame_sender).await {
+                    log::error!("Error sending deferred acks: {:?}", e);
+                }
             }
             log::trace!("Stream of httprequest data from client has ended.");
+            if idle_timed_out {
+                frame_handler.time_out_all_tubes();
+            } else {
+                frame_handler.fail_all_tubes(
+                    "Stream of data from client has ended".to_string()
+                );
+            }
+            let close_reason = match transport_error {
+                Some(detail) => CloseReason::TransportError(detail),
+                None if idle_timed_out => CloseReason::IdleTimeout,
+                None if channel_ctx.asked_peer_to_leave() => CloseReason::Drained,
+                None => CloseReason::PeerWentAway(channel_ctx.peer_going_away()),
+            };
+            if let Some(lifecycle) = lifecycle {
+                lifecycle.close(close_reason.clone(), bytes_received, frame_sender.bytes_sent());
+            }
+            channel_ctx.publish_closed(close_reason);
+            if idle_timed_out {
+                frame_sender.abort().await;
+            }
         });
 
-        future::ok(res)
+        res
+    }
+
+    /**
+     * Like accept_channel(), but the channel's frames are carried over the 
+     * WebSocket the request is upgraded to. Returns the response that 
+     * completes the WebSocket handshake.
+     */
+    #[cfg(feature = "websocket")]
+    fn accept_websocket_channel(
+        &self,
+        info: ChannelInfo,
+        identity: Option<Identity>,
+        websocket_upgrade: WebSocketUpgrade,
+        open_channel_guard: OpenChannelGuard,
+    ) -> hyper::Response<hyper::Body> {
+        let (incoming_sender, req_body) = hyper::Body::channel();
+        let (mut res_parts, res_body) = 
+            self.accept_channel(info, identity, req_body, open_channel_guard).into_parts();
+        let accept_key = match hyper::header::HeaderValue::from_str(&websocket_upgrade.accept_key) {
+            Ok(accept_key) => accept_key,
+            Err(e) => unreachable!("Base64 is always a valid header value: {:?}", e),
+        };
+        res_parts.status = hyper::StatusCode::SWITCHING_PROTOCOLS;
+        res_parts.headers.insert(
+            hyper::header::CONNECTION,
+            hyper::header::HeaderValue::from_static("upgrade"),
+        );
+        res_parts.headers.insert(
+            hyper::header::UPGRADE,
+            hyper::header::HeaderValue::from_static("websocket"),
+        );
+        res_parts.headers.insert(hyper::header::SEC_WEBSOCKET_ACCEPT, accept_key);
+
+        let executor = self.server_ctx.lock().unwrap().channel_executor.clone();
+        executor.spawn(async move {
+            match websocket_upgrade.on_upgrade.await {
+                Ok(upgraded) => websocket::bridge(
+                    upgraded,
+                    incoming_sender,
+                    res_body,
+                    PeerType::Server,
+                ).await,
+                Err(e) => {
+                    log::error!("Upgrading to a WebSocket failed: {:?}", e);
+                    incoming_sender.abort();
+                },
+            }
+        });
+        hyper::Response::from_parts(res_parts, hyper::Body::empty())
     }
 }
+impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
+    type Response = hyper::Response<hyper::Body>;
+    type Error = std::convert::Infallible;
+    type Future = future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self, 
+        _cx: &mut futures::task::Context<'_>,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        let http_req = self.clone();
+        Box::pin(async move {
+            log::trace!("Http request received. Headers: {:?}", redact_headers(req.headers()));
+            let health_check_res = {
+                let server_ctx = http_req.server_ctx.lock().unwrap();
+                server_ctx.limits.health_checks.as_ref()
+                    .and_then(|health_checks| health_checks.respond(&req, server_ctx.draining))
+            };
+            if let Some(res) = health_check_res {
+                return Ok(res);
+            }
+            #[cfg(feature = "websocket")]
+            let (req, websocket_upgrade) = {
+                let mut req = req;
+                let websocket_transport = 
+                    http_req.server_ctx.lock().unwrap().limits.websocket_transport;
+                let websocket_upgrade = match websocket_transport {
+                    true => match WebSocketUpgrade::take(&mut req) {
+                        Ok(websocket_upgrade) => websocket_upgrade,
+                        Err(res) => return Ok(*res),
+                    },
+                    false => None,
+                };
+                (req, websocket_upgrade)
+            };
+            let (req_parts, req_body) = req.into_parts();
+            let info = ChannelInfo::new(req_parts, http_req.remote_addr);
+
+            let open_channel_guard = match OpenChannelGuard::try_new(&http_req.server_ctx) {
+                Some(open_channel_guard) => open_channel_guard,
+                None => {
+                    log::warn!(
+                        "Refusing channel from {:?}: Too many open channels", 
+                        info.remote_addr,
+                    );
+                    let mut res = hyper::Response::new(hyper::Body::from("Too many open channels"));
+                    *res.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+                    return Ok(res);
+                },
+            };
+
+            let authenticator = http_req.server_ctx.lock().unwrap().authenticator.clone();
+            let identity = match authenticator {
+                Some(authenticator) => match authenticator.authenticate(&info).await {
+                    Ok(identity) => Some(identity),
+                    Err(e) => {
+                        log::warn!("Rejected channel from {:?}: {:?}", info.remote_addr, e);
+                        return Ok(make_rejection_response(&e));
+                    },
+                },
+                None => None,
+            };
+
+            #[cfg(feature = "websocket")]
+            if let Some(websocket_upgrade) = websocket_upgrade {
+                return Ok(http_req.accept_websocket_channel(
+                    info,
+                    identity,
+                    websocket_upgrade,
+                    open_channel_guard,
+                ));
+            }
+            Ok(http_req.accept_channel(info, identity, req_body, open_channel_guard))
+        })
+    }
+}
+
+/**
+ * Serves tubez channels as a hyper (and tower) service, so that tubez can be
+ * mounted on a route of an existing HTTP/2 server (see server::service()).
+ * Each request it's called with is a channel.
+ */
+#[derive(Clone)]
+pub struct TubezService {
+    http_req: TubezHttpReq,
+}
+impl TubezService {
+    pub(in crate::server) fn new(server_ctx: Arc<Mutex<ServerContext>>) -> Self {
+        TubezService {
+            http_req: TubezHttpReq::new(server_ctx, None),
+        }
+    }
+
+    /**
+     * Accepts a channel whose frames are carried over req_body and the 
+     * returned body rather than an HTTP connection (see testing::loopback()).
+     * Returns None if the Server already has as many channels open as it 
+     * allows.
+     */
+    #[cfg(feature = "testing")]
+    pub(in crate::server) fn accept_loopback_channel(
+        &self,
+        req_body: hyper::Body,
+    ) -> Option<hyper::Body> {
+        let open_channel_guard = OpenChannelGuard::try_new(&self.http_req.server_ctx)?;
+        let (mut req_parts, ()) = hyper::Request::new(()).into_parts();
+        req_parts.headers.insert(
+            frame::FRAMING_VERSION_HEADER, 
+            hyper::header::HeaderValue::from_static(frame::FramingVersion::LATEST.header_value()),
+        );
+        let info = ChannelInfo::new(req_parts, None);
+        let res = self.http_req.accept_channel(info, None, req_body, open_channel_guard);
+        Some(res.into_body())
+    }
+}
+impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezService {
+    type Response = hyper::Response<hyper::Body>;
+    type Error = std::convert::Infallible;
+    type Future = future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self, 
+        cx: &mut futures::task::Context<'_>,
+    ) -> futures::task::Poll<Result<(), Self::Error>> {
+        hyper::service::Service::poll_ready(&mut self.http_req, cx)
+    }
+
+    fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        hyper::service::Service::call(&mut self.http_req, req)
+    }
+}
+
+/**
+ * Serves the channel carried straight over io (see 
+ * ServerBuilder::with_raw_transport()). The client's head is turned into the
+ * request the channel would've been opened with over HTTP/2, so that it's 
+ * accepted (or refused) just the same, and the response's status and headers
+ * are sent back as the server's head. Refused channels are answered with the
+ * response's body, after which io is shut down.
+ */
+pub(in crate::server) async fn serve_raw_connection<IO>(
+    server_ctx: Arc<Mutex<ServerContext>>,
+    remote_addr: Option<SocketAddr>,
+    io: IO,
+) where
+    IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+{
+    let mut io = tokio::io::BufReader::new(io);
+    let head = match raw_transport::RawHead::read(&mut io).await {
+        Ok(head) => head,
+        Err(e) => {
+            log::warn!("Refusing raw connection from {:?}: {}", remote_addr, e);
+            return;
+        },
+    };
+    let (incoming_sender, req_body) = hyper::Body::channel();
+    let mut req = hyper::Request::new(req_body);
+    *req.method_mut() = hyper::Method::POST;
+    *req.headers_mut() = head.headers;
+    let res = match head.subject.parse() {
+        Ok(uri) => {
+            *req.uri_mut() = uri;
+            let mut http_req = TubezHttpReq::new(server_ctx, remote_addr);
+            match hyper::service::Service::call(&mut http_req, req).await {
+                Ok(res) => res,
+                Err(e) => match e {},
+            }
+        },
+        Err(e) => {
+            log::warn!("Refusing raw connection from {:?}: {:?}", remote_addr, e);
+            let mut res = hyper::Response::new(hyper::Body::from("Invalid channel path"));
+            *res.status_mut() = hyper::StatusCode::BAD_REQUEST;
+            res
+        },
+    };
+
+    let (res_parts, res_body) = res.into_parts();
+    let res_head = raw_transport::RawHead {
+        subject: res_parts.status.as_str().to_string(),
+        headers: res_parts.headers,
+    };
+    if let Err(e) = io.write_all(&res_head.encode()).await {
+        log::trace!("Writing to raw connection from {:?} failed: {:?}", remote_addr, e);
+        return;
+    }
+    if res_parts.status != hyper::StatusCode::OK {
+        if let Ok(detail) = hyper::body::to_bytes(res_body).await {
+            let _ = io.write_all(&detail).await;
+        }
+        let _ = io.shutdown().await;
+        return;
+    }
+    raw_transport::bridge(io, incoming_sender, res_body).await;
+}
 
 pub(in crate::server) struct TubezMakeSvc {
     server_ctx: Arc<Mutex<ServerContext>>,
@@ -136,17 +660,8 @@ impl TubezMakeSvc {
         }
     }
 
-    fn publish_channel(&mut self, channel: Channel) {
-        let mut server_ctx = self.server_ctx.lock().unwrap();
-        server_ctx.pending_events.push_back(
-            Ok(ServerEvent::NewChannel(channel))
-        );
-        if let Some(waker) = server_ctx.waker.take() {
-            waker.wake();
-        }
-    }
 }
-impl<T> hyper::service::Service<T> for TubezMakeSvc {
+impl<C: PeerAddr> hyper::service::Service<&C> for TubezMakeSvc {
     type Response = TubezHttpReq;
     type Error = std::io::Error;
     type Future = future::Ready<Result<Self::Response, Self::Error>>;
@@ -158,14 +673,205 @@ impl<T> hyper::service::Service<T> for TubezMakeSvc {
         Ok(()).into()
     }
 
-    fn call(&mut self, _: T) -> Self::Future {
-        let channel_ctx = Arc::new(Mutex::new(ChannelContext::new()));
-        let weak_channel = Arc::downgrade(&channel_ctx);
-        let channel = Channel::new(channel_ctx);
-        self.publish_channel(channel);
-        future::ok(TubezHttpReq::new(
-            self.server_ctx.clone(),
-            weak_channel,
-        ))
+    fn call(&mut self, conn: &C) -> Self::Future {
+        future::ok(TubezHttpReq::new(self.server_ctx.clone(), conn.peer_addr()))
+    }
+}
+
+#[cfg(test)]
+mod hyper_tubez_service_tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use super::super::auth::Authenticator;
+    use super::super::lifecycle_hooks::LifecycleHooks;
+    use super::super::server_builder::ServerLimits;
+
+    #[test]
+    fn credentials_are_redacted_from_logged_headers() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert(hyper::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted.get(hyper::header::AUTHORIZATION).unwrap(), "<redacted>");
+        assert_eq!(redacted.get("x-tenant-id").unwrap(), "acme");
+        assert!(redacted.get(hyper::header::COOKIE).is_none());
+    }
+
+    fn make_server_ctx(authenticator: Option<Authenticator>) -> Arc<Mutex<ServerContext>> {
+        Arc::new(Mutex::new(ServerContext {
+            ack_timeout_policy: None,
+            authenticator,
+            channel_executor: crate::common::ChannelExecutor::default(),
+            draining: false,
+            event_queue_limit: None,
+            extension_frame_handlers: frame::ExtensionFrameHandlers::new(),
+            is_complete: false,
+            late_payload_policy: frame::LatePayloadPolicy::default(),
+            lifecycle_hooks: LifecycleHooks::default(),
+            limits: ServerLimits::default(),
+            max_pending_tubes_per_channel: None,
+            next_open_channel_id: 0,
+            num_open_channels: 0,
+            open_channels: HashMap::new(),
+            outgoing_frame_interceptors: frame::FrameInterceptors::new(),
+            pending_events: std::collections::VecDeque::new(),
+            #[cfg(feature = "bench")]
+            serves_bench_tubes: false,
+            slow_consumer_limit: None,
+            waker: None,
+        }))
+    }
+
+    fn make_request(tenant_id: &str) -> hyper::Request<hyper::Body> {
+        hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("http://tubez.example.com/tenants/acme")
+            .header("x-tenant-id", tenant_id)
+            .body(hyper::Body::empty())
+            .unwrap()
+    }
+
+    fn take_new_channel(server_ctx: &Arc<Mutex<ServerContext>>) -> Channel {
+        let event = server_ctx.lock().unwrap().pending_events.pop_front();
+        match event {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn channels_carry_the_request_metadata() {
+        let server_ctx = make_server_ctx(None);
+        let remote_addr = Some("10.1.2.3:45678".parse().unwrap());
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), remote_addr);
+
+        hyper::service::Service::call(&mut http_req, make_request("acme")).await.unwrap();
+
+        let channel = take_new_channel(&server_ctx);
+        assert_eq!(channel.remote_addr(), remote_addr);
+        assert_eq!(channel.request_method(), hyper::Method::POST);
+        assert_eq!(channel.request_uri().path(), "/tenants/acme");
+        assert_eq!(channel.request_headers().get("x-tenant-id").unwrap(), "acme");
+        assert_eq!(channel.identity(), None);
+    }
+
+    #[tokio::test]
+    async fn only_authenticated_channels_are_published() {
+        let authenticator = Authenticator::new(|info: &ChannelInfo| {
+            let tenant_id = info.request_headers.get("x-tenant-id").cloned();
+            async move {
+                match tenant_id {
+                    Some(tenant_id) if tenant_id == "acme" => Ok(Identity::new("acme")),
+                    _ => Err(AuthError::Forbidden("Unknown tenant".to_string())),
+                }
+            }
+        });
+        let server_ctx = make_server_ctx(Some(authenticator));
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), None);
+
+        let res = hyper::service::Service::call(&mut http_req, make_request("initech"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::FORBIDDEN);
+        assert_eq!(hyper::body::to_bytes(res.into_body()).await.unwrap(), "Unknown tenant");
+        assert!(server_ctx.lock().unwrap().pending_events.is_empty());
+
+        let res = hyper::service::Service::call(&mut http_req, make_request("acme"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let channel = take_new_channel(&server_ctx);
+        assert_eq!(channel.identity(), Some(&Identity::new("acme")));
+    }
+
+    #[tokio::test]
+    async fn channels_beyond_the_limit_are_refused_until_one_ends() {
+        let server_ctx = make_server_ctx(None);
+        server_ctx.lock().unwrap().limits.max_concurrent_channels = Some(1);
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), None);
+
+        let (req_body_sender, req_body) = hyper::Body::channel();
+        let req = make_request("acme").map(|_| req_body);
+        let res = hyper::service::Service::call(&mut http_req, req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+
+        let res = hyper::service::Service::call(&mut http_req, make_request("acme"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(req_body_sender);
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while server_ctx.lock().unwrap().num_open_channels > 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.unwrap();
+        let res = hyper::service::Service::call(&mut http_req, make_request("acme"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    async fn next_channel_event(channel: &mut Channel) -> Option<crate::common::ChannelEvent> {
+        use futures::StreamExt;
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), channel.next()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn channels_the_client_finishes_are_closed_as_peer_went_away() {
+        let server_ctx = make_server_ctx(None);
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), None);
+
+        let _res = hyper::service::Service::call(&mut http_req, make_request("acme"))
+            .await
+            .unwrap();
+        let mut channel = take_new_channel(&server_ctx);
+        match next_channel_event(&mut channel).await {
+            Some(crate::common::ChannelEvent::Closed(CloseReason::PeerWentAway(None))) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        assert!(next_channel_event(&mut channel).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn channels_whose_transport_fails_are_closed_with_the_error() {
+        let server_ctx = make_server_ctx(None);
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), None);
+
+        let (req_body_sender, req_body) = hyper::Body::channel();
+        let req = make_request("acme").map(|_| req_body);
+        let _res = hyper::service::Service::call(&mut http_req, req).await.unwrap();
+        let mut channel = take_new_channel(&server_ctx);
+        req_body_sender.abort();
+
+        match next_channel_event(&mut channel).await {
+            Some(crate::common::ChannelEvent::Closed(CloseReason::TransportError(_))) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn drained_channels_are_closed_as_drained() {
+        let server_ctx = make_server_ctx(None);
+        let mut http_req = TubezHttpReq::new(server_ctx.clone(), None);
+
+        let (req_body_sender, req_body) = hyper::Body::channel();
+        let req = make_request("acme").map(|_| req_body);
+        let _res = hyper::service::Service::call(&mut http_req, req).await.unwrap();
+        let mut channel = take_new_channel(&server_ctx);
+        channel.drain(frame::DrainReason::Shutdown, None).await.unwrap();
+        match next_channel_event(&mut channel).await {
+            Some(crate::common::ChannelEvent::Drained) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        drop(req_body_sender);
+
+        match next_channel_event(&mut channel).await {
+            Some(crate::common::ChannelEvent::Closed(CloseReason::Drained)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
     }
 }
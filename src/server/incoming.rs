@@ -0,0 +1,62 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task;
+
+use hyper::server::conn::AddrStream;
+
+/**
+ * A connection accepted by a Server, which may know the address of the
+ * client's end of it (see Channel::remote_addr()).
+ */
+pub(in crate::server) trait PeerAddr {
+    fn peer_addr(&self) -> Option<SocketAddr>;
+}
+impl PeerAddr for AddrStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        Some(self.remote_addr())
+    }
+}
+
+/**
+ * A connection yielded by the accept stream given to
+ * Server::from_incoming(). Nothing is known about where these come from, so
+ * they have no peer address.
+ */
+pub(in crate::server) struct IncomingIo<IO>(pub(in crate::server) IO);
+impl<IO> PeerAddr for IncomingIo<IO> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+impl<IO: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for IncomingIo<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+impl<IO: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for IncomingIo<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &[u8],
+    ) -> task::Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+    ) -> task::Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::common::tube;
+use crate::common::CloseReason;
+use super::auth::ChannelInfo;
+use super::auth::Identity;
+
+type Hook<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+/**
+ * What's known about a channel once it's accepted (see
+ * Server::on_channel_open()).
+ */
+#[derive(Clone, Debug)]
+pub struct ChannelOpenInfo {
+    /**
+     * Identifies the channel among all of those the Server has accepted, so
+     * that the info given to each of the Server's hooks can be correlated.
+     */
+    pub channel_id: u64,
+    pub identity: Option<Identity>,
+    pub info: ChannelInfo,
+}
+
+/**
+ * What happened over the life of a channel (see Server::on_channel_close()).
+ */
+#[derive(Clone, Debug)]
+pub struct ChannelCloseInfo {
+    /**
+     * The encoded frames received from the client.
+     */
+    pub bytes_received: u64,
+    /**
+     * The encoded frames sent to the client.
+     */
+    pub bytes_sent: u64,
+    pub channel_id: u64,
+    /**
+     * How long the channel was open.
+     */
+    pub duration: Duration,
+    pub identity: Option<Identity>,
+    pub info: ChannelInfo,
+    /**
+     * How many Tubes the client opened on the channel.
+     */
+    pub num_tubes: u64,
+    pub reason: CloseReason,
+}
+
+/**
+ * What's known about a Tube once the client opens it (see
+ * Server::on_tube_open()).
+ */
+#[derive(Clone, Debug)]
+pub struct TubeOpenInfo {
+    pub channel_id: u64,
+    pub headers: HashMap<String, Vec<u8>>,
+    pub identity: Option<Identity>,
+    pub remote_addr: Option<SocketAddr>,
+    pub tube_id: u32,
+}
+
+/**
+ * What happened over the life of a Tube (see Server::on_tube_close()).
+ */
+#[derive(Clone, Debug)]
+pub struct TubeCloseInfo {
+    pub channel_id: u64,
+    /**
+     * How long the Tube was open.
+     */
+    pub duration: Duration,
+    pub identity: Option<Identity>,
+    pub outcome: tube::TubeOutcome,
+    pub payload_bytes_received: u64,
+    pub payload_bytes_sent: u64,
+    pub remote_addr: Option<SocketAddr>,
+    pub tube_id: u32,
+}
+
+/**
+ * The hooks registered on a Server. Channels take a snapshot of them when
+ * they're accepted.
+ */
+#[derive(Clone, Default)]
+pub(in crate::server) struct LifecycleHooks {
+    channel_close: Vec<Hook<ChannelCloseInfo>>,
+    channel_open: Vec<Hook<ChannelOpenInfo>>,
+    tube_close: Vec<Hook<TubeCloseInfo>>,
+    tube_open: Vec<Hook<TubeOpenInfo>>,
+}
+impl LifecycleHooks {
+    pub(in crate::server) fn add_channel_close(
+        &mut self,
+        hook: impl Fn(&ChannelCloseInfo) + Send + Sync + 'static,
+    ) {
+        self.channel_close.push(Arc::new(hook));
+    }
+
+    pub(in crate::server) fn add_channel_open(
+        &mut self,
+        hook: impl Fn(&ChannelOpenInfo) + Send + Sync + 'static,
+    ) {
+        self.channel_open.push(Arc::new(hook));
+    }
+
+    pub(in crate::server) fn add_tube_close(
+        &mut self,
+        hook: impl Fn(&TubeCloseInfo) + Send + Sync + 'static,
+    ) {
+        self.tube_close.push(Arc::new(hook));
+    }
+
+    pub(in crate::server) fn add_tube_open(
+        &mut self,
+        hook: impl Fn(&TubeOpenInfo) + Send + Sync + 'static,
+    ) {
+        self.tube_open.push(Arc::new(hook));
+    }
+
+    pub(in crate::server) fn is_empty(&self) -> bool {
+        self.channel_close.is_empty() &&
+            self.channel_open.is_empty() &&
+            self.tube_close.is_empty() &&
+            self.tube_open.is_empty()
+    }
+}
+impl std::fmt::Debug for LifecycleHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LifecycleHooks")
+            .field("channel_close", &self.channel_close.len())
+            .field("channel_open", &self.channel_open.len())
+            .field("tube_close", &self.tube_close.len())
+            .field("tube_open", &self.tube_open.len())
+            .finish()
+    }
+}
+
+/**
+ * Runs a Server's LifecycleHooks over the life of one of its channels.
+ */
+#[derive(Debug)]
+pub(in crate::server) struct ChannelLifecycle {
+    channel_id: u64,
+    hooks: LifecycleHooks,
+    identity: Option<Identity>,
+    info: ChannelInfo,
+    num_tubes: AtomicU64,
+    opened_at: Instant,
+}
+impl ChannelLifecycle {
+    /**
+     * Runs the channel open hooks.
+     */
+    pub(in crate::server) fn open(
+        hooks: LifecycleHooks,
+        channel_id: u64,
+        info: &ChannelInfo,
+        identity: &Option<Identity>,
+    ) -> Arc<Self> {
+        let lifecycle = ChannelLifecycle {
+            channel_id,
+            hooks,
+            identity: identity.clone(),
+            info: info.clone(),
+            num_tubes: AtomicU64::new(0),
+            opened_at: Instant::now(),
+        };
+        if !lifecycle.hooks.channel_open.is_empty() {
+            let open_info = ChannelOpenInfo {
+                channel_id,
+                identity: lifecycle.identity.clone(),
+                info: lifecycle.info.clone(),
+            };
+            for hook in lifecycle.hooks.channel_open.iter() {
+                hook(&open_info);
+            }
+        }
+        Arc::new(lifecycle)
+    }
+
+    /**
+     * Runs the channel close hooks.
+     */
+    pub(in crate::server) fn close(
+        &self,
+        reason: CloseReason,
+        bytes_received: u64,
+        bytes_sent: u64,
+    ) {
+        if self.hooks.channel_close.is_empty() {
+            return;
+        }
+        let close_info = ChannelCloseInfo {
+            bytes_received,
+            bytes_sent,
+            channel_id: self.channel_id,
+            duration: self.opened_at.elapsed(),
+            identity: self.identity.clone(),
+            info: self.info.clone(),
+            num_tubes: self.num_tubes.load(Ordering::Relaxed),
+            reason,
+        };
+        for hook in self.hooks.channel_close.iter() {
+            hook(&close_info);
+        }
+    }
+}
+impl tube::TubeLifecycleObserver for ChannelLifecycle {
+    fn tube_opened(&self, tube_id: u32, headers: &HashMap<String, Vec<u8>>) {
+        self.num_tubes.fetch_add(1, Ordering::Relaxed);
+        if self.hooks.tube_open.is_empty() {
+            return;
+        }
+        let open_info = TubeOpenInfo {
+            channel_id: self.channel_id,
+            headers: headers.clone(),
+            identity: self.identity.clone(),
+            remote_addr: self.info.remote_addr,
+            tube_id,
+        };
+        for hook in self.hooks.tube_open.iter() {
+            hook(&open_info);
+        }
+    }
+
+    fn tube_closed(&self, tube_id: u32, outcome: tube::TubeOutcome, stats: tube::TubeStats) {
+        if self.hooks.tube_close.is_empty() {
+            return;
+        }
+        let close_info = TubeCloseInfo {
+            channel_id: self.channel_id,
+            duration: stats.duration,
+            identity: self.identity.clone(),
+            outcome,
+            payload_bytes_received: stats.payload_bytes_received,
+            payload_bytes_sent: stats.payload_bytes_sent,
+            remote_addr: self.info.remote_addr,
+            tube_id,
+        };
+        for hook in self.hooks.tube_close.iter() {
+            hook(&close_info);
+        }
+    }
+}
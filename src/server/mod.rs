@@ -1,12 +1,34 @@
+mod auth;
 mod channel;
+mod health_checks;
 mod hyper_tubez_service;
+mod incoming;
+mod lifecycle_hooks;
 mod server;
+mod server_builder;
 mod server_context;
 mod server_error;
 mod server_event;
+mod service;
 
+pub use auth::AuthError;
+pub use auth::ChannelInfo;
+pub use auth::Identity;
 pub use channel::Channel;
-pub use channel::ChannelEvent;
+pub use channel::DrainError;
+pub use channel::GoAwayError;
+pub use channel::SendExtensionFrameError;
+pub use health_checks::HealthChecks;
+pub use hyper_tubez_service::TubezService;
+pub use lifecycle_hooks::ChannelCloseInfo;
+pub use lifecycle_hooks::ChannelOpenInfo;
+pub use lifecycle_hooks::TubeCloseInfo;
+pub use lifecycle_hooks::TubeOpenInfo;
 pub use server::Server;
+pub use server_builder::ServerBuilder;
+pub use crate::common::ChannelEvent;
+pub use crate::common::CloseReason;
+pub use crate::common::PingError;
 pub use server_error::ServerError;
 pub use server_event::ServerEvent;
+pub use service::service;
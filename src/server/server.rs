@@ -1,42 +1,531 @@
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use hyper::server::accept::Accept;
+use hyper::server::conn::AddrIncoming;
+
+use crate::common::frame;
+use crate::common::ChannelContext;
+use crate::common::ChannelExecutor;
+use crate::common::tube;
+use super::auth::AuthError;
+use super::auth::Authenticator;
+use super::auth::ChannelInfo;
+use super::auth::Identity;
+#[cfg(feature = "testing")]
+use super::channel::Channel;
+use super::channel::drain_channel;
+use super::hyper_tubez_service::serve_raw_connection;
 use super::hyper_tubez_service::TubezMakeSvc;
+use super::hyper_tubez_service::TubezService;
+use super::incoming::PeerAddr;
+use super::lifecycle_hooks::ChannelCloseInfo;
+use super::lifecycle_hooks::ChannelOpenInfo;
+use super::lifecycle_hooks::LifecycleHooks;
+use super::lifecycle_hooks::TubeCloseInfo;
+use super::lifecycle_hooks::TubeOpenInfo;
+use super::server_builder::incoming_from_std;
+use super::server_builder::ServerBuilder;
+use super::server_builder::ServerLimits;
 use super::server_context::ServerContext;
 use super::server_error::ServerError;
 use super::server_event::ServerEvent;
 
+/**
+ * A listener (or accept stream) that a Server is currently serving channels
+ * from.
+ */
+struct Listener {
+    /**
+     * None for an accept stream given to Server::from_incoming().
+     */
+    local_addr: Option<SocketAddr>,
+    /**
+     * Signals the listener to stop accepting new connections. Connections 
+     * that were already accepted by it (and the channels/tubes running on 
+     * them) are left to run to completion.
+     */
+    shutdown: tokio::sync::oneshot::Sender<()>,
+}
+
 pub struct Server {
+    /**
+     * Empty for a Server that only receives channels from TubezServices (see
+     * server::service()).
+     */
+    listeners: Vec<Listener>,
     server_ctx: Arc<Mutex<ServerContext>>,
 }
 impl Server {
-    pub async fn new(addr: &SocketAddr) -> Self {
+    /**
+     * Binds a listener to `addr` and starts serving channels on it. Resolves 
+     * only after the listener is bound, so bind failures (address in use, 
+     * insufficient permissions, etc) are returned here rather than surfacing 
+     * later on the event stream.
+     */
+    pub async fn new(addr: &SocketAddr) -> Result<Self, ServerError> {
+        Self::builder().build(addr).await
+    }
+
+    /**
+     * Like Server::new(), but listens on each of addrs (e.g. `0.0.0.0:3000`
+     * and `[::]:3000`) and surfaces the channels from all of them on the one
+     * event stream. When addrs has both IPv4 and IPv6 addresses the IPv6 
+     * listeners are made IPv6-only, so that they don't collide with the IPv4
+     * listeners on the same port.
+     *
+     * Resolves once every listener is bound. If any of them fails to bind, 
+     * none are served and the failing address is returned in the error.
+     */
+    pub async fn new_with_addrs(addrs: &[SocketAddr]) -> Result<Self, ServerError> {
+        Self::builder().build_with_addrs(addrs).await
+    }
+
+    /**
+     * Like Server::new(), but serves on a listener that is already bound 
+     * (e.g. one handed over by socket activation, or bound to port 0 by a 
+     * test that needs the address up front).
+     */
+    pub async fn from_listener(listener: tokio::net::TcpListener) -> Result<Self, ServerError> {
+        Self::builder().build_from_listener(listener).await
+    }
+
+    /**
+     * Serves channels on each connection yielded by incoming, for transports
+     * that a TCP listener can't provide. Errors yielded by incoming are 
+     * surfaced on the Server's event stream and stop it from accepting
+     * further connections.
+     *
+     * Channels accepted this way have no Channel::remote_addr() and the 
+     * Server has no local_addr().
+     */
+    pub fn from_incoming<S, IO, E>(incoming: S) -> Self
+    where
+        S: futures::Stream<Item = Result<IO, E>> + Send + 'static,
+        IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Self::builder().build_from_incoming(incoming)
+    }
+
+    /**
+     * Binds a listener to the Unix domain socket at path and starts serving
+     * channels on it, e.g. for sidecars collocated with the Server that
+     * shouldn't need a TCP port opened (see client::UnixConnector). Binding
+     * fails if a file already exists at path, so a socket left behind by a
+     * previous run has to be removed first.
+     *
+     * Like with Server::from_incoming(), channels accepted this way have no
+     * Channel::remote_addr() and the Server has no local_addr().
+     */
+    #[cfg(unix)]
+    pub async fn from_unix_path(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, ServerError> {
+        Self::builder().build_from_unix_path(path).await
+    }
+
+    /**
+     * Starts configuring a Server that enforces resource limits on its 
+     * clients.
+     */
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    pub(in crate::server) fn from_incoming_parts<I>(
+        incoming: I,
+        local_addr: Option<SocketAddr>,
+        limits: ServerLimits,
+    ) -> Self
+    where
+        I: Accept + Send + 'static,
+        I::Conn: tokio::io::AsyncRead + tokio::io::AsyncWrite + PeerAddr + Send + Unpin + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut server = Self::without_listener(limits);
+        server.add_listener(incoming, local_addr);
+        server
+    }
+
+    pub(in crate::server) fn add_listener<I>(
+        &mut self,
+        incoming: I,
+        local_addr: Option<SocketAddr>,
+    )
+    where
+        I: Accept + Send + 'static,
+        I::Conn: tokio::io::AsyncRead + tokio::io::AsyncWrite + PeerAddr + Send + Unpin + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let shutdown = Self::serve(incoming, local_addr, self.server_ctx.clone());
+        self.listeners.push(Listener {
+            local_addr,
+            shutdown,
+        });
+    }
+
+    /**
+     * A Server that only receives channels from the TubezServices made for 
+     * it (see server::service()).
+     */
+    pub(in crate::server) fn without_listener(limits: ServerLimits) -> Self {
         let server_ctx = Arc::new(Mutex::new(ServerContext {
+            ack_timeout_policy: None,
+            authenticator: None,
+            channel_executor: ChannelExecutor::default(),
+            draining: false,
+            event_queue_limit: None,
+            extension_frame_handlers: frame::ExtensionFrameHandlers::new(),
             is_complete: false,
+            late_payload_policy: frame::LatePayloadPolicy::default(),
+            lifecycle_hooks: LifecycleHooks::default(),
+            limits,
+            max_pending_tubes_per_channel: None,
+            next_open_channel_id: 0,
+            num_open_channels: 0,
+            open_channels: HashMap::new(),
+            outgoing_frame_interceptors: frame::FrameInterceptors::new(),
             pending_events: VecDeque::new(),
+            #[cfg(feature = "bench")]
+            serves_bench_tubes: false,
+            slow_consumer_limit: None,
             waker: None,
         }));
 
-        let hyper_server = 
-            hyper::Server::bind(&addr)
-                .http2_only(true)
-                .serve(TubezMakeSvc::new(server_ctx.clone()));
+        Server {
+            listeners: vec![],
+            server_ctx,
+        }
+    }
+
+    pub(in crate::server) fn make_service(&self) -> TubezService {
+        TubezService::new(self.server_ctx.clone())
+    }
+
+    /**
+     * Serves a channel whose frames are carried over req_body and the 
+     * returned body rather than an HTTP connection (see testing::loopback()),
+     * handing back the Channel instead of publishing it as a 
+     * ServerEvent::NewChannel. Returns None if the Server already has as 
+     * many channels open as it allows.
+     */
+    #[cfg(feature = "testing")]
+    pub(in crate) fn accept_loopback_channel(
+        &self,
+        req_body: hyper::Body,
+    ) -> Option<(Channel, hyper::Body)> {
+        let res_body = self.make_service().accept_loopback_channel(req_body)?;
+        match self.server_ctx.lock().unwrap().pending_events.pop_back() {
+            Some(Ok(ServerEvent::NewChannel(channel))) => Some((channel, res_body)),
+            _ => unreachable!("Loopback channels are published as they're accepted"),
+        }
+    }
+
+    /**
+     * Authenticates each channel before it is accepted. A channel is only
+     * surfaced as a ServerEvent::NewChannel (carrying the Identity, see
+     * Channel::identity()) once authenticate resolves to Ok, and each Tube 
+     * the client opens on it starts with a TubeEvent::AuthenticatedAndReady.
+     * Rejected channels are answered with an HTTP error status instead (see
+     * AuthError::status_code()).
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn with_authenticator<F, Fut>(self, authenticate: F) -> Self
+    where
+        F: Fn(&ChannelInfo) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<Identity, AuthError>> + Send + 'static,
+    {
+        self.server_ctx.lock().unwrap().authenticator = Some(Authenticator::new(authenticate));
+        self
+    }
+
+    /**
+     * The address of the currently-bound listener (the first one, if it was
+     * made with Server::new_with_addrs()). This is mostly useful for 
+     * discovering the port that was picked when binding to port 0. None if 
+     * the Server is serving from an accept stream (see 
+     * Server::from_incoming()).
+     */
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listeners.first().and_then(|listener| listener.local_addr)
+    }
+
+    /**
+     * The addresses of all of the currently-bound listeners, in the order 
+     * they were given to Server::new_with_addrs().
+     */
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().filter_map(|listener| listener.local_addr).collect()
+    }
+
+    /**
+     * Registers an interceptor that can inspect, rewrite, or veto every frame 
+     * sent to clients on any of this Server's channels (including channels 
+     * that were established before the interceptor was added). Interceptors 
+     * run in the order they were added.
+     */
+    pub fn add_outgoing_frame_interceptor(
+        &mut self, 
+        interceptor: impl frame::FrameInterceptor + 'static,
+    ) {
+        let server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.outgoing_frame_interceptors.add(interceptor);
+    }
+
+    /**
+     * Registers a hook that's run as each channel is accepted (before it's
+     * yielded as a ServerEvent::NewChannel), e.g. for access logging. 
+     *
+     * Lifecycle hooks run inline as things happen (usually on the channel's
+     * frame-processing task), so they should not block. They only apply to channels established after they 
+     * are registered, and run in the order they were registered.
+     */
+    pub fn on_channel_open(&mut self, hook: impl Fn(&ChannelOpenInfo) + Send + Sync + 'static) {
+        self.server_ctx.lock().unwrap().lifecycle_hooks.add_channel_open(hook);
+    }
+
+    /**
+     * Registers a hook that's run once each channel has closed (see 
+     * ChannelEvent::Closed), after the hooks for each of its Tubes (see 
+     * on_channel_open()). 
+     */
+    pub fn on_channel_close(&mut self, hook: impl Fn(&ChannelCloseInfo) + Send + Sync + 'static) {
+        self.server_ctx.lock().unwrap().lifecycle_hooks.add_channel_close(hook);
+    }
+
+    /**
+     * Registers a hook that's run as clients open each Tube (see 
+     * on_channel_open()), e.g. for quota accounting.
+     */
+    pub fn on_tube_open(&mut self, hook: impl Fn(&TubeOpenInfo) + Send + Sync + 'static) {
+        self.server_ctx.lock().unwrap().lifecycle_hooks.add_tube_open(hook);
+    }
+
+    /**
+     * Registers a hook that's run as each Tube a client opened is closed or 
+     * aborted (see on_channel_open()). This is run with the Tube's internal 
+     * state locked, so it must not use the Tube.
+     */
+    pub fn on_tube_close(&mut self, hook: impl Fn(&TubeCloseInfo) + Send + Sync + 'static) {
+        self.server_ctx.lock().unwrap().lifecycle_hooks.add_tube_close(hook);
+    }
+
+    /**
+     * Registers a handler for ExtensionFrames of the given type_id received 
+     * from clients on any of this Server's channels. type_id must be within 
+     * the range reserved for extensions (frame::MIN_EXTENSION_FRAMETYPE 
+     * through frame::MAX_EXTENSION_FRAMETYPE).
+     */
+    pub fn register_extension_frame_handler(
+        &mut self,
+        type_id: u8,
+        handler: impl frame::ExtensionFrameHandler + 'static,
+    ) -> Result<(), frame::ExtensionFrameRegistrationError> {
+        let server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.extension_frame_handlers.register(type_id, handler)
+    }
+
+    /**
+     * Limits how many tubes each channel will queue before the application
+     * has received them from the channel's event stream. While a channel is
+     * at its limit the server stops reading from that channel, so the
+     * client's make_tube() calls (and its sends on other tubes of the channel)
+     * stall until the application catches up, rather than the server
+     * buffering tubes and payloads it isn't ready for.
+     *
+     * Only applies to channels established after it is set. None (the
+     * default) queues tubes without limit.
+     */
+    pub fn set_max_pending_tubes_per_channel(&mut self, max_pending_tubes: Option<usize>) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.max_pending_tubes_per_channel = max_pending_tubes;
+    }
+
+    /**
+     * Runs the internal tasks of each channel (and its tubes) on the given
+     * executor, e.g. a runtime dedicated to latency-critical traffic. The 
+     * HTTP/2 connections themselves are still driven by the runtime the 
+     * Server was created on.
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn set_channel_executor(&mut self, executor: ChannelExecutor) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.channel_executor = executor;
+    }
+
+    /**
+     * Sets how long tubes on each channel wait for acks from the client, and
+     * what happens to a tube when one doesn't come in time (see 
+     * Channel::set_ack_timeout_policy()).
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn set_ack_timeout_policy(&mut self, policy: Option<tube::AckTimeoutPolicy>) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.ack_timeout_policy = policy;
+    }
+
+    /**
+     * Bounds the TubeEvents queued on each tube of each channel (see 
+     * Channel::set_event_queue_limit()).
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn set_event_queue_limit(&mut self, limit: Option<tube::EventQueueLimit>) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.event_queue_limit = limit;
+    }
+
+    /**
+     * Evicts the consumers of tubes on each channel that stop taking events
+     * off of them (see Channel::set_slow_consumer_limit()).
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn set_slow_consumer_limit(&mut self, limit: Option<tube::SlowConsumerLimit>) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.slow_consumer_limit = limit;
+    }
 
-        let tubez_server = Server {
-            server_ctx: server_ctx.clone(),
+    /**
+     * Sets what each channel does with payloads that arrive for tubes that 
+     * have already been closed or aborted (see 
+     * Channel::set_late_payload_policy()).
+     *
+     * Only applies to channels established after it is set.
+     */
+    pub fn set_late_payload_policy(&mut self, policy: frame::LatePayloadPolicy) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.late_payload_policy = policy;
+    }
+
+    /**
+     * Serves the built-in echo/throughput bench service (see bench::run()) to
+     * tubes opened with the bench::BENCH_HEADER. Bench tubes are handled 
+     * internally and never show up on a channel's event stream.
+     *
+     * Only applies to channels established after it is set.
+     */
+    #[cfg(feature = "bench")]
+    pub fn set_serves_bench_tubes(&mut self, serves_bench_tubes: bool) {
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.serves_bench_tubes = serves_bench_tubes;
+    }
+
+    /**
+     * Drains every channel that is currently open (see Channel::drain()), 
+     * e.g. ahead of a rolling restart. Each yields a ChannelEvent::Drained
+     * once its Tubes have all finished. Channels accepted after this are not
+     * drained, so listeners that should stop accepting channels need to be 
+     * shut down separately. The Server's readiness health check fails from
+     * then on (see ServerBuilder::with_health_checks()).
+     *
+     * Resolves with the number of channels a Drain was sent on. Channels 
+     * whose client has already gone away are skipped.
+     */
+    pub async fn drain_all(
+        &self,
+        reason: frame::DrainReason,
+        deadline: Option<std::time::SystemTime>,
+    ) -> usize {
+        let mut open_channels: Vec<(u64, ChannelContext)> = {
+            let mut server_ctx = self.server_ctx.lock().unwrap();
+            server_ctx.draining = true;
+            server_ctx.open_channels.iter()
+                .map(|(id, channel_ctx)| (*id, channel_ctx.clone()))
+                .collect()
         };
+        open_channels.sort_by_key(|(id, _)| *id);
+
+        let mut num_drained = 0;
+        for (_, channel_ctx) in open_channels {
+            match drain_channel(&channel_ctx, reason, deadline).await {
+                Ok(()) => num_drained += 1,
+                Err(e) => log::warn!("Failed to drain a channel: {:?}", e),
+            }
+        }
+        num_drained
+    }
+
+    /**
+     * Binds a new listener to `addr` and then stops the previous listener(s) 
+     * from accepting any new connections. Channels that were established on the 
+     * previous listener continue to run (and continue to emit events from this
+     * Server) until their clients go away.
+     *
+     * If binding the new listener fails, the previous listener is left 
+     * running and an error is returned.
+     */
+    pub fn rebind(&mut self, addr: &SocketAddr) -> Result<(), ServerError> {
+        match AddrIncoming::bind(addr) {
+            Ok(incoming) => {
+                self.replace_listener(incoming);
+                Ok(())
+            },
+            Err(e) => Err(ServerError::BindError(e)),
+        }
+    }
+
+    /**
+     * Like Server::rebind(), but takes over an already-bound listener. This is
+     * useful when the listening socket was inherited from another process 
+     * (e.g. via fd-passing during an in-place binary upgrade) or was bound 
+     * with SO_REUSEPORT alongside an older process.
+     */
+    pub fn rebind_from_tcp(
+        &mut self, 
+        listener: std::net::TcpListener,
+    ) -> Result<(), ServerError> {
+        let incoming = incoming_from_std(listener)?;
+        self.replace_listener(incoming);
+        Ok(())
+    }
+
+    fn replace_listener(&mut self, incoming: AddrIncoming) {
+        let old_listeners = std::mem::take(&mut self.listeners);
+        let local_addr = incoming.local_addr();
+        self.add_listener(incoming, Some(local_addr));
+        for old_listener in old_listeners {
+            log::trace!("Shutting down previous listener...");
+            let _ = old_listener.shutdown.send(());
+        }
+    }
+
+    fn serve<I>(
+        incoming: I,
+        local_addr: Option<SocketAddr>,
+        server_ctx: Arc<Mutex<ServerContext>>,
+    ) -> tokio::sync::oneshot::Sender<()>
+    where
+        I: Accept + Send + 'static,
+        I::Conn: tokio::io::AsyncRead + tokio::io::AsyncWrite + PeerAddr + Send + Unpin + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+        let limits = server_ctx.lock().unwrap().limits.clone();
+        if limits.raw_transport {
+            tokio::spawn(Self::serve_raw(incoming, local_addr, server_ctx, shutdown_receiver));
+            return shutdown_sender;
+        }
+        let hyper_server = 
+            limits.configure_http(hyper::Server::builder(incoming))
+                .serve(TubezMakeSvc::new(server_ctx.clone()))
+                .with_graceful_shutdown(async {
+                    // Either an explicit shutdown signal or the Server being
+                    // dropped will stop this listener.
+                    let _ = shutdown_receiver.await;
+                });
 
         tokio::spawn(async move {
             if let Err(e) = hyper_server.await {
-                let mut server_ctx = server_ctx.lock().unwrap();
-                log::error!("Http server error: {}", e);
-                server_ctx.pending_events.push_back(Err(ServerError::Err(format!("{:?}", e))));
-                // TODO: Need to iterate all tubes and error them here as well.
-                if let Some(waker) = server_ctx.waker.take() {
-                    waker.wake();
-                };
+                log::error!("Http server error (listening on {:?}): {}", local_addr, e);
+                Self::publish_listener_error(&server_ctx, local_addr, format!("{:?}", e));
             } else {
                 // TODO: Indicate that the http request has EOM'd? Not sure...
                 // 
@@ -45,7 +534,63 @@ impl Server {
             }
         });
 
-        tubez_server
+        shutdown_sender
+    }
+
+    /**
+     * Like serve(), but each connection accepted from incoming carries a 
+     * channel with no HTTP (see ServerBuilder::with_raw_transport()).
+     */
+    async fn serve_raw<I>(
+        incoming: I,
+        local_addr: Option<SocketAddr>,
+        server_ctx: Arc<Mutex<ServerContext>>,
+        mut shutdown_receiver: tokio::sync::oneshot::Receiver<()>,
+    )
+    where
+        I: Accept + Send + 'static,
+        I::Conn: tokio::io::AsyncRead + tokio::io::AsyncWrite + PeerAddr + Send + Unpin + 'static,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut incoming = Box::pin(incoming);
+        loop {
+            let accepted = tokio::select! {
+                // Either an explicit shutdown signal or the Server being 
+                // dropped will stop this listener.
+                _ = &mut shutdown_receiver => return,
+                accepted = futures::future::poll_fn(|cx| incoming.as_mut().poll_accept(cx)) =>
+                    accepted,
+            };
+            match accepted {
+                Some(Ok(conn)) => {
+                    let remote_addr = conn.peer_addr();
+                    tokio::spawn(serve_raw_connection(server_ctx.clone(), remote_addr, conn));
+                },
+                Some(Err(e)) => {
+                    let e = e.into();
+                    log::error!("Raw listener error (listening on {:?}): {}", local_addr, e);
+                    Self::publish_listener_error(&server_ctx, local_addr, format!("{:?}", e));
+                    return;
+                },
+                None => return,
+            }
+        }
+    }
+
+    fn publish_listener_error(
+        server_ctx: &Arc<Mutex<ServerContext>>,
+        local_addr: Option<SocketAddr>,
+        detail: String,
+    ) {
+        let mut server_ctx = server_ctx.lock().unwrap();
+        server_ctx.pending_events.push_back(Err(ServerError::ListenerError {
+            local_addr,
+            detail,
+        }));
+        // TODO: Need to iterate all tubes and error them here as well.
+        if let Some(waker) = server_ctx.waker.take() {
+            waker.wake();
+        };
     }
 
     pub async fn new_tube() /*TODO: -> Tube*/ {
@@ -78,5 +623,608 @@ impl futures::stream::Stream for Server {
 
 #[cfg(test)]
 mod server_tests {
-    // TODO
+    use super::*;
+    use crate::server::HealthChecks;
+
+    #[tokio::test]
+    async fn new_errors_if_address_is_in_use() {
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let occupied_addr = occupied.local_addr().unwrap();
+
+        match Server::new(&occupied_addr).await {
+            Err(ServerError::BindError(_)) => (),
+            Err(e) => panic!("Unexpected error from Server::new(): {:?}", e),
+            Ok(_) => panic!("Binding to an occupied address succeeded!?"),
+        }
+    }
+
+    #[tokio::test]
+    async fn local_addr_reports_ephemeral_port() {
+        let server = Server::new(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+        assert_ne!(server.local_addr().unwrap().port(), 0);
+        tokio::net::TcpStream::connect(server.local_addr().unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn new_with_addrs_listens_on_ipv4_and_ipv6_on_the_same_port() {
+        // Find a port that's free on both the IPv4 and IPv6 wildcards
+        let port = std::net::TcpListener::bind("[::]:0").unwrap().local_addr().unwrap().port();
+        let addrs: Vec<SocketAddr> = vec![
+            format!("0.0.0.0:{}", port).parse().unwrap(),
+            format!("[::]:{}", port).parse().unwrap(),
+        ];
+
+        let server = Server::new_with_addrs(&addrs).await.unwrap();
+        assert_eq!(server.local_addrs(), addrs);
+        assert_eq!(server.local_addr(), Some(addrs[0]));
+
+        tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port)).await.unwrap();
+        tokio::net::TcpStream::connect(format!("[::1]:{}", port)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn new_with_addrs_reports_which_addr_failed_to_bind() {
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let occupied_addr = occupied.local_addr().unwrap();
+        let addrs = vec!["127.0.0.1:0".parse().unwrap(), occupied_addr];
+
+        match Server::new_with_addrs(&addrs).await {
+            Err(ServerError::AddrBindError { addr, .. }) => assert_eq!(addr, occupied_addr),
+            Err(e) => panic!("Unexpected error from Server::new_with_addrs(): {:?}", e),
+            Ok(_) => panic!("Binding to an occupied address succeeded!?"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rebind_keeps_previous_listener_if_new_bind_fails() {
+        let mut server = Server::new(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let original_addr = server.local_addr();
+
+        // Occupy a port so that rebinding to it fails
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let occupied_addr = occupied.local_addr().unwrap();
+
+        match server.rebind(&occupied_addr) {
+            Err(ServerError::BindError(_)) => {
+                assert_eq!(server.listeners.len(), 1);
+                assert_eq!(server.local_addr(), original_addr);
+            },
+            Err(e) => panic!("Unexpected error from Server::rebind(): {:?}", e),
+            Ok(()) => panic!("Rebinding to an occupied address succeeded!?"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rebind_from_tcp_serves_on_inherited_listener() {
+        let mut server = Server::new(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        server.rebind_from_tcp(listener).unwrap();
+        assert_eq!(server.local_addr(), Some(addr));
+
+        tokio::net::TcpStream::connect(addr).await.unwrap();
+    }
+
+    #[cfg(feature = "client")]
+    async fn accept_echo_channel(server: &mut Server) {
+        use futures::StreamExt;
+        use crate::common::ChannelEvent;
+
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        tokio::spawn(async move {
+            while let Some(ChannelEvent::NewTube(mut tube)) = channel.next().await {
+                tokio::spawn(async move {
+                    while let Some(event) = tube.next().await {
+                        if let tube::TubeEvent::Payload(data) = event {
+                            tube.send_and_forget(data).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[cfg(feature = "client")]
+    async fn assert_echoes(tube: &mut tube::Tube, data: &'static str) {
+        use futures::StreamExt;
+        use std::time::Duration;
+
+        tube.send(data.as_bytes().to_vec(), Duration::from_secs(5)).await.unwrap();
+        let echoed = tokio::time::timeout(Duration::from_secs(5), tube.next()).await.unwrap();
+        assert_eq!(echoed, Some(tube::TubeEvent::Payload(data.into())));
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn rebind_keeps_existing_channels_and_serves_new_ones_on_the_new_addr() {
+        use std::time::Duration;
+        use crate::client::Client;
+
+        let mut server = Server::new(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let original_addr = server.local_addr().unwrap();
+        let mut client = Client::new(format!("http://{}/", original_addr).parse().unwrap());
+        let mut channel = client.make_tube_channel(HashMap::new()).await.unwrap();
+        accept_echo_channel(&mut server).await;
+        let mut tube = channel.make_tube(HashMap::new()).await.unwrap();
+        assert_echoes(&mut tube, "before rebinding").await;
+
+        server.rebind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let new_addr = server.local_addr().unwrap();
+        assert_ne!(new_addr, original_addr);
+
+        // The channel established on the previous listener keeps running
+        assert_echoes(&mut tube, "after rebinding").await;
+
+        let mut new_client = Client::new(format!("http://{}/", new_addr).parse().unwrap());
+        let mut new_channel = new_client.make_tube_channel(HashMap::new()).await.unwrap();
+        accept_echo_channel(&mut server).await;
+        let mut new_tube = new_channel.make_tube(HashMap::new()).await.unwrap();
+        assert_echoes(&mut new_tube, "on the new listener").await;
+
+        // The previous listener is closed once it has been shut down
+        let refused = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match tokio::net::TcpStream::connect(original_addr).await {
+                    Ok(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                    Err(e) => return e,
+                }
+            }
+        }).await.unwrap();
+        assert_eq!(refused.kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn from_listener_serves_on_the_given_listener() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::from_listener(listener).await.unwrap();
+        assert_eq!(server.local_addr(), Some(addr));
+
+        tokio::net::TcpStream::connect(addr).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn from_incoming_surfaces_accept_errors() {
+        use futures::StreamExt;
+
+        let incoming = futures::stream::iter(vec![
+            Err::<tokio::io::DuplexStream, _>(std::io::Error::other("Accept failed")),
+        ]);
+        let mut server = Server::from_incoming(incoming);
+        assert_eq!(server.local_addr(), None);
+
+        match server.next().await {
+            Some(Err(ServerError::ListenerError { local_addr: None, .. })) => (),
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        }
+    }
+    #[tokio::test]
+    async fn drain_all_drains_open_channels_until_their_tubes_finish() {
+        use futures::FutureExt;
+        use futures::StreamExt;
+        use hyper::service::Service;
+        use crate::common::ChannelEvent;
+        use crate::common::CloseReason;
+
+        let (mut service, mut server) = Server::builder().build_service();
+        assert_eq!(server.drain_all(frame::DrainReason::Shutdown, None).await, 0);
+
+        let (mut req_body_sender, req_body) = hyper::Body::channel();
+        let req = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("http://api.example.com/tubez")
+            .body(req_body)
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        tokio::spawn(hyper::body::to_bytes(res.into_body()));
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        let timeout = std::time::Duration::from_secs(1);
+
+        let newtube = frame::encode::newtube_frame(1, std::collections::HashMap::new());
+        req_body_sender.send_data(newtube.unwrap().into()).await.unwrap();
+        let _tube = match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(tube)) => tube,
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        };
+
+        assert_eq!(server.drain_all(frame::DrainReason::Shutdown, None).await, 1);
+        let newtube = frame::encode::newtube_frame(3, std::collections::HashMap::new());
+        req_body_sender.send_data(newtube.unwrap().into()).await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(channel.next().now_or_never().is_none());
+
+        let abort = frame::encode::abort_frame(1, frame::AbortReason::ApplicationError);
+        req_body_sender.send_data(abort.unwrap().into()).await.unwrap();
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::Drained) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+
+        drop(req_body_sender);
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::Closed(CloseReason::Drained)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        tokio::time::timeout(timeout, async {
+            while !server.server_ctx.lock().unwrap().open_channels.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn health_checks_are_answered_over_http1() {
+        use tokio::io::AsyncReadExt;
+        use tokio::io::AsyncWriteExt;
+
+        async fn get_status_line(addr: SocketAddr, path: &str) -> String {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let req = format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path,
+            );
+            stream.write_all(req.as_bytes()).await.unwrap();
+            let mut res = String::new();
+            stream.read_to_string(&mut res).await.unwrap();
+            res.lines().next().unwrap().to_string()
+        }
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let server = Server::builder()
+            .with_health_checks(HealthChecks::default())
+            .build(&addr)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        assert_eq!(get_status_line(addr, "/healthz").await, "HTTP/1.1 200 OK");
+        assert_eq!(get_status_line(addr, "/readyz").await, "HTTP/1.1 200 OK");
+        assert_eq!(server.drain_all(frame::DrainReason::Shutdown, None).await, 0);
+        assert_eq!(get_status_line(addr, "/healthz").await, "HTTP/1.1 200 OK");
+        assert_eq!(get_status_line(addr, "/readyz").await, "HTTP/1.1 503 Service Unavailable");
+    }
+
+    #[tokio::test]
+    async fn channels_are_carried_over_chunked_http1_requests() {
+        use futures::StreamExt;
+        use tokio::io::AsyncReadExt;
+        use tokio::io::AsyncWriteExt;
+        use crate::common::ChannelEvent;
+
+        let listen_addr = "127.0.0.1:0".parse().unwrap();
+        let mut server = Server::builder().build(&listen_addr).await.unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let req = "POST /chat HTTP/1.1\r\nHost: localhost\r\n\
+                   Transfer-Encoding: chunked\r\n\r\n";
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut res = vec![];
+        while !res.ends_with(b"\r\n\r\n") {
+            res.push(stream.read_u8().await.unwrap());
+        }
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK"), "{}", res);
+        assert!(res.to_lowercase().contains("transfer-encoding: chunked"), "{}", res);
+
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        assert_eq!(channel.request_uri(), "/chat");
+        let newtube = frame::encode::newtube_frame(1, std::collections::HashMap::new()).unwrap();
+        let mut chunk = format!("{:x}\r\n", newtube.len()).into_bytes();
+        chunk.extend_from_slice(&newtube);
+        chunk.extend_from_slice(b"\r\n");
+        stream.write_all(&chunk).await.unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(_)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+
+        let server = Server::builder().with_http2_only().build(&listen_addr).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut res = vec![];
+        // Refused by either closing or resetting the connection.
+        let _ = stream.read_to_end(&mut res).await;
+        assert!(!res.starts_with(b"HTTP/1.1 200"), "{}", String::from_utf8_lossy(&res));
+    }
+
+    #[tokio::test]
+    async fn channels_negotiate_a_max_payload_frame_size_the_decoder_accepts() {
+        use tokio::io::AsyncWriteExt;
+        use crate::common::raw_transport::RawHead;
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let server = Server::builder()
+            .with_raw_transport()
+            .with_max_payload_frame_size(8192)
+            .with_max_payload_size(1000)
+            .build(&addr)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = tokio::io::BufReader::new(stream);
+        let mut req_head = RawHead {
+            subject: "/".to_string(),
+            headers: hyper::HeaderMap::new(),
+        };
+        req_head.headers.insert(frame::MAX_PAYLOAD_FRAME_SIZE_HEADER, 4096.into());
+        stream.write_all(&req_head.encode()).await.unwrap();
+        let res_head = RawHead::read(&mut stream).await.unwrap();
+        assert_eq!(res_head.subject, "200");
+        assert_eq!(res_head.headers.get(frame::MAX_PAYLOAD_FRAME_SIZE_HEADER).unwrap(), "1000");
+    }
+
+    #[tokio::test]
+    async fn raw_listeners_serve_channels_without_http() {
+        use futures::StreamExt;
+        use tokio::io::AsyncWriteExt;
+        use crate::common::ChannelEvent;
+        use crate::common::raw_transport::RawHead;
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let mut server = Server::builder()
+            .with_raw_transport()
+            .build(&addr)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = tokio::io::BufReader::new(stream);
+        let mut req_head = RawHead {
+            subject: "/chat".to_string(),
+            headers: hyper::HeaderMap::new(),
+        };
+        req_head.headers.insert(
+            frame::FRAMING_VERSION_HEADER,
+            frame::FramingVersion::LATEST.header_value().parse().unwrap(),
+        );
+        stream.write_all(&req_head.encode()).await.unwrap();
+        let res_head = RawHead::read(&mut stream).await.unwrap();
+        assert_eq!(res_head.subject, "200");
+        assert_eq!(
+            res_head.headers.get(frame::FRAMING_VERSION_HEADER).unwrap(),
+            frame::FramingVersion::LATEST.header_value(),
+        );
+
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        assert_eq!(channel.request_uri(), "/chat");
+        let newtube = frame::encode::encode_frame_with_version(
+            frame::Frame::NewTube {
+                tube_id: 1,
+                headers: std::collections::HashMap::new(),
+            },
+            frame::FramingVersion::LATEST,
+        );
+        stream.write_all(&newtube.unwrap()).await.unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(_)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_channels_are_closed_and_their_tubes_aborted() {
+        use futures::StreamExt;
+        use hyper::service::Service;
+        use crate::common::ChannelEvent;
+        use crate::common::CloseReason;
+        use crate::common::tube::TubeEvent;
+
+        let idle_timeout = std::time::Duration::from_millis(50);
+        let (mut service, mut server) = Server::builder()
+            .with_idle_channel_timeout(idle_timeout)
+            .build_service();
+        let (mut req_body_sender, req_body) = hyper::Body::channel();
+        let req = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("http://api.example.com/tubez")
+            .body(req_body)
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        let res_body = tokio::spawn(hyper::body::to_bytes(res.into_body()));
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        let timeout = std::time::Duration::from_secs(1);
+
+        let newtube = frame::encode::newtube_frame(1, std::collections::HashMap::new());
+        req_body_sender.send_data(newtube.unwrap().into()).await.unwrap();
+        let mut tube = match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(tube)) => tube,
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        };
+        let channel_ctx = server.server_ctx.lock().unwrap().open_channels[&0].clone();
+
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::Closed(CloseReason::IdleTimeout)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+        assert_eq!(
+            tokio::time::timeout(timeout, tube.next()).await.unwrap(), 
+            Some(TubeEvent::IdleTimeout),
+        );
+        assert!(channel_ctx.tube_managers.is_empty());
+        assert!(tokio::time::timeout(timeout, res_body).await.unwrap().unwrap().is_err());
+        tokio::time::timeout(timeout, async {
+            while !server.server_ctx.lock().unwrap().open_channels.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        }).await.unwrap();
+        drop(req_body_sender);
+    }
+
+    #[tokio::test]
+    async fn lifecycle_hooks_see_each_channel_and_tube() {
+        use futures::StreamExt;
+        use hyper::service::Service;
+        use crate::common::ChannelEvent;
+        use crate::common::CloseReason;
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let (mut service, mut server) = Server::builder().build_service();
+        let channel_opens = events.clone();
+        server.on_channel_open(move |info: &ChannelOpenInfo| {
+            channel_opens.lock().unwrap().push(format!("channel_open {}", info.channel_id));
+        });
+        let tube_opens = events.clone();
+        server.on_tube_open(move |info: &TubeOpenInfo| {
+            tube_opens.lock().unwrap().push(format!(
+                "tube_open {} {} {:?}",
+                info.channel_id,
+                info.tube_id,
+                info.headers.get("name"),
+            ));
+        });
+        let tube_closes = events.clone();
+        server.on_tube_close(move |info: &TubeCloseInfo| {
+            tube_closes.lock().unwrap().push(format!(
+                "tube_close {} {} {:?} {}",
+                info.channel_id,
+                info.tube_id,
+                info.outcome,
+                info.payload_bytes_received,
+            ));
+        });
+        let channel_closes = events.clone();
+        server.on_channel_close(move |info: &ChannelCloseInfo| {
+            assert!(info.bytes_received > 0);
+            channel_closes.lock().unwrap().push(format!(
+                "channel_close {} {} {:?}",
+                info.channel_id,
+                info.num_tubes,
+                info.reason,
+            ));
+        });
+
+        let (mut req_body_sender, req_body) = hyper::Body::channel();
+        let req = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("http://api.example.com/tubez")
+            .body(req_body)
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        tokio::spawn(hyper::body::to_bytes(res.into_body()));
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        let timeout = std::time::Duration::from_secs(1);
+
+        let headers = std::collections::HashMap::from([("name".to_string(), "a".to_string())]);
+        let newtube = frame::encode::newtube_frame(1, headers);
+        req_body_sender.send_data(newtube.unwrap().into()).await.unwrap();
+        let _tube = match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(tube)) => tube,
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        };
+        let payload = frame::encode::payload_frame(1, None, vec![0; 5].into());
+        req_body_sender.send_data(payload.unwrap().into()).await.unwrap();
+        let abort = frame::encode::abort_frame(1, frame::AbortReason::ApplicationError);
+        req_body_sender.send_data(abort.unwrap().into()).await.unwrap();
+
+        drop(req_body_sender);
+        loop {
+            match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+                Some(ChannelEvent::Closed(_)) => break,
+                Some(_) => (),
+                None => panic!("The channel ended without a Closed event"),
+            }
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            "channel_open 0".to_string(),
+            "tube_open 0 1 Some([97])".to_string(),
+            "tube_close 0 1 AbortedFromRemote(ApplicationError) 5".to_string(),
+            format!("channel_close 0 1 {:?}", CloseReason::PeerWentAway(None)),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[cfg(feature = "websocket")]
+    #[tokio::test]
+    async fn channels_are_carried_over_websockets() {
+        use futures::StreamExt;
+        use tokio::io::AsyncReadExt;
+        use tokio::io::AsyncWriteExt;
+        use crate::common::ChannelEvent;
+        use crate::common::PeerType;
+        use crate::common::websocket;
+
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let mut server = Server::builder()
+            .with_websocket_transport()
+            .build(&addr)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let req = format!(
+            "GET /tubez HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\n\
+             Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: {}\r\n\r\n",
+            key,
+        );
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut res = vec![];
+        while !res.ends_with(b"\r\n\r\n") {
+            res.push(stream.read_u8().await.unwrap());
+        }
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 101 Switching Protocols"), "{}", res);
+        assert!(res.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), "{}", res);
+
+        let mut channel = match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) => channel,
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        };
+        let newtube = frame::encode::newtube_frame(1, std::collections::HashMap::new());
+        let newtube = websocket::WsFrame::Data(newtube.unwrap().into());
+        stream.write_all(&websocket::encode(&newtube, PeerType::Client)).await.unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+        match tokio::time::timeout(timeout, channel.next()).await.unwrap() {
+            Some(ChannelEvent::NewTube(_)) => (),
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn from_unix_path_serves_on_the_socket_and_wont_clobber_it() {
+        let path = std::env::temp_dir().join(
+            format!("tubez-server-from-unix-path-{}.sock", std::process::id())
+        );
+        let _ = std::fs::remove_file(&path);
+        let server = Server::from_unix_path(&path).await.unwrap();
+        assert_eq!(server.local_addr(), None);
+        tokio::net::UnixStream::connect(&path).await.unwrap();
+
+        match Server::from_unix_path(&path).await {
+            Err(ServerError::UnixBindError { path: failed_path, .. }) => 
+                assert_eq!(failed_path, path),
+            Err(e) => panic!("Unexpected error from Server::from_unix_path(): {:?}", e),
+            Ok(_) => panic!("Binding to an existing socket file succeeded!?"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
 }
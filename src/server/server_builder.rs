@@ -0,0 +1,448 @@
+use std::net::SocketAddr;
+
+use futures::StreamExt;
+use futures::TryStreamExt;
+use hyper::server::accept::Accept;
+use hyper::server::conn::AddrIncoming;
+
+use crate::common::frame;
+use super::health_checks::HealthChecks;
+use super::hyper_tubez_service::TubezService;
+use super::incoming::IncomingIo;
+use super::server::Server;
+use super::server_error::ServerError;
+
+/**
+ * Binds a listening socket to addr much like hyper::Server::try_bind() does,
+ * except that IPv6 sockets can be made IPv6-only (so that they can share a
+ * port with an IPv4 listener).
+ */
+fn bind_listener(addr: &SocketAddr, only_v6: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(*addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    if only_v6 {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&(*addr).into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/**
+ * Starts accepting connections from an already-bound listener much like 
+ * hyper::Server::from_tcp() does, but yields the AddrIncoming itself so that
+ * it can be served over HTTP or raw (see ServerBuilder::with_raw_transport()).
+ */
+pub(in crate::server) fn incoming_from_std(
+    listener: std::net::TcpListener,
+) -> Result<AddrIncoming, ServerError> {
+    let listener = listener.set_nonblocking(true)
+        .and_then(|()| tokio::net::TcpListener::from_std(listener));
+    match listener {
+        Ok(listener) => match AddrIncoming::from_listener(listener) {
+            Ok(incoming) => Ok(incoming),
+            Err(e) => Err(ServerError::BindError(e)),
+        },
+        Err(e) => Err(ServerError::Err(format!("Couldn't accept on the listener: {}", e))),
+    }
+}
+
+/**
+ * The resource limits a Server enforces on its clients (see ServerBuilder).
+ * None leaves a limit off (or, for the HTTP/2 settings, at hyper's default).
+ */
+#[derive(Clone, Debug, Default)]
+pub(in crate::server) struct ServerLimits {
+    /**
+     * Bounds the frames each channel's Decoder accepts from the client.
+     */
+    pub(in crate::server) decoder_limits: frame::DecoderLimits,
+    /**
+     * Sizes the pool of buffers each channel encodes its frames into.
+     */
+    pub(in crate::server) frame_buffer_pool: frame::BufferPoolConfig,
+    /**
+     * Listeners keep accepting HTTP/1 connections when these are given (even
+     * if http2_only is set), so that plain HTTP probes can reach them.
+     */
+    pub(in crate::server) health_checks: Option<HealthChecks>,
+    pub(in crate::server) http2_initial_connection_window_size: Option<u32>,
+    pub(in crate::server) http2_initial_stream_window_size: Option<u32>,
+    pub(in crate::server) http2_max_concurrent_streams: Option<u32>,
+    pub(in crate::server) http2_max_header_list_size: Option<u32>,
+    pub(in crate::server) http2_max_send_buf_size: Option<usize>,
+    /**
+     * Whether listeners refuse HTTP/1 connections (and with them, channels
+     * that clients fall back to HTTP/1.1 chunked transfer for).
+     */
+    pub(in crate::server) http2_only: bool,
+    /**
+     * How long a channel may go without receiving anything from its client 
+     * before it's closed with CloseReason::IdleTimeout.
+     */
+    pub(in crate::server) idle_channel_timeout: Option<std::time::Duration>,
+    pub(in crate::server) max_concurrent_channels: Option<usize>,
+    /**
+     * The largest payload frame size channels negotiate with their clients
+     * (frame::MAX_PAYLOAD_FRAME_SIZE if None).
+     */
+    pub(in crate::server) max_payload_frame_size: Option<usize>,
+    pub(in crate::server) max_tubes_per_channel: Option<usize>,
+    pub(in crate::server) rate_limits: Option<frame::RateLimits>,
+    /**
+     * Whether listeners carry channels straight over their connections 
+     * rather than over HTTP.
+     */
+    pub(in crate::server) raw_transport: bool,
+    /**
+     * Whether channels may also be opened over WebSockets (which listeners
+     * then keep accepting HTTP/1 connections for, even if http2_only is set).
+     */
+    pub(in crate::server) websocket_transport: bool,
+    /**
+     * How each channel coalesces the frames it sends into writes.
+     */
+    pub(in crate::server) write_coalescing: frame::WriteCoalescing,
+}
+impl ServerLimits {
+    pub(in crate::server) fn configure_http<I: Accept>(
+        &self,
+        mut builder: hyper::server::Builder<I>,
+    ) -> hyper::server::Builder<I> {
+        builder = builder
+            .http2_only(
+                self.http2_only && self.health_checks.is_none() && !self.websocket_transport
+            )
+            .http2_initial_connection_window_size(self.http2_initial_connection_window_size)
+            .http2_initial_stream_window_size(self.http2_initial_stream_window_size)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams);
+        if let Some(max_header_list_size) = self.http2_max_header_list_size {
+            builder = builder.http2_max_header_list_size(max_header_list_size);
+        }
+        if let Some(max_send_buf_size) = self.http2_max_send_buf_size {
+            builder = builder.http2_max_send_buf_size(max_send_buf_size);
+        }
+        builder
+    }
+}
+
+/**
+ * Makes a Server with guardrails against clients that would use more than
+ * their share of its resources (see Server::builder()). Limits that aren't
+ * given are left off.
+ */
+#[derive(Default)]
+pub struct ServerBuilder {
+    limits: ServerLimits,
+}
+impl ServerBuilder {
+    pub fn new() -> Self {
+        ServerBuilder {
+            limits: ServerLimits::default(),
+        }
+    }
+
+    /**
+     * Binds a listener to addr and starts serving channels on it (see
+     * Server::new()).
+     */
+    pub async fn build(self, addr: &SocketAddr) -> Result<Server, ServerError> {
+        let incoming = match AddrIncoming::bind(addr) {
+            Ok(incoming) => incoming,
+            Err(e) => return Err(ServerError::BindError(e)),
+        };
+        let local_addr = incoming.local_addr();
+        Ok(Server::from_incoming_parts(incoming, Some(local_addr), self.limits))
+    }
+
+    /**
+     * See Server::new_with_addrs().
+     */
+    pub async fn build_with_addrs(self, addrs: &[SocketAddr]) -> Result<Server, ServerError> {
+        if addrs.is_empty() {
+            return Err(ServerError::Err("No addresses were given to listen on".to_string()));
+        }
+        let dual_stack = 
+            addrs.iter().any(SocketAddr::is_ipv4) && addrs.iter().any(SocketAddr::is_ipv6);
+
+        // Bind everything up front so that nothing is served if any bind fails
+        let mut incomings = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let listener = match bind_listener(addr, dual_stack && addr.is_ipv6()) {
+                Ok(listener) => listener,
+                Err(error) => return Err(ServerError::AddrBindError {
+                    addr: *addr,
+                    error,
+                }),
+            };
+            incomings.push(incoming_from_std(listener)?);
+        }
+
+        let mut server = Server::without_listener(self.limits);
+        for incoming in incomings {
+            let local_addr = incoming.local_addr();
+            server.add_listener(incoming, Some(local_addr));
+        }
+        Ok(server)
+    }
+
+    /**
+     * See Server::from_listener().
+     */
+    pub async fn build_from_listener(
+        self,
+        listener: tokio::net::TcpListener,
+    ) -> Result<Server, ServerError> {
+        let incoming = match AddrIncoming::from_listener(listener) {
+            Ok(incoming) => incoming,
+            Err(e) => return Err(ServerError::BindError(e)),
+        };
+        let local_addr = incoming.local_addr();
+        Ok(Server::from_incoming_parts(incoming, Some(local_addr), self.limits))
+    }
+
+    /**
+     * See Server::from_incoming().
+     */
+    pub fn build_from_incoming<S, IO, E>(self, incoming: S) -> Server
+    where
+        S: futures::Stream<Item = Result<IO, E>> + Send + 'static,
+        IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        let mut incoming = Box::pin(incoming.map_ok(IncomingIo));
+        let incoming = hyper::server::accept::poll_fn(move |cx| incoming.poll_next_unpin(cx));
+        Server::from_incoming_parts(incoming, None, self.limits)
+    }
+
+    /**
+     * See Server::from_unix_path().
+     */
+    #[cfg(unix)]
+    pub async fn build_from_unix_path(
+        self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Server, ServerError> {
+        let path = path.as_ref();
+        let listener = match tokio::net::UnixListener::bind(path) {
+            Ok(listener) => listener,
+            Err(error) => return Err(ServerError::UnixBindError {
+                path: path.to_path_buf(),
+                error,
+            }),
+        };
+        let incoming = futures::stream::poll_fn(move |cx| {
+            listener.poll_accept(cx).map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+        });
+        Ok(self.build_from_incoming(incoming))
+    }
+
+    /**
+     * See server::service(). The HTTP/2 settings are left to the server the
+     * TubezService is mounted on.
+     */
+    pub fn build_service(self) -> (TubezService, Server) {
+        let server = Server::without_listener(self.limits);
+        (server.make_service(), server)
+    }
+
+    /**
+     * Bounds the frames received from clients (see frame::DecoderLimits). A
+     * channel whose client sends a frame beyond these limits is failed.
+     */
+    pub fn with_decoder_limits(mut self, decoder_limits: frame::DecoderLimits) -> Self {
+        self.limits.decoder_limits = decoder_limits;
+        self
+    }
+
+    /**
+     * Sizes the pool of buffers each channel encodes the frames it sends
+     * into (see frame::BufferPoolConfig). Channels report how often they
+     * reused a pooled buffer via Channel::buffer_pool_stats().
+     */
+    pub fn with_frame_buffer_pool(mut self, config: frame::BufferPoolConfig) -> Self {
+        self.limits.frame_buffer_pool = config;
+        self
+    }
+
+    /**
+     * Answers GETs on the HealthChecks' paths (over HTTP/1 as well as 
+     * HTTP/2) with the Server's liveness and readiness, alongside the 
+     * channels it serves. A TubezService (see build_service()) answers them 
+     * too, though whether they're reachable over HTTP/1 is up to the server
+     * it's mounted on.
+     */
+    pub fn with_health_checks(mut self, health_checks: HealthChecks) -> Self {
+        self.limits.health_checks = Some(health_checks);
+        self
+    }
+
+    pub fn with_http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.limits.http2_initial_connection_window_size = Some(size);
+        self
+    }
+
+    pub fn with_http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.limits.http2_initial_stream_window_size = Some(size);
+        self
+    }
+
+    /**
+     * The most channels a single client connection may have open at once.
+     */
+    pub fn with_http2_max_concurrent_streams(mut self, max: u32) -> Self {
+        self.limits.http2_max_concurrent_streams = Some(max);
+        self
+    }
+
+    /**
+     * The largest (uncompressed) set of HTTP headers a client may open a
+     * channel with.
+     */
+    pub fn with_http2_max_header_list_size(mut self, size: u32) -> Self {
+        self.limits.http2_max_header_list_size = Some(size);
+        self
+    }
+
+    /**
+     * How many bytes the Server buffers for sending on each channel before
+     * sends wait on the client to read them.
+     */
+    pub fn with_http2_max_send_buf_size(mut self, size: usize) -> Self {
+        self.limits.http2_max_send_buf_size = Some(size);
+        self
+    }
+
+    /**
+     * Refuses HTTP/1 connections, which listeners otherwise accept so that 
+     * clients that can't make channels over HTTP/2 (e.g. behind proxies 
+     * that only speak HTTP/1.1) can fall back to a chunked HTTP/1.1 request
+     * (see client::ClientBuilder::with_http2_only()). Listeners that answer
+     * health checks or WebSockets still accept HTTP/1 connections for them.
+     */
+    pub fn with_http2_only(mut self) -> Self {
+        self.limits.http2_only = true;
+        self
+    }
+
+    /**
+     * Closes channels whose clients send nothing (not even a Ping) for 
+     * timeout, e.g. because they vanished without the connection being 
+     * reset. The channel's unfinished Tubes are aborted with 
+     * AbortReason::IdleTimeout, it yields 
+     * ChannelEvent::Closed(CloseReason::IdleTimeout), and its transport is 
+     * reset.
+     */
+    pub fn with_idle_channel_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.limits.idle_channel_timeout = Some(timeout);
+        self
+    }
+
+    /**
+     * The most channels the Server will have open at once across all of its
+     * clients. Channels beyond it are refused with a 503 Service Unavailable
+     * (see client::ChannelConnectError::Rejected).
+     */
+    pub fn with_max_concurrent_channels(mut self, max: usize) -> Self {
+        self.limits.max_concurrent_channels = Some(max);
+        self
+    }
+
+    /**
+     * Shorthand for bounding frame sizes in the DecoderLimits (see
+     * with_decoder_limits()).
+     */
+    pub fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.limits.decoder_limits.max_frame_size = size;
+        self
+    }
+
+    /**
+     * Caps the payload frame size each channel negotiates with its client
+     * (see frame::MAX_PAYLOAD_FRAME_SIZE_HEADER), so that both peers split
+     * larger payloads into frames of at most this size. Channels never
+     * negotiate a size beyond the DecoderLimits' max_payload_size, so clients
+     * don't send payload frames that the channel would reject.
+     */
+    pub fn with_max_payload_frame_size(mut self, size: usize) -> Self {
+        self.limits.max_payload_frame_size = Some(size);
+        self
+    }
+
+    /**
+     * Shorthand for bounding payload sizes in the DecoderLimits (see
+     * with_decoder_limits()).
+     */
+    pub fn with_max_payload_size(mut self, size: usize) -> Self {
+        self.limits.decoder_limits.max_payload_size = size;
+        self
+    }
+
+    /**
+     * The most unfinished Tubes a client may have open on each channel.
+     * Tubes beyond it are refused with an OverLimit Error frame (which the 
+     * client's make_tube() fails with client::MakeTubeError::TooManyTubes).
+     */
+    pub fn with_max_tubes_per_channel(mut self, max: usize) -> Self {
+        self.limits.max_tubes_per_channel = Some(max);
+        self
+    }
+
+    /**
+     * Bounds how quickly the client on each channel may open Tubes and send
+     * payload data (see frame::RateLimits). Each channel is limited 
+     * separately.
+     */
+    pub fn with_rate_limits(mut self, rate_limits: frame::RateLimits) -> Self {
+        self.limits.rate_limits = Some(rate_limits);
+        self
+    }
+
+    /**
+     * Serves channels straight over the connections the Server's listeners 
+     * accept, with no HTTP at all: each connection carries one channel, 
+     * whose frames follow a short head exchanged with the client (see 
+     * client::ClientBuilder::with_raw_transport()). This trades 
+     * compatibility with HTTP proxies and load balancers for less overhead,
+     * so it's meant for controlled environments. TLS can be layered 
+     * underneath by serving from_incoming() TLS streams.
+     *
+     * Channels are otherwise served (and authenticated, limited, etc) just 
+     * like ones opened over HTTP/2, but the HTTP/2 settings, health checks 
+     * and WebSockets don't apply to raw listeners. A TubezService (see 
+     * build_service()) still serves HTTP.
+     */
+    pub fn with_raw_transport(mut self) -> Self {
+        self.limits.raw_transport = true;
+        self
+    }
+
+    /**
+     * Also serves channels opened over WebSockets (see 
+     * client::ClientBuilder::with_websocket_transport()), for clients behind
+     * proxies or gateways that can't carry a streaming HTTP/2 request body.
+     * A channel's frames are carried in binary WebSocket messages, and it's
+     * otherwise served just like a channel opened over HTTP/2.
+     */
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket_transport(mut self) -> Self {
+        self.limits.websocket_transport = true;
+        self
+    }
+
+    /**
+     * How each channel coalesces the frames it sends (e.g. the acks for a
+     * burst of payloads) into fewer, larger writes to its transport (see
+     * frame::WriteCoalescing).
+     */
+    pub fn with_write_coalescing(mut self, write_coalescing: frame::WriteCoalescing) -> Self {
+        self.limits.write_coalescing = write_coalescing;
+        self
+    }
+}
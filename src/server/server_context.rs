@@ -1,11 +1,50 @@
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::task;
 
+use crate::common::frame;
+use crate::common::ChannelContext;
+use crate::common::ChannelExecutor;
+use crate::common::tube;
+use super::auth::Authenticator;
+use super::lifecycle_hooks::LifecycleHooks;
+use super::server_builder::ServerLimits;
 use super::server_error::ServerError;
 use super::server_event::ServerEvent;
 
 pub(in crate::server) struct ServerContext {
+    pub(in crate::server) ack_timeout_policy: Option<tube::AckTimeoutPolicy>,
+    pub(in crate::server) authenticator: Option<Authenticator>,
+    pub(in crate::server) channel_executor: ChannelExecutor,
+    /**
+     * Set once the Server has started draining (see Server::drain_all()), 
+     * after which it reports that it isn't ready (see HealthChecks).
+     */
+    pub(in crate::server) draining: bool,
+    pub(in crate::server) event_queue_limit: Option<tube::EventQueueLimit>,
+    pub(in crate::server) extension_frame_handlers: frame::ExtensionFrameHandlers,
     pub(in crate::server) is_complete: bool,
+    pub(in crate::server) late_payload_policy: frame::LatePayloadPolicy,
+    pub(in crate::server) lifecycle_hooks: LifecycleHooks,
+    pub(in crate::server) limits: ServerLimits,
+    pub(in crate::server) max_pending_tubes_per_channel: Option<usize>,
+    /**
+     * Used to key open_channels.
+     */
+    pub(in crate::server) next_open_channel_id: u64,
+    /**
+     * Channels that have been accepted and whose client is still sending.
+     */
+    pub(in crate::server) num_open_channels: usize,
+    /**
+     * The contexts of the accepted channels whose client is still sending,
+     * so that they can be drained together (see Server::drain_all()).
+     */
+    pub(in crate::server) open_channels: HashMap<u64, ChannelContext>,
+    pub(in crate::server) outgoing_frame_interceptors: frame::FrameInterceptors,
+    #[cfg(feature = "bench")]
+    pub(in crate::server) serves_bench_tubes: bool,
     pub(in crate::server) pending_events: VecDeque<Result<ServerEvent, ServerError>>,
+    pub(in crate::server) slow_consumer_limit: Option<tube::SlowConsumerLimit>,
     pub(in crate::server) waker: Option<task::Waker>,
 }
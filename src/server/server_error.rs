@@ -1,6 +1,34 @@
 #[derive(Debug)]
 pub enum ServerError {
-    // TODO: Actually enumerate errors...
-    Err(String)
+    /**
+     * One of the addresses given to Server::new_with_addrs() couldn't be
+     * bound.
+     */
+    AddrBindError {
+        addr: std::net::SocketAddr,
+        error: std::io::Error,
+    },
+    BindError(hyper::Error),
+    // TODO: Actually enumerate remaining errors...
+    Err(String),
+    /**
+     * A listener stopped accepting connections. Channels already accepted 
+     * from it (and any other listeners) are unaffected.
+     */
+    ListenerError {
+        /**
+         * None for an accept stream given to Server::from_incoming().
+         */
+        local_addr: Option<std::net::SocketAddr>,
+        detail: String,
+    },
+    /**
+     * The path given to Server::from_unix_path() couldn't be bound (e.g.
+     * because a file already exists there).
+     */
+    UnixBindError {
+        path: std::path::PathBuf,
+        error: std::io::Error,
+    },
 }
 
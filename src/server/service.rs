@@ -0,0 +1,48 @@
+use super::hyper_tubez_service::TubezService;
+use super::server::Server;
+
+/**
+ * Makes a TubezService for mounting tubez on a route of an existing hyper 
+ * (or axum, etc) server, along with the Server that the channels it accepts 
+ * are surfaced on. The Server is configured and polled for ServerEvents like
+ * any other, but doesn't listen for connections itself (see 
+ * ServerBuilder::build_service() for one with resource limits).
+ *
+ * Clients make channels over HTTP/2, falling back to HTTP/1.1 chunked 
+ * transfer when they can't, so the existing server should accept HTTP/2 
+ * connections (and may also accept HTTP/1). Channels accepted through a 
+ * TubezService have no Channel::remote_addr().
+ */
+pub fn service() -> (TubezService, Server) {
+    Server::builder().build_service()
+}
+
+#[cfg(test)]
+mod service_tests {
+    use futures::StreamExt;
+    use hyper::service::Service;
+
+    use super::*;
+    use crate::server::ServerEvent;
+
+    #[tokio::test]
+    async fn channels_from_the_service_are_surfaced_on_the_server() {
+        let (mut service, mut server) = service();
+        assert_eq!(server.local_addr(), None);
+
+        let (_req_body_sender, req_body) = hyper::Body::channel();
+        let req = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("http://api.example.com/tubez")
+            .body(req_body)
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+
+        match server.next().await {
+            Some(Ok(ServerEvent::NewChannel(channel))) =>
+                assert_eq!(channel.request_uri().path(), "/tubez"),
+            unexpected => panic!("Unexpected server event: {:?}", unexpected),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::client;
+use crate::common::ChannelExecutor;
+use crate::server;
+
+/**
+ * Makes a client Channel and the server Channel it's connected to, with their
+ * frames carried over in-memory pipes rather than sockets and HTTP. This is
+ * meant for unit-testing tube handlers: Tubes made on the client Channel
+ * surface as ChannelEvent::NewTubes on the server Channel just as they would
+ * over a real connection, but without a listener, ports, or TLS to set up.
+ *
+ * Both Channels are served on the Tokio runtime loopback() is called from.
+ * The server Channel has the defaults of a Server made with
+ * Server::builder(), and the client Channel never reconnects or sends
+ * heartbeats.
+ */
+pub fn loopback() -> (client::Channel, server::Channel) {
+    let (_, server) = server::Server::builder().build_service();
+    let (client_body_sender, server_req_body) = hyper::Body::channel();
+    let (server_channel, client_res_body) = match server.accept_loopback_channel(server_req_body) {
+        Some(accepted) => accepted,
+        None => unreachable!("A Server without a channel limit accepts every channel"),
+    };
+    let client_channel = client::Channel::new_loopback(
+        client_body_sender,
+        client_res_body,
+        ChannelExecutor::default(),
+    );
+    (client_channel, server_channel)
+}
+
+#[cfg(test)]
+mod testing_tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::common::tube::TubeEvent;
+
+    #[tokio::test]
+    async fn loopback_channels_carry_tubes_both_ways() {
+        let (mut client_channel, mut server_channel) = loopback();
+        let timeout = Duration::from_secs(1);
+
+        let mut client_tube = client_channel.make_tube(HashMap::new()).await.unwrap();
+        let mut server_tube = match tokio::time::timeout(timeout, server_channel.next()).await {
+            Ok(Some(server::ChannelEvent::NewTube(tube))) => tube,
+            unexpected => panic!("Unexpected channel event: {:?}", unexpected),
+        };
+
+        client_tube.send_and_forget("ping").await.unwrap();
+        match tokio::time::timeout(timeout, server_tube.next()).await {
+            Ok(Some(TubeEvent::Payload(payload))) => assert_eq!(&payload[..], b"ping"),
+            unexpected => panic!("Unexpected tube event: {:?}", unexpected),
+        }
+        server_tube.send_and_forget("pong").await.unwrap();
+        match tokio::time::timeout(timeout, client_tube.next()).await {
+            Ok(Some(TubeEvent::Payload(payload))) => assert_eq!(&payload[..], b"pong"),
+            unexpected => panic!("Unexpected tube event: {:?}", unexpected),
+        }
+    }
+}
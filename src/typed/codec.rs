@@ -0,0 +1,91 @@
+use serde_core::de::DeserializeOwned;
+use serde_core::Serialize;
+
+/**
+ * How a TypedTube encodes each message into the Payload frame that carries 
+ * it. Both ends of a Tube must use the same Codec.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Codec {
+    /**
+     * Compact binary encoding (requires the "bincode" feature).
+     */
+    #[cfg(feature = "bincode")]
+    Bincode,
+    #[default]
+    Json,
+}
+impl Codec {
+    pub fn encode<T: Serialize>(&self, message: &T) -> Result<bytes::Bytes, CodecError> {
+        match self {
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => match bincode::serialize(message) {
+                Ok(data) => Ok(data.into()),
+                Err(e) => Err(CodecError::BincodeError(e)),
+            },
+            Codec::Json => match serde_json::to_vec(message) {
+                Ok(data) => Ok(data.into()),
+                Err(e) => Err(CodecError::JsonError(e)),
+            },
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, CodecError> {
+        match self {
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => match bincode::deserialize(data) {
+                Ok(message) => Ok(message),
+                Err(e) => Err(CodecError::BincodeError(e)),
+            },
+            Codec::Json => match serde_json::from_slice(data) {
+                Ok(message) => Ok(message),
+                Err(e) => Err(CodecError::JsonError(e)),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    #[cfg(feature = "bincode")]
+    BincodeError(bincode::Error),
+    JsonError(serde_json::Error),
+}
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "bincode")]
+            CodecError::BincodeError(e) => write!(f, "bincode error: {}", e),
+            CodecError::JsonError(e) => write!(f, "json error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod codec_tests {
+    use super::*;
+
+    #[test]
+    fn messages_roundtrip_through_json() {
+        let message = (7u32, "hello".to_string(), vec![Some(true), None]);
+        let data = Codec::Json.encode(&message).unwrap();
+        assert_eq!(data.as_ref(), br#"[7,"hello",[true,null]]"#);
+        assert_eq!(Codec::Json.decode::<(u32, String, Vec<Option<bool>>)>(&data).unwrap(), message);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn messages_roundtrip_through_bincode() {
+        let message = (7u32, "hello".to_string(), vec![Some(true), None]);
+        let data = Codec::Bincode.encode(&message).unwrap();
+        assert_eq!(Codec::Bincode.decode::<(u32, String, Vec<Option<bool>>)>(&data).unwrap(), message);
+    }
+
+    #[test]
+    fn errors_on_messages_that_dont_decode() {
+        match Codec::Json.decode::<u32>(b"\"not a number\"") {
+            Err(CodecError::JsonError(_)) => (),
+            unexpected => panic!("Unexpected result: {:?}", unexpected),
+        }
+    }
+}
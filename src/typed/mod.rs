@@ -0,0 +1,7 @@
+mod codec;
+mod typed_tube;
+
+pub use codec::Codec;
+pub use codec::CodecError;
+pub use typed_tube::TypedSendError;
+pub use typed_tube::TypedTube;
@@ -0,0 +1,191 @@
+use std::marker::PhantomData;
+
+use serde_core::de::DeserializeOwned;
+use serde_core::Serialize;
+
+use crate::common::frame;
+use crate::common::tube;
+use crate::common::tube::Tube;
+use crate::common::tube::TubeEvent;
+use super::Codec;
+use super::CodecError;
+
+#[derive(Debug)]
+pub enum TypedSendError {
+    CodecError(CodecError),
+    SendError(tube::error::SendError),
+}
+
+/**
+ * Wraps a Tube to send and receive serde-encoded messages of type T instead
+ * of raw payload bytes. Each message is carried by a single Payload frame,
+ * encoded with the TypedTube's Codec.
+ *
+ * As a Stream, a TypedTube yields the messages it receives (or a CodecError
+ * for a payload that doesn't decode as a T) and ends when the underlying
+ * Tube does. Other TubeEvents aren't surfaced, but if the Tube was aborted
+ * its AbortReason is available from abort_reason() once the Stream ends.
+ */
+#[derive(Debug)]
+pub struct TypedTube<T> {
+    abort_reason: Option<frame::AbortReason>,
+    codec: Codec,
+    tube: Tube,
+    _message: PhantomData<fn() -> T>,
+}
+impl<T: Serialize + DeserializeOwned> TypedTube<T> {
+    pub fn new(tube: Tube, codec: Codec) -> Self {
+        TypedTube {
+            abort_reason: None,
+            codec,
+            tube,
+            _message: PhantomData,
+        }
+    }
+
+    pub fn abort_reason(&self) -> Option<&frame::AbortReason> {
+        self.abort_reason.as_ref()
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub async fn has_finished_sending(
+        &mut self,
+    ) -> Result<(), tube::error::HasFinishedSendingError> {
+        self.tube.has_finished_sending().await
+    }
+
+    pub fn into_inner(self) -> Tube {
+        self.tube
+    }
+
+    /**
+     * Sends message and waits for the peer to ack it (for up to the Tube's
+     * ack_timeout()).
+     */
+    pub async fn send(&mut self, message: &T) -> Result<(), TypedSendError> {
+        let data = match self.codec.encode(message) {
+            Ok(data) => data,
+            Err(e) => return Err(TypedSendError::CodecError(e)),
+        };
+        match self.tube.send_acked(data).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(TypedSendError::SendError(e)),
+        }
+    }
+
+    pub async fn send_and_forget(&mut self, message: &T) -> Result<(), TypedSendError> {
+        let data = match self.codec.encode(message) {
+            Ok(data) => data,
+            Err(e) => return Err(TypedSendError::CodecError(e)),
+        };
+        match self.tube.send_and_forget(data).await {
+            Ok(()) => Ok(()),
+            Err(e) => Err(TypedSendError::SendError(e)),
+        }
+    }
+
+    pub fn tube(&self) -> &Tube {
+        &self.tube
+    }
+
+    pub fn tube_mut(&mut self) -> &mut Tube {
+        &mut self.tube
+    }
+}
+impl<T: Serialize + DeserializeOwned> futures::stream::Stream for TypedTube<T> {
+    type Item = Result<T, CodecError>;
+
+    fn poll_next(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Option<Self::Item>> {
+        loop {
+            let event = futures::ready!(core::pin::Pin::new(&mut self.tube).poll_next(cx));
+            match event {
+                Some(TubeEvent::Payload(data)) =>
+                    return futures::task::Poll::Ready(Some(self.codec.decode(&data))),
+                Some(TubeEvent::Abort(reason)) => self.abort_reason = Some(reason),
+                Some(_) => (),
+                None => return futures::task::Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod typed_tube_tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use futures::StreamExt;
+    use hyper::body::HttpBody;
+
+    use super::*;
+    use crate::common::PeerType;
+    use crate::common::UniqueId;
+
+    fn make_typed_tube<T: Serialize + DeserializeOwned>(
+        codec: Codec,
+    ) -> (TypedTube<T>, hyper::Body, Arc<Mutex<tube::TubeManager>>) {
+        let (body_sender, req_body) = hyper::Body::channel();
+        let frame_sender = frame::FrameSender::new(
+            Box::new(body_sender),
+            frame::FramingVersion::V1,
+            frame::FrameInterceptors::new(),
+        );
+        let tube_manager = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let tube = Tube::new(
+            PeerType::Client,
+            UniqueId::new(1, None),
+            HashMap::new(),
+            frame_sender,
+            tube_manager.clone(),
+        );
+        (TypedTube::new(tube, codec), req_body, tube_manager)
+    }
+
+    #[tokio::test]
+    async fn sends_each_message_as_an_encoded_payload() {
+        let (mut typed_tube, mut req_body, _tube_manager) =
+            make_typed_tube::<(String, u32)>(Codec::Json);
+
+        typed_tube.send_and_forget(&("hello".to_string(), 3)).await.unwrap();
+        let data = req_body.data().await.unwrap().unwrap();
+        let mut decoder = frame::Decoder::new_with_version(frame::FramingVersion::V1);
+        let frames: Vec<frame::Frame> = decoder.decode(data.to_vec()).unwrap().into();
+        match frames.as_slice() {
+            [frame::Frame::Payload { tube_id: 1, data, .. }] =>
+                assert_eq!(data.as_ref(), br#"["hello",3]"#),
+            unexpected => panic!("Unexpected frames: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn yields_decoded_messages_and_codec_errors() {
+        let (mut typed_tube, _req_body, tube_manager) =
+            make_typed_tube::<Vec<u32>>(Codec::Json);
+        {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("[1,2]".into()));
+            tube_mgr.pending_events.push_back(TubeEvent::Payload("oops".into()));
+            tube_mgr.pending_events.push_back(TubeEvent::Abort(
+                frame::AbortReason::ApplicationAbort,
+            ));
+            tube_mgr.set_completion_state(tube::TubeCompletionState::AbortedFromRemote(
+                frame::AbortReason::ApplicationAbort,
+            ));
+        }
+
+        assert_eq!(typed_tube.next().await.unwrap().unwrap(), vec![1, 2]);
+        match typed_tube.next().await {
+            Some(Err(CodecError::JsonError(_))) => (),
+            unexpected => panic!("Unexpected message: {:?}", unexpected),
+        }
+        assert!(typed_tube.next().await.is_none());
+        assert_eq!(typed_tube.abort_reason(), Some(&frame::AbortReason::ApplicationAbort));
+    }
+}